@@ -0,0 +1,137 @@
+//! The server's own analyses of compiled sources, shared by the diagnostics of open
+//! documents, `forge-lsp.baseline` and `forge-lsp.fixAll`.
+
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+use tower_lsp::lsp_types::{Diagnostic, Url};
+
+use crate::{
+    annotations::project_sources, config::DiagnosticsSettings, interface_sync, mutability, natspec,
+    project::ProjectConfig, selectors, storage_layout, struct_literals, suppressions, syntax,
+    unused, unused_returns,
+};
+
+/// Codes of the findings of the analyses.
+pub const ANALYSIS_CODES: &[&str] = &[
+    selectors::SELECTOR_CLASH_CODE,
+    selectors::SELECTOR_COLLISION_CODE,
+    storage_layout::STORAGE_GAP_CODE,
+    unused_returns::UNUSED_RETURN_CODE,
+    natspec::NATSPEC_CODE,
+    mutability::MUTABILITY_CODE,
+    mutability::STATICCALL_CODE,
+    interface_sync::INTERFACE_MISSING_CODE,
+    interface_sync::INTERFACE_STALE_CODE,
+    struct_literals::STRUCT_MISSING_FIELDS_CODE,
+    struct_literals::STRUCT_FIELD_ORDER_CODE,
+    unused::UNUSED_IMPORT_CODE,
+    unused::UNUSED_VARIABLE_CODE,
+    unused::UNUSED_PARAMETER_CODE,
+];
+
+/// Findings of the analyses of `uri` in `ast_data`, those behind `settings` included when
+/// enabled. Suppression comments are not applied.
+pub fn analysis_diagnostics(
+    ast_data: &Value,
+    uri: &Url,
+    source_bytes: &[u8],
+    settings: &DiagnosticsSettings,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = selectors::selector_clashes(ast_data, uri, source_bytes);
+    diagnostics.extend(selectors::inheritance_collisions(
+        ast_data,
+        uri,
+        source_bytes,
+    ));
+    diagnostics.extend(storage_layout::storage_gap_diagnostics(
+        ast_data,
+        uri,
+        source_bytes,
+    ));
+    diagnostics.extend(unused_returns::unused_return_diagnostics(
+        ast_data,
+        uri,
+        source_bytes,
+    ));
+    if settings.unused {
+        diagnostics.extend(unused::unused_diagnostics(ast_data, uri, source_bytes));
+    }
+    if settings.natspec {
+        diagnostics.extend(natspec::natspec_diagnostics(ast_data, uri, source_bytes));
+    }
+    if settings.mutability {
+        diagnostics.extend(mutability::mutability_diagnostics(
+            ast_data,
+            uri,
+            source_bytes,
+        ));
+    }
+    if settings.interfaces {
+        diagnostics.extend(interface_sync::interface_diagnostics(
+            ast_data,
+            uri,
+            source_bytes,
+        ));
+    }
+    diagnostics
+}
+
+/// The findings of one source of a project.
+#[derive(Debug, Clone)]
+pub struct SourceFindings {
+    /// Root of the project of the source.
+    pub root: PathBuf,
+    pub path: PathBuf,
+    pub uri: Url,
+    pub source: String,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// The findings of every source of the project at `root` outside its dependencies, from
+/// the project AST `ast_data`, struct literal checks included, without those silenced by
+/// suppression comments. `open` has the text of the documents open in the editor, by URI;
+/// other sources are read from disk.
+pub fn project_findings(
+    root: &Path,
+    ast_data: &Value,
+    open: &HashMap<Url, String>,
+    settings: &DiagnosticsSettings,
+) -> Vec<SourceFindings> {
+    let config = ProjectConfig::load(root);
+    let mut findings = Vec::new();
+    for path in project_sources(root) {
+        if config.is_dependency(&path) {
+            continue;
+        }
+        let Ok(uri) = Url::from_file_path(&path) else {
+            continue;
+        };
+        let Some(source) = open
+            .get(&uri)
+            .cloned()
+            .or_else(|| std::fs::read_to_string(&path).ok())
+        else {
+            continue;
+        };
+        let tree = syntax::parse(&path.to_string_lossy(), &source);
+        let mut diagnostics = analysis_diagnostics(ast_data, &uri, source.as_bytes(), settings);
+        diagnostics.extend(struct_literals::struct_literal_diagnostics(
+            &tree,
+            &uri,
+            source.as_bytes(),
+            Some(ast_data),
+        ));
+        suppressions::retain_unsuppressed(&mut diagnostics, &source);
+        findings.push(SourceFindings {
+            root: root.to_path_buf(),
+            path,
+            uri,
+            source,
+            diagnostics,
+        });
+    }
+    findings
+}
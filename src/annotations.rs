@@ -0,0 +1,427 @@
+//! Index of structured comments: `TODO`, `FIXME`, `audit:` notes and
+//! `@custom:security` NatSpec tags.
+//!
+//! Audit workflows leave findings in the code as comments. `forge-lsp/annotations`
+//! lists them for the whole project, anchored to the declaration they are written in
+//! or directly above, and `diagnostics.annotations` surfaces them as hints.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use tower_lsp::lsp_types::{
+    Diagnostic, DiagnosticSeverity, Location, NumberOrString, Range, TextDocumentIdentifier, Url,
+};
+
+use crate::{
+    ast::{self, parse_src},
+    goto::bytes_to_pos,
+};
+
+/// Name of the custom request.
+pub const ANNOTATIONS_METHOD: &str = "forge-lsp/annotations";
+
+/// Diagnostic code of annotation hints.
+pub const ANNOTATION_CODE: &str = "annotation";
+
+/// Directories that hold dependencies or build output rather than project sources.
+pub(crate) const SKIPPED_DIRS: &[&str] = &["lib", "node_modules", "out", "cache"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AnnotationTag {
+    Todo,
+    Fixme,
+    /// `audit:` or `@audit` notes
+    Audit,
+    /// `@custom:security` NatSpec tags
+    Security,
+}
+
+impl AnnotationTag {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Todo => "TODO",
+            Self::Fixme => "FIXME",
+            Self::Audit => "audit",
+            Self::Security => "@custom:security",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Annotation {
+    pub tag: AnnotationTag,
+    /// Comment text after the tag.
+    pub text: String,
+    /// From the tag to the end of the comment line.
+    pub location: Location,
+    /// Qualified name of the declaration the comment belongs to, e.g. `Vault.withdraw`.
+    pub symbol: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnnotationsParams {
+    /// Restrict the index to one document. The whole project is indexed when absent.
+    pub text_document: Option<TextDocumentIdentifier>,
+}
+
+/// An annotation found in a source, as byte offsets.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct RawAnnotation {
+    tag: AnnotationTag,
+    text: String,
+    start: usize,
+    end: usize,
+}
+
+/// Byte ranges of the comments in `source`, skipping comment markers inside string
+/// literals.
+fn comments(source: &str) -> Vec<(usize, usize)> {
+    let bytes = source.as_bytes();
+    let mut comments = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            quote @ (b'"' | b'\'') => {
+                i += 1;
+                while i < bytes.len() && bytes[i] != quote && bytes[i] != b'\n' {
+                    i += if bytes[i] == b'\\' { 2 } else { 1 };
+                }
+                i += 1;
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                let end = source[i..].find('\n').map_or(bytes.len(), |n| i + n);
+                comments.push((i, end));
+                i = end;
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                let end = source[i + 2..]
+                    .find("*/")
+                    .map_or(bytes.len(), |n| i + n + 4);
+                comments.push((i, end));
+                i = end;
+            }
+            _ => i += 1,
+        }
+    }
+    comments
+}
+
+/// Tag at the start of `text` and the number of bytes it takes.
+fn tag(text: &str) -> Option<(AnnotationTag, usize)> {
+    let word_end = |len: usize| {
+        text.as_bytes()
+            .get(len)
+            .is_none_or(|b| !b.is_ascii_alphanumeric() && *b != b'_')
+    };
+    if text.starts_with("TODO") && word_end(4) {
+        return Some((AnnotationTag::Todo, 4));
+    }
+    if text.starts_with("FIXME") && word_end(5) {
+        return Some((AnnotationTag::Fixme, 5));
+    }
+    if text.starts_with("@custom:security") && word_end(16) {
+        return Some((AnnotationTag::Security, 16));
+    }
+    if text.starts_with("@audit") && word_end(6) {
+        return Some((AnnotationTag::Audit, 6));
+    }
+    let prefix = text.get(..6)?;
+    prefix
+        .eq_ignore_ascii_case("audit:")
+        .then_some((AnnotationTag::Audit, 6))
+}
+
+/// Annotations in the comments of `source`, one per tagged comment line.
+fn scan(source: &str) -> Vec<RawAnnotation> {
+    let mut annotations = Vec::new();
+    for (start, end) in comments(source) {
+        let mut line_start = start;
+        for line in source[start..end].split('\n') {
+            let content = line.trim_start_matches(|c: char| c.is_whitespace() || "/*!".contains(c));
+            let offset = line_start + line.len() - content.len();
+            line_start += line.len() + 1;
+
+            let Some((tag, tag_len)) = tag(content) else {
+                continue;
+            };
+            let content = content.trim_end();
+            let content = content.strip_suffix("*/").unwrap_or(content).trim_end();
+            let text = content[tag_len.min(content.len())..]
+                .trim_start_matches(|c: char| c == ':' || c == '-' || c.is_whitespace());
+            annotations.push(RawAnnotation {
+                tag,
+                text: text.to_string(),
+                start: offset,
+                end: offset + content.len(),
+            });
+        }
+    }
+    annotations
+}
+
+/// A named declaration of a source unit, as a byte range.
+struct Declaration {
+    qualified_name: String,
+    start: usize,
+    end: usize,
+}
+
+/// Top-level declarations and contract members of `source_unit`, outermost first.
+fn declarations(source_unit: &Value) -> Vec<Declaration> {
+    fn push(node: &Value, container: Option<&str>, out: &mut Vec<Declaration>) {
+        let Some(name) = node.get("name").and_then(Value::as_str) else {
+            return;
+        };
+        let name = match (name, node.get("kind").and_then(Value::as_str)) {
+            ("", Some(kind @ ("constructor" | "fallback" | "receive"))) => kind,
+            ("", _) => return,
+            (name, _) => name,
+        };
+        let Some((start, length, _)) = node.get("src").and_then(Value::as_str).and_then(parse_src)
+        else {
+            return;
+        };
+        let qualified_name = match container {
+            Some(container) => format!("{container}.{name}"),
+            None => name.to_string(),
+        };
+        out.push(Declaration {
+            qualified_name: qualified_name.clone(),
+            start,
+            end: start + length,
+        });
+        if node.get("nodeType").and_then(Value::as_str) == Some("ContractDefinition") {
+            for member in node
+                .get("nodes")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+            {
+                push(member, Some(&qualified_name), out);
+            }
+        }
+    }
+
+    let mut out = Vec::new();
+    for node in source_unit
+        .get("nodes")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+    {
+        push(node, None, &mut out);
+    }
+    out
+}
+
+/// Whether only whitespace and comment lines separate `start` from `end`.
+fn only_comments_between(source: &str, start: usize, end: usize) -> bool {
+    source.get(start..end).is_some_and(|between| {
+        between.lines().all(|line| {
+            let line = line.trim();
+            line.is_empty()
+                || line.starts_with("//")
+                || line.starts_with('*')
+                || line.starts_with("/*")
+        })
+    })
+}
+
+/// The declaration directly below the comment line ending at `end`, or else the
+/// innermost declaration containing it.
+fn anchor(declarations: &[Declaration], source: &str, start: usize, end: usize) -> Option<String> {
+    let line_end = source[end..].find('\n').map_or(source.len(), |n| end + n);
+    declarations
+        .iter()
+        .find(|d| d.start >= line_end && only_comments_between(source, line_end, d.start))
+        .or_else(|| {
+            declarations
+                .iter()
+                .rev()
+                .find(|d| d.start <= start && end <= d.end)
+        })
+        .map(|d| d.qualified_name.clone())
+}
+
+fn to_range(source: &[u8], start: usize, end: usize) -> Option<Range> {
+    Some(Range {
+        start: bytes_to_pos(source, start)?,
+        end: bytes_to_pos(source, end)?,
+    })
+}
+
+/// Annotations in one document. `ast_data`, if available, anchors them to declarations.
+pub fn annotations(ast_data: Option<&Value>, uri: &Url, source: &[u8]) -> Vec<Annotation> {
+    let Ok(text) = std::str::from_utf8(source) else {
+        return vec![];
+    };
+    let declarations = ast_data
+        .and_then(|ast_data| ast::source_unit(ast_data, uri))
+        .map(declarations)
+        .unwrap_or_default();
+
+    scan(text)
+        .into_iter()
+        .filter_map(|raw| {
+            Some(Annotation {
+                symbol: anchor(&declarations, text, raw.start, raw.end),
+                location: Location {
+                    uri: uri.clone(),
+                    range: to_range(source, raw.start, raw.end)?,
+                },
+                tag: raw.tag,
+                text: raw.text,
+            })
+        })
+        .collect()
+}
+
+/// Hint diagnostics for the annotations in one document.
+pub fn annotation_diagnostics(uri: &Url, source: &[u8]) -> Vec<Diagnostic> {
+    annotations(None, uri, source)
+        .into_iter()
+        .map(|annotation| Diagnostic {
+            range: annotation.location.range,
+            severity: Some(DiagnosticSeverity::HINT),
+            code: Some(NumberOrString::String(ANNOTATION_CODE.to_string())),
+            source: Some("forge-lsp".to_string()),
+            message: if annotation.text.is_empty() {
+                annotation.tag.label().to_string()
+            } else {
+                format!("{}: {}", annotation.tag.label(), annotation.text)
+            },
+            ..Diagnostic::default()
+        })
+        .collect()
+}
+
+/// Solidity files under `root`, skipping hidden directories, dependencies and build
+/// output.
+pub fn project_sources(root: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            match entry.file_type() {
+                Ok(kind)
+                    if kind.is_dir()
+                        && !name.starts_with('.')
+                        && !SKIPPED_DIRS.contains(&name.as_ref()) =>
+                {
+                    dirs.push(path)
+                }
+                Ok(_) if name.ends_with(".sol") => files.push(path),
+                _ => {}
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const SOURCE: &str = concat!(
+        "// TODO: split into modules\n",
+        "contract Vault {\n",
+        "    string constant NOTE = \"// FIXME: not a comment\";\n",
+        "    /// @custom:security reentrancy-guarded\n",
+        "    function withdraw() public {\n",
+        "        /* audit: check the return value */\n",
+        "    }\n",
+        "}\n",
+    );
+
+    #[test]
+    fn test_scan_tags() {
+        let found: Vec<_> = scan(SOURCE)
+            .into_iter()
+            .map(|raw| (raw.tag, raw.text, &SOURCE[raw.start..raw.end]))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (
+                    AnnotationTag::Todo,
+                    "split into modules".to_string(),
+                    "TODO: split into modules"
+                ),
+                (
+                    AnnotationTag::Security,
+                    "reentrancy-guarded".to_string(),
+                    "@custom:security reentrancy-guarded"
+                ),
+                (
+                    AnnotationTag::Audit,
+                    "check the return value".to_string(),
+                    "audit: check the return value"
+                ),
+            ]
+        );
+        // Words that merely start with a tag are not annotations
+        assert!(scan("// TODOS are tracked elsewhere").is_empty());
+    }
+
+    #[test]
+    fn test_annotations_anchored_to_declarations() {
+        let uri = Url::parse("file:///project/src/Vault.sol").unwrap();
+        let at = |needle: &str| SOURCE.find(needle).unwrap();
+        let withdraw_src = format!(
+            "{}:{}:0",
+            at("function"),
+            at("    }\n}") + 5 - at("function")
+        );
+        let contract_src = format!("{}:{}:0", at("contract"), SOURCE.len() - at("contract") - 1);
+        let ast = ast::mock_ast(
+            "/project/src/Vault.sol",
+            json!({
+                "nodeType": "SourceUnit",
+                "absolutePath": "/project/src/Vault.sol",
+                "nodes": [{
+                    "nodeType": "ContractDefinition",
+                    "name": "Vault",
+                    "src": contract_src,
+                    "nodes": [{
+                        "nodeType": "FunctionDefinition",
+                        "name": "withdraw",
+                        "src": withdraw_src
+                    }]
+                }]
+            }),
+        );
+
+        let symbols: Vec<_> = annotations(Some(&ast), &uri, SOURCE.as_bytes())
+            .into_iter()
+            .map(|a| a.symbol)
+            .collect();
+        assert_eq!(
+            symbols,
+            [
+                Some("Vault".to_string()),
+                Some("Vault.withdraw".to_string()),
+                Some("Vault.withdraw".to_string())
+            ]
+        );
+
+        let hints = annotation_diagnostics(&uri, SOURCE.as_bytes());
+        assert_eq!(hints.len(), 3);
+        assert_eq!(hints[0].message, "TODO: split into modules");
+        assert_eq!(hints[0].severity, Some(DiagnosticSeverity::HINT));
+        assert_eq!(
+            hints[0].range.start,
+            tower_lsp::lsp_types::Position::new(0, 3)
+        );
+    }
+}
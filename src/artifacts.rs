@@ -0,0 +1,208 @@
+//! Detection of compiled artifacts older than the sources they were compiled from.
+//!
+//! Storage layouts, ABIs and selectors read from `out/` describe the sources as they were
+//! at the last `forge build`. When a source changed since, the artifacts are reported with
+//! an informational diagnostic on the project's `foundry.toml` and a "Rebuild now" lens at
+//! the top of its Solidity files, instead of serving the old data silently.
+//!
+//! Artifacts are out of date when a source outside the dependencies was modified after
+//! the newest build-info file was written, or when forge's files cache
+//! (`solidity-files-cache.json`), written by the same build, doesn't list it: it was
+//! added, or renamed, without being compiled.
+
+use serde_json::Value;
+use std::{
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+use tower_lsp::lsp_types::{
+    CodeLens, Command, Diagnostic, DiagnosticSeverity, NumberOrString, Position, Range,
+};
+
+use crate::{annotations::project_sources, project::ProjectConfig};
+
+/// Rebuilds the artifacts of a project. Argument: the project root.
+pub const REBUILD_COMMAND: &str = "forge-lsp.rebuild";
+
+/// Diagnostic code for out-of-date artifacts.
+pub const STALE_ARTIFACTS_CODE: &str = "stale-artifacts";
+
+/// Forge's record of the sources it compiled, in the cache directory.
+const FILES_CACHE: &str = "solidity-files-cache.json";
+
+/// Sources listed in the diagnostic before the rest are counted.
+const LISTED_SOURCES: usize = 3;
+
+/// Artifacts of a project that no longer match its sources.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StaleArtifacts {
+    pub root: PathBuf,
+    /// Artifacts directory, relative to the root.
+    pub out: String,
+    /// The changed sources, relative to the root, sorted.
+    pub sources: Vec<PathBuf>,
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).ok()?.modified().ok()
+}
+
+/// When the newest build-info file in `out` was written.
+fn last_build(out: &Path) -> Option<SystemTime> {
+    std::fs::read_dir(out.join("build-info"))
+        .ok()?
+        .flatten()
+        .filter(|entry| entry.file_name().to_string_lossy().ends_with(".json"))
+        .filter_map(|entry| modified(&entry.path()))
+        .max()
+}
+
+/// The sources forge's files cache at `path` lists, by path relative to the root or
+/// absolute, if the cache is at least as new as the build.
+fn compiled_sources(path: &Path, built: SystemTime) -> Option<serde_json::Map<String, Value>> {
+    if modified(path)? < built {
+        return None;
+    }
+    let mut cache: Value = serde_json::from_slice(&std::fs::read(path).ok()?).ok()?;
+    match cache.get_mut("files")?.take() {
+        Value::Object(files) => Some(files),
+        _ => None,
+    }
+}
+
+/// The out-of-date artifacts of the project at `root`, if it has artifacts and any are.
+pub fn stale_artifacts(root: &Path) -> Option<StaleArtifacts> {
+    let config = ProjectConfig::load(root);
+    let built = last_build(&root.join(&config.out))?;
+    let compiled = compiled_sources(&root.join(&config.cache_path).join(FILES_CACHE), built);
+
+    let mut sources = Vec::new();
+    for path in project_sources(root) {
+        if config.is_dependency(&path) {
+            continue;
+        }
+        let Some(changed) = modified(&path) else {
+            continue;
+        };
+        let relative = path.strip_prefix(root).unwrap_or(&path);
+        let uncompiled = compiled.as_ref().is_some_and(|files| {
+            !files.contains_key(&*relative.to_string_lossy())
+                && !files.contains_key(&*path.to_string_lossy())
+        });
+        if changed > built || uncompiled {
+            sources.push(relative.to_path_buf());
+        }
+    }
+    (!sources.is_empty()).then(|| StaleArtifacts {
+        root: root.to_path_buf(),
+        out: config.out,
+        sources,
+    })
+}
+
+impl StaleArtifacts {
+    /// The informational diagnostic published on the project's `foundry.toml`.
+    pub fn diagnostic(&self) -> Diagnostic {
+        let mut listed: Vec<String> = self
+            .sources
+            .iter()
+            .take(LISTED_SOURCES)
+            .map(|source| format!("`{}`", source.display()))
+            .collect();
+        if self.sources.len() > LISTED_SOURCES {
+            listed.push(format!("{} more", self.sources.len() - LISTED_SOURCES));
+        }
+        Diagnostic {
+            range: Range::default(),
+            severity: Some(DiagnosticSeverity::INFORMATION),
+            code: Some(NumberOrString::String(STALE_ARTIFACTS_CODE.to_string())),
+            source: Some("forge-lsp".to_string()),
+            message: format!(
+                "The artifacts in `{}/` are out of date: {} changed since the last build. \
+                 Storage layouts, ABIs and selectors read from them may be stale; run \
+                 `forge build` to refresh them",
+                self.out,
+                listed.join(", ")
+            ),
+            ..Diagnostic::default()
+        }
+    }
+
+    /// The "Rebuild now" lens at the top of a source of the project.
+    pub fn lens(&self) -> CodeLens {
+        CodeLens {
+            range: Range::new(Position::new(0, 0), Position::new(0, 0)),
+            command: Some(Command {
+                title: format!("Artifacts in {}/ are out of date: Rebuild now", self.out),
+                command: REBUILD_COMMAND.to_string(),
+                arguments: Some(vec![Value::from(self.root.to_string_lossy().as_ref())]),
+            }),
+            data: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn set_modified(path: &Path, time: SystemTime) {
+        std::fs::File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(time)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_stale_artifacts() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::create_dir_all(root.join("lib/dep")).unwrap();
+        std::fs::create_dir_all(root.join("out/build-info")).unwrap();
+        let source = root.join("src/Counter.sol");
+        let dependency = root.join("lib/dep/Dep.sol");
+        let build_info = root.join("out/build-info/abc.json");
+        for file in [&source, &dependency, &build_info] {
+            std::fs::write(file, "").unwrap();
+        }
+
+        let now = SystemTime::now();
+        set_modified(&source, now - Duration::from_secs(60));
+        set_modified(&build_info, now - Duration::from_secs(30));
+        assert_eq!(stale_artifacts(root), None);
+
+        // Dependencies are not checked, project sources are
+        set_modified(&dependency, now);
+        assert_eq!(stale_artifacts(root), None);
+        set_modified(&source, now);
+        let stale = stale_artifacts(root).unwrap();
+        assert_eq!(stale.sources, [PathBuf::from("src/Counter.sol")]);
+        assert_eq!(stale.out, "out");
+        let diagnostic = stale.diagnostic();
+        assert_eq!(diagnostic.severity, Some(DiagnosticSeverity::INFORMATION));
+        assert!(diagnostic.message.contains("`src/Counter.sol` changed"));
+        assert_eq!(
+            stale.lens().command.unwrap().arguments.unwrap(),
+            [Value::from(root.to_string_lossy().as_ref())]
+        );
+
+        // A source the files cache of the build doesn't list was never compiled
+        set_modified(&source, now - Duration::from_secs(60));
+        std::fs::create_dir_all(root.join("cache")).unwrap();
+        let cache = root.join("cache").join(FILES_CACHE);
+        std::fs::write(&cache, r#"{"files": {"src/Counter.sol": {}}}"#).unwrap();
+        assert_eq!(stale_artifacts(root), None);
+        std::fs::write(&cache, r#"{"files": {}}"#).unwrap();
+        assert!(stale_artifacts(root).is_some());
+        set_modified(&cache, now - Duration::from_secs(45));
+        assert_eq!(stale_artifacts(root), None);
+
+        // Projects without artifacts have nothing out of date
+        std::fs::remove_dir_all(root.join("out")).unwrap();
+        assert_eq!(stale_artifacts(root), None);
+    }
+}
@@ -0,0 +1,280 @@
+//! Helpers shared by the request handlers that work on the solc JSON AST.
+
+use serde_json::Value;
+use std::{collections::HashMap, path::Path};
+use tower_lsp::lsp_types::{Position, Url};
+
+use crate::{
+    goto::{NodeInfo, cache_ids, pos_to_bytes},
+    paths,
+    references::byte_to_id,
+};
+
+/// Parse a solc `src` string of the form `start:length:fileId`.
+pub fn parse_src(src: &str) -> Option<(usize, usize, &str)> {
+    let mut parts = src.split(':');
+    let start = parts.next()?.parse().ok()?;
+    let length = parts.next()?.parse().ok()?;
+    let file_id = parts.next()?;
+    Some((start, length, file_id))
+}
+
+/// The `nodeType` of `node`.
+pub fn node_type(node: &Value) -> Option<&str> {
+    node.get("nodeType").and_then(Value::as_str)
+}
+
+/// The `name` of `node`, empty for unnamed nodes like an unnamed parameter.
+pub fn name(node: &Value) -> &str {
+    node.get("name").and_then(Value::as_str).unwrap_or_default()
+}
+
+/// The nodes of the array at `key` of `node`.
+pub fn children<'a>(node: &'a Value, key: &str) -> impl Iterator<Item = &'a Value> {
+    node.get(key)
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+}
+
+/// The byte range `(start, end)` of `node`, from its `src`.
+pub fn span_bounds(node: &Value) -> Option<(usize, usize)> {
+    location_bounds(node, "src")
+}
+
+/// The byte range `(start, end)` of the location at `key` of `node`, like its
+/// `nameLocation`.
+pub fn location_bounds(node: &Value, key: &str) -> Option<(usize, usize)> {
+    let (start, length, _) = parse_src(node.get(key)?.as_str()?)?;
+    Some((start, start + length))
+}
+
+/// Visit every AST node (any object with a `nodeType`) below and including `node`.
+pub fn walk<'a>(node: &'a Value, visit: &mut impl FnMut(&'a Value)) {
+    match node {
+        Value::Object(map) => {
+            if map.contains_key("nodeType") {
+                visit(node);
+            }
+            map.values().for_each(|child| walk(child, visit));
+        }
+        Value::Array(items) => items.iter().for_each(|child| walk(child, visit)),
+        _ => {}
+    }
+}
+
+/// Index every AST node of every source by its id.
+pub fn index_nodes(sources: &Value) -> HashMap<u64, &Value> {
+    let mut index = HashMap::new();
+    for contents in sources.as_object().into_iter().flat_map(|s| s.values()) {
+        if let Some(ast) = contents
+            .get(0)
+            .and_then(|content| content.get("source_file"))
+            .and_then(|source_file| source_file.get("ast"))
+        {
+            walk(ast, &mut |node| {
+                if let Some(id) = node.get("id").and_then(Value::as_u64) {
+                    index.insert(id, node);
+                }
+            });
+        }
+    }
+    index
+}
+
+/// Field of a source entry holding the canonical key of its file, see
+/// [`resolve_source_keys`].
+pub const CANONICAL_PATH: &str = "canonical_path";
+
+/// Record the canonical key (see [`paths::canonical_key`]) of every source of `ast_data` in
+/// its entry. Forge reports paths relative to `root`, the directory it ran in. Resolving
+/// symlinks touches the file system, so this runs once, when an AST is indexed or cached,
+/// and lookups read the recorded key.
+pub fn resolve_source_keys(ast_data: &mut Value, root: &Path) {
+    let Some(sources) = ast_data.get_mut("sources").and_then(Value::as_object_mut) else {
+        return;
+    };
+    for (path, contents) in sources.iter_mut() {
+        if let Some(entry) = contents.get_mut(0).and_then(Value::as_object_mut) {
+            let key = paths::canonical_key(&root.join(path).to_string_lossy());
+            entry.insert(CANONICAL_PATH.to_string(), Value::String(key));
+        }
+    }
+}
+
+/// The `SourceUnit` AST of the file at `file_uri`.
+pub fn source_unit<'a>(ast_data: &'a Value, file_uri: &Url) -> Option<&'a Value> {
+    let file_key = paths::uri_to_key(file_uri)?;
+    ast_data
+        .get("sources")?
+        .as_object()?
+        .iter()
+        .find_map(|(path, contents)| {
+            let entry = contents.get(0)?;
+            let source_ast = entry.get("source_file")?.get("ast")?;
+            let path = source_ast
+                .get("absolutePath")
+                .and_then(Value::as_str)
+                .unwrap_or(path);
+            let canonical = entry.get(CANONICAL_PATH).and_then(Value::as_str);
+            (paths::normalize_path(path) == file_key || canonical == Some(file_key.as_str()))
+                .then_some(source_ast)
+        })
+}
+
+/// Whether `byte_position` falls within the `src` range of `node`, end inclusive.
+pub fn contains(node: &Value, byte_position: usize) -> bool {
+    node.get("src")
+        .and_then(Value::as_str)
+        .and_then(parse_src)
+        .is_some_and(|(start, length, _)| start <= byte_position && byte_position <= start + length)
+}
+
+/// The `source_id_to_path` map of the first build info.
+pub fn id_to_path(ast_data: &Value) -> Option<&serde_json::Map<String, Value>> {
+    ast_data
+        .get("build_infos")?
+        .as_array()?
+        .first()?
+        .get("source_id_to_path")?
+        .as_object()
+}
+
+/// The symbol under the cursor: the innermost node at `position` and the declaration
+/// it refers to.
+pub struct SymbolAtPosition {
+    pub node: NodeInfo,
+    pub declaration_id: u64,
+    pub declaration: NodeInfo,
+}
+
+/// Resolve the declaration referenced at `position`. Besides references, the name of a
+/// declaration itself resolves to that declaration.
+pub fn symbol_at_position(
+    ast_data: &Value,
+    file_uri: &Url,
+    position: Position,
+    source_bytes: &[u8],
+) -> Option<SymbolAtPosition> {
+    let (nodes, path_to_abs) = cache_ids(ast_data.get("sources")?);
+    let abs_path = paths::lookup_path(&path_to_abs, file_uri.as_str())?;
+
+    let byte_position = pos_to_bytes(source_bytes, position);
+    let node_id = byte_to_id(&nodes, abs_path, byte_position)?;
+    let node = nodes.get(abs_path)?.get(&node_id)?;
+
+    let declaration_id = match node.referenced_declaration {
+        Some(id) => id,
+        None if is_declaration_name_at(node, byte_position) => node_id,
+        None => return None,
+    };
+    let declaration = nodes
+        .values()
+        .find_map(|file_nodes| file_nodes.get(&declaration_id))?;
+
+    Some(SymbolAtPosition {
+        node: node.clone(),
+        declaration_id,
+        declaration: declaration.clone(),
+    })
+}
+
+/// Whether `node` is a declaration whose name covers `byte_position`.
+fn is_declaration_name_at(node: &NodeInfo, byte_position: usize) -> bool {
+    let is_declaration = node.node_type.as_deref().is_some_and(|node_type| {
+        node_type.ends_with("Definition") || node_type == "VariableDeclaration"
+    });
+    is_declaration
+        && node
+            .name_location
+            .as_deref()
+            .and_then(parse_src)
+            .is_some_and(|(start, length, _)| (start..start + length).contains(&byte_position))
+}
+
+/// The `sources` entry of the source with the id `id`, whose AST is `unit`, as forge
+/// reports it.
+#[cfg(test)]
+pub fn mock_source(id: u64, unit: Value) -> Value {
+    serde_json::json!([{ "source_file": { "id": id, "ast": unit } }])
+}
+
+/// Build output of the single source `path`, with the id 0, whose AST is `unit`.
+#[cfg(test)]
+pub fn mock_ast(path: &str, unit: Value) -> Value {
+    serde_json::json!({
+        "sources": { path: mock_source(0, unit) },
+        "build_infos": [{ "source_id_to_path": { "0": path } }]
+    })
+}
+
+/// The `src` of the `len` bytes at the first `needle` of `source`, in the file with the id 0.
+#[cfg(test)]
+pub fn mock_src(source: &str, needle: &str, len: usize) -> String {
+    format!("{}:{}:0", source.find(needle).unwrap(), len)
+}
+
+/// The `src` from the first `needle` of `source` through the next `until` after it, in the
+/// file with the id 0.
+#[cfg(test)]
+pub fn mock_span(source: &str, needle: &str, until: &str) -> String {
+    let start = source.find(needle).unwrap();
+    let end = start + source[start..].find(until).unwrap() + until.len();
+    format!("{}:{}:0", start, end - start)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_src() {
+        assert_eq!(parse_src("10:5:0"), Some((10, 5, "0")));
+        assert_eq!(parse_src("10:5"), None);
+        assert_eq!(parse_src("a:5:0"), None);
+    }
+
+    #[test]
+    fn test_index_nodes_reaches_nested_nodes() {
+        let sources = json!({
+            "A.sol": mock_source(0, json!({
+                "id": 1,
+                "nodeType": "SourceUnit",
+                "nodes": [{
+                    "id": 2,
+                    "nodeType": "StructDefinition",
+                    "members": [{
+                        "id": 3,
+                        "nodeType": "VariableDeclaration",
+                        "typeName": { "id": 4, "nodeType": "ElementaryTypeName" }
+                    }]
+                }]
+            }))
+        });
+
+        let index = index_nodes(&sources);
+        assert_eq!(index.len(), 4);
+        assert_eq!(index[&4]["nodeType"], "ElementaryTypeName");
+    }
+
+    #[test]
+    fn test_source_keys_resolve_against_root() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("src")).unwrap();
+        let file = dir.path().join("src/A.sol");
+        std::fs::write(&file, "contract A {}").unwrap();
+
+        let mut ast_data = mock_ast(
+            "src/A.sol",
+            json!({ "id": 1, "nodeType": "SourceUnit", "absolutePath": "src/A.sol" }),
+        );
+        resolve_source_keys(&mut ast_data, dir.path());
+
+        // The key is resolved under the root, not the working directory
+        let key = paths::canonical_key(&file.to_string_lossy());
+        assert_eq!(ast_data["sources"]["src/A.sol"][0][CANONICAL_PATH], key);
+        let uri = Url::from_file_path(std::fs::canonicalize(&file).unwrap()).unwrap();
+        assert_eq!(source_unit(&ast_data, &uri).unwrap()["id"], 1);
+    }
+}
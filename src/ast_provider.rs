@@ -0,0 +1,313 @@
+//! Cached, coalesced access to per-file AST data.
+//!
+//! Every request handler goes through [`AstProvider::get_or_fetch`]: files of an indexed
+//! project are answered from the project AST, a cache hit returns the shared AST
+//! immediately, and concurrent misses for the same file wait on a single forge invocation
+//! instead of each spawning their own.
+
+use crate::{
+    ast::{resolve_source_keys, source_unit},
+    index::WorkspaceIndex,
+    runner::{Runner, RunnerError},
+    singleflight::SingleFlight,
+};
+use serde_json::Value;
+use std::{collections::HashMap, path::Path, sync::Arc};
+use tokio::sync::RwLock;
+use tower_lsp::lsp_types::Url;
+
+/// Result of an AST fetch. Both sides are reference counted so a single result can be
+/// handed to every waiter of a coalesced request.
+pub type AstResult = Result<Arc<Value>, Arc<RunnerError>>;
+
+pub struct AstProvider {
+    compiler: Arc<dyn Runner>,
+    index: Option<Arc<WorkspaceIndex>>,
+    cache: RwLock<HashMap<String, Arc<Value>>>,
+    in_flight: SingleFlight<String, AstResult>,
+}
+
+impl AstProvider {
+    pub fn new(compiler: Arc<dyn Runner>) -> Self {
+        Self {
+            compiler,
+            index: None,
+            cache: RwLock::new(HashMap::new()),
+            in_flight: SingleFlight::new(),
+        }
+    }
+
+    /// A provider that answers from the project ASTs of `index` before compiling files.
+    pub fn with_index(compiler: Arc<dyn Runner>, index: Arc<WorkspaceIndex>) -> Self {
+        Self {
+            index: Some(index),
+            ..Self::new(compiler)
+        }
+    }
+
+    /// The AST of the indexed project including `uri`. The index is dropped on save, so an
+    /// indexed project matches the files on disk.
+    async fn indexed(&self, uri: &Url) -> Option<Arc<Value>> {
+        let project = self.index.as_ref()?.project_for(uri).await?;
+        Some(project.ast.clone())
+    }
+
+    /// Return the cached AST for `uri`, if any.
+    pub async fn get(&self, uri: &Url) -> Option<Arc<Value>> {
+        self.cache.read().await.get(uri.as_str()).cloned()
+    }
+
+    /// Return the indexed or cached AST for `uri` without compiling.
+    pub async fn available(&self, uri: &Url) -> Option<Arc<Value>> {
+        match self.indexed(uri).await {
+            Some(ast) => Some(ast),
+            None => self.get(uri).await,
+        }
+    }
+
+    /// Return the indexed or cached AST for `uri`, fetching it from the compiler on a miss.
+    pub async fn get_or_fetch(&self, uri: &Url) -> AstResult {
+        if let Some(ast) = self.available(uri).await {
+            return Ok(ast);
+        }
+        self.fetch(uri).await
+    }
+
+    /// Drop any cached AST for `uri` and fetch a fresh one, unless an indexed project
+    /// already has it.
+    pub async fn refresh(&self, uri: &Url) -> AstResult {
+        self.invalidate(uri).await;
+        if let Some(ast) = self.indexed(uri).await {
+            return Ok(ast);
+        }
+        self.fetch(uri).await
+    }
+
+    /// Store AST data obtained elsewhere.
+    pub async fn insert(&self, uri: &Url, ast: Arc<Value>) {
+        self.cache.write().await.insert(uri.to_string(), ast);
+    }
+
+    /// Remove the cached AST for `uri`. Returns whether an entry was present.
+    pub async fn invalidate(&self, uri: &Url) -> bool {
+        self.cache.write().await.remove(uri.as_str()).is_some()
+    }
+
+    /// Remove the cached ASTs of the files under `root`. Returns how many were dropped.
+    pub async fn invalidate_under(&self, root: &Path) -> usize {
+        let mut cache = self.cache.write().await;
+        let before = cache.len();
+        cache.retain(|key, _| {
+            Url::parse(key)
+                .ok()
+                .and_then(|uri| uri.to_file_path().ok())
+                .is_none_or(|path| !path.starts_with(root))
+        });
+        before - cache.len()
+    }
+
+    /// The files whose cached AST includes `uri`, because they import it directly or
+    /// through other imports.
+    pub async fn dependents(&self, uri: &Url) -> Vec<Url> {
+        self.cache
+            .read()
+            .await
+            .iter()
+            .filter(|(key, ast)| key.as_str() != uri.as_str() && source_unit(ast, uri).is_some())
+            .filter_map(|(key, _)| Url::parse(key).ok())
+            .collect()
+    }
+
+    /// Remove every cached AST. Returns how many entries were dropped.
+    pub async fn clear(&self) -> usize {
+        let mut cache = self.cache.write().await;
+        let dropped = cache.len();
+        cache.clear();
+        dropped
+    }
+
+    async fn fetch(&self, uri: &Url) -> AstResult {
+        self.in_flight
+            .run(uri.to_string(), || async {
+                let result = self.compile(uri).await.map(Arc::new);
+                // Populate the cache before the request leaves the in-flight table so a
+                // late caller either joins this request or sees the cached value.
+                if let Ok(ast) = &result {
+                    self.insert(uri, ast.clone()).await;
+                }
+                result.map_err(Arc::new)
+            })
+            .await
+    }
+
+    async fn compile(&self, uri: &Url) -> Result<Value, RunnerError> {
+        let path = uri.to_file_path().map_err(|_| RunnerError::InvalidUrl)?;
+        let path_str = path.to_str().ok_or(RunnerError::InvalidUrl)?;
+        let mut ast = self.compiler.ast(path_str).await?;
+        // Forge compiles a single file in the server's directory
+        if let Ok(cwd) = std::env::current_dir() {
+            resolve_source_keys(&mut ast, &cwd);
+        }
+        Ok(ast)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::BuildOutput;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tower_lsp::async_trait;
+
+    #[derive(Default)]
+    struct CountingRunner {
+        ast_calls: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl Runner for CountingRunner {
+        async fn build(&self, _: &str) -> Result<BuildOutput, RunnerError> {
+            Ok(BuildOutput::default())
+        }
+
+        async fn lint(&self, _: &str) -> Result<Value, RunnerError> {
+            Ok(Value::Null)
+        }
+
+        async fn ast(&self, file: &str) -> Result<Value, RunnerError> {
+            self.ast_calls.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            Ok(serde_json::json!({ "file": file }))
+        }
+    }
+
+    struct FixedRunner(Value);
+
+    #[async_trait]
+    impl Runner for FixedRunner {
+        async fn build(&self, _: &str) -> Result<BuildOutput, RunnerError> {
+            Ok(BuildOutput::default())
+        }
+
+        async fn lint(&self, _: &str) -> Result<Value, RunnerError> {
+            Ok(Value::Null)
+        }
+
+        async fn ast(&self, _: &str) -> Result<Value, RunnerError> {
+            Ok(self.0.clone())
+        }
+    }
+
+    fn uri(path: &str) -> Url {
+        Url::from_file_path(path).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_get_or_fetch_coalesces_concurrent_requests() {
+        let runner = Arc::new(CountingRunner::default());
+        let provider = AstProvider::new(runner.clone());
+        let uri = uri("/tmp/project/src/A.sol");
+
+        let (a, b, c) = tokio::join!(
+            provider.get_or_fetch(&uri),
+            provider.get_or_fetch(&uri),
+            provider.get_or_fetch(&uri)
+        );
+
+        assert_eq!(runner.ast_calls.load(Ordering::SeqCst), 1);
+        assert!(Arc::ptr_eq(a.as_ref().unwrap(), b.as_ref().unwrap()));
+        assert!(Arc::ptr_eq(a.as_ref().unwrap(), c.as_ref().unwrap()));
+
+        // Served from the cache afterwards
+        provider.get_or_fetch(&uri).await.unwrap();
+        assert_eq!(runner.ast_calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_dependents_include_importers() {
+        let provider = AstProvider::new(Arc::new(CountingRunner::default()));
+        let (token, vault, other) = (
+            uri("/tmp/project/src/Token.sol"),
+            uri("/tmp/project/src/Vault.sol"),
+            uri("/tmp/project/src/Other.sol"),
+        );
+        let ast = |paths: &[&str]| {
+            let sources: serde_json::Map<String, Value> = paths
+                .iter()
+                .map(|path| {
+                    let unit =
+                        serde_json::json!({ "nodeType": "SourceUnit", "absolutePath": path });
+                    (path.to_string(), crate::ast::mock_source(0, unit))
+                })
+                .collect();
+            Arc::new(serde_json::json!({ "sources": sources }))
+        };
+        provider
+            .insert(&token, ast(&["/tmp/project/src/Token.sol"]))
+            .await;
+        provider
+            .insert(
+                &vault,
+                ast(&["/tmp/project/src/Vault.sol", "/tmp/project/src/Token.sol"]),
+            )
+            .await;
+        provider
+            .insert(&other, ast(&["/tmp/project/src/Other.sol"]))
+            .await;
+
+        assert!(provider.dependents(&vault).await.is_empty());
+        assert_eq!(provider.dependents(&token).await, [vault]);
+    }
+
+    #[tokio::test]
+    async fn test_invalidate_and_refresh() {
+        let runner = Arc::new(CountingRunner::default());
+        let provider = AstProvider::new(runner.clone());
+        let uri = uri("/tmp/project/src/B.sol");
+
+        assert!(!provider.invalidate(&uri).await);
+        provider.get_or_fetch(&uri).await.unwrap();
+        assert!(provider.invalidate(&uri).await);
+        assert!(provider.get(&uri).await.is_none());
+
+        provider.refresh(&uri).await.unwrap();
+        assert_eq!(runner.ast_calls.load(Ordering::SeqCst), 2);
+        assert!(provider.get(&uri).await.is_some());
+
+        provider
+            .get_or_fetch(&self::uri("/tmp/project/src/C.sol"))
+            .await
+            .unwrap();
+        assert_eq!(provider.clear().await, 2);
+        assert!(provider.get(&uri).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_indexed_files_skip_the_compiler() {
+        let indexed = uri("/tmp/project/src/A.sol");
+        let project_ast = crate::ast::mock_ast(
+            "src/A.sol",
+            serde_json::json!({
+                "nodeType": "SourceUnit",
+                "absolutePath": "/tmp/project/src/A.sol"
+            }),
+        );
+        let index = Arc::new(WorkspaceIndex::new(Arc::new(FixedRunner(project_ast))));
+        index
+            .build(std::path::Path::new("/tmp/project"))
+            .await
+            .unwrap();
+
+        let runner = Arc::new(CountingRunner::default());
+        let provider = AstProvider::with_index(runner.clone(), index);
+        provider.get_or_fetch(&indexed).await.unwrap();
+        provider.refresh(&indexed).await.unwrap();
+        assert_eq!(runner.ast_calls.load(Ordering::SeqCst), 0);
+
+        provider
+            .get_or_fetch(&uri("/tmp/project/src/B.sol"))
+            .await
+            .unwrap();
+        assert_eq!(runner.ast_calls.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,201 @@
+//! Baselines of the server's own findings, to adopt it on a codebase that already has many.
+//!
+//! `forge-lsp.baseline` records every current finding of each indexed project in
+//! `forge-lsp.baseline.json` at the project root, meant to be committed. Afterwards a
+//! finding is only reported when the baseline doesn't have it. Findings are matched by
+//! file, code, message and the text of their first line, not by line number, so they stay
+//! recorded when code above them moves. A finding recorded once absorbs one occurrence: a
+//! second copy of the same line reports again.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+use tower_lsp::lsp_types::{Diagnostic, NumberOrString};
+
+/// Records the current findings of the indexed projects into their baselines.
+pub const BASELINE_COMMAND: &str = "forge-lsp.baseline";
+
+/// Name of the baseline file at the project root.
+pub const BASELINE_FILE: &str = "forge-lsp.baseline.json";
+
+/// Source of the diagnostics a baseline records.
+const SOURCE: &str = "forge-lsp";
+
+/// A recorded finding.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Finding {
+    /// Path of the file, relative to the project root, with `/` separators.
+    pub file: String,
+    pub code: String,
+    pub message: String,
+    /// The first line of the finding in the source, trimmed.
+    pub line: String,
+}
+
+impl Finding {
+    /// The finding `diagnostic` reports in `path` of the project at `root`, if it is one of
+    /// the server's.
+    pub fn new(root: &Path, path: &Path, diagnostic: &Diagnostic, source: &str) -> Option<Self> {
+        let Some(NumberOrString::String(code)) = &diagnostic.code else {
+            return None;
+        };
+        if diagnostic.source.as_deref() != Some(SOURCE) {
+            return None;
+        }
+        let file = path.strip_prefix(root).ok()?;
+        let line = source.lines().nth(diagnostic.range.start.line as usize)?;
+        Some(Self {
+            file: file.to_string_lossy().replace('\\', "/"),
+            code: code.clone(),
+            message: diagnostic.message.clone(),
+            line: line.trim().to_string(),
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct BaselineFile {
+    findings: Vec<Finding>,
+}
+
+/// The recorded findings of one project, with how often each was recorded.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Baseline {
+    findings: HashMap<Finding, usize>,
+}
+
+impl Baseline {
+    pub fn new(findings: impl IntoIterator<Item = Finding>) -> Self {
+        let mut baseline = Self::default();
+        for finding in findings {
+            *baseline.findings.entry(finding).or_default() += 1;
+        }
+        baseline
+    }
+
+    /// Location of the baseline of the project at `root`.
+    pub fn path(root: &Path) -> PathBuf {
+        root.join(BASELINE_FILE)
+    }
+
+    /// The baseline of the project at `root`, empty if it has none or it doesn't parse.
+    pub fn load(root: &Path) -> Self {
+        std::fs::read(Self::path(root))
+            .ok()
+            .and_then(|bytes| serde_json::from_slice::<BaselineFile>(&bytes).ok())
+            .map(|file| Self::new(file.findings))
+            .unwrap_or_default()
+    }
+
+    /// Write the baseline of the project at `root`, its findings sorted so the file diffs
+    /// well.
+    pub fn save(&self, root: &Path) -> std::io::Result<()> {
+        let mut findings: Vec<Finding> = self
+            .findings
+            .iter()
+            .flat_map(|(finding, count)| std::iter::repeat_n(finding.clone(), *count))
+            .collect();
+        findings.sort();
+        let json = serde_json::to_string_pretty(&BaselineFile { findings })
+            .map_err(std::io::Error::other)?;
+        std::fs::write(Self::path(root), json + "\n")
+    }
+
+    /// Number of recorded findings.
+    pub fn len(&self) -> usize {
+        self.findings.values().sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.findings.is_empty()
+    }
+
+    /// Drop the diagnostics of `path`, with the text `source`, that the baseline records.
+    pub fn retain_new(
+        &self,
+        root: &Path,
+        path: &Path,
+        diagnostics: &mut Vec<Diagnostic>,
+        source: &str,
+    ) {
+        if self.is_empty() {
+            return;
+        }
+        let mut remaining = self.findings.clone();
+        diagnostics.retain(|diagnostic| {
+            let Some(count) = Finding::new(root, path, diagnostic, source)
+                .and_then(|finding| remaining.get_mut(&finding))
+                .filter(|count| **count > 0)
+            else {
+                return true;
+            };
+            *count -= 1;
+            false
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower_lsp::lsp_types::{Position, Range};
+
+    const SOURCE: &str = "\
+contract Vault {
+    function run() public {
+        token.transfer(to, 1);
+        token.transfer(to, 1);
+    }
+}
+";
+
+    fn diagnostic(line: u32, source: &str) -> Diagnostic {
+        Diagnostic {
+            range: Range::new(Position::new(line, 8), Position::new(line, 29)),
+            code: Some(NumberOrString::String("unused-return".to_string())),
+            source: Some(source.to_string()),
+            message: "the return value of `transfer` is ignored: returns (bool)".to_string(),
+            ..Diagnostic::default()
+        }
+    }
+
+    #[test]
+    fn test_baseline_keeps_only_new_findings() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let path = root.join("src/Vault.sol");
+
+        let recorded = Baseline::new(Finding::new(
+            root,
+            &path,
+            &diagnostic(2, "forge-lsp"),
+            SOURCE,
+        ));
+        recorded.save(root).unwrap();
+        let baseline = Baseline::load(root);
+        assert_eq!(baseline, recorded);
+        assert_eq!(baseline.len(), 1);
+
+        // The recorded finding moved down a line; its copy and forge's findings are new
+        let moved = format!("// SPDX-License-Identifier: MIT\n{SOURCE}");
+        let mut diagnostics = vec![
+            diagnostic(3, "forge-lsp"),
+            diagnostic(4, "forge-lsp"),
+            diagnostic(3, "forge-lint"),
+        ];
+        baseline.retain_new(root, &path, &mut diagnostics, &moved);
+        let kept: Vec<(u32, Option<&str>)> = diagnostics
+            .iter()
+            .map(|d| (d.range.start.line, d.source.as_deref()))
+            .collect();
+        assert_eq!(kept, [(4, Some("forge-lsp")), (3, Some("forge-lint"))]);
+
+        // Another file has none of the findings
+        let mut diagnostics = vec![diagnostic(2, "forge-lsp")];
+        baseline.retain_new(root, &root.join("src/Other.sol"), &mut diagnostics, SOURCE);
+        assert_eq!(diagnostics.len(), 1);
+        assert!(Baseline::load(&root.join("missing")).is_empty());
+    }
+}
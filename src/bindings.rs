@@ -0,0 +1,119 @@
+//! Rust bindings of the project's contracts, generated with `forge bind`.
+//!
+//! `forge-lsp.generateBindings` runs `forge bind --overwrite` on a project with the
+//! `bindings` settings: where the bindings go, the name and version of the generated
+//! crate, or a module instead, and which contracts they cover. With `bindings.onAbiChange`
+//! the bindings are generated again after each build of the project that changes the ABI
+//! of one of its contracts, keeping downstream code in sync with the contracts.
+
+use serde_json::Value;
+
+use crate::{config::BindingsSettings, index::content_hash};
+
+/// Generates the bindings of the project of a file URI, or of every project of the
+/// workspace without one.
+pub const GENERATE_BINDINGS_COMMAND: &str = "forge-lsp.generateBindings";
+
+/// The arguments of `forge bind` after the project root for `settings`. Existing bindings
+/// are overwritten, so a run always reflects the current contracts.
+pub fn bind_args(settings: &BindingsSettings) -> Vec<String> {
+    let mut args = vec!["--overwrite".to_string()];
+    let mut option = |flag: &str, value: &Option<String>| {
+        if let Some(value) = value {
+            args.extend([flag.to_string(), value.clone()]);
+        }
+    };
+    option("--bindings-path", &settings.path);
+    option("--crate-name", &settings.crate_name);
+    option("--crate-version", &settings.crate_version);
+    option("--alloy-version", &settings.alloy_version);
+    for select in &settings.select {
+        args.extend(["--select".to_string(), select.clone()]);
+    }
+    if settings.module {
+        args.push("--module".to_string());
+    }
+    if settings.single_file {
+        args.push("--single-file".to_string());
+    }
+    args
+}
+
+/// Hash of the ABIs of every contract in the `forge build --json` output `output`, to
+/// tell builds that change an ABI from the others. `None` for outputs without contracts.
+pub fn abi_fingerprint(output: &Value) -> Option<u64> {
+    fn collect<'a>(value: &'a Value, abis: &mut Vec<&'a Value>) {
+        match value {
+            Value::Object(fields) => {
+                for (key, value) in fields {
+                    if key == "abi" {
+                        abis.push(value);
+                    } else {
+                        collect(value, abis);
+                    }
+                }
+            }
+            Value::Array(items) => items.iter().for_each(|item| collect(item, abis)),
+            _ => {}
+        }
+    }
+
+    let mut abis = Vec::new();
+    collect(output.get("contracts")?, &mut abis);
+    Some(content_hash(serde_json::to_string(&abis).ok()?.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_bind_args() {
+        assert_eq!(bind_args(&BindingsSettings::default()), ["--overwrite"]);
+
+        let settings = BindingsSettings {
+            path: Some("crates/bindings".to_string()),
+            crate_name: Some("vault-bindings".to_string()),
+            select: vec!["^Vault$".to_string()],
+            module: true,
+            ..BindingsSettings::default()
+        };
+        assert_eq!(
+            bind_args(&settings),
+            [
+                "--overwrite",
+                "--bindings-path",
+                "crates/bindings",
+                "--crate-name",
+                "vault-bindings",
+                "--select",
+                "^Vault$",
+                "--module"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_abi_fingerprint() {
+        let output = |abi: Value| {
+            json!({
+                "contracts": {
+                    "src/Vault.sol": {
+                        "Vault": [{ "contract": { "abi": abi, "evm": { "bytecode": "0x00" } } }]
+                    }
+                }
+            })
+        };
+        let deposit = json!([{ "type": "function", "name": "deposit", "inputs": [] }]);
+        let mut changed_bytecode = output(deposit.clone());
+        changed_bytecode["contracts"]["src/Vault.sol"]["Vault"][0]["contract"]["evm"] =
+            json!({ "bytecode": "0x01" });
+
+        let fingerprint = abi_fingerprint(&output(deposit));
+        assert!(fingerprint.is_some());
+        assert_eq!(abi_fingerprint(&changed_bytecode), fingerprint);
+        assert_ne!(abi_fingerprint(&output(json!([]))), fingerprint);
+        assert_eq!(abi_fingerprint(&json!({ "sources": {} })), None);
+    }
+}
@@ -1,16 +1,19 @@
-use crate::utils::byte_offset_to_position;
+use crate::{
+    documents::LineIndex,
+    runner::{ForgeDiagnosticMessage, compiler_errors},
+};
 use std::path::Path;
 use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString, Position, Range};
 
-fn ignored_code_for_tests(value: &serde_json::Value) -> bool {
-    let error_code = value
-        .get("errorCode")
-        .and_then(|v| v.as_str())
-        .unwrap_or_default();
-    let file_path = value
-        .get("sourceLocation")
-        .and_then(|loc| loc.get("file"))
-        .and_then(|f| f.as_str())
+/// Source of the diagnostics of `forge build`.
+pub const BUILD_DIAGNOSTICS_SOURCE: &str = "forge-build";
+
+fn ignored_code_for_tests(err: &ForgeDiagnosticMessage<'_>) -> bool {
+    let error_code = err.error_code.as_deref().unwrap_or_default();
+    let file_path = err
+        .source_location
+        .as_ref()
+        .map(|loc| loc.file.as_ref())
         .unwrap_or_default();
 
     // Ignore error code 5574, 3860 for test files (code size limit)
@@ -18,99 +21,125 @@ fn ignored_code_for_tests(value: &serde_json::Value) -> bool {
         || (error_code == "3860" && (file_path.contains(".t.sol") || file_path.contains(".s.sol")))
 }
 
+/// Diagnostics of the compiler `errors` in the file named `filename`, whose text is
+/// `content`.
 pub fn build_output_to_diagnostics(
-    forge_output: &serde_json::Value,
+    errors: &[ForgeDiagnosticMessage<'_>],
     filename: &str,
     content: &str,
 ) -> Vec<Diagnostic> {
-    let mut diagnostics = Vec::new();
+    diagnostics_of(errors, content, |file| {
+        Path::new(file)
+            .file_name()
+            .and_then(|os_str| os_str.to_str())
+            == Some(filename)
+    })
+}
 
-    if let Some(errors) = forge_output.get("errors").and_then(|e| e.as_array()) {
-        for err in errors {
-            if ignored_code_for_tests(err) {
-                continue;
-            }
+/// Diagnostics of the file at `path` in the build of the whole project at `root`. The
+/// build covers every source of the project, so files are told apart by their path, not
+/// their name.
+pub fn project_build_diagnostics(
+    forge_output: &serde_json::Value,
+    root: &Path,
+    path: &Path,
+    content: &str,
+) -> Vec<Diagnostic> {
+    diagnostics_of(&compiler_errors(forge_output), content, |file| {
+        root.join(file) == path
+    })
+}
 
-            let source_file = err
-                .get("sourceLocation")
-                .and_then(|loc| loc.get("file"))
-                .and_then(|f| f.as_str())
-                .and_then(|full_path| Path::new(full_path).file_name())
-                .and_then(|os_str| os_str.to_str());
+/// Diagnostics of the `errors` in the file whose source location `is_file` accepts, whose
+/// text is `content`.
+fn diagnostics_of(
+    errors: &[ForgeDiagnosticMessage<'_>],
+    content: &str,
+    is_file: impl Fn(&str) -> bool,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let line_index = LineIndex::new(content);
 
-            if source_file != Some(filename) {
-                continue;
-            }
+    for err in errors {
+        if ignored_code_for_tests(err) {
+            continue;
+        }
 
-            let start_offset = err
-                .get("sourceLocation")
-                .and_then(|loc| loc.get("start"))
-                .and_then(|s| s.as_u64())
-                .unwrap_or(0) as usize;
-
-            let end_offset = err
-                .get("sourceLocation")
-                .and_then(|loc| loc.get("end"))
-                .and_then(|s| s.as_u64())
-                .map(|v| v as usize)
-                .unwrap_or(start_offset);
-
-            let (start_line, start_col) = byte_offset_to_position(content, start_offset);
-            let (mut end_line, mut end_col) = byte_offset_to_position(content, end_offset);
-
-            if end_col > 0 {
-                end_col -= 1;
-            } else if end_line > 0 {
-                end_line -= 1;
-                end_col = content
-                    .lines()
-                    .nth(end_line.try_into().unwrap())
-                    .map(|l| l.len() as u32)
-                    .unwrap_or(0);
-            }
+        if !err
+            .source_location
+            .as_ref()
+            .is_some_and(|loc| is_file(loc.file.as_ref()))
+        {
+            continue;
+        }
 
-            let range = Range {
-                start: Position {
-                    line: start_line,
-                    character: start_col,
-                },
-                end: Position {
-                    line: end_line,
-                    character: end_col + 1,
-                },
-            };
-
-            let message = err
-                .get("message")
-                .and_then(|m| m.as_str())
-                .unwrap_or("Unknown error")
-                .to_string();
-
-            let severity = match err.get("severity").and_then(|s| s.as_str()) {
-                Some("error") => Some(DiagnosticSeverity::ERROR),
-                Some("warning") => Some(DiagnosticSeverity::WARNING),
-                Some("note") => Some(DiagnosticSeverity::INFORMATION),
-                Some("help") => Some(DiagnosticSeverity::HINT),
-                _ => Some(DiagnosticSeverity::INFORMATION),
-            };
-
-            let code = err
-                .get("errorCode")
-                .and_then(|c| c.as_str())
-                .map(|s| NumberOrString::String(s.to_string()));
-
-            diagnostics.push(Diagnostic {
-                range,
-                severity,
-                code,
-                code_description: None,
-                source: Some("forge-build".to_string()),
-                message: format!("[forge build] {message}"),
-                related_information: None,
-                tags: None,
-                data: None,
-            });
+        let (start_offset, end_offset) = match &err.source_location {
+            Some(loc) => {
+                let start = usize::try_from(loc.start).unwrap_or(0);
+                let end = usize::try_from(loc.end)
+                    .ok()
+                    .filter(|&end| end >= start)
+                    .unwrap_or(start);
+                (start, end)
+            }
+            None => (0, 0),
+        };
+
+        let start = line_index.position(content, start_offset);
+        let Position {
+            line: mut end_line,
+            character: mut end_col,
+        } = line_index.position(content, end_offset);
+
+        if end_col > 0 {
+            end_col -= 1;
+        } else if end_line > 0 {
+            end_line -= 1;
+            end_col = content
+                .lines()
+                .nth(end_line.try_into().unwrap())
+                .map(|l| l.encode_utf16().count() as u32)
+                .unwrap_or(0);
         }
+
+        let range = Range {
+            start,
+            end: Position {
+                line: end_line,
+                character: end_col + 1,
+            },
+        };
+
+        let message = if err.message.is_empty() {
+            "Unknown error"
+        } else {
+            err.message.as_ref()
+        };
+
+        let severity = match err.severity.as_ref() {
+            "error" => Some(DiagnosticSeverity::ERROR),
+            "warning" => Some(DiagnosticSeverity::WARNING),
+            "note" => Some(DiagnosticSeverity::INFORMATION),
+            "help" => Some(DiagnosticSeverity::HINT),
+            _ => Some(DiagnosticSeverity::INFORMATION),
+        };
+
+        let code = err
+            .error_code
+            .as_deref()
+            .map(|s| NumberOrString::String(s.to_string()));
+
+        diagnostics.push(Diagnostic {
+            range,
+            severity,
+            code,
+            code_description: None,
+            source: Some(BUILD_DIAGNOSTICS_SOURCE.to_string()),
+            message: format!("[forge build] {message}"),
+            related_information: None,
+            tags: None,
+            data: None,
+        });
     }
 
     diagnostics
@@ -119,7 +148,10 @@ pub fn build_output_to_diagnostics(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::runner::{ForgeRunner, Runner};
+    use crate::{
+        runner::{ForgeRunner, Runner},
+        utils::byte_offset_to_position,
+    };
     use std::fs;
 
     static CONTRACT: &str = r#"// SPDX-License-Identifier: MIT
@@ -155,7 +187,7 @@ libs = ["lib"]
         let contract_path = src_dir.join("Contract.sol");
         fs::write(&contract_path, contents).expect("failed to write contract");
 
-        let compiler = ForgeRunner;
+        let compiler = ForgeRunner::default();
         (temp_dir, contract_path, compiler)
     }
 
@@ -173,7 +205,7 @@ libs = ["lib"]
         let (temp_dir, _contract_path, compiler) = setup(CONTRACT);
         let file_path = temp_dir.path().to_string_lossy().to_string();
 
-        let json = compiler.build(&file_path).await.unwrap();
+        let json = compiler.build(&file_path).await.unwrap().into_value();
         assert!(
             json.get("errors").is_some(),
             "Expected 'errors' array in build output"
@@ -185,7 +217,7 @@ libs = ["lib"]
         let (temp_dir, _contract_path, compiler) = setup(CONTRACT);
         let file_path = temp_dir.path().to_string_lossy().to_string();
 
-        let json = compiler.build(&file_path).await.unwrap();
+        let json = compiler.build(&file_path).await.unwrap().into_value();
         if let Some(errors) = json.get("errors")
             && let Some(first) = errors.get(0)
         {
@@ -212,7 +244,7 @@ libs = ["lib"]
             .file_name()
             .and_then(|f| f.to_str())
             .expect("filename");
-        let diagnostics = build_output_to_diagnostics(&build_output, filename, &source_code);
+        let diagnostics = build_output_to_diagnostics(&build_output.errors, filename, &source_code);
         assert!(!diagnostics.is_empty(), "no diagnostics found");
 
         let diag = &diagnostics[0];
@@ -238,7 +270,7 @@ libs = ["lib"]
             .and_then(|f| f.to_str())
             .expect("Failed to get filename");
 
-        let diagnostics = build_output_to_diagnostics(&build_output, filename, &source_code);
+        let diagnostics = build_output_to_diagnostics(&build_output.errors, filename, &source_code);
         assert!(!diagnostics.is_empty(), "Expected at least one diagnostic");
 
         let diag = &diagnostics[0];
@@ -251,13 +283,17 @@ libs = ["lib"]
 
     #[tokio::test]
     async fn test_ignored_code_for_tests() {
+        let ignored = |error: serde_json::Value| {
+            let output = serde_json::json!({ "errors": [error] });
+            ignored_code_for_tests(&compiler_errors(&output)[0])
+        };
         let error_json = serde_json::json!({
             "errorCode": "5574",
             "sourceLocation": {
                 "file": "test/ERC6909Claims.t.sol"
             }
         });
-        assert!(ignored_code_for_tests(&error_json));
+        assert!(ignored(error_json));
 
         let error_json_non_test = serde_json::json!({
             "errorCode": "5574",
@@ -265,7 +301,7 @@ libs = ["lib"]
                 "file": "contracts/ERC6909Claims.sol"
             }
         });
-        assert!(!ignored_code_for_tests(&error_json_non_test));
+        assert!(!ignored(error_json_non_test));
 
         let error_json_other_code = serde_json::json!({
             "errorCode": "1234",
@@ -273,6 +309,111 @@ libs = ["lib"]
                 "file": "test/ERC6909Claims.t.sol"
             }
         });
-        assert!(!ignored_code_for_tests(&error_json_other_code));
+        assert!(!ignored(error_json_other_code));
+    }
+
+    #[test]
+    fn test_build_output_to_diagnostics_borrowed_parse() {
+        let content = "contract A {\n    uint x\n}\n";
+        let output = serde_json::json!({
+            "errors": [{
+                "sourceLocation": { "file": "src/A.sol", "start": 17, "end": 23 },
+                "type": "ParserError",
+                "component": "general",
+                "severity": "error",
+                "errorCode": "2314",
+                "message": "Expected ';' but got '}'",
+                "formattedMessage": "ParserError: Expected ';'"
+            }, {
+                "sourceLocation": { "file": "src/Other.sol", "start": 0, "end": 1 },
+                "severity": "warning",
+                "message": "unrelated"
+            }, {
+                "severity": "error",
+                "message": "no location"
+            }, {
+                // Not a compiler error, skipped without losing the others
+                "sourceLocation": { "file": "src/A.sol", "start": "17" },
+                "message": "malformed"
+            },
+            "not an error",
+            {
+                "sourceLocation": { "file": "src/A.sol" },
+                "severity": "warning",
+                "message": "no span"
+            }]
+        });
+
+        let diagnostics = build_output_to_diagnostics(&compiler_errors(&output), "A.sol", content);
+        assert_eq!(diagnostics.len(), 2);
+        let diag = &diagnostics[0];
+        assert_eq!(diag.message, "[forge build] Expected ';' but got '}'");
+        assert_eq!(diag.code, Some(NumberOrString::String("2314".to_string())));
+        assert_eq!(diag.range.start, Position::new(1, 4));
+        assert_eq!(diag.range.end, Position::new(1, 10));
+        assert_eq!(diagnostics[1].message, "[forge build] no span");
+        assert_eq!(diagnostics[1].range.start, Position::new(0, 0));
+    }
+
+    #[test]
+    fn test_diagnostic_columns_count_utf16() {
+        // `é` is two bytes and one UTF-16 code unit, and lines end with `\r\n`
+        let content = "string s = \"é\";\r\nuint x\r\n}";
+        let start = content.find('x').unwrap();
+        let output = serde_json::json!({ "errors": [{
+            "sourceLocation": { "file": "src/A.sol", "start": start - 5, "end": start + 1 },
+            "severity": "error",
+            "message": "Expected ';'"
+        }]});
+        let diagnostics = build_output_to_diagnostics(&compiler_errors(&output), "A.sol", content);
+        assert_eq!(
+            diagnostics[0].range,
+            Range::new(Position::new(1, 0), Position::new(1, 6))
+        );
+
+        let output = serde_json::json!({ "errors": [{
+            "sourceLocation": { "file": "src/A.sol", "start": 11, "end": 15 },
+            "severity": "warning",
+            "message": "Unused"
+        }]});
+        let diagnostics = build_output_to_diagnostics(&compiler_errors(&output), "A.sol", content);
+        assert_eq!(
+            diagnostics[0].range,
+            Range::new(Position::new(0, 11), Position::new(0, 14))
+        );
+    }
+
+    #[test]
+    fn test_project_build_diagnostics_match_paths() {
+        let output = serde_json::json!({ "errors": [
+            {
+                "sourceLocation": { "file": "src/Vault.sol", "start": 0, "end": 8 },
+                "severity": "error",
+                "errorCode": "7576",
+                "message": "Undeclared identifier."
+            },
+            {
+                "sourceLocation": { "file": "test/mocks/Vault.sol", "start": 0, "end": 8 },
+                "severity": "warning",
+                "errorCode": "2072",
+                "message": "Unused local variable."
+            }
+        ]});
+        let root = Path::new("/project");
+        let content = "contract Vault {}";
+
+        let diagnostics =
+            project_build_diagnostics(&output, root, &root.join("src/Vault.sol"), content);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].message,
+            "[forge build] Undeclared identifier."
+        );
+
+        // By name alone both files would match
+        assert_eq!(
+            build_output_to_diagnostics(&compiler_errors(&output), "Vault.sol", content).len(),
+            2
+        );
     }
 }
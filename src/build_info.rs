@@ -0,0 +1,502 @@
+//! Loading of Foundry build-info files (`out/build-info/*.json`).
+//!
+//! Build-info files may be stored plain or gzip/zstd compressed. Files above a size limit
+//! (and every compressed file, whose decompressed size is unknown up front) are never
+//! materialized as a whole `serde_json::Value`: only the `source_id_to_path` and
+//! `output.sources` sections are extracted while streaming, everything else is skipped.
+//!
+//! The returned value has the same shape as `forge build --json --ast` output, so it can
+//! be handed directly to the goto/references machinery. That output, as forge prints it,
+//! gets the same limit in [`read_forge_output`]: above it `contracts` is skipped.
+//!
+//! The limit is the `largeFiles.buildOutputBytes` setting, shared with the runners through
+//! a [`SizeLimit`].
+
+use crate::runner::{BuildOutput, CompileOutput, Runner, RunnerError};
+use serde::{Deserialize, de::IgnoredAny};
+use serde_json::{Map, Value, json};
+use std::{
+    fs::File,
+    io::{BufReader, Read},
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+use tower_lsp::async_trait;
+
+/// Default size (in bytes) above which build output is streamed section by section, the
+/// default of `largeFiles.buildOutputBytes`.
+pub const DEFAULT_MAX_BUILD_INFO_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Size above which build output is read section by section, which the settings can
+/// change while runners holding it keep running.
+#[derive(Debug, Clone)]
+pub struct SizeLimit(Arc<AtomicU64>);
+
+impl SizeLimit {
+    pub fn new(bytes: u64) -> Self {
+        Self(Arc::new(AtomicU64::new(bytes)))
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    pub fn set(&self, bytes: u64) {
+        self.0.store(bytes, Ordering::Relaxed);
+    }
+}
+
+impl Default for SizeLimit {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_BUILD_INFO_SIZE)
+    }
+}
+
+/// Build-info directory relative to the project root, for Foundry's default `out` dir.
+pub const BUILD_INFO_DIR: &str = "out/build-info";
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+/// Detect the compression format from the first bytes of a file.
+pub fn detect_compression(header: &[u8]) -> Compression {
+    if header.starts_with(&GZIP_MAGIC) {
+        Compression::Gzip
+    } else if header.starts_with(&ZSTD_MAGIC) {
+        Compression::Zstd
+    } else {
+        Compression::None
+    }
+}
+
+/// The only parts of a build-info file the server needs. Unknown fields (`input`,
+/// `output.contracts`, ...) are skipped by the deserializer without being allocated.
+#[derive(Debug, Default, Deserialize)]
+struct BuildInfoSections {
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(default)]
+    source_id_to_path: Map<String, Value>,
+    #[serde(default)]
+    output: OutputSections,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct OutputSections {
+    #[serde(default)]
+    sources: Map<String, Value>,
+}
+
+/// Parse `stdout`, the output of `forge build --json --ast`. Output above `max_size` skips
+/// the `contracts` section in the deserializer without allocating it. Entries of `errors`
+/// that are not compiler errors are skipped.
+pub fn read_forge_output(stdout: &[u8], max_size: u64) -> Result<BuildOutput, RunnerError> {
+    if stdout.len() as u64 <= max_size {
+        let mut output: CompileOutput<'_, Value> = serde_json::from_slice(stdout)?;
+        let contracts = output.contracts.take();
+        Ok(output.into_owned(contracts))
+    } else {
+        let output: CompileOutput<'_, IgnoredAny> = serde_json::from_slice(stdout)?;
+        Ok(output.into_owned(None))
+    }
+}
+
+/// Read a single build-info file, decompressing and streaming as needed.
+pub fn read_build_info(path: &Path, max_size: u64) -> Result<Value, RunnerError> {
+    let mut file = File::open(path).map_err(RunnerError::ReadBuildInfo)?;
+    let size = file.metadata().map_err(RunnerError::ReadBuildInfo)?.len();
+
+    let mut header = [0u8; 4];
+    let read = file.read(&mut header).map_err(RunnerError::ReadBuildInfo)?;
+    let compression = detect_compression(&header[..read]);
+    let mut reader = (&header[..read]).chain(BufReader::new(file));
+
+    match compression {
+        Compression::None if size <= max_size => {
+            let mut bytes = Vec::with_capacity(size as usize);
+            reader
+                .read_to_end(&mut bytes)
+                .map_err(RunnerError::ReadBuildInfo)?;
+            let full: Value = serde_json::from_slice(&bytes)?;
+            Ok(from_full_build_info(full))
+        }
+        Compression::None => streamed(reader),
+        Compression::Gzip => streamed(flate2::read::GzDecoder::new(reader)),
+        Compression::Zstd => {
+            let decoder = ruzstd::decoding::StreamingDecoder::new(reader).map_err(|e| {
+                RunnerError::ReadBuildInfo(std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+            })?;
+            streamed(decoder)
+        }
+    }
+}
+
+/// Read a whole build-info file, `input` and `output.contracts` included, decompressing as
+/// needed. Meant for the occasional command; requests use [`read_build_info`].
+pub fn read_full_build_info(path: &Path) -> Result<Value, RunnerError> {
+    let mut file = File::open(path).map_err(RunnerError::ReadBuildInfo)?;
+    let mut header = [0u8; 4];
+    let read = file.read(&mut header).map_err(RunnerError::ReadBuildInfo)?;
+    let reader = (&header[..read]).chain(BufReader::new(file));
+    let value = match detect_compression(&header[..read]) {
+        Compression::None => serde_json::from_reader(reader)?,
+        Compression::Gzip => serde_json::from_reader(flate2::read::GzDecoder::new(reader))?,
+        Compression::Zstd => {
+            let decoder = ruzstd::decoding::StreamingDecoder::new(reader).map_err(|e| {
+                RunnerError::ReadBuildInfo(std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+            })?;
+            serde_json::from_reader(decoder)?
+        }
+    };
+    Ok(value)
+}
+
+/// The build-info files in `build_info_dir`, in a stable (sorted) order.
+pub fn build_info_files(build_info_dir: &Path) -> Result<Vec<PathBuf>, RunnerError> {
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(build_info_dir)
+        .map_err(RunnerError::ReadBuildInfo)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| is_build_info_file(path))
+        .collect();
+    paths.sort();
+    Ok(paths)
+}
+
+/// Read every build-info file in `build_info_dir`, in a stable (sorted) order.
+pub fn read_build_info_dir(
+    build_info_dir: &Path,
+    max_size: u64,
+) -> Result<Vec<Value>, RunnerError> {
+    build_info_files(build_info_dir)?
+        .iter()
+        .map(|path| read_build_info(path, max_size))
+        .collect()
+}
+
+fn is_build_info_file(path: &Path) -> bool {
+    let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+        return false;
+    };
+    name.ends_with(".json") || name.ends_with(".json.gz") || name.ends_with(".json.zst")
+}
+
+fn streamed<R: Read>(reader: R) -> Result<Value, RunnerError> {
+    let mut de = serde_json::Deserializer::from_reader(BufReader::new(reader));
+    let sections = BuildInfoSections::deserialize(&mut de)?;
+    Ok(to_forge_output(sections, Value::Null))
+}
+
+/// Pick the relevant sections from a fully loaded build-info value.
+fn from_full_build_info(mut full: Value) -> Value {
+    let mut take = |key: &str| full.get_mut(key).map(Value::take);
+    let id = take("id").and_then(|v| v.as_str().map(str::to_string));
+    let source_id_to_path = match take("source_id_to_path") {
+        Some(Value::Object(map)) => map,
+        _ => Map::new(),
+    };
+    let mut output = take("output").unwrap_or(Value::Null);
+    let sources = match output.get_mut("sources").map(Value::take) {
+        Some(Value::Object(map)) => map,
+        _ => Map::new(),
+    };
+    let errors = output
+        .get_mut("errors")
+        .map(Value::take)
+        .unwrap_or(Value::Null);
+
+    to_forge_output(
+        BuildInfoSections {
+            id,
+            source_id_to_path,
+            output: OutputSections { sources },
+        },
+        errors,
+    )
+}
+
+/// Reshape build-info sections into `forge build --json` form:
+/// `sources.<path>` becomes `[{ "source_file": { "id", "ast" } }]`.
+fn to_forge_output(sections: BuildInfoSections, errors: Value) -> Value {
+    let build_id = sections.id.clone().unwrap_or_default();
+    let sources: Map<String, Value> = sections
+        .output
+        .sources
+        .into_iter()
+        .map(|(path, source_file)| {
+            (
+                path,
+                json!([{ "source_file": source_file, "build_id": build_id }]),
+            )
+        })
+        .collect();
+
+    let mut output = json!({
+        "sources": sources,
+        "build_infos": [{
+            "id": sections.id,
+            "source_id_to_path": sections.source_id_to_path,
+        }],
+    });
+    if errors.is_array() {
+        output["errors"] = errors;
+    }
+    output
+}
+
+/// Find the Foundry project root (the nearest directory with a `foundry.toml`) of `path`.
+pub fn find_project_root(path: &Path) -> Option<PathBuf> {
+    path.ancestors()
+        .find(|dir| dir.join("foundry.toml").is_file())
+        .map(Path::to_path_buf)
+}
+
+/// Runner that never spawns a process. AST requests are answered from the build-info files
+/// of the last `forge build`; builds and lints are refused.
+#[derive(Debug, Clone, Default)]
+pub struct BuildInfoRunner {
+    max_size: SizeLimit,
+}
+
+impl BuildInfoRunner {
+    /// A runner streaming the build-info files above `max_size`.
+    pub fn new(max_size: SizeLimit) -> Self {
+        Self { max_size }
+    }
+
+    fn build_infos(&self, root: &Path) -> Result<Vec<Value>, RunnerError> {
+        read_build_info_dir(&root.join(BUILD_INFO_DIR), self.max_size.get())
+    }
+
+    /// The first build info that compiled `file`.
+    fn file_ast(&self, file: &str) -> Result<Value, RunnerError> {
+        let path = Path::new(file);
+        let root = find_project_root(path)
+            .ok_or_else(|| RunnerError::MissingBuildInfo(file.to_string()))?;
+        let relative = path.strip_prefix(&root).unwrap_or(path);
+        let key = relative.to_string_lossy();
+
+        self.build_infos(&root)?
+            .into_iter()
+            .find(|info| info["sources"].get(key.as_ref()).is_some())
+            .ok_or_else(|| RunnerError::MissingBuildInfo(file.to_string()))
+    }
+
+    /// Every build info of the project, merged into a single output.
+    fn merged_ast(&self, root: &str) -> Result<Value, RunnerError> {
+        let mut sources = Map::new();
+        let mut build_infos = Vec::new();
+        for mut info in self.build_infos(Path::new(root))? {
+            if let Some(Value::Object(info_sources)) = info.get_mut("sources").map(Value::take) {
+                sources.extend(info_sources);
+            }
+            if let Some(Value::Array(infos)) = info.get_mut("build_infos").map(Value::take) {
+                build_infos.extend(infos);
+            }
+        }
+        Ok(json!({ "sources": sources, "build_infos": build_infos }))
+    }
+}
+
+#[async_trait]
+impl Runner for BuildInfoRunner {
+    async fn build(&self, _: &str) -> Result<BuildOutput, RunnerError> {
+        Err(RunnerError::SubprocessDisabled)
+    }
+
+    async fn lint(&self, _: &str) -> Result<Value, RunnerError> {
+        Err(RunnerError::SubprocessDisabled)
+    }
+
+    async fn ast(&self, file: &str) -> Result<Value, RunnerError> {
+        self.file_ast(file)
+    }
+
+    async fn project_ast(&self, root: &str) -> Result<Value, RunnerError> {
+        self.merged_ast(root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn sample_build_info() -> Value {
+        json!({
+            "id": "abc123",
+            "source_id_to_path": { "0": "src/A.sol" },
+            "language": "Solidity",
+            "input": { "sources": { "src/A.sol": { "content": "contract A {}" } } },
+            "output": {
+                "errors": [],
+                "contracts": { "src/A.sol": { "A": { "abi": [] } } },
+                "sources": {
+                    "src/A.sol": {
+                        "id": 0,
+                        "ast": { "id": 1, "nodeType": "SourceUnit", "src": "0:13:0", "absolutePath": "src/A.sol" }
+                    }
+                }
+            }
+        })
+    }
+
+    fn write_file(dir: &Path, name: &str, bytes: &[u8]) -> PathBuf {
+        let path = dir.join(name);
+        std::fs::write(&path, bytes).expect("write build info");
+        path
+    }
+
+    fn assert_forge_shape(value: &Value) {
+        let source = &value["sources"]["src/A.sol"][0]["source_file"];
+        assert_eq!(source["id"], 0);
+        assert_eq!(source["ast"]["nodeType"], "SourceUnit");
+        assert_eq!(
+            value["build_infos"][0]["source_id_to_path"]["0"],
+            "src/A.sol"
+        );
+    }
+
+    #[test]
+    fn test_detect_compression() {
+        assert_eq!(detect_compression(&[0x1f, 0x8b, 0x08]), Compression::Gzip);
+        assert_eq!(detect_compression(&ZSTD_MAGIC), Compression::Zstd);
+        assert_eq!(detect_compression(b"{\"id\""), Compression::None);
+        assert_eq!(detect_compression(&[]), Compression::None);
+    }
+
+    #[test]
+    fn test_read_plain_build_info() {
+        let dir = tempfile::tempdir().unwrap();
+        let bytes = serde_json::to_vec(&sample_build_info()).unwrap();
+        let path = write_file(dir.path(), "abc123.json", &bytes);
+
+        let full = read_build_info(&path, DEFAULT_MAX_BUILD_INFO_SIZE).unwrap();
+        assert_forge_shape(&full);
+        assert!(full["errors"].is_array());
+
+        // Exceeding the limit switches to streaming extraction with the same shape
+        let streamed = read_build_info(&path, 1).unwrap();
+        assert_forge_shape(&streamed);
+        assert!(streamed.get("errors").is_none());
+    }
+
+    #[test]
+    fn test_read_forge_output() {
+        let output = json!({
+            "errors": [{
+                "sourceLocation": { "file": "src/A.sol", "start": 0, "end": 13 },
+                "type": "Warning",
+                "component": "general",
+                "severity": "warning",
+                "errorCode": "2072",
+                "message": "Unused local variable.",
+                "formattedMessage": "Warning: Unused local variable."
+            }],
+            "sources": sample_build_info()["output"]["sources"]
+                .as_object()
+                .unwrap()
+                .iter()
+                .map(|(path, source_file)| (path.clone(), json!([{ "source_file": source_file }])))
+                .collect::<Map<String, Value>>(),
+            "contracts": { "src/A.sol": { "A": [{ "contract": { "abi": [] } }] } },
+            "build_infos": [{ "id": "abc123", "source_id_to_path": { "0": "src/A.sol" } }]
+        });
+        let stdout = serde_json::to_vec(&output).unwrap();
+
+        let parsed = read_forge_output(&stdout, DEFAULT_MAX_BUILD_INFO_SIZE).unwrap();
+        assert_eq!(parsed.into_value(), output);
+
+        // Past the limit the contracts are skipped, the rest is kept as forge printed it
+        let sections = read_forge_output(&stdout, 1).unwrap().into_value();
+        assert_forge_shape(&sections);
+        assert_eq!(sections["errors"], output["errors"]);
+        assert!(sections.get("contracts").is_none());
+
+        // An entry that is not a compiler error is skipped, not the whole output
+        let stdout = serde_json::to_vec(&json!({
+            "errors": [{ "sourceLocation": { "file": 0 } }, output["errors"][0], null],
+            "sources": output["sources"]
+        }))
+        .unwrap();
+        let parsed = read_forge_output(&stdout, DEFAULT_MAX_BUILD_INFO_SIZE).unwrap();
+        assert_eq!(parsed.errors.len(), 1);
+        assert_eq!(parsed.errors[0].message, "Unused local variable.");
+        assert_eq!(
+            parsed.sources.len(),
+            output["sources"].as_object().unwrap().len()
+        );
+    }
+
+    #[test]
+    fn test_read_compressed_build_info() {
+        let dir = tempfile::tempdir().unwrap();
+        let bytes = serde_json::to_vec(&sample_build_info()).unwrap();
+
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        gz.write_all(&bytes).unwrap();
+        let gz_path = write_file(dir.path(), "abc123.json.gz", &gz.finish().unwrap());
+        assert_forge_shape(&read_build_info(&gz_path, DEFAULT_MAX_BUILD_INFO_SIZE).unwrap());
+
+        let zst = ruzstd::encoding::compress_to_vec(
+            bytes.as_slice(),
+            ruzstd::encoding::CompressionLevel::Fastest,
+        );
+        let zst_path = write_file(dir.path(), "abc123.json.zst", &zst);
+        assert_forge_shape(&read_build_info(&zst_path, DEFAULT_MAX_BUILD_INFO_SIZE).unwrap());
+
+        // Whole files keep what the server otherwise skips
+        let full = read_full_build_info(&zst_path).unwrap();
+        assert_eq!(full, sample_build_info());
+        assert_eq!(read_full_build_info(&gz_path).unwrap(), sample_build_info());
+    }
+
+    #[tokio::test]
+    async fn test_build_info_runner() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("foundry.toml"), "[profile.default]\n").unwrap();
+        let build_info_dir = dir.path().join(BUILD_INFO_DIR);
+        std::fs::create_dir_all(&build_info_dir).unwrap();
+        let bytes = serde_json::to_vec(&sample_build_info()).unwrap();
+        write_file(&build_info_dir, "abc123.json", &bytes);
+
+        let runner = BuildInfoRunner::default();
+        let file = dir.path().join("src/A.sol");
+        let ast = runner.ast(file.to_str().unwrap()).await.unwrap();
+        assert_forge_shape(&ast);
+
+        let root = dir.path().to_str().unwrap();
+        assert_forge_shape(&runner.project_ast(root).await.unwrap());
+
+        let missing = dir.path().join("src/B.sol");
+        assert!(matches!(
+            runner.ast(missing.to_str().unwrap()).await,
+            Err(RunnerError::MissingBuildInfo(_))
+        ));
+        assert!(matches!(
+            runner.build(file.to_str().unwrap()).await,
+            Err(RunnerError::SubprocessDisabled)
+        ));
+    }
+
+    #[test]
+    fn test_read_build_info_dir_skips_other_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let bytes = serde_json::to_vec(&sample_build_info()).unwrap();
+        write_file(dir.path(), "abc123.json", &bytes);
+        write_file(dir.path(), "notes.txt", b"not json");
+
+        let infos = read_build_info_dir(dir.path(), DEFAULT_MAX_BUILD_INFO_SIZE).unwrap();
+        assert_eq!(infos.len(), 1);
+        assert_forge_shape(&infos[0]);
+    }
+}
@@ -0,0 +1,455 @@
+//! Call hierarchy: the functions and modifiers calling a function across the build, and
+//! those it calls, from the `FunctionCall` and `ModifierInvocation` nodes of the AST.
+//!
+//! Calls of the declarations a function overrides, like calls through an interface, count
+//! as incoming calls of the function too.
+
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use tower_lsp::lsp_types::{
+    CallHierarchyIncomingCall, CallHierarchyItem, CallHierarchyOutgoingCall, Position, Range,
+    SymbolKind, Url,
+};
+
+use crate::{
+    ast::{self, location_bounds, span_bounds},
+    goto::{bytes_to_pos, pos_to_bytes},
+    paths,
+};
+
+/// A function or modifier.
+struct Callable<'a> {
+    node: &'a Value,
+    /// Source path as reported by forge.
+    path: &'a str,
+    /// Name of the declaring contract, `None` for free functions.
+    contract: Option<&'a str>,
+}
+
+/// A call of `callee` from `caller`, at the byte span of the called name.
+struct Call {
+    caller: u64,
+    callee: u64,
+    span: (usize, usize),
+}
+
+/// Source files read from disk, by forge path, to turn byte offsets into positions.
+#[derive(Default)]
+struct Sources {
+    sources: HashMap<String, Option<Vec<u8>>>,
+}
+
+impl Sources {
+    fn get(&mut self, path: &str) -> Option<&[u8]> {
+        self.sources
+            .entry(path.to_string())
+            .or_insert_with(|| std::fs::read(paths::resolve_source_path(path)?).ok())
+            .as_deref()
+    }
+}
+
+fn span_range(source: &[u8], (start, end): (usize, usize)) -> Option<Range> {
+    Some(Range::new(
+        bytes_to_pos(source, start)?,
+        bytes_to_pos(source, end)?,
+    ))
+}
+
+/// The byte span of the name of `node`, or of all of it for unnamed functions.
+fn name_span(node: &Value) -> Option<(usize, usize)> {
+    location_bounds(node, "nameLocation")
+        .filter(|(start, end)| start < end)
+        .or_else(|| span_bounds(node))
+}
+
+/// The declaration `callee` refers to and the span of the called name.
+fn called(callee: &Value) -> Option<(u64, (usize, usize))> {
+    let id = callee.get("referencedDeclaration")?.as_u64()?;
+    let span = if callee.get("nodeType").and_then(Value::as_str) == Some("MemberAccess") {
+        location_bounds(callee, "memberLocation").or_else(|| span_bounds(callee))
+    } else {
+        span_bounds(callee)
+    };
+    Some((id, span?))
+}
+
+/// The calls made by `node`: function calls, and modifier invocations of functions.
+fn calls_of(node: &Value) -> Vec<(u64, (usize, usize))> {
+    let mut calls = Vec::new();
+    ast::walk(node, &mut |node| {
+        let callee = match node.get("nodeType").and_then(Value::as_str) {
+            Some("FunctionCall")
+                if matches!(
+                    node.get("kind").and_then(Value::as_str),
+                    None | Some("functionCall")
+                ) =>
+            {
+                node.get("expression").map(|expression| {
+                    // `f{value: 1}()` calls `f`
+                    if expression.get("nodeType").and_then(Value::as_str)
+                        == Some("FunctionCallOptions")
+                    {
+                        expression.get("expression").unwrap_or(expression)
+                    } else {
+                        expression
+                    }
+                })
+            }
+            Some("ModifierInvocation") => node.get("modifierName"),
+            _ => None,
+        };
+        calls.extend(callee.and_then(called));
+    });
+    calls
+}
+
+/// The functions and modifiers of the build and the calls between them.
+struct CallGraph<'a> {
+    callables: HashMap<u64, Callable<'a>>,
+    calls: Vec<Call>,
+}
+
+impl<'a> CallGraph<'a> {
+    fn new(ast_data: &'a Value) -> Self {
+        let mut graph = Self {
+            callables: HashMap::new(),
+            calls: Vec::new(),
+        };
+        let sources = ast_data.get("sources").and_then(Value::as_object);
+        for (path, contents) in sources.into_iter().flatten() {
+            let Some(source_ast) = contents
+                .get(0)
+                .and_then(|content| content.get("source_file"))
+                .and_then(|source_file| source_file.get("ast"))
+            else {
+                continue;
+            };
+            let path = source_ast
+                .get("absolutePath")
+                .and_then(Value::as_str)
+                .unwrap_or(path);
+
+            let top_level = source_ast
+                .get("nodes")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten();
+            for node in top_level {
+                if node.get("nodeType").and_then(Value::as_str) == Some("ContractDefinition") {
+                    let contract = node.get("name").and_then(Value::as_str);
+                    let members = node.get("nodes").and_then(Value::as_array);
+                    for member in members.into_iter().flatten() {
+                        graph.add(member, path, contract);
+                    }
+                } else {
+                    graph.add(node, path, None);
+                }
+            }
+        }
+        graph
+    }
+
+    fn add(&mut self, node: &'a Value, path: &'a str, contract: Option<&'a str>) {
+        if !matches!(
+            node.get("nodeType").and_then(Value::as_str),
+            Some("FunctionDefinition" | "ModifierDefinition")
+        ) {
+            return;
+        }
+        let Some(id) = node.get("id").and_then(Value::as_u64) else {
+            return;
+        };
+        self.calls
+            .extend(calls_of(node).into_iter().map(|(callee, span)| Call {
+                caller: id,
+                callee,
+                span,
+            }));
+        self.callables.insert(
+            id,
+            Callable {
+                node,
+                path,
+                contract,
+            },
+        );
+    }
+
+    /// The callable whose name is at the start of the selection of `item`.
+    fn find(&self, item: &CallHierarchyItem, sources: &mut Sources) -> Option<u64> {
+        let key = paths::uri_to_key(&item.uri)?;
+        self.callables.iter().find_map(|(id, callable)| {
+            let uri = paths::path_to_uri(&paths::resolve_source_path(callable.path)?)?;
+            if paths::uri_to_key(&uri)? != key {
+                return None;
+            }
+            let offset = pos_to_bytes(sources.get(callable.path)?, item.selection_range.start);
+            let (start, end) = name_span(callable.node)?;
+            (start..=end).contains(&offset).then_some(*id)
+        })
+    }
+
+    /// `id` and the declarations it overrides, directly or not.
+    fn with_bases(&self, id: u64) -> Vec<u64> {
+        let mut found = vec![id];
+        let mut next = 0;
+        while let Some(&current) = found.get(next) {
+            next += 1;
+            let bases = self
+                .callables
+                .get(&current)
+                .and_then(|callable| callable.node.get("baseFunctions"))
+                .and_then(Value::as_array);
+            for base in bases.into_iter().flatten().filter_map(Value::as_u64) {
+                if !found.contains(&base) {
+                    found.push(base);
+                }
+            }
+        }
+        found
+    }
+
+    fn item(&self, id: u64, sources: &mut Sources) -> Option<CallHierarchyItem> {
+        let callable = self.callables.get(&id)?;
+        let node = callable.node;
+        let name = node.get("name").and_then(Value::as_str).unwrap_or_default();
+        let (name, kind) = match node.get("kind").and_then(Value::as_str) {
+            _ if node.get("nodeType").and_then(Value::as_str) == Some("ModifierDefinition") => {
+                (name, SymbolKind::METHOD)
+            }
+            Some("constructor") => ("constructor", SymbolKind::CONSTRUCTOR),
+            Some(kind @ ("fallback" | "receive")) => (kind, SymbolKind::FUNCTION),
+            _ => (name, SymbolKind::FUNCTION),
+        };
+        let uri = paths::path_to_uri(&paths::resolve_source_path(callable.path)?)?;
+        let source = sources.get(callable.path)?;
+        Some(CallHierarchyItem {
+            name: name.to_string(),
+            kind,
+            tags: None,
+            detail: callable.contract.map(str::to_string),
+            uri,
+            range: span_range(source, span_bounds(node)?)?,
+            selection_range: span_range(source, name_span(node)?)?,
+            data: None,
+        })
+    }
+
+    /// Ranges of `spans` in the file of `id`.
+    fn ranges(&self, id: u64, spans: &[(usize, usize)], sources: &mut Sources) -> Vec<Range> {
+        let Some(source) = self
+            .callables
+            .get(&id)
+            .and_then(|callable| sources.get(callable.path))
+        else {
+            return vec![];
+        };
+        spans
+            .iter()
+            .filter_map(|&span| span_range(source, span))
+            .collect()
+    }
+}
+
+/// The function or modifier at `position`, either its name or a call of it.
+pub fn prepare(
+    ast_data: &Value,
+    file_uri: &Url,
+    position: Position,
+    source_bytes: &[u8],
+) -> Option<CallHierarchyItem> {
+    let symbol = ast::symbol_at_position(ast_data, file_uri, position, source_bytes)?;
+    CallGraph::new(ast_data).item(symbol.declaration_id, &mut Sources::default())
+}
+
+/// The functions and modifiers calling `item`, or a declaration it overrides, with the
+/// ranges of the calls.
+pub fn incoming_calls(
+    ast_data: &Value,
+    item: &CallHierarchyItem,
+) -> Vec<CallHierarchyIncomingCall> {
+    let graph = CallGraph::new(ast_data);
+    let mut sources = Sources::default();
+    let Some(id) = graph.find(item, &mut sources) else {
+        return vec![];
+    };
+    let targets = graph.with_bases(id);
+
+    let mut callers: BTreeMap<u64, Vec<(usize, usize)>> = BTreeMap::new();
+    for call in graph
+        .calls
+        .iter()
+        .filter(|call| targets.contains(&call.callee))
+    {
+        callers.entry(call.caller).or_default().push(call.span);
+    }
+    let mut incoming: Vec<CallHierarchyIncomingCall> = callers
+        .into_iter()
+        .filter_map(|(caller, spans)| {
+            Some(CallHierarchyIncomingCall {
+                from: graph.item(caller, &mut sources)?,
+                from_ranges: graph.ranges(caller, &spans, &mut sources),
+            })
+        })
+        .collect();
+    incoming.sort_by(|a, b| {
+        (a.from.uri.as_str(), a.from.range.start.line)
+            .cmp(&(b.from.uri.as_str(), b.from.range.start.line))
+    });
+    incoming
+}
+
+/// The functions and modifiers `item` calls, in the order of their first call, with the
+/// ranges of the calls.
+pub fn outgoing_calls(
+    ast_data: &Value,
+    item: &CallHierarchyItem,
+) -> Vec<CallHierarchyOutgoingCall> {
+    let graph = CallGraph::new(ast_data);
+    let mut sources = Sources::default();
+    let Some(id) = graph.find(item, &mut sources) else {
+        return vec![];
+    };
+
+    let mut callees: Vec<(u64, Vec<(usize, usize)>)> = Vec::new();
+    for call in graph.calls.iter().filter(|call| call.caller == id) {
+        if !graph.callables.contains_key(&call.callee) {
+            continue;
+        }
+        match callees
+            .iter_mut()
+            .find(|(callee, _)| *callee == call.callee)
+        {
+            Some((_, spans)) => spans.push(call.span),
+            None => callees.push((call.callee, vec![call.span])),
+        }
+    }
+    callees.sort_by_key(|(_, spans)| spans[0].0);
+    callees
+        .into_iter()
+        .filter_map(|(callee, spans)| {
+            Some(CallHierarchyOutgoingCall {
+                to: graph.item(callee, &mut sources)?,
+                from_ranges: graph.ranges(id, &spans, &mut sources),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax;
+
+    const OWNABLE: &str = "\
+contract Ownable {
+    address internal owner;
+
+    modifier onlyOwner() {
+        _checkOwner();
+        _;
+    }
+
+    function _checkOwner() internal view {}
+
+    function transferOwnership(address next) public onlyOwner {
+        _transferOwnership(next);
+    }
+
+    function renounceOwnership() public onlyOwner {
+        _transferOwnership(address(0));
+    }
+
+    function _transferOwnership(address next) internal {
+        owner = next;
+    }
+}
+";
+
+    fn position_of(needle: &str, nth: usize) -> Position {
+        let offset = OWNABLE.match_indices(needle).nth(nth).unwrap().0;
+        bytes_to_pos(OWNABLE.as_bytes(), offset).unwrap()
+    }
+
+    fn project() -> (tempfile::TempDir, Value, Url) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("Ownable.sol");
+        std::fs::write(&path, OWNABLE).unwrap();
+        let ast = syntax::parse(&path.to_string_lossy(), OWNABLE);
+        (dir, ast, Url::from_file_path(path).unwrap())
+    }
+
+    #[test]
+    fn test_incoming_calls_across_callers() {
+        let (_dir, ast, uri) = project();
+        // From a call of the function
+        let item = prepare(
+            &ast,
+            &uri,
+            position_of("_transferOwnership", 1),
+            OWNABLE.as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(item.name, "_transferOwnership");
+        assert_eq!(item.detail.as_deref(), Some("Ownable"));
+        assert_eq!(
+            item.selection_range.start,
+            position_of("_transferOwnership", 2)
+        );
+
+        let incoming = incoming_calls(&ast, &item);
+        let callers: Vec<(&str, Vec<Position>)> = incoming
+            .iter()
+            .map(|call| {
+                let starts = call.from_ranges.iter().map(|range| range.start).collect();
+                (call.from.name.as_str(), starts)
+            })
+            .collect();
+        assert_eq!(
+            callers,
+            [
+                (
+                    "transferOwnership",
+                    vec![position_of("_transferOwnership", 0)]
+                ),
+                (
+                    "renounceOwnership",
+                    vec![position_of("_transferOwnership", 1)]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_outgoing_calls_include_modifiers() {
+        let (_dir, ast, uri) = project();
+        let item = prepare(
+            &ast,
+            &uri,
+            position_of("transferOwnership", 0),
+            OWNABLE.as_bytes(),
+        )
+        .unwrap();
+        let outgoing = outgoing_calls(&ast, &item);
+        let callees: Vec<(&str, SymbolKind)> = outgoing
+            .iter()
+            .map(|call| (call.to.name.as_str(), call.to.kind))
+            .collect();
+        assert_eq!(
+            callees,
+            [
+                ("onlyOwner", SymbolKind::METHOD),
+                ("_transferOwnership", SymbolKind::FUNCTION)
+            ]
+        );
+        assert_eq!(
+            outgoing[0].from_ranges[0].start,
+            position_of("onlyOwner", 1)
+        );
+
+        // The modifier calls on
+        let modifier = outgoing_calls(&ast, &outgoing[0].to);
+        assert_eq!(modifier.len(), 1);
+        assert_eq!(modifier[0].to.name, "_checkOwner");
+    }
+}
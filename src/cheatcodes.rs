@@ -0,0 +1,285 @@
+//! Hover and goto for forge-std's cheatcodes and console libraries: `vm.prank`,
+//! `console.log`, ...
+//!
+//! Tests and scripts call cheatcodes through `vm`, the `Vm` interface at the cheatcode
+//! address that forge-std's `Test` and `Script` declare, and log through its `console`
+//! libraries. Those calls only resolve once the file compiles, so the members after `vm.`,
+//! `console.` and `console2.` are looked up by name in the forge-std sources of the
+//! project instead, straight from the buffer: hovers show every overload with the
+//! documentation forge-std gives it, and goto lists their declarations in `Vm.sol` and the
+//! console libraries. Files that import nothing from forge-std are left to the compiler.
+
+use std::path::{Path, PathBuf};
+use tower_lsp::lsp_types::{
+    Hover, HoverContents, Location, MarkupContent, MarkupKind, Position, Range, Url,
+};
+
+use crate::{
+    grammar::{Token, tokens},
+    hover::{doc_comment_above, strip_comment_markers},
+    project::ProjectConfig,
+    utils,
+};
+
+/// The receivers forge-std declares, and the forge-std files declaring their members, the
+/// first one declaring a member giving its declarations.
+const RECEIVERS: &[(&str, &[&str])] = &[
+    ("vm", &["Vm.sol"]),
+    ("console", &["console.sol"]),
+    ("console2", &["console2.sol", "console.sol"]),
+    ("safeconsole", &["safeconsole.sol"]),
+];
+
+/// Overloads shown in a hover; `console.log` alone has hundreds.
+const MAX_HOVER_OVERLOADS: usize = 8;
+
+/// A function of forge-std a cheatcode or console call may resolve to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Declaration {
+    pub path: PathBuf,
+    /// Range of the function's name.
+    pub range: Range,
+    /// The declaration up to its `;` or body, on one line.
+    pub signature: String,
+    /// Its doc comment, without comment markers.
+    pub docs: String,
+}
+
+fn is_word(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_' || b == b'$'
+}
+
+fn lsp_position(source: &str, offset: usize) -> Position {
+    let (line, character) = utils::byte_offset_to_position(source, offset);
+    Position::new(line, character)
+}
+
+/// The receiver of `receiver.member` whose member covers `offset`, with the member and its
+/// byte range.
+fn member_at(source: &str, offset: usize) -> Option<(&'static str, &str, (usize, usize))> {
+    let bytes = source.as_bytes();
+    let mut start = offset.min(bytes.len());
+    while start > 0 && is_word(bytes[start - 1]) {
+        start -= 1;
+    }
+    let mut end = offset.min(bytes.len());
+    while end < bytes.len() && is_word(bytes[end]) {
+        end += 1;
+    }
+    let dot = source[..start].trim_end();
+    let before = dot.strip_suffix('.')?.trim_end();
+    let receiver_start = before
+        .bytes()
+        .rposition(|b| !is_word(b))
+        .map_or(0, |i| i + 1);
+    // `this.vm.prank` or `a.console.log` aren't forge-std's
+    if before[..receiver_start].trim_end().ends_with('.') || start == end {
+        return None;
+    }
+    let receiver = &before[receiver_start..];
+    let (receiver, _) = RECEIVERS.iter().find(|(name, _)| *name == receiver)?;
+    Some((receiver, &source[start..end], (start, end)))
+}
+
+/// The functions named `name` in `source`, the text of the forge-std file at `path`.
+pub fn declarations_in(source: &str, path: &Path, name: &str) -> Vec<Declaration> {
+    let tokens = tokens(source);
+    let mut declarations = Vec::new();
+    for (i, window) in tokens.windows(3).enumerate() {
+        let [
+            Token::Word(start, "function"),
+            Token::Word(name_start, word),
+            Token::Punct(_, b'('),
+        ] = *window
+        else {
+            continue;
+        };
+        if word != name {
+            continue;
+        }
+        let end = tokens[i + 3..]
+            .iter()
+            .find(|token| matches!(token, Token::Punct(_, b';' | b'{')))
+            .map_or(source.len(), Token::start);
+        let signature = source[start..end]
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .replace("( ", "(")
+            .replace(" )", ")");
+        declarations.push(Declaration {
+            path: path.to_path_buf(),
+            range: Range::new(
+                lsp_position(source, name_start),
+                lsp_position(source, name_start + name.len()),
+            ),
+            signature: format!("{signature};"),
+            docs: strip_comment_markers(&doc_comment_above(source, start)),
+        });
+    }
+    declarations
+}
+
+/// The forge-std functions the `vm.` or `console.` member at `position` of `source`, the
+/// buffer of the file at `path`, may call, with the member's range.
+pub fn cheatcode_at(
+    source: &str,
+    position: Position,
+    path: &Path,
+) -> Option<(Range, Vec<Declaration>)> {
+    if !source.contains("forge-std/") {
+        return None;
+    }
+    let offset = utils::position_to_byte_offset(source, position.line, position.character);
+    let (receiver, member, (start, end)) = member_at(source, offset)?;
+    let config = ProjectConfig::find(path)?;
+    let (_, files) = RECEIVERS.iter().find(|(name, _)| *name == receiver)?;
+    let declarations = files.iter().find_map(|file| {
+        let library = config.resolve_import(path, &format!("forge-std/{file}"))?;
+        let text = std::fs::read_to_string(&library).ok()?;
+        let declarations = declarations_in(&text, &library, member);
+        (!declarations.is_empty()).then_some(declarations)
+    })?;
+    let range = Range::new(lsp_position(source, start), lsp_position(source, end));
+    Some((range, declarations))
+}
+
+/// Hover of the cheatcode or console function at `position`: each overload's signature and
+/// documentation.
+pub fn cheatcode_hover(source: &str, position: Position, path: &Path) -> Option<Hover> {
+    let (range, declarations) = cheatcode_at(source, position, path)?;
+    let mut sections: Vec<String> = declarations
+        .iter()
+        .take(MAX_HOVER_OVERLOADS)
+        .map(|declaration| {
+            let mut section = format!("```solidity\n{}\n```", declaration.signature);
+            if !declaration.docs.is_empty() {
+                section.push_str("\n\n");
+                section.push_str(&declaration.docs);
+            }
+            section
+        })
+        .collect();
+    if declarations.len() > MAX_HOVER_OVERLOADS {
+        let file = declarations[0]
+            .path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        sections.push(format!(
+            "…and {} more overloads in `{file}`",
+            declarations.len() - MAX_HOVER_OVERLOADS
+        ));
+    }
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: sections.join("\n\n---\n\n"),
+        }),
+        range: Some(range),
+    })
+}
+
+/// The declarations in forge-std of the cheatcode or console function at `position`.
+pub fn cheatcode_locations(source: &str, position: Position, path: &Path) -> Vec<Location> {
+    let Some((_, declarations)) = cheatcode_at(source, position, path) else {
+        return vec![];
+    };
+    declarations
+        .into_iter()
+        .filter_map(|declaration| {
+            let uri = Url::from_file_path(&declaration.path).ok()?;
+            Some(Location::new(uri, declaration.range))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    const VM: &str = "\
+interface VmSafe {
+    /// Gets the nonce of an account.
+    function getNonce(address account) external view returns (uint64 nonce);
+}
+
+interface Vm is VmSafe {
+    /// Sets the *next* call's `msg.sender` to be the input address.
+    function prank(address msgSender) external;
+
+    /// Sets the *next* call's `msg.sender` to be the input address,
+    /// and the `tx.origin` to be the second input.
+    function prank(
+        address msgSender,
+        address txOrigin
+    ) external;
+}
+";
+
+    const TEST: &str = "\
+import {Test} from \"forge-std/Test.sol\";
+
+contract VaultTest is Test {
+    function test_deposit() public {
+        vm.prank(alice);
+        this.vm.prank(alice);
+        vault.prank(alice);
+        console2.log(1);
+    }
+}
+";
+
+    #[test]
+    fn test_cheatcode_hover_and_locations() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        fs::write(root.join("foundry.toml"), "[profile.default]\n").unwrap();
+        fs::create_dir_all(root.join("lib/forge-std/src")).unwrap();
+        fs::create_dir_all(root.join("test")).unwrap();
+        let vm = root.join("lib/forge-std/src/Vm.sol");
+        fs::write(&vm, VM).unwrap();
+        fs::write(
+            root.join("lib/forge-std/src/console.sol"),
+            "library console {\n    function log(uint256 p0) internal view {}\n}\n",
+        )
+        .unwrap();
+        let path = root.join("test/Vault.t.sol");
+        let at = |needle: &str| {
+            let offset = TEST.find(needle).unwrap() + needle.len() - 2;
+            lsp_position(TEST, offset)
+        };
+
+        let hover = cheatcode_hover(TEST, at("vm.prank"), &path).unwrap();
+        let HoverContents::Markup(markup) = hover.contents else {
+            panic!("no markdown");
+        };
+        assert_eq!(
+            markup.value,
+            "```solidity\nfunction prank(address msgSender) external;\n```\n\n\
+             Sets the *next* call's `msg.sender` to be the input address.\n\n---\n\n\
+             ```solidity\nfunction prank(address msgSender, address txOrigin) external;\n```\
+             \n\nSets the *next* call's `msg.sender` to be the input address,\n\
+             and the `tx.origin` to be the second input."
+        );
+        assert_eq!(
+            hover.range,
+            Some(Range::new(Position::new(4, 11), Position::new(4, 16)))
+        );
+
+        let locations = cheatcode_locations(TEST, at("vm.prank"), &path);
+        let lines: Vec<u32> = locations.iter().map(|l| l.range.start.line).collect();
+        assert_eq!(lines, [7, 11]);
+        assert_eq!(locations[0].uri, Url::from_file_path(&vm).unwrap());
+
+        // Without `console2.sol`, `console2` falls back to `console.sol`
+        let log = cheatcode_locations(TEST, at("console2.log"), &path);
+        assert_eq!(log.len(), 1);
+
+        assert!(cheatcode_locations(TEST, at("this.vm.prank"), &path).is_empty());
+        assert!(cheatcode_locations(TEST, at("vault.prank"), &path).is_empty());
+        let plain = TEST.replace("forge-std/Test.sol", "./Base.sol");
+        assert!(cheatcode_locations(&plain, at("vm.prank"), &path).is_empty());
+    }
+}
@@ -1,7 +1,27 @@
-use clap::Parser;
-use eyre::Result;
+use clap::{Parser, Subcommand};
+use eyre::{Result, WrapErr};
+use std::{collections::HashMap, io::BufWriter, path::PathBuf};
 
-use crate::lsp::ForgeLsp;
+use crate::{
+    analysis::{self, ANALYSIS_CODES},
+    annotations::ANNOTATIONS_METHOD,
+    build_info::BuildInfoRunner,
+    config::{DiagnosticsSettings, ServerOptions},
+    config_schema::CONFIGURATION_SCHEMA_METHOD,
+    edits,
+    expand_type::EXPAND_TYPE_METHOD,
+    fix_all::{self, FixAllParams},
+    lsif,
+    lsp::ForgeLsp,
+    preview::PREVIEW_EDIT_METHOD,
+    profiles::STATUS_METHOD,
+    references::GROUPED_REFERENCES_METHOD,
+    rename::SCOPED_RENAME_METHOD,
+    roles::ROLE_GRAPH_METHOD,
+    runner::{ForgeRunner, Runner},
+    test_explorer::{DISCOVER_TESTS_METHOD, RUN_TESTS_METHOD},
+    test_names::RESOLVE_TEST_NAME_METHOD,
+};
 use tower_lsp::{LspService, Server};
 use tracing::info;
 
@@ -11,16 +31,168 @@ pub struct LspArgs {
     /// See: <https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#implementationConsiderations>
     #[arg(long)]
     pub stdio: bool,
+
+    /// Never spawn `forge` or other processes. Only in-process analysis of existing
+    /// build-info files is used, so build and lint diagnostics are unavailable.
+    #[arg(long, global = true)]
+    pub no_subprocess: bool,
+
+    #[command(subcommand)]
+    pub command: Option<LspCommand>,
+}
+
+#[derive(Clone, Debug, Subcommand)]
+pub enum LspCommand {
+    /// Write an LSIF dump of definitions, references and hovers for the whole project
+    Lsif(LsifArgs),
+    /// Apply the fixes of every finding of one rule across the project
+    FixAll(FixAllArgs),
+}
+
+#[derive(Clone, Debug, clap::Args)]
+pub struct LsifArgs {
+    /// File the dump is written to
+    #[arg(long, short, default_value = "dump.lsif")]
+    pub output: PathBuf,
+
+    /// Project root. Defaults to the current directory.
+    #[arg(long)]
+    pub root: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, clap::Args)]
+pub struct FixAllArgs {
+    /// Code of the findings to fix, like `natspec-missing` or `mixed-case-function`
+    #[arg(long)]
+    pub rule: String,
+
+    /// Only fix files whose path relative to the project root matches this glob
+    #[arg(long)]
+    pub path: Option<String>,
+
+    /// Project root. Defaults to the current directory.
+    #[arg(long)]
+    pub root: Option<PathBuf>,
+}
+
+fn project_root(root: Option<PathBuf>) -> Result<PathBuf> {
+    let root = match root {
+        Some(root) => root,
+        None => std::env::current_dir()?,
+    };
+    root.canonicalize().wrap_err("invalid project root")
+}
+
+fn compiler(no_subprocess: bool) -> Box<dyn Runner> {
+    if no_subprocess {
+        Box::new(BuildInfoRunner::default())
+    } else {
+        Box::new(ForgeRunner::default())
+    }
+}
+
+impl LsifArgs {
+    pub async fn run(self, no_subprocess: bool) -> Result<()> {
+        let root = project_root(self.root)?;
+        let root_str = root.to_string_lossy();
+
+        let compiler = compiler(no_subprocess);
+        let ast_data = compiler
+            .project_ast(&root_str)
+            .await
+            .wrap_err("failed to build the project AST")?;
+
+        let file = std::fs::File::create(&self.output)
+            .wrap_err_with(|| format!("failed to create {}", self.output.display()))?;
+        lsif::write_dump(&ast_data, &root, BufWriter::new(file))?;
+        info!("Wrote LSIF dump to {}", self.output.display());
+        Ok(())
+    }
+}
+
+impl FixAllArgs {
+    pub async fn run(self, no_subprocess: bool) -> Result<()> {
+        let root = project_root(self.root)?;
+        let root_str = root.to_string_lossy();
+
+        let compiler = compiler(no_subprocess);
+        let ast_data = compiler
+            .project_ast(&root_str)
+            .await
+            .wrap_err("failed to build the project AST")?;
+        let settings = DiagnosticsSettings {
+            natspec: true,
+            mutability: true,
+            interfaces: true,
+            ..DiagnosticsSettings::default()
+        };
+        let mut findings = analysis::project_findings(&root, &ast_data, &HashMap::new(), &settings);
+        if !ANALYSIS_CODES.contains(&self.rule.as_str()) {
+            let output = compiler
+                .lint(&root_str)
+                .await
+                .wrap_err("failed to lint the project")?;
+            fix_all::add_lint_findings(&mut findings, &root, &output);
+        }
+
+        let params = FixAllParams {
+            rule: self.rule,
+            path: self.path,
+        };
+        let result = fix_all::fix_all(&findings, &params, &|uri| {
+            std::fs::read_to_string(uri.to_file_path().ok()?).ok()
+        });
+        let changes = result.edit.changes.unwrap_or_default();
+        for (uri, file_edits) in &changes {
+            let Ok(path) = uri.to_file_path() else {
+                continue;
+            };
+            let text = std::fs::read_to_string(&path)
+                .wrap_err_with(|| format!("failed to read {}", path.display()))?;
+            let text = edits::apply(&text, file_edits)?;
+            std::fs::write(&path, text)
+                .wrap_err_with(|| format!("failed to write {}", path.display()))?;
+        }
+        info!("Fixed {} findings in {} files", result.fixed, changes.len());
+        if result.skipped > 0 {
+            info!(
+                "Skipped {} findings whose fixes overlap, run again to fix them",
+                result.skipped
+            );
+        }
+        Ok(())
+    }
 }
 
 impl LspArgs {
     pub async fn run(self) -> Result<()> {
+        match self.command {
+            Some(LspCommand::Lsif(args)) => return args.run(self.no_subprocess).await,
+            Some(LspCommand::FixAll(args)) => return args.run(self.no_subprocess).await,
+            None => {}
+        }
+
         // Start stdio LSP server
         info!("Starting Foundry LSP server...");
 
         let stdin = tokio::io::stdin();
         let stdout = tokio::io::stdout();
-        let (service, socket) = LspService::new(ForgeLsp::new);
+        let options = ServerOptions {
+            no_subprocess: self.no_subprocess,
+        };
+        let (service, socket) = LspService::build(|client| ForgeLsp::with_options(client, options))
+            .custom_method(EXPAND_TYPE_METHOD, ForgeLsp::expand_type)
+            .custom_method(ANNOTATIONS_METHOD, ForgeLsp::annotations)
+            .custom_method(PREVIEW_EDIT_METHOD, ForgeLsp::preview_edit)
+            .custom_method(SCOPED_RENAME_METHOD, ForgeLsp::scoped_rename)
+            .custom_method(GROUPED_REFERENCES_METHOD, ForgeLsp::grouped_references)
+            .custom_method(RESOLVE_TEST_NAME_METHOD, ForgeLsp::resolve_test_name)
+            .custom_method(DISCOVER_TESTS_METHOD, ForgeLsp::discover_tests)
+            .custom_method(RUN_TESTS_METHOD, ForgeLsp::run_tests)
+            .custom_method(STATUS_METHOD, ForgeLsp::status)
+            .custom_method(ROLE_GRAPH_METHOD, ForgeLsp::role_graph)
+            .custom_method(CONFIGURATION_SCHEMA_METHOD, ForgeLsp::configuration_schema)
+            .finish();
 
         Server::new(stdin, stdout, socket).serve(service).await;
 
@@ -0,0 +1,176 @@
+//! Logging to the client without waiting on it.
+//!
+//! Handlers used to await each `window/logMessage` where they wrote it, so a request paid
+//! for a write to the client before doing any work, and one more for each step it logged.
+//! [`ClientLog`] queues the messages instead, for a background task to send: messages queued
+//! within [`BATCH_INTERVAL`] of the first go out together, consecutive ones of the same
+//! level in a single notification, so the client receives at most one batch per interval.
+//! Messages below the `logLevel` setting are dropped before they are queued, and a burst
+//! over [`MAX_BATCH`] messages in one interval loses its least severe messages, replaced by
+//! a count of them.
+
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicU8, Ordering},
+    },
+    time::Duration,
+};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tower_lsp::{Client, lsp_types::MessageType};
+
+use crate::config::LogLevel;
+
+/// How long messages are collected before a batch is sent.
+pub const BATCH_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Messages sent per batch; the others are counted.
+pub const MAX_BATCH: usize = 200;
+
+fn rank(level: LogLevel) -> u8 {
+    match level {
+        LogLevel::Error => 1,
+        LogLevel::Warning => 2,
+        LogLevel::Info => 3,
+        LogLevel::Log => 4,
+    }
+}
+
+fn message_rank(typ: MessageType) -> u8 {
+    if typ == MessageType::ERROR {
+        1
+    } else if typ == MessageType::WARNING {
+        2
+    } else if typ == MessageType::INFO {
+        3
+    } else {
+        4
+    }
+}
+
+/// The notifications sending the `messages` queued within an interval: runs of messages of
+/// the same level joined by newlines. Past [`MAX_BATCH`] messages the least severe ones are
+/// dropped first, so a burst of logs can't crowd out an error, and a count of them is sent
+/// last.
+pub fn batch(messages: Vec<(MessageType, String)>) -> Vec<(MessageType, String)> {
+    let dropped = messages.len().saturating_sub(MAX_BATCH);
+    let mut kept = vec![true; messages.len()];
+    if dropped > 0 {
+        // Stable, so the earliest messages of a level are the ones kept
+        let mut by_severity: Vec<usize> = (0..messages.len()).collect();
+        by_severity.sort_by_key(|&i| message_rank(messages[i].0));
+        for &i in &by_severity[MAX_BATCH..] {
+            kept[i] = false;
+        }
+    }
+    let mut batches: Vec<(MessageType, String)> = Vec::new();
+    let kept_messages = messages
+        .into_iter()
+        .zip(kept)
+        .filter_map(|(m, k)| k.then_some(m));
+    for (typ, message) in kept_messages {
+        match batches.last_mut() {
+            Some((last, text)) if *last == typ => {
+                text.push('\n');
+                text.push_str(&message);
+            }
+            _ => batches.push((typ, message)),
+        }
+    }
+    if dropped > 0 {
+        batches.push((
+            MessageType::WARNING,
+            format!("{dropped} more log messages dropped"),
+        ));
+    }
+    batches
+}
+
+/// A queue of log messages to the client.
+#[derive(Debug, Clone)]
+pub struct ClientLog {
+    sender: UnboundedSender<(MessageType, String)>,
+    /// Rank of the least severe level logged.
+    level: Arc<AtomicU8>,
+}
+
+impl ClientLog {
+    /// A queue sending to `client` from a background task.
+    pub fn new(client: Client) -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        tokio::spawn(forward(client, receiver));
+        Self {
+            sender,
+            level: Arc::new(AtomicU8::new(rank(LogLevel::default()))),
+        }
+    }
+
+    /// Log the messages of `level` and the more severe ones from now on.
+    pub fn set_level(&self, level: LogLevel) {
+        self.level.store(rank(level), Ordering::Relaxed);
+    }
+
+    /// Queue `message` of `typ`, unless the level drops it.
+    pub fn log(&self, typ: MessageType, message: impl Into<String>) {
+        if message_rank(typ) <= self.level.load(Ordering::Relaxed) {
+            // Fails only once the server is shutting down
+            let _ = self.sender.send((typ, message.into()));
+        }
+    }
+}
+
+/// Send the messages `receiver` gets to `client` in batches.
+async fn forward(client: Client, mut receiver: UnboundedReceiver<(MessageType, String)>) {
+    while let Some(first) = receiver.recv().await {
+        tokio::time::sleep(BATCH_INTERVAL).await;
+        let mut messages = vec![first];
+        while let Ok(message) = receiver.try_recv() {
+            messages.push(message);
+        }
+        for (typ, message) in batch(messages) {
+            client.log_message(typ, message).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_batch() {
+        let message = |typ, text: &str| (typ, text.to_string());
+        assert_eq!(
+            batch(vec![
+                message(MessageType::INFO, "Got a textDocument/hover request"),
+                message(MessageType::INFO, "Got a textDocument/definition request"),
+                message(MessageType::ERROR, "Failed to read file"),
+                message(MessageType::INFO, "Got a textDocument/hover request"),
+            ]),
+            [
+                message(
+                    MessageType::INFO,
+                    "Got a textDocument/hover request\nGot a textDocument/definition request"
+                ),
+                message(MessageType::ERROR, "Failed to read file"),
+                message(MessageType::INFO, "Got a textDocument/hover request"),
+            ]
+        );
+
+        let mut burst = vec![message(MessageType::LOG, "line"); MAX_BATCH + 3];
+        burst.push(message(MessageType::ERROR, "Failed to compile"));
+        burst.insert(0, message(MessageType::WARNING, "Stale AST"));
+        let batches = batch(burst);
+        assert_eq!(batches.len(), 4);
+        assert_eq!(batches[0], message(MessageType::WARNING, "Stale AST"));
+        assert_eq!(batches[1].1.lines().count(), MAX_BATCH - 2);
+        assert_eq!(batches[2], message(MessageType::ERROR, "Failed to compile"));
+        assert_eq!(
+            batches[3],
+            message(MessageType::WARNING, "5 more log messages dropped")
+        );
+
+        assert!(message_rank(MessageType::WARNING) <= rank(LogLevel::Warning));
+        assert!(message_rank(MessageType::INFO) > rank(LogLevel::Warning));
+    }
+}
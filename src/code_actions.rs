@@ -0,0 +1,162 @@
+//! Quick fixes for diagnostics that know how to fix themselves.
+//!
+//! A fixable diagnostic carries a [`Fix`] in its `data` when it is published. The client
+//! sends the diagnostic back with `textDocument/codeAction`, and the fix becomes a
+//! `CodeAction` editing the document the diagnostic is in, and any related files the fix
+//! also changes. Fixes whose edits no longer fit the document, or overlap each other, are
+//! not offered.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use tower_lsp::lsp_types::{
+    CodeAction, CodeActionKind, CodeActionOrCommand, Diagnostic, TextEdit, Url, WorkspaceEdit,
+};
+
+use crate::edits::EditBuilder;
+
+/// Edits fixing a diagnostic, stored in [`Diagnostic::data`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Fix {
+    /// Title of the code action.
+    pub title: String,
+    pub edits: Vec<TextEdit>,
+    /// Edits of other files the fix also makes, like declarations in interfaces.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub related: HashMap<Url, Vec<TextEdit>>,
+}
+
+impl Fix {
+    pub fn new(title: impl Into<String>, edits: Vec<TextEdit>) -> Self {
+        Self {
+            title: title.into(),
+            edits,
+            related: HashMap::new(),
+        }
+    }
+
+    /// The fix as diagnostic data.
+    pub fn to_data(&self) -> Option<Value> {
+        serde_json::to_value(self).ok()
+    }
+
+    /// The fix carried by `diagnostic`, if any.
+    pub fn from_diagnostic(diagnostic: &Diagnostic) -> Option<Self> {
+        let fix: Self = serde_json::from_value(diagnostic.data.clone()?).ok()?;
+        (!fix.edits.is_empty() || !fix.related.is_empty()).then_some(fix)
+    }
+}
+
+/// Whether a client asking only for `only` wants actions of `kind`: no filter, or a
+/// requested kind `kind` is or is nested in, like `refactor.rewrite` in `refactor`.
+pub fn requested(only: Option<&[CodeActionKind]>, kind: &CodeActionKind) -> bool {
+    only.is_none_or(|kinds| {
+        kinds.iter().any(|requested| {
+            kind.as_str()
+                .strip_prefix(requested.as_str())
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+        })
+    })
+}
+
+/// Quick fixes for the fixable diagnostics among `diagnostics` of `uri`, whose text is
+/// `source`.
+pub fn quick_fixes(
+    uri: &Url,
+    source: &str,
+    diagnostics: &[Diagnostic],
+) -> Vec<CodeActionOrCommand> {
+    diagnostics
+        .iter()
+        .filter_map(|diagnostic| {
+            let fix = Fix::from_diagnostic(diagnostic)?;
+            let edits = EditBuilder::with_edits(source, &fix.edits).ok()?.build();
+            let mut changes = fix.related;
+            if !edits.is_empty() {
+                changes.insert(uri.clone(), edits);
+            }
+            Some(CodeActionOrCommand::CodeAction(CodeAction {
+                title: fix.title,
+                kind: Some(CodeActionKind::QUICKFIX),
+                diagnostics: Some(vec![diagnostic.clone()]),
+                edit: Some(WorkspaceEdit {
+                    changes: Some(changes),
+                    ..WorkspaceEdit::default()
+                }),
+                // Only one fix is offered per diagnostic
+                is_preferred: Some(true),
+                ..CodeAction::default()
+            }))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower_lsp::lsp_types::{Position, Range};
+
+    const SOURCE: &str = "contract A {\n    add_num();\n}\n";
+
+    fn edit_at(line: u32, character: u32, new_text: &str) -> TextEdit {
+        TextEdit {
+            range: Range::new(
+                Position::new(line, character),
+                Position::new(line, character + 3),
+            ),
+            new_text: new_text.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_requested_kinds_nest() {
+        let rewrite = CodeActionKind::REFACTOR_REWRITE;
+        assert!(requested(None, &rewrite));
+        assert!(requested(Some(&[CodeActionKind::REFACTOR]), &rewrite));
+        assert!(requested(
+            Some(&[CodeActionKind::REFACTOR_REWRITE]),
+            &rewrite
+        ));
+        assert!(!requested(Some(&[CodeActionKind::QUICKFIX]), &rewrite));
+        assert!(!requested(Some(&[rewrite]), &CodeActionKind::REFACTOR));
+        assert!(!requested(
+            Some(&[CodeActionKind::new("refactor.re")]),
+            &CodeActionKind::REFACTOR_REWRITE
+        ));
+    }
+
+    #[test]
+    fn test_quick_fixes_from_diagnostic_data() {
+        let uri = Url::parse("file:///project/src/A.sol").unwrap();
+        let edit = TextEdit {
+            range: Range::new(Position::new(1, 4), Position::new(1, 11)),
+            new_text: "addNum".to_string(),
+        };
+        let fixable = Diagnostic {
+            message: "function names should use mixedCase".to_string(),
+            data: Fix::new("Rename to `addNum`", vec![edit.clone()]).to_data(),
+            ..Diagnostic::default()
+        };
+        let plain = Diagnostic {
+            message: "unused variable".to_string(),
+            ..Diagnostic::default()
+        };
+
+        let overlapping = Diagnostic {
+            message: "two suggestions for one span".to_string(),
+            data: Fix::new("Rename", vec![edit.clone(), edit_at(1, 8, "add")]).to_data(),
+            ..Diagnostic::default()
+        };
+
+        let actions = quick_fixes(&uri, SOURCE, &[plain, fixable.clone(), overlapping]);
+        assert_eq!(actions.len(), 1);
+        let CodeActionOrCommand::CodeAction(action) = &actions[0] else {
+            panic!("expected a code action");
+        };
+        assert_eq!(action.title, "Rename to `addNum`");
+        assert_eq!(action.diagnostics, Some(vec![fixable]));
+        let changes = action.edit.as_ref().unwrap().changes.as_ref().unwrap();
+        assert_eq!(changes[&uri], [edit]);
+    }
+}
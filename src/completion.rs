@@ -0,0 +1,595 @@
+//! Completion of names visible at the cursor and of members after `.`.
+//!
+//! Names come from the cached AST: locals and parameters declared before the cursor in the
+//! enclosing function, members of the enclosing contract and its bases, and top-level
+//! definitions of every compiled source. After `.` the receiver is resolved by name to a
+//! contract, struct or enum and its members are offered instead.
+
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use tower_lsp::lsp_types::{CompletionItem, CompletionItemKind, Position, Url};
+
+use crate::{
+    ast::{self, children, node_type, parse_src},
+    goto::pos_to_bytes,
+};
+
+/// Characters that open a completion session.
+pub const TRIGGER_CHARACTERS: &[&str] = &["."];
+
+fn src_end(node: &Value) -> Option<usize> {
+    let (start, length, _) = parse_src(node.get("src")?.as_str()?)?;
+    Some(start + length)
+}
+
+fn item(declaration: &Value) -> Option<CompletionItem> {
+    let name = declaration.get("name")?.as_str()?;
+    if name.is_empty() {
+        return None;
+    }
+
+    let kind = match node_type(declaration) {
+        Some("VariableDeclaration") if declaration["stateVariable"].as_bool() == Some(true) => {
+            CompletionItemKind::FIELD
+        }
+        Some("VariableDeclaration") => CompletionItemKind::VARIABLE,
+        Some("FunctionDefinition") => CompletionItemKind::FUNCTION,
+        Some("ModifierDefinition") => CompletionItemKind::METHOD,
+        Some("EventDefinition") => CompletionItemKind::EVENT,
+        Some("ErrorDefinition") => CompletionItemKind::CONSTRUCTOR,
+        Some("StructDefinition") => CompletionItemKind::STRUCT,
+        Some("EnumDefinition") => CompletionItemKind::ENUM,
+        Some("EnumValue") => CompletionItemKind::ENUM_MEMBER,
+        Some("UserDefinedValueTypeDefinition") => CompletionItemKind::TYPE_PARAMETER,
+        Some("ContractDefinition") => match declaration["contractKind"].as_str() {
+            Some("interface") => CompletionItemKind::INTERFACE,
+            Some("library") => CompletionItemKind::MODULE,
+            _ => CompletionItemKind::CLASS,
+        },
+        _ => return None,
+    };
+    let detail = declaration
+        .get("typeDescriptions")
+        .and_then(|descriptions| descriptions.get("typeString"))
+        .and_then(Value::as_str)
+        .map(str::to_string);
+
+    Some(CompletionItem {
+        label: name.to_string(),
+        kind: Some(kind),
+        detail,
+        ..CompletionItem::default()
+    })
+}
+
+/// Declarations visible by name in the scope around a cursor.
+struct Scope<'a> {
+    index: HashMap<u64, &'a Value>,
+    /// Innermost first: locals, then contract members, then top-level definitions.
+    declarations: Vec<&'a Value>,
+    contract: Option<&'a Value>,
+}
+
+impl<'a> Scope<'a> {
+    fn resolve(ast_data: &'a Value, source_unit: &'a Value, cursor: usize) -> Scope<'a> {
+        let index = ast_data
+            .get("sources")
+            .map(ast::index_nodes)
+            .unwrap_or_default();
+        let mut declarations = Vec::new();
+
+        let contract = children(source_unit, "nodes").find(|node| {
+            node_type(node) == Some("ContractDefinition") && ast::contains(node, cursor)
+        });
+        if let Some(contract) = contract {
+            let callable = children(contract, "nodes").find(|node| {
+                matches!(
+                    node_type(node),
+                    Some("FunctionDefinition" | "ModifierDefinition")
+                ) && ast::contains(node, cursor)
+            });
+            if let Some(callable) = callable {
+                for parameters in ["parameters", "returnParameters"] {
+                    if let Some(list) = callable.get(parameters) {
+                        declarations.extend(children(list, "parameters"));
+                    }
+                }
+                if let Some(body) = callable.get("body") {
+                    collect_locals(body, cursor, &mut declarations);
+                }
+                // Innermost declarations shadow outer ones
+                declarations.reverse();
+            }
+
+            declarations.extend(contract_members(&index, contract));
+        }
+
+        // Top-level definitions of every source, which covers imported files
+        let mut sources: Vec<&Value> = index
+            .values()
+            .copied()
+            .filter(|node| node_type(node) == Some("SourceUnit"))
+            .collect();
+        sources.sort_by_key(|unit| !std::ptr::eq(*unit, source_unit));
+        declarations.extend(sources.into_iter().flat_map(|unit| children(unit, "nodes")));
+
+        Scope {
+            index,
+            declarations,
+            contract,
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Option<&'a Value> {
+        self.declarations
+            .iter()
+            .copied()
+            .find(|declaration| declaration["name"] == name)
+    }
+
+    /// Follow a type name to the contract, struct or enum it refers to.
+    fn type_definition(&self, type_name: &Value) -> Option<&'a Value> {
+        let id = type_name
+            .get("referencedDeclaration")
+            .or_else(|| type_name.get("pathNode")?.get("referencedDeclaration"))?
+            .as_u64()?;
+        self.index.get(&id).copied()
+    }
+
+    /// Members reachable with `receiver.` where `receiver` is the name before the dot.
+    fn members(&self, receiver: &str) -> Vec<&'a Value> {
+        if receiver == "this" {
+            return self
+                .contract
+                .map(|contract| external_members(&self.index, contract))
+                .unwrap_or_default();
+        }
+        if receiver == "super" {
+            return self
+                .contract
+                .map(|contract| {
+                    contract_members(&self.index, contract)
+                        .filter(|member| member["scope"] != contract["id"])
+                        .filter(|member| node_type(member) == Some("FunctionDefinition"))
+                        .collect()
+                })
+                .unwrap_or_default();
+        }
+
+        let Some(declaration) = self.lookup(receiver) else {
+            return vec![];
+        };
+        match node_type(declaration) {
+            // A type name: everything declared in the contract, interface or library
+            Some("ContractDefinition") => contract_members(&self.index, declaration).collect(),
+            Some("EnumDefinition") => children(declaration, "members").collect(),
+            Some("VariableDeclaration") => {
+                let Some(definition) = declaration
+                    .get("typeName")
+                    .and_then(|type_name| self.type_definition(type_name))
+                else {
+                    return vec![];
+                };
+                match node_type(definition) {
+                    Some("ContractDefinition") => external_members(&self.index, definition),
+                    Some("StructDefinition") => children(definition, "members").collect(),
+                    _ => vec![],
+                }
+            }
+            _ => vec![],
+        }
+    }
+}
+
+/// Add the locals declared before `cursor` in the blocks around it, outermost first.
+fn collect_locals<'a>(node: &'a Value, cursor: usize, declarations: &mut Vec<&'a Value>) {
+    match node_type(node) {
+        Some("Block" | "UncheckedBlock") => {
+            for statement in children(node, "statements") {
+                if node_type(statement) == Some("VariableDeclarationStatement")
+                    && src_end(statement).is_some_and(|end| end <= cursor)
+                {
+                    declarations.extend(children(statement, "declarations"));
+                } else if ast::contains(statement, cursor) {
+                    collect_locals(statement, cursor, declarations);
+                }
+            }
+        }
+        Some("ForStatement") => {
+            if let Some(init) = node.get("initializationExpression") {
+                declarations.extend(children(init, "declarations"));
+            }
+            descend(node, cursor, declarations);
+        }
+        Some("TryCatchClause") => {
+            if let Some(parameters) = node.get("parameters") {
+                declarations.extend(children(parameters, "parameters"));
+            }
+            descend(node, cursor, declarations);
+        }
+        _ => descend(node, cursor, declarations),
+    }
+}
+
+fn descend<'a>(node: &'a Value, cursor: usize, declarations: &mut Vec<&'a Value>) {
+    let Some(map) = node.as_object() else {
+        return;
+    };
+    for child in map.values() {
+        let nested: Vec<&Value> = match child {
+            Value::Array(items) => items.iter().collect(),
+            Value::Object(_) => vec![child],
+            _ => continue,
+        };
+        for child in nested {
+            if child.get("nodeType").is_some() && ast::contains(child, cursor) {
+                collect_locals(child, cursor, declarations);
+            }
+        }
+    }
+}
+
+/// Members of `contract` and of its bases, most derived first.
+fn contract_members<'a>(
+    index: &HashMap<u64, &'a Value>,
+    contract: &'a Value,
+) -> impl Iterator<Item = &'a Value> {
+    let bases: Vec<&'a Value> = children(contract, "linearizedBaseContracts")
+        .filter_map(Value::as_u64)
+        .filter_map(|id| index.get(&id).copied())
+        .collect();
+    let bases = if bases.is_empty() {
+        vec![contract]
+    } else {
+        bases
+    };
+    bases
+        .into_iter()
+        .flat_map(|base| children(base, "nodes"))
+        .filter(|member| node_type(member) != Some("UsingForDirective"))
+}
+
+/// Members reachable on an instance of `contract` from outside it.
+fn external_members<'a>(index: &HashMap<u64, &'a Value>, contract: &'a Value) -> Vec<&'a Value> {
+    contract_members(index, contract)
+        .filter(|member| {
+            let public = matches!(member["visibility"].as_str(), Some("public" | "external"));
+            match node_type(member) {
+                Some("FunctionDefinition") => public && member["kind"] == "function",
+                Some("VariableDeclaration") => public,
+                _ => false,
+            }
+        })
+        .collect()
+}
+
+/// The identifier before the `.` that precedes the word at `cursor`, if any.
+fn member_receiver(source: &[u8], cursor: usize) -> Option<&str> {
+    let is_ident = |b: &u8| b.is_ascii_alphanumeric() || *b == b'_' || *b == b'$';
+    let before = source.get(..cursor)?;
+    let word_start = before.len() - before.iter().rev().take_while(|b| is_ident(b)).count();
+    let dot = word_start.checked_sub(1)?;
+    if source[dot] != b'.' {
+        return None;
+    }
+    let receiver_start = dot
+        - source[..dot]
+            .iter()
+            .rev()
+            .take_while(|b| is_ident(b))
+            .count();
+    let receiver = std::str::from_utf8(&source[receiver_start..dot]).ok()?;
+    (!receiver.is_empty()).then_some(receiver)
+}
+
+/// Completion items for `position` in the file at `file_uri`.
+pub fn completions(
+    ast_data: &Value,
+    file_uri: &Url,
+    position: Position,
+    source_bytes: &[u8],
+) -> Vec<CompletionItem> {
+    let Some(source_unit) = ast::source_unit(ast_data, file_uri) else {
+        return vec![];
+    };
+    let cursor = pos_to_bytes(source_bytes, position);
+    let scope = Scope::resolve(ast_data, source_unit, cursor);
+
+    let declarations = match member_receiver(source_bytes, cursor) {
+        Some(receiver) => scope.members(receiver),
+        None => scope.declarations.clone(),
+    };
+
+    // Later declarations with the same name are shadowed or overridden
+    let mut seen = HashSet::new();
+    declarations
+        .into_iter()
+        .filter_map(item)
+        .filter(|item| seen.insert(item.label.clone()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{mock_span, mock_src};
+    use serde_json::json;
+
+    const SOURCE: &str = r#"interface IToken {
+    function transfer(address to, uint256 amount) external returns (bool);
+}
+
+contract Base {
+    uint256 internal fee;
+}
+
+contract Vault is Base {
+    enum Status { Open, Closed }
+    struct Position { address owner; uint256 amount; }
+
+    IToken public token;
+    Position position;
+    event Deposited(uint256 amount);
+
+    function deposit(uint256 amount) external {
+        uint256 before = fee;
+        token.
+        position.
+        Status.
+        uint256 later = 1;
+    }
+}
+"#;
+
+    fn var(id: u64, name: &str, src: String, state: bool, type_name: Value) -> Value {
+        json!({
+            "id": id,
+            "src": src,
+            "nodeType": "VariableDeclaration",
+            "name": name,
+            "stateVariable": state,
+            "visibility": "internal",
+            "typeName": type_name
+        })
+    }
+
+    fn uint() -> Value {
+        json!({ "nodeType": "ElementaryTypeName", "name": "uint256" })
+    }
+
+    fn user_type(id: u64) -> Value {
+        json!({ "nodeType": "UserDefinedTypeName", "referencedDeclaration": id })
+    }
+
+    fn named(id: u64, node_type: &str, name: &str, src: String) -> Value {
+        json!({ "id": id, "src": src, "nodeType": node_type, "name": name })
+    }
+
+    fn contract(id: u64, kind: &str, name: &str, bases: &[u64], nodes: Vec<Value>) -> Value {
+        let until = if name == "Vault" { "}\n}" } else { "}" };
+        let mut contract = named(
+            id,
+            "ContractDefinition",
+            name,
+            mock_span(SOURCE, &format!("{kind} {name}"), until),
+        );
+        contract["contractKind"] = json!(kind);
+        contract["linearizedBaseContracts"] = json!(bases);
+        contract["nodes"] = json!(nodes);
+        contract
+    }
+
+    fn deposit() -> Value {
+        let before = var(
+            44,
+            "before",
+            mock_src(SOURCE, "uint256 before", 14),
+            false,
+            uint(),
+        );
+        let later = var(
+            46,
+            "later",
+            mock_src(SOURCE, "uint256 later", 13),
+            false,
+            uint(),
+        );
+        let statements = json!([{
+            "id": 43,
+            "src": mock_span(SOURCE, "uint256 before", ";"),
+            "nodeType": "VariableDeclarationStatement",
+            "declarations": [before]
+        }, {
+            "id": 45,
+            "src": mock_span(SOURCE, "uint256 later", ";"),
+            "nodeType": "VariableDeclarationStatement",
+            "declarations": [later]
+        }]);
+
+        let mut function = named(
+            40,
+            "FunctionDefinition",
+            "deposit",
+            mock_span(SOURCE, "function deposit", "}"),
+        );
+        function["kind"] = json!("function");
+        function["visibility"] = json!("external");
+        function["parameters"] = json!({
+            "nodeType": "ParameterList",
+            "parameters": [var(41, "amount", mock_src(SOURCE, "uint256 amount)", 14), false, uint())]
+        });
+        function["returnParameters"] = json!({ "nodeType": "ParameterList", "parameters": [] });
+        function["body"] = json!({
+            "id": 42,
+            "src": mock_span(SOURCE, "{\n        uint256 before", "}"),
+            "nodeType": "Block",
+            "statements": statements
+        });
+        function
+    }
+
+    fn mock_ast(path: &str) -> Value {
+        let mut transfer = named(
+            3,
+            "FunctionDefinition",
+            "transfer",
+            mock_span(SOURCE, "function transfer", ";"),
+        );
+        transfer["kind"] = json!("function");
+        transfer["visibility"] = json!("external");
+
+        let mut status = named(
+            21,
+            "EnumDefinition",
+            "Status",
+            mock_span(SOURCE, "enum Status", "}"),
+        );
+        status["members"] = json!([
+            named(22, "EnumValue", "Open", mock_src(SOURCE, "Open", 4)),
+            named(23, "EnumValue", "Closed", mock_src(SOURCE, "Closed", 6)),
+        ]);
+
+        let address = json!({ "nodeType": "ElementaryTypeName", "name": "address" });
+        let mut position = named(
+            24,
+            "StructDefinition",
+            "Position",
+            mock_span(SOURCE, "struct Position", "}"),
+        );
+        position["members"] = json!([
+            var(
+                25,
+                "owner",
+                mock_src(SOURCE, "address owner", 13),
+                false,
+                address
+            ),
+            var(
+                26,
+                "amount",
+                mock_src(SOURCE, "uint256 amount;", 14),
+                false,
+                uint()
+            ),
+        ]);
+
+        let mut token = var(
+            30,
+            "token",
+            mock_src(SOURCE, "IToken public token", 19),
+            true,
+            user_type(2),
+        );
+        token["visibility"] = json!("public");
+
+        let vault = contract(
+            20,
+            "contract",
+            "Vault",
+            &[20, 10],
+            vec![
+                status,
+                position,
+                token,
+                var(
+                    31,
+                    "position",
+                    mock_src(SOURCE, "Position position", 17),
+                    true,
+                    user_type(24),
+                ),
+                named(
+                    32,
+                    "EventDefinition",
+                    "Deposited",
+                    mock_span(SOURCE, "event Deposited", ";"),
+                ),
+                deposit(),
+            ],
+        );
+        let nodes = vec![
+            contract(2, "interface", "IToken", &[2], vec![transfer]),
+            contract(
+                10,
+                "contract",
+                "Base",
+                &[10],
+                vec![var(
+                    11,
+                    "fee",
+                    mock_src(SOURCE, "uint256 internal fee", 20),
+                    true,
+                    uint(),
+                )],
+            ),
+            vault,
+        ];
+
+        ast::mock_ast(
+            path,
+            json!({
+                "id": 1,
+                "src": format!("0:{}:0", SOURCE.len()),
+                "nodeType": "SourceUnit",
+                "absolutePath": path,
+                "nodes": nodes
+            }),
+        )
+    }
+
+    fn labels_at(needle: &str, offset: usize) -> Vec<String> {
+        let path = "/tmp/forge-lsp-completion/Vault.sol";
+        let ast = mock_ast(path);
+        let uri = Url::from_file_path(path).unwrap();
+        let cursor = SOURCE.find(needle).unwrap() + offset;
+        let position = crate::goto::bytes_to_pos(SOURCE.as_bytes(), cursor).unwrap();
+        completions(&ast, &uri, position, SOURCE.as_bytes())
+            .into_iter()
+            .map(|item| item.label)
+            .collect()
+    }
+
+    #[test]
+    fn test_scope_completions() {
+        let labels = labels_at("token.", 0);
+        for expected in [
+            "before",
+            "amount",
+            "fee",
+            "token",
+            "position",
+            "Status",
+            "Position",
+            "Deposited",
+            "deposit",
+            "IToken",
+            "Vault",
+        ] {
+            assert!(labels.contains(&expected.to_string()), "missing {expected}");
+        }
+        // Declared after the cursor
+        assert!(!labels.contains(&"later".to_string()));
+    }
+
+    #[test]
+    fn test_member_completions() {
+        assert_eq!(labels_at("token.", "token.".len()), vec!["transfer"]);
+        assert_eq!(
+            labels_at("position.", "position.".len()),
+            vec!["owner", "amount"]
+        );
+        assert_eq!(
+            labels_at("Status.", "Status.".len()),
+            vec!["Open", "Closed"]
+        );
+    }
+
+    #[test]
+    fn test_member_receiver() {
+        let source = b"foo.ba";
+        assert_eq!(member_receiver(source, 6), Some("foo"));
+        assert_eq!(member_receiver(source, 4), Some("foo"));
+        assert_eq!(member_receiver(source, 3), None);
+        assert_eq!(member_receiver(b"f().x", 5), None);
+    }
+}
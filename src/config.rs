@@ -0,0 +1,618 @@
+//! Client-provided server settings.
+//!
+//! Settings arrive as `initializationOptions` on `initialize` and as the payload of
+//! `workspace/didChangeConfiguration`. Both may be either the settings object itself or
+//! wrapped under a `"forge-lsp"` key. Unknown fields are ignored, and a malformed field
+//! falls back to its default, with a warning naming it, keeping the other settings.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString};
+
+use crate::{
+    build::BUILD_DIAGNOSTICS_SOURCE, build_info::DEFAULT_MAX_BUILD_INFO_SIZE,
+    fix_all::glob_matches, lint::LINT_DIAGNOSTICS_SOURCE,
+};
+
+/// Key clients may nest the server settings under.
+pub const SETTINGS_SECTION: &str = "forge-lsp";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Settings {
+    pub diagnostics: DiagnosticsSettings,
+    pub inlay_hints: InlayHintsSettings,
+    /// Trust the workspace up front instead of asking before the first `forge` run.
+    pub trusted_workspace: bool,
+    /// Show the gas costs of functions as code lenses and in hovers, running the tests of
+    /// each project with `forge test --gas-report`.
+    pub gas_estimates: bool,
+    /// Show the storage slot of state variables in hovers, running `forge inspect` on
+    /// their contract.
+    pub storage_layout_hovers: bool,
+    pub test_on_save: TestOnSaveSettings,
+    pub model_checker: ModelCheckerSettings,
+    pub bindings: BindingsSettings,
+    pub rename: RenameSettings,
+    pub large_files: LargeFilesSettings,
+    /// Language of the server's messages, as a tag such as `de`, instead of the client's
+    /// `initializeParams.locale`.
+    pub locale: Option<String>,
+    /// Least severe level of the messages logged to the client.
+    pub log_level: LogLevel,
+}
+
+/// Options fixed for the lifetime of the server, set from the command line.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ServerOptions {
+    /// Never spawn processes; answer requests from existing build-info files only.
+    pub no_subprocess: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct DiagnosticsSettings {
+    pub trigger: DiagnosticsTrigger,
+    /// Quiet period after the last edit before diagnostics run in `onChange` mode.
+    pub debounce_ms: u64,
+    /// Show `TODO`, `FIXME`, `audit:` and `@custom:security` comments as hints.
+    pub annotations: bool,
+    /// Require `@notice`, `@param` and `@return` on external and public functions in `src/`.
+    pub natspec: bool,
+    /// Report functions that could be declared `view` or `pure`, and `STATICCALL`s of view
+    /// functions.
+    pub mutability: bool,
+    /// Report drift between contracts and their `I`-prefixed interfaces.
+    pub interfaces: bool,
+    /// Report unused imports, local variables and parameters.
+    pub unused: bool,
+    /// Publish the diagnostics of `forge build`.
+    pub build: bool,
+    /// Publish the diagnostics of `forge lint`.
+    pub lint: bool,
+    /// Severities of diagnostics by code, such as a `forge lint` rule, `off` hiding them.
+    pub rules: BTreeMap<String, RuleSeverity>,
+    /// Files whose diagnostics are hidden, as globs matched against their path relative
+    /// to the project root.
+    pub exclude: Vec<String>,
+}
+
+impl Default for DiagnosticsSettings {
+    fn default() -> Self {
+        Self {
+            trigger: DiagnosticsTrigger::default(),
+            debounce_ms: 500,
+            annotations: false,
+            natspec: false,
+            mutability: false,
+            interfaces: false,
+            unused: true,
+            build: true,
+            lint: true,
+            rules: BTreeMap::new(),
+            exclude: vec![],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct InlayHintsSettings {
+    /// Show parameter names before positional call arguments.
+    pub parameter_names: bool,
+    /// Show the types of the targets of tuple destructuring assignments.
+    pub types: bool,
+    /// Show the names of return variables at `return` statements and after calls whose
+    /// return values are dropped.
+    pub return_names: bool,
+}
+
+impl Default for InlayHintsSettings {
+    fn default() -> Self {
+        Self {
+            parameter_names: true,
+            types: true,
+            return_names: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct TestOnSaveSettings {
+    /// Which tests run after a save.
+    #[serde(rename = "match")]
+    pub matching: TestOnSaveMatch,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ModelCheckerSettings {
+    /// Contracts checked after each save of their file, as `path:Name` with the path
+    /// relative to the project root.
+    pub contracts: Vec<String>,
+    /// Engine of the SMTChecker: `chc`, `bmc` or `all`.
+    pub engine: String,
+    /// Properties to check, all of them when empty.
+    pub targets: Vec<String>,
+    /// Timeout of each query in milliseconds, solc's when unset.
+    pub timeout: Option<u64>,
+}
+
+impl Default for ModelCheckerSettings {
+    fn default() -> Self {
+        Self {
+            contracts: vec![],
+            engine: "chc".to_string(),
+            targets: vec![],
+            timeout: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct BindingsSettings {
+    /// Generate the bindings again after each build that changes the ABIs of the project.
+    pub on_abi_change: bool,
+    /// Directory of the bindings relative to the project root, forge's `out/bindings` when
+    /// unset.
+    pub path: Option<String>,
+    /// Name of the generated crate.
+    pub crate_name: Option<String>,
+    /// Version of the generated crate.
+    pub crate_version: Option<String>,
+    /// Generate a module to include in an existing crate instead of a crate.
+    pub module: bool,
+    /// Generate all bindings in a single file.
+    pub single_file: bool,
+    /// Regular expressions of the contracts to generate bindings for, all of them when
+    /// empty.
+    pub select: Vec<String>,
+    /// Version of alloy the generated crate depends on.
+    pub alloy_version: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct RenameSettings {
+    /// Rename the functions a renamed function overrides or is overridden by along with it.
+    pub overrides: bool,
+    /// Mark the edits of those functions for the client to confirm before applying them.
+    pub confirm_overrides: bool,
+}
+
+impl Default for RenameSettings {
+    fn default() -> Self {
+        Self {
+            overrides: true,
+            confirm_overrides: false,
+        }
+    }
+}
+
+/// Limits above which files, such as flattened contracts, get a reduced feature set.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct LargeFilesSettings {
+    /// Size in bytes above which a file is large.
+    pub max_bytes: usize,
+    /// Sizes replacing `maxBytes` for the files under directories relative to the project
+    /// root, the most nested directory applying.
+    pub directories: BTreeMap<String, usize>,
+    /// Size in bytes of `forge build` output and build-info files above which only the
+    /// sections the server reads are parsed.
+    pub build_output_bytes: u64,
+}
+
+impl Default for LargeFilesSettings {
+    fn default() -> Self {
+        Self {
+            max_bytes: 1024 * 1024,
+            directories: BTreeMap::new(),
+            build_output_bytes: DEFAULT_MAX_BUILD_INFO_SIZE,
+        }
+    }
+}
+
+impl LargeFilesSettings {
+    /// The size above which the file at `relative`, its `/`-separated path relative to its
+    /// project root, is large.
+    pub fn max_bytes_for(&self, relative: &str) -> usize {
+        self.directories
+            .iter()
+            .filter(|(directory, _)| {
+                let directory = directory.trim_end_matches('/');
+                relative
+                    .strip_prefix(directory)
+                    .is_some_and(|rest| rest.starts_with('/'))
+            })
+            .max_by_key(|(directory, _)| directory.trim_end_matches('/').len())
+            .map_or(self.max_bytes, |(_, max_bytes)| *max_bytes)
+    }
+
+    /// Whether the file at `relative`, of `len` bytes, is large.
+    pub fn is_large(&self, relative: &str, len: usize) -> bool {
+        len > self.max_bytes_for(relative)
+    }
+}
+
+/// Severity a diagnostic code is published with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RuleSeverity {
+    Off,
+    Error,
+    Warning,
+    Info,
+    Hint,
+}
+
+impl RuleSeverity {
+    /// The LSP severity, none for `off`.
+    pub fn severity(self) -> Option<DiagnosticSeverity> {
+        match self {
+            Self::Off => None,
+            Self::Error => Some(DiagnosticSeverity::ERROR),
+            Self::Warning => Some(DiagnosticSeverity::WARNING),
+            Self::Info => Some(DiagnosticSeverity::INFORMATION),
+            Self::Hint => Some(DiagnosticSeverity::HINT),
+        }
+    }
+}
+
+/// Tests run after a file is saved.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TestOnSaveMatch {
+    /// None.
+    #[default]
+    Off,
+    /// The test contracts of the saved file.
+    File,
+    /// The test contracts of the saved file and of the files importing it, directly or
+    /// through other imports.
+    Imports,
+}
+
+/// Levels of the messages logged to the client, each including the more severe ones.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LogLevel {
+    Error,
+    Warning,
+    /// Requests as they arrive, and the steps of builds and test runs.
+    #[default]
+    Info,
+    /// Everything.
+    Log,
+}
+
+/// When forge build/lint diagnostics are run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DiagnosticsTrigger {
+    /// On open and save, and after edits once the debounce period has passed.
+    OnChange,
+    /// On open and save.
+    #[default]
+    OnSave,
+    /// Only when the `forge-lsp.runDiagnostics` command is executed.
+    Manual,
+}
+
+/// Document events that may cause diagnostics to run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiagnosticsEvent {
+    Open,
+    Change,
+    Save,
+    Command,
+}
+
+impl DiagnosticsTrigger {
+    /// Whether diagnostics should run for `event` under this policy.
+    pub fn runs_on(self, event: DiagnosticsEvent) -> bool {
+        matches!(
+            (self, event),
+            (_, DiagnosticsEvent::Command)
+                | (Self::OnChange, _)
+                | (
+                    Self::OnSave,
+                    DiagnosticsEvent::Open | DiagnosticsEvent::Save
+                )
+        )
+    }
+}
+
+impl DiagnosticsSettings {
+    /// Whether the diagnostics of the file at `relative`, its `/`-separated path relative
+    /// to its project root, are hidden.
+    pub fn excludes(&self, relative: &str) -> bool {
+        self.exclude
+            .iter()
+            .any(|pattern| glob_matches(pattern, relative))
+    }
+
+    /// Drop the diagnostics of disabled sources and rules, and apply the severities of the
+    /// others' rules.
+    pub fn apply(&self, diagnostics: &mut Vec<Diagnostic>) {
+        diagnostics.retain_mut(|diagnostic| {
+            match diagnostic.source.as_deref() {
+                Some(BUILD_DIAGNOSTICS_SOURCE) if !self.build => return false,
+                Some(LINT_DIAGNOSTICS_SOURCE) if !self.lint => return false,
+                _ => {}
+            }
+            let rule = match &diagnostic.code {
+                Some(NumberOrString::String(code)) => self.rules.get(code),
+                Some(NumberOrString::Number(code)) => self.rules.get(&code.to_string()),
+                None => None,
+            };
+            match rule.map(|rule| rule.severity()) {
+                Some(None) => false,
+                Some(severity) => {
+                    diagnostic.severity = severity;
+                    true
+                }
+                None => true,
+            }
+        });
+    }
+}
+
+impl Settings {
+    /// Parse settings from a client payload, falling back to defaults when it is absent,
+    /// and field by field where it is malformed.
+    pub fn from_value(value: Option<&Value>) -> Self {
+        let Some(value) = value else {
+            return Self::default();
+        };
+        let value = value.get(SETTINGS_SECTION).unwrap_or(value);
+        if let Ok(settings) = Self::deserialize(value) {
+            return settings;
+        }
+        let valid = valid_fields(value, "", &|value| Self::deserialize(value).is_ok());
+        Self::deserialize(&valid).unwrap_or_default()
+    }
+}
+
+/// The fields of the object `value`, at `path` in the settings, that keep the settings
+/// `accepts`, warning about each one dropped. A malformed section is kept with its valid
+/// fields alone.
+fn valid_fields(value: &Value, path: &str, accepts: &dyn Fn(&Value) -> bool) -> Value {
+    let Value::Object(fields) = value else {
+        return value.clone();
+    };
+    let mut valid = Map::new();
+    for (key, field) in fields {
+        let with = |field: Value| {
+            let mut with = valid.clone();
+            with.insert(key.clone(), field);
+            Value::Object(with)
+        };
+        if accepts(&with(field.clone())) {
+            valid.insert(key.clone(), field.clone());
+            continue;
+        }
+        let key_path = format!("{path}{key}");
+        if field.is_object() {
+            let nested = valid_fields(field, &format!("{key_path}."), &|nested| {
+                accepts(&with(nested.clone()))
+            });
+            if accepts(&with(nested.clone())) {
+                valid.insert(key.clone(), nested);
+                continue;
+            }
+        }
+        tracing::warn!("ignoring the malformed setting {key_path}: {field}");
+    }
+    Value::Object(valid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_settings_from_value() {
+        assert_eq!(Settings::from_value(None), Settings::default());
+        assert_eq!(
+            Settings::default().diagnostics.trigger,
+            DiagnosticsTrigger::OnSave
+        );
+
+        let flat = json!({ "diagnostics": { "trigger": "onChange", "debounceMs": 250 } });
+        let settings = Settings::from_value(Some(&flat));
+        assert_eq!(settings.diagnostics.trigger, DiagnosticsTrigger::OnChange);
+        assert_eq!(settings.diagnostics.debounce_ms, 250);
+        assert!(settings.diagnostics.unused);
+        assert!(!settings.trusted_workspace);
+        assert!(!settings.gas_estimates);
+        assert!(!settings.storage_layout_hovers);
+        assert_eq!(settings.test_on_save.matching, TestOnSaveMatch::Off);
+        assert_eq!(settings.model_checker, ModelCheckerSettings::default());
+        assert!(!settings.bindings.on_abi_change);
+        assert!(settings.rename.overrides && !settings.rename.confirm_overrides);
+        assert_eq!(settings.locale, None);
+        assert_eq!(settings.log_level, LogLevel::Info);
+
+        let nested = json!({
+            "forge-lsp": {
+                "diagnostics": { "trigger": "manual", "unused": false },
+                "trustedWorkspace": true,
+                "gasEstimates": true,
+                "storageLayoutHovers": true,
+                "testOnSave": { "match": "imports" },
+                "modelChecker": { "contracts": ["src/Vault.sol:Vault"], "engine": "bmc" },
+                "bindings": { "onAbiChange": true, "crateName": "vault-bindings" },
+                "rename": { "confirmOverrides": true },
+                "locale": "es",
+                "logLevel": "warning"
+            }
+        });
+        let settings = Settings::from_value(Some(&nested));
+        assert_eq!(settings.diagnostics.trigger, DiagnosticsTrigger::Manual);
+        assert!(!settings.diagnostics.unused);
+        assert!(settings.trusted_workspace && settings.gas_estimates);
+        assert!(settings.storage_layout_hovers);
+        assert_eq!(settings.test_on_save.matching, TestOnSaveMatch::Imports);
+        assert_eq!(settings.model_checker.contracts, ["src/Vault.sol:Vault"]);
+        assert_eq!(settings.model_checker.engine, "bmc");
+        assert!(settings.bindings.on_abi_change);
+        assert_eq!(
+            settings.bindings.crate_name.as_deref(),
+            Some("vault-bindings")
+        );
+        assert!(settings.rename.overrides && settings.rename.confirm_overrides);
+        assert_eq!(settings.locale.as_deref(), Some("es"));
+        assert_eq!(settings.log_level, LogLevel::Warning);
+        assert_eq!(settings.diagnostics.debounce_ms, 500);
+        assert!(!settings.diagnostics.annotations);
+        assert!(settings.inlay_hints.parameter_names && settings.inlay_hints.types);
+
+        let hints = json!({ "inlayHints": { "parameterNames": false } });
+        let settings = Settings::from_value(Some(&hints));
+        assert!(!settings.inlay_hints.parameter_names);
+        assert!(settings.inlay_hints.types);
+
+        let large = json!({
+            "largeFiles": {
+                "maxBytes": 2048,
+                "directories": { "flat/": 0 },
+                "buildOutputBytes": 1024
+            }
+        });
+        let settings = Settings::from_value(Some(&large));
+        assert_eq!(settings.large_files.max_bytes, 2048);
+        assert_eq!(settings.large_files.directories["flat/"], 0);
+        assert_eq!(settings.large_files.build_output_bytes, 1024);
+        assert_eq!(Settings::default().large_files.max_bytes, 1024 * 1024);
+        assert_eq!(
+            Settings::default().large_files.build_output_bytes,
+            64 * 1024 * 1024
+        );
+
+        let malformed = json!({ "diagnostics": { "trigger": "sometimes" } });
+        assert_eq!(Settings::from_value(Some(&malformed)), Settings::default());
+    }
+
+    #[test]
+    fn test_malformed_fields_keep_the_others() {
+        let value = json!({
+            "diagnostics": {
+                "trigger": "sometimes",
+                "debounceMs": 250,
+                "rules": { "unsafe-typecast": "error", "mixed-case-function": 3 },
+                "exclude": ["lib/**"]
+            },
+            "trustedWorkspace": true,
+            "locale": 7,
+            "testOnSave": "yes",
+            "logLevel": "warning"
+        });
+        let settings = Settings::from_value(Some(&value));
+        assert_eq!(settings.diagnostics.trigger, DiagnosticsTrigger::OnSave);
+        assert_eq!(settings.diagnostics.debounce_ms, 250);
+        assert_eq!(
+            settings.diagnostics.rules,
+            BTreeMap::from([("unsafe-typecast".to_string(), RuleSeverity::Error)])
+        );
+        assert_eq!(settings.diagnostics.exclude, ["lib/**"]);
+        assert!(settings.diagnostics.unused);
+        assert!(settings.trusted_workspace);
+        assert_eq!(settings.locale, None);
+        assert_eq!(settings.test_on_save, TestOnSaveSettings::default());
+        assert_eq!(settings.log_level, LogLevel::Warning);
+    }
+
+    #[test]
+    fn test_diagnostics_rules() {
+        let value = json!({
+            "diagnostics": {
+                "build": false,
+                "rules": { "mixed-case-function": "off", "divide-before-multiply": "error" },
+                "exclude": ["lib/**", "test/**"]
+            }
+        });
+        let settings = Settings::from_value(Some(&value)).diagnostics;
+        assert!(!settings.build && settings.lint);
+        assert!(settings.excludes("lib/forge-std/src/Test.sol"));
+        assert!(settings.excludes("test/Vault.t.sol"));
+        assert!(!settings.excludes("src/Vault.sol"));
+
+        let diagnostic = |source: &str, code: &str| Diagnostic {
+            severity: Some(DiagnosticSeverity::WARNING),
+            code: Some(NumberOrString::String(code.to_string())),
+            source: Some(source.to_string()),
+            ..Diagnostic::default()
+        };
+        let mut diagnostics = vec![
+            diagnostic(BUILD_DIAGNOSTICS_SOURCE, "2072"),
+            diagnostic(LINT_DIAGNOSTICS_SOURCE, "mixed-case-function"),
+            diagnostic(LINT_DIAGNOSTICS_SOURCE, "divide-before-multiply"),
+            diagnostic(LINT_DIAGNOSTICS_SOURCE, "unsafe-typecast"),
+        ];
+        settings.apply(&mut diagnostics);
+        let kept: Vec<_> = diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.code.clone().unwrap(), diagnostic.severity))
+            .collect();
+        assert_eq!(
+            kept,
+            [
+                (
+                    NumberOrString::String("divide-before-multiply".to_string()),
+                    Some(DiagnosticSeverity::ERROR)
+                ),
+                (
+                    NumberOrString::String("unsafe-typecast".to_string()),
+                    Some(DiagnosticSeverity::WARNING)
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_large_files() {
+        let settings = LargeFilesSettings {
+            max_bytes: 1000,
+            directories: BTreeMap::from([
+                ("flat".to_string(), 0),
+                ("src/generated/".to_string(), 5000),
+                ("src/generated/huge".to_string(), 100_000),
+            ]),
+            ..LargeFilesSettings::default()
+        };
+        assert!(!settings.is_large("src/Vault.sol", 1000));
+        assert!(settings.is_large("src/Vault.sol", 1001));
+        assert!(settings.is_large("flat/Vault.sol", 1));
+        assert!(!settings.is_large("flattened/Vault.sol", 1));
+        assert_eq!(settings.max_bytes_for("src/generated/Router.sol"), 5000);
+        assert_eq!(
+            settings.max_bytes_for("src/generated/huge/Router.sol"),
+            100_000
+        );
+    }
+
+    #[test]
+    fn test_trigger_runs_on() {
+        use DiagnosticsEvent::*;
+
+        let on_change = DiagnosticsTrigger::OnChange;
+        assert!(
+            [Open, Change, Save, Command]
+                .iter()
+                .all(|e| on_change.runs_on(*e))
+        );
+
+        let on_save = DiagnosticsTrigger::OnSave;
+        assert!(on_save.runs_on(Open) && on_save.runs_on(Save) && on_save.runs_on(Command));
+        assert!(!on_save.runs_on(Change));
+
+        let manual = DiagnosticsTrigger::Manual;
+        assert!(manual.runs_on(Command));
+        assert!(!manual.runs_on(Open) && !manual.runs_on(Change) && !manual.runs_on(Save));
+    }
+}
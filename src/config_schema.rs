@@ -0,0 +1,310 @@
+//! `forge-lsp/configurationSchema`: a JSON schema of the server settings.
+//!
+//! Client plugins generate their settings UI from it and validate the user's configuration
+//! before sending it, instead of copying the settings from the README by hand. The schema
+//! describes the settings object of [`Settings`], the one clients send as
+//! `initializationOptions`, possibly under a `"forge-lsp"` key. Descriptions, types and
+//! allowed values are written here; defaults are those of [`Settings::default`], so they
+//! can't drift. Enumerated settings also carry VS Code's `enumDescriptions`.
+
+use serde_json::{Value, json};
+
+use crate::config::{SETTINGS_SECTION, Settings};
+
+/// Name of the custom request.
+pub const CONFIGURATION_SCHEMA_METHOD: &str = "forge-lsp/configurationSchema";
+
+fn boolean(description: &str) -> Value {
+    json!({ "type": "boolean", "description": description })
+}
+
+fn integer(description: &str) -> Value {
+    json!({ "type": "integer", "minimum": 0, "description": description })
+}
+
+fn optional(kind: &str, description: &str) -> Value {
+    json!({ "type": [kind, "null"], "description": description })
+}
+
+fn strings(description: &str) -> Value {
+    json!({ "type": "array", "items": { "type": "string" }, "description": description })
+}
+
+fn one_of(values: &[(&str, &str)], description: &str) -> Value {
+    json!({
+        "type": "string",
+        "enum": values.iter().map(|(value, _)| value).collect::<Vec<_>>(),
+        "enumDescriptions": values.iter().map(|(_, doc)| doc).collect::<Vec<_>>(),
+        "description": description,
+    })
+}
+
+fn object(description: &str, properties: Value) -> Value {
+    json!({
+        "type": "object",
+        "description": description,
+        "properties": properties,
+        "additionalProperties": false,
+    })
+}
+
+/// The settings without their defaults.
+fn properties() -> Value {
+    json!({
+        "diagnostics": object("Which diagnostics are published, and when.", json!({
+            "trigger": one_of(
+                &[
+                    ("onChange", "On open and save, and after edits once `debounceMs` passed."),
+                    ("onSave", "On open and save."),
+                    ("manual", "Only when the `forge-lsp.runDiagnostics` command is executed."),
+                ],
+                "When `forge build` and `forge lint` diagnostics run.",
+            ),
+            "debounceMs": integer(
+                "Quiet period in milliseconds after the last edit before diagnostics run in \
+                 `onChange` mode.",
+            ),
+            "annotations": boolean(
+                "Show `TODO`, `FIXME`, `audit:` and `@custom:security` comments as hints.",
+            ),
+            "natspec": boolean(
+                "Require `@notice`, `@param` and `@return` on external and public functions \
+                 in `src/`.",
+            ),
+            "mutability": boolean(
+                "Report functions that could be declared `view` or `pure`, and `STATICCALL`s \
+                 of view functions.",
+            ),
+            "interfaces": boolean(
+                "Report drift between contracts and their `I`-prefixed interfaces.",
+            ),
+            "unused": boolean("Report unused imports, local variables and parameters."),
+            "build": boolean("Publish the diagnostics of `forge build`."),
+            "lint": boolean("Publish the diagnostics of `forge lint`."),
+            "rules": {
+                "type": "object",
+                "description": "Severities of diagnostics by code, such as a `forge lint` rule; \
+                                `off` hides them.",
+                "additionalProperties": {
+                    "type": "string",
+                    "enum": ["off", "error", "warning", "info", "hint"],
+                },
+            },
+            "exclude": strings(
+                "Files whose diagnostics are hidden, as globs matched against their path \
+                 relative to the project root.",
+            ),
+        })),
+        "inlayHints": object("Which inlay hints are shown.", json!({
+            "parameterNames": boolean("Show parameter names before positional call arguments."),
+            "types": boolean("Show the types of the targets of tuple destructuring assignments."),
+            "returnNames": boolean(
+                "Show the names of return variables at `return` statements and after calls \
+                 whose return values are dropped.",
+            ),
+        })),
+        "trustedWorkspace": boolean(
+            "Trust the workspace up front instead of asking before the first `forge` run.",
+        ),
+        "gasEstimates": boolean(
+            "Show the gas costs of functions as code lenses and in hovers, running the tests \
+             of each project with `forge test --gas-report`.",
+        ),
+        "storageLayoutHovers": boolean(
+            "Show the storage slot of state variables in hovers, running `forge inspect` on \
+             their contract.",
+        ),
+        "testOnSave": object("Tests run after a file is saved.", json!({
+            "match": one_of(
+                &[
+                    ("off", "None."),
+                    ("file", "The test contracts of the saved file."),
+                    (
+                        "imports",
+                        "The test contracts of the saved file and of the files importing it.",
+                    ),
+                ],
+                "Which tests run after a save.",
+            ),
+        })),
+        "modelChecker": object("solc's SMTChecker, run after saves.", json!({
+            "contracts": strings(
+                "Contracts checked after each save of their file, as `path:Name` with the path \
+                 relative to the project root.",
+            ),
+            "engine": one_of(
+                &[
+                    ("chc", "Constrained Horn clauses, across transactions."),
+                    ("bmc", "Bounded model checking, function by function."),
+                    ("all", "Both engines."),
+                ],
+                "Engine of the SMTChecker.",
+            ),
+            "targets": strings("Properties to check, all of them when empty."),
+            "timeout": optional(
+                "integer",
+                "Timeout of each query in milliseconds, solc's when unset.",
+            ),
+        })),
+        "bindings": object("Rust bindings generated with `forge bind`.", json!({
+            "onAbiChange": boolean(
+                "Generate the bindings again after each build that changes the ABIs of the \
+                 project.",
+            ),
+            "path": optional(
+                "string",
+                "Directory of the bindings relative to the project root, forge's \
+                 `out/bindings` when unset.",
+            ),
+            "crateName": optional("string", "Name of the generated crate."),
+            "crateVersion": optional("string", "Version of the generated crate."),
+            "module": boolean(
+                "Generate a module to include in an existing crate instead of a crate.",
+            ),
+            "singleFile": boolean("Generate all bindings in a single file."),
+            "select": strings(
+                "Regular expressions of the contracts to generate bindings for, all of them \
+                 when empty.",
+            ),
+            "alloyVersion": optional("string", "Version of alloy the generated crate depends on."),
+        })),
+        "rename": object("How renames treat overriding functions.", json!({
+            "overrides": boolean(
+                "Rename the functions a renamed function overrides or is overridden by along \
+                 with it.",
+            ),
+            "confirmOverrides": boolean(
+                "Mark the edits of those functions for the client to confirm before applying \
+                 them.",
+            ),
+        })),
+        "largeFiles": object(
+            "Limits above which files, such as flattened contracts, get a reduced feature set.",
+            json!({
+                "maxBytes": integer("Size in bytes above which a file is large."),
+                "directories": {
+                    "type": "object",
+                    "description": "Sizes replacing `maxBytes` for the files under directories \
+                                    relative to the project root, the most nested directory \
+                                    applying.",
+                    "additionalProperties": { "type": "integer", "minimum": 0 },
+                },
+                "buildOutputBytes": integer(
+                    "Size in bytes of `forge build` output and build-info files above which only \
+                     the sections the server reads are parsed.",
+                ),
+            }),
+        ),
+        "locale": optional(
+            "string",
+            "Language of the server's messages, as a tag such as `de`, instead of the client's \
+             locale. English, German and Spanish are supported.",
+        ),
+        "logLevel": one_of(
+            &[
+                ("error", "Errors only."),
+                ("warning", "Errors and warnings."),
+                ("info", "Also requests as they arrive, and the steps of builds and test runs."),
+                ("log", "Everything."),
+            ],
+            "Least severe level of the messages logged to the client.",
+        ),
+    })
+}
+
+/// Set the `default` of each schema in `properties` from `defaults`, the matching defaults.
+fn set_defaults(properties: &mut Value, defaults: &Value) {
+    let Some(properties) = properties.as_object_mut() else {
+        return;
+    };
+    for (name, schema) in properties {
+        let Some(default) = defaults.get(name) else {
+            continue;
+        };
+        match schema.get_mut("properties") {
+            Some(nested) => set_defaults(nested, default),
+            None => schema["default"] = default.clone(),
+        }
+    }
+}
+
+/// The JSON schema of the server settings.
+pub fn configuration_schema() -> Value {
+    let mut properties = properties();
+    let defaults = serde_json::to_value(Settings::default()).unwrap_or_default();
+    set_defaults(&mut properties, &defaults);
+    json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "title": "forge-lsp settings",
+        "description": format!(
+            "Settings of forge-lsp, sent as `initializationOptions` and in \
+             `workspace/didChangeConfiguration`, possibly under a `\"{SETTINGS_SECTION}\"` key."
+        ),
+        "type": "object",
+        "properties": properties,
+        "additionalProperties": false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The `/`-separated paths of the settings described by `schema`, or given by `value`.
+    fn schema_paths(schema: &Value, prefix: &str, paths: &mut Vec<String>) {
+        for (name, property) in schema["properties"].as_object().into_iter().flatten() {
+            let path = format!("{prefix}{name}");
+            schema_paths(property, &format!("{path}/"), paths);
+            if property.get("properties").is_none() {
+                paths.push(path);
+            }
+        }
+    }
+
+    fn value_paths(value: &Value, prefix: &str, paths: &mut Vec<String>) {
+        for (name, field) in value.as_object().into_iter().flatten() {
+            let path = format!("{prefix}{name}");
+            // Maps of settings by key are objects too, but empty by default
+            match field {
+                Value::Object(fields) if !fields.is_empty() => {
+                    value_paths(field, &format!("{path}/"), paths);
+                }
+                _ => paths.push(path),
+            }
+        }
+    }
+
+    #[test]
+    fn test_configuration_schema() {
+        let schema = configuration_schema();
+        let defaults = serde_json::to_value(Settings::default()).unwrap();
+        let (mut described, mut settings) = (vec![], vec![]);
+        schema_paths(&schema, "", &mut described);
+        value_paths(&defaults, "", &mut settings);
+        described.sort();
+        settings.sort();
+        assert_eq!(described, settings);
+
+        let diagnostics = &schema["properties"]["diagnostics"]["properties"];
+        assert_eq!(diagnostics["trigger"]["default"], "onSave");
+        assert_eq!(diagnostics["debounceMs"]["default"], 500);
+        assert_eq!(diagnostics["rules"]["default"], json!({}));
+        let bindings = &schema["properties"]["bindings"]["properties"];
+        assert_eq!(bindings["path"]["default"], Value::Null);
+        assert_eq!(
+            schema["properties"]["testOnSave"]["properties"]["match"]["enum"],
+            json!(["off", "file", "imports"])
+        );
+
+        // Every value the schema allows parses to that value
+        for trigger in diagnostics["trigger"]["enum"].as_array().unwrap() {
+            let settings = json!({ "diagnostics": { "trigger": trigger } });
+            assert_eq!(
+                serde_json::to_value(Settings::from_value(Some(&settings))).unwrap()["diagnostics"]
+                    ["trigger"],
+                *trigger
+            );
+        }
+        assert_eq!(Settings::from_value(Some(&defaults)), Settings::default());
+    }
+}
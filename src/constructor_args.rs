@@ -0,0 +1,417 @@
+//! Constructor arguments of `new Contract(...)` in scripts, checked against the constructor
+//! signature in the project index while the script is still being edited: parameter name
+//! hints, completion of the parameters, and argument count mismatches.
+//!
+//! The calls come from the in-process parse of the script, or from its text for the call
+//! being typed, so none of them wait for the compiler.
+
+use serde_json::Value;
+use tower_lsp::lsp_types::{
+    CompletionItem, CompletionItemKind, Diagnostic, DiagnosticSeverity, Documentation, InlayHint,
+    InlayHintKind, InlayHintLabel, InsertTextFormat, NumberOrString, Position, Range, Url,
+};
+
+use crate::{
+    ast::{self, children, name, node_type, span_bounds},
+    goto::{bytes_to_pos, pos_to_bytes},
+    inlay_hints::is_self_describing,
+};
+
+/// Diagnostic code of a `new` expression passing the wrong number of arguments.
+pub const CONSTRUCTOR_ARGUMENTS_CODE: &str = "constructor-arguments";
+
+/// Parameters of the constructor of the deployable contract `contract` in the project AST,
+/// empty when it declares none.
+fn constructor_parameters<'a>(project_ast: &'a Value, contract: &str) -> Option<Vec<&'a Value>> {
+    let sources = project_ast.get("sources")?.as_object()?;
+    let definition = sources
+        .values()
+        .filter_map(|contents| contents.get(0)?.get("source_file")?.get("ast"))
+        .flat_map(|unit| children(unit, "nodes"))
+        .find(|node| {
+            node_type(node) == Some("ContractDefinition")
+                && node.get("contractKind").and_then(Value::as_str) == Some("contract")
+                && node.get("abstract").and_then(Value::as_bool) != Some(true)
+                && name(node) == contract
+        })?;
+    Some(
+        children(definition, "nodes")
+            .find(|node| {
+                node_type(node) == Some("FunctionDefinition")
+                    && node.get("kind").and_then(Value::as_str) == Some("constructor")
+            })
+            .and_then(|constructor| constructor.get("parameters"))
+            .map(|list| children(list, "parameters").collect())
+            .unwrap_or_default(),
+    )
+}
+
+fn type_string(parameter: &Value) -> &str {
+    parameter
+        .get("typeDescriptions")
+        .and_then(|descriptions| descriptions.get("typeString"))
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+}
+
+/// A `new Contract(...)` call of the script and the name of the contract it deploys.
+struct NewCall<'a> {
+    call: &'a Value,
+    contract: &'a str,
+}
+
+impl<'a> NewCall<'a> {
+    fn arguments(&self) -> impl Iterator<Item = &'a Value> {
+        children(self.call, "arguments")
+    }
+
+    fn is_named(&self) -> bool {
+        children(self.call, "names").next().is_some()
+    }
+}
+
+/// The `new` calls in `uri` of the parsed `tree`.
+fn new_calls<'a>(tree: &'a Value, uri: &Url) -> Vec<NewCall<'a>> {
+    let Some(unit) = ast::source_unit(tree, uri) else {
+        return vec![];
+    };
+    let mut calls = Vec::new();
+    ast::walk(unit, &mut |node| {
+        if node_type(node) != Some("FunctionCall") {
+            return;
+        }
+        // `new Vault{salt: salt}(...)`
+        let mut callee = node.get("expression");
+        while let Some(options) =
+            callee.filter(|callee| node_type(callee) == Some("FunctionCallOptions"))
+        {
+            callee = options.get("expression");
+        }
+        let contract = callee
+            .filter(|callee| node_type(callee) == Some("NewExpression"))
+            .and_then(|new| new.get("typeName"))
+            .filter(|type_name| node_type(type_name) == Some("UserDefinedTypeName"))
+            .and_then(|type_name| type_name.get("pathNode"))
+            .and_then(|path| path.get("name"))
+            .and_then(Value::as_str);
+        if let Some(contract) = contract {
+            calls.push(NewCall {
+                call: node,
+                contract,
+            });
+        }
+    });
+    calls
+}
+
+/// `name:` labels before the positional constructor arguments of the `new` calls of `uri`
+/// within `range`.
+pub fn constructor_hints(
+    tree: &Value,
+    uri: &Url,
+    source_bytes: &[u8],
+    range: Range,
+    project_ast: &Value,
+) -> Vec<InlayHint> {
+    let mut hints = Vec::new();
+    for call in new_calls(tree, uri) {
+        if call.is_named() {
+            continue;
+        }
+        let Some(parameters) = constructor_parameters(project_ast, call.contract) else {
+            continue;
+        };
+        for (argument, parameter) in call.arguments().zip(parameters) {
+            let parameter_name = name(parameter);
+            if parameter_name.is_empty() || is_self_describing(argument, parameter_name) {
+                continue;
+            }
+            let Some(position) =
+                span_bounds(argument).and_then(|(start, _)| bytes_to_pos(source_bytes, start))
+            else {
+                continue;
+            };
+            if position < range.start || position > range.end {
+                continue;
+            }
+            hints.push(InlayHint {
+                position,
+                label: InlayHintLabel::String(format!("{parameter_name}:")),
+                kind: Some(InlayHintKind::PARAMETER),
+                text_edits: None,
+                tooltip: None,
+                padding_left: None,
+                padding_right: Some(true),
+                data: None,
+            });
+        }
+    }
+    hints.sort_by_key(|hint| hint.position);
+    hints
+}
+
+/// Errors for the `new` calls of `uri` passing more or fewer arguments than the constructor
+/// takes.
+pub fn argument_count_diagnostics(
+    tree: &Value,
+    uri: &Url,
+    source_bytes: &[u8],
+    project_ast: &Value,
+) -> Vec<Diagnostic> {
+    new_calls(tree, uri)
+        .into_iter()
+        .filter_map(|call| {
+            let parameters = constructor_parameters(project_ast, call.contract)?;
+            let given = call.arguments().count();
+            if given == parameters.len() {
+                return None;
+            }
+            let (start, end) = span_bounds(call.call)?;
+            let expected = match parameters.len() {
+                1 => "1 argument".to_string(),
+                count => format!("{count} arguments"),
+            };
+            let signature: Vec<String> = parameters
+                .iter()
+                .map(|parameter| {
+                    format!("{} {}", type_string(parameter), name(parameter))
+                        .trim_end()
+                        .to_string()
+                })
+                .collect();
+            Some(Diagnostic {
+                range: Range::new(
+                    bytes_to_pos(source_bytes, start)?,
+                    bytes_to_pos(source_bytes, end)?,
+                ),
+                severity: Some(DiagnosticSeverity::ERROR),
+                code: Some(NumberOrString::String(
+                    CONSTRUCTOR_ARGUMENTS_CODE.to_string(),
+                )),
+                source: Some("forge-lsp".to_string()),
+                message: format!(
+                    "the `{}` constructor takes {expected}, {given} given: constructor({})",
+                    call.contract,
+                    signature.join(", ")
+                ),
+                ..Diagnostic::default()
+            })
+        })
+        .collect()
+}
+
+/// The contract and argument index of the `new Contract(` call the cursor at `cursor` is
+/// in, as typed in `source`, and whether no arguments have been typed yet.
+fn open_new_call(source: &str, cursor: usize) -> Option<(&str, usize, bool)> {
+    let before = source.get(..cursor)?;
+    let mut depth = 0usize;
+    let mut commas = 0;
+    let mut open = None;
+    for (offset, c) in before.char_indices().rev() {
+        match c {
+            ')' | ']' | '}' => depth += 1,
+            '(' | '[' | '{' if depth > 0 => depth -= 1,
+            '(' => {
+                open = Some(offset);
+                break;
+            }
+            '[' | '{' | ';' => return None,
+            ',' if depth == 0 => commas += 1,
+            _ => {}
+        }
+    }
+    let open = open?;
+    let mut callee = before[..open].trim_end();
+    // Call options, `new Vault{salt: salt}(`
+    if callee.ends_with('}') {
+        callee = callee[..callee.rfind('{')?].trim_end();
+    }
+    let name_start = callee
+        .rfind(|c: char| !(c.is_alphanumeric() || c == '_' || c == '$' || c == '.'))
+        .map_or(0, |i| i + 1);
+    let contract = &callee[name_start..];
+    let keyword = callee[..name_start].trim_end();
+    let is_new = keyword.ends_with("new")
+        && !keyword[..keyword.len() - 3]
+            .ends_with(|c: char| c.is_alphanumeric() || c == '_' || c == '$');
+    if contract.is_empty() || !is_new {
+        return None;
+    }
+    // The contract name without its import alias or library qualifier
+    let contract = contract.rsplit('.').next()?;
+    Some((contract, commas, before[open + 1..].trim().is_empty()))
+}
+
+/// Completions of the constructor parameters at `position` inside a `new Contract(` call:
+/// the parameter at the cursor, and all of them as a snippet before the first argument.
+pub fn constructor_completions(
+    project_ast: &Value,
+    position: Position,
+    source_bytes: &[u8],
+) -> Vec<CompletionItem> {
+    let source = String::from_utf8_lossy(source_bytes);
+    let cursor = pos_to_bytes(source_bytes, position);
+    let Some((contract, index, empty)) = open_new_call(&source, cursor) else {
+        return vec![];
+    };
+    let Some(parameters) = constructor_parameters(project_ast, contract) else {
+        return vec![];
+    };
+
+    let mut items = Vec::new();
+    if empty && parameters.len() > 1 && parameters.iter().all(|p| !name(p).is_empty()) {
+        let names: Vec<&str> = parameters.iter().map(|parameter| name(parameter)).collect();
+        let snippet: Vec<String> = names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| format!("${{{}:{name}}}", i + 1))
+            .collect();
+        items.push(CompletionItem {
+            label: names.join(", "),
+            kind: Some(CompletionItemKind::SNIPPET),
+            detail: Some(format!("`{contract}` constructor arguments")),
+            insert_text: Some(snippet.join(", ")),
+            insert_text_format: Some(InsertTextFormat::SNIPPET),
+            ..CompletionItem::default()
+        });
+    }
+    if let Some(parameter) = parameters.get(index).filter(|p| !name(p).is_empty()) {
+        items.push(CompletionItem {
+            label: name(parameter).to_string(),
+            kind: Some(CompletionItemKind::VARIABLE),
+            detail: Some(type_string(parameter).to_string()),
+            documentation: Some(Documentation::String(format!(
+                "Parameter {} of the `{contract}` constructor",
+                index + 1
+            ))),
+            ..CompletionItem::default()
+        });
+    }
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax;
+    use serde_json::json;
+
+    const SCRIPT: &str = "\
+contract DeployVault is Script {
+    function run() public {
+        address owner = msg.sender;
+        new Vault(owner, 100);
+        new Vault{salt: bytes32(0)}(owner);
+        new Vault({owner: owner, cap: 1});
+        new Token();
+    }
+}
+";
+
+    const SCRIPT_PATH: &str = "/project/script/DeployVault.s.sol";
+
+    fn parameter(name: &str, type_string: &str) -> Value {
+        json!({
+            "nodeType": "VariableDeclaration",
+            "name": name,
+            "typeDescriptions": { "typeString": type_string }
+        })
+    }
+
+    fn project_ast() -> Value {
+        ast::mock_ast(
+            "src/Vault.sol",
+            json!({
+                "nodeType": "SourceUnit",
+                "absolutePath": "src/Vault.sol",
+                "nodes": [
+                    {
+                        "nodeType": "ContractDefinition",
+                        "name": "Vault",
+                        "contractKind": "contract",
+                        "abstract": false,
+                        "nodes": [{
+                            "nodeType": "FunctionDefinition",
+                            "kind": "constructor",
+                            "parameters": { "parameters": [
+                                parameter("owner", "address"),
+                                parameter("cap", "uint256")
+                            ]}
+                        }]
+                    },
+                    {
+                        "nodeType": "ContractDefinition",
+                        "name": "Token",
+                        "contractKind": "contract",
+                        "abstract": false,
+                        "nodes": []
+                    }
+                ]
+            }),
+        )
+    }
+
+    fn uri() -> Url {
+        Url::from_file_path(SCRIPT_PATH).unwrap()
+    }
+
+    #[test]
+    fn test_constructor_hints_and_argument_counts() {
+        let tree = syntax::parse(SCRIPT_PATH, SCRIPT);
+        let everything = Range::new(Position::new(0, 0), Position::new(9, 0));
+
+        let hints = constructor_hints(&tree, &uri(), SCRIPT.as_bytes(), everything, &project_ast());
+        let labels: Vec<(Position, String)> = hints
+            .into_iter()
+            .filter_map(|hint| match hint.label {
+                InlayHintLabel::String(label) => Some((hint.position, label)),
+                InlayHintLabel::LabelParts(_) => None,
+            })
+            .collect();
+        // `owner` already says what it is
+        assert_eq!(labels, [(Position::new(3, 25), "cap:".to_string())]);
+
+        let diagnostics =
+            argument_count_diagnostics(&tree, &uri(), SCRIPT.as_bytes(), &project_ast());
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].range,
+            Range::new(Position::new(4, 8), Position::new(4, 42))
+        );
+        assert_eq!(
+            diagnostics[0].message,
+            "the `Vault` constructor takes 2 arguments, 1 given: constructor(address owner, uint256 cap)"
+        );
+    }
+
+    #[test]
+    fn test_constructor_completions_while_typing() {
+        let complete = |source: &str| {
+            let cursor = source.find('|').unwrap();
+            let source = source.replace('|', "");
+            let position = bytes_to_pos(source.as_bytes(), cursor).unwrap();
+            constructor_completions(&project_ast(), position, source.as_bytes())
+                .into_iter()
+                .map(|item| (item.label, item.insert_text))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            complete("vault = new Vault(|"),
+            [
+                (
+                    "owner, cap".to_string(),
+                    Some("${1:owner}, ${2:cap}".to_string())
+                ),
+                ("owner".to_string(), None)
+            ]
+        );
+        assert_eq!(
+            complete("new Vault{salt: s}(address(this), |"),
+            [("cap".to_string(), None)]
+        );
+        assert_eq!(complete("renew Vault(|"), []);
+        assert_eq!(complete("new Missing(|"), []);
+        assert_eq!(complete("new Vault(owner, cap);\nfoo(|"), []);
+    }
+}
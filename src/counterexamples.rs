@@ -0,0 +1,705 @@
+//! Counterexamples of failed fuzz and invariant tests, as diagnostics on the test.
+//!
+//! forge reports the input a fuzz test failed with as calldata, and as the arguments it
+//! decoded from it joined on one line. The calldata is decoded again here with the ABI of
+//! the test contract, read from its artifact, so each value is listed under the name of its
+//! parameter as a Solidity literal: `amount = 2, to = 0x5615dEB798BB3E4dFa0139dFa1b3D433Cc23b72f`.
+//! The diagnostic on the test function offers a quick fix inserting a unit test that calls
+//! the fuzz test with those values, so the failing case stays covered once the fuzzer moves
+//! on. Values of array and tuple parameters aren't single literals, so tests taking them get
+//! no regression test, and without an artifact the arguments are listed as forge printed
+//! them. A broken invariant lists the sequence of calls breaking it instead.
+
+use serde_json::Value;
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString, TextEdit, Url};
+
+use crate::{
+    ast::{self, children, name, span_bounds},
+    code_actions::Fix,
+    edits::EditBuilder,
+    events::{self, canonical_type},
+    forge_test::{self, TestOutcome},
+    project::ProjectConfig,
+    selectors::keccak256,
+};
+
+/// Diagnostic code of a counterexample.
+pub const COUNTEREXAMPLE_CODE: &str = "fuzz-counterexample";
+
+/// Prefixes of fuzz tests, dropped from the names of their regression tests.
+const FUZZ_PREFIXES: &[&str] = &["testFuzz_", "testFuzz", "test_", "test"];
+
+/// A type of the ABI.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum AbiType {
+    Uint,
+    Int,
+    Address,
+    Bool,
+    FixedBytes(usize),
+    Bytes,
+    String,
+    Array(Box<AbiType>, Option<usize>),
+    Tuple(Vec<AbiType>),
+}
+
+impl AbiType {
+    /// The type of the ABI parameter `param`.
+    fn of(param: &Value) -> Option<Self> {
+        Self::parse(param.get("type")?.as_str()?, param.get("components"))
+    }
+
+    fn parse(abi_type: &str, components: Option<&Value>) -> Option<Self> {
+        if let Some(element) = abi_type.strip_suffix(']') {
+            let open = element.rfind('[')?;
+            let length = &element[open + 1..];
+            let length = match length {
+                "" => None,
+                _ => Some(length.parse().ok()?),
+            };
+            let element = Self::parse(&element[..open], components)?;
+            return Some(Self::Array(Box::new(element), length));
+        }
+        Some(match abi_type {
+            "address" => Self::Address,
+            "bool" => Self::Bool,
+            "bytes" => Self::Bytes,
+            "string" => Self::String,
+            "function" => Self::FixedBytes(24),
+            "tuple" => Self::Tuple(
+                components?
+                    .as_array()?
+                    .iter()
+                    .map(Self::of)
+                    .collect::<Option<_>>()?,
+            ),
+            _ if abi_type.starts_with("uint") => Self::Uint,
+            _ if abi_type.starts_with("int") => Self::Int,
+            _ => Self::FixedBytes(abi_type.strip_prefix("bytes")?.parse().ok()?),
+        })
+    }
+
+    fn is_dynamic(&self) -> bool {
+        match self {
+            Self::Bytes | Self::String | Self::Array(_, None) => true,
+            Self::Array(element, Some(_)) => element.is_dynamic(),
+            Self::Tuple(members) => members.iter().any(Self::is_dynamic),
+            _ => false,
+        }
+    }
+
+    /// Bytes the type takes in the head of the tuple holding it.
+    fn head_size(&self) -> usize {
+        match self {
+            _ if self.is_dynamic() => 32,
+            Self::Array(element, Some(length)) => element.head_size() * length,
+            Self::Tuple(members) => members.iter().map(Self::head_size).sum(),
+            _ => 32,
+        }
+    }
+}
+
+fn word(data: &[u8], at: usize) -> Option<&[u8]> {
+    data.get(at..at.checked_add(32)?)
+}
+
+fn read_usize(data: &[u8], at: usize) -> Option<usize> {
+    let word = word(data, at)?;
+    if word[..24].iter().any(|&b| b != 0) {
+        return None;
+    }
+    Some(u64::from_be_bytes(word[24..].try_into().ok()?) as usize)
+}
+
+/// The unsigned big-endian number `bytes` in decimal.
+fn decimal(bytes: &[u8]) -> String {
+    let mut number = bytes.to_vec();
+    let mut digits = Vec::new();
+    while number.iter().any(|&b| b != 0) {
+        let mut remainder = 0u32;
+        for byte in &mut number {
+            let value = (remainder << 8) | u32::from(*byte);
+            *byte = (value / 10) as u8;
+            remainder = value % 10;
+        }
+        digits.push(char::from(b'0' + remainder as u8));
+    }
+    if digits.is_empty() {
+        return "0".to_string();
+    }
+    digits.iter().rev().collect()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// The EIP-55 checksummed spelling of `address`, the one Solidity accepts as a literal.
+fn checksummed(address: &[u8]) -> String {
+    let lower = hex(address);
+    let hash = keccak256(lower.as_bytes());
+    let mixed: String = lower
+        .chars()
+        .enumerate()
+        .map(|(i, c)| {
+            let nibble = (hash[i / 2] >> (if i % 2 == 0 { 4 } else { 0 })) & 0xf;
+            if nibble >= 8 {
+                c.to_ascii_uppercase()
+            } else {
+                c
+            }
+        })
+        .collect();
+    format!("0x{mixed}")
+}
+
+/// `bytes` as a Solidity string literal.
+fn string_literal(bytes: &[u8]) -> String {
+    let mut literal = String::from("\"");
+    for &byte in bytes {
+        match byte {
+            b'"' => literal.push_str("\\\""),
+            b'\\' => literal.push_str("\\\\"),
+            b'\n' => literal.push_str("\\n"),
+            0x20..=0x7e => literal.push(char::from(byte)),
+            _ => literal.push_str(&format!("\\x{byte:02x}")),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+/// The value of `abi_type` encoded at `at` of `data`, as Solidity source.
+fn decode_at(abi_type: &AbiType, data: &[u8], at: usize) -> Option<String> {
+    Some(match abi_type {
+        AbiType::Uint => decimal(word(data, at)?),
+        AbiType::Int => {
+            let word = word(data, at)?;
+            if word[0] & 0x80 == 0 {
+                decimal(word)
+            } else {
+                // The magnitude of a negative number is its two's complement
+                let mut magnitude: Vec<u8> = word.iter().map(|b| !b).collect();
+                for byte in magnitude.iter_mut().rev() {
+                    let (sum, carry) = byte.overflowing_add(1);
+                    *byte = sum;
+                    if !carry {
+                        break;
+                    }
+                }
+                format!("-{}", decimal(&magnitude))
+            }
+        }
+        AbiType::Address => checksummed(&word(data, at)?[12..]),
+        AbiType::Bool => (word(data, at)?[31] != 0).to_string(),
+        AbiType::FixedBytes(size) => {
+            format!("bytes{size}(0x{})", hex(word(data, at)?.get(..*size)?))
+        }
+        AbiType::Bytes | AbiType::String => {
+            let length = read_usize(data, at)?;
+            let start = at + 32;
+            let bytes = data.get(start..start.checked_add(length)?)?;
+            match abi_type {
+                AbiType::Bytes => format!("hex\"{}\"", hex(bytes)),
+                _ => string_literal(bytes),
+            }
+        }
+        AbiType::Array(element, length) => {
+            let (length, start) = match length {
+                Some(length) => (*length, at),
+                None => (read_usize(data, at)?, at + 32),
+            };
+            // Lengths come from the data; each element takes a word at least
+            if length > data.len() / 32 {
+                return None;
+            }
+            let elements = vec![element.as_ref().clone(); length];
+            format!("[{}]", decode_tuple(&elements, data, start)?.join(", "))
+        }
+        AbiType::Tuple(members) => format!("({})", decode_tuple(members, data, at)?.join(", ")),
+    })
+}
+
+/// The values of `types` encoded as a tuple at `base` of `data`.
+fn decode_tuple(types: &[AbiType], data: &[u8], base: usize) -> Option<Vec<String>> {
+    let mut head = base;
+    let mut values = Vec::new();
+    for abi_type in types {
+        let at = if abi_type.is_dynamic() {
+            base.checked_add(read_usize(data, head)?)?
+        } else {
+            head
+        };
+        values.push(decode_at(abi_type, data, at)?);
+        head += abi_type.head_size();
+    }
+    Some(values)
+}
+
+/// A call decoded with an ABI.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedCall {
+    pub function: String,
+    /// Each parameter of the function with the value of the call, as Solidity source.
+    pub arguments: Vec<(String, String)>,
+    /// The values as the arguments of a call of the function, when each is a single
+    /// expression of the parameter's type.
+    pub call_arguments: Option<Vec<String>>,
+}
+
+/// `value` of the ABI parameter `param` as an argument of type `param`: converted when the
+/// literal alone has another type, like `payable(0x...)`, `IERC20(0x...)` or
+/// `Price.wrap(1)`.
+fn call_argument(param: &Value, value: &str) -> Option<String> {
+    let abi_type = param.get("type")?.as_str()?;
+    let internal = param
+        .get("internalType")
+        .and_then(Value::as_str)
+        .unwrap_or(abi_type);
+    if abi_type.ends_with(']') || abi_type.starts_with("tuple") {
+        return None;
+    }
+    Some(if internal == abi_type {
+        value.to_string()
+    } else if internal == "address payable" {
+        format!("payable({value})")
+    } else if let Some(name) = internal
+        .strip_prefix("contract ")
+        .or_else(|| internal.strip_prefix("enum "))
+    {
+        format!("{name}({value})")
+    } else {
+        // A user-defined value type
+        format!("{internal}.wrap({value})")
+    })
+}
+
+/// `calldata`, a hex string, decoded with the function of `abi` whose selector it starts
+/// with.
+pub fn decode_calldata(abi: &Value, calldata: &str) -> Option<DecodedCall> {
+    let hex = calldata.strip_prefix("0x").unwrap_or(calldata);
+    let data: Vec<u8> = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect::<Option<_>>()?;
+    let (selector, encoded) = (data.get(..4)?, data.get(4..)?);
+    let function = abi.as_array()?.iter().find(|item| {
+        let signature = || {
+            let inputs = item
+                .get("inputs")?
+                .as_array()?
+                .iter()
+                .map(canonical_type)
+                .collect::<Option<Vec<_>>>()?;
+            Some(format!(
+                "{}({})",
+                item.get("name")?.as_str()?,
+                inputs.join(",")
+            ))
+        };
+        item.get("type").and_then(Value::as_str) == Some("function")
+            && signature()
+                .is_some_and(|signature| keccak256(signature.as_bytes())[..4] == *selector)
+    })?;
+    let inputs = function.get("inputs")?.as_array()?;
+    let types: Vec<AbiType> = inputs.iter().map(AbiType::of).collect::<Option<_>>()?;
+    let values = decode_tuple(&types, encoded, 0)?;
+    let call_arguments = inputs
+        .iter()
+        .zip(&values)
+        .map(|(param, value)| call_argument(param, value))
+        .collect();
+    let arguments = inputs
+        .iter()
+        .zip(values)
+        .enumerate()
+        .map(|(i, (param, value))| {
+            let name = param
+                .get("name")
+                .and_then(Value::as_str)
+                .unwrap_or_default();
+            let name = if name.is_empty() {
+                format!("#{i}")
+            } else {
+                name.to_string()
+            };
+            (name, value)
+        })
+        .collect();
+    Some(DecodedCall {
+        function: function.get("name")?.as_str()?.to_string(),
+        arguments,
+        call_arguments,
+    })
+}
+
+/// The ABI of `contract`, declared in `uri`, from its artifact in the project of `uri`.
+pub fn artifact_abi(uri: &Url, contract: &str) -> Option<Value> {
+    let path = uri.to_file_path().ok()?;
+    let config = ProjectConfig::find(&path)?;
+    let artifact = events::artifact_path(&config.root.join(&config.out), &path, contract);
+    let artifact: Value = serde_json::from_str(&std::fs::read_to_string(artifact).ok()?).ok()?;
+    artifact.get("abi").cloned()
+}
+
+/// The leading whitespace of the line holding `offset`.
+fn indent_at(source: &str, offset: usize) -> &str {
+    let line_start = source[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let line = &source[line_start..];
+    &line[..line.len() - line.trim_start_matches([' ', '\t']).len()]
+}
+
+/// A name for the regression test of the fuzz test `test` no function of `contract` has.
+fn regression_name(contract: &Value, test: &str) -> String {
+    let base = FUZZ_PREFIXES
+        .iter()
+        .find_map(|prefix| test.strip_prefix(prefix))
+        .filter(|base| !base.is_empty())
+        .unwrap_or(test);
+    let taken = |candidate: &str| children(contract, "nodes").any(|node| name(node) == candidate);
+    let name = format!("test_{base}_counterexample");
+    std::iter::once(name.clone())
+        .chain((2..).map(|i| format!("{name}{i}")))
+        .find(|candidate| !taken(candidate))
+        .unwrap_or(name)
+}
+
+/// The name of a test calling the fuzz test `test` of `contract` with `arguments`, and the
+/// edits inserting it after `test`.
+fn regression_test(
+    source: &str,
+    contract: &Value,
+    test: &Value,
+    arguments: &[String],
+) -> Option<(String, Vec<TextEdit>)> {
+    let (start, end) = span_bounds(test)?;
+    let test_name = name(test);
+    let name = regression_name(contract, test_name);
+    let indent = indent_at(source, start);
+    let level = test
+        .pointer("/body/statements/0")
+        .and_then(span_bounds)
+        .map(|(start, _)| indent_at(source, start))
+        .and_then(|inner| inner.strip_prefix(indent))
+        .filter(|level| !level.is_empty())
+        .unwrap_or("    ");
+    let text = format!(
+        "\n\n{indent}function {name}() public {{\n{indent}{level}{}({});\n{indent}}}",
+        test_name,
+        arguments.join(", ")
+    );
+    let mut edits = EditBuilder::new(source);
+    edits.insert(end, text).ok()?;
+    Some((name, edits.build()))
+}
+
+/// The message listing the calls of an invariant's counterexample.
+fn sequence_message(outcome: &TestOutcome) -> String {
+    let calls: Vec<String> = outcome
+        .counterexample_calls
+        .iter()
+        .enumerate()
+        .map(|(i, call)| {
+            let function = call
+                .signature
+                .as_deref()
+                .map(|signature| signature.split('(').next().unwrap_or(signature));
+            let mut line = match (&call.contract, function) {
+                (Some(contract), Some(function)) => format!("{contract}.{function}"),
+                (None, Some(function)) => function.to_string(),
+                _ => call.calldata.clone().unwrap_or_default(),
+            };
+            if function.is_some() {
+                line.push_str(&format!("({})", call.args.as_deref().unwrap_or_default()));
+            }
+            if let Some(sender) = &call.sender {
+                line.push_str(&format!(" from {sender}"));
+            }
+            format!("{}. {line}", i + 1)
+        })
+        .collect();
+    format!(
+        "`{}` is broken by the sequence:\n{}",
+        outcome.test,
+        calls.join("\n")
+    )
+}
+
+/// Diagnostics on the failed fuzz and invariant tests of `uri` among `outcomes` listing
+/// their counterexamples. `abi` gives the ABI of a contract of `uri` by name.
+pub fn counterexample_diagnostics(
+    ast_data: &Value,
+    uri: &Url,
+    source_bytes: &[u8],
+    outcomes: &[TestOutcome],
+    abi: impl Fn(&str) -> Option<Value>,
+) -> Vec<Diagnostic> {
+    let source = String::from_utf8_lossy(source_bytes);
+    let mut diagnostics = Vec::new();
+    let contracts = ast::source_unit(ast_data, uri)
+        .and_then(|unit| unit.get("nodes"))
+        .and_then(Value::as_array);
+    for contract in contracts.into_iter().flatten() {
+        let contract_name = name(contract);
+        let functions = contract.get("nodes").and_then(Value::as_array);
+        for function in functions.into_iter().flatten() {
+            let Some(outcome) = outcomes.iter().find(|outcome| {
+                !outcome.passed
+                    && outcome.contract == contract_name
+                    && name(function) == outcome.test
+                    && function.get("nodeType").and_then(Value::as_str)
+                        == Some("FunctionDefinition")
+            }) else {
+                continue;
+            };
+            let (message, fix) = match &outcome.counterexample_calls[..] {
+                [] => continue,
+                [call] if call.sender.is_none() => {
+                    let decoded = call
+                        .calldata
+                        .as_deref()
+                        .zip(abi(contract_name))
+                        .and_then(|(calldata, abi)| decode_calldata(&abi, calldata))
+                        .filter(|decoded| decoded.function == outcome.test);
+                    match decoded {
+                        Some(decoded) => {
+                            let values: Vec<String> = decoded
+                                .arguments
+                                .iter()
+                                .map(|(name, value)| format!("{name} = {value}"))
+                                .collect();
+                            let message =
+                                format!("`{}` fails for {}", outcome.test, values.join(", "));
+                            let fix = decoded.call_arguments.as_deref().and_then(|arguments| {
+                                let (name, edits) =
+                                    regression_test(&source, contract, function, arguments)?;
+                                Fix::new(format!("Add regression test `{name}`"), edits).to_data()
+                            });
+                            (message, fix)
+                        }
+                        None => {
+                            let args = call.args.as_deref().unwrap_or_default();
+                            let message =
+                                format!("`{}` fails with the arguments {args}", outcome.test);
+                            (message, None)
+                        }
+                    }
+                }
+                _ => (sequence_message(outcome), None),
+            };
+            let Some(range) = forge_test::node_range(source_bytes, function) else {
+                continue;
+            };
+            diagnostics.push(Diagnostic {
+                range,
+                severity: Some(DiagnosticSeverity::INFORMATION),
+                code: Some(NumberOrString::String(COUNTEREXAMPLE_CODE.to_string())),
+                source: Some("forge test".to_string()),
+                message,
+                data: fix,
+                ..Diagnostic::default()
+            });
+        }
+    }
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::forge_test::CounterexampleCall;
+    use serde_json::json;
+    use tower_lsp::lsp_types::Position;
+
+    fn word(value: u64) -> String {
+        format!("{value:064x}")
+    }
+
+    #[test]
+    fn test_decode_calldata() {
+        let abi = json!([
+            { "type": "function", "name": "setUp", "inputs": [] },
+            {
+                "type": "function",
+                "name": "testFuzz_withdraw",
+                "inputs": [
+                    { "name": "amount", "type": "uint256", "internalType": "uint256" },
+                    { "name": "delta", "type": "int128", "internalType": "int128" },
+                    { "name": "to", "type": "address", "internalType": "address payable" },
+                    { "name": "memo", "type": "string", "internalType": "string" },
+                    { "name": "ids", "type": "uint8[]", "internalType": "uint8[]" },
+                    { "name": "tag", "type": "bytes4", "internalType": "bytes4" }
+                ]
+            }
+        ]);
+        let selector = hex(&keccak256(
+            b"testFuzz_withdraw(uint256,int128,address,string,uint8[],bytes4)",
+        )[..4]);
+        let calldata = [
+            format!("0x{selector}"),
+            word(2),
+            "f".repeat(63) + "b",
+            format!("{:0>64}", "5615deb798bb3e4dfa0139dfa1b3d433cc23b72f"),
+            word(6 * 32),
+            word(8 * 32),
+            format!("{:0<64}", "deadbeef"),
+            // memo
+            word(4),
+            format!("{:0<64}", hex(b"a\"b\n")),
+            // ids
+            word(2),
+            word(1),
+            word(7),
+        ]
+        .concat();
+
+        let decoded = decode_calldata(&abi, &calldata).unwrap();
+        assert_eq!(decoded.function, "testFuzz_withdraw");
+        let arguments: Vec<String> = decoded
+            .arguments
+            .iter()
+            .map(|(name, value)| format!("{name} = {value}"))
+            .collect();
+        assert_eq!(
+            arguments,
+            [
+                "amount = 2",
+                "delta = -5",
+                "to = 0x5615dEB798BB3E4dFa0139dFa1b3D433Cc23b72f",
+                "memo = \"a\\\"b\\n\"",
+                "ids = [1, 7]",
+                "tag = bytes4(0xdeadbeef)",
+            ]
+        );
+        // Arrays aren't single literals
+        assert_eq!(decoded.call_arguments, None);
+
+        assert_eq!(decode_calldata(&abi, "0x12345678"), None);
+        assert_eq!(decimal(&[0xff; 32]).len(), 78);
+    }
+
+    const SOURCE: &str = "\
+contract VaultTest {
+    function testFuzz_withdraw(uint256 amount, address to) public {
+        vault.withdraw(amount, to);
+    }
+}
+";
+
+    #[test]
+    fn test_counterexample_diagnostics() {
+        let path = "/project/test/Vault.t.sol";
+        let uri = Url::from_file_path(path).unwrap();
+        let start = SOURCE.find("function").unwrap();
+        let end = SOURCE.find("    }\n}").unwrap() + 5;
+        let statement = SOURCE.find("vault.").unwrap();
+        let name_start = SOURCE.find("testFuzz_withdraw").unwrap();
+        let contract = json!({
+            "nodeType": "ContractDefinition",
+            "contractKind": "contract",
+            "abstract": false,
+            "name": "VaultTest",
+            "nodes": [{
+                "nodeType": "FunctionDefinition",
+                "kind": "function",
+                "name": "testFuzz_withdraw",
+                "visibility": "public",
+                "src": format!("{start}:{}:0", end - start),
+                "nameLocation": format!("{name_start}:17:0"),
+                "body": { "statements": [{ "src": format!("{statement}:26:0") }] }
+            }]
+        });
+        let ast_data = ast::mock_ast(
+            path,
+            json!({
+                "nodeType": "SourceUnit", "absolutePath": path, "nodes": [contract]
+            }),
+        );
+        let abi = json!([{
+            "type": "function",
+            "name": "testFuzz_withdraw",
+            "inputs": [
+                { "name": "amount", "type": "uint256", "internalType": "uint256" },
+                { "name": "to", "type": "address", "internalType": "contract IReceiver" }
+            ]
+        }]);
+        let selector = hex(&keccak256(b"testFuzz_withdraw(uint256,address)")[..4]);
+        let outcome = |calls: Vec<CounterexampleCall>| TestOutcome {
+            contract: "VaultTest".to_string(),
+            test: "testFuzz_withdraw".to_string(),
+            passed: false,
+            reason: None,
+            counterexample: Some("3, 0x0000000000000000000000000000000000000001".to_string()),
+            counterexample_calls: calls,
+            failing_call: None,
+        };
+        let call = CounterexampleCall {
+            sender: None,
+            address: None,
+            contract: None,
+            signature: None,
+            args: Some("3, 0x0000000000000000000000000000000000000001".to_string()),
+            calldata: Some(format!("0x{selector}{}{}", word(3), word(1))),
+        };
+
+        let diagnostics = counterexample_diagnostics(
+            &ast_data,
+            &uri,
+            SOURCE.as_bytes(),
+            &[outcome(vec![call.clone()])],
+            |contract| (contract == "VaultTest").then(|| abi.clone()),
+        );
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].message,
+            "`testFuzz_withdraw` fails for amount = 3, \
+             to = 0x0000000000000000000000000000000000000001"
+        );
+        assert_eq!(diagnostics[0].range.start, Position::new(1, 13));
+        let fix = Fix::from_diagnostic(&diagnostics[0]).unwrap();
+        assert_eq!(
+            fix.title,
+            "Add regression test `test_withdraw_counterexample`"
+        );
+        assert_eq!(fix.edits[0].range.start, Position::new(3, 5));
+        assert_eq!(
+            fix.edits[0].new_text,
+            "\n\n    function test_withdraw_counterexample() public {\n        \
+             testFuzz_withdraw(3, IReceiver(0x0000000000000000000000000000000000000001));\n    }"
+        );
+
+        // Without an artifact, forge's arguments
+        let diagnostics = counterexample_diagnostics(
+            &ast_data,
+            &uri,
+            SOURCE.as_bytes(),
+            &[outcome(vec![call.clone()])],
+            |_| None,
+        );
+        assert_eq!(
+            diagnostics[0].message,
+            "`testFuzz_withdraw` fails with the arguments 3, \
+             0x0000000000000000000000000000000000000001"
+        );
+        assert!(diagnostics[0].data.is_none());
+
+        let step = |signature: &str, args: &str| CounterexampleCall {
+            sender: Some("0x01".to_string()),
+            contract: Some("Vault".to_string()),
+            signature: Some(signature.to_string()),
+            args: Some(args.to_string()),
+            ..call.clone()
+        };
+        let sequence = outcome(vec![
+            step("deposit(uint256)", "5"),
+            step("withdraw(uint256)", "6"),
+        ]);
+        let diagnostics =
+            counterexample_diagnostics(&ast_data, &uri, SOURCE.as_bytes(), &[sequence], |_| None);
+        assert_eq!(
+            diagnostics[0].message,
+            "`testFuzz_withdraw` is broken by the sequence:\n\
+             1. Vault.deposit(5) from 0x01\n2. Vault.withdraw(6) from 0x01"
+        );
+    }
+}
@@ -0,0 +1,436 @@
+//! "Create deploy script" refactoring: writes `script/Deploy<Contract>.s.sol`, a Foundry
+//! script deploying the contract under the cursor.
+//!
+//! The script declares one placeholder per constructor parameter, typed as the parameter
+//! is, and deploys between `vm.startBroadcast()` and `vm.stopBroadcast()`. A comment above
+//! the script lists the `forge script` command for each RPC endpoint of `foundry.toml`.
+
+use serde_json::Value;
+use std::{collections::BTreeSet, path::PathBuf};
+use tower_lsp::lsp_types::{
+    CodeAction, CodeActionKind, CodeActionOrCommand, CreateFile, CreateFileOptions,
+    DocumentChangeOperation, DocumentChanges, OneOf, OptionalVersionedTextDocumentIdentifier,
+    Position, Range, ResourceOp, TextDocumentEdit, TextEdit, Url, WorkspaceEdit,
+};
+
+use crate::{
+    ast::{self, children, location_bounds, name, node_type, span_bounds},
+    goto::pos_to_bytes,
+    project::{ProjectConfig, relative_path},
+};
+
+const DEFAULT_LICENSE: &str = "// SPDX-License-Identifier: UNLICENSED";
+
+const DEFAULT_PRAGMA: &str = "pragma solidity ^0.8.13;";
+
+fn text<'a>(source: &'a str, node: &Value) -> &'a str {
+    span_bounds(node)
+        .and_then(|(start, end)| source.get(start..end))
+        .unwrap_or_default()
+}
+
+/// The deployable contract whose header, from `contract` through its name, contains
+/// `offset`.
+fn contract_at(unit: &Value, offset: usize) -> Option<&Value> {
+    children(unit, "nodes").find(|node| {
+        node_type(node) == Some("ContractDefinition")
+            && node.get("contractKind").and_then(Value::as_str) == Some("contract")
+            && node.get("abstract").and_then(Value::as_bool) != Some(true)
+            && span_bounds(node)
+                .zip(location_bounds(node, "nameLocation"))
+                .is_some_and(|((start, _), (_, name_end))| (start..=name_end).contains(&offset))
+    })
+}
+
+/// The type of `parameter` as the script spells it: types declared in `contract` are
+/// qualified with its name. Also collects the names the script has to import for it.
+fn script_type(
+    source: &str,
+    contract: &Value,
+    parameter: &Value,
+    imports: &mut BTreeSet<String>,
+) -> String {
+    let Some(type_name) = parameter.get("typeName") else {
+        return String::new();
+    };
+    let Some((start, end)) = span_bounds(type_name) else {
+        return String::new();
+    };
+    let members: Vec<u64> = children(contract, "nodes")
+        .filter_map(|node| node.get("id").and_then(Value::as_u64))
+        .collect();
+
+    let mut user_types = Vec::new();
+    ast::walk(type_name, &mut |node| {
+        if node_type(node) == Some("UserDefinedTypeName")
+            && let Some(span) = span_bounds(node)
+        {
+            user_types.push((span, node));
+        }
+    });
+    user_types.sort_by_key(|(span, _)| *span);
+
+    let mut spelled = String::new();
+    let mut cursor = start;
+    for ((type_start, type_end), node) in user_types {
+        if type_start < cursor {
+            continue;
+        }
+        let Some(written) = source.get(type_start..type_end) else {
+            continue;
+        };
+        spelled.push_str(source.get(cursor..type_start).unwrap_or_default());
+        let declared_in_contract = node
+            .get("referencedDeclaration")
+            .and_then(Value::as_u64)
+            .is_some_and(|id| members.contains(&id));
+        if declared_in_contract {
+            spelled.push_str(&format!("{}.{written}", name(contract)));
+        } else {
+            spelled.push_str(written);
+            let imported = written.split('.').next().unwrap_or(written);
+            if imported != name(contract) {
+                imports.insert(imported.to_string());
+            }
+        }
+        cursor = type_end;
+    }
+    spelled.push_str(source.get(cursor..end).unwrap_or_default());
+    spelled
+}
+
+/// Initial value of a placeholder of elementary type `ty`; other types are left zeroed.
+pub fn placeholder(ty: &str) -> Option<String> {
+    let value = match ty {
+        "address" => "address(0)".to_string(),
+        "address payable" => "payable(address(0))".to_string(),
+        "bool" => "false".to_string(),
+        "string" | "bytes" => "\"\"".to_string(),
+        _ if ty.starts_with("bytes") => format!("{ty}(0)"),
+        _ if ty.starts_with("uint") || ty.starts_with("int") => "0".to_string(),
+        _ => return None,
+    };
+    Some(value)
+}
+
+/// `name` with a lowercase first letter.
+fn variable_name(name: &str) -> String {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .map(|first| first.to_lowercase().chain(chars).collect())
+        .unwrap_or_default()
+}
+
+/// Where to write the deploy script of `contract`, and the path to run it with, relative
+/// to the project root.
+fn script_path(config: &ProjectConfig, contract: &str) -> (PathBuf, String) {
+    let dir = config.script.trim_end_matches('/');
+    let relative = format!("{dir}/Deploy{contract}.s.sol");
+    (config.root.join(&relative), relative)
+}
+
+/// The comment block with the `forge script` command for each RPC endpoint.
+fn rpc_comment(config: &ProjectConfig, relative_script: &str) -> String {
+    let command =
+        |rpc: &str| format!("//   forge script {relative_script} --rpc-url {rpc} --broadcast\n");
+    if config.rpc_endpoints.is_empty() {
+        return format!(
+            "// Deploy with:\n{}// Endpoints added under [rpc_endpoints] in foundry.toml can be passed by name.\n",
+            command("<rpc-url>")
+        );
+    }
+    let mut comment = "// Deploy to one of the [rpc_endpoints] of foundry.toml:\n".to_string();
+    for endpoint in &config.rpc_endpoints {
+        comment.push_str(&command(endpoint));
+    }
+    comment
+}
+
+/// Text of the deploy script of `contract`, declared in `unit` with text `source`.
+fn script(
+    source: &str,
+    unit: &Value,
+    contract: &Value,
+    import_path: &str,
+    rpc_comment: &str,
+) -> String {
+    let contract_name = name(contract);
+    let license = source
+        .lines()
+        .find(|line| line.contains("SPDX-License-Identifier:"))
+        .map_or(DEFAULT_LICENSE, str::trim);
+    let pragma = children(unit, "nodes")
+        .find(|node| {
+            node_type(node) == Some("PragmaDirective")
+                && node
+                    .get("literals")
+                    .and_then(|literals| literals.get(0))
+                    .and_then(Value::as_str)
+                    == Some("solidity")
+        })
+        .map(|node| text(source, node))
+        .filter(|pragma| !pragma.is_empty())
+        .map_or(DEFAULT_PRAGMA.to_string(), |pragma| {
+            format!("{};", pragma.trim_end_matches(';'))
+        });
+
+    let parameters: Vec<&Value> = children(contract, "nodes")
+        .find(|node| {
+            node_type(node) == Some("FunctionDefinition")
+                && node.get("kind").and_then(Value::as_str) == Some("constructor")
+        })
+        .map(|constructor| {
+            constructor
+                .get("parameters")
+                .map(|list| children(list, "parameters").collect())
+                .unwrap_or_default()
+        })
+        .unwrap_or_default();
+
+    let mut imports = BTreeSet::new();
+    let mut names = Vec::new();
+    let mut declarations = String::new();
+    for (index, parameter) in parameters.iter().enumerate() {
+        let ty = script_type(source, contract, parameter, &mut imports);
+        let variable = match name(parameter) {
+            "" => format!("arg{index}"),
+            name => name.to_string(),
+        };
+        let location = match parameter.get("storageLocation").and_then(Value::as_str) {
+            Some("memory" | "calldata" | "storage") => " memory",
+            _ => "",
+        };
+        let elementary = parameter
+            .get("typeName")
+            .is_some_and(|type_name| node_type(type_name) == Some("ElementaryTypeName"));
+        let initializer = elementary
+            .then(|| placeholder(&ty))
+            .flatten()
+            .map(|value| format!(" = {value}"))
+            .unwrap_or_default();
+        declarations.push_str(&format!(
+            "        {ty}{location} {variable}{initializer};\n"
+        ));
+        names.push(variable);
+    }
+
+    let mut deployed = variable_name(contract_name);
+    if names.contains(&deployed) {
+        deployed = "deployed".to_string();
+    }
+    let mut imported = vec![contract_name.to_string()];
+    imported.extend(imports);
+
+    let mut script = format!(
+        "{license}\n{pragma}\n\nimport {{Script}} from \"forge-std/Script.sol\";\nimport {{{}}} from \"{import_path}\";\n\n{rpc_comment}contract Deploy{contract_name} is Script {{\n    function run() public returns ({contract_name} {deployed}) {{\n",
+        imported.join(", ")
+    );
+    if !declarations.is_empty() {
+        script.push_str("        // Constructor arguments\n");
+        script.push_str(&declarations);
+        script.push('\n');
+    }
+    script.push_str(&format!(
+        "        vm.startBroadcast();\n        {deployed} = new {contract_name}({});\n        vm.stopBroadcast();\n    }}\n}}\n",
+        names.join(", ")
+    ));
+    script
+}
+
+/// The "Create deploy script" action for the contract whose header is at `position` in
+/// `uri`, unless its deploy script already exists.
+pub fn deploy_script_action(
+    ast_data: &Value,
+    uri: &Url,
+    position: Position,
+    source_bytes: &[u8],
+    config: &ProjectConfig,
+) -> Option<CodeActionOrCommand> {
+    let path = uri.to_file_path().ok()?;
+    let unit = ast::source_unit(ast_data, uri)?;
+    let contract = contract_at(unit, pos_to_bytes(source_bytes, position))?;
+    let (script_file, relative_script) = script_path(config, name(contract));
+    if script_file.exists() {
+        return None;
+    }
+    let import_path = relative_path(script_file.parent()?, &path)?;
+    let source = String::from_utf8_lossy(source_bytes);
+    let text = script(
+        &source,
+        unit,
+        contract,
+        &import_path,
+        &rpc_comment(config, &relative_script),
+    );
+
+    let script_uri = Url::from_file_path(&script_file).ok()?;
+    let operations = vec![
+        DocumentChangeOperation::Op(ResourceOp::Create(CreateFile {
+            uri: script_uri.clone(),
+            options: Some(CreateFileOptions {
+                overwrite: Some(false),
+                ignore_if_exists: Some(true),
+            }),
+            annotation_id: None,
+        })),
+        DocumentChangeOperation::Edit(TextDocumentEdit {
+            text_document: OptionalVersionedTextDocumentIdentifier {
+                uri: script_uri,
+                version: None,
+            },
+            edits: vec![OneOf::Left(TextEdit {
+                range: Range::default(),
+                new_text: text,
+            })],
+        }),
+    ];
+    Some(CodeActionOrCommand::CodeAction(CodeAction {
+        title: format!("Create deploy script {relative_script}"),
+        kind: Some(CodeActionKind::REFACTOR),
+        edit: Some(WorkspaceEdit {
+            document_changes: Some(DocumentChanges::Operations(operations)),
+            ..WorkspaceEdit::default()
+        }),
+        ..CodeAction::default()
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax;
+
+    const VAULT: &str = "\
+// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.20;
+
+import {IERC20} from \"./IERC20.sol\";
+
+contract Vault {
+    struct Limits {
+        uint256 cap;
+    }
+
+    constructor(IERC20 token, address owner, Limits memory limits, string memory tag, uint8) {}
+}
+";
+
+    fn project(foundry_toml: &str) -> (tempfile::TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("foundry.toml"), foundry_toml).unwrap();
+        std::fs::create_dir_all(dir.path().join("src")).unwrap();
+        let path = dir.path().join("src/Vault.sol");
+        std::fs::write(&path, VAULT).unwrap();
+        (dir, path)
+    }
+
+    fn created_script(action: CodeActionOrCommand) -> (Url, String) {
+        let CodeActionOrCommand::CodeAction(action) = action else {
+            panic!("expected a code action");
+        };
+        let Some(DocumentChanges::Operations(operations)) = action.edit.unwrap().document_changes
+        else {
+            panic!("expected document operations");
+        };
+        let [
+            DocumentChangeOperation::Op(ResourceOp::Create(create)),
+            DocumentChangeOperation::Edit(edit),
+        ] = operations.as_slice()
+        else {
+            panic!("expected a created file and its text");
+        };
+        let OneOf::Left(text) = &edit.edits[0] else {
+            panic!("expected a plain text edit");
+        };
+        (create.uri.clone(), text.new_text.clone())
+    }
+
+    #[test]
+    fn test_deploy_script_with_constructor_placeholders() {
+        let (dir, path) = project(
+            "[profile.default]\n\n[rpc_endpoints]\nsepolia = \"${SEPOLIA_RPC_URL}\"\nmainnet = \"${MAINNET_RPC_URL}\"\n",
+        );
+        let config = ProjectConfig::load(dir.path());
+        let uri = Url::from_file_path(&path).unwrap();
+        let tree = syntax::parse(path.to_str().unwrap(), VAULT);
+
+        let action =
+            deploy_script_action(&tree, &uri, Position::new(5, 11), VAULT.as_bytes(), &config)
+                .unwrap();
+        let (script_uri, text) = created_script(action);
+        assert_eq!(
+            script_uri,
+            Url::from_file_path(dir.path().join("script/DeployVault.s.sol")).unwrap()
+        );
+        assert_eq!(
+            text,
+            "\
+// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.20;
+
+import {Script} from \"forge-std/Script.sol\";
+import {Vault, IERC20} from \"../src/Vault.sol\";
+
+// Deploy to one of the [rpc_endpoints] of foundry.toml:
+//   forge script script/DeployVault.s.sol --rpc-url mainnet --broadcast
+//   forge script script/DeployVault.s.sol --rpc-url sepolia --broadcast
+contract DeployVault is Script {
+    function run() public returns (Vault vault) {
+        // Constructor arguments
+        IERC20 token;
+        address owner = address(0);
+        Vault.Limits memory limits;
+        string memory tag = \"\";
+        uint8 arg4 = 0;
+
+        vm.startBroadcast();
+        vault = new Vault(token, owner, limits, tag, arg4);
+        vm.stopBroadcast();
+    }
+}
+"
+        );
+
+        // Only offered on the contract header
+        assert!(
+            deploy_script_action(
+                &tree,
+                &uri,
+                Position::new(11, 20),
+                VAULT.as_bytes(),
+                &config
+            )
+            .is_none()
+        );
+    }
+
+    #[test]
+    fn test_deploy_script_in_configured_dir_unless_it_exists() {
+        let (dir, path) = project("[profile.default]\nscript = \"scripts\"\n");
+        let config = ProjectConfig::load(dir.path());
+        let uri = Url::from_file_path(&path).unwrap();
+        let source = "pragma solidity ^0.8.0;\ncontract Vault {}\n";
+        let tree = syntax::parse(path.to_str().unwrap(), source);
+
+        let action =
+            deploy_script_action(&tree, &uri, Position::new(1, 0), source.as_bytes(), &config)
+                .unwrap();
+        let (script_uri, text) = created_script(action);
+        assert!(script_uri.path().ends_with("/scripts/DeployVault.s.sol"));
+        assert!(
+            text.starts_with("// SPDX-License-Identifier: UNLICENSED\npragma solidity ^0.8.0;\n")
+        );
+        assert!(text.contains(
+            "// Deploy with:\n//   forge script scripts/DeployVault.s.sol --rpc-url <rpc-url> --broadcast\n"
+        ));
+        assert!(text.contains("        vm.startBroadcast();\n        vault = new Vault();\n"));
+
+        std::fs::create_dir_all(dir.path().join("scripts")).unwrap();
+        std::fs::write(dir.path().join("scripts/DeployVault.s.sol"), "").unwrap();
+        assert!(
+            deploy_script_action(&tree, &uri, Position::new(1, 0), source.as_bytes(), &config)
+                .is_none()
+        );
+    }
+}
@@ -0,0 +1,154 @@
+//! Documentation preview through `forge doc`.
+//!
+//! `forge doc` writes one markdown page per contract, interface, library and free item to
+//! `<out>/src/<source path>/<kind>.<name>.md`. The preview command generates the pages into
+//! a temporary directory and returns those of the current contract, so the project's own
+//! `docs/` is left alone.
+
+use serde::Serialize;
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+/// Generates the documentation of the file URI given as the first argument, optionally
+/// narrowed down to the contract at the position given as the second.
+pub const PREVIEW_DOCS_COMMAND: &str = "forge-lsp.previewDocs";
+
+const CONTRACT_KINDS: [&str; 3] = ["contract", "interface", "library"];
+
+/// A generated documentation page.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocPage {
+    /// Name of the documented item.
+    pub name: String,
+    /// `contract`, `interface`, `library`, `function`, `struct`, ...
+    pub kind: String,
+    pub markdown: String,
+}
+
+/// Directory `forge doc --out <out>` writes the pages of the source at `relative` to.
+pub fn pages_dir(out: &Path, relative: &Path) -> PathBuf {
+    out.join("src").join(relative)
+}
+
+/// The pages generated in `dir`, sorted by name.
+pub fn read_pages(dir: &Path) -> io::Result<Vec<DocPage>> {
+    let mut pages = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let Some(stem) = path
+            .extension()
+            .is_some_and(|ext| ext == "md")
+            .then(|| path.file_stem().and_then(|stem| stem.to_str()))
+            .flatten()
+        else {
+            continue;
+        };
+        let Some((kind, name)) = stem.split_once('.') else {
+            continue;
+        };
+        pages.push(DocPage {
+            name: name.to_string(),
+            kind: kind.to_string(),
+            markdown: std::fs::read_to_string(&path)?,
+        });
+    }
+    pages.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(pages)
+}
+
+/// Name of the contract, interface or library whose declaration is the last one starting
+/// before `offset` in `source`.
+pub fn contract_at(source: &str, offset: usize) -> Option<&str> {
+    let mut start = 0;
+    let mut current = None;
+    for line in source.split_inclusive('\n') {
+        if start > offset {
+            break;
+        }
+        let header = line.trim_start();
+        let header = header.strip_prefix("abstract ").unwrap_or(header);
+        let name = CONTRACT_KINDS.iter().find_map(|kind| {
+            let rest = header.strip_prefix(kind)?;
+            let rest = rest.strip_prefix(char::is_whitespace)?.trim_start();
+            let end = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '$'))
+                .unwrap_or(rest.len());
+            (end > 0).then(|| &rest[..end])
+        });
+        if name.is_some() {
+            current = name;
+        }
+        start += line.len();
+    }
+    current
+}
+
+/// The pages of the contract called `contract`, or every page when there is none.
+pub fn select_pages(pages: Vec<DocPage>, contract: Option<&str>) -> Vec<DocPage> {
+    let Some(contract) = contract else {
+        return pages;
+    };
+    if !pages.iter().any(|page| page.name == contract) {
+        return pages;
+    }
+    pages
+        .into_iter()
+        .filter(|page| page.name == contract)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "\
+// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.0;
+
+interface IVault {
+    function deposit() external;
+}
+
+abstract contract Vault is IVault {
+    function deposit() external {}
+}
+";
+
+    #[test]
+    fn test_contract_at_cursor() {
+        assert_eq!(contract_at(SOURCE, 10), None);
+        let deposit = SOURCE.find("deposit").unwrap();
+        assert_eq!(contract_at(SOURCE, deposit), Some("IVault"));
+        let body = SOURCE.rfind("deposit").unwrap();
+        assert_eq!(contract_at(SOURCE, body), Some("Vault"));
+    }
+
+    #[test]
+    fn test_read_and_select_pages() {
+        let out = tempfile::tempdir().unwrap();
+        let dir = pages_dir(out.path(), Path::new("src/Vault.sol"));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("contract.Vault.md"), "# Vault\n").unwrap();
+        std::fs::write(dir.join("interface.IVault.md"), "# IVault\n").unwrap();
+        std::fs::write(dir.join("README.txt"), "not a page").unwrap();
+
+        let pages = read_pages(&dir).unwrap();
+        let names: Vec<_> = pages.iter().map(|page| page.name.as_str()).collect();
+        assert_eq!(names, ["IVault", "Vault"]);
+
+        let vault = select_pages(pages.clone(), Some("Vault"));
+        assert_eq!(
+            vault,
+            [DocPage {
+                name: "Vault".to_string(),
+                kind: "contract".to_string(),
+                markdown: "# Vault\n".to_string(),
+            }]
+        );
+        assert_eq!(select_pages(pages.clone(), Some("Missing")), pages);
+        assert_eq!(select_pages(pages.clone(), None), pages);
+    }
+}
@@ -0,0 +1,275 @@
+//! In-memory contents of the documents open in the editor.
+//!
+//! With incremental sync the client only sends the edited ranges, so the server keeps
+//! each open document and applies the edits itself. Handlers read sources through
+//! [`DocumentStore::read`] so unsaved edits are seen; files that are not open are read
+//! from disk. Code that reads other files without awaiting, like the locations of
+//! references, reads them from a [`Snapshot`] the same way.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+use tokio::sync::RwLock;
+use tower_lsp::lsp_types::{Position, TextDocumentContentChangeEvent, Url};
+
+/// Byte offsets of line starts, to convert LSP positions (UTF-16 columns) to byte offsets.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineIndex {
+    line_starts: Vec<usize>,
+}
+
+impl LineIndex {
+    pub fn new(text: &str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self { line_starts }
+    }
+
+    /// Byte offset of `position` in `text`. Positions past the end of a line clamp to the
+    /// line end, and positions past the last line clamp to the end of the text.
+    pub fn offset(&self, text: &str, position: Position) -> usize {
+        let Some(&line_start) = self.line_starts.get(position.line as usize) else {
+            return text.len();
+        };
+        let line_end = self
+            .line_starts
+            .get(position.line as usize + 1)
+            .map_or(text.len(), |next| next - 1);
+        let line = text[line_start..line_end]
+            .strip_suffix('\r')
+            .unwrap_or(&text[line_start..line_end]);
+
+        let mut utf16_column = 0;
+        for (i, c) in line.char_indices() {
+            if utf16_column >= position.character as usize {
+                return line_start + i;
+            }
+            utf16_column += c.len_utf16();
+        }
+        line_start + line.len()
+    }
+
+    /// Byte offset of the start of `line`.
+    pub fn line_start(&self, line: usize) -> Option<usize> {
+        self.line_starts.get(line).copied()
+    }
+
+    /// Position of the byte `offset` of `text`, with its column in UTF-16 code units.
+    pub fn position(&self, text: &str, offset: usize) -> Position {
+        let offset = offset.min(text.len());
+        let line = self.line_starts.partition_point(|start| *start <= offset) - 1;
+        let line_start = self.line_starts[line];
+        let column = text
+            .get(line_start..offset)
+            .map_or(offset - line_start, |before| before.encode_utf16().count());
+        Position::new(line as u32, column as u32)
+    }
+}
+
+/// An open document and the version the client last reported for it.
+#[derive(Debug, Clone)]
+pub struct Document {
+    pub text: String,
+    pub version: i32,
+    line_index: LineIndex,
+}
+
+impl Document {
+    pub fn new(text: String, version: i32) -> Self {
+        let line_index = LineIndex::new(&text);
+        Self {
+            text,
+            version,
+            line_index,
+        }
+    }
+
+    /// Apply one content change: a replacement of `range`, or of the whole text when the
+    /// change has no range.
+    pub fn apply_change(&mut self, change: TextDocumentContentChangeEvent) {
+        match change.range {
+            Some(range) => {
+                let start = self.line_index.offset(&self.text, range.start);
+                let end = self.line_index.offset(&self.text, range.end).max(start);
+                self.text.replace_range(start..end, &change.text);
+            }
+            None => self.text = change.text,
+        }
+        self.line_index = LineIndex::new(&self.text);
+    }
+}
+
+/// Open documents by URI.
+#[derive(Debug, Default)]
+pub struct DocumentStore {
+    documents: RwLock<HashMap<Url, Document>>,
+}
+
+impl DocumentStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn open(&self, uri: Url, text: String, version: i32) {
+        self.documents
+            .write()
+            .await
+            .insert(uri, Document::new(text, version));
+    }
+
+    /// Apply the changes of a `didChange` notification in order and return the resulting
+    /// text. Changes to a document that was never opened are ignored.
+    pub async fn change(
+        &self,
+        uri: &Url,
+        version: i32,
+        changes: Vec<TextDocumentContentChangeEvent>,
+    ) -> Option<String> {
+        let mut documents = self.documents.write().await;
+        let document = documents.get_mut(uri)?;
+        for change in changes {
+            document.apply_change(change);
+        }
+        document.version = version;
+        Some(document.text.clone())
+    }
+
+    pub async fn close(&self, uri: &Url) {
+        self.documents.write().await.remove(uri);
+    }
+
+    /// URIs and versions of the open documents.
+    pub async fn versions(&self) -> Vec<(Url, i32)> {
+        let documents = self.documents.read().await;
+        documents
+            .iter()
+            .map(|(uri, document)| (uri.clone(), document.version))
+            .collect()
+    }
+
+    /// Text of an open document.
+    pub async fn get(&self, uri: &Url) -> Option<String> {
+        let documents = self.documents.read().await;
+        documents.get(uri).map(|document| document.text.clone())
+    }
+
+    /// Contents of `uri`: the open document if there is one, else the file on disk.
+    pub async fn read(&self, uri: &Url) -> std::io::Result<Vec<u8>> {
+        if let Some(text) = self.get(uri).await {
+            return Ok(text.into_bytes());
+        }
+        let path = uri.to_file_path().map_err(|()| {
+            std::io::Error::new(std::io::ErrorKind::InvalidInput, "invalid file URI")
+        })?;
+        tokio::fs::read(path).await
+    }
+
+    /// The texts of the open documents now, for code that reads other sources without
+    /// awaiting the store.
+    pub async fn snapshot(&self) -> Snapshot {
+        let documents = self.documents.read().await;
+        let texts = documents
+            .iter()
+            .filter_map(|(uri, document)| Some((uri.to_file_path().ok()?, document.text.clone())))
+            .collect();
+        Snapshot { texts }
+    }
+}
+
+/// Texts of the open documents at one point, by path.
+#[derive(Debug, Clone, Default)]
+pub struct Snapshot {
+    texts: HashMap<PathBuf, String>,
+}
+
+impl Snapshot {
+    /// Contents of `path`, like [`DocumentStore::read`]: the open document if there was
+    /// one, else the file on disk.
+    pub fn read(&self, path: &Path) -> Option<Vec<u8>> {
+        match self.texts.get(path) {
+            Some(text) => Some(text.clone().into_bytes()),
+            None => std::fs::read(path).ok(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower_lsp::lsp_types::Range;
+
+    fn change(start: (u32, u32), end: (u32, u32), text: &str) -> TextDocumentContentChangeEvent {
+        TextDocumentContentChangeEvent {
+            range: Some(Range::new(
+                Position::new(start.0, start.1),
+                Position::new(end.0, end.1),
+            )),
+            range_length: None,
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_line_index_utf16_columns() {
+        // `é` is two bytes, `𝔸` is four bytes and two UTF-16 code units
+        let text = "a\r\nré𝔸x\n";
+        let index = LineIndex::new(text);
+        assert_eq!(index.offset(text, Position::new(0, 1)), 1);
+        assert_eq!(index.offset(text, Position::new(1, 2)), 3 + "ré".len());
+        assert_eq!(index.offset(text, Position::new(1, 4)), 3 + "ré𝔸".len());
+        // Clamped to the line end, before `\r\n`
+        assert_eq!(index.offset(text, Position::new(0, 9)), 1);
+        assert_eq!(index.offset(text, Position::new(7, 0)), text.len());
+        assert_eq!(index.position(text, 3 + "ré𝔸".len()), Position::new(1, 4));
+        assert_eq!(index.position(text, text.len()), Position::new(2, 0));
+    }
+
+    #[tokio::test]
+    async fn test_incremental_changes() {
+        let store = DocumentStore::new();
+        let uri = Url::parse("file:///tmp/A.sol").unwrap();
+        store
+            .open(uri.clone(), "contract A {\n}\n".to_string(), 1)
+            .await;
+
+        let text = store
+            .change(
+                &uri,
+                2,
+                vec![
+                    change((0, 9), (0, 10), "Vault"),
+                    change((1, 0), (1, 0), "    uint256 x;\n"),
+                ],
+            )
+            .await;
+        assert_eq!(
+            text.as_deref(),
+            Some("contract Vault {\n    uint256 x;\n}\n")
+        );
+
+        // A change without a range replaces the document
+        store
+            .change(
+                &uri,
+                3,
+                vec![TextDocumentContentChangeEvent {
+                    range: None,
+                    range_length: None,
+                    text: "contract B {}".to_string(),
+                }],
+            )
+            .await;
+        assert_eq!(store.read(&uri).await.unwrap(), b"contract B {}");
+        assert_eq!(store.versions().await, [(uri.clone(), 3)]);
+        let snapshot = store.snapshot().await;
+        assert_eq!(
+            snapshot.read(Path::new("/tmp/A.sol")).as_deref(),
+            Some(&b"contract B {}"[..])
+        );
+
+        store.close(&uri).await;
+        assert_eq!(store.get(&uri).await, None);
+    }
+}
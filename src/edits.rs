@@ -0,0 +1,269 @@
+//! Text edits of a single document.
+//!
+//! [`EditBuilder`] collects edits as byte ranges of the document's text and converts them
+//! to LSP positions once, in [`EditBuilder::build`]. Overlapping edits are rejected instead
+//! of producing a corrupt file, edits that touch are merged into one, and repeated edits
+//! are kept once. Positions count UTF-16 columns, as clients send and read them, through
+//! the same [`LineIndex`] as the open documents.
+
+use thiserror::Error;
+use tower_lsp::lsp_types::{Position, Range, TextEdit};
+
+use crate::documents::LineIndex;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum EditError {
+    #[error("edit at {start}..{end} overlaps another edit")]
+    Overlap { start: usize, end: usize },
+    #[error("edit at {start}..{end} is outside the document")]
+    OutOfBounds { start: usize, end: usize },
+    #[error("edit at {start}..{end} splits a character")]
+    NotCharBoundary { start: usize, end: usize },
+    #[error("position {}:{} is outside the document", .0.line, .0.character)]
+    InvalidPosition(Position),
+}
+
+/// Replacement of the bytes `start..end`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Edit {
+    start: usize,
+    end: usize,
+    new_text: String,
+}
+
+/// Edits of one document, validated against its text.
+#[derive(Debug, Clone)]
+pub struct EditBuilder<'a> {
+    text: &'a str,
+    line_index: LineIndex,
+    edits: Vec<Edit>,
+}
+
+impl<'a> EditBuilder<'a> {
+    pub fn new(text: &'a str) -> Self {
+        Self {
+            text,
+            line_index: LineIndex::new(text),
+            edits: Vec::new(),
+        }
+    }
+
+    /// A builder holding `edits`, failing on the first invalid one.
+    pub fn with_edits(text: &'a str, edits: &[TextEdit]) -> Result<Self, EditError> {
+        let mut builder = Self::new(text);
+        for edit in edits {
+            builder.replace_range(edit.range, edit.new_text.clone())?;
+        }
+        Ok(builder)
+    }
+
+    /// Byte offset of the start of `line`, or the end of the text past the last line.
+    pub fn line_offset(&self, line: usize) -> usize {
+        self.line_index.line_start(line).unwrap_or(self.text.len())
+    }
+
+    /// Byte offset of `position`. A character past the end of its line is clamped to it.
+    pub fn offset(&self, position: Position) -> Result<usize, EditError> {
+        if self.line_index.line_start(position.line as usize).is_none() {
+            return Err(EditError::InvalidPosition(position));
+        }
+        Ok(self.line_index.offset(self.text, position))
+    }
+
+    /// Position of the byte `offset`.
+    pub fn position(&self, offset: usize) -> Position {
+        self.line_index.position(self.text, offset)
+    }
+
+    /// Replace the bytes `start..end` with `new_text`.
+    pub fn replace(
+        &mut self,
+        start: usize,
+        end: usize,
+        new_text: impl Into<String>,
+    ) -> Result<&mut Self, EditError> {
+        if start > end || end > self.text.len() {
+            return Err(EditError::OutOfBounds { start, end });
+        }
+        if !self.text.is_char_boundary(start) || !self.text.is_char_boundary(end) {
+            return Err(EditError::NotCharBoundary { start, end });
+        }
+        let edit = Edit {
+            start,
+            end,
+            new_text: new_text.into(),
+        };
+        if self.edits.contains(&edit) {
+            return Ok(self);
+        }
+        if self
+            .edits
+            .iter()
+            .any(|other| other.start < end && start < other.end)
+        {
+            return Err(EditError::Overlap { start, end });
+        }
+        self.edits.push(edit);
+        Ok(self)
+    }
+
+    /// Insert `new_text` at the byte `offset`.
+    pub fn insert(
+        &mut self,
+        offset: usize,
+        new_text: impl Into<String>,
+    ) -> Result<&mut Self, EditError> {
+        self.replace(offset, offset, new_text)
+    }
+
+    /// Replace the text within the LSP `range`.
+    pub fn replace_range(
+        &mut self,
+        range: Range,
+        new_text: impl Into<String>,
+    ) -> Result<&mut Self, EditError> {
+        let (start, end) = (self.offset(range.start)?, self.offset(range.end)?);
+        self.replace(start, end, new_text)
+    }
+
+    /// The edits in document order, with edits that touch merged in the order they were
+    /// added.
+    fn merged(mut self) -> (Self, Vec<Edit>) {
+        let mut edits = std::mem::take(&mut self.edits);
+        // Stable, so insertions at the same offset keep their order
+        edits.sort_by_key(|edit| (edit.start, edit.end));
+        let mut merged: Vec<Edit> = Vec::with_capacity(edits.len());
+        for edit in edits {
+            match merged.last_mut() {
+                Some(last) if last.end == edit.start => {
+                    last.end = edit.end;
+                    last.new_text.push_str(&edit.new_text);
+                }
+                _ => merged.push(edit),
+            }
+        }
+        (self, merged)
+    }
+
+    /// The edits as LSP text edits, in document order.
+    pub fn build(self) -> Vec<TextEdit> {
+        let (builder, edits) = self.merged();
+        edits
+            .into_iter()
+            .map(|edit| TextEdit {
+                range: Range::new(builder.position(edit.start), builder.position(edit.end)),
+                new_text: edit.new_text,
+            })
+            .collect()
+    }
+
+    /// The text with the edits applied.
+    pub fn apply(self) -> String {
+        let (builder, edits) = self.merged();
+        let mut text = builder.text.to_string();
+        for edit in edits.iter().rev() {
+            text.replace_range(edit.start..edit.end, &edit.new_text);
+        }
+        text
+    }
+}
+
+/// Apply the LSP `edits` to `text`.
+pub fn apply(text: &str, edits: &[TextEdit]) -> Result<String, EditError> {
+    Ok(EditBuilder::with_edits(text, edits)?.apply())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "contract A {\r\n    uint x;\n}";
+
+    #[test]
+    fn test_positions_convert_once() {
+        let builder = EditBuilder::new(TEXT);
+        assert_eq!(builder.position(0), Position::new(0, 0));
+        assert_eq!(builder.position(18), Position::new(1, 4));
+        assert_eq!(builder.position(TEXT.len()), Position::new(2, 1));
+        // Past the end of a line is clamped before its line break
+        assert_eq!(builder.offset(Position::new(0, 40)), Ok(12));
+        assert_eq!(builder.line_offset(2), 26);
+        assert_eq!(builder.line_offset(3), TEXT.len());
+        assert_eq!(
+            builder.offset(Position::new(3, 0)),
+            Err(EditError::InvalidPosition(Position::new(3, 0)))
+        );
+    }
+
+    #[test]
+    fn test_overlapping_edits_are_rejected() {
+        let mut builder = EditBuilder::new(TEXT);
+        builder.replace(18, 22, "uint256").unwrap();
+        assert_eq!(
+            builder.replace(20, 24, "y").unwrap_err(),
+            EditError::Overlap { start: 20, end: 24 }
+        );
+        // The same edit twice is kept once
+        builder.replace(18, 22, "uint256").unwrap();
+        assert_eq!(
+            builder.replace(0, TEXT.len() + 1, "").unwrap_err(),
+            EditError::OutOfBounds {
+                start: 0,
+                end: TEXT.len() + 1
+            }
+        );
+        assert_eq!(builder.build().len(), 1);
+
+        let mut builder = EditBuilder::new("é");
+        assert_eq!(
+            builder.insert(1, "x").unwrap_err(),
+            EditError::NotCharBoundary { start: 1, end: 1 }
+        );
+    }
+
+    #[test]
+    fn test_utf16_columns() {
+        // `é` is two bytes and one UTF-16 code unit, `𝔸` four bytes and two
+        let text = "string s = \"é𝔸\"; uint x;";
+        let mut builder = EditBuilder::new(text);
+        let x = text.find('x').unwrap();
+        assert_eq!(builder.position(x), Position::new(0, 23));
+        assert_eq!(builder.offset(Position::new(0, 23)), Ok(x));
+        builder.replace(x, x + 1, "y").unwrap();
+        let edits = builder.build();
+        assert_eq!(
+            edits[0].range,
+            Range::new(Position::new(0, 23), Position::new(0, 24))
+        );
+        assert_eq!(apply(text, &edits).unwrap(), "string s = \"é𝔸\"; uint y;");
+    }
+
+    #[test]
+    fn test_touching_edits_merge() {
+        let mut builder = EditBuilder::new(TEXT);
+        builder
+            .replace(24, 25, " = 1;")
+            .unwrap()
+            .replace(22, 24, " y")
+            .unwrap()
+            .insert(18, "/// x\n    ")
+            .unwrap();
+        let text = builder.clone().apply();
+        let edits = builder.build();
+        assert_eq!(
+            edits,
+            [
+                TextEdit {
+                    range: Range::new(Position::new(1, 4), Position::new(1, 4)),
+                    new_text: "/// x\n    ".to_string(),
+                },
+                TextEdit {
+                    range: Range::new(Position::new(1, 8), Position::new(1, 11)),
+                    new_text: " y = 1;".to_string(),
+                },
+            ]
+        );
+        assert_eq!(text, "contract A {\r\n    /// x\n    uint y = 1;\n}");
+        assert_eq!(apply(TEXT, &edits), Ok(text));
+    }
+}
@@ -0,0 +1,210 @@
+//! Errors of requests failing for a reason the client can tell its user.
+//!
+//! Handlers used to log these failures and answer `null`, which a client can't tell apart
+//! from there being nothing at the position. Each is now an error response with a code of
+//! its own, from the range JSON-RPC leaves to servers, and its details as `data`, tagged by
+//! `kind`:
+//!
+//! | Code     | `kind`              | `data`                          |
+//! |----------|---------------------|---------------------------------|
+//! | `-32001` | `forgeNotFound`     |                                 |
+//! | `-32002` | `compilationFailed` | `uri`, `errors`                 |
+//! | `-32003` | `outsideWorkspace`  | `uri`                           |
+//! | `-32004` | `invalidPosition`   | `uri`, `position`, `lineCount`  |
+//!
+//! Other failures, like a workspace that isn't trusted, are still logged.
+
+use serde::Serialize;
+use serde_json::Value;
+use thiserror::Error;
+use tower_lsp::{
+    jsonrpc::{self, ErrorCode},
+    lsp_types::{Position, Url},
+};
+
+use crate::{ast, runner::RunnerError};
+
+/// Code of [`RequestError::ForgeNotFound`].
+pub const FORGE_NOT_FOUND: i64 = -32001;
+/// Code of [`RequestError::CompilationFailed`].
+pub const COMPILATION_FAILED: i64 = -32002;
+/// Code of [`RequestError::OutsideWorkspace`].
+pub const OUTSIDE_WORKSPACE: i64 = -32003;
+/// Code of [`RequestError::InvalidPosition`].
+pub const INVALID_POSITION: i64 = -32004;
+
+/// Compiler errors listed in the message; `data` has them all.
+const LISTED_ERRORS: usize = 3;
+
+fn listed(errors: &[String]) -> String {
+    let mut listed = errors[..errors.len().min(LISTED_ERRORS)].join("; ");
+    if errors.len() > LISTED_ERRORS {
+        listed.push_str(&format!(" and {} more", errors.len() - LISTED_ERRORS));
+    }
+    listed
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(
+    tag = "kind",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum RequestError {
+    #[error("forge was not found, install Foundry to compile the project")]
+    ForgeNotFound,
+    #[error("Failed to compile {uri}: {}", listed(errors))]
+    CompilationFailed {
+        uri: Url,
+        /// Messages of the compiler errors.
+        errors: Vec<String>,
+    },
+    #[error("{uri} is outside of the workspace")]
+    OutsideWorkspace { uri: Url },
+    #[error("Line {} is past the end of {uri}, which has {line_count} lines", position.line)]
+    InvalidPosition {
+        uri: Url,
+        position: Position,
+        line_count: u32,
+    },
+}
+
+impl RequestError {
+    pub fn code(&self) -> i64 {
+        match self {
+            Self::ForgeNotFound => FORGE_NOT_FOUND,
+            Self::CompilationFailed { .. } => COMPILATION_FAILED,
+            Self::OutsideWorkspace { .. } => OUTSIDE_WORKSPACE,
+            Self::InvalidPosition { .. } => INVALID_POSITION,
+        }
+    }
+
+    /// The error of a forge run for `uri` failing with `error`, when the client can act on
+    /// it.
+    pub fn from_runner(uri: &Url, error: &RunnerError) -> Option<Self> {
+        match error {
+            RunnerError::Shared(error) => Self::from_runner(uri, error),
+            RunnerError::CommandError(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                Some(Self::ForgeNotFound)
+            }
+            RunnerError::CommandFailed(message) => Some(Self::CompilationFailed {
+                uri: uri.clone(),
+                errors: vec![message.clone()],
+            }),
+            RunnerError::JsonError(_) | RunnerError::EmptyOutput => Some(Self::CompilationFailed {
+                uri: uri.clone(),
+                errors: vec![error.to_string()],
+            }),
+            _ => None,
+        }
+    }
+
+    /// The error of a build output `ast_data` that has no AST of `uri` because compiling it
+    /// failed.
+    pub fn from_build(ast_data: &Value, uri: &Url) -> Option<Self> {
+        if ast::source_unit(ast_data, uri).is_some() {
+            return None;
+        }
+        let errors: Vec<String> = ast_data
+            .get("errors")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter(|error| error.get("severity").and_then(Value::as_str) == Some("error"))
+            .filter_map(|error| {
+                let message = error.get("message").and_then(Value::as_str)?;
+                Some(message.to_string())
+            })
+            .collect();
+        (!errors.is_empty()).then(|| Self::CompilationFailed {
+            uri: uri.clone(),
+            errors,
+        })
+    }
+
+    /// Checks that `position` is within `source_bytes`, the text of `uri`. A character past
+    /// the end of its line is valid, the protocol takes it as the end of the line.
+    pub fn check_position(uri: &Url, source_bytes: &[u8], position: Position) -> Result<(), Self> {
+        let line_count = source_bytes.iter().filter(|&&b| b == b'\n').count() as u32 + 1;
+        if position.line < line_count {
+            return Ok(());
+        }
+        Err(Self::InvalidPosition {
+            uri: uri.clone(),
+            position,
+            line_count,
+        })
+    }
+}
+
+impl From<RequestError> for jsonrpc::Error {
+    fn from(error: RequestError) -> Self {
+        Self {
+            code: ErrorCode::ServerError(error.code()),
+            message: error.to_string().into(),
+            data: serde_json::to_value(&error).ok(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_request_errors() {
+        let uri = Url::parse("file:///project/src/Vault.sol").unwrap();
+
+        let not_found = RunnerError::CommandError(std::io::ErrorKind::NotFound.into());
+        let shared = RunnerError::Shared(Arc::new(not_found));
+        assert_eq!(
+            RequestError::from_runner(&uri, &shared),
+            Some(RequestError::ForgeNotFound)
+        );
+        assert_eq!(
+            RequestError::from_runner(&uri, &RunnerError::Untrusted),
+            None
+        );
+
+        let output = json!({
+            "errors": [
+                { "severity": "warning", "message": "Unused local variable." },
+                { "severity": "error", "message": "Expected ';' but got '}'" }
+            ]
+        });
+        let failed = RequestError::from_build(&output, &uri).unwrap();
+        assert_eq!(
+            failed.to_string(),
+            "Failed to compile file:///project/src/Vault.sol: Expected ';' but got '}'"
+        );
+        let error = jsonrpc::Error::from(failed);
+        assert_eq!(error.code, ErrorCode::ServerError(COMPILATION_FAILED));
+        assert_eq!(
+            error.data,
+            Some(json!({
+                "kind": "compilationFailed",
+                "uri": "file:///project/src/Vault.sol",
+                "errors": ["Expected ';' but got '}'"]
+            }))
+        );
+
+        let source = b"contract Vault {\n}\n";
+        assert!(RequestError::check_position(&uri, source, Position::new(2, 0)).is_ok());
+        let invalid = RequestError::check_position(&uri, source, Position::new(3, 0)).unwrap_err();
+        assert_eq!(
+            jsonrpc::Error::from(invalid).data,
+            Some(json!({
+                "kind": "invalidPosition",
+                "uri": "file:///project/src/Vault.sol",
+                "position": { "line": 3, "character": 0 },
+                "lineCount": 3
+            }))
+        );
+        assert_eq!(
+            jsonrpc::Error::from(RequestError::ForgeNotFound).data,
+            Some(json!({ "kind": "forgeNotFound" }))
+        );
+    }
+}
@@ -0,0 +1,265 @@
+//! Export of the events of contracts for off-chain indexers.
+//!
+//! `forge-lsp.exportEvents` reads the ABI of each selected contract from its artifact in
+//! `out/` and describes every event the way a subgraph or indexer consumes it: the
+//! canonical signature and its topic hash, and for each parameter whether it is indexed,
+//! the topic it fills, and whether the topic holds the value or only its hash.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use tower_lsp::lsp_types::Url;
+
+use crate::selectors::keccak256;
+
+/// Exports the events of contracts, given as `{"uri": ..., "contracts": [...], "output": ...}`.
+pub const EXPORT_EVENTS_COMMAND: &str = "forge-lsp.exportEvents";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportEventsParams {
+    /// File declaring the contracts.
+    pub uri: Url,
+    /// Names of the contracts to export, every contract of the file when empty.
+    #[serde(default)]
+    pub contracts: Vec<String>,
+    /// File the schema is written to, relative to the project root, when given.
+    #[serde(default)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EventSchema {
+    pub contracts: Vec<ContractEvents>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContractEvents {
+    pub name: String,
+    /// Source of the contract, relative to the project root.
+    pub source: String,
+    pub events: Vec<Event>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Event {
+    pub name: String,
+    /// Canonical signature, `Transfer(address,address,uint256)`.
+    pub signature: String,
+    /// Keccak-256 of the signature, the first topic of the event's logs. Anonymous events
+    /// have none.
+    pub topic0: Option<String>,
+    pub anonymous: bool,
+    pub inputs: Vec<EventInput>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EventInput {
+    pub name: String,
+    /// Canonical ABI type, tuples written out as their components.
+    #[serde(rename = "type")]
+    pub abi_type: String,
+    /// Solidity type, such as `struct Vault.Position`, when the ABI records it.
+    pub internal_type: Option<String>,
+    pub indexed: bool,
+    /// Index of the topic holding an indexed parameter.
+    pub topic: Option<usize>,
+    /// Whether the topic holds the hash of the value rather than the value, as it does for
+    /// indexed strings, bytes, arrays and tuples.
+    pub hashed: bool,
+}
+
+/// Path of the artifact of `contract`, declared in the file at `path`, in the artifacts
+/// directory `out`.
+pub fn artifact_path(out: &Path, path: &Path, contract: &str) -> PathBuf {
+    out.join(path.file_name().unwrap_or_default())
+        .join(format!("{contract}.json"))
+}
+
+/// The canonical type of the ABI parameter `param`.
+pub(crate) fn canonical_type(param: &Value) -> Option<String> {
+    let abi_type = param.get("type")?.as_str()?;
+    let Some(suffix) = abi_type.strip_prefix("tuple") else {
+        return Some(abi_type.to_string());
+    };
+    let components = param
+        .get("components")?
+        .as_array()?
+        .iter()
+        .map(canonical_type)
+        .collect::<Option<Vec<_>>>()?;
+    Some(format!("({}){suffix}", components.join(",")))
+}
+
+/// Whether a value of the canonical type `abi_type` is hashed when indexed.
+fn hashed_when_indexed(abi_type: &str) -> bool {
+    abi_type == "string"
+        || abi_type == "bytes"
+        || abi_type.ends_with(']')
+        || abi_type.ends_with(')')
+}
+
+/// The events of the contract ABI `abi`.
+pub fn abi_events(abi: &Value) -> Vec<Event> {
+    let entries = abi.as_array().into_iter().flatten();
+    entries
+        .filter(|entry| entry["type"] == "event")
+        .filter_map(|entry| {
+            let name = entry.get("name")?.as_str()?.to_string();
+            let anonymous = entry["anonymous"].as_bool().unwrap_or(false);
+            // Anonymous events use every topic for indexed parameters
+            let mut next_topic = usize::from(!anonymous);
+            let mut types = Vec::new();
+            let mut inputs = Vec::new();
+            for input in entry.get("inputs")?.as_array()? {
+                let abi_type = canonical_type(input)?;
+                let indexed = input["indexed"].as_bool().unwrap_or(false);
+                let topic = indexed.then_some(next_topic);
+                next_topic += usize::from(indexed);
+                inputs.push(EventInput {
+                    name: input["name"].as_str().unwrap_or_default().to_string(),
+                    internal_type: input["internalType"].as_str().map(str::to_string),
+                    indexed,
+                    topic,
+                    hashed: indexed && hashed_when_indexed(&abi_type),
+                    abi_type: abi_type.clone(),
+                });
+                types.push(abi_type);
+            }
+            let signature = format!("{name}({})", types.join(","));
+            let topic0 = (!anonymous).then(|| {
+                let hex: String = keccak256(signature.as_bytes())
+                    .iter()
+                    .map(|b| format!("{b:02x}"))
+                    .collect();
+                format!("0x{hex}")
+            });
+            Some(Event {
+                name,
+                signature,
+                topic0,
+                anonymous,
+                inputs,
+            })
+        })
+        .collect()
+}
+
+/// The events of `contract`, declared in the file at `path`, from its artifact in `out`.
+pub fn contract_events(
+    root: &Path,
+    out: &Path,
+    path: &Path,
+    contract: &str,
+) -> Result<ContractEvents, String> {
+    let artifact = artifact_path(out, path, contract);
+    let text = std::fs::read_to_string(&artifact).map_err(|e| {
+        format!(
+            "Failed to read {}, run `forge build` first: {e}",
+            artifact.display()
+        )
+    })?;
+    let artifact: Value =
+        serde_json::from_str(&text).map_err(|e| format!("Invalid artifact of {contract}: {e}"))?;
+    Ok(ContractEvents {
+        name: contract.to_string(),
+        source: path
+            .strip_prefix(root)
+            .unwrap_or(path)
+            .to_string_lossy()
+            .replace('\\', "/"),
+        events: abi_events(&artifact["abi"]),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_abi_events() {
+        let abi = json!([
+            { "type": "function", "name": "deposit", "inputs": [] },
+            {
+                "type": "event",
+                "name": "Transfer",
+                "anonymous": false,
+                "inputs": [
+                    { "name": "from", "type": "address", "internalType": "address", "indexed": true },
+                    { "name": "to", "type": "address", "internalType": "address", "indexed": true },
+                    { "name": "value", "type": "uint256", "internalType": "uint256", "indexed": false }
+                ]
+            },
+            {
+                "type": "event",
+                "name": "Opened",
+                "anonymous": true,
+                "inputs": [
+                    {
+                        "name": "position",
+                        "type": "tuple[]",
+                        "internalType": "struct Vault.Position[]",
+                        "indexed": true,
+                        "components": [
+                            { "name": "owner", "type": "address" },
+                            { "name": "shares", "type": "uint128" }
+                        ]
+                    },
+                    { "name": "memo", "type": "string", "indexed": true }
+                ]
+            }
+        ]);
+
+        let events = abi_events(&abi);
+        assert_eq!(events.len(), 2);
+
+        let transfer = &events[0];
+        assert_eq!(transfer.signature, "Transfer(address,address,uint256)");
+        assert_eq!(
+            transfer.topic0.as_deref(),
+            Some("0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef")
+        );
+        let topics: Vec<_> = transfer.inputs.iter().map(|input| input.topic).collect();
+        assert_eq!(topics, [Some(1), Some(2), None]);
+        assert!(transfer.inputs.iter().all(|input| !input.hashed));
+
+        let opened = &events[1];
+        assert_eq!(opened.signature, "Opened((address,uint128)[],string)");
+        assert_eq!(opened.topic0, None);
+        let topics: Vec<_> = opened
+            .inputs
+            .iter()
+            .map(|input| (input.topic, input.hashed))
+            .collect();
+        assert_eq!(topics, [(Some(0), true), (Some(1), true)]);
+        assert_eq!(
+            opened.inputs[0].internal_type.as_deref(),
+            Some("struct Vault.Position[]")
+        );
+    }
+
+    #[test]
+    fn test_contract_events() {
+        let root = tempfile::tempdir().unwrap();
+        let out = root.path().join("out");
+        let path = root.path().join("src/Vault.sol");
+        let artifact = artifact_path(&out, &path, "Vault");
+        assert_eq!(artifact, out.join("Vault.sol/Vault.json"));
+        std::fs::create_dir_all(artifact.parent().unwrap()).unwrap();
+        std::fs::write(
+            &artifact,
+            json!({ "abi": [{ "type": "event", "name": "Paused", "inputs": [] }] }).to_string(),
+        )
+        .unwrap();
+
+        let events = contract_events(root.path(), &out, &path, "Vault").unwrap();
+        assert_eq!(events.source, "src/Vault.sol");
+        assert_eq!(events.events[0].signature, "Paused()");
+        assert!(contract_events(root.path(), &out, &path, "Pool").is_err());
+    }
+}
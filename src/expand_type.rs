@@ -0,0 +1,336 @@
+//! `forge-lsp/expandType`: the full definition of the struct or enum under the cursor,
+//! so clients can show it inline without navigating to it.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use tower_lsp::lsp_types::{Location, Position, Range, Url};
+
+use crate::{
+    ast::{self, parse_src},
+    goto::bytes_to_pos,
+    paths,
+};
+
+/// Name of the custom request.
+pub const EXPAND_TYPE_METHOD: &str = "forge-lsp/expandType";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TypeKind {
+    Struct,
+    Enum,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExpandedType {
+    /// Qualified name, e.g. `Vault.Position`.
+    pub name: String,
+    pub kind: TypeKind,
+    /// Definition text with one field or variant per line.
+    pub text: String,
+    /// Where the type is defined, if the defining file could be read.
+    pub location: Option<Location>,
+}
+
+/// Follow a type name node to the struct or enum definition it names. Arrays and
+/// mappings resolve to their element and value types.
+fn type_definition<'a>(index: &HashMap<u64, &'a Value>, type_name: &'a Value) -> Option<&'a Value> {
+    match type_name.get("nodeType")?.as_str()? {
+        "UserDefinedTypeName" => {
+            let id = type_name
+                .get("referencedDeclaration")
+                .or_else(|| type_name.get("pathNode")?.get("referencedDeclaration"))?
+                .as_u64()?;
+            definition(index, index.get(&id)?)
+        }
+        "ArrayTypeName" => type_definition(index, type_name.get("baseType")?),
+        "Mapping" => type_definition(index, type_name.get("valueType")?),
+        _ => None,
+    }
+}
+
+/// Resolve a declaration to a struct or enum definition: either the declaration itself
+/// or the type of a variable.
+fn definition<'a>(index: &HashMap<u64, &'a Value>, declaration: &'a Value) -> Option<&'a Value> {
+    match declaration.get("nodeType")?.as_str()? {
+        "StructDefinition" | "EnumDefinition" => Some(declaration),
+        "VariableDeclaration" => type_definition(index, declaration.get("typeName")?),
+        _ => None,
+    }
+}
+
+/// Source text of `node`, with runs of whitespace collapsed to single spaces.
+fn node_text(node: &Value, source: Option<&str>) -> Option<String> {
+    let (start, length, _) = parse_src(node.get("src")?.as_str()?)?;
+    let text = source?.get(start..start + length)?;
+    Some(text.split_whitespace().collect::<Vec<_>>().join(" "))
+}
+
+fn struct_field(member: &Value, source: Option<&str>) -> Option<String> {
+    if let Some(text) = node_text(member, source) {
+        return Some(text);
+    }
+    // Without the source, rebuild the field from the type description
+    let type_string = member
+        .get("typeDescriptions")?
+        .get("typeString")?
+        .as_str()?;
+    let name = member.get("name")?.as_str()?;
+    Some(format!("{type_string} {name}"))
+}
+
+fn render(definition: &Value, kind: TypeKind, name: &str, source: Option<&str>) -> String {
+    let members = definition
+        .get("members")
+        .and_then(Value::as_array)
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+
+    let mut lines = Vec::with_capacity(members.len() + 2);
+    match kind {
+        TypeKind::Struct => {
+            lines.push(format!("struct {name} {{"));
+            lines.extend(
+                members
+                    .iter()
+                    .filter_map(|member| struct_field(member, source))
+                    .map(|field| format!("    {field};")),
+            );
+        }
+        TypeKind::Enum => {
+            lines.push(format!("enum {name} {{"));
+            let last = members.len().saturating_sub(1);
+            lines.extend(members.iter().enumerate().filter_map(|(value, member)| {
+                let variant = member.get("name")?.as_str()?;
+                let separator = if value < last { "," } else { "" };
+                Some(format!("    {variant}{separator} // {value}"))
+            }));
+        }
+    }
+    lines.push("}".to_string());
+    lines.join("\n")
+}
+
+/// Expand the struct or enum referenced at `position`.
+pub fn expand_type(
+    ast_data: &Value,
+    file_uri: &Url,
+    position: Position,
+    source_bytes: &[u8],
+) -> Option<ExpandedType> {
+    let symbol = ast::symbol_at_position(ast_data, file_uri, position, source_bytes)?;
+    let index = ast::index_nodes(ast_data.get("sources")?);
+    let definition = definition(&index, index.get(&symbol.declaration_id)?)?;
+
+    let kind = match definition.get("nodeType")?.as_str()? {
+        "StructDefinition" => TypeKind::Struct,
+        _ => TypeKind::Enum,
+    };
+    let name = definition
+        .get("canonicalName")
+        .or_else(|| definition.get("name"))?
+        .as_str()?
+        .to_string();
+
+    // Field text comes from the defining file; fall back to type descriptions without it
+    let (start, length, file_id) = parse_src(definition.get("src")?.as_str()?)?;
+    let target_path = ast::id_to_path(ast_data)
+        .and_then(|id_to_path| id_to_path.get(file_id)?.as_str())
+        .and_then(paths::resolve_source_path);
+    let target_source = target_path
+        .as_ref()
+        .and_then(|path| std::fs::read_to_string(path).ok());
+
+    let location = target_path
+        .as_deref()
+        .zip(target_source.as_deref())
+        .and_then(|(path, source)| {
+            Some(Location {
+                uri: paths::path_to_uri(path)?,
+                range: Range {
+                    start: bytes_to_pos(source.as_bytes(), start)?,
+                    end: bytes_to_pos(source.as_bytes(), start + length)?,
+                },
+            })
+        });
+
+    Some(ExpandedType {
+        text: render(definition, kind, &name, target_source.as_deref()),
+        name,
+        kind,
+        location,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{mock_span, mock_src};
+    use serde_json::json;
+
+    const SOURCE: &str = r#"contract Vault {
+    enum Status { Open, Closed }
+
+    struct Position {
+        address owner;
+        uint256   amount; // padded
+        Status status;
+    }
+
+    mapping(address => Position) positions;
+
+    function close(address who) external {
+        positions[who].status = Status.Closed;
+    }
+}
+"#;
+
+    fn mock_ast(path: &str) -> Value {
+        ast::mock_ast(
+            path,
+            json!({
+                "id": 1,
+                "src": format!("0:{}:0", SOURCE.len()),
+                "nodeType": "SourceUnit",
+                "absolutePath": path,
+                "nodes": [{
+                    "id": 2,
+                    "src": mock_span(SOURCE, "enum Status", "}"),
+                    "nodeType": "EnumDefinition",
+                    "name": "Status",
+                    "canonicalName": "Vault.Status",
+                    "nameLocation": mock_src(SOURCE, "Status {", 6),
+                    "members": [{
+                        "id": 3,
+                        "src": mock_src(SOURCE, "Open", 4),
+                        "nodeType": "EnumValue",
+                        "name": "Open"
+                    }, {
+                        "id": 4,
+                        "src": mock_src(SOURCE, "Closed", 6),
+                        "nodeType": "EnumValue",
+                        "name": "Closed"
+                    }]
+                }, {
+                    "id": 5,
+                    "src": mock_span(SOURCE, "struct Position", "}"),
+                    "nodeType": "StructDefinition",
+                    "name": "Position",
+                    "canonicalName": "Vault.Position",
+                    "nameLocation": mock_src(SOURCE, "Position {", 8),
+                    "members": [{
+                        "id": 6,
+                        "src": mock_src(SOURCE, "address owner", 13),
+                        "nodeType": "VariableDeclaration",
+                        "name": "owner",
+                        "typeDescriptions": { "typeString": "address" }
+                    }, {
+                        "id": 7,
+                        "src": mock_src(SOURCE, "uint256   amount", 16),
+                        "nodeType": "VariableDeclaration",
+                        "name": "amount",
+                        "typeDescriptions": { "typeString": "uint256" }
+                    }, {
+                        "id": 8,
+                        "src": mock_src(SOURCE, "Status status", 13),
+                        "nodeType": "VariableDeclaration",
+                        "name": "status",
+                        "typeDescriptions": { "typeString": "enum Vault.Status" }
+                    }]
+                }, {
+                    "id": 9,
+                    "src": mock_span(SOURCE, "mapping(address", "positions;"),
+                    "nodeType": "VariableDeclaration",
+                    "name": "positions",
+                    "nameLocation": mock_src(SOURCE, "positions;", 9),
+                    "typeName": {
+                        "id": 10,
+                        "src": mock_span(SOURCE, "mapping(address", "Position)"),
+                        "nodeType": "Mapping",
+                        "valueType": {
+                            "id": 11,
+                            "src": mock_src(SOURCE, "Position)", 8),
+                            "nodeType": "UserDefinedTypeName",
+                            "pathNode": {
+                                "id": 12,
+                                "src": mock_src(SOURCE, "Position)", 8),
+                                "nodeType": "IdentifierPath",
+                                "name": "Position",
+                                "referencedDeclaration": 5
+                            },
+                            "referencedDeclaration": 5
+                        }
+                    }
+                }, {
+                    "id": 13,
+                    "src": mock_src(SOURCE, "positions[who]", 9),
+                    "nodeType": "Identifier",
+                    "referencedDeclaration": 9
+                }, {
+                    "id": 14,
+                    "src": mock_src(SOURCE, "Status.Closed", 6),
+                    "nodeType": "Identifier",
+                    "referencedDeclaration": 2
+                }]
+            }),
+        )
+    }
+
+    fn position_of(needle: &str) -> Position {
+        bytes_to_pos(SOURCE.as_bytes(), SOURCE.find(needle).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_expand_struct_through_mapping_variable() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("Vault.sol");
+        std::fs::write(&path, SOURCE).unwrap();
+        let ast = mock_ast(path.to_str().unwrap());
+        let uri = Url::from_file_path(&path).unwrap();
+
+        let expanded = expand_type(&ast, &uri, position_of("positions[who]"), SOURCE.as_bytes())
+            .expect("struct should expand");
+        assert_eq!(expanded.name, "Vault.Position");
+        assert_eq!(expanded.kind, TypeKind::Struct);
+        assert_eq!(
+            expanded.text,
+            "struct Vault.Position {\n    address owner;\n    uint256 amount;\n    Status status;\n}"
+        );
+        let location = expanded.location.unwrap();
+        assert_eq!(location.uri, uri);
+        assert_eq!(location.range.start, position_of("struct Position"));
+    }
+
+    #[test]
+    fn test_expand_enum_with_values() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("Vault.sol");
+        std::fs::write(&path, SOURCE).unwrap();
+        let ast = mock_ast(path.to_str().unwrap());
+        let uri = Url::from_file_path(&path).unwrap();
+
+        let expanded =
+            expand_type(&ast, &uri, position_of("Status.Closed"), SOURCE.as_bytes()).unwrap();
+        assert_eq!(expanded.kind, TypeKind::Enum);
+        assert_eq!(
+            expanded.text,
+            "enum Vault.Status {\n    Open, // 0\n    Closed // 1\n}"
+        );
+
+        // Not on a type
+        assert!(expand_type(&ast, &uri, position_of("function"), SOURCE.as_bytes()).is_none());
+    }
+
+    #[test]
+    fn test_expand_struct_without_source_uses_type_descriptions() {
+        let ast = mock_ast("/nonexistent/Vault.sol");
+        let index = ast::index_nodes(&ast["sources"]);
+        let text = render(index[&5], TypeKind::Struct, "Vault.Position", None);
+        assert_eq!(
+            text,
+            "struct Vault.Position {\n    address owner;\n    uint256 amount;\n    enum Vault.Status status;\n}"
+        );
+    }
+}
@@ -0,0 +1,538 @@
+//! Extracting statements into an internal function: a refactoring replacing the statements
+//! a selection covers with a call to a new internal function of the same contract, after
+//! the function they come from.
+//!
+//! Locals of the enclosing function the statements read become parameters. Locals they
+//! declare or assign that are read afterwards come back as return values, and those they
+//! assign are passed in too, since they may keep their value; inside a loop, afterwards
+//! includes the next iteration, and the enclosing function's return variables are always
+//! read afterwards. Statements returning, breaking
+//! out of a loop they don't hold, holding a modifier's `_` or inline assembly, whose
+//! references the AST doesn't resolve, can't be extracted.
+
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use tower_lsp::lsp_types::{
+    CodeAction, CodeActionKind, CodeActionOrCommand, Range, Url, WorkspaceEdit,
+};
+
+use crate::{
+    ast::{self, name, node_type, span_bounds},
+    edits::EditBuilder,
+    goto::pos_to_bytes,
+};
+
+/// Name of the new function, numbered when the contract already has it.
+const FUNCTION_NAME: &str = "_extracted";
+
+fn id(node: &Value) -> Option<u64> {
+    node.get("id").and_then(Value::as_u64)
+}
+
+/// The innermost node of one of `kinds` whose span contains `start..end`.
+fn innermost<'a>(root: &'a Value, kinds: &[&str], start: usize, end: usize) -> Option<&'a Value> {
+    let mut found: Option<(&Value, usize)> = None;
+    ast::walk(root, &mut |node| {
+        if node_type(node).is_some_and(|kind| kinds.contains(&kind))
+            && let Some((node_start, node_end)) = span_bounds(node)
+            && node_start <= start
+            && end <= node_end
+            && found.is_none_or(|(_, length)| node_end - node_start < length)
+        {
+            found = Some((node, node_end - node_start));
+        }
+    });
+    found.map(|(node, _)| node)
+}
+
+/// The end of `statement`, past the `;` the AST may leave out of its span.
+fn statement_end(source: &str, statement: &Value) -> Option<usize> {
+    let (_, end) = span_bounds(statement)?;
+    let rest = source.get(end..)?;
+    let trimmed = rest.trim_start();
+    Some(match trimmed.strip_prefix(';') {
+        Some(_) => end + (rest.len() - trimmed.len()) + 1,
+        None => end,
+    })
+}
+
+/// The statements of one block `start..end` covers, each whole, with the byte range from
+/// the first to the end of the last.
+fn selected_statements<'a>(
+    function: &'a Value,
+    source: &str,
+    start: usize,
+    end: usize,
+) -> Option<(Vec<&'a Value>, usize, usize)> {
+    // Whitespace around the statements doesn't count
+    let selected = source.get(start..end)?;
+    let start = start + (selected.len() - selected.trim_start().len());
+    let end = start + selected.trim().len();
+    if start >= end {
+        return None;
+    }
+    let block = innermost(function, &["Block", "UncheckedBlock"], start, end)?;
+    let mut statements = Vec::new();
+    for statement in block.get("statements")?.as_array()? {
+        let (statement_start, _) = span_bounds(statement)?;
+        let statement_end = statement_end(source, statement)?;
+        if statement_end <= start || end <= statement_start {
+            continue;
+        }
+        if statement_start < start || end < statement_end {
+            return None;
+        }
+        statements.push(statement);
+    }
+    let first = span_bounds(statements.first()?)?.0;
+    let last = statement_end(source, statements.last()?)?;
+    Some((statements, first, last))
+}
+
+/// Whether control leaves `statements` other than by falling through their end.
+fn escapes(statements: &[&Value]) -> bool {
+    let mut escapes = false;
+    for statement in statements {
+        // Loops the selection holds catch their own `break` and `continue`
+        let loops: Vec<(usize, usize)> = {
+            let mut loops = Vec::new();
+            ast::walk(statement, &mut |node| {
+                if matches!(
+                    node_type(node),
+                    Some("ForStatement" | "WhileStatement" | "DoWhileStatement")
+                ) {
+                    loops.extend(span_bounds(node));
+                }
+            });
+            loops
+        };
+        ast::walk(statement, &mut |node| match node_type(node) {
+            Some("Return" | "PlaceholderStatement" | "InlineAssembly") => escapes = true,
+            Some("Break" | "Continue") => {
+                let inside_loop = span_bounds(node).is_some_and(|(start, end)| {
+                    loops
+                        .iter()
+                        .any(|&(loop_start, loop_end)| loop_start <= start && end <= loop_end)
+                });
+                escapes |= !inside_loop;
+            }
+            _ => {}
+        });
+    }
+    escapes
+}
+
+/// The locals `statements` assign, and the ids of the identifiers they only write to: the
+/// targets of `=` and `delete`.
+fn assignments(statements: &[&Value]) -> (HashSet<u64>, HashSet<u64>) {
+    let mut assigned = HashSet::new();
+    let mut only_written = HashSet::new();
+    let mut target = |node: &Value, read: bool| {
+        let targets = match node_type(node) {
+            Some("TupleExpression") => node
+                .get("components")
+                .and_then(Value::as_array)
+                .map(|components| components.iter().collect())
+                .unwrap_or_default(),
+            _ => vec![node],
+        };
+        for target in targets {
+            if node_type(target) == Some("Identifier") {
+                assigned.extend(target.get("referencedDeclaration").and_then(Value::as_u64));
+                if !read {
+                    only_written.extend(id(target));
+                }
+            }
+        }
+    };
+    for statement in statements {
+        ast::walk(statement, &mut |node| match node_type(node) {
+            Some("Assignment") => {
+                if let Some(left) = node.get("leftHandSide") {
+                    target(left, node["operator"] != "=");
+                }
+            }
+            Some("UnaryOperation")
+                if matches!(node["operator"].as_str(), Some("++" | "--" | "delete")) =>
+            {
+                if let Some(operand) = node.get("subExpression") {
+                    target(operand, node["operator"] != "delete");
+                }
+            }
+            _ => {}
+        });
+    }
+    (assigned, only_written)
+}
+
+/// `uint256[] memory shares` for the local `declaration`, or without the name.
+fn declaration_text(declaration: &Value, source: &str, named: bool) -> Option<String> {
+    let (start, end) = span_bounds(declaration.get("typeName")?)?;
+    let mut text = source.get(start..end)?.to_string();
+    if let Some(location @ ("memory" | "storage" | "calldata")) =
+        declaration.get("storageLocation").and_then(Value::as_str)
+    {
+        text = format!("{text} {location}");
+    }
+    if named {
+        text = format!("{text} {}", name(declaration));
+    }
+    Some(text)
+}
+
+/// The leading whitespace of the line holding `offset`.
+fn indent_at(source: &str, offset: usize) -> &str {
+    let line_start = source[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let line = &source[line_start..];
+    &line[..line.len() - line.trim_start_matches([' ', '\t']).len()]
+}
+
+/// A name for the new function no declaration of `contract` has.
+fn fresh_name(contract: &Value) -> String {
+    let mut taken = HashSet::new();
+    ast::walk(contract, &mut |node| {
+        taken.insert(name(node).to_string());
+    });
+    let mut candidate = FUNCTION_NAME.to_string();
+    let mut suffix = 1;
+    while taken.contains(&candidate) {
+        suffix += 1;
+        candidate = format!("{FUNCTION_NAME}{suffix}");
+    }
+    candidate
+}
+
+/// "Extract into internal function" for the statements `range` of `uri` selects.
+pub fn extract_function_action(
+    ast_data: &Value,
+    uri: &Url,
+    range: Range,
+    source_bytes: &[u8],
+) -> Option<CodeActionOrCommand> {
+    let unit = ast::source_unit(ast_data, uri)?;
+    let source = String::from_utf8_lossy(source_bytes);
+    let start = pos_to_bytes(source_bytes, range.start);
+    let end = pos_to_bytes(source_bytes, range.end);
+    if start >= end {
+        return None;
+    }
+    let contract = innermost(unit, &["ContractDefinition"], start, end)?;
+    let function = innermost(contract, &["FunctionDefinition"], start, end)?;
+    let (function_start, function_end) = span_bounds(function)?;
+    let (statements, first, last) =
+        selected_statements(function.get("body")?, &source, start, end)?;
+    if escapes(&statements) {
+        return None;
+    }
+    let inside = |offset: usize| first <= offset && offset < last;
+
+    // Locals by id, and the identifiers referring to them inside and after the selection
+    let mut locals: HashMap<u64, &Value> = HashMap::new();
+    ast::walk(function, &mut |node| {
+        if node_type(node) == Some("VariableDeclaration")
+            && let Some(id) = id(node)
+        {
+            locals.insert(id, node);
+        }
+    });
+    let returned: HashSet<u64> = function
+        .pointer("/returnParameters/parameters")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(id)
+        .collect();
+    let enclosing_loop = innermost(
+        function,
+        &["ForStatement", "WhileStatement", "DoWhileStatement"],
+        first,
+        last,
+    )
+    .and_then(span_bounds);
+    // Locals the selection uses, with their first use
+    let mut used: Vec<(usize, u64)> = Vec::new();
+    let mut read_after: HashSet<u64> = returned.clone();
+    ast::walk(function, &mut |node| {
+        if node_type(node) != Some("Identifier") {
+            return;
+        }
+        let (Some(declaration), Some((offset, _))) = (
+            node.get("referencedDeclaration").and_then(Value::as_u64),
+            span_bounds(node),
+        ) else {
+            return;
+        };
+        if !locals.contains_key(&declaration) {
+            return;
+        }
+        if inside(offset) {
+            match used.iter_mut().find(|(_, used)| *used == declaration) {
+                Some((first_use, _)) => *first_use = (*first_use).min(offset),
+                None => used.push((offset, declaration)),
+            }
+        } else if offset >= last
+            || enclosing_loop
+                .is_some_and(|(loop_start, loop_end)| loop_start <= offset && offset < loop_end)
+        {
+            read_after.insert(declaration);
+        }
+    });
+    used.sort();
+    let used: Vec<u64> = used
+        .into_iter()
+        .map(|(_, declaration)| declaration)
+        .collect();
+    let (assigned, written) = assignments(&statements);
+    let mut read: HashSet<u64> = HashSet::new();
+    for statement in &statements {
+        ast::walk(statement, &mut |node| {
+            if node_type(node) == Some("Identifier")
+                && !id(node).is_some_and(|id| written.contains(&id))
+            {
+                read.extend(node.get("referencedDeclaration").and_then(Value::as_u64));
+            }
+        });
+    }
+
+    let declared_inside = |declaration: u64| {
+        locals
+            .get(&declaration)
+            .and_then(|d| span_bounds(d))
+            .is_some_and(|(s, _)| inside(s))
+    };
+    let declaration = |id: &u64| locals.get(id).copied();
+    let output =
+        |id: u64| read_after.contains(&id) && (declared_inside(id) || assigned.contains(&id));
+    let mut outputs: Vec<&Value> = used
+        .iter()
+        .filter(|&&id| output(id))
+        .filter_map(declaration)
+        .collect();
+    // Locals of the function read or returned are passed in; the others start out declared
+    let parameters: Vec<&Value> = used
+        .iter()
+        .filter(|&&id| !declared_inside(id) && (read.contains(&id) || output(id)))
+        .filter_map(declaration)
+        .collect();
+    let redeclared: Vec<&Value> = used
+        .iter()
+        .filter(|&&id| !declared_inside(id) && !read.contains(&id) && !output(id))
+        .filter_map(declaration)
+        .collect();
+    outputs.sort_by_key(|output| span_bounds(output).map(|(start, _)| start));
+
+    let name = fresh_name(contract);
+    let function_indent = indent_at(&source, function_start);
+    let statement_indent = indent_at(&source, first);
+    let level = function
+        .pointer("/body/statements/0")
+        .and_then(span_bounds)
+        .map(|(start, _)| indent_at(&source, start))
+        .and_then(|indent| indent.strip_prefix(function_indent))
+        .filter(|level| !level.is_empty())
+        .unwrap_or("    ");
+    let body_indent = format!("{function_indent}{level}");
+
+    // The new function, after the one the statements come from
+    let mut body: Vec<String> = redeclared
+        .iter()
+        .map(|local| {
+            Some(format!(
+                "{body_indent}{};",
+                declaration_text(local, &source, true)?
+            ))
+        })
+        .collect::<Option<_>>()?;
+    for line in source[first..last].lines() {
+        // Lines continuing a statement keep their indent relative to it
+        let line = line
+            .strip_prefix(statement_indent)
+            .unwrap_or(line.trim_start());
+        body.push(if line.is_empty() {
+            String::new()
+        } else {
+            format!("{body_indent}{line}")
+        });
+    }
+    let output_names: Vec<&str> = outputs.iter().map(|output| self::name(output)).collect();
+    match output_names.as_slice() {
+        [] => {}
+        [single] => body.push(format!("{body_indent}return {single};")),
+        names => body.push(format!("{body_indent}return ({});", names.join(", "))),
+    }
+    let parameter_list: Vec<String> = parameters
+        .iter()
+        .map(|parameter| declaration_text(parameter, &source, true))
+        .collect::<Option<_>>()?;
+    let mutability = match function.get("stateMutability").and_then(Value::as_str) {
+        Some(mutability @ ("view" | "pure")) => format!(" {mutability}"),
+        _ => String::new(),
+    };
+    let returns = if outputs.is_empty() {
+        String::new()
+    } else {
+        let types: Vec<String> = outputs
+            .iter()
+            .map(|output| declaration_text(output, &source, false))
+            .collect::<Option<_>>()?;
+        format!(" returns ({})", types.join(", "))
+    };
+    let signature = format!(
+        "function {name}({}) internal{mutability}{returns}",
+        parameter_list.join(", ")
+    );
+    let new_function = format!(
+        "\n\n{function_indent}{signature} {{\n{}\n{function_indent}}}",
+        body.join("\n")
+    );
+
+    // The call replacing the statements, declaring what they declared for what follows
+    let arguments: Vec<&str> = parameters
+        .iter()
+        .map(|parameter| self::name(parameter))
+        .collect();
+    let call = format!("{name}({})", arguments.join(", "));
+    let (declared, reassigned): (Vec<&Value>, Vec<&Value>) = outputs
+        .iter()
+        .partition(|output| span_bounds(output).is_some_and(|(start, _)| inside(start)));
+    let replacement = if outputs.is_empty() {
+        format!("{call};")
+    } else if reassigned.is_empty() {
+        let declarations: Vec<String> = declared
+            .iter()
+            .map(|output| declaration_text(output, &source, true))
+            .collect::<Option<_>>()?;
+        match declarations.as_slice() {
+            [single] => format!("{single} = {call};"),
+            declarations => format!("({}) = {call};", declarations.join(", ")),
+        }
+    } else {
+        // A tuple either declares or assigns, so what the statements declared comes first
+        let mut lines: Vec<String> = declared
+            .iter()
+            .map(|output| Some(format!("{};", declaration_text(output, &source, true)?)))
+            .collect::<Option<_>>()?;
+        lines.push(match output_names.as_slice() {
+            [single] => format!("{single} = {call};"),
+            names => format!("({}) = {call};", names.join(", ")),
+        });
+        lines.join(&format!("\n{statement_indent}"))
+    };
+
+    let mut builder = EditBuilder::new(&source);
+    builder.replace(first, last, replacement).ok()?;
+    builder.insert(function_end, new_function).ok()?;
+    Some(CodeActionOrCommand::CodeAction(CodeAction {
+        title: format!("Extract into internal function `{name}`"),
+        kind: Some(CodeActionKind::REFACTOR_EXTRACT),
+        edit: Some(WorkspaceEdit {
+            changes: Some(HashMap::from([(uri.clone(), builder.build())])),
+            ..WorkspaceEdit::default()
+        }),
+        ..CodeAction::default()
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax;
+    use tower_lsp::lsp_types::Position;
+
+    const SOURCE: &str = "\
+contract Vault {
+    uint256 public total;
+
+    event Deposited(uint256 net);
+
+    function deposit(uint256 amount) public returns (uint256 minted) {
+        uint256 fee = amount / 100;
+        uint256 net = amount - fee;
+        total += net;
+        minted = net * 2;
+        emit Deposited(net);
+    }
+
+    function preview(uint256[] memory amounts) public pure returns (uint256) {
+        uint256 sum;
+        for (uint256 i; i < amounts.length; i++) {
+            if (amounts[i] == 0) {
+                continue;
+            }
+            uint256 amount = amounts[i] * 2;
+            sum += amount;
+        }
+        return sum;
+    }
+}
+";
+
+    fn extract(start: (u32, u32), end: (u32, u32)) -> Option<(String, String)> {
+        let path = "/project/src/Vault.sol";
+        let uri = Url::from_file_path(path).unwrap();
+        let tree = syntax::parse(path, SOURCE);
+        let range = Range::new(Position::new(start.0, start.1), Position::new(end.0, end.1));
+        let CodeActionOrCommand::CodeAction(action) =
+            extract_function_action(&tree, &uri, range, SOURCE.as_bytes())?
+        else {
+            panic!("expected a code action");
+        };
+        let edits = &action.edit.unwrap().changes.unwrap()[&uri];
+        let text = EditBuilder::with_edits(SOURCE, edits).unwrap().apply();
+        Some((action.title, text))
+    }
+
+    #[test]
+    fn test_extract_function() {
+        // From `uint256 fee` to `minted = net * 2;`: `net` is read afterwards and `minted` is
+        // returned
+        let (title, text) = extract((6, 4), (9, 25)).unwrap();
+        assert_eq!(title, "Extract into internal function `_extracted`");
+        assert!(text.contains(
+            "
+    function deposit(uint256 amount) public returns (uint256 minted) {
+        uint256 net;
+        (minted, net) = _extracted(amount, minted);
+        emit Deposited(net);
+    }
+
+    function _extracted(uint256 amount, uint256 minted) internal returns (uint256, uint256) {
+        uint256 fee = amount / 100;
+        uint256 net = amount - fee;
+        total += net;
+        minted = net * 2;
+        return (minted, net);
+    }
+"
+        ));
+
+        // In a loop, `sum` is read by the next iteration
+        let (_, text) = extract((19, 12), (20, 26)).unwrap();
+        assert!(text.contains(
+            "
+            sum = _extracted(amounts, i, sum);
+        }
+        return sum;
+    }
+
+    function _extracted(uint256[] memory amounts, uint256 i, uint256 sum) internal pure returns (uint256) {
+        uint256 amount = amounts[i] * 2;
+        sum += amount;
+        return sum;
+    }
+"
+        ));
+    }
+
+    #[test]
+    fn test_extract_function_needs_whole_statements() {
+        // Half of `uint256 fee = amount / 100;`
+        assert!(extract((6, 4), (6, 20)).is_none());
+        // An empty selection
+        assert!(extract((6, 8), (6, 8)).is_none());
+        // `continue` leaves the selection for the loop around it
+        assert!(extract((16, 12), (18, 13)).is_none());
+        // `return sum;`
+        assert!(extract((22, 8), (22, 19)).is_none());
+    }
+}
@@ -0,0 +1,409 @@
+//! Navigation while a file neither compiles nor parses.
+//!
+//! Mid-edit, a file often has syntax the parser rejects and the project has errors forge
+//! won't build past. Rather than going dark, goto definition and the outline fall back to a
+//! scan of the tokens of the buffer for declarations, by keyword: contracts, interfaces and
+//! libraries, functions, modifiers, events, errors, structs, enums, user-defined value
+//! types and constants. Names declared in other files are looked up in the last successful
+//! build of the project. Answers match by name only, so overloads and shadowed names all
+//! come back as candidates.
+
+use serde_json::Value;
+use std::collections::HashMap;
+use tower_lsp::lsp_types::{DocumentSymbol, Location, Position, Range, SymbolKind, Url};
+
+use crate::{
+    ast::{self, parse_src},
+    goto::bytes_to_pos,
+    grammar::{Token, tokens},
+    index::ProjectIndex,
+    paths, rename, utils,
+};
+
+/// A declaration found by scanning tokens.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScannedDeclaration {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// Byte offset of the name.
+    pub name_start: usize,
+    /// Byte span of the whole declaration, to its `;` or closing brace.
+    pub start: usize,
+    pub end: usize,
+    pub children: Vec<ScannedDeclaration>,
+}
+
+/// The kind of the declaration a `keyword` opens, with whether it is named after the
+/// keyword rather than by it.
+fn declaration_kind(keyword: &str) -> Option<(SymbolKind, bool)> {
+    Some(match keyword {
+        "contract" => (SymbolKind::CLASS, true),
+        "interface" => (SymbolKind::INTERFACE, true),
+        "library" => (SymbolKind::MODULE, true),
+        "function" => (SymbolKind::FUNCTION, true),
+        "modifier" => (SymbolKind::METHOD, true),
+        "event" | "error" => (SymbolKind::EVENT, true),
+        "struct" | "enum" | "type" => (SymbolKind::STRUCT, true),
+        "constant" => (SymbolKind::CONSTANT, true),
+        "immutable" => (SymbolKind::FIELD, true),
+        "constructor" => (SymbolKind::CONSTRUCTOR, false),
+        "fallback" | "receive" => (SymbolKind::FUNCTION, false),
+        _ => return None,
+    })
+}
+
+/// Index of the `;` or body `{` ending the header of a declaration from `from`. Only the
+/// initializer of a variable has braces in parentheses, so elsewhere an unclosed
+/// parenthesis doesn't hide the body.
+fn declaration_end(tokens: &[Token], from: usize, variable: bool) -> Option<usize> {
+    let mut depth = 0usize;
+    for (i, token) in tokens.iter().enumerate().skip(from) {
+        match token {
+            Token::Punct(_, b'(') => depth += 1,
+            Token::Punct(_, b')') => depth = depth.saturating_sub(1),
+            Token::Punct(_, b';') => return Some(i),
+            Token::Punct(_, b'{') if depth == 0 || !variable => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// The declarations of `source`, nested in the contracts, structs and enums declaring them.
+pub fn scan(source: &str) -> Vec<ScannedDeclaration> {
+    let tokens = tokens(source);
+    let mut roots = Vec::new();
+    // Declarations whose body is open, with the brace depth inside it
+    let mut open: Vec<(ScannedDeclaration, usize)> = Vec::new();
+    // Declarations whose body opens at a token index
+    let mut opening: HashMap<usize, ScannedDeclaration> = HashMap::new();
+    let mut depth = 0usize;
+
+    let push = |declaration: ScannedDeclaration,
+                open: &mut Vec<(ScannedDeclaration, usize)>,
+                roots: &mut Vec<ScannedDeclaration>| match open.last_mut() {
+        Some((parent, _)) => parent.children.push(declaration),
+        None => roots.push(declaration),
+    };
+
+    for (i, token) in tokens.iter().enumerate() {
+        match *token {
+            Token::Punct(_, b'{') => {
+                depth += 1;
+                if let Some(declaration) = opening.remove(&i) {
+                    open.push((declaration, depth));
+                }
+            }
+            Token::Punct(end, b'}') => {
+                if open
+                    .last()
+                    .is_some_and(|(_, open_depth)| *open_depth == depth)
+                {
+                    let (mut declaration, _) = open.pop().unwrap();
+                    declaration.end = end + 1;
+                    push(declaration, &mut open, &mut roots);
+                }
+                depth = depth.saturating_sub(1);
+            }
+            Token::Word(start, keyword) => {
+                let Some((kind, named_after)) = declaration_kind(keyword) else {
+                    continue;
+                };
+                // Declarations don't nest in function bodies, where `function` is a type
+                if open
+                    .last()
+                    .is_some_and(|(parent, _)| parent.kind == SymbolKind::FUNCTION)
+                {
+                    continue;
+                }
+                let (name, name_start) = if named_after {
+                    let Some(Token::Word(name_start, name)) = tokens.get(i + 1) else {
+                        continue;
+                    };
+                    if keyword == "type" && !tokens.get(i + 2).is_some_and(|t| t.is_word("is")) {
+                        continue;
+                    }
+                    (name.to_string(), *name_start)
+                } else {
+                    (keyword.to_string(), start)
+                };
+                // Constants and immutables start at the keyword, the scan doesn't track types
+                let start = match (keyword, i.checked_sub(1).map(|i| tokens[i])) {
+                    ("contract", Some(previous)) if previous.is_word("abstract") => {
+                        previous.start()
+                    }
+                    _ => start,
+                };
+                let is_variable = matches!(keyword, "constant" | "immutable");
+                let Some(end) = declaration_end(&tokens, i + 1, is_variable) else {
+                    continue;
+                };
+                let mut declaration = ScannedDeclaration {
+                    name,
+                    kind,
+                    name_start,
+                    start,
+                    end: tokens[end].start() + 1,
+                    children: vec![],
+                };
+                if matches!(tokens[end], Token::Punct(_, b'{')) && !is_variable {
+                    declaration.end = source.len();
+                    opening.insert(end, declaration);
+                } else {
+                    push(declaration, &mut open, &mut roots);
+                }
+            }
+            _ => {}
+        }
+    }
+    // Bodies left open by an unfinished edit end with the file
+    while let Some((declaration, _)) = open.pop() {
+        push(declaration, &mut open, &mut roots);
+    }
+    roots
+}
+
+/// Position of `offset` in `source`, the end of the file included.
+fn position(source: &str, offset: usize) -> Position {
+    let (line, character) = utils::byte_offset_to_position(source, offset);
+    Position::new(line, character)
+}
+
+impl ScannedDeclaration {
+    fn name_range(&self, source: &str) -> Range {
+        Range::new(
+            position(source, self.name_start),
+            position(source, self.name_start + self.name.len()),
+        )
+    }
+}
+
+fn symbol(source: &str, declaration: &ScannedDeclaration) -> DocumentSymbol {
+    let range = Range::new(
+        position(source, declaration.start),
+        position(source, declaration.end),
+    );
+    let children: Vec<DocumentSymbol> = declaration
+        .children
+        .iter()
+        .map(|child| symbol(source, child))
+        .collect();
+    #[allow(deprecated)]
+    DocumentSymbol {
+        name: declaration.name.clone(),
+        detail: None,
+        kind: declaration.kind,
+        range,
+        selection_range: declaration.name_range(source),
+        children: (!children.is_empty()).then_some(children),
+        tags: None,
+        deprecated: None,
+    }
+}
+
+/// The outline of `source` from a scan of its tokens.
+pub fn document_symbols(source: &str) -> Vec<DocumentSymbol> {
+    scan(source)
+        .iter()
+        .map(|declaration| symbol(source, declaration))
+        .collect()
+}
+
+fn flatten<'a>(declarations: &'a [ScannedDeclaration], all: &mut Vec<&'a ScannedDeclaration>) {
+    for declaration in declarations {
+        all.push(declaration);
+        flatten(&declaration.children, all);
+    }
+}
+
+/// Whether `node` declares something a name can refer to from another file.
+fn is_declaration(node: &Value) -> bool {
+    match node.get("nodeType").and_then(Value::as_str) {
+        Some(
+            "ContractDefinition"
+            | "FunctionDefinition"
+            | "ModifierDefinition"
+            | "EventDefinition"
+            | "ErrorDefinition"
+            | "StructDefinition"
+            | "EnumDefinition"
+            | "UserDefinedValueTypeDefinition",
+        ) => true,
+        Some("VariableDeclaration") => node["stateVariable"] == true || node["constant"] == true,
+        _ => false,
+    }
+}
+
+/// Declarations named `name` in the files of `project` other than `uri`, from its last
+/// successful build.
+fn indexed_declarations(project: &ProjectIndex, uri: &Url, name: &str) -> Vec<Location> {
+    let file_key = paths::uri_to_key(uri);
+    let mut locations = Vec::new();
+    let sources = project.ast.get("sources").and_then(Value::as_object);
+    for contents in sources.into_iter().flat_map(|sources| sources.values()) {
+        let Some(unit) = contents
+            .get(0)
+            .and_then(|content| content.get("source_file")?.get("ast"))
+        else {
+            continue;
+        };
+        let Some(path) = unit.get("absolutePath").and_then(Value::as_str) else {
+            continue;
+        };
+        let path = project.root.join(path);
+        let Ok(file_uri) = Url::from_file_path(&path) else {
+            continue;
+        };
+        if paths::uri_to_key(&file_uri) == file_key {
+            continue;
+        }
+        let mut spans = Vec::new();
+        ast::walk(unit, &mut |node| {
+            if is_declaration(node) && node["name"] == name {
+                spans.extend(
+                    node.get("nameLocation")
+                        .or_else(|| node.get("src"))
+                        .and_then(Value::as_str)
+                        .and_then(parse_src),
+                );
+            }
+        });
+        if spans.is_empty() {
+            continue;
+        }
+        let Ok(file) = std::fs::read(&path) else {
+            continue;
+        };
+        locations.extend(spans.into_iter().filter_map(|(start, length, _)| {
+            let range = Range::new(
+                bytes_to_pos(&file, start)?,
+                bytes_to_pos(&file, start + length)?,
+            );
+            Some(Location::new(file_uri.clone(), range))
+        }));
+    }
+    locations
+}
+
+/// Candidate definitions of the name at `position` of `uri`, whose text is `source`: the
+/// declarations of that name in the buffer, then those of the other files of `project`.
+pub fn definitions(
+    uri: &Url,
+    source: &[u8],
+    position: Position,
+    project: Option<&ProjectIndex>,
+) -> Vec<Location> {
+    let Some((_, name)) = rename::identifier_at(source, position) else {
+        return vec![];
+    };
+    let text = String::from_utf8_lossy(source);
+    let scanned = scan(&text);
+    let mut declarations = Vec::new();
+    flatten(&scanned, &mut declarations);
+    let mut locations: Vec<Location> = declarations
+        .into_iter()
+        .filter(|declaration| declaration.name == name)
+        .map(|declaration| Location::new(uri.clone(), declaration.name_range(&text)))
+        .collect();
+    if let Some(project) = project {
+        locations.extend(indexed_declarations(project, uri, &name));
+    }
+    locations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Unbalanced mid-edit: `deposit` misses a parenthesis and `withdraw` its closing brace
+    static DIRTY: &str = "pragma solidity ^0.8.0;
+
+abstract contract Vault is IVault {
+    uint256 public constant FEE = 30;
+
+    event Deposited(address indexed owner, uint256 amount);
+
+    struct Position {
+        uint256 shares;
+    }
+
+    constructor() {}
+
+    function deposit(uint256 amount public {
+        emit Deposited(msg.sender, amount;
+        function (uint256) internal hook;
+    }
+
+    function withdraw() external {
+        if (true) {
+            deposit(1);
+";
+
+    fn outline(symbols: &[DocumentSymbol]) -> Vec<(String, SymbolKind, usize)> {
+        symbols
+            .iter()
+            .map(|symbol| {
+                let children = symbol.children.as_ref().map_or(0, Vec::len);
+                (symbol.name.clone(), symbol.kind, children)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_scan_dirty_buffer() {
+        let symbols = document_symbols(DIRTY);
+        assert_eq!(
+            outline(&symbols),
+            [("Vault".to_string(), SymbolKind::CLASS, 6)]
+        );
+        let vault = &symbols[0];
+        assert_eq!(vault.range.start, Position::new(2, 0));
+        assert_eq!(vault.selection_range.start, Position::new(2, 18));
+        assert_eq!(
+            outline(vault.children.as_ref().unwrap()),
+            [
+                ("FEE".to_string(), SymbolKind::CONSTANT, 0),
+                ("Deposited".to_string(), SymbolKind::EVENT, 0),
+                ("Position".to_string(), SymbolKind::STRUCT, 0),
+                ("constructor".to_string(), SymbolKind::CONSTRUCTOR, 0),
+                ("deposit".to_string(), SymbolKind::FUNCTION, 0),
+                ("withdraw".to_string(), SymbolKind::FUNCTION, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_definitions_fall_back_to_the_last_build() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_path_buf();
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        let interface =
+            "interface IVault {\n    function total() external view returns (uint256);\n}\n";
+        std::fs::write(root.join("src/IVault.sol"), interface).unwrap();
+        let vault_path = root.join("src/Vault.sol");
+        std::fs::write(&vault_path, DIRTY).unwrap();
+        let interface_path = root.join("src/IVault.sol");
+        let ast = crate::syntax::parse(interface_path.to_str().unwrap(), interface);
+        let project = ProjectIndex::new(root.clone(), ast);
+        let uri = Url::from_file_path(&vault_path).unwrap();
+
+        // `deposit(1)` in the unfinished `withdraw`
+        let call = bytes_to_pos(DIRTY.as_bytes(), DIRTY.rfind("deposit").unwrap()).unwrap();
+        let found = definitions(&uri, DIRTY.as_bytes(), call, Some(&project));
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].uri, uri);
+        assert_eq!(found[0].range.start, Position::new(13, 13));
+
+        // `IVault` is declared in a file of the last build
+        let base = bytes_to_pos(DIRTY.as_bytes(), DIRTY.find("IVault").unwrap()).unwrap();
+        let found = definitions(&uri, DIRTY.as_bytes(), base, Some(&project));
+        assert_eq!(
+            found,
+            [Location::new(
+                Url::from_file_path(&interface_path).unwrap(),
+                Range::new(Position::new(0, 10), Position::new(0, 16))
+            )]
+        );
+        assert!(definitions(&uri, DIRTY.as_bytes(), base, None).is_empty());
+    }
+}
@@ -0,0 +1,246 @@
+//! Import paths following renamed and moved files.
+//!
+//! Before the editor renames or moves Solidity files, or directories of them, it asks for
+//! the edits keeping every import of their projects pointing at the same files: imports of
+//! the moved files, and the relative imports in them. Each import keeps its style: relative
+//! imports stay relative, remapped ones keep their remapping when the new location is still
+//! under its path, and the others become relative to the project root, which forge resolves
+//! too.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::{Path, PathBuf},
+};
+use tower_lsp::lsp_types::{TextEdit, Url};
+
+use crate::{
+    annotations::project_sources,
+    build_info::find_project_root,
+    edits::EditBuilder,
+    grammar::{Token, tokens},
+    project::{ProjectConfig, normalize, relative_path},
+};
+
+/// The files moving when `from` is renamed to `to`: the Solidity files under it for a
+/// directory, with their new paths.
+pub fn moved_files(from: &Path, to: &Path) -> Vec<(PathBuf, PathBuf)> {
+    if !from.is_dir() {
+        return vec![(from.to_path_buf(), to.to_path_buf())];
+    }
+    project_sources(from)
+        .into_iter()
+        .filter_map(|file| {
+            let moved = to.join(file.strip_prefix(from).ok()?);
+            Some((file, moved))
+        })
+        .collect()
+}
+
+/// Byte ranges of the paths of the import directives of `source`, inside the quotes.
+pub fn import_paths(source: &str) -> Vec<(usize, usize)> {
+    let tokens = tokens(source);
+    let mut paths = Vec::new();
+    for (i, token) in tokens.iter().enumerate() {
+        let Token::Word(start, "import") = *token else {
+            continue;
+        };
+        // The directive's only string is its path
+        let end = tokens[i + 1..]
+            .iter()
+            .find(|token| matches!(token, Token::Punct(_, b';')))
+            .map_or(source.len(), Token::start);
+        let directive = &source[start..end];
+        let Some(open) = directive.find(['"', '\'']) else {
+            continue;
+        };
+        let quote = directive.as_bytes()[open] as char;
+        if let Some(length) = directive[open + 1..].find(quote) {
+            paths.push((start + open + 1, start + open + 1 + length));
+        }
+    }
+    paths
+}
+
+/// The path importing `target`, which moves to `new_target`, from `importer` once moved to
+/// `new_importer`, in the style of `import`, if it changes.
+fn rewrite(
+    config: &ProjectConfig,
+    (importer, new_importer): (&Path, &Path),
+    import: &str,
+    (target, new_target): (&Path, &Path),
+) -> Option<String> {
+    let rewritten = if import.starts_with("./") || import.starts_with("../") {
+        relative_path(new_importer.parent()?, new_target)?
+    } else if target == new_target {
+        return None;
+    } else {
+        let remapped = config
+            .remapping_for(importer, import)
+            .and_then(|remapping| {
+                let base = normalize(&config.root.join(&remapping.path));
+                target.strip_prefix(&base).ok()?;
+                let rest = new_target.strip_prefix(&base).ok()?.to_str()?;
+                Some(format!("{}{}", remapping.prefix, rest.replace('\\', "/")))
+            });
+        match remapped {
+            Some(remapped) => remapped,
+            None => match new_target.strip_prefix(&config.root) {
+                Ok(rest) => rest.to_str()?.replace('\\', "/"),
+                Err(_) => relative_path(new_importer.parent()?, new_target)?,
+            },
+        }
+    };
+    (rewritten != import).then_some(rewritten)
+}
+
+/// The edits to the imports of the projects of the files moving by `moves`, old path to
+/// new. `read` gives the text of a file, unsaved edits included. Edits are keyed by the
+/// files' current URIs, since the client applies them before moving anything.
+pub fn import_edits(
+    moves: &[(PathBuf, PathBuf)],
+    read: impl Fn(&Path) -> Option<String>,
+) -> HashMap<Url, Vec<TextEdit>> {
+    let moved: HashMap<&Path, &Path> = moves
+        .iter()
+        .map(|(from, to)| (from.as_path(), to.as_path()))
+        .collect();
+    let new_path = |path: &Path| moved.get(path).copied().unwrap_or(path).to_path_buf();
+    let roots: BTreeSet<PathBuf> = moves
+        .iter()
+        .filter_map(|(from, _)| find_project_root(from))
+        .collect();
+
+    let mut changes = HashMap::new();
+    for root in roots {
+        let config = ProjectConfig::load(&root);
+        for importer in project_sources(&root) {
+            let Some(source) = read(&importer) else {
+                continue;
+            };
+            let new_importer = new_path(&importer);
+            let mut edits = EditBuilder::new(&source);
+            for (start, end) in import_paths(&source) {
+                let import = &source[start..end];
+                let Some(target) = config.resolve_import(&importer, import) else {
+                    continue;
+                };
+                let new_target = new_path(&target);
+                if let Some(rewritten) = rewrite(
+                    &config,
+                    (&importer, &new_importer),
+                    import,
+                    (&target, &new_target),
+                ) {
+                    // Import paths never overlap
+                    _ = edits.replace(start, end, rewritten);
+                }
+            }
+            let edits = edits.build();
+            if !edits.is_empty()
+                && let Ok(uri) = Url::from_file_path(&importer)
+            {
+                changes.insert(uri, edits);
+            }
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tower_lsp::lsp_types::{Position, Range};
+
+    fn write(root: &Path, path: &str, contents: &str) {
+        let path = root.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    #[test]
+    fn test_import_paths() {
+        let source = r#"import "./A.sol";
+// import "./B.sol";
+import {C, D as E} from 'lib/C.sol';
+import * as F from "../F.sol";
+"#;
+        let paths: Vec<&str> = import_paths(source)
+            .into_iter()
+            .map(|(start, end)| &source[start..end])
+            .collect();
+        assert_eq!(paths, ["./A.sol", "lib/C.sol", "../F.sol"]);
+    }
+
+    #[test]
+    fn test_import_edits_follow_moved_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        write(
+            &root,
+            "foundry.toml",
+            "[profile.default]\nremappings = [\"@vault/=src/vault/\"]\n",
+        );
+        write(&root, "src/vault/Vault.sol", "import \"../Token.sol\";\n");
+        write(&root, "src/Token.sol", "");
+        write(
+            &root,
+            "src/Router.sol",
+            "import \"./vault/Vault.sol\";\nimport \"src/Token.sol\";\n",
+        );
+        write(
+            &root,
+            "test/Vault.t.sol",
+            "import {Vault} from \"@vault/Vault.sol\";\n",
+        );
+        write(
+            &root,
+            "script/Deploy.s.sol",
+            "import \"src/vault/Vault.sol\";\n",
+        );
+        let read = |path: &Path| fs::read_to_string(path).ok();
+        let uri = |path: &str| Url::from_file_path(root.join(path)).unwrap();
+        let texts = |edits: &HashMap<Url, Vec<TextEdit>>, path: &str| -> Vec<String> {
+            edits[&uri(path)]
+                .iter()
+                .map(|edit| edit.new_text.clone())
+                .collect()
+        };
+
+        // Into a subdirectory the remapping covers
+        let moves = moved_files(
+            &root.join("src/vault/Vault.sol"),
+            &root.join("src/vault/core/Vault.sol"),
+        );
+        let edits = import_edits(&moves, read);
+        assert_eq!(edits.len(), 4);
+        assert_eq!(texts(&edits, "src/vault/Vault.sol"), ["../../Token.sol"]);
+        assert_eq!(texts(&edits, "src/Router.sol"), ["./vault/core/Vault.sol"]);
+        assert_eq!(texts(&edits, "test/Vault.t.sol"), ["@vault/core/Vault.sol"]);
+        assert_eq!(
+            texts(&edits, "script/Deploy.s.sol"),
+            ["src/vault/core/Vault.sol"]
+        );
+        assert_eq!(
+            edits[&uri("test/Vault.t.sol")][0].range,
+            Range::new(Position::new(0, 21), Position::new(0, 37))
+        );
+
+        // A directory, out of the remapping's path
+        let moves = moved_files(&root.join("src/vault"), &root.join("src/core"));
+        assert_eq!(
+            moves,
+            [(
+                root.join("src/vault/Vault.sol"),
+                root.join("src/core/Vault.sol")
+            )]
+        );
+        let edits = import_edits(&moves, read);
+        assert!(!edits.contains_key(&uri("src/vault/Vault.sol")));
+        assert_eq!(texts(&edits, "test/Vault.t.sol"), ["src/core/Vault.sol"]);
+
+        // Moving a file that imports nothing of the project's moves no other import
+        let moves = moved_files(&root.join("src/Router.sol"), &root.join("src/Router2.sol"));
+        assert!(import_edits(&moves, read).is_empty());
+    }
+}
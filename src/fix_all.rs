@@ -0,0 +1,263 @@
+//! Applying every quick fix of one rule across the workspace at once.
+//!
+//! `forge-lsp.fixAll` and `forge-lsp fix-all` collect the fixable findings of a rule, the
+//! server's own codes like `natspec-missing` or a `forge lint` rule like
+//! `mixed-case-function`, optionally only in the files matching a glob, and combine their
+//! fixes into one edit. A fix overlapping one already taken is skipped rather than
+//! corrupting the file; running the command again picks it up.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{collections::HashMap, path::Path};
+use tower_lsp::lsp_types::{NumberOrString, TextEdit, Url, WorkspaceEdit};
+
+use crate::{analysis::SourceFindings, code_actions::Fix, edits::EditBuilder, lint};
+
+/// Fixes every fixable finding of a rule, given as `{"rule": ..., "path": ...}`.
+pub const FIX_ALL_COMMAND: &str = "forge-lsp.fixAll";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FixAllParams {
+    /// Code of the diagnostics to fix.
+    pub rule: String,
+    /// Glob the paths of the fixed files, relative to their project root, must match. `*`
+    /// and `?` stay within a directory, `**` spans directories.
+    #[serde(default)]
+    pub path: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FixAllResult {
+    pub edit: WorkspaceEdit,
+    /// Number of findings fixed by the edit.
+    pub fixed: usize,
+    /// Number of fixable findings left out because their fix overlaps another.
+    pub skipped: usize,
+}
+
+/// Whether the `/`-separated `path` matches `pattern`.
+pub fn glob_matches(pattern: &str, path: &str) -> bool {
+    fn matches(pattern: &[u8], path: &[u8]) -> bool {
+        match pattern {
+            [] => path.is_empty(),
+            // `**/` also matches no directory at all
+            [b'*', b'*', b'/', rest @ ..] => {
+                matches(rest, path)
+                    || (1..=path.len())
+                        .filter(|&i| path[i - 1] == b'/')
+                        .any(|i| matches(rest, &path[i..]))
+            }
+            [b'*', b'*', rest @ ..] => (0..=path.len()).any(|i| matches(rest, &path[i..])),
+            [b'*', rest @ ..] => (0..=path.len())
+                .take_while(|&i| i == 0 || path[i - 1] != b'/')
+                .any(|i| matches(rest, &path[i..])),
+            [b'?', rest @ ..] => {
+                path.first().is_some_and(|&c| c != b'/') && matches(rest, &path[1..])
+            }
+            [c, rest @ ..] => path.first() == Some(c) && matches(rest, &path[1..]),
+        }
+    }
+    matches(pattern.as_bytes(), path.as_bytes())
+}
+
+/// Add the findings of the `forge lint` output `lint_output` for the project at `root` to
+/// its files among `findings`.
+pub fn add_lint_findings(findings: &mut [SourceFindings], root: &Path, lint_output: &Value) {
+    for file in findings.iter_mut().filter(|file| file.root == root) {
+        file.diagnostics.extend(lint::lint_output_to_diagnostics(
+            lint_output,
+            &file.path.to_string_lossy(),
+            &file.source,
+        ));
+    }
+}
+
+/// The files `fix_all` edits, with the text each edit applies to.
+struct Texts<'a, F> {
+    known: HashMap<Url, String>,
+    read: &'a F,
+}
+
+impl<F: Fn(&Url) -> Option<String>> Texts<'_, F> {
+    fn get(&mut self, uri: &Url) -> Option<&str> {
+        if !self.known.contains_key(uri) {
+            self.known.insert(uri.clone(), (self.read)(uri)?);
+        }
+        self.known.get(uri).map(String::as_str)
+    }
+}
+
+/// One edit fixing every finding of `params.rule` among `findings` whose root-relative path
+/// matches `params.path`. Files of related edits the findings don't cover are read with
+/// `read`.
+pub fn fix_all(
+    findings: &[SourceFindings],
+    params: &FixAllParams,
+    read: &impl Fn(&Url) -> Option<String>,
+) -> FixAllResult {
+    let mut texts = Texts {
+        known: findings
+            .iter()
+            .map(|file| (file.uri.clone(), file.source.clone()))
+            .collect(),
+        read,
+    };
+    let mut accepted: HashMap<Url, Vec<TextEdit>> = HashMap::new();
+    let mut result = FixAllResult::default();
+
+    for file in findings {
+        let relative = file.path.strip_prefix(&file.root).unwrap_or(&file.path);
+        if let Some(pattern) = &params.path
+            && !glob_matches(pattern, &relative.to_string_lossy().replace('\\', "/"))
+        {
+            continue;
+        }
+        for diagnostic in &file.diagnostics {
+            if diagnostic.code != Some(NumberOrString::String(params.rule.clone())) {
+                continue;
+            }
+            let Some(fix) = Fix::from_diagnostic(diagnostic) else {
+                continue;
+            };
+            let mut changes = fix.related;
+            changes
+                .entry(file.uri.clone())
+                .or_default()
+                .extend(fix.edits);
+
+            let fits = changes.iter().all(|(uri, edits)| {
+                let taken = accepted.get(uri).map(Vec::as_slice).unwrap_or_default();
+                texts.get(uri).is_some_and(|text| {
+                    EditBuilder::with_edits(text, &[taken, edits].concat()).is_ok()
+                })
+            });
+            if !fits {
+                result.skipped += 1;
+                continue;
+            }
+            for (uri, edits) in changes {
+                accepted.entry(uri).or_default().extend(edits);
+            }
+            result.fixed += 1;
+        }
+    }
+
+    let changes: HashMap<Url, Vec<TextEdit>> = accepted
+        .into_iter()
+        .filter_map(|(uri, edits)| {
+            let edits = EditBuilder::with_edits(texts.get(&uri)?, &edits)
+                .ok()?
+                .build();
+            Some((uri, edits))
+        })
+        .collect();
+    if !changes.is_empty() {
+        result.edit.changes = Some(changes);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use tower_lsp::lsp_types::{Diagnostic, Position, Range};
+
+    #[test]
+    fn test_glob_matches() {
+        assert!(glob_matches("src/**", "src/Vault.sol"));
+        assert!(glob_matches("src/**", "src/vault/Vault.sol"));
+        assert!(glob_matches("src/**/*.sol", "src/Vault.sol"));
+        assert!(glob_matches("src/**/*.sol", "src/a/b/Vault.sol"));
+        assert!(glob_matches("**/*.t.sol", "test/Vault.t.sol"));
+        assert!(glob_matches("src/V?ult.sol", "src/Vault.sol"));
+        assert!(!glob_matches("src/*.sol", "src/vault/Vault.sol"));
+        assert!(!glob_matches("src/**", "test/Vault.t.sol"));
+        assert!(!glob_matches("*.sol", "src/Vault.sol"));
+    }
+
+    fn insert(line: u32, text: &str) -> TextEdit {
+        TextEdit {
+            range: Range::new(Position::new(line, 0), Position::new(line, 0)),
+            new_text: text.to_string(),
+        }
+    }
+
+    fn finding(code: &str, fix: Option<Fix>) -> Diagnostic {
+        Diagnostic {
+            code: Some(NumberOrString::String(code.to_string())),
+            data: fix.and_then(|fix| fix.to_data()),
+            ..Diagnostic::default()
+        }
+    }
+
+    fn file(root: &Path, path: &str, diagnostics: Vec<Diagnostic>) -> SourceFindings {
+        let path = root.join(path);
+        SourceFindings {
+            root: root.to_path_buf(),
+            uri: Url::from_file_path(&path).unwrap(),
+            path,
+            source: "contract A {\n    function f() public {}\n}\n".to_string(),
+            diagnostics,
+        }
+    }
+
+    #[test]
+    fn test_fix_all_combines_fixes_of_one_rule() {
+        let root = PathBuf::from("/project");
+        let interface = Url::from_file_path("/project/src/IA.sol").unwrap();
+        let mut related = Fix::new("Add `f` to `IA`", vec![insert(1, "    // a\n")]);
+        related
+            .related
+            .insert(interface.clone(), vec![insert(0, "// f\n")]);
+        let findings = [
+            file(
+                &root,
+                "src/A.sol",
+                vec![
+                    finding("rule", Some(related)),
+                    // Overlaps the edit above
+                    finding(
+                        "rule",
+                        Some(Fix::new(
+                            "Replace",
+                            vec![TextEdit {
+                                range: Range::new(Position::new(0, 0), Position::new(1, 8)),
+                                new_text: String::new(),
+                            }],
+                        )),
+                    ),
+                    finding("rule", None),
+                    finding("other", Some(Fix::new("Other", vec![insert(0, "// x\n")]))),
+                ],
+            ),
+            file(
+                &root,
+                "test/A.t.sol",
+                vec![finding(
+                    "rule",
+                    Some(Fix::new("Test", vec![insert(0, "// t\n")])),
+                )],
+            ),
+        ];
+        let read = |uri: &Url| (uri == &interface).then(|| "interface IA {}\n".to_string());
+
+        let params = FixAllParams {
+            rule: "rule".to_string(),
+            path: Some("src/**".to_string()),
+        };
+        let result = fix_all(&findings, &params, &read);
+        assert_eq!((result.fixed, result.skipped), (1, 1));
+        let changes = result.edit.changes.unwrap();
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[&interface], [insert(0, "// f\n")]);
+
+        let params = FixAllParams {
+            path: None,
+            ..params
+        };
+        assert_eq!(fix_all(&findings, &params, &read).fixed, 2);
+    }
+}
@@ -0,0 +1,220 @@
+//! Flattened views of contracts through `forge flatten`.
+//!
+//! `forge flatten` inlines every file a source imports, each after a `// <path>` comment
+//! naming it relative to the project root, without their licenses, pragmas and imports.
+//! The flatten command returns that source under a `forge-lsp-flattened:` URI, for the
+//! client to show as a read-only virtual document, with the table mapping its lines back to
+//! the lines of the files they were copied from. The server keeps the table, so definition,
+//! hover and reference requests made in the view are answered at the original line.
+
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, path::Path};
+use tower_lsp::lsp_types::{Position, Url};
+
+use crate::project::normalize;
+
+/// Flattens the file URI given as the argument.
+pub const FLATTEN_COMMAND: &str = "forge-lsp.flatten";
+
+/// Scheme of the URIs of flattened views.
+pub const FLATTENED_SCHEME: &str = "forge-lsp-flattened";
+
+/// Consecutive lines of a flattened source copied from consecutive lines of a file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LineMapping {
+    /// First line of the run in the flattened source, zero-based.
+    pub line: u32,
+    /// Number of lines of the run.
+    pub count: u32,
+    /// The file the lines come from.
+    pub uri: Url,
+    /// First line of the run in that file.
+    pub original_line: u32,
+}
+
+/// The result of the flatten command.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Flattened {
+    /// URI of the virtual document.
+    pub uri: Url,
+    pub source: String,
+    pub mappings: Vec<LineMapping>,
+}
+
+/// URI of the flattened view of `file`, with its path.
+pub fn flattened_uri(file: &Url) -> Option<Url> {
+    Url::parse(&format!("{FLATTENED_SCHEME}://{}", file.path())).ok()
+}
+
+/// The mappings of the lines of `flattened`, the output of `forge flatten` for the project
+/// at `root`, to the files named by its section comments. `read` gives the text of a file.
+/// Lines forge rewrote, and those of sections whose file can't be read, are left out.
+pub fn line_mappings(
+    flattened: &str,
+    root: &Path,
+    read: impl Fn(&Path) -> Option<String>,
+) -> Vec<LineMapping> {
+    let mut mappings: Vec<LineMapping> = Vec::new();
+    let mut originals: HashMap<String, Option<(Url, Vec<String>)>> = HashMap::new();
+    // The file of the current section and the line after the last one matched in it
+    let mut section: Option<(String, usize)> = None;
+    for (line, text) in flattened.lines().enumerate() {
+        if let Some(path) = text
+            .strip_prefix("// ")
+            .filter(|path| path.ends_with(".sol") && !path.contains(char::is_whitespace))
+        {
+            let original = originals.entry(path.to_string()).or_insert_with(|| {
+                let file = normalize(&root.join(path));
+                let text = read(&file)?;
+                let uri = Url::from_file_path(&file).ok()?;
+                Some((uri, text.lines().map(str::to_string).collect()))
+            });
+            if original.is_some() {
+                section = Some((path.to_string(), 0));
+                continue;
+            }
+        }
+        let Some((path, next)) = section.as_mut() else {
+            continue;
+        };
+        let Some((uri, lines)) = &originals[path.as_str()] else {
+            continue;
+        };
+        // Blank lines only match the line right after the last match; others are looked
+        // for further on, past the lines forge removed
+        let found = if text.trim().is_empty() {
+            lines
+                .get(*next)
+                .is_some_and(|original| original.trim().is_empty())
+                .then_some(*next)
+        } else {
+            lines[(*next).min(lines.len())..]
+                .iter()
+                .position(|original| original.trim_end() == text.trim_end())
+                .map(|offset| *next + offset)
+        };
+        let Some(original) = found else {
+            continue;
+        };
+        *next = original + 1;
+        let (line, original) = (line as u32, original as u32);
+        match mappings.last_mut() {
+            Some(last)
+                if last.uri == *uri
+                    && last.line + last.count == line
+                    && last.original_line + last.count == original =>
+            {
+                last.count += 1;
+            }
+            _ => mappings.push(LineMapping {
+                line,
+                count: 1,
+                uri: uri.clone(),
+                original_line: original,
+            }),
+        }
+    }
+    mappings
+}
+
+/// Where `position` of a flattened source with `mappings` was copied from.
+pub fn original_position(mappings: &[LineMapping], position: Position) -> Option<(Url, Position)> {
+    let mapping = mappings.iter().find(|mapping| {
+        mapping.line <= position.line && position.line < mapping.line + mapping.count
+    })?;
+    let line = mapping.original_line + (position.line - mapping.line);
+    Some((mapping.uri.clone(), Position::new(line, position.character)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOKEN: &str = "\
+// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.20;
+
+contract Token {
+    uint256 public supply;
+
+    function mint() external {}
+}
+";
+
+    const VAULT: &str = "\
+// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.20;
+
+import {Token} from \"./Token.sol\";
+
+contract Vault {
+    Token token;
+}
+";
+
+    const FLATTENED: &str = "\
+// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.20;
+
+// src/Token.sol
+
+contract Token {
+    uint256 public supply;
+
+    function mint() external {}
+}
+
+// src/Vault.sol
+
+contract Vault {
+    Token token;
+}
+";
+
+    #[test]
+    fn test_line_mappings() {
+        let root = Path::new("/project");
+        let read = |path: &Path| match path.to_str()? {
+            "/project/src/Token.sol" => Some(TOKEN.to_string()),
+            "/project/src/Vault.sol" => Some(VAULT.to_string()),
+            _ => None,
+        };
+        let mappings = line_mappings(FLATTENED, root, read);
+        let token = Url::from_file_path("/project/src/Token.sol").unwrap();
+        let vault = Url::from_file_path("/project/src/Vault.sol").unwrap();
+        assert_eq!(
+            mappings,
+            [
+                LineMapping {
+                    line: 5,
+                    count: 5,
+                    uri: token.clone(),
+                    original_line: 3,
+                },
+                LineMapping {
+                    line: 13,
+                    count: 3,
+                    uri: vault.clone(),
+                    original_line: 5,
+                },
+            ]
+        );
+        assert_eq!(
+            original_position(&mappings, Position::new(8, 13)),
+            Some((token, Position::new(6, 13)))
+        );
+        assert_eq!(
+            original_position(&mappings, Position::new(14, 4)),
+            Some((vault, Position::new(6, 4)))
+        );
+        assert_eq!(original_position(&mappings, Position::new(1, 0)), None);
+
+        let uri = flattened_uri(&Url::from_file_path("/project/src/Vault.sol").unwrap());
+        assert_eq!(
+            uri.unwrap().as_str(),
+            "forge-lsp-flattened:///project/src/Vault.sol"
+        );
+    }
+}
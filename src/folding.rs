@@ -0,0 +1,292 @@
+//! Folding ranges for declarations and blocks that span several lines, the import block,
+//! and multi-line comments.
+//!
+//! Declarations and imports come from the in-process parse, which is the last successful
+//! one while the buffer doesn't parse. Comments are found in the current text.
+
+use serde_json::Value;
+use tower_lsp::lsp_types::{FoldingRange, FoldingRangeKind, Url};
+
+use crate::{
+    ast::{self, parse_src},
+    goto::bytes_to_pos,
+};
+
+/// Nodes whose source can be folded.
+const FOLDABLE: &[&str] = &[
+    "ContractDefinition",
+    "FunctionDefinition",
+    "ModifierDefinition",
+    "StructDefinition",
+    "EnumDefinition",
+    "EventDefinition",
+    "ErrorDefinition",
+    "Block",
+    "UncheckedBlock",
+    "InlineAssembly",
+];
+
+fn folding_range(start_line: u32, end_line: u32, kind: FoldingRangeKind) -> FoldingRange {
+    FoldingRange {
+        start_line,
+        start_character: None,
+        end_line,
+        end_character: None,
+        kind: Some(kind),
+        collapsed_text: None,
+    }
+}
+
+/// Lines of the start and the end of `node`.
+fn node_lines(node: &Value, source_bytes: &[u8]) -> Option<(u32, u32)> {
+    let (start, length, _) = parse_src(node.get("src")?.as_str()?)?;
+    Some((
+        bytes_to_pos(source_bytes, start)?.line,
+        bytes_to_pos(source_bytes, start + length)?.line,
+    ))
+}
+
+/// One range per run of consecutive imports spanning several lines.
+fn import_ranges(source_unit: &Value, source_bytes: &[u8]) -> Vec<FoldingRange> {
+    let mut runs = Vec::new();
+    let mut run: Option<(u32, u32)> = None;
+    for node in source_unit
+        .get("nodes")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+    {
+        let import = (node.get("nodeType").and_then(Value::as_str) == Some("ImportDirective"))
+            .then(|| node_lines(node, source_bytes))
+            .flatten();
+        run = match (import, run) {
+            (Some((_, end)), Some((start, _))) => Some((start, end)),
+            (Some(lines), None) => Some(lines),
+            (None, run) => {
+                runs.extend(run);
+                None
+            }
+        };
+    }
+    runs.extend(run);
+    runs.into_iter()
+        .filter(|(start, end)| end > start)
+        .map(|(start, end)| folding_range(start, end, FoldingRangeKind::Imports))
+        .collect()
+}
+
+/// Block comments spanning several lines, and runs of several `//` comments on lines of
+/// their own. String literals are skipped so quoted `//` and `/*` don't count.
+fn comment_ranges(source: &str) -> Vec<FoldingRange> {
+    let mut ranges = Vec::new();
+    let mut line = 0;
+    // Whether only whitespace precedes the cursor on its line
+    let mut line_start = true;
+    // First and last line of the current run of line comments
+    let mut run: Option<(u32, u32)> = None;
+    let mut chars = source.chars().peekable();
+
+    let end_run = |run: &mut Option<(u32, u32)>, ranges: &mut Vec<FoldingRange>| {
+        if let Some((start, end)) = run.take()
+            && end > start
+        {
+            ranges.push(folding_range(start, end, FoldingRangeKind::Comment));
+        }
+    };
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => {
+                if run.is_some_and(|(_, end)| end < line) {
+                    end_run(&mut run, &mut ranges);
+                }
+                line += 1;
+                line_start = true;
+                continue;
+            }
+            '/' if chars.peek() == Some(&'/') => {
+                if line_start {
+                    run = Some((run.map_or(line, |(start, _)| start), line));
+                } else {
+                    end_run(&mut run, &mut ranges);
+                }
+                while chars.peek().is_some_and(|&c| c != '\n') {
+                    chars.next();
+                }
+                continue;
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                end_run(&mut run, &mut ranges);
+                chars.next();
+                let start = line;
+                let mut previous = ' ';
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        line += 1;
+                    }
+                    if previous == '*' && c == '/' {
+                        break;
+                    }
+                    previous = c;
+                }
+                if line > start {
+                    ranges.push(folding_range(start, line, FoldingRangeKind::Comment));
+                }
+            }
+            '"' | '\'' => {
+                let mut escaped = false;
+                for next in chars.by_ref() {
+                    match next {
+                        _ if escaped => escaped = false,
+                        '\\' => escaped = true,
+                        '\n' => {
+                            line += 1;
+                            break;
+                        }
+                        _ if next == c => break,
+                        _ => {}
+                    }
+                }
+            }
+            c if c.is_whitespace() => continue,
+            _ => {}
+        }
+        if run.is_some() {
+            end_run(&mut run, &mut ranges);
+        }
+        line_start = false;
+    }
+    end_run(&mut run, &mut ranges);
+    ranges
+}
+
+/// Folding ranges of `uri`, one per starting line. A declaration and the body that
+/// opens on the same line fold as one range; the closing line stays visible. Imports and
+/// comments have no closing line, so they fold entirely.
+pub fn folding_ranges(ast_data: &Value, uri: &Url, source_bytes: &[u8]) -> Vec<FoldingRange> {
+    let mut ranges = comment_ranges(&String::from_utf8_lossy(source_bytes));
+    let Some(source_unit) = ast::source_unit(ast_data, uri) else {
+        return ranges;
+    };
+    ranges.extend(import_ranges(source_unit, source_bytes));
+
+    ast::walk(source_unit, &mut |node| {
+        if !node
+            .get("nodeType")
+            .and_then(Value::as_str)
+            .is_some_and(|node_type| FOLDABLE.contains(&node_type))
+        {
+            return;
+        }
+        let Some((start_line, end_line)) = node_lines(node, source_bytes) else {
+            return;
+        };
+        if end_line <= start_line + 1 {
+            return;
+        }
+        ranges.push(folding_range(
+            start_line,
+            end_line - 1,
+            FoldingRangeKind::Region,
+        ));
+    });
+
+    // The outermost range starting on a line wins
+    ranges.sort_by_key(|range| (range.start_line, std::cmp::Reverse(range.end_line)));
+    ranges.dedup_by_key(|range| range.start_line);
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax;
+
+    const SOURCE: &str = "\
+contract Vault {
+    struct Deposit {
+        address owner;
+        uint256 amount;
+    }
+
+    function deposit(uint256 amount) public {
+        if (amount > 0) {
+            total += amount;
+        }
+    }
+
+    function empty() public {}
+}
+";
+
+    const COMMENTED: &str = "\
+import {A} from \"./A.sol\";
+import {
+    B,
+    C
+} from \"./BC.sol\";
+
+/**
+ * @title Vault
+ */
+contract Vault {
+    // One line
+    uint256 x; // trailing
+    // first
+    // second
+    string s = \"// not a /* comment\";
+}
+";
+
+    fn lines(ranges: Vec<FoldingRange>) -> Vec<(u32, u32, Option<FoldingRangeKind>)> {
+        ranges
+            .into_iter()
+            .map(|range| (range.start_line, range.end_line, range.kind))
+            .collect()
+    }
+
+    #[test]
+    fn test_folding_ranges_of_declarations_and_blocks() {
+        let path = "/project/src/Vault.sol";
+        let uri = Url::from_file_path(path).unwrap();
+        let ast = syntax::parse(path, SOURCE);
+
+        let lines: Vec<(u32, u32)> = folding_ranges(&ast, &uri, SOURCE.as_bytes())
+            .iter()
+            .map(|range| (range.start_line, range.end_line))
+            .collect();
+        // `deposit` and its body start on the same line; `empty` fits on one line
+        assert_eq!(lines, [(0, 12), (1, 3), (6, 9), (7, 8)]);
+    }
+
+    #[test]
+    fn test_folding_ranges_of_imports_and_comments() {
+        let path = "/project/src/Vault.sol";
+        let uri = Url::from_file_path(path).unwrap();
+        let ast = syntax::parse(path, COMMENTED);
+
+        assert_eq!(
+            lines(folding_ranges(&ast, &uri, COMMENTED.as_bytes())),
+            [
+                (0, 4, Some(FoldingRangeKind::Imports)),
+                (6, 8, Some(FoldingRangeKind::Comment)),
+                (9, 14, Some(FoldingRangeKind::Region)),
+                (12, 13, Some(FoldingRangeKind::Comment)),
+            ]
+        );
+
+        // Comments still fold while the buffer doesn't parse
+        let broken = COMMENTED.replace("uint256 x;", "uint256 x");
+        assert_eq!(
+            lines(folding_ranges(
+                &serde_json::json!({}),
+                &uri,
+                broken.as_bytes()
+            )),
+            [
+                (6, 8, Some(FoldingRangeKind::Comment)),
+                (12, 13, Some(FoldingRangeKind::Comment)),
+            ]
+        );
+    }
+}
@@ -0,0 +1,629 @@
+//! Running Foundry tests from the editor.
+//!
+//! Test contracts get a "Run all tests in contract" lens and their `test*` functions a
+//! "Run test" lens, both invoking [`RUN_TEST_COMMAND`]. The command runs `forge test` on
+//! the selected tests and reports each failure on the name of its test function.
+//!
+//! [`TEST_RUN_COMMAND`] runs them with the traces of the failures instead, streaming what
+//! forge prints as progress. The trace of a failed test tells which of the calls the test
+//! function made failed first, such as the `assertEq` cheatcode or a reverting call of the
+//! contract under test, and how many calls of the same function came before it. The
+//! failure is reported on that call of the function's body when it has as many, and on the
+//! function's name otherwise, for failures the traces don't show.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use tower_lsp::lsp_types::{
+    CodeLens, Command, Diagnostic, DiagnosticSeverity, NumberOrString, Range, Url,
+};
+
+use crate::{
+    ast::{self, name, parse_src},
+    goto::bytes_to_pos,
+};
+
+/// Runs the tests of a contract. Arguments: the file URI, the contract name and optionally
+/// the name of a single test function.
+pub const RUN_TEST_COMMAND: &str = "forge-lsp.runTest";
+
+/// Runs the tests of a contract, or one of them, given as `{"uri": ..., "contract": ...,
+/// "test": ...}`, streaming forge's output and reporting failures on the failing calls.
+pub const TEST_RUN_COMMAND: &str = "forge-lsp.test.run";
+
+/// Diagnostic code of a failed test.
+pub const TEST_FAILURE_CODE: &str = "test-failure";
+
+/// Diagnostic code of a passed test, reported by runs after saves.
+pub const TEST_PASS_CODE: &str = "test-pass";
+
+/// Prefix forge runs functions with as tests.
+const TEST_PREFIX: &str = "test";
+
+/// Prefix of the cheatcodes making the next call's revert expected.
+const EXPECT_REVERT_PREFIX: &str = "expectRevert";
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TestRunParams {
+    /// Test file.
+    pub uri: Url,
+    pub contract: String,
+    /// A single test function of the contract, or all of them.
+    #[serde(default)]
+    pub test: Option<String>,
+}
+
+/// The first call a failed test made that failed, from its trace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FailingCall {
+    /// Name of the called function, such as `assertEq` or `withdraw`.
+    pub function: String,
+    /// Number of calls of that function the test made before, successful ones.
+    pub occurrence: usize,
+}
+
+/// A call of a counterexample: the arguments of a failed fuzz run, or one call of the
+/// sequence breaking an invariant.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CounterexampleCall {
+    /// Caller of the call of an invariant sequence.
+    pub sender: Option<String>,
+    /// Called contract of an invariant sequence.
+    pub address: Option<String>,
+    pub contract: Option<String>,
+    pub signature: Option<String>,
+    /// The decoded arguments, comma-separated.
+    pub args: Option<String>,
+    pub calldata: Option<String>,
+}
+
+/// Result of a single test function.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestOutcome {
+    pub contract: String,
+    /// Function name, without the parameter list forge reports.
+    pub test: String,
+    pub passed: bool,
+    /// Revert reason or assertion message of a failure.
+    pub reason: Option<String>,
+    /// Arguments of the failing fuzz run.
+    pub counterexample: Option<String>,
+    /// Calls of the counterexample of a failed fuzz or invariant test.
+    pub counterexample_calls: Vec<CounterexampleCall>,
+    /// The call a failure comes from, when its traces were kept.
+    pub failing_call: Option<FailingCall>,
+}
+
+impl TestOutcome {
+    fn message(&self) -> String {
+        let mut message = format!("`{}` failed", self.test);
+        if let Some(reason) = &self.reason {
+            message.push_str(&format!(": {reason}"));
+        }
+        if let Some(args) = &self.counterexample {
+            message.push_str(&format!("\ncounterexample: {args}"));
+        }
+        message
+    }
+}
+
+pub(crate) fn node_range(source: &[u8], node: &Value) -> Option<Range> {
+    let src = node.get("nameLocation").or_else(|| node.get("src"))?;
+    let (start, length, _) = parse_src(src.as_str()?)?;
+    Some(Range::new(
+        bytes_to_pos(source, start)?,
+        bytes_to_pos(source, start + length)?,
+    ))
+}
+
+fn is_test(function: &Value) -> bool {
+    function.get("nodeType").and_then(Value::as_str) == Some("FunctionDefinition")
+        && function.get("kind").and_then(Value::as_str) == Some("function")
+        && name(function).starts_with(TEST_PREFIX)
+        && matches!(
+            function.get("visibility").and_then(Value::as_str),
+            Some("public" | "external")
+        )
+}
+
+/// The deployable contracts of `uri` with the test functions they declare.
+fn test_contracts<'a>(ast_data: &'a Value, uri: &Url) -> Vec<(&'a Value, Vec<&'a Value>)> {
+    let Some(nodes) = ast::source_unit(ast_data, uri)
+        .and_then(|unit| unit.get("nodes"))
+        .and_then(Value::as_array)
+    else {
+        return vec![];
+    };
+
+    nodes
+        .iter()
+        .filter(|node| {
+            node.get("nodeType").and_then(Value::as_str) == Some("ContractDefinition")
+                && node.get("contractKind").and_then(Value::as_str) == Some("contract")
+                && node.get("abstract").and_then(Value::as_bool) != Some(true)
+        })
+        .filter_map(|contract| {
+            let tests: Vec<&Value> = contract
+                .get("nodes")
+                .and_then(Value::as_array)?
+                .iter()
+                .filter(|node| is_test(node))
+                .collect();
+            (!tests.is_empty()).then_some((contract, tests))
+        })
+        .collect()
+}
+
+fn lens(range: Range, title: &str, arguments: Vec<Value>) -> CodeLens {
+    CodeLens {
+        range,
+        command: Some(Command {
+            title: title.to_string(),
+            command: RUN_TEST_COMMAND.to_string(),
+            arguments: Some(arguments),
+        }),
+        data: None,
+    }
+}
+
+/// Run lenses for the test contracts and test functions of `uri`.
+pub fn test_lenses(ast_data: &Value, uri: &Url, source_bytes: &[u8]) -> Vec<CodeLens> {
+    let mut lenses = Vec::new();
+    for (contract, tests) in test_contracts(ast_data, uri) {
+        let contract_name = name(contract);
+        let Some(range) = node_range(source_bytes, contract) else {
+            continue;
+        };
+        lenses.push(lens(
+            range,
+            "Run all tests in contract",
+            vec![Value::from(uri.as_str()), Value::from(contract_name)],
+        ));
+        for test in tests {
+            let test_name = name(test);
+            let Some(range) = node_range(source_bytes, test) else {
+                continue;
+            };
+            lenses.push(lens(
+                range,
+                "Run test",
+                vec![
+                    Value::from(uri.as_str()),
+                    Value::from(contract_name),
+                    Value::from(test_name),
+                ],
+            ));
+        }
+    }
+    lenses
+}
+
+/// The failing call of the test result `result`, from the calls of the test function in
+/// its execution trace. Reverts the test expected, after an `expectRevert` cheatcode, are
+/// not failures.
+fn failing_call(result: &Value) -> Option<FailingCall> {
+    let arena = result
+        .get("traces")?
+        .as_array()?
+        .iter()
+        .find(|trace| trace.get(0).and_then(Value::as_str) == Some("Execution"))?
+        .get(1)?
+        .get("arena")?
+        .as_array()?;
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    let mut expecting_revert = false;
+    for child in arena.first()?.get("children")?.as_array()? {
+        let trace = &arena.get(child.as_u64()? as usize)?["trace"];
+        let Some(function) = trace["decoded"]["call_data"]["signature"]
+            .as_str()
+            .and_then(|signature| signature.split('(').next())
+        else {
+            expecting_revert = false;
+            continue;
+        };
+        if trace["success"] == false && !expecting_revert {
+            return Some(FailingCall {
+                function: function.to_string(),
+                occurrence: counts.get(function).copied().unwrap_or(0),
+            });
+        }
+        *counts.entry(function).or_default() += 1;
+        expecting_revert = function.starts_with(EXPECT_REVERT_PREFIX);
+    }
+    None
+}
+
+fn string(value: &Value, key: &str) -> Option<String> {
+    value.get(key).and_then(Value::as_str).map(str::to_string)
+}
+
+fn counterexample_call(call: &Value) -> CounterexampleCall {
+    CounterexampleCall {
+        sender: string(call, "sender"),
+        address: string(call, "addr"),
+        contract: string(call, "contract_name"),
+        signature: string(call, "signature"),
+        args: string(call, "args"),
+        calldata: string(call, "calldata"),
+    }
+}
+
+/// The calls of the counterexample of the test result `result`: the single call of a fuzz
+/// test, the sequence of an invariant.
+pub(crate) fn counterexample_calls(result: &Value) -> Vec<CounterexampleCall> {
+    match result.get("counterexample") {
+        Some(example) if example.get("Single").is_some() => {
+            vec![counterexample_call(&example["Single"])]
+        }
+        Some(example) => example
+            .get("Sequence")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .map(counterexample_call)
+            .collect(),
+        None => vec![],
+    }
+}
+
+/// The test results of `forge test --json` output, keyed by `<path>:<contract>`.
+pub fn outcomes(output: &Value) -> Vec<TestOutcome> {
+    let Some(suites) = output.as_object() else {
+        return vec![];
+    };
+
+    let mut outcomes = Vec::new();
+    for (suite, results) in suites {
+        let contract = suite.rsplit(':').next().unwrap_or(suite);
+        let Some(results) = results.get("test_results").and_then(Value::as_object) else {
+            continue;
+        };
+        for (signature, result) in results {
+            let status = result.get("status").and_then(Value::as_str);
+            if status == Some("Skipped") {
+                continue;
+            }
+            let counterexample = result
+                .get("counterexample")
+                .and_then(|example| example.get("Single"))
+                .and_then(|single| single.get("args"))
+                .and_then(Value::as_str)
+                .map(str::to_string);
+            outcomes.push(TestOutcome {
+                contract: contract.to_string(),
+                test: signature.split('(').next().unwrap_or(signature).to_string(),
+                passed: status == Some("Success"),
+                reason: result
+                    .get("reason")
+                    .and_then(Value::as_str)
+                    .map(str::to_string),
+                counterexample,
+                counterexample_calls: counterexample_calls(result),
+                failing_call: failing_call(result),
+            });
+        }
+    }
+    outcomes.sort_by(|a, b| (&a.contract, &a.test).cmp(&(&b.contract, &b.test)));
+    outcomes
+}
+
+/// The test contracts of `uri`, by name.
+pub fn test_suites<'a>(ast_data: &'a Value, uri: &Url) -> Vec<&'a str> {
+    test_contracts(ast_data, uri)
+        .into_iter()
+        .map(|(contract, _)| name(contract))
+        .collect()
+}
+
+/// The name of the function a call calls, `assertEq` in `assertEq(a, b)`, `vm.assertEq(a, b)`
+/// and `vault.withdraw{value: 1}(2)` alike.
+fn callee_name(call: &Value) -> Option<&str> {
+    let mut callee = call.get("expression")?;
+    // Call options wrap the callee
+    while callee.get("nodeType").and_then(Value::as_str) == Some("FunctionCallOptions") {
+        callee = callee.get("expression")?;
+    }
+    match callee.get("nodeType")?.as_str()? {
+        "Identifier" => Some(name(callee)),
+        "MemberAccess" => callee.get("memberName")?.as_str(),
+        _ => None,
+    }
+}
+
+/// Range of the call of the body of `test` that `call` failed in: its `occurrence`-th call
+/// of the function, in source order.
+fn call_range(source: &[u8], test: &Value, call: &FailingCall) -> Option<Range> {
+    let mut calls = Vec::new();
+    ast::walk(test.get("body")?, &mut |node| {
+        if node.get("nodeType").and_then(Value::as_str) == Some("FunctionCall")
+            && callee_name(node) == Some(call.function.as_str())
+            && let Some((start, length, _)) =
+                node.get("src").and_then(Value::as_str).and_then(parse_src)
+        {
+            calls.push((start, start + length));
+        }
+    });
+    calls.sort_unstable();
+    let (start, end) = *calls.get(call.occurrence)?;
+    Some(Range::new(
+        bytes_to_pos(source, start)?,
+        bytes_to_pos(source, end)?,
+    ))
+}
+
+/// Diagnostics on the test functions of `uri` that failed in `outcomes`.
+pub fn failure_diagnostics(
+    ast_data: &Value,
+    uri: &Url,
+    source_bytes: &[u8],
+    outcomes: &[TestOutcome],
+) -> Vec<Diagnostic> {
+    outcome_diagnostics(ast_data, uri, source_bytes, outcomes, false)
+}
+
+/// Diagnostics on the test functions of `uri` with an outcome in `outcomes`: errors on
+/// the failures, and hints on the passes when `passes` is set.
+pub fn outcome_diagnostics(
+    ast_data: &Value,
+    uri: &Url,
+    source_bytes: &[u8],
+    outcomes: &[TestOutcome],
+    passes: bool,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    for (contract, tests) in test_contracts(ast_data, uri) {
+        for test in tests {
+            let Some(outcome) = outcomes.iter().find(|outcome| {
+                (passes || !outcome.passed)
+                    && name(contract) == outcome.contract
+                    && name(test) == outcome.test
+            }) else {
+                continue;
+            };
+            let call = outcome
+                .failing_call
+                .as_ref()
+                .filter(|_| !outcome.passed)
+                .and_then(|call| call_range(source_bytes, test, call));
+            let Some(range) = call.or_else(|| node_range(source_bytes, test)) else {
+                continue;
+            };
+            let (severity, code, message) = if outcome.passed {
+                (
+                    DiagnosticSeverity::HINT,
+                    TEST_PASS_CODE,
+                    format!("`{}` passed", outcome.test),
+                )
+            } else {
+                (
+                    DiagnosticSeverity::ERROR,
+                    TEST_FAILURE_CODE,
+                    outcome.message(),
+                )
+            };
+            diagnostics.push(Diagnostic {
+                range,
+                severity: Some(severity),
+                code: Some(NumberOrString::String(code.to_string())),
+                source: Some("forge test".to_string()),
+                message,
+                ..Diagnostic::default()
+            });
+        }
+    }
+    diagnostics
+}
+
+/// One-line summary of a test run.
+pub fn summary(outcomes: &[TestOutcome]) -> String {
+    let failed = outcomes.iter().filter(|outcome| !outcome.passed).count();
+    format!("{} tests passed, {failed} failed", outcomes.len() - failed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tower_lsp::lsp_types::Position;
+
+    const SOURCE: &str = "\
+contract CounterTest {
+    function setUp() public {}
+    function test_Increment() public {}
+    function testFuzz_Set(uint256 x) public {}
+    function helper() internal {}
+}
+";
+
+    fn function(name: &str, visibility: &str) -> Value {
+        let start = SOURCE.find(&format!("function {name}")).unwrap();
+        json!({
+            "nodeType": "FunctionDefinition",
+            "kind": "function",
+            "name": name,
+            "visibility": visibility,
+            "src": format!("{start}:10:0"),
+            "nameLocation": format!("{}:{}:0", start + "function ".len(), name.len())
+        })
+    }
+
+    fn mock_ast(path: &str) -> Value {
+        let mut contract = json!({
+            "nodeType": "ContractDefinition",
+            "contractKind": "contract",
+            "abstract": false,
+            "name": "CounterTest",
+            "src": format!("0:{}:0", SOURCE.len()),
+            "nameLocation": "9:11:0"
+        });
+        contract["nodes"] = json!([
+            function("setUp", "public"),
+            function("test_Increment", "public"),
+            function("testFuzz_Set", "public"),
+            function("helper", "internal")
+        ]);
+        ast::mock_ast(
+            path,
+            json!({ "nodeType": "SourceUnit", "absolutePath": path, "nodes": [contract] }),
+        )
+    }
+
+    fn forge_output() -> Value {
+        json!({
+            "test/Counter.t.sol:CounterTest": {
+                "duration": "1ms",
+                "test_results": {
+                    "test_Increment()": { "status": "Success", "reason": null },
+                    "testFuzz_Set(uint256)": {
+                        "status": "Failure",
+                        "reason": "assertion failed: 1 != 2",
+                        "counterexample": { "Single": { "calldata": "0x", "args": "2" } }
+                    }
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn test_lenses_on_test_contract() {
+        let path = "/project/test/Counter.t.sol";
+        let uri = Url::from_file_path(path).unwrap();
+        let lenses = test_lenses(&mock_ast(path), &uri, SOURCE.as_bytes());
+
+        let titles: Vec<_> = lenses
+            .iter()
+            .map(|lens| {
+                let command = lens.command.as_ref().unwrap();
+                assert_eq!(command.command, RUN_TEST_COMMAND);
+                (
+                    command.title.as_str(),
+                    command.arguments.as_ref().unwrap().len(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            titles,
+            [
+                ("Run all tests in contract", 2),
+                ("Run test", 3),
+                ("Run test", 3)
+            ]
+        );
+        assert_eq!(lenses[0].range.start, Position::new(0, 9));
+        let arguments = lenses[2]
+            .command
+            .as_ref()
+            .unwrap()
+            .arguments
+            .as_ref()
+            .unwrap();
+        assert_eq!(arguments[2], "testFuzz_Set");
+    }
+
+    #[test]
+    fn test_failures_reported_on_test_name() {
+        let path = "/project/test/Counter.t.sol";
+        let uri = Url::from_file_path(path).unwrap();
+        let outcomes = outcomes(&forge_output());
+        assert_eq!(outcomes.len(), 2);
+        assert_eq!(summary(&outcomes), "1 tests passed, 1 failed");
+
+        let diagnostics = failure_diagnostics(&mock_ast(path), &uri, SOURCE.as_bytes(), &outcomes);
+        assert_eq!(diagnostics.len(), 1);
+        let start = SOURCE.find("testFuzz_Set").unwrap();
+        assert_eq!(
+            diagnostics[0].range.start,
+            bytes_to_pos(SOURCE.as_bytes(), start).unwrap()
+        );
+        assert_eq!(
+            diagnostics[0].message,
+            "`testFuzz_Set` failed: assertion failed: 1 != 2\ncounterexample: 2"
+        );
+
+        let diagnostics =
+            outcome_diagnostics(&mock_ast(path), &uri, SOURCE.as_bytes(), &outcomes, true);
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::HINT));
+        assert_eq!(diagnostics[0].message, "`test_Increment` passed");
+        assert_eq!(test_suites(&mock_ast(path), &uri), ["CounterTest"]);
+    }
+
+    #[test]
+    fn test_failure_reported_on_failing_call() {
+        let source = "\
+contract VaultTest {
+    function test_Withdraw() public {
+        vault.deposit{value: 1}();
+        assertEq(vault.balance(), 1);
+        vm.expectRevert();
+        vault.withdraw(2);
+        vault.withdraw(1);
+        assertEq(vault.balance(), 1);
+    }
+}
+";
+        let path = "/project/test/Vault.t.sol";
+        let uri = Url::from_file_path(path).unwrap();
+        let ast_data = crate::syntax::parse(path, source);
+        let calls = [
+            ("deposit()", true),
+            ("balance()", true),
+            ("assertEq(uint256,uint256)", true),
+            ("expectRevert()", true),
+            ("withdraw(uint256)", false),
+            ("withdraw(uint256)", true),
+            ("balance()", true),
+            ("assertEq(uint256,uint256)", false),
+        ];
+        let mut arena = vec![json!({
+            "children": (1..=calls.len()).collect::<Vec<_>>(),
+            "trace": { "success": false, "decoded": { "call_data": { "signature": "test_Withdraw()" } } }
+        })];
+        arena.extend(calls.iter().map(|(signature, success)| {
+            json!({
+                "children": [],
+                "trace": { "success": success, "decoded": { "call_data": { "signature": signature } } }
+            })
+        }));
+        let output = json!({
+            "test/Vault.t.sol:VaultTest": {
+                "test_results": {
+                    "test_Withdraw()": {
+                        "status": "Failure",
+                        "reason": "assertion failed: 0 != 1",
+                        "traces": [["Deployment", { "arena": [] }], ["Execution", { "arena": arena }]]
+                    }
+                }
+            }
+        });
+
+        let outcomes = outcomes(&output);
+        assert_eq!(
+            outcomes[0].failing_call,
+            Some(FailingCall {
+                function: "assertEq".to_string(),
+                occurrence: 1,
+            })
+        );
+        let diagnostics = failure_diagnostics(&ast_data, &uri, source.as_bytes(), &outcomes);
+        assert_eq!(
+            diagnostics[0].range,
+            Range::new(Position::new(7, 8), Position::new(7, 36))
+        );
+        assert_eq!(
+            diagnostics[0].message,
+            "`test_Withdraw` failed: assertion failed: 0 != 1"
+        );
+
+        // A failure the source has no matching call for stays on the test's name
+        let mut outcomes = outcomes;
+        outcomes[0].failing_call = Some(FailingCall {
+            function: "assertEq".to_string(),
+            occurrence: 2,
+        });
+        let diagnostics = failure_diagnostics(&ast_data, &uri, source.as_bytes(), &outcomes);
+        assert_eq!(diagnostics[0].range.start, Position::new(1, 13));
+    }
+}
@@ -0,0 +1,478 @@
+//! Document and range formatting through `forge fmt`, and on-type re-indentation.
+//!
+//! The buffer is always formatted as a whole. Document formatting replaces it in one edit;
+//! range formatting diffs the lines of the formatted output against the buffer and keeps
+//! the changes touching the requested lines.
+//!
+//! Formatting on type can't wait for forge, nor rely on the buffer parsing while it is
+//! being typed, so it only re-indents: after a `}` the lines of the block it closes, after
+//! a `;` the lines of the statement it ends and after a newline the new line. Each line is
+//! indented one level, of the project's `tab_width` or a tab, per line holding brackets
+//! still open at its start, plus one where it continues the statement of the line above,
+//! as forge fmt indents them.
+
+use tower_lsp::lsp_types::{Position, Range, TextEdit};
+
+use crate::{edits::EditBuilder, project::FmtSettings};
+
+/// Characters after which the client asks for on-type formatting.
+pub const ON_TYPE_TRIGGER_CHARACTERS: [&str; 3] = ["}", ";", "\n"];
+
+/// Largest line table the diff computes before replacing the changed lines wholesale.
+const MAX_DIFF_CELLS: usize = 4_000_000;
+
+/// Lines `start..end` of the original text are replaced by `lines` of the formatted one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Hunk<'a> {
+    pub start: usize,
+    pub end: usize,
+    pub lines: Vec<&'a str>,
+}
+
+/// The changed lines between `original` and `formatted`, from a longest common subsequence
+/// of their lines.
+pub(crate) fn hunks<'a>(original: &[&str], formatted: &[&'a str]) -> Vec<Hunk<'a>> {
+    let prefix = original
+        .iter()
+        .zip(formatted)
+        .take_while(|(a, b)| a == b)
+        .count();
+    let suffix = original[prefix..]
+        .iter()
+        .rev()
+        .zip(formatted[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old = &original[prefix..original.len() - suffix];
+    let new = &formatted[prefix..formatted.len() - suffix];
+    if old.is_empty() && new.is_empty() {
+        return vec![];
+    }
+    if (old.len() + 1) * (new.len() + 1) > MAX_DIFF_CELLS {
+        return vec![Hunk {
+            start: prefix,
+            end: prefix + old.len(),
+            lines: new.to_vec(),
+        }];
+    }
+
+    // lcs[i][j]: length of the longest common subsequence of old[i..] and new[j..]
+    let width = new.len() + 1;
+    let mut lcs = vec![0u32; (old.len() + 1) * width];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i * width + j] = if old[i] == new[j] {
+                lcs[(i + 1) * width + j + 1] + 1
+            } else {
+                lcs[(i + 1) * width + j].max(lcs[i * width + j + 1])
+            };
+        }
+    }
+
+    let mut hunks = Vec::new();
+    let mut current: Option<Hunk> = None;
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            hunks.extend(current.take());
+            i += 1;
+            j += 1;
+            continue;
+        }
+        let hunk = current.get_or_insert_with(|| Hunk {
+            start: prefix + i,
+            end: prefix + i,
+            lines: vec![],
+        });
+        if j < new.len() && (i == old.len() || lcs[i * width + j + 1] >= lcs[(i + 1) * width + j]) {
+            hunk.lines.push(new[j]);
+            j += 1;
+        } else {
+            hunk.end += 1;
+            i += 1;
+        }
+    }
+    hunks.extend(current);
+    hunks
+}
+
+/// A single edit replacing `original` with `formatted`, if they differ.
+pub fn document_edits(original: &str, formatted: &str) -> Vec<TextEdit> {
+    let mut edits = EditBuilder::new(original);
+    if original != formatted {
+        // Whole-buffer bounds never fail
+        _ = edits.replace(0, original.len(), formatted);
+    }
+    edits.build()
+}
+
+/// Minimal line edits turning `original` into `formatted` that touch the lines of `range`.
+pub fn range_edits(original: &str, formatted: &str, range: Range) -> Vec<TextEdit> {
+    let old: Vec<&str> = original.split_inclusive('\n').collect();
+    let new: Vec<&str> = formatted.split_inclusive('\n').collect();
+    let (first, last) = (range.start.line as usize, range.end.line as usize);
+
+    let mut edits = EditBuilder::new(original);
+    for hunk in hunks(&old, &new) {
+        let touched = if hunk.start == hunk.end {
+            (first..=last).contains(&hunk.start)
+        } else {
+            hunk.start <= last && hunk.end > first
+        };
+        if touched {
+            // Hunks cover disjoint line spans of the original
+            _ = edits.replace(
+                edits.line_offset(hunk.start),
+                edits.line_offset(hunk.end),
+                hunk.lines.concat(),
+            );
+        }
+    }
+    edits.build()
+}
+
+/// What the scanner is inside of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Lexical {
+    Code,
+    LineComment,
+    BlockComment,
+    String(u8),
+}
+
+/// A line of the buffer, as indentation sees it.
+#[derive(Debug, Clone, Default)]
+struct Line {
+    /// Lines of the brackets open at the start of the line, innermost last.
+    open: Vec<usize>,
+    /// Whether the line starts inside a block comment or a string.
+    in_literal: bool,
+    /// Whether the line starts inside an `assembly` block, whose statements end without `;`.
+    yul: bool,
+    /// Last character of the line outside comments.
+    last_code: Option<u8>,
+}
+
+/// The bracket structure of a buffer.
+#[derive(Debug, Clone, Default)]
+struct Scan {
+    lines: Vec<Line>,
+    /// Offset of each closing bracket, with the line of the bracket it closes.
+    closed: Vec<(usize, usize)>,
+    /// Offsets of the `;` outside comments and strings.
+    semicolons: Vec<usize>,
+}
+
+fn scan(source: &str) -> Scan {
+    let bytes = source.as_bytes();
+    let mut scan = Scan {
+        lines: vec![Line::default()],
+        ..Scan::default()
+    };
+    // Open brackets, with their line and whether they are in assembly
+    let mut open: Vec<(usize, bool)> = vec![];
+    let mut statement_start = 0;
+    let mut state = Lexical::Code;
+    let mut i = 0;
+    while i < bytes.len() {
+        let byte = bytes[i];
+        let next = bytes.get(i + 1).copied();
+        let line = scan.lines.len() - 1;
+        match (state, byte) {
+            (_, b'\n') => {
+                if state == Lexical::LineComment {
+                    state = Lexical::Code;
+                }
+                scan.lines.push(Line {
+                    open: open.iter().map(|(line, _)| *line).collect(),
+                    in_literal: state != Lexical::Code,
+                    yul: open.last().is_some_and(|(_, yul)| *yul),
+                    last_code: None,
+                });
+            }
+            (Lexical::Code, b'/') if next == Some(b'/') => state = Lexical::LineComment,
+            (Lexical::Code, b'/') if next == Some(b'*') => {
+                state = Lexical::BlockComment;
+                i += 1;
+            }
+            (Lexical::Code, _) => {
+                let in_yul = open.last().is_some_and(|(_, yul)| *yul);
+                match byte {
+                    b'"' | b'\'' => state = Lexical::String(byte),
+                    b'{' | b'(' | b'[' => {
+                        let yul = in_yul
+                            || byte == b'{'
+                                && source[statement_start..i]
+                                    .split(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                                    .any(|word| word == "assembly");
+                        open.push((line, yul));
+                    }
+                    b'}' | b')' | b']' => {
+                        if let Some((opener, _)) = open.pop() {
+                            scan.closed.push((i, opener));
+                        }
+                    }
+                    b';' => scan.semicolons.push(i),
+                    _ => {}
+                }
+                if matches!(byte, b';' | b'{' | b'}') {
+                    statement_start = i + 1;
+                }
+                if !byte.is_ascii_whitespace() {
+                    scan.lines[line].last_code = Some(byte);
+                }
+            }
+            (Lexical::BlockComment, b'*') if next == Some(b'/') => {
+                state = Lexical::Code;
+                i += 1;
+            }
+            (Lexical::String(_), b'\\') => i += 1,
+            (Lexical::String(quote), _) if byte == quote => {
+                state = Lexical::Code;
+                scan.lines[line].last_code = Some(byte);
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    scan
+}
+
+/// The indentation level of line `index` of `lines`, whose text is `text`.
+fn level(lines: &[Line], index: usize, text: &str) -> usize {
+    let text = text.trim_start();
+    let leading_closers = text
+        .bytes()
+        .take_while(|byte| matches!(byte, b'}' | b')' | b']') || byte.is_ascii_whitespace())
+        .filter(|byte| !byte.is_ascii_whitespace())
+        .count();
+    let open = &lines[index].open;
+    let open = &open[..open.len().saturating_sub(leading_closers)];
+    // Brackets opened on the same line add one level together
+    let mut levels = open.len();
+    for pair in open.windows(2) {
+        if pair[0] == pair[1] {
+            levels -= 1;
+        }
+    }
+
+    // Lines continue the statement above unless it ended, or they are comments, open or
+    // close a block, or are in assembly, where statements end with the line
+    let comment = text.starts_with("//") || text.starts_with("/*");
+    let previous = lines[..index].iter().rev().find_map(|line| line.last_code);
+    let continues = !comment
+        && !lines[index].yul
+        && !text.starts_with(['{', '}', ')', ']'])
+        && previous.is_some_and(|last| !matches!(last, b';' | b'{' | b'}' | b',' | b'(' | b'['));
+    levels + usize::from(continues)
+}
+
+/// Edits re-indenting the lines `first..=last` of `source` that need it. Blank lines are
+/// left alone, except `keep_blank`, the line a newline was typed into.
+fn reindent(
+    source: &str,
+    lines: &[Line],
+    first: usize,
+    last: usize,
+    keep_blank: Option<usize>,
+    settings: &FmtSettings,
+) -> Vec<TextEdit> {
+    let mut edits = EditBuilder::new(source);
+    for (index, text) in source.split('\n').enumerate().take(last + 1).skip(first) {
+        let line = &lines[index];
+        let text = text.strip_suffix('\r').unwrap_or(text);
+        let blank = text.trim().is_empty();
+        if line.in_literal || (blank && keep_blank != Some(index)) {
+            continue;
+        }
+        let indent = settings.indent(level(lines, index, text));
+        let current = text.len() - text.trim_start().len();
+        let current = if blank { text.len() } else { current };
+        if text[..current] != indent {
+            let start = edits.line_offset(index);
+            // Offsets within the line's leading whitespace are in bounds
+            _ = edits.replace(start, start + current, indent);
+        }
+    }
+    edits.build()
+}
+
+/// Edits re-indenting what typing `character` before `position` completed in `source`.
+pub fn on_type_edits(
+    source: &str,
+    position: Position,
+    character: &str,
+    settings: &FmtSettings,
+) -> Vec<TextEdit> {
+    let Scan {
+        lines,
+        closed,
+        semicolons,
+    } = scan(source);
+    let line = position.line as usize;
+    if line >= lines.len() {
+        return vec![];
+    }
+    let edits = EditBuilder::new(source);
+    let line_start = edits.line_offset(line);
+    let line_text = &source[line_start..];
+    let line_text = line_text.split('\n').next().unwrap_or_default();
+    // Offset of the typed character, from the UTF-16 column after it
+    let typed = line_text
+        .char_indices()
+        .scan(0u32, |column, (offset, ch)| {
+            *column += ch.len_utf16() as u32;
+            Some((offset, *column))
+        })
+        .find(|(_, column)| *column == position.character)
+        .map(|(offset, _)| line_start + offset);
+
+    match character {
+        "\n" => reindent(source, &lines, line, line, Some(line), settings),
+        "}" => {
+            let Some(opener) = typed.and_then(|typed| {
+                closed
+                    .iter()
+                    .find(|(offset, _)| *offset == typed)
+                    .map(|(_, opener)| *opener)
+            }) else {
+                return vec![];
+            };
+            reindent(source, &lines, (opener + 1).min(line), line, None, settings)
+        }
+        ";" => {
+            if typed.is_none_or(|typed| !semicolons.contains(&typed)) {
+                return vec![];
+            }
+            // The statement starts on the line after the end of the previous one
+            let start = lines[..line]
+                .iter()
+                .rposition(|line| matches!(line.last_code, Some(b';' | b'{' | b'}')))
+                .map_or(0, |end| end + 1);
+            reindent(source, &lines, start, line, None, settings)
+        }
+        _ => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower_lsp::lsp_types::Position;
+
+    const ORIGINAL: &str = "\
+contract A {
+    uint x ;
+    function f( ) public {}
+
+    function g() public {
+        x=1;
+    }
+}";
+
+    const FORMATTED: &str = "\
+contract A {
+    uint256 x;
+    function f() public {}
+
+    function g() public {
+        x = 1;
+    }
+}
+";
+
+    #[test]
+    fn test_document_edits_replace_buffer() {
+        assert!(document_edits(FORMATTED, FORMATTED).is_empty());
+        let edits = document_edits(ORIGINAL, FORMATTED);
+        assert_eq!(edits.len(), 1);
+        assert_eq!(
+            edits[0].range,
+            Range::new(Position::new(0, 0), Position::new(7, 1))
+        );
+        assert_eq!(edits[0].new_text, FORMATTED);
+    }
+
+    #[test]
+    fn test_range_edits_are_minimal() {
+        let everything = Range::new(Position::new(0, 0), Position::new(7, 1));
+        let edits = range_edits(ORIGINAL, FORMATTED, everything);
+        let changed: Vec<_> = edits
+            .iter()
+            .map(|edit| (edit.range.start.line, edit.range.end.line))
+            .collect();
+        assert_eq!(changed, [(1, 3), (5, 6), (7, 7)]);
+        assert_eq!(
+            edits[0].new_text,
+            "    uint256 x;\n    function f() public {}\n"
+        );
+        // The unterminated last line is replaced up to the end of the buffer
+        assert_eq!(edits[2].range.end, Position::new(7, 1));
+        assert_eq!(edits[2].new_text, "}\n");
+
+        let body = Range::new(Position::new(5, 0), Position::new(5, 4));
+        let edits = range_edits(ORIGINAL, FORMATTED, body);
+        assert_eq!(edits.len(), 1);
+        assert_eq!(edits[0].new_text, "        x = 1;\n");
+    }
+
+    #[test]
+    fn test_on_type_edits_reindent() {
+        let settings = FmtSettings::default();
+        let source = "\
+contract A {
+function f(uint a) public {
+      uint x = a +
+ 1;
+        assembly {
+  let y := x
+          mstore(0, y)
+        }
+    // call
+    foo(
+  a, \"}\",
+        b
+    );
+
+  }
+}";
+        let edits = on_type_edits(source, Position::new(14, 3), "}", &settings);
+        assert_eq!(
+            crate::edits::apply(source, &edits).unwrap(),
+            "\
+contract A {
+function f(uint a) public {
+        uint x = a +
+            1;
+        assembly {
+            let y := x
+            mstore(0, y)
+        }
+        // call
+        foo(
+            a, \"}\",
+            b
+        );
+
+    }
+}"
+        );
+
+        let source = "contract B {\n        uint x; // y;\n}";
+        let edits = on_type_edits(source, Position::new(1, 15), ";", &settings);
+        assert_eq!(
+            crate::edits::apply(source, &edits).unwrap(),
+            "contract B {\n    uint x; // y;\n}"
+        );
+        assert!(on_type_edits(source, Position::new(1, 20), ";", &settings).is_empty());
+
+        let source = "contract C {\n\tfunction f() public {\n\n";
+        let tabs = FmtSettings {
+            tabs: true,
+            ..FmtSettings::default()
+        };
+        let edits = on_type_edits(source, Position::new(2, 0), "\n", &tabs);
+        assert_eq!(edits.len(), 1);
+        assert_eq!(edits[0].new_text, "\t\t");
+    }
+}
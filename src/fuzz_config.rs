@@ -0,0 +1,664 @@
+//! Configurations of the Echidna and Medusa property fuzzers, and their findings.
+//!
+//! `echidna.yaml` and `medusa.json` name the contracts to fuzz and the functions to target
+//! or exclude as strings, `"Vault"` and `"Vault.deposit(uint256)"`, which nothing checks
+//! until the fuzzer starts. In the editor they are checked against the contracts of the
+//! project index and completed from them. `forge-lsp.runFuzzer` runs the fuzzer of a
+//! configuration and reports each violated property on its function while it runs.
+
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+};
+use tower_lsp::lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionTextEdit, Diagnostic, DiagnosticSeverity,
+    Location, NumberOrString, Position, Range, TextEdit, Url,
+};
+
+use crate::{
+    ast, build_info, selectors,
+    test_names::{self, TestName},
+};
+
+/// Runs the fuzzer of a configuration. Arguments: the configuration's URI and optionally
+/// the contract to fuzz.
+pub const RUN_FUZZER_COMMAND: &str = "forge-lsp.runFuzzer";
+
+/// Diagnostic code of a reference to a contract or function that does not exist.
+pub const UNKNOWN_TARGET_CODE: &str = "fuzz-unknown-target";
+
+/// Diagnostic code of a property the fuzzer falsified.
+pub const PROPERTY_VIOLATION_CODE: &str = "property-violation";
+
+/// Lines of a call sequence kept in the message of a violation.
+const MAX_DETAIL_LINES: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Fuzzer {
+    Echidna,
+    Medusa,
+}
+
+impl Fuzzer {
+    /// The fuzzer configured by the file at `path`, judged by its name.
+    pub fn of_config(path: &Path) -> Option<Self> {
+        match path.file_name()?.to_str()? {
+            "echidna.yaml" | "echidna.yml" | "echidna.config.yaml" | "echidna.config.yml" => {
+                Some(Self::Echidna)
+            }
+            "medusa.json" => Some(Self::Medusa),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Echidna => "echidna",
+            Self::Medusa => "medusa",
+        }
+    }
+
+    /// Program and arguments fuzzing the project at `root` with the configuration at
+    /// `config`, limited to `contract` when given.
+    pub fn command_line(self, root: &str, config: &str, contract: Option<&str>) -> Vec<String> {
+        let mut args: Vec<String> = match self {
+            Self::Echidna => ["echidna", root, "--config", config, "--format", "text"]
+                .map(String::from)
+                .to_vec(),
+            Self::Medusa => ["medusa", "fuzz", "--config", config]
+                .map(String::from)
+                .to_vec(),
+        };
+        if let Some(contract) = contract {
+            let flag = match self {
+                Self::Echidna => "--contract",
+                Self::Medusa => "--target-contracts",
+            };
+            args.extend([flag.to_string(), contract.to_string()]);
+        }
+        args
+    }
+
+    /// Keys whose values name contracts and functions.
+    fn reference_keys(self) -> &'static [(&'static str, TargetKind)] {
+        match self {
+            Self::Echidna => &[
+                ("filterFunctions", TargetKind::Function),
+                ("deployContracts", TargetKind::Contract),
+            ],
+            Self::Medusa => &[
+                ("targetContracts", TargetKind::Contract),
+                ("targetFunctionSignatures", TargetKind::Function),
+                ("excludeFunctionSignatures", TargetKind::Function),
+            ],
+        }
+    }
+}
+
+/// The fuzzer configured by the file at `uri` and the root of its Foundry project.
+pub fn fuzzer_config(uri: &Url) -> Option<(Fuzzer, PathBuf)> {
+    let path = uri.to_file_path().ok()?;
+    let fuzzer = Fuzzer::of_config(&path)?;
+    Some((fuzzer, build_info::find_project_root(&path)?))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetKind {
+    /// `Vault`
+    Contract,
+    /// `Vault.deposit(uint256)`
+    Function,
+}
+
+/// A string of a configuration naming a contract or function.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reference {
+    pub kind: TargetKind,
+    pub text: String,
+    /// Range of the text, quotes excluded.
+    pub range: Range,
+}
+
+/// The key a line of YAML or JSON starts with, and the rest of the line.
+fn line_key(line: &str) -> Option<(&str, &str)> {
+    let trimmed = line.trim_start();
+    let (key, rest) = trimmed.split_once(':')?;
+    let key = key.trim().trim_matches(|c| c == '"' || c == '\'');
+    key.chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_')
+        .then_some((key, rest))
+        .filter(|(key, _)| !key.is_empty())
+}
+
+/// The quoted strings of `line`, with the column their text starts at.
+fn quoted_strings(line: &str) -> Vec<(usize, &str)> {
+    let mut strings = Vec::new();
+    let mut rest = line;
+    let mut offset = 0;
+    while let Some(start) = rest.find(['"', '\'']) {
+        let quote = rest[start..].chars().next().unwrap_or('"');
+        let after = &rest[start + 1..];
+        let Some(end) = after.find(quote) else {
+            break;
+        };
+        strings.push((offset + start + 1, &after[..end]));
+        offset += start + end + 2;
+        rest = &after[end + 1..];
+    }
+    strings
+}
+
+/// The references to contracts and functions in the configuration `source` of `fuzzer`.
+/// Values belong to the last key above them, so lists spanning lines are covered.
+pub fn references(fuzzer: Fuzzer, source: &str) -> Vec<Reference> {
+    let keys = fuzzer.reference_keys();
+    let mut current = None;
+    let mut references = Vec::new();
+    for (line_number, line) in source.lines().enumerate() {
+        if line.trim_start().starts_with('#') {
+            continue;
+        }
+        let (values, column) = match line_key(line) {
+            Some((key, rest)) => {
+                current = keys
+                    .iter()
+                    .find(|(name, _)| *name == key)
+                    .map(|(_, kind)| *kind);
+                (rest, line.len() - rest.len())
+            }
+            None => (line, 0),
+        };
+        let Some(kind) = current else {
+            continue;
+        };
+        let mut strings: Vec<(usize, &str)> = quoted_strings(values)
+            .into_iter()
+            .map(|(start, text)| (column + start, text))
+            .collect();
+        // Unquoted YAML list items
+        if strings.is_empty()
+            && let Some(item) = values.trim_start().strip_prefix("- ")
+            && !item.trim().is_empty()
+        {
+            let item = item.trim();
+            strings.push((line.find(item).unwrap_or_default(), item));
+        }
+        for (start, text) in strings {
+            // Addresses of `deployContracts` pairs
+            if text.starts_with("0x") {
+                continue;
+            }
+            references.push(Reference {
+                kind,
+                text: text.to_string(),
+                range: Range::new(
+                    Position::new(line_number as u32, start as u32),
+                    Position::new(line_number as u32, (start + text.len()) as u32),
+                ),
+            });
+        }
+    }
+    references
+}
+
+/// The contracts of a project with the signatures of the functions they can be fuzzed
+/// through, inherited ones included.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FuzzTargets {
+    pub contracts: BTreeMap<String, Vec<String>>,
+}
+
+impl FuzzTargets {
+    /// The targets of the project AST `ast_data`.
+    pub fn new(ast_data: &Value) -> Self {
+        let Some(sources) = ast_data.get("sources") else {
+            return Self::default();
+        };
+        let index = ast::index_nodes(sources);
+        let mut contracts = BTreeMap::new();
+        for node in index.values() {
+            if node["nodeType"] != "ContractDefinition" || node["contractKind"] == "interface" {
+                continue;
+            }
+            let Some(name) = node["name"].as_str() else {
+                continue;
+            };
+            let bases = node
+                .get("linearizedBaseContracts")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(|id| index.get(&id.as_u64()?));
+            let mut signatures: Vec<String> = bases
+                .flat_map(|base| {
+                    base.get("nodes")
+                        .and_then(Value::as_array)
+                        .into_iter()
+                        .flatten()
+                })
+                .filter(|function| {
+                    function["nodeType"] == "FunctionDefinition"
+                        && function["kind"] == "function"
+                        && matches!(function["visibility"].as_str(), Some("public" | "external"))
+                })
+                .filter_map(|function| selectors::canonical_signature(function, &index))
+                .collect();
+            signatures.sort();
+            signatures.dedup();
+            contracts.insert(name.to_string(), signatures);
+        }
+        Self { contracts }
+    }
+
+    /// Why `reference` names nothing in these targets, if it doesn't.
+    fn problem(&self, reference: &Reference) -> Option<String> {
+        let (contract, function) = match reference.kind {
+            TargetKind::Contract => (reference.text.as_str(), None),
+            TargetKind::Function => match reference.text.split_once('(') {
+                Some((name, _)) => match name.rsplit_once('.') {
+                    Some((contract, _)) => (contract, Some(&reference.text[contract.len() + 1..])),
+                    None => {
+                        return Some(format!(
+                            "`{}` is not `Contract.function(types)`",
+                            reference.text
+                        ));
+                    }
+                },
+                None => {
+                    return Some(format!(
+                        "`{}` is not `Contract.function(types)`",
+                        reference.text
+                    ));
+                }
+            },
+        };
+        let Some(signatures) = self.contracts.get(contract) else {
+            return Some(format!("No contract `{contract}` in the project"));
+        };
+        match function {
+            Some(signature) if !signatures.iter().any(|known| known == signature) => {
+                Some(format!("`{contract}` has no public function `{signature}`"))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Warnings on the references of the configuration `source` to contracts and functions
+/// missing from `targets`.
+pub fn config_diagnostics(fuzzer: Fuzzer, source: &str, targets: &FuzzTargets) -> Vec<Diagnostic> {
+    references(fuzzer, source)
+        .into_iter()
+        .filter_map(|reference| {
+            Some(Diagnostic {
+                range: reference.range,
+                severity: Some(DiagnosticSeverity::WARNING),
+                code: Some(NumberOrString::String(UNKNOWN_TARGET_CODE.to_string())),
+                source: Some(fuzzer.name().to_string()),
+                message: targets.problem(&reference)?,
+                ..Diagnostic::default()
+            })
+        })
+        .collect()
+}
+
+/// Completions of the reference at `position` in the configuration `source`.
+pub fn config_completions(
+    fuzzer: Fuzzer,
+    source: &str,
+    position: Position,
+    targets: &FuzzTargets,
+) -> Vec<CompletionItem> {
+    let Some(reference) = references(fuzzer, source)
+        .into_iter()
+        .find(|reference| reference.range.start <= position && position <= reference.range.end)
+    else {
+        return vec![];
+    };
+    let item = |label: String, kind, detail: Option<String>| CompletionItem {
+        text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(
+            reference.range,
+            label.clone(),
+        ))),
+        label,
+        kind: Some(kind),
+        detail,
+        ..CompletionItem::default()
+    };
+    match reference.kind {
+        TargetKind::Contract => targets
+            .contracts
+            .keys()
+            .map(|name| item(name.clone(), CompletionItemKind::CLASS, None))
+            .collect(),
+        TargetKind::Function => targets
+            .contracts
+            .iter()
+            .flat_map(|(contract, signatures)| {
+                signatures.iter().map(move |signature| {
+                    item(
+                        format!("{contract}.{signature}"),
+                        CompletionItemKind::FUNCTION,
+                        Some(contract.clone()),
+                    )
+                })
+            })
+            .collect(),
+    }
+}
+
+/// The result of one property in the output of a fuzzer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PropertyResult {
+    pub contract: Option<String>,
+    pub function: String,
+    pub failed: bool,
+    /// The call sequence falsifying the property.
+    pub details: Vec<String>,
+}
+
+/// Reads the output of a fuzzer line by line as it runs.
+#[derive(Debug, Clone)]
+pub struct OutputParser {
+    fuzzer: Fuzzer,
+    pub results: Vec<PropertyResult>,
+    /// Whether lines belong to the last failure.
+    in_failure: bool,
+}
+
+/// `Contract.function(types)`, `function(types)` or `function` split into the contract
+/// and the function name.
+fn split_target(target: &str) -> (Option<String>, String) {
+    let name = target.split('(').next().unwrap_or(target).trim();
+    match name.rsplit_once('.') {
+        Some((contract, function)) => (Some(contract.to_string()), function.to_string()),
+        None => (None, name.to_string()),
+    }
+}
+
+impl OutputParser {
+    pub fn new(fuzzer: Fuzzer) -> Self {
+        Self {
+            fuzzer,
+            results: vec![],
+            in_failure: false,
+        }
+    }
+
+    fn result(&self, line: &str) -> Option<PropertyResult> {
+        let line = line.trim();
+        match self.fuzzer {
+            // `echidna_balance: failed!💥`, `assertion in transfer(address,uint256): passing`
+            Fuzzer::Echidna => {
+                let (target, status) = line.rsplit_once(": ")?;
+                let failed = status.starts_with("failed");
+                if !failed && !status.starts_with("passing") {
+                    return None;
+                }
+                let target = target.strip_prefix("assertion in ").unwrap_or(target);
+                let (contract, function) = split_target(target);
+                Some(PropertyResult {
+                    contract,
+                    function,
+                    failed,
+                    details: vec![],
+                })
+            }
+            // `[FAILED] Property Test: Vault.property_solvent()`
+            Fuzzer::Medusa => {
+                let (failed, rest) = if let Some(rest) = line.strip_prefix("[FAILED]") {
+                    (true, rest)
+                } else {
+                    (false, line.strip_prefix("[PASSED]")?)
+                };
+                let (_, target) = rest.split_once(':')?;
+                let (contract, function) = split_target(target);
+                Some(PropertyResult {
+                    contract,
+                    function,
+                    failed,
+                    details: vec![],
+                })
+            }
+        }
+    }
+
+    /// Read the next line of output. Returns whether a failure was added or extended.
+    pub fn push_line(&mut self, line: &str) -> bool {
+        if let Some(result) = self.result(line) {
+            self.in_failure = result.failed;
+            let failed = result.failed;
+            // A later report of the same property replaces the earlier one
+            self.results.retain(|known| {
+                known.contract != result.contract || known.function != result.function
+            });
+            self.results.push(result);
+            return failed;
+        }
+        if !self.in_failure || line.trim().is_empty() {
+            return false;
+        }
+        match self.results.last_mut() {
+            Some(last) if last.details.len() < MAX_DETAIL_LINES => {
+                last.details.push(line.trim().to_string());
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Locations of the property `function` of `contract` in the project AST `ast_data` of the
+/// project at `root`, or of every property so named when the contract is unknown.
+pub fn property_locations(
+    ast_data: &Value,
+    root: &Path,
+    contract: Option<&str>,
+    function: &str,
+) -> Vec<Location> {
+    let contracts: Vec<String> = match contract {
+        Some(contract) => vec![contract.to_string()],
+        None => {
+            let sources = ast_data.get("sources").unwrap_or(&Value::Null);
+            let mut declaring: Vec<String> = ast::index_nodes(sources)
+                .values()
+                .filter(|node| {
+                    node["nodeType"] == "ContractDefinition"
+                        && node
+                            .get("nodes")
+                            .and_then(Value::as_array)
+                            .into_iter()
+                            .flatten()
+                            .any(|member| member["name"] == function)
+                })
+                .filter_map(|node| Some(node["name"].as_str()?.to_string()))
+                .collect();
+            declaring.sort();
+            declaring.dedup();
+            declaring
+        }
+    };
+    let mut locations: Vec<Location> = contracts
+        .iter()
+        .flat_map(|contract| {
+            test_names::resolve_test_name(
+                ast_data,
+                root,
+                &TestName {
+                    path: None,
+                    contract,
+                    test: Some(function),
+                },
+            )
+        })
+        .collect();
+    locations.dedup();
+    locations
+}
+
+/// Diagnostics on the functions of the failed properties in `results`, by file.
+pub fn violation_diagnostics(
+    fuzzer: Fuzzer,
+    results: &[PropertyResult],
+    ast_data: &Value,
+    root: &Path,
+) -> HashMap<Url, Vec<Diagnostic>> {
+    let mut diagnostics: HashMap<_, Vec<Diagnostic>> = HashMap::new();
+    for result in results.iter().filter(|result| result.failed) {
+        let mut message = format!("`{}` was falsified by {}", result.function, fuzzer.name());
+        for line in &result.details {
+            message.push('\n');
+            message.push_str(line);
+        }
+        for location in
+            property_locations(ast_data, root, result.contract.as_deref(), &result.function)
+        {
+            diagnostics
+                .entry(location.uri)
+                .or_default()
+                .push(Diagnostic {
+                    range: location.range,
+                    severity: Some(DiagnosticSeverity::ERROR),
+                    code: Some(NumberOrString::String(PROPERTY_VIOLATION_CODE.to_string())),
+                    source: Some(fuzzer.name().to_string()),
+                    message: message.clone(),
+                    ..Diagnostic::default()
+                });
+        }
+    }
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const ECHIDNA_CONFIG: &str = "\
+testMode: assertion
+# filterFunctions: [\"Commented.out()\"]
+filterFunctions:
+  - \"Vault.deposit(uint256)\"
+  - Vault.missing()
+deployContracts: [[\"0x10\", \"Token\"]]
+";
+
+    fn targets() -> FuzzTargets {
+        FuzzTargets {
+            contracts: BTreeMap::from([
+                (
+                    "Vault".to_string(),
+                    vec![
+                        "deposit(uint256)".to_string(),
+                        "echidna_solvent()".to_string(),
+                    ],
+                ),
+                ("Token".to_string(), vec![]),
+            ]),
+        }
+    }
+
+    #[test]
+    fn test_config_references() {
+        let references = references(Fuzzer::Echidna, ECHIDNA_CONFIG);
+        let texts: Vec<(&str, TargetKind)> = references
+            .iter()
+            .map(|reference| (reference.text.as_str(), reference.kind))
+            .collect();
+        assert_eq!(
+            texts,
+            [
+                ("Vault.deposit(uint256)", TargetKind::Function),
+                ("Vault.missing()", TargetKind::Function),
+                ("Token", TargetKind::Contract),
+            ]
+        );
+        assert_eq!(references[0].range.start, Position::new(3, 5));
+
+        let medusa = "{\n  \"fuzzing\": {\n    \"targetContracts\": [\"Vault\", \"Pool\"],\n    \"excludeFunctionSignatures\": [\"Vault.deposit(address)\"]\n  }\n}";
+        let messages: Vec<String> = config_diagnostics(Fuzzer::Medusa, medusa, &targets())
+            .into_iter()
+            .map(|diagnostic| diagnostic.message)
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                "No contract `Pool` in the project",
+                "`Vault` has no public function `deposit(address)`"
+            ]
+        );
+
+        let items = config_completions(
+            Fuzzer::Echidna,
+            ECHIDNA_CONFIG,
+            Position::new(4, 6),
+            &targets(),
+        );
+        let labels: Vec<&str> = items.iter().map(|item| item.label.as_str()).collect();
+        assert_eq!(
+            labels,
+            ["Vault.deposit(uint256)", "Vault.echidna_solvent()"]
+        );
+    }
+
+    #[test]
+    fn test_fuzzer_output() {
+        let mut echidna = OutputParser::new(Fuzzer::Echidna);
+        assert!(!echidna.push_line("[2024-01-01 00:00:00.00] Compiling ."));
+        assert!(echidna.push_line("echidna_solvent: failed!💥"));
+        assert!(echidna.push_line("  Call sequence:"));
+        assert!(echidna.push_line("    deposit(1)"));
+        assert!(!echidna.push_line("assertion in deposit(uint256): passing"));
+        assert!(!echidna.push_line("    unrelated"));
+        assert_eq!(
+            echidna.results[0],
+            PropertyResult {
+                contract: None,
+                function: "echidna_solvent".to_string(),
+                failed: true,
+                details: vec!["Call sequence:".to_string(), "deposit(1)".to_string()],
+            }
+        );
+        assert_eq!(echidna.results[1].function, "deposit");
+
+        let mut medusa = OutputParser::new(Fuzzer::Medusa);
+        assert!(medusa.push_line("[FAILED] Property Test: Vault.property_solvent()"));
+        assert_eq!(medusa.results[0].contract.as_deref(), Some("Vault"));
+        assert_eq!(medusa.results[0].function, "property_solvent");
+
+        let source =
+            "contract Vault {\n    function echidna_solvent() public returns (bool) {}\n}\n";
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("Vault.sol"), source).unwrap();
+        let at = |text: &str| format!("{}:{}:0", source.find(text).unwrap(), text.len());
+        let ast_data = ast::mock_ast(
+            "Vault.sol",
+            json!({
+                "nodeType": "SourceUnit",
+                "absolutePath": "Vault.sol",
+                "nodes": [{
+                    "nodeType": "ContractDefinition",
+                    "id": 1,
+                    "name": "Vault",
+                    "nameLocation": at("Vault"),
+                    "linearizedBaseContracts": [1],
+                    "nodes": [{
+                        "nodeType": "FunctionDefinition",
+                        "name": "echidna_solvent",
+                        "nameLocation": at("echidna_solvent")
+                    }]
+                }]
+            }),
+        );
+        let diagnostics =
+            violation_diagnostics(Fuzzer::Echidna, &echidna.results, &ast_data, dir.path());
+        let diagnostics: Vec<&Diagnostic> = diagnostics.values().flatten().collect();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].range.start, Position::new(1, 13));
+        assert_eq!(
+            diagnostics[0].message,
+            "`echidna_solvent` was falsified by echidna\nCall sequence:\ndeposit(1)"
+        );
+    }
+}
@@ -0,0 +1,438 @@
+//! Gas costs of functions, shown on their definitions when `gasEstimates` is enabled.
+//!
+//! The costs come from `forge test --gas-report`, which measures every call the tests
+//! make, averaged per function, and the deployment of each contract. Functions the tests
+//! never call fall back to the compiler's static estimates, which are upper bounds and
+//! unbounded for loops over storage. Both are read per project and refreshed on save.
+
+use serde_json::Value;
+use std::{collections::HashMap, path::Path};
+use tower_lsp::lsp_types::{CodeLens, Command, Position, Range, Url};
+
+use crate::{
+    ast::{self, parse_src},
+    goto::bytes_to_pos,
+    selectors,
+};
+
+/// Gas cost of a function, or of deploying a contract.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GasCost {
+    /// Measured over the calls of the test suite.
+    Measured {
+        calls: u64,
+        min: u64,
+        mean: u64,
+        median: u64,
+        max: u64,
+    },
+    /// Measured deployment of a contract, with the size of its runtime code in bytes.
+    Deployment { gas: u64, size: u64 },
+    /// Static compiler estimate, `None` when it has no upper bound.
+    Estimated(Option<u64>),
+}
+
+impl GasCost {
+    /// One-line summary, the title of the lens.
+    pub fn summary(&self) -> String {
+        match self {
+            Self::Measured {
+                calls: 1, median, ..
+            } => format!("gas: {median} (1 call)"),
+            Self::Measured {
+                calls,
+                min,
+                mean,
+                median,
+                max,
+            } => format!("gas: {mean} avg, {median} median, {min}-{max} ({calls} calls)"),
+            Self::Deployment { gas, size } => format!("deployment gas: {gas} ({size} bytes)"),
+            Self::Estimated(Some(gas)) => format!("gas: ~{gas} (compiler estimate)"),
+            Self::Estimated(None) => "gas: unbounded (compiler estimate)".to_string(),
+        }
+    }
+}
+
+/// Gas costs of one contract.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct ContractGas {
+    deployment: Option<GasCost>,
+    /// By external signature, e.g. `transfer(address,uint256)`.
+    functions: HashMap<String, GasCost>,
+}
+
+/// Gas costs of the contracts of one project.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GasReport {
+    /// By `<path>:<name>`, the path relative to the project root.
+    contracts: HashMap<String, ContractGas>,
+}
+
+/// Key of the contract `name` in the source `path`, relative to `root` when inside it.
+fn contract_key(root: &Path, path: &str, name: &str) -> String {
+    let path = Path::new(path);
+    let path = path.strip_prefix(root).unwrap_or(path);
+    format!("{}:{name}", path.to_string_lossy().replace('\\', "/"))
+}
+
+fn estimate(value: &Value) -> Option<GasCost> {
+    match value.as_str()? {
+        "infinite" => Some(GasCost::Estimated(None)),
+        gas => gas.parse().ok().map(|gas| GasCost::Estimated(Some(gas))),
+    }
+}
+
+impl GasReport {
+    /// The costs measured in the `forge test --gas-report --json` output `report` of the
+    /// project at `root`.
+    pub fn from_gas_report(root: &Path, report: &Value) -> Self {
+        let mut gas = Self::default();
+        for contract in report.as_array().into_iter().flatten() {
+            let Some((path, name)) = contract
+                .get("contract")
+                .and_then(Value::as_str)
+                .and_then(|id| id.rsplit_once(':'))
+            else {
+                continue;
+            };
+            let field = |value: &Value, key: &str| value.get(key).and_then(Value::as_u64);
+            let entry = gas
+                .contracts
+                .entry(contract_key(root, path, name))
+                .or_default();
+            entry.deployment = contract.get("deployment").and_then(|deployment| {
+                Some(GasCost::Deployment {
+                    gas: field(deployment, "gas")?,
+                    size: field(deployment, "size").unwrap_or_default(),
+                })
+            });
+            let functions = contract.get("functions").and_then(Value::as_object);
+            for (signature, cost) in functions.into_iter().flatten() {
+                let (Some(calls), Some(min), Some(mean), Some(median), Some(max)) = (
+                    field(cost, "calls"),
+                    field(cost, "min"),
+                    field(cost, "mean"),
+                    field(cost, "median"),
+                    field(cost, "max"),
+                ) else {
+                    continue;
+                };
+                entry.functions.insert(
+                    signature.clone(),
+                    GasCost::Measured {
+                        calls,
+                        min,
+                        mean,
+                        median,
+                        max,
+                    },
+                );
+            }
+        }
+        gas
+    }
+
+    /// The compiler estimates in the `forge build --json --extra-output evm.gasEstimates`
+    /// output `output` of the project at `root`.
+    pub fn from_build_output(root: &Path, output: &Value) -> Self {
+        let mut gas = Self::default();
+        let files = output.get("contracts").and_then(Value::as_object);
+        for (path, contracts) in files.into_iter().flatten() {
+            for (name, contract) in contracts.as_object().into_iter().flatten() {
+                // Forge lists each contract once per compiler version it was built with
+                let contract = contract
+                    .get(0)
+                    .and_then(|build| build.get("contract"))
+                    .unwrap_or(contract);
+                let Some(estimates) = contract.get("evm").and_then(|evm| evm.get("gasEstimates"))
+                else {
+                    continue;
+                };
+                let functions: HashMap<String, GasCost> = estimates
+                    .get("external")
+                    .and_then(Value::as_object)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|(signature, cost)| Some((signature.clone(), estimate(cost)?)))
+                    .collect();
+                if functions.is_empty() {
+                    continue;
+                }
+                gas.contracts.insert(
+                    contract_key(root, path, name),
+                    ContractGas {
+                        deployment: None,
+                        functions,
+                    },
+                );
+            }
+        }
+        gas
+    }
+
+    /// Add the costs of `other`, which take precedence over those already known.
+    pub fn merge(&mut self, other: Self) {
+        for (key, contract) in other.contracts {
+            let entry = self.contracts.entry(key).or_default();
+            if contract.deployment.is_some() {
+                entry.deployment = contract.deployment;
+            }
+            entry.functions.extend(contract.functions);
+        }
+    }
+
+    /// Cost of the function `name` with the external signature `signature`, if known, of
+    /// the contract `key`. Without one, as for struct parameters, the function is found
+    /// by name when it isn't overloaded.
+    fn function(&self, key: &str, name: &str, signature: Option<&str>) -> Option<&GasCost> {
+        let functions = &self.contracts.get(key)?.functions;
+        if let Some(cost) = signature.and_then(|signature| functions.get(signature)) {
+            return Some(cost);
+        }
+        let prefix = format!("{name}(");
+        let mut overloads = functions
+            .iter()
+            .filter(|(signature, _)| signature.starts_with(&prefix));
+        match (overloads.next(), overloads.next()) {
+            (Some((_, cost)), None) => Some(cost),
+            _ => None,
+        }
+    }
+}
+
+fn name_range(source: &[u8], node: &Value) -> Option<Range> {
+    let src = node.get("nameLocation").or_else(|| node.get("src"))?;
+    let (start, length, _) = parse_src(src.as_str()?)?;
+    Some(Range::new(
+        bytes_to_pos(source, start)?,
+        bytes_to_pos(source, start + length)?,
+    ))
+}
+
+/// The known costs of the contracts and functions defined in `uri`, of the project at
+/// `root`, with the ranges of their names.
+pub fn costs<'a>(
+    report: &'a GasReport,
+    ast_data: &Value,
+    uri: &Url,
+    root: &Path,
+    source_bytes: &[u8],
+) -> Vec<(Range, &'a GasCost)> {
+    let Some(unit) = ast::source_unit(ast_data, uri) else {
+        return vec![];
+    };
+    let Some(index) = ast_data.get("sources").map(ast::index_nodes) else {
+        return vec![];
+    };
+    let Some(path) = unit.get("absolutePath").and_then(Value::as_str) else {
+        return vec![];
+    };
+    let contracts = unit
+        .get("nodes")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter(|node| node.get("nodeType").and_then(Value::as_str) == Some("ContractDefinition"));
+
+    let mut costs = Vec::new();
+    for contract in contracts {
+        let Some(contract_name) = contract.get("name").and_then(Value::as_str) else {
+            continue;
+        };
+        let key = contract_key(root, path, contract_name);
+        if let Some(deployment) = report
+            .contracts
+            .get(&key)
+            .and_then(|gas| gas.deployment.as_ref())
+            && let Some(range) = name_range(source_bytes, contract)
+        {
+            costs.push((range, deployment));
+        }
+        let functions = contract
+            .get("nodes")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter(|node| {
+                node.get("nodeType").and_then(Value::as_str) == Some("FunctionDefinition")
+                    && node.get("kind").and_then(Value::as_str) == Some("function")
+                    && matches!(
+                        node.get("visibility").and_then(Value::as_str),
+                        Some("public" | "external")
+                    )
+            });
+        for function in functions {
+            let Some(name) = function.get("name").and_then(Value::as_str) else {
+                continue;
+            };
+            if let Some(cost) = report.function(
+                &key,
+                name,
+                selectors::canonical_signature(function, &index).as_deref(),
+            ) && let Some(range) = name_range(source_bytes, function)
+            {
+                costs.push((range, cost));
+            }
+        }
+    }
+    costs
+}
+
+/// Lenses with the costs of the contracts and functions defined in `uri`.
+pub fn gas_lenses(
+    report: &GasReport,
+    ast_data: &Value,
+    uri: &Url,
+    root: &Path,
+    source_bytes: &[u8],
+) -> Vec<CodeLens> {
+    costs(report, ast_data, uri, root, source_bytes)
+        .into_iter()
+        .map(|(range, cost)| CodeLens {
+            range,
+            // Without a command to run the lens is a label
+            command: Some(Command {
+                title: cost.summary(),
+                command: String::new(),
+                arguments: None,
+            }),
+            data: None,
+        })
+        .collect()
+}
+
+/// The cost of the contract or function whose name is at `position`, for its hover.
+pub fn gas_hover_section(
+    report: &GasReport,
+    ast_data: &Value,
+    uri: &Url,
+    root: &Path,
+    position: Position,
+    source_bytes: &[u8],
+) -> Option<String> {
+    costs(report, ast_data, uri, root, source_bytes)
+        .into_iter()
+        .find(|(range, _)| range.start <= position && position <= range.end)
+        .map(|(_, cost)| format!("---\n{}", cost.summary()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::path::PathBuf;
+
+    const SOURCE: &str = "\
+contract Vault {
+    function deposit(uint256 amount, address to) external {}
+    function sweep(Token.Order memory order) external {}
+    function drain() external {}
+}
+";
+
+    fn function(name: &str, offset: usize, types: &[Value]) -> Value {
+        json!({
+            "nodeType": "FunctionDefinition",
+            "kind": "function",
+            "visibility": "external",
+            "name": name,
+            "nameLocation": format!("{offset}:{}:0", name.len()),
+            "parameters": {
+                "parameters": types.iter().map(|type_name| json!({
+                    "typeName": type_name
+                })).collect::<Vec<_>>()
+            }
+        })
+    }
+
+    #[test]
+    fn test_gas_costs_of_definitions() {
+        let root = PathBuf::from("/project");
+        let uri = Url::from_file_path("/project/src/Vault.sol").unwrap();
+        let offset = |name: &str| SOURCE.find(name).unwrap();
+        let ast_data = ast::mock_ast(
+            "/project/src/Vault.sol",
+            json!({
+                "absolutePath": "/project/src/Vault.sol",
+                "nodes": [{
+                    "nodeType": "ContractDefinition",
+                    "name": "Vault",
+                    "nameLocation": format!("{}:5:0", offset("Vault")),
+                    "nodes": [
+                        function("deposit", offset("deposit"), &[
+                            json!({ "nodeType": "ElementaryTypeName", "name": "uint" }),
+                            json!({ "nodeType": "ElementaryTypeName", "name": "address" }),
+                        ]),
+                        function("sweep", offset("sweep"), &[json!({
+                            "nodeType": "UserDefinedTypeName",
+                            "referencedDeclaration": 7
+                        })]),
+                        function("drain", offset("drain"), &[]),
+                    ]
+                }]
+            }),
+        );
+
+        let mut report = GasReport::from_build_output(
+            &root,
+            &json!({ "contracts": { "src/Vault.sol": { "Vault": [{ "contract": {
+                "evm": { "gasEstimates": { "external": {
+                    "deposit(uint256,address)": "24500",
+                    "drain()": "infinite",
+                }}}
+            }}]}}}),
+        );
+        report.merge(GasReport::from_gas_report(
+            &root,
+            &json!([{
+                "contract": "src/Vault.sol:Vault",
+                "deployment": { "gas": 156813, "size": 509 },
+                "functions": {
+                    "deposit(uint256,address)": {
+                        "calls": 4, "min": 21000, "mean": 43482, "median": 45000, "max": 65000
+                    },
+                    "sweep((address,uint256))": {
+                        "calls": 1, "min": 30000, "mean": 30000, "median": 30000, "max": 30000
+                    }
+                }
+            }]),
+        ));
+
+        let lenses = gas_lenses(&report, &ast_data, &uri, &root, SOURCE.as_bytes());
+        let titles: Vec<(u32, &str)> = lenses
+            .iter()
+            .map(|lens| {
+                let title = lens.command.as_ref().unwrap().title.as_str();
+                (lens.range.start.line, title)
+            })
+            .collect();
+        assert_eq!(
+            titles,
+            [
+                (0, "deployment gas: 156813 (509 bytes)"),
+                (1, "gas: 43482 avg, 45000 median, 21000-65000 (4 calls)"),
+                // Matched by name, the struct isn't in the AST
+                (2, "gas: 30000 (1 call)"),
+                (3, "gas: unbounded (compiler estimate)"),
+            ]
+        );
+
+        let position = Position::new(1, 15);
+        assert_eq!(
+            gas_hover_section(&report, &ast_data, &uri, &root, position, SOURCE.as_bytes())
+                .as_deref(),
+            Some("---\ngas: 43482 avg, 45000 median, 21000-65000 (4 calls)")
+        );
+        assert!(
+            gas_hover_section(
+                &report,
+                &ast_data,
+                &uri,
+                &root,
+                Position::new(1, 4),
+                SOURCE.as_bytes()
+            )
+            .is_none()
+        );
+    }
+}
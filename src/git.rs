@@ -0,0 +1,91 @@
+//! Branch switch detection.
+//!
+//! A checkout rewrites `.git/HEAD`, which the client watches for the server. Cached ASTs and
+//! diagnostics describe the previous branch, so a changed HEAD triggers a reindex.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+use tower_lsp::lsp_types::Url;
+
+/// Watcher glob for the HEAD file of the workspace repositories.
+pub const HEAD_GLOB: &str = "**/.git/HEAD";
+
+/// Whether `uri` is the HEAD file of a repository.
+pub fn is_head(uri: &Url) -> bool {
+    uri.to_file_path()
+        .is_ok_and(|path| path.ends_with(Path::new(".git").join("HEAD")))
+}
+
+/// HEAD file of the repository at `root`.
+pub fn head_file(root: &Path) -> PathBuf {
+    root.join(".git").join("HEAD")
+}
+
+/// What HEAD points at: the branch name, or the abbreviated commit of a detached HEAD.
+pub fn describe(head: &str) -> &str {
+    match head.strip_prefix("ref:") {
+        Some(reference) => {
+            let reference = reference.trim();
+            reference.strip_prefix("refs/heads/").unwrap_or(reference)
+        }
+        None => head.get(..7).unwrap_or(head),
+    }
+}
+
+/// The last seen contents of each HEAD file.
+#[derive(Debug, Default)]
+pub struct HeadTracker {
+    heads: HashMap<PathBuf, String>,
+}
+
+impl HeadTracker {
+    /// Read the HEAD file at `path`. Returns the new HEAD if it differs from the last one
+    /// seen; the first read of a file only records it.
+    pub fn update(&mut self, path: &Path) -> Option<String> {
+        let head = std::fs::read_to_string(path).ok()?.trim().to_string();
+        match self.heads.insert(path.to_path_buf(), head.clone()) {
+            Some(previous) if previous != head => Some(head),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe_head() {
+        assert_eq!(describe("ref: refs/heads/feature/vault"), "feature/vault");
+        assert_eq!(
+            describe("3f2a9c1d8e7b6a5f4e3d2c1b0a9f8e7d6c5b4a39"),
+            "3f2a9c1"
+        );
+    }
+
+    #[test]
+    fn test_head_tracker_reports_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join(".git")).unwrap();
+        let head = head_file(dir.path());
+        assert!(is_head(&Url::from_file_path(&head).unwrap()));
+        assert!(!is_head(
+            &Url::from_file_path(dir.path().join("HEAD")).unwrap()
+        ));
+
+        let mut tracker = HeadTracker::default();
+        assert_eq!(tracker.update(&head), None);
+        std::fs::write(&head, "ref: refs/heads/main\n").unwrap();
+        assert_eq!(tracker.update(&head), None);
+        assert_eq!(tracker.update(&head), None);
+
+        std::fs::write(&head, "ref: refs/heads/dev\n").unwrap();
+        assert_eq!(
+            tracker.update(&head).as_deref(),
+            Some("ref: refs/heads/dev")
+        );
+        assert_eq!(tracker.update(&head), None);
+    }
+}
@@ -1,7 +1,18 @@
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
 use tower_lsp::lsp_types::{Location, Position, Range, Url};
 
+use crate::{
+    ast,
+    build_info::find_project_root,
+    documents::{LineIndex, Snapshot},
+    paths,
+};
+
 #[derive(Debug, Clone)]
 pub struct NodeInfo {
     pub src: String,
@@ -9,6 +20,76 @@ pub struct NodeInfo {
     pub referenced_declaration: Option<u64>,
     pub node_type: Option<String>,
     pub member_location: Option<String>,
+    /// How a reference uses its declaration. [`Access::Read`] for nodes that aren't
+    /// references.
+    pub access: Access,
+}
+
+/// How a reference uses the declaration it refers to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Access {
+    #[default]
+    Read,
+    /// Assigned to, incremented, decremented or deleted, directly or through an index,
+    /// member or tuple component. Compound assignments like `+=` also read, and count as
+    /// writes.
+    Write,
+    /// Called as a function or invoked as a modifier.
+    Call,
+}
+
+/// Record the access of every node below `node`, which is used with `access`.
+pub fn collect_accesses(node: &Value, access: Access, accesses: &mut HashMap<u64, Access>) {
+    let map = match node {
+        Value::Object(map) => map,
+        Value::Array(items) => {
+            for item in items {
+                collect_accesses(item, access, accesses);
+            }
+            return;
+        }
+        _ => return,
+    };
+    if let Some(id) = map.get("id").and_then(Value::as_u64) {
+        accesses.insert(id, access);
+    }
+
+    let node_type = map
+        .get("nodeType")
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+    let attribute = |key: &str| map.get(key).and_then(Value::as_str);
+    for (key, child) in map {
+        let child_access = match (node_type, key.as_str()) {
+            ("Assignment", "leftHandSide") => Access::Write,
+            ("UnaryOperation", "subExpression")
+                if matches!(attribute("operator"), Some("++" | "--" | "delete")) =>
+            {
+                Access::Write
+            }
+            // Writing `a[i]`, `a.b` or `(a, b)` writes `a` and `b`
+            ("IndexAccess" | "IndexRangeAccess", "baseExpression")
+            | ("MemberAccess", "expression")
+            | ("TupleExpression", "components")
+                if access == Access::Write =>
+            {
+                Access::Write
+            }
+            // Unlike solc, the in-process parse can't tell calls from type conversions and
+            // leaves out the kind
+            ("FunctionCall", "expression")
+                if matches!(attribute("kind"), None | Some("functionCall")) =>
+            {
+                Access::Call
+            }
+            // `f{value: 1}()` calls `f`
+            ("FunctionCallOptions", "expression") => access,
+            ("ModifierInvocation", "modifierName") => Access::Call,
+            _ => Access::Read,
+        };
+        collect_accesses(child, child_access, accesses);
+    }
 }
 
 fn push_if_node_or_array<'a>(tree: &'a Value, key: &str, stack: &mut Vec<&'a Value>) {
@@ -41,14 +122,25 @@ pub fn cache_ids(
                 && let Some(source_file) = first_content.get("source_file")
                 && let Some(ast) = source_file.get("ast")
             {
-                // Get the absolute path for this file
-                let abs_path = ast
-                    .get("absolutePath")
-                    .and_then(|v| v.as_str())
-                    .unwrap_or(path)
-                    .to_string();
-
-                path_to_abs.insert(path.clone(), abs_path.clone());
+                // Get the absolute path for this file, normalized so it matches
+                // editor URIs regardless of platform path spelling
+                let abs_path = paths::normalize_path(
+                    ast.get("absolutePath")
+                        .and_then(|v| v.as_str())
+                        .unwrap_or(path),
+                );
+
+                path_to_abs.insert(paths::normalize_path(path), abs_path.clone());
+                // Also register the symlink-resolved location, which is what editors
+                // report when the file was opened through its real path
+                if let Some(key) = first_content
+                    .get(ast::CANONICAL_PATH)
+                    .and_then(Value::as_str)
+                {
+                    path_to_abs
+                        .entry(key.to_string())
+                        .or_insert_with(|| abs_path.clone());
+                }
 
                 // Initialize the nodes map for this file
                 if !nodes.contains_key(&abs_path) {
@@ -69,10 +161,14 @@ pub fn cache_ids(
                                 .and_then(|v| v.as_str())
                                 .map(|s| s.to_string()),
                             member_location: None,
+                            access: Access::Read,
                         },
                     );
                 }
 
+                let mut accesses = HashMap::new();
+                collect_accesses(ast, Access::Read, &mut accesses);
+
                 let mut stack = vec![ast];
 
                 while let Some(tree) = stack.pop() {
@@ -118,6 +214,7 @@ pub fn cache_ids(
                                 .get("memberLocation")
                                 .and_then(|v| v.as_str())
                                 .map(|s| s.to_string()),
+                            access: accesses.get(&id).copied().unwrap_or_default(),
                         };
 
                         nodes.get_mut(&abs_path).unwrap().insert(id, node_info);
@@ -201,13 +298,8 @@ pub fn goto_bytes(
     uri: &str,
     position: usize,
 ) -> Option<(String, usize)> {
-    let path = match uri.starts_with("file://") {
-        true => &uri[7..],
-        false => uri,
-    };
-
     // Get absolute path for this file
-    let abs_path = path_to_abs.get(path)?;
+    let abs_path = paths::lookup_path(path_to_abs, uri)?;
 
     // Get nodes for the current file only
     let current_file_nodes = nodes.get(abs_path)?;
@@ -248,16 +340,10 @@ pub fn goto_bytes(
     // Get the referenced declaration ID
     let ref_id = current_file_nodes[&chosen_id].referenced_declaration?;
 
-    // Search for the referenced declaration across all files
-    let mut target_node: Option<&NodeInfo> = None;
-    for file_nodes in nodes.values() {
-        if let Some(node) = file_nodes.get(&ref_id) {
-            target_node = Some(node);
-            break;
-        }
-    }
-
-    let node = target_node?;
+    // Search for the referenced declaration across all files, dependencies included
+    let (declaring_file, node) = nodes
+        .iter()
+        .find_map(|(file, file_nodes)| Some((file, file_nodes.get(&ref_id)?)))?;
 
     // Get location from nameLocation or src
     let (location_str, file_id) = if let Some(name_location) = &node.name_location {
@@ -277,50 +363,51 @@ pub fn goto_bytes(
     };
 
     let location: usize = location_str.parse().ok()?;
-    let file_path = id_to_path.get(file_id)?.clone();
+    // The build info names the file; a file it doesn't list, e.g. a library compiled in
+    // another build, is the one the declaration was found in
+    let file_path = id_to_path
+        .get(file_id)
+        .filter(|path| !path.is_empty())
+        .unwrap_or(declaring_file)
+        .clone();
 
     Some((file_path, location))
 }
+/// Byte offset of `position`, whose column counts UTF-16 code units, in `source_bytes`.
 pub fn pos_to_bytes(source_bytes: &[u8], position: Position) -> usize {
     let text = String::from_utf8_lossy(source_bytes);
-    let lines: Vec<&str> = text.lines().collect();
-
-    let mut byte_offset = 0;
-
-    for (line_num, line_text) in lines.iter().enumerate() {
-        if line_num < position.line as usize {
-            byte_offset += line_text.len() + 1; // +1 for newline
-        } else if line_num == position.line as usize {
-            let char_offset = std::cmp::min(position.character as usize, line_text.len());
-            byte_offset += char_offset;
-            break;
-        }
-    }
-
-    byte_offset
+    LineIndex::new(&text).offset(&text, position)
 }
 
+/// Position of the byte `byte_offset` of `source_bytes`, with its column in UTF-16 code
+/// units, or `None` past the end of the text.
 pub fn bytes_to_pos(source_bytes: &[u8], byte_offset: usize) -> Option<Position> {
     let text = String::from_utf8_lossy(source_bytes);
-    let mut curr_offset = 0;
+    (byte_offset <= text.len()).then(|| LineIndex::new(&text).position(&text, byte_offset))
+}
 
-    for (line_num, line_text) in text.lines().enumerate() {
-        let line_bytes = line_text.len() + 1; // +1 for newline
-        if curr_offset + line_bytes > byte_offset {
-            let col = byte_offset - curr_offset;
-            return Some(Position::new(line_num as u32, col as u32));
-        }
-        curr_offset += line_bytes;
+/// Path on disk of a source path reported by forge. Relative paths, like those of
+/// libraries under `lib/`, are relative to the project root forge ran in, or else the
+/// working directory.
+fn resolve_target(file_path: &str, root: Option<&Path>) -> Option<PathBuf> {
+    if let Some(root) = root
+        && Path::new(file_path).is_relative()
+        && root.join(file_path).is_file()
+    {
+        return Some(root.join(file_path));
     }
-
-    None
+    paths::resolve_source_path(file_path)
 }
 
+/// Location of the declaration referenced at `position`, in whichever file of the build
+/// declares it, or `None` when nothing there refers to a declaration. The position is
+/// that of the declaring file's text in `documents`.
 pub fn goto_declaration(
     ast_data: &Value,
     file_uri: &Url,
     position: Position,
     source_bytes: &[u8],
+    documents: &Snapshot,
 ) -> Option<Location> {
     let sources = ast_data.get("sources")?;
     let build_infos = ast_data.get("build_infos")?.as_array()?;
@@ -343,18 +430,15 @@ pub fn goto_declaration(
         byte_position,
     ) {
         // Read the target file to convert byte position to line/column
-        let target_file_path = std::path::Path::new(&file_path);
+        let root = file_uri
+            .to_file_path()
+            .ok()
+            .and_then(|path| find_project_root(&path));
+        let absolute_path = resolve_target(&file_path, root.as_deref())?;
 
-        // Make the path absolute if it's relative
-        let absolute_path = if target_file_path.is_absolute() {
-            target_file_path.to_path_buf()
-        } else {
-            std::env::current_dir().ok()?.join(target_file_path)
-        };
-
-        if let Ok(target_source_bytes) = std::fs::read(&absolute_path)
+        if let Some(target_source_bytes) = documents.read(&absolute_path)
             && let Some(target_position) = bytes_to_pos(&target_source_bytes, location_bytes)
-            && let Ok(target_uri) = Url::from_file_path(&absolute_path)
+            && let Some(target_uri) = paths::path_to_uri(&absolute_path)
         {
             return Some(Location {
                 uri: target_uri,
@@ -366,14 +450,7 @@ pub fn goto_declaration(
         }
     }
 
-    // Fallback to current position
-    Some(Location {
-        uri: file_uri.clone(),
-        range: Range {
-            start: position,
-            end: position,
-        },
-    })
+    None
 }
 #[cfg(test)]
 mod tests {
@@ -443,7 +520,13 @@ mod tests {
         // Test goto declaration on line 22, column 8 (position of "name" in add_vote function,
         // 0-based = line 21)
         let position = Position::new(21, 8);
-        let result = goto_declaration(&ast_data, &file_uri, position, &source_bytes);
+        let result = goto_declaration(
+            &ast_data,
+            &file_uri,
+            position,
+            &source_bytes,
+            &Snapshot::default(),
+        );
 
         assert!(result.is_some());
         let location = result.unwrap();
@@ -470,7 +553,13 @@ mod tests {
 
         // Test goto declaration on "votes" usage (line 23, 0-based = line 22)
         let position = Position::new(22, 25); // Position of "votes" in name.add_one(votes)
-        let result = goto_declaration(&ast_data, &file_uri, position, &source_bytes);
+        let result = goto_declaration(
+            &ast_data,
+            &file_uri,
+            position,
+            &source_bytes,
+            &Snapshot::default(),
+        );
 
         assert!(result.is_some());
         let location = result.unwrap();
@@ -497,7 +586,13 @@ mod tests {
 
         // Test goto declaration on function call "name" in constructor (line 17, 0-based = line 16)
         let position = Position::new(16, 8); // Position of "name" function call
-        let result = goto_declaration(&ast_data, &file_uri, position, &source_bytes);
+        let result = goto_declaration(
+            &ast_data,
+            &file_uri,
+            position,
+            &source_bytes,
+            &Snapshot::default(),
+        );
 
         assert!(result.is_some());
         // The result should point to the function declaration
@@ -521,7 +616,13 @@ mod tests {
 
         // Test goto declaration on "votes" in constructor (line 16, 0-based = line 15)
         let position = Position::new(15, 8); // Position of "votes" in constructor
-        let result = goto_declaration(&ast_data, &file_uri, position, &source_bytes);
+        let result = goto_declaration(
+            &ast_data,
+            &file_uri,
+            position,
+            &source_bytes,
+            &Snapshot::default(),
+        );
 
         assert!(result.is_some());
         let location = result.unwrap();
@@ -548,7 +649,13 @@ mod tests {
 
         // Test goto declaration on immutable variable "SCREAM" (line 10, 0-based = line 9)
         let position = Position::new(9, 20); // Position of "SCREAM"
-        let result = goto_declaration(&ast_data, &file_uri, position, &source_bytes);
+        let result = goto_declaration(
+            &ast_data,
+            &file_uri,
+            position,
+            &source_bytes,
+            &Snapshot::default(),
+        );
 
         assert!(result.is_some());
         let location = result.unwrap();
@@ -571,7 +678,13 @@ mod tests {
 
         // Test goto declaration on a position with no reference (e.g., a comment or whitespace)
         let position = Position::new(0, 0); // Start of file (comment)
-        let result = goto_declaration(&ast_data, &file_uri, position, &source_bytes);
+        let result = goto_declaration(
+            &ast_data,
+            &file_uri,
+            position,
+            &source_bytes,
+            &Snapshot::default(),
+        );
 
         assert!(result.is_some());
         let location = result.unwrap();
@@ -667,8 +780,20 @@ mod tests {
         // Test that goto_declaration and goto_definition return the same result
         let position = Position::new(21, 8); // "name" in add_vote function
 
-        let declaration_result = goto_declaration(&ast_data, &file_uri, position, &source_bytes);
-        let definition_result = goto_declaration(&ast_data, &file_uri, position, &source_bytes); // Same function used for both
+        let declaration_result = goto_declaration(
+            &ast_data,
+            &file_uri,
+            position,
+            &source_bytes,
+            &Snapshot::default(),
+        );
+        let definition_result = goto_declaration(
+            &ast_data,
+            &file_uri,
+            position,
+            &source_bytes,
+            &Snapshot::default(),
+        ); // Same function used for both
 
         assert!(declaration_result.is_some());
         assert!(definition_result.is_some());
@@ -708,7 +833,13 @@ mod tests {
         ];
 
         for (position, description) in test_positions {
-            let result = goto_declaration(&ast_data, &file_uri, position, &source_bytes);
+            let result = goto_declaration(
+                &ast_data,
+                &file_uri,
+                position,
+                &source_bytes,
+                &Snapshot::default(),
+            );
             assert!(
                 result.is_some(),
                 "Failed to find definition for {description}"
@@ -762,27 +893,23 @@ mod tests {
 
         // Create a mock AST structure with nameLocations array
         let mock_sources = json!({
-            "test.sol": [{
-                "source_file": {
-                    "ast": {
-                        "id": 1,
-                        "src": "0:100:0",
-                        "nodeType": "SourceUnit",
-                        "absolutePath": "test.sol",
-                        "nodes": [{
-                            "id": 2,
-                            "src": "10:20:0",
-                            "nodeType": "ContractDefinition",
-                            "nameLocations": ["15:8:0", "25:8:0"]
-                        }, {
-                            "id": 3,
-                            "src": "30:15:0",
-                            "nodeType": "VariableDeclaration",
-                            "nameLocation": "35:5:0"
-                        }]
-                    }
-                }
-            }]
+            "test.sol": ast::mock_source(0, json!({
+                "id": 1,
+                "src": "0:100:0",
+                "nodeType": "SourceUnit",
+                "absolutePath": "test.sol",
+                "nodes": [{
+                    "id": 2,
+                    "src": "10:20:0",
+                    "nodeType": "ContractDefinition",
+                    "nameLocations": ["15:8:0", "25:8:0"]
+                }, {
+                    "id": 3,
+                    "src": "30:15:0",
+                    "nodeType": "VariableDeclaration",
+                    "nameLocation": "35:5:0"
+                }]
+            }))
         });
 
         let (nodes, _path_to_abs) = cache_ids(&mock_sources);
@@ -801,4 +928,183 @@ mod tests {
         let node3 = &test_file_nodes[&3];
         assert_eq!(node3.name_location, Some("35:5:0".to_string()));
     }
+
+    #[test]
+    fn test_goto_bytes_matches_windows_paths() {
+        use serde_json::json;
+
+        // Forge on Windows reports backslash paths with an uppercase drive letter
+        let mock_sources = json!({
+            "C:\\project\\src\\A.sol": ast::mock_source(0, json!({
+                "id": 1,
+                "src": "0:100:0",
+                "nodeType": "SourceUnit",
+                "absolutePath": "C:\\project\\src\\A.sol",
+                "nodes": [{
+                    "id": 2,
+                    "src": "10:5:0",
+                    "nodeType": "VariableDeclaration",
+                    "nameLocation": "10:5:0"
+                }, {
+                    "id": 3,
+                    "src": "40:5:0",
+                    "nodeType": "Identifier",
+                    "referencedDeclaration": 2
+                }]
+            }))
+        });
+        let id_to_path: HashMap<String, String> =
+            [("0".to_string(), "C:\\project\\src\\A.sol".to_string())].into();
+
+        let (nodes, path_to_abs) = cache_ids(&mock_sources);
+        assert!(nodes.contains_key("c:/project/src/A.sol"));
+
+        // Editors send a lowercase, percent-encoded drive letter
+        for uri in [
+            "file:///c%3A/project/src/A.sol",
+            "file:///C:/project/src/A.sol",
+        ] {
+            let result = goto_bytes(&nodes, &path_to_abs, &id_to_path, uri, 42);
+            assert_eq!(
+                result,
+                Some(("C:\\project\\src\\A.sol".to_string(), 10)),
+                "{uri}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_goto_declaration_into_library() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::write(root.join("foundry.toml"), "[profile.default]\n").unwrap();
+        std::fs::create_dir_all(root.join("lib/forge-std/src")).unwrap();
+        std::fs::write(
+            root.join("lib/forge-std/src/Test.sol"),
+            "abstract contract Test {}\n",
+        )
+        .unwrap();
+        let vault = root.join("src/Vault.sol");
+        let source = "import \"forge-std/Test.sol\";\ncontract Vault is Test {}\n";
+
+        // The library was compiled in another build, so this build info doesn't list it
+        let ast_data = serde_json::json!({
+            "sources": {
+                vault.to_str().unwrap(): ast::mock_source(0, serde_json::json!({
+                    "id": 10, "nodeType": "SourceUnit", "src": "0:55:0",
+                    "absolutePath": vault.to_str().unwrap(),
+                    "nodes": [{
+                        "id": 11, "nodeType": "ContractDefinition", "src": "29:25:0",
+                        "baseContracts": [{
+                            "id": 12, "nodeType": "InheritanceSpecifier", "src": "47:4:0",
+                            "baseName": {
+                                "id": 13, "nodeType": "IdentifierPath", "src": "47:4:0",
+                                "referencedDeclaration": 2
+                            }
+                        }]
+                    }]
+                })),
+                "lib/forge-std/src/Test.sol": ast::mock_source(1, serde_json::json!({
+                    "id": 1, "nodeType": "SourceUnit", "src": "0:26:1",
+                    "absolutePath": "lib/forge-std/src/Test.sol",
+                    "nodes": [{
+                        "id": 2, "nodeType": "ContractDefinition", "src": "0:25:1",
+                        "nameLocation": "18:4:1"
+                    }]
+                }))
+            },
+            "build_infos": [{"source_id_to_path": {"0": vault.to_str().unwrap()}}]
+        });
+
+        let uri = Url::from_file_path(&vault).unwrap();
+        let location = goto_declaration(
+            &ast_data,
+            &uri,
+            Position::new(1, 19),
+            source.as_bytes(),
+            &Snapshot::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            location.uri,
+            Url::from_file_path(root.join("lib/forge-std/src/Test.sol")).unwrap()
+        );
+        assert_eq!(location.range.start, Position::new(0, 18));
+
+        // The position is in the library's text as the editor has it
+        let store = crate::documents::DocumentStore::new();
+        let text = "abstract\ncontract Test {}\n".to_string();
+        store.open(location.uri.clone(), text, 1).await;
+        let location = goto_declaration(
+            &ast_data,
+            &uri,
+            Position::new(1, 19),
+            source.as_bytes(),
+            &store.snapshot().await,
+        )
+        .unwrap();
+        assert_eq!(location.range.start, Position::new(1, 9));
+
+        // Nothing to resolve: no result rather than the cursor position
+        assert_eq!(
+            goto_declaration(
+                &ast_data,
+                &uri,
+                Position::new(1, 2),
+                source.as_bytes(),
+                &Snapshot::default()
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_cache_ids_records_access() {
+        let source = "\
+contract Vault {
+    uint256 total;
+    uint256[] amounts;
+
+    modifier only() {
+        _;
+    }
+
+    function add(uint256 x) internal returns (uint256) {
+        return x;
+    }
+
+    function f() public only {
+        total = add(total);
+        amounts[total]++;
+        delete amounts;
+    }
+}
+";
+        let path = "/project/src/Vault.sol";
+        let ast_data = crate::syntax::parse(path, source);
+        let (nodes, _) = cache_ids(ast_data.get("sources").unwrap());
+        let mut accesses: Vec<(u32, &str, Access)> = nodes[path]
+            .values()
+            .filter(|node| node.referenced_declaration.is_some())
+            .filter_map(|node| {
+                let (start, length, _) = crate::ast::parse_src(&node.src)?;
+                let line = bytes_to_pos(source.as_bytes(), start)?.line;
+                Some((line, &source[start..start + length], node.access))
+            })
+            .collect();
+        accesses.sort_by_key(|(line, text, access)| (*line, *text, *access as u8));
+        assert_eq!(
+            accesses,
+            [
+                (9, "x", Access::Read),
+                (12, "only", Access::Call),
+                (13, "add", Access::Call),
+                (13, "total", Access::Read),
+                (13, "total", Access::Write),
+                (14, "amounts", Access::Write),
+                (14, "total", Access::Read),
+                (15, "amounts", Access::Write),
+            ]
+        );
+    }
 }
@@ -0,0 +1,303 @@
+//! Syntax of recent solc releases the in-process parser predates.
+//!
+//! Files using it would fail to parse and lose symbols, folding, highlighting and goto
+//! until a build. Before parsing, each construct of a [`Feature`] the file's pragma admits
+//! is blanked out with spaces, which keeps every offset, and restored on the converted AST
+//! the way solc records it:
+//!
+//! - transient state variables, `uint256 transient locked;`, from 0.8.28, get the
+//!   `transient` storage location;
+//! - storage layout specifiers, `contract Vault layout at 0x1000 {`, from 0.8.29, become
+//!   the contract's `storageLayout`.
+//!
+//! `tstore`, `tload` and `mcopy` in assembly and user-defined operators already parse.
+
+use serde_json::{Value, json};
+
+use crate::{
+    ast::parse_src,
+    header::{Version, VersionReq},
+};
+
+/// Syntax introduced by a solc release.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Feature {
+    TransientStorage,
+    StorageLayout,
+}
+
+impl Feature {
+    /// The first solc release accepting the syntax.
+    pub fn since(self) -> Version {
+        match self {
+            Feature::TransientStorage => Version::new(0, 8, 28),
+            Feature::StorageLayout => Version::new(0, 8, 29),
+        }
+    }
+
+    /// Whether a file whose `pragma solidity` requires `pragma` may use the syntax: the
+    /// range admits its first release or a later one. Files without a pragma may.
+    pub fn enabled(self, pragma: Option<&VersionReq>) -> bool {
+        let since = self.since();
+        let later = (since.minor..since.minor + 5).flat_map(|minor| {
+            let first = if minor == since.minor { since.patch } else { 0 };
+            (first..100).map(move |patch| Version::new(since.major, minor, patch))
+        });
+        pragma.is_none_or(|pragma| later.into_iter().any(|version| pragma.matches(version)))
+    }
+}
+
+/// A token of Solidity code, comments and string literals left out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Token<'a> {
+    Word(usize, &'a str),
+    Punct(usize, u8),
+}
+
+impl Token<'_> {
+    pub(crate) fn start(&self) -> usize {
+        match self {
+            Token::Word(start, _) | Token::Punct(start, _) => *start,
+        }
+    }
+
+    pub(crate) fn is_word(&self, word: &str) -> bool {
+        matches!(self, Token::Word(_, w) if *w == word)
+    }
+}
+
+pub(crate) fn tokens(source: &str) -> Vec<Token<'_>> {
+    let bytes = source.as_bytes();
+    let word = |b: u8| b.is_ascii_alphanumeric() || b == b'_' || b == b'$';
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                i = source[i..].find('\n').map_or(bytes.len(), |end| i + end);
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i = source[i + 2..]
+                    .find("*/")
+                    .map_or(bytes.len(), |end| i + end + 4);
+            }
+            quote @ (b'"' | b'\'') => {
+                i += 1;
+                while i < bytes.len() && bytes[i] != quote {
+                    i += if bytes[i] == b'\\' { 2 } else { 1 };
+                }
+                i += 1;
+            }
+            b if word(b) => {
+                let start = i;
+                while i < bytes.len() && word(bytes[i]) {
+                    i += 1;
+                }
+                tokens.push(Token::Word(start, &source[start..i]));
+            }
+            b if b.is_ascii_whitespace() => i += 1,
+            b => {
+                tokens.push(Token::Punct(i, b));
+                i += 1;
+            }
+        }
+    }
+    tokens
+}
+
+/// The version range of the first `pragma solidity` of `tokens` in `source`.
+fn pragma(source: &str, tokens: &[Token]) -> Option<VersionReq> {
+    let at = tokens
+        .windows(2)
+        .position(|pair| pair[0].is_word("pragma") && pair[1].is_word("solidity"))?;
+    let start = tokens.get(at + 2)?.start();
+    let end = tokens[at + 2..]
+        .iter()
+        .find(|token| matches!(token, Token::Punct(_, b';')))?
+        .start();
+    VersionReq::parse(&source[start..end])
+}
+
+/// A source with the syntax the parser doesn't know blanked out.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Lowered {
+    /// The source to parse, of the same length as the original.
+    pub source: String,
+    /// Offsets of the `transient` locations of state variables.
+    pub transient: Vec<usize>,
+    /// Byte ranges of storage layout specifiers, `layout at` to the end of the expression.
+    pub layouts: Vec<(usize, usize)>,
+}
+
+/// `source` with the syntax of the features its pragma enables blanked out, or `None` when
+/// it uses none.
+pub fn lower(source: &str) -> Option<Lowered> {
+    let tokens = tokens(source);
+    let pragma = pragma(source, &tokens);
+    let mut lowered = Lowered::default();
+
+    if Feature::TransientStorage.enabled(pragma.as_ref()) {
+        // The location sits between the type and the name or a visibility, where a
+        // variable named `transient` would be followed by `;`, `=` or `,`
+        for window in tokens.windows(3) {
+            if window[1].is_word("transient")
+                && matches!(window[0], Token::Word(..) | Token::Punct(_, b']'))
+                && matches!(window[2], Token::Word(..))
+            {
+                lowered.transient.push(window[1].start());
+            }
+        }
+    }
+
+    // The specifier follows the contract name or its bases, up to the body or the bases
+    if Feature::StorageLayout.enabled(pragma.as_ref()) {
+        let mut in_header = false;
+        let mut depth = 0usize;
+        let mut layout: Option<usize> = None;
+        for (i, token) in tokens.iter().enumerate() {
+            match token {
+                Token::Word(_, "contract") => in_header = true,
+                Token::Punct(_, b'(' | b'[') => depth += 1,
+                Token::Punct(_, b')' | b']') => depth = depth.saturating_sub(1),
+                Token::Word(start, "layout")
+                    if in_header
+                        && depth == 0
+                        && tokens.get(i + 1).is_some_and(|next| next.is_word("at")) =>
+                {
+                    layout = Some(*start);
+                }
+                Token::Punct(end, b'{') | Token::Word(end, "is") if depth == 0 => {
+                    if let Some(start) = layout.take() {
+                        let length = source[start..*end].trim_end().len();
+                        lowered.layouts.push((start, start + length));
+                    }
+                    in_header &= token.is_word("is");
+                }
+                _ => {}
+            }
+        }
+    }
+
+    if lowered.transient.is_empty() && lowered.layouts.is_empty() {
+        return None;
+    }
+    let mut bytes = source.as_bytes().to_vec();
+    let blank = |bytes: &mut Vec<u8>, start: usize, end: usize| {
+        for b in &mut bytes[start..end] {
+            // Newlines stay, so lines and columns do too
+            if *b != b'\n' && *b != b'\r' {
+                *b = b' ';
+            }
+        }
+    };
+    for &start in &lowered.transient {
+        blank(&mut bytes, start, start + "transient".len());
+    }
+    for &(start, end) in &lowered.layouts {
+        blank(&mut bytes, start, end);
+    }
+    // Only ASCII is replaced, whole characters included, so the bytes stay UTF-8
+    lowered.source = String::from_utf8(bytes).ok()?;
+    Some(lowered)
+}
+
+fn src_range(node: &Value) -> Option<(usize, usize)> {
+    let (start, length, _) = parse_src(node.get("src")?.as_str()?)?;
+    Some((start, start + length))
+}
+
+fn visit_mut(node: &mut Value, visit: &mut impl FnMut(&mut Value)) {
+    match node {
+        Value::Object(map) => {
+            if map.contains_key("nodeType") {
+                visit(node);
+            }
+            if let Value::Object(map) = node {
+                map.values_mut().for_each(|child| visit_mut(child, visit));
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(|child| visit_mut(child, visit)),
+        _ => {}
+    }
+}
+
+/// Record the syntax blanked out of `lowered` on `ast`, its converted `SourceUnit` in
+/// file `file_id`.
+pub fn restore(ast: &mut Value, lowered: &Lowered, file_id: usize) {
+    visit_mut(ast, &mut |node| {
+        let Some((start, end)) = src_range(node) else {
+            return;
+        };
+        match node["nodeType"].as_str() {
+            Some("VariableDeclaration")
+                if node["stateVariable"] == true
+                    && lowered.transient.iter().any(|&t| start <= t && t < end) =>
+            {
+                node["storageLocation"] = "transient".into();
+            }
+            Some("ContractDefinition") => {
+                if let Some(&(layout_start, layout_end)) = lowered
+                    .layouts
+                    .iter()
+                    .find(|&&(layout, _)| start <= layout && layout < end)
+                {
+                    let length = layout_end - layout_start;
+                    node["storageLayout"] = json!({
+                        "nodeType": "StorageLayoutSpecifier",
+                        "src": format!("{layout_start}:{length}:{file_id}"),
+                    });
+                }
+            }
+            _ => {}
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_feature_enabled() {
+        let req = |text: &str| VersionReq::parse(text).unwrap();
+        assert!(Feature::TransientStorage.enabled(None));
+        assert!(Feature::TransientStorage.enabled(Some(&req("^0.8.20"))));
+        assert!(Feature::TransientStorage.enabled(Some(&req(">=0.8.30"))));
+        assert!(!Feature::TransientStorage.enabled(Some(&req("0.8.26"))));
+        assert!(!Feature::StorageLayout.enabled(Some(&req(">=0.8.20 <0.8.29"))));
+    }
+
+    #[test]
+    fn test_lower() {
+        let source = "pragma solidity ^0.8.29;
+contract Vault is Base layout at 0x1000 + uint256(keccak256(\"vault\")) {
+    uint256 transient locked;
+    bool public transient entered;
+    // uint256 transient comment;
+    function f() public {
+        uint256 transient = 1;
+    }
+}
+";
+        let lowered = lower(source).unwrap();
+        assert_eq!(lowered.source.len(), source.len());
+        assert_eq!(lowered.transient.len(), 2);
+        assert_eq!(lowered.layouts.len(), 1);
+        assert!(lowered.source.contains("uint256           locked;"));
+        let header: Vec<&str> = lowered
+            .source
+            .lines()
+            .nth(1)
+            .unwrap()
+            .split_whitespace()
+            .collect();
+        assert_eq!(header, ["contract", "Vault", "is", "Base", "{"]);
+        assert!(lowered.source.contains("uint256 transient = 1;"));
+
+        assert_eq!(lower("pragma solidity 0.8.20;\ncontract A {}"), None);
+        assert_eq!(
+            lower("pragma solidity 0.8.26;\ncontract A { uint256 transient x; }"),
+            None
+        );
+    }
+}
@@ -0,0 +1,529 @@
+//! Hover for the file header: the SPDX license identifier and the version pragma.
+//!
+//! Neither needs an AST. The license is looked up in a table of the identifiers used in
+//! Solidity projects; the pragma is resolved the way forge auto-detects the compiler, which
+//! picks the newest installed solc matching the range.
+
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+use tower_lsp::lsp_types::{Hover, HoverContents, MarkupContent, MarkupKind, Position, Range};
+
+const SPDX_MARKER: &str = "SPDX-License-Identifier:";
+
+/// License identifiers with their full names.
+const LICENSES: &[(&str, &str)] = &[
+    (
+        "AGPL-3.0-only",
+        "GNU Affero General Public License v3.0 only",
+    ),
+    (
+        "AGPL-3.0-or-later",
+        "GNU Affero General Public License v3.0 or later",
+    ),
+    ("Apache-2.0", "Apache License 2.0"),
+    ("BSD-2-Clause", "BSD 2-Clause \"Simplified\" License"),
+    (
+        "BSD-3-Clause",
+        "BSD 3-Clause \"New\" or \"Revised\" License",
+    ),
+    ("BUSL-1.1", "Business Source License 1.1"),
+    ("CC0-1.0", "Creative Commons Zero v1.0 Universal"),
+    ("GPL-2.0-only", "GNU General Public License v2.0 only"),
+    (
+        "GPL-2.0-or-later",
+        "GNU General Public License v2.0 or later",
+    ),
+    ("GPL-3.0", "GNU General Public License v3.0 only"),
+    ("GPL-3.0-only", "GNU General Public License v3.0 only"),
+    (
+        "GPL-3.0-or-later",
+        "GNU General Public License v3.0 or later",
+    ),
+    ("ISC", "ISC License"),
+    (
+        "LGPL-2.1-only",
+        "GNU Lesser General Public License v2.1 only",
+    ),
+    (
+        "LGPL-2.1-or-later",
+        "GNU Lesser General Public License v2.1 or later",
+    ),
+    (
+        "LGPL-3.0-only",
+        "GNU Lesser General Public License v3.0 only",
+    ),
+    (
+        "LGPL-3.0-or-later",
+        "GNU Lesser General Public License v3.0 or later",
+    ),
+    ("MIT", "MIT License"),
+    ("MPL-2.0", "Mozilla Public License 2.0"),
+    ("Unlicense", "The Unlicense"),
+    ("WTFPL", "Do What The F*ck You Want To Public License"),
+];
+
+/// A solc release version.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+}
+
+impl Version {
+    pub fn new(major: u64, minor: u64, patch: u64) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    /// Parse a full `major.minor.patch` version.
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut parts = text.trim().split('.');
+        let version = Self::new(
+            parts.next()?.parse().ok()?,
+            parts.next()?.parse().ok()?,
+            parts.next()?.parse().ok()?,
+        );
+        parts.next().is_none().then_some(version)
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Exact,
+    Greater,
+    GreaterEq,
+    Less,
+    LessEq,
+    Caret,
+    Tilde,
+}
+
+/// One comparison of a version range. Omitted components (`^0.8`) are `None`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Comparator {
+    op: Op,
+    major: u64,
+    minor: Option<u64>,
+    patch: Option<u64>,
+}
+
+impl Comparator {
+    fn parse(op: Op, text: &str) -> Option<Self> {
+        let mut parts = text.split('.');
+        let component = |part: Option<&str>| -> Option<Option<u64>> {
+            match part {
+                None | Some("x" | "X" | "*") => Some(None),
+                Some(part) => part.parse().ok().map(Some),
+            }
+        };
+        let major = parts.next()?.parse().ok()?;
+        let minor = component(parts.next())?;
+        let patch = minor.and(component(parts.next())?);
+        parts.next().is_none().then_some(Self {
+            op,
+            major,
+            minor,
+            patch,
+        })
+    }
+
+    fn floor(&self) -> Version {
+        Version::new(self.major, self.minor.unwrap_or(0), self.patch.unwrap_or(0))
+    }
+
+    /// First version after the versions a partial version stands for.
+    fn past_partial(&self) -> Version {
+        match (self.minor, self.patch) {
+            (None, _) => Version::new(self.major + 1, 0, 0),
+            (Some(minor), None) => Version::new(self.major, minor + 1, 0),
+            (Some(minor), Some(patch)) => Version::new(self.major, minor, patch + 1),
+        }
+    }
+
+    /// First version a caret range excludes: the next release changing the leftmost
+    /// non-zero component.
+    fn past_caret(&self) -> Version {
+        match (self.major, self.minor, self.patch) {
+            (0, Some(0), Some(patch)) => Version::new(0, 0, patch + 1),
+            (0, Some(0), None) => Version::new(0, 1, 0),
+            (0, Some(minor), _) => Version::new(0, minor + 1, 0),
+            (major, _, _) => Version::new(major + 1, 0, 0),
+        }
+    }
+
+    fn matches(&self, version: Version) -> bool {
+        let floor = self.floor();
+        match self.op {
+            Op::Exact => floor <= version && version < self.past_partial(),
+            Op::Greater => version >= self.past_partial(),
+            Op::GreaterEq => version >= floor,
+            Op::Less => version < floor,
+            Op::LessEq => version < self.past_partial(),
+            Op::Caret => floor <= version && version < self.past_caret(),
+            Op::Tilde => {
+                let past = match self.minor {
+                    Some(minor) => Version::new(self.major, minor + 1, 0),
+                    None => Version::new(self.major + 1, 0, 0),
+                };
+                floor <= version && version < past
+            }
+        }
+    }
+}
+
+/// The version range of a `pragma solidity` directive: alternatives separated by `||`, each
+/// a set of comparators that must all hold.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionReq {
+    alternatives: Vec<Vec<Comparator>>,
+}
+
+impl VersionReq {
+    pub fn parse(text: &str) -> Option<Self> {
+        let alternatives = text
+            .split("||")
+            .map(Self::parse_set)
+            .collect::<Option<Vec<_>>>()?;
+        Some(Self { alternatives })
+    }
+
+    fn parse_set(text: &str) -> Option<Vec<Comparator>> {
+        let tokens: Vec<&str> = text.split_whitespace().collect();
+        if tokens.is_empty() {
+            return None;
+        }
+        // Hyphen range: `0.8.0 - 0.8.19`
+        if let [from, "-", to] = tokens[..] {
+            return Some(vec![
+                Comparator::parse(Op::GreaterEq, from)?,
+                Comparator::parse(Op::LessEq, to)?,
+            ]);
+        }
+
+        let mut comparators = Vec::new();
+        let mut pending: Option<&str> = None;
+        for token in tokens {
+            // Operators may be separated from their version: `>= 0.8.0`
+            let token = match pending.take() {
+                Some(op) => format!("{op}{token}"),
+                None if token.chars().all(|c| "<>=^~".contains(c)) => {
+                    pending = Some(token);
+                    continue;
+                }
+                None => token.to_string(),
+            };
+            if token == "*" {
+                continue;
+            }
+            let split = token
+                .find(|c: char| !"<>=^~".contains(c))
+                .unwrap_or(token.len());
+            let op = match &token[..split] {
+                "" | "=" => Op::Exact,
+                ">" => Op::Greater,
+                ">=" => Op::GreaterEq,
+                "<" => Op::Less,
+                "<=" => Op::LessEq,
+                "^" => Op::Caret,
+                "~" => Op::Tilde,
+                _ => return None,
+            };
+            comparators.push(Comparator::parse(op, &token[split..])?);
+        }
+        pending.is_none().then_some(comparators)
+    }
+
+    pub fn matches(&self, version: Version) -> bool {
+        self.alternatives
+            .iter()
+            .any(|set| set.iter().all(|comparator| comparator.matches(version)))
+    }
+}
+
+/// Directory svm, which forge installs solc with, keeps its releases in.
+fn svm_dir() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("SVM_HOME") {
+        return Some(dir.into());
+    }
+    let home = PathBuf::from(std::env::var_os("HOME")?);
+    let legacy = home.join(".svm");
+    if legacy.is_dir() {
+        return Some(legacy);
+    }
+    let data = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| home.join(".local").join("share"));
+    Some(data.join("svm"))
+}
+
+/// The solc versions installed in `dir`, each in a `<version>/solc-<version>` binary.
+fn installed_in(dir: &Path) -> Vec<Version> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return vec![];
+    };
+    let mut versions: Vec<Version> = entries
+        .filter_map(|entry| {
+            let name = entry.ok()?.file_name().into_string().ok()?;
+            let version = Version::parse(&name)?;
+            dir.join(&name)
+                .join(format!("solc-{name}"))
+                .is_file()
+                .then_some(version)
+        })
+        .collect();
+    versions.sort();
+    versions
+}
+
+/// The solc versions forge has installed, oldest first.
+pub fn installed_solc_versions() -> Vec<Version> {
+    svm_dir().map(|dir| installed_in(&dir)).unwrap_or_default()
+}
+
+/// The solc version `foundry.toml` in `root` pins with `solc` or `solc_version`, if any.
+pub fn pinned_solc(root: &Path) -> Option<Version> {
+    let config = std::fs::read_to_string(root.join("foundry.toml")).ok()?;
+    config.lines().find_map(|line| {
+        let (key, value) = line.split_once('=')?;
+        matches!(key.trim(), "solc" | "solc_version" | "solc-version")
+            .then(|| Version::parse(value.trim().trim_matches(['"', '\''])))
+            .flatten()
+    })
+}
+
+/// Hover for the license identifier or version pragma at `position`, if there is one.
+pub fn header_hover(source: &str, position: Position, root: Option<&Path>) -> Option<Hover> {
+    let line = source.lines().nth(position.line as usize)?;
+    let column = position.character as usize;
+    if let Some(hover) = license_hover(line, position.line, column) {
+        return Some(hover);
+    }
+
+    let (range, req) = pragma_at(line, position.line, column)?;
+    let pinned = root.and_then(pinned_solc);
+    Some(pragma_hover(req, range, &installed_solc_versions(), pinned))
+}
+
+fn markdown_hover(value: String, range: Range) -> Hover {
+    Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value,
+        }),
+        range: Some(range),
+    }
+}
+
+/// Character range of the trimmed `part` of `line`, which starts at byte `start`.
+fn char_range(line: &str, line_number: u32, start: usize, part: &str) -> (usize, Range) {
+    let leading = part.len() - part.trim_start().len();
+    let begin = line[..start + leading].chars().count();
+    let end = begin + part.trim().chars().count();
+    (
+        begin,
+        Range::new(
+            Position::new(line_number, begin as u32),
+            Position::new(line_number, end as u32),
+        ),
+    )
+}
+
+/// Hover for an SPDX license expression on `line` when `column` is on it.
+fn license_hover(line: &str, line_number: u32, column: usize) -> Option<Hover> {
+    let start = line.find(SPDX_MARKER)? + SPDX_MARKER.len();
+    let rest = &line[start..];
+    let expression = rest.split("*/").next().unwrap_or(rest);
+    let (begin, range) = char_range(line, line_number, start, expression);
+    if column < begin || column > range.end.character as usize {
+        return None;
+    }
+
+    let entries: Vec<String> = expression
+        .split(|c: char| c.is_whitespace() || c == '(' || c == ')')
+        .filter(|id| !id.is_empty() && !matches!(*id, "AND" | "OR" | "WITH"))
+        .map(|id| {
+            let id = id.trim_end_matches('+');
+            match LICENSES.iter().find(|(known, _)| *known == id) {
+                Some((_, name)) => {
+                    format!("**{name}** ([`{id}`](https://spdx.org/licenses/{id}.html))")
+                }
+                None if id == "UNLICENSED" => {
+                    "**UNLICENSED**: not licensed, all rights reserved".to_string()
+                }
+                None => format!("`{id}` is not a known SPDX license identifier"),
+            }
+        })
+        .collect();
+    if entries.is_empty() {
+        return None;
+    }
+    Some(markdown_hover(entries.join("\n\n"), range))
+}
+
+/// Range and version requirement of a `pragma solidity` directive on `line` when `column`
+/// is on it.
+fn pragma_at(line: &str, line_number: u32, column: usize) -> Option<(Range, &str)> {
+    let start = line.find("pragma")?;
+    let rest = line[start + "pragma".len()..].trim_start();
+    let req = rest.strip_prefix("solidity")?;
+    let req = req.split(';').next().unwrap_or(req).trim();
+    let statement = line[start..].split_inclusive(';').next()?;
+    let (begin, range) = char_range(line, line_number, start, statement);
+    if column < begin || column > range.end.character as usize {
+        return None;
+    }
+    Some((range, req))
+}
+
+/// Hover explaining which compiler `req` resolves to.
+fn pragma_hover(req: &str, range: Range, installed: &[Version], pinned: Option<Version>) -> Hover {
+    let mut value = format!("**pragma solidity** `{req}`\n\n");
+    let Some(parsed) = VersionReq::parse(req) else {
+        value.push_str("The version range could not be parsed.");
+        return markdown_hover(value, range);
+    };
+
+    let matching: Vec<Version> = installed
+        .iter()
+        .copied()
+        .filter(|version| parsed.matches(*version))
+        .collect();
+    if let Some(pinned) = pinned {
+        value.push_str(&format!("`foundry.toml` pins solc `{pinned}`"));
+        value.push_str(match parsed.matches(pinned) {
+            true => ", which satisfies the range.",
+            false => ", which does not satisfy the range.",
+        });
+    } else {
+        match matching.last() {
+            Some(newest) => value.push_str(&format!(
+                "Forge picks solc `{newest}`, the newest installed version in the range."
+            )),
+            None => value.push_str(
+                "No installed solc version satisfies the range, forge installs the newest \
+                 matching release.",
+            ),
+        }
+    }
+
+    if !matching.is_empty() {
+        let list: Vec<String> = matching.iter().map(|v| format!("`{v}`")).collect();
+        value.push_str(&format!("\n\nInstalled in range: {}", list.join(", ")));
+    }
+    markdown_hover(value, range)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn req(text: &str) -> VersionReq {
+        VersionReq::parse(text).unwrap()
+    }
+
+    fn hover_text(hover: &Hover) -> &str {
+        match &hover.contents {
+            HoverContents::Markup(markup) => &markup.value,
+            _ => panic!("expected markdown"),
+        }
+    }
+
+    #[test]
+    fn test_version_ranges() {
+        let v = |text| Version::parse(text).unwrap();
+        assert!(req("^0.8.20").matches(v("0.8.28")));
+        assert!(!req("^0.8.20").matches(v("0.8.19")));
+        assert!(!req("^0.8.20").matches(v("0.9.0")));
+        assert!(req("^0.8").matches(v("0.8.0")));
+        assert!(req("~0.8.4").matches(v("0.8.30")));
+        assert!(req(">=0.6.2 <0.9.0").matches(v("0.8.0")));
+        assert!(req(">= 0.6.2 < 0.9.0").matches(v("0.6.2")));
+        assert!(!req(">=0.6.2 <0.9.0").matches(v("0.9.0")));
+        assert!(req("0.8.19").matches(v("0.8.19")));
+        assert!(!req("=0.8.19").matches(v("0.8.20")));
+        assert!(req(">0.7").matches(v("0.8.0")));
+        assert!(!req(">0.7").matches(v("0.7.6")));
+        assert!(req("<=0.8").matches(v("0.8.30")));
+        assert!(req("0.8.0 - 0.8.19").matches(v("0.8.19")));
+        assert!(req("^0.7.0 || ^0.8.0").matches(v("0.7.6")));
+        assert!(VersionReq::parse("^abc").is_none());
+        assert!(VersionReq::parse(">=").is_none());
+    }
+
+    #[test]
+    fn test_license_hover() {
+        let line = "// SPDX-License-Identifier: MIT OR Apache-2.0";
+        let hover = license_hover(line, 0, 30).unwrap();
+        assert_eq!(
+            hover.range,
+            Some(Range::new(Position::new(0, 28), Position::new(0, 45)))
+        );
+        let text = hover_text(&hover);
+        assert!(text.contains("**MIT License** ([`MIT`](https://spdx.org/licenses/MIT.html))"));
+        assert!(text.contains("**Apache License 2.0**"));
+        assert!(license_hover(line, 0, 5).is_none());
+
+        let hover = license_hover("/* SPDX-License-Identifier: UNLICENSED */", 0, 30).unwrap();
+        assert!(hover_text(&hover).contains("all rights reserved"));
+    }
+
+    #[test]
+    fn test_pragma_hover_picks_newest_installed() {
+        let line = "pragma solidity ^0.8.20;";
+        let (range, text) = pragma_at(line, 2, 18).unwrap();
+        assert_eq!(text, "^0.8.20");
+        assert_eq!(range, Range::new(Position::new(2, 0), Position::new(2, 24)));
+        assert!(pragma_at("pragma abicoder v2;", 0, 3).is_none());
+
+        let installed = [
+            Version::new(0, 8, 19),
+            Version::new(0, 8, 24),
+            Version::new(0, 8, 28),
+        ];
+        let hover = pragma_hover(text, range, &installed, None);
+        let value = hover_text(&hover);
+        assert!(value.contains("Forge picks solc `0.8.28`"));
+        assert!(value.contains("Installed in range: `0.8.24`, `0.8.28`"));
+
+        let hover = pragma_hover(text, range, &installed, Some(Version::new(0, 8, 19)));
+        assert!(hover_text(&hover).contains("pins solc `0.8.19`, which does not satisfy"));
+
+        let hover = pragma_hover("^0.4.0", range, &installed, None);
+        assert!(hover_text(&hover).contains("forge installs the newest matching release"));
+    }
+
+    #[test]
+    fn test_installed_and_pinned_versions() {
+        let svm = tempfile::tempdir().unwrap();
+        for version in ["0.8.24", "0.8.19"] {
+            let dir = svm.path().join(version);
+            std::fs::create_dir(&dir).unwrap();
+            std::fs::write(dir.join(format!("solc-{version}")), "").unwrap();
+        }
+        std::fs::create_dir(svm.path().join("0.8.30")).unwrap();
+        std::fs::write(svm.path().join(".global-version"), "0.8.24").unwrap();
+        assert_eq!(
+            installed_in(svm.path()),
+            [Version::new(0, 8, 19), Version::new(0, 8, 24)]
+        );
+
+        std::fs::write(
+            svm.path().join("foundry.toml"),
+            "[profile.default]\nsrc = \"src\"\nsolc_version = \"0.8.24\"\n",
+        )
+        .unwrap();
+        assert_eq!(pinned_solc(svm.path()), Some(Version::new(0, 8, 24)));
+    }
+}
@@ -0,0 +1,459 @@
+use serde_json::Value;
+use tower_lsp::lsp_types::{Hover, HoverContents, MarkupContent, MarkupKind, Position, Range, Url};
+
+use crate::{
+    ast::{self, parse_src},
+    goto::bytes_to_pos,
+    paths, selectors,
+};
+
+/// Maximum number of signature lines shown in a hover preview.
+const MAX_SIGNATURE_LINES: usize = 8;
+
+/// Maximum number of doc comment lines read above a declaration.
+const MAX_DOC_LINES: usize = 12;
+
+/// Extract the signature of the declaration at `start..start + length` in `source`.
+///
+/// The signature ends before the body (`{`) or the terminating `;`, whichever comes first.
+pub fn signature_preview(source: &str, start: usize, length: usize) -> Option<String> {
+    let end = (start + length).min(source.len());
+    let declaration = source.get(start..end)?;
+    let signature_end = declaration.find(['{', ';']).unwrap_or(declaration.len());
+
+    let signature: Vec<&str> = declaration[..signature_end]
+        .trim_end()
+        .lines()
+        .take(MAX_SIGNATURE_LINES)
+        .collect();
+    if signature.is_empty() {
+        return None;
+    }
+
+    // Continuation lines keep their indentation relative to the declaration line
+    let indent = source[..start]
+        .rsplit('\n')
+        .next()
+        .map(|prefix| prefix.len() - prefix.trim_start().len())
+        .unwrap_or(0);
+    let lines: Vec<&str> = signature
+        .iter()
+        .enumerate()
+        .map(|(i, line)| {
+            if i == 0 {
+                line
+            } else {
+                strip_indent(line, indent)
+            }
+        })
+        .collect();
+
+    Some(lines.join("\n"))
+}
+
+fn strip_indent(line: &str, indent: usize) -> &str {
+    let whitespace = line.len() - line.trim_start().len();
+    &line[whitespace.min(indent)..]
+}
+
+/// Collect the `///` or `/** */` comment lines immediately above the line containing `start`.
+pub fn doc_comment_above(source: &str, start: usize) -> Vec<String> {
+    let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+    let mut doc = Vec::new();
+    let mut in_block = false;
+
+    for line in source[..line_start].lines().rev() {
+        let trimmed = line.trim();
+        if in_block {
+            doc.push(trimmed.to_string());
+            if trimmed.starts_with("/**") {
+                in_block = false;
+            }
+        } else if trimmed.starts_with("///") {
+            doc.push(trimmed.to_string());
+        } else if trimmed.ends_with("*/") {
+            doc.push(trimmed.to_string());
+            in_block = !trimmed.starts_with("/**");
+        } else {
+            break;
+        }
+
+        if doc.len() >= MAX_DOC_LINES {
+            break;
+        }
+    }
+
+    // A block comment that is not a doc comment (`/* */`) is not shown
+    if in_block {
+        return Vec::new();
+    }
+
+    doc.reverse();
+    doc
+}
+
+/// Strip comment markers (`///`, `/**`, `*/`, leading `*`) from doc comment lines.
+pub fn strip_comment_markers(lines: &[String]) -> String {
+    lines
+        .iter()
+        .map(|line| {
+            let line = line.trim();
+            let line = line.strip_prefix("///").unwrap_or(line);
+            let line = line.strip_prefix("/**").unwrap_or(line);
+            let line = line.strip_suffix("*/").unwrap_or(line).trim();
+            line.strip_prefix('*').unwrap_or(line).trim().to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// NatSpec tags of a doc comment.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct NatSpec {
+    pub title: Option<String>,
+    pub notice: Option<String>,
+    pub dev: Option<String>,
+    pub params: Vec<(String, String)>,
+    pub returns: Vec<String>,
+    /// Contract named by `@inheritdoc`, whose documentation is inherited.
+    pub inheritdoc: Option<String>,
+}
+
+impl NatSpec {
+    /// Parse the text of a doc comment without its comment markers. Untagged text is
+    /// the `@notice`, and lines without a tag continue the previous one.
+    pub fn parse(text: &str) -> Self {
+        let mut natspec = NatSpec::default();
+        let mut tags: Vec<(String, String)> = Vec::new();
+        for line in text.lines().map(str::trim) {
+            if let Some(tagged) = line.strip_prefix('@') {
+                let (tag, body) = tagged
+                    .split_once(char::is_whitespace)
+                    .unwrap_or((tagged, ""));
+                tags.push((tag.to_string(), body.trim().to_string()));
+            } else if let Some((_, body)) = tags.last_mut() {
+                if !line.is_empty() {
+                    if !body.is_empty() {
+                        body.push(' ');
+                    }
+                    body.push_str(line);
+                }
+            } else if !line.is_empty() {
+                tags.push(("notice".to_string(), line.to_string()));
+            }
+        }
+
+        for (tag, body) in tags {
+            match tag.as_str() {
+                "title" => natspec.title = Some(body),
+                "notice" => append(&mut natspec.notice, body),
+                "dev" => append(&mut natspec.dev, body),
+                "param" => {
+                    let (name, description) =
+                        body.split_once(char::is_whitespace).unwrap_or((&body, ""));
+                    natspec
+                        .params
+                        .push((name.to_string(), description.trim().to_string()));
+                }
+                "return" => natspec.returns.push(body),
+                "inheritdoc" => natspec.inheritdoc = Some(body),
+                _ => {}
+            }
+        }
+        natspec
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self == &NatSpec::default()
+    }
+
+    fn to_markdown(&self) -> String {
+        let mut sections = Vec::new();
+        if let Some(title) = &self.title {
+            sections.push(format!("**{title}**"));
+        }
+        sections.extend(self.notice.clone());
+        sections.extend(self.dev.as_ref().map(|dev| format!("*@dev* {dev}")));
+        sections.extend(
+            self.inheritdoc
+                .as_ref()
+                .map(|base| format!("*@inheritdoc* `{base}`")),
+        );
+        if !self.params.is_empty() {
+            let params: Vec<String> = self
+                .params
+                .iter()
+                .map(|(name, description)| format!("- `{name}` — {description}"))
+                .collect();
+            sections.push(format!("**Parameters**\n{}", params.join("\n")));
+        }
+        if !self.returns.is_empty() {
+            let returns: Vec<String> = self.returns.iter().map(|r| format!("- {r}")).collect();
+            sections.push(format!("**Returns**\n{}", returns.join("\n")));
+        }
+        sections.join("\n\n")
+    }
+}
+
+fn append(section: &mut Option<String>, body: String) {
+    match section {
+        Some(text) => {
+            text.push(' ');
+            text.push_str(&body);
+        }
+        None => *section = Some(body),
+    }
+}
+
+/// The doc comment of a declaration: the AST's `documentation`, or the comment above the
+/// declaration in the source for nodes solc does not attach documentation to.
+pub fn natspec(declaration: Option<&Value>, source: &str, start: usize) -> NatSpec {
+    let documentation = declaration.and_then(|node| node.get("documentation"));
+    let text = documentation
+        .and_then(|doc| doc.get("text").or(Some(doc)))
+        .and_then(Value::as_str);
+    match text {
+        Some(text) => NatSpec::parse(text),
+        None => NatSpec::parse(&strip_comment_markers(&doc_comment_above(source, start))),
+    }
+}
+
+/// Type, visibility and mutability of a declaration, as a Markdown line.
+fn type_info(declaration: &Value) -> Option<String> {
+    let mut parts = Vec::new();
+    if let Some(type_string) = declaration
+        .get("typeDescriptions")
+        .and_then(|descriptions| descriptions.get("typeString"))
+        .and_then(Value::as_str)
+    {
+        parts.push(format!("Type: `{type_string}`"));
+    }
+    if let Some(visibility) = declaration.get("visibility").and_then(Value::as_str) {
+        parts.push(format!("Visibility: `{visibility}`"));
+    }
+    let mutability = declaration
+        .get("stateMutability")
+        .or_else(|| declaration.get("mutability"))
+        .and_then(Value::as_str);
+    if let Some(mutability) = mutability.filter(|m| *m != "mutable" && *m != "nonpayable") {
+        parts.push(format!("Mutability: `{mutability}`"));
+    }
+
+    (!parts.is_empty()).then(|| parts.join(" · "))
+}
+
+/// Build a hover for the symbol at `position`: the signature of its declaration, its NatSpec
+/// documentation, its type, visibility and mutability, and the selector or event topic of
+/// functions, errors and events.
+pub fn hover(
+    ast_data: &Value,
+    file_uri: &Url,
+    position: Position,
+    source_bytes: &[u8],
+) -> Option<Hover> {
+    let symbol = ast::symbol_at_position(ast_data, file_uri, position, source_bytes)?;
+    let id_to_path = ast::id_to_path(ast_data)?;
+    let index = ast::index_nodes(ast_data.get("sources")?);
+    let declaration = index.get(&symbol.declaration_id).copied();
+
+    let (start, length, file_id) = parse_src(&symbol.declaration.src)?;
+    let target_path = paths::resolve_source_path(id_to_path.get(file_id)?.as_str()?)?;
+    let target_source = std::fs::read_to_string(target_path).ok()?;
+    let signature = signature_preview(&target_source, start, length)?;
+
+    let mut sections = vec![format!("```solidity\n{signature}\n```")];
+    let natspec = natspec(declaration, &target_source, start);
+    if !natspec.is_empty() {
+        sections.push(natspec.to_markdown());
+    }
+    if let Some(info) = declaration.and_then(type_info) {
+        sections.push(format!("---\n{info}"));
+    }
+    if let Some(hash) =
+        declaration.and_then(|declaration| selectors::signature_hash(declaration, &index))
+    {
+        sections.push(format!("---\n{}", hash.to_markdown()));
+    }
+
+    let (node_start, node_length, _) = parse_src(&symbol.node.src)?;
+    let range = Some(Range {
+        start: bytes_to_pos(source_bytes, node_start)?,
+        end: bytes_to_pos(source_bytes, node_start + node_length)?,
+    });
+
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: sections.join("\n\n"),
+        }),
+        range,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::mock_span;
+    use serde_json::json;
+
+    const SOURCE: &str = r#"contract C {
+    /// Adds one.
+    /// @param x the input
+    function add_one(
+        uint256 x
+    ) public pure returns (uint256) {
+        return x + 1;
+    }
+
+    /**
+     * @notice The answer
+     */
+    uint256 public constant ANSWER = 42;
+
+    function f() public pure returns (uint256) {
+        return add_one(ANSWER);
+    }
+}
+"#;
+
+    fn mock_ast(path: &str) -> Value {
+        let call = SOURCE.find("add_one(ANSWER)").unwrap();
+        let answer = SOURCE.find("ANSWER)").unwrap();
+        ast::mock_ast(
+            path,
+            json!({
+                "id": 1,
+                "src": format!("0:{}:0", SOURCE.len()),
+                "nodeType": "SourceUnit",
+                "absolutePath": path,
+                "nodes": [{
+                    "id": 2,
+                    "src": mock_span(SOURCE, "function add_one", "}\n"),
+                    "nodeType": "FunctionDefinition",
+                    "nameLocation": format!("{}:7:0", SOURCE.find("add_one").unwrap()),
+                    "documentation": {
+                        "nodeType": "StructuredDocumentation",
+                        "text": " Adds one.\n @param x the input"
+                    },
+                    "visibility": "public",
+                    "stateMutability": "pure",
+                    "typeDescriptions": {
+                        "typeString": "function (uint256) pure returns (uint256)"
+                    }
+                }, {
+                    "id": 3,
+                    "src": mock_span(SOURCE, "uint256 public constant", ";"),
+                    "nodeType": "VariableDeclaration",
+                    "visibility": "public",
+                    "mutability": "constant",
+                    "typeDescriptions": { "typeString": "uint256" }
+                }, {
+                    "id": 4,
+                    "src": format!("{call}:7:0"),
+                    "nodeType": "Identifier",
+                    "referencedDeclaration": 2
+                }, {
+                    "id": 5,
+                    "src": format!("{answer}:6:0"),
+                    "nodeType": "Identifier",
+                    "referencedDeclaration": 3
+                }]
+            }),
+        )
+    }
+
+    fn hover_text(hover: Hover) -> String {
+        match hover.contents {
+            HoverContents::Markup(markup) => markup.value,
+            other => panic!("unexpected hover contents: {other:?}"),
+        }
+    }
+
+    fn position_of(needle: &str) -> Position {
+        let offset = SOURCE.find(needle).unwrap();
+        bytes_to_pos(SOURCE.as_bytes(), offset).unwrap()
+    }
+
+    #[test]
+    fn test_hover_previews_function_declaration() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("C.sol");
+        std::fs::write(&path, SOURCE).unwrap();
+        let path_str = path.to_str().unwrap();
+        let ast = mock_ast(path_str);
+        let uri = Url::from_file_path(&path).unwrap();
+
+        let hover = hover(
+            &ast,
+            &uri,
+            position_of("add_one(ANSWER)"),
+            SOURCE.as_bytes(),
+        )
+        .unwrap();
+        let text = hover_text(hover.clone());
+        assert_eq!(
+            text,
+            "```solidity\nfunction add_one(\n    uint256 x\n) public pure returns (uint256)\n```\n\n\
+             Adds one.\n\n\
+             **Parameters**\n- `x` — the input\n\n\
+             ---\nType: `function (uint256) pure returns (uint256)` · Visibility: `public` · \
+             Mutability: `pure`"
+        );
+        assert_eq!(hover.range.unwrap().start, position_of("add_one(ANSWER)"));
+
+        let hover = super::hover(&ast, &uri, position_of("ANSWER)"), SOURCE.as_bytes()).unwrap();
+        assert_eq!(
+            hover_text(hover),
+            "```solidity\nuint256 public constant ANSWER = 42\n```\n\n\
+             The answer\n\n\
+             ---\nType: `uint256` · Visibility: `public` · Mutability: `constant`"
+        );
+    }
+
+    #[test]
+    fn test_hover_outside_symbols_is_none() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("C.sol");
+        std::fs::write(&path, SOURCE).unwrap();
+        let ast = mock_ast(path.to_str().unwrap());
+        let uri = Url::from_file_path(&path).unwrap();
+
+        // Inside the source unit but not on a symbol
+        assert!(hover(&ast, &uri, position_of("return x"), SOURCE.as_bytes()).is_none());
+    }
+
+    #[test]
+    fn test_doc_comment_skips_plain_block_comments() {
+        let source = "/* license */\ncontract A {}\n";
+        let start = source.find("contract").unwrap();
+        assert!(doc_comment_above(source, start).is_empty());
+        assert_eq!(
+            signature_preview(source, start, "contract A {}".len()).as_deref(),
+            Some("contract A")
+        );
+    }
+
+    #[test]
+    fn test_natspec_parse() {
+        let natspec = NatSpec::parse(
+            "@title Vault\n@notice Deposits funds\n  for the caller.\n@dev Reentrancy guarded\n\
+             @param amount the amount\n@param to recipient\n@return shares minted",
+        );
+        assert_eq!(natspec.title.as_deref(), Some("Vault"));
+        assert_eq!(
+            natspec.notice.as_deref(),
+            Some("Deposits funds for the caller.")
+        );
+        assert_eq!(natspec.dev.as_deref(), Some("Reentrancy guarded"));
+        assert_eq!(
+            natspec.params,
+            vec![
+                ("amount".to_string(), "the amount".to_string()),
+                ("to".to_string(), "recipient".to_string())
+            ]
+        );
+        assert_eq!(natspec.returns, vec!["shares minted".to_string()]);
+
+        assert!(NatSpec::parse("").is_empty());
+    }
+}
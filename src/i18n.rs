@@ -0,0 +1,625 @@
+//! Localization of the messages the server writes itself.
+//!
+//! The language is the client's `locale` from `initialize`, unless the `locale` setting
+//! overrides it, and English when neither names a supported language. Messages are
+//! translated on their way to the client rather than where they are written: the analyses
+//! keep producing English, which baselines and tests compare, and the catalog maps each
+//! English message, gettext style, to its translations. Catalog patterns stand for the
+//! message with `{}` for each variable part, and translations refer to those parts as `{0}`,
+//! `{1}`, ... in whatever order the language needs. Messages without a pattern are left in
+//! English, so the catalog can grow one rule at a time; it covers the server's analyses —
+//! their diagnostics, related information and fixes — and the titles of their rules.
+
+use tower_lsp::lsp_types::{
+    Diagnostic, Hover, HoverContents, MarkupContent, MarkupKind, NumberOrString, Position, Range,
+};
+
+use crate::{
+    code_actions::Fix, interface_sync, mutability, natspec, selectors, storage_layout,
+    struct_literals, suppressions, unused, unused_returns, utils,
+};
+
+/// A language of the catalog.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Locale {
+    #[default]
+    En,
+    De,
+    Es,
+}
+
+impl Locale {
+    /// The language of the BCP 47 tag `tag`, such as `de-CH` or `es`, by its primary
+    /// subtag.
+    pub fn from_tag(tag: &str) -> Option<Self> {
+        let language = tag.split(['-', '_']).next()?.to_ascii_lowercase();
+        match language.as_str() {
+            "en" => Some(Self::En),
+            "de" => Some(Self::De),
+            "es" => Some(Self::Es),
+            _ => None,
+        }
+    }
+
+    /// The language of the first of `tags` naming a supported one, English otherwise.
+    pub fn negotiate<'a>(tags: impl IntoIterator<Item = Option<&'a str>>) -> Self {
+        tags.into_iter()
+            .flatten()
+            .find_map(Self::from_tag)
+            .unwrap_or_default()
+    }
+}
+
+/// An English message pattern of a rule and its translations.
+struct Entry {
+    code: &'static str,
+    en: &'static str,
+    de: &'static str,
+    es: &'static str,
+}
+
+const fn entry(code: &'static str, en: &'static str, de: &'static str, es: &'static str) -> Entry {
+    Entry { code, en, de, es }
+}
+
+/// Titles of the rules.
+const RULES: &[Entry] = &[
+    entry(
+        selectors::SELECTOR_CLASH_CODE,
+        "Function selector clash",
+        "Kollision von Funktionsselektoren",
+        "Colisión de selectores de función",
+    ),
+    entry(
+        selectors::SELECTOR_COLLISION_CODE,
+        "Selector shared across an inheritance hierarchy",
+        "Selektor innerhalb einer Vererbungshierarchie mehrfach vergeben",
+        "Selector compartido en una jerarquía de herencia",
+    ),
+    entry(
+        storage_layout::STORAGE_GAP_CODE,
+        "Storage gap size",
+        "Größe der Speicherlücke",
+        "Tamaño del hueco de almacenamiento",
+    ),
+    entry(
+        unused_returns::UNUSED_RETURN_CODE,
+        "Ignored return value",
+        "Ignorierter Rückgabewert",
+        "Valor de retorno ignorado",
+    ),
+    entry(
+        natspec::NATSPEC_CODE,
+        "Missing NatSpec tag",
+        "Fehlendes NatSpec-Tag",
+        "Etiqueta NatSpec ausente",
+    ),
+    entry(
+        natspec::NATSPEC_PARAM_CODE,
+        "`@param` naming no parameter",
+        "`@param` ohne passenden Parameter",
+        "`@param` que no nombra ningún parámetro",
+    ),
+    entry(
+        mutability::MUTABILITY_CODE,
+        "Function could be `view` or `pure`",
+        "Funktion könnte `view` oder `pure` sein",
+        "La función podría ser `view` o `pure`",
+    ),
+    entry(
+        mutability::STATICCALL_CODE,
+        "Call with STATICCALL from a view function",
+        "STATICCALL-Aufruf aus einer view-Funktion",
+        "Llamada con STATICCALL desde una función view",
+    ),
+    entry(
+        interface_sync::INTERFACE_MISSING_CODE,
+        "Function missing from its interface",
+        "Funktion fehlt in ihrem Interface",
+        "Función ausente de su interfaz",
+    ),
+    entry(
+        interface_sync::INTERFACE_STALE_CODE,
+        "Interface declaration not implemented",
+        "Interface-Deklaration nicht implementiert",
+        "Declaración de interfaz no implementada",
+    ),
+    entry(
+        struct_literals::STRUCT_MISSING_FIELDS_CODE,
+        "Struct literal missing fields",
+        "Struct-Literal mit fehlenden Feldern",
+        "Literal de struct con campos ausentes",
+    ),
+    entry(
+        struct_literals::STRUCT_FIELD_ORDER_CODE,
+        "Struct literal fields out of order",
+        "Felder eines Struct-Literals in falscher Reihenfolge",
+        "Campos de un literal de struct desordenados",
+    ),
+    entry(
+        unused::UNUSED_IMPORT_CODE,
+        "Unused import",
+        "Ungenutzter Import",
+        "Importación sin usar",
+    ),
+    entry(
+        unused::UNUSED_VARIABLE_CODE,
+        "Unused local variable",
+        "Ungenutzte lokale Variable",
+        "Variable local sin usar",
+    ),
+    entry(
+        unused::UNUSED_PARAMETER_CODE,
+        "Unused parameter",
+        "Ungenutzter Parameter",
+        "Parámetro sin usar",
+    ),
+];
+
+/// Messages of the rules, their related information and the titles of their fixes. The
+/// patterns of a code are tried in order, so longer ones sharing a prefix come first.
+const MESSAGES: &[Entry] = &[
+    entry(
+        selectors::SELECTOR_CLASH_CODE,
+        "selector {} of `{}` clashes with {}",
+        "Selektor {0} von `{1}` kollidiert mit {2}",
+        "el selector {0} de `{1}` colisiona con {2}",
+    ),
+    entry(
+        selectors::SELECTOR_COLLISION_CODE,
+        "functions {} of `{}` share selector {}",
+        "die Funktionen {0} von `{1}` teilen sich den Selektor {2}",
+        "las funciones {0} de `{1}` comparten el selector {2}",
+    ),
+    entry(
+        selectors::SELECTOR_COLLISION_CODE,
+        "`{}` has selector {}",
+        "`{0}` hat den Selektor {1}",
+        "`{0}` tiene el selector {1}",
+    ),
+    entry(
+        storage_layout::STORAGE_GAP_CODE,
+        "`{}` uses {} storage slots, leaving no room for `{}` within {} reserved slots",
+        "`{0}` belegt {1} Speicherslots und lässt innerhalb von {3} reservierten Slots keinen \
+         Platz für `{2}`",
+        "`{0}` usa {1} slots de almacenamiento y no deja espacio para `{2}` dentro de {3} slots \
+         reservados",
+    ),
+    entry(
+        storage_layout::STORAGE_GAP_CODE,
+        "`{}` uses {} storage slots and a {}-slot `{}`, {} in total; resize `{}` to {} to keep \
+         {} reserved slots",
+        "`{0}` belegt {1} Speicherslots und ein `{3}` mit {2} Slots, insgesamt {4}; ändere die \
+         Größe von `{5}` auf {6}, um {7} reservierte Slots zu behalten",
+        "`{0}` usa {1} slots de almacenamiento y un `{3}` de {2} slots, {4} en total; cambia el \
+         tamaño de `{5}` a {6} para mantener {7} slots reservados",
+    ),
+    entry(
+        unused_returns::UNUSED_RETURN_CODE,
+        "the return value of `{}` is ignored: returns ({})",
+        "der Rückgabewert von `{0}` wird ignoriert: gibt ({1}) zurück",
+        "se ignora el valor de retorno de `{0}`: devuelve ({1})",
+    ),
+    entry(
+        natspec::NATSPEC_CODE,
+        "`{}` is missing `{}`",
+        "`{0}` fehlt `{1}`",
+        "a `{0}` le falta `{1}`",
+    ),
+    entry(
+        natspec::NATSPEC_CODE,
+        "Add NatSpec stub",
+        "NatSpec-Gerüst hinzufügen",
+        "Añadir un esqueleto de NatSpec",
+    ),
+    entry(
+        natspec::NATSPEC_PARAM_CODE,
+        "`{}` has no parameter `{}`",
+        "`{0}` hat keinen Parameter `{1}`",
+        "`{0}` no tiene ningún parámetro `{1}`",
+    ),
+    entry(
+        natspec::NATSPEC_PARAM_CODE,
+        "Rename to `{}`",
+        "In `{0}` umbenennen",
+        "Renombrar a `{0}`",
+    ),
+    entry(
+        mutability::MUTABILITY_CODE,
+        "`{}` could be declared `{}`, with the 1 declaration it overrides",
+        "`{0}` könnte als `{1}` deklariert werden, zusammen mit der Deklaration, die es \
+         überschreibt",
+        "`{0}` podría declararse `{1}`, junto con la declaración que sobrescribe",
+    ),
+    entry(
+        mutability::MUTABILITY_CODE,
+        "`{}` could be declared `{}`, with the {} declarations it overrides",
+        "`{0}` könnte als `{1}` deklariert werden, zusammen mit den {2} Deklarationen, die es \
+         überschreibt",
+        "`{0}` podría declararse `{1}`, junto con las {2} declaraciones que sobrescribe",
+    ),
+    entry(
+        mutability::MUTABILITY_CODE,
+        "`{}` could be declared `{}`",
+        "`{0}` könnte als `{1}` deklariert werden",
+        "`{0}` podría declararse `{1}`",
+    ),
+    entry(
+        mutability::MUTABILITY_CODE,
+        "Declare `{}` as `{}`",
+        "`{0}` als `{1}` deklarieren",
+        "Declarar `{0}` como `{1}`",
+    ),
+    entry(
+        mutability::STATICCALL_CODE,
+        "`{}` is called with STATICCALL from the view function `{}`, and reverts if the called \
+         contract modifies state",
+        "`{0}` wird aus der view-Funktion `{1}` mit STATICCALL aufgerufen und macht einen \
+         Revert, wenn der aufgerufene Vertrag den Zustand ändert",
+        "`{0}` se llama con STATICCALL desde la función view `{1}` y revierte si el contrato \
+         llamado modifica el estado",
+    ),
+    entry(
+        interface_sync::INTERFACE_MISSING_CODE,
+        "`{}` is not declared in `{}`",
+        "`{0}` ist in `{1}` nicht deklariert",
+        "`{0}` no está declarada en `{1}`",
+    ),
+    entry(
+        interface_sync::INTERFACE_MISSING_CODE,
+        "`{}` is missing `{}` of `{}`",
+        "in `{0}` fehlt `{1}` von `{2}`",
+        "a `{0}` le falta `{1}` de `{2}`",
+    ),
+    entry(
+        interface_sync::INTERFACE_MISSING_CODE,
+        "Add `{}` to `{}`",
+        "`{0}` zu `{1}` hinzufügen",
+        "Añadir `{0}` a `{1}`",
+    ),
+    entry(
+        interface_sync::INTERFACE_STALE_CODE,
+        "`{}` is not implemented by `{}`",
+        "`{0}` wird von `{1}` nicht implementiert",
+        "`{1}` no implementa `{0}`",
+    ),
+    entry(
+        interface_sync::INTERFACE_STALE_CODE,
+        "`{}` does not implement `{}` of `{}`",
+        "`{0}` implementiert `{1}` von `{2}` nicht",
+        "`{0}` no implementa `{1}` de `{2}`",
+    ),
+    entry(
+        interface_sync::INTERFACE_STALE_CODE,
+        "Remove `{}` from `{}`",
+        "`{0}` aus `{1}` entfernen",
+        "Quitar `{0}` de `{1}`",
+    ),
+    entry(
+        struct_literals::STRUCT_MISSING_FIELDS_CODE,
+        "`{}` is missing field {}",
+        "in `{0}` fehlt das Feld {1}",
+        "a `{0}` le falta el campo {1}",
+    ),
+    entry(
+        struct_literals::STRUCT_MISSING_FIELDS_CODE,
+        "`{}` is missing fields {}",
+        "in `{0}` fehlen die Felder {1}",
+        "a `{0}` le faltan los campos {1}",
+    ),
+    entry(
+        struct_literals::STRUCT_MISSING_FIELDS_CODE,
+        "Add missing fields",
+        "Fehlende Felder hinzufügen",
+        "Añadir los campos que faltan",
+    ),
+    entry(
+        struct_literals::STRUCT_FIELD_ORDER_CODE,
+        "fields of `{}` are not in declaration order: {}",
+        "die Felder von `{0}` stehen nicht in Deklarationsreihenfolge: {1}",
+        "los campos de `{0}` no siguen el orden de declaración: {1}",
+    ),
+    entry(
+        struct_literals::STRUCT_FIELD_ORDER_CODE,
+        "Reorder fields to declaration order",
+        "Felder in Deklarationsreihenfolge bringen",
+        "Reordenar los campos según su declaración",
+    ),
+    entry(
+        unused::UNUSED_IMPORT_CODE,
+        "`{}` is imported but never used",
+        "`{0}` wird importiert, aber nie verwendet",
+        "`{0}` se importa pero nunca se usa",
+    ),
+    entry(
+        unused::UNUSED_IMPORT_CODE,
+        "nothing `{}` declares is used",
+        "nichts, was `{0}` deklariert, wird verwendet",
+        "no se usa nada de lo que declara `{0}`",
+    ),
+    entry(
+        unused::UNUSED_IMPORT_CODE,
+        "Remove unused import of `{}`",
+        "Ungenutzten Import von `{0}` entfernen",
+        "Quitar la importación sin usar de `{0}`",
+    ),
+    entry(
+        unused::UNUSED_VARIABLE_CODE,
+        "the local variable `{}` is assigned but never read",
+        "der lokalen Variable `{0}` wird ein Wert zugewiesen, sie wird aber nie gelesen",
+        "a la variable local `{0}` se le asigna un valor pero nunca se lee",
+    ),
+    entry(
+        unused::UNUSED_VARIABLE_CODE,
+        "the local variable `{}` is never used",
+        "die lokale Variable `{0}` wird nie verwendet",
+        "la variable local `{0}` nunca se usa",
+    ),
+    entry(
+        unused::UNUSED_VARIABLE_CODE,
+        "Remove unused variable `{}`",
+        "Ungenutzte Variable `{0}` entfernen",
+        "Quitar la variable sin usar `{0}`",
+    ),
+    entry(
+        unused::UNUSED_PARAMETER_CODE,
+        "the parameter `{}` is never used",
+        "der Parameter `{0}` wird nie verwendet",
+        "el parámetro `{0}` nunca se usa",
+    ),
+    entry(
+        unused::UNUSED_PARAMETER_CODE,
+        "Remove the name of unused parameter `{}`",
+        "Namen des ungenutzten Parameters `{0}` entfernen",
+        "Quitar el nombre del parámetro sin usar `{0}`",
+    ),
+];
+
+impl Entry {
+    fn text(&self, locale: Locale) -> &'static str {
+        match locale {
+            Locale::En => self.en,
+            Locale::De => self.de,
+            Locale::Es => self.es,
+        }
+    }
+}
+
+/// The parts of `message` standing for the `{}` of `pattern`, if it matches it. Each part
+/// is as short as the rest of the pattern allows.
+fn captures<'a>(pattern: &str, message: &'a str) -> Option<Vec<&'a str>> {
+    let mut literals = pattern.split("{}");
+    let mut rest = message.strip_prefix(literals.next()?)?;
+    let literals: Vec<&str> = literals.collect();
+    let mut parts = Vec::new();
+    for (i, literal) in literals.iter().enumerate() {
+        let end = if i + 1 == literals.len() {
+            // The last part runs up to the final literal, at the end of the message
+            rest.strip_suffix(literal)?.len()
+        } else {
+            rest.find(literal)?
+        };
+        parts.push(&rest[..end]);
+        rest = &rest[end + literal.len()..];
+    }
+    (literals.is_empty() && rest.is_empty()
+        || !literals.is_empty() && parts.iter().all(|part| !part.is_empty()))
+    .then_some(parts)
+}
+
+/// `template` with `{0}`, `{1}`, ... replaced by `parts`.
+fn fill(template: &str, parts: &[&str]) -> String {
+    let mut text = template.to_string();
+    // From the last, so `{1}` isn't replaced within `{10}`
+    for (i, part) in parts.iter().enumerate().rev() {
+        text = text.replace(&format!("{{{i}}}"), part);
+    }
+    text
+}
+
+/// `message` of a finding of the rule `code` in `locale`, if the catalog translates it.
+pub fn translate(code: &str, message: &str, locale: Locale) -> Option<String> {
+    if locale == Locale::En {
+        return None;
+    }
+    MESSAGES
+        .iter()
+        .filter(|entry| entry.code == code)
+        .find_map(|entry| Some(fill(entry.text(locale), &captures(entry.en, message)?)))
+}
+
+/// Title of the rule `code` in `locale`.
+pub fn rule_title(code: &str, locale: Locale) -> Option<&'static str> {
+    RULES
+        .iter()
+        .find(|entry| entry.code == code)
+        .map(|entry| entry.text(locale))
+}
+
+/// Hover of the rule code at `position` of a suppression comment of `source`: its title in
+/// `locale`.
+pub fn rule_hover(source: &str, position: Position, locale: Locale) -> Option<Hover> {
+    let line = source.lines().nth(position.line as usize)?;
+    let offset = utils::position_to_byte_offset(line, 0, position.character);
+    let (code, (start, end)) = suppressions::code_at(line, offset)?;
+    let title = rule_title(code, locale)?;
+    let character = |offset: usize| utils::byte_offset_to_position(line, offset).1;
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: format!("**{title}** (`{code}`)"),
+        }),
+        range: Some(Range::new(
+            Position::new(position.line, character(start)),
+            Position::new(position.line, character(end)),
+        )),
+    })
+}
+
+/// Translate the messages, related information and fix titles of the server's own
+/// diagnostics among `diagnostics` to `locale`.
+pub fn localize_diagnostics(diagnostics: &mut [Diagnostic], locale: Locale) {
+    if locale == Locale::En {
+        return;
+    }
+    for diagnostic in diagnostics {
+        let Some(NumberOrString::String(code)) = diagnostic.code.clone() else {
+            continue;
+        };
+        if diagnostic.source.as_deref() != Some("forge-lsp") {
+            continue;
+        }
+        if let Some(message) = translate(&code, &diagnostic.message, locale) {
+            diagnostic.message = message;
+        }
+        for related in diagnostic.related_information.iter_mut().flatten() {
+            if let Some(message) = translate(&code, &related.message, locale) {
+                related.message = message;
+            }
+        }
+        if let Some(mut fix) = Fix::from_diagnostic(diagnostic)
+            && let Some(title) = translate(&code, &fix.title, locale)
+        {
+            fix.title = title;
+            diagnostic.data = fix.to_data();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower_lsp::lsp_types::TextEdit;
+
+    #[test]
+    fn test_locale_from_tag() {
+        assert_eq!(Locale::from_tag("de-CH"), Some(Locale::De));
+        assert_eq!(Locale::from_tag("es_419"), Some(Locale::Es));
+        assert_eq!(Locale::from_tag("EN"), Some(Locale::En));
+        assert_eq!(Locale::from_tag("ja"), None);
+        assert_eq!(
+            Locale::negotiate([None, Some("ja"), Some("es")]),
+            Locale::Es
+        );
+        assert_eq!(Locale::negotiate([None, Some("ja")]), Locale::En);
+    }
+
+    #[test]
+    fn test_translate_messages() {
+        assert_eq!(
+            translate(
+                unused::UNUSED_IMPORT_CODE,
+                "`Vault` is imported but never used",
+                Locale::De
+            )
+            .as_deref(),
+            Some("`Vault` wird importiert, aber nie verwendet")
+        );
+        // Parts can move
+        assert_eq!(
+            translate(
+                interface_sync::INTERFACE_STALE_CODE,
+                "`withdraw` is not implemented by `Vault`",
+                Locale::Es
+            )
+            .as_deref(),
+            Some("`Vault` no implementa `withdraw`")
+        );
+        // Longer patterns sharing a prefix win
+        assert_eq!(
+            translate(
+                mutability::MUTABILITY_CODE,
+                "`total` could be declared `view`, with the 2 declarations it overrides",
+                Locale::Es
+            )
+            .as_deref(),
+            Some("`total` podría declararse `view`, junto con las 2 declaraciones que sobrescribe")
+        );
+        assert_eq!(
+            translate(
+                mutability::MUTABILITY_CODE,
+                "`total` could be declared `pure`",
+                Locale::De
+            )
+            .as_deref(),
+            Some("`total` könnte als `pure` deklariert werden")
+        );
+        // English stays as it is
+        assert_eq!(
+            translate(
+                interface_sync::INTERFACE_MISSING_CODE,
+                "`IVault` is missing `deposit(uint256)` of `Vault`",
+                Locale::De
+            )
+            .as_deref(),
+            Some("in `IVault` fehlt `deposit(uint256)` von `Vault`")
+        );
+        assert_eq!(
+            translate(
+                unused::UNUSED_IMPORT_CODE,
+                "`Vault` is imported but never used",
+                Locale::En
+            ),
+            None
+        );
+        assert_eq!(
+            translate(unused::UNUSED_IMPORT_CODE, "something else", Locale::De),
+            None
+        );
+        assert_eq!(
+            rule_title(unused::UNUSED_PARAMETER_CODE, Locale::Es),
+            Some("Parámetro sin usar")
+        );
+        for code in crate::analysis::ANALYSIS_CODES
+            .iter()
+            .chain([&natspec::NATSPEC_PARAM_CODE])
+        {
+            assert!(rule_title(code, Locale::De).is_some(), "{code}");
+        }
+    }
+
+    #[test]
+    fn test_rule_hover() {
+        let source = "contract A {\n    f(); // forge-lsp-disable-line unused-return\n}\n";
+        let hover = rule_hover(source, Position::new(1, 40), Locale::De).unwrap();
+        let HoverContents::Markup(markup) = hover.contents else {
+            panic!("no markdown");
+        };
+        assert_eq!(
+            markup.value,
+            "**Ignorierter Rückgabewert** (`unused-return`)"
+        );
+        assert_eq!(
+            hover.range,
+            Some(Range::new(Position::new(1, 35), Position::new(1, 48)))
+        );
+        assert!(rule_hover(source, Position::new(1, 20), Locale::De).is_none());
+    }
+
+    #[test]
+    fn test_localize_diagnostics() {
+        let range = Range::new(Position::new(0, 0), Position::new(0, 5));
+        let diagnostic = |source: &str| Diagnostic {
+            range,
+            code: Some(NumberOrString::String(
+                unused::UNUSED_VARIABLE_CODE.to_string(),
+            )),
+            source: Some(source.to_string()),
+            message: "the local variable `x` is never used".to_string(),
+            data: Fix::new(
+                "Remove unused variable `x`",
+                vec![TextEdit::new(range, String::new())],
+            )
+            .to_data(),
+            ..Diagnostic::default()
+        };
+        let mut diagnostics = [diagnostic("forge-lsp"), diagnostic("forge lint")];
+        localize_diagnostics(&mut diagnostics, Locale::Es);
+        assert_eq!(diagnostics[0].message, "la variable local `x` nunca se usa");
+        assert_eq!(
+            Fix::from_diagnostic(&diagnostics[0]).unwrap().title,
+            "Quitar la variable sin usar `x`"
+        );
+        assert_eq!(diagnostics[1], diagnostic("forge lint"));
+    }
+}
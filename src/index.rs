@@ -0,0 +1,777 @@
+//! Project-wide background index.
+//!
+//! When the server starts it finds the Foundry projects in the workspace and compiles each
+//! one once with `forge build --ast`. The AST of a whole project covers every source in
+//! it, so requests on any indexed file are answered from that AST, including references
+//! that cross files, without compiling the file again. Saving a file drops its project
+//! from the index until a background rebuild replaces it.
+//!
+//! The index also remembers a hash of every source, so a file that is moved without
+//! changing keeps its entries under the new path instead of triggering a rebuild.
+
+use serde_json::Value;
+use std::{
+    collections::{HashMap, HashSet},
+    hash::{DefaultHasher, Hash, Hasher},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tokio::sync::RwLock;
+use tower_lsp::lsp_types::{DocumentHighlight, LinkedEditingRanges, Location, Position, Url};
+
+use crate::{
+    annotations::SKIPPED_DIRS,
+    ast,
+    build_info::find_project_root,
+    documents::Snapshot,
+    index_cache, paths,
+    references::ReferenceIndex,
+    runner::{Runner, RunnerError},
+};
+
+/// Disk location of the source `path` reported by forge for the project at `root`. Forge
+/// reports paths relative to the root it ran in.
+fn source_path(root: &Path, path: &str) -> PathBuf {
+    root.join(path)
+}
+
+fn source_key(root: &Path, path: &str) -> String {
+    paths::canonical_key(&source_path(root, path).to_string_lossy())
+}
+
+/// Hash of a file's contents, to recognize a moved file at its new path.
+pub fn content_hash(contents: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    contents.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Replace every string and object key of `value` found in `replacements`.
+fn replace_strings(value: &mut Value, replacements: &HashMap<String, String>) {
+    match value {
+        Value::String(string) => {
+            if let Some(replacement) = replacements.get(string.as_str()) {
+                *string = replacement.clone();
+            }
+        }
+        Value::Array(items) => items
+            .iter_mut()
+            .for_each(|item| replace_strings(item, replacements)),
+        Value::Object(map) => {
+            let renamed: Vec<String> = map
+                .keys()
+                .filter(|key| replacements.contains_key(key.as_str()))
+                .cloned()
+                .collect();
+            for key in renamed {
+                if let Some(entry) = map.remove(&key) {
+                    map.insert(replacements[&key].clone(), entry);
+                }
+            }
+            map.values_mut()
+                .for_each(|child| replace_strings(child, replacements));
+        }
+        _ => {}
+    }
+}
+
+fn uri_key(uri: &Url) -> Option<String> {
+    let path = uri.to_file_path().ok()?;
+    Some(paths::canonical_key(&path.to_string_lossy()))
+}
+
+/// One compiled project: its AST, the files it covers and the import graph between them.
+pub struct ProjectIndex {
+    pub root: PathBuf,
+    pub ast: Arc<Value>,
+    reference_index: Option<ReferenceIndex>,
+    /// Disk location of every source, by canonical key.
+    files: HashMap<String, PathBuf>,
+    /// Canonical keys of the files each source imports directly.
+    imports: HashMap<String, HashSet<String>>,
+    /// Content hash of every source when the project was compiled, by canonical key.
+    hashes: HashMap<String, u64>,
+}
+
+impl ProjectIndex {
+    pub fn new(root: PathBuf, ast_data: Value) -> Self {
+        let mut project = Self::with_hashes(root, ast_data, HashMap::new());
+        project.hashes = project
+            .files
+            .iter()
+            .filter_map(|(key, path)| Some((key.clone(), content_hash(&std::fs::read(path).ok()?))))
+            .collect();
+        project
+    }
+
+    fn with_hashes(root: PathBuf, mut ast_data: Value, hashes: HashMap<String, u64>) -> Self {
+        ast::resolve_source_keys(&mut ast_data, &root);
+        let mut files = HashMap::new();
+        let mut imports = HashMap::new();
+        if let Some(sources) = ast_data.get("sources").and_then(Value::as_object) {
+            for (path, contents) in sources {
+                let Some(unit) = contents
+                    .get(0)
+                    .and_then(|c| c.get("source_file")?.get("ast"))
+                else {
+                    continue;
+                };
+                let path = unit
+                    .get("absolutePath")
+                    .and_then(Value::as_str)
+                    .unwrap_or(path);
+                let key = source_key(&root, path);
+                files.insert(key.clone(), source_path(&root, path));
+
+                let mut imported = HashSet::new();
+                ast::walk(unit, &mut |node| {
+                    if node.get("nodeType").and_then(Value::as_str) == Some("ImportDirective")
+                        && let Some(target) = node.get("absolutePath").and_then(Value::as_str)
+                    {
+                        imported.insert(source_key(&root, target));
+                    }
+                });
+                imports.insert(key, imported);
+            }
+        }
+
+        Self {
+            reference_index: ReferenceIndex::new(&ast_data),
+            root,
+            ast: Arc::new(ast_data),
+            files,
+            imports,
+            hashes,
+        }
+    }
+
+    /// Content hash of every source when the project was compiled, by canonical key.
+    pub fn hashes(&self) -> &HashMap<String, u64> {
+        &self.hashes
+    }
+
+    /// Hash of the contents `uri` was compiled from.
+    pub fn content_hash(&self, uri: &Url) -> Option<u64> {
+        self.hashes.get(&uri_key(uri)?).copied()
+    }
+
+    /// This project with the source at `from` moved to `to`, or `None` if the project does
+    /// not include `from`. Paths in the AST keep their spelling: relative to the root when
+    /// forge reported them so, absolute otherwise.
+    pub fn renamed(&self, from: &Url, to: &Url) -> Option<Self> {
+        let (from_key, to_path) = (uri_key(from)?, to.to_file_path().ok()?);
+        if !self.files.contains_key(&from_key) {
+            return None;
+        }
+        let relative = to_path
+            .strip_prefix(&self.root)
+            .map(|path| path.to_string_lossy().replace('\\', "/"))
+            .ok();
+        let absolute = to_path.to_string_lossy().into_owned();
+
+        // Every spelling of `from` forge used, from the source entries
+        let mut replacements = HashMap::new();
+        for (path, contents) in self.ast.get("sources")?.as_object()? {
+            let absolute_path = contents
+                .get(0)
+                .and_then(|c| c.get("source_file")?.get("ast")?.get("absolutePath"))
+                .and_then(Value::as_str);
+            for spelling in std::iter::once(path.as_str()).chain(absolute_path) {
+                if source_key(&self.root, spelling) != from_key {
+                    continue;
+                }
+                let replacement = match &relative {
+                    Some(relative) if Path::new(spelling).is_relative() => relative.clone(),
+                    _ => absolute.clone(),
+                };
+                replacements.insert(spelling.to_string(), replacement);
+            }
+        }
+
+        let mut ast_data = (*self.ast).clone();
+        replace_strings(&mut ast_data, &replacements);
+        let mut hashes = self.hashes.clone();
+        if let Some(hash) = hashes.remove(&from_key) {
+            hashes.insert(uri_key(to)?, hash);
+        }
+        Some(Self::with_hashes(self.root.clone(), ast_data, hashes))
+    }
+
+    /// Whether the project's AST includes `uri`.
+    pub fn contains(&self, uri: &Url) -> bool {
+        uri_key(uri).is_some_and(|key| self.files.contains_key(&key))
+    }
+
+    /// Number of sources in the project, dependencies included.
+    pub fn file_count(&self) -> usize {
+        self.files.len()
+    }
+
+    /// The files that import `uri`, directly or through other imports.
+    pub fn importers(&self, uri: &Url) -> Vec<Url> {
+        let Some(key) = uri_key(uri) else {
+            return vec![];
+        };
+        let mut found = HashSet::from([key.clone()]);
+        let mut queue = vec![key.clone()];
+        while let Some(current) = queue.pop() {
+            for (file, imported) in &self.imports {
+                if imported.contains(&current) && found.insert(file.clone()) {
+                    queue.push(file.clone());
+                }
+            }
+        }
+        found.remove(&key);
+
+        let mut importers: Vec<Url> = found
+            .iter()
+            .filter_map(|file| paths::path_to_uri(self.files.get(file)?))
+            .collect();
+        importers.sort();
+        importers
+    }
+
+    /// Locations of the symbol at `position` in `uri` and of every reference to it across
+    /// the project, in the texts of `documents`.
+    pub fn references(
+        &self,
+        uri: &Url,
+        position: Position,
+        source_bytes: &[u8],
+        documents: &Snapshot,
+    ) -> Vec<Location> {
+        let Some(index) = &self.reference_index else {
+            return vec![];
+        };
+        match index.target_at(uri, position, source_bytes) {
+            Some(target_node_id) => index.locations(target_node_id, documents),
+            None => vec![],
+        }
+    }
+
+    /// Ranges of the symbol at `position` in `uri` to edit together, if they are all in
+    /// `uri`.
+    pub fn linked_editing_ranges(
+        &self,
+        uri: &Url,
+        position: Position,
+        source_bytes: &[u8],
+    ) -> Option<LinkedEditingRanges> {
+        self.reference_index
+            .as_ref()?
+            .linked_editing_ranges(uri, position, source_bytes)
+    }
+
+    /// Highlights of the symbol at `position` within `uri`.
+    pub fn highlights(
+        &self,
+        uri: &Url,
+        position: Position,
+        source_bytes: &[u8],
+    ) -> Vec<DocumentHighlight> {
+        match &self.reference_index {
+            Some(index) => index.highlights(uri, position, source_bytes),
+            None => vec![],
+        }
+    }
+}
+
+/// The Foundry projects under `folder`: every directory with a `foundry.toml`, skipping
+/// hidden directories, dependencies and build output. A folder inside a project yields
+/// that project.
+pub fn discover_projects(folder: &Path) -> Vec<PathBuf> {
+    let mut projects = Vec::new();
+    let mut dirs = vec![folder.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        if dir.join("foundry.toml").is_file() {
+            projects.push(dir.clone());
+        }
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if entry.file_type().is_ok_and(|kind| kind.is_dir())
+                && !name.starts_with('.')
+                && !SKIPPED_DIRS.contains(&name.as_ref())
+            {
+                dirs.push(entry.path());
+            }
+        }
+    }
+
+    if projects.is_empty() {
+        projects.extend(find_project_root(folder));
+    }
+    projects.sort();
+    projects
+}
+
+/// The indexed projects of the workspace, by root.
+pub struct WorkspaceIndex {
+    compiler: Arc<dyn Runner>,
+    projects: RwLock<HashMap<PathBuf, Arc<ProjectIndex>>>,
+    /// Projects dropped by [`WorkspaceIndex::invalidate`] since their last successful
+    /// build, for navigation while they don't compile.
+    stale: RwLock<HashMap<PathBuf, Arc<ProjectIndex>>>,
+}
+
+impl WorkspaceIndex {
+    pub fn new(compiler: Arc<dyn Runner>) -> Self {
+        Self {
+            compiler,
+            projects: RwLock::new(HashMap::new()),
+            stale: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Compile the project at `root`, replace its index entry and save it to the project's
+    /// cache directory.
+    pub async fn build(&self, root: &Path) -> Result<Arc<ProjectIndex>, RunnerError> {
+        let root_str = root.to_str().ok_or(RunnerError::InvalidUrl)?;
+        let ast_data = self.compiler.project_ast(root_str).await?;
+        let project = Arc::new(ProjectIndex::new(root.to_path_buf(), ast_data));
+        self.projects
+            .write()
+            .await
+            .insert(root.to_path_buf(), project.clone());
+        self.stale.write().await.remove(root);
+
+        let saved = project.clone();
+        let result = tokio::task::spawn_blocking(move || index_cache::save(&saved)).await;
+        if let Ok(Err(e)) = result {
+            tracing::warn!("failed to save the index of {}: {e}", root.display());
+        }
+        Ok(project)
+    }
+
+    /// The index of the project at `root` saved by an earlier run of the server, if the
+    /// project has not changed since, and a fresh build otherwise.
+    pub async fn load_or_build(&self, root: &Path) -> Result<Arc<ProjectIndex>, RunnerError> {
+        let cached_root = root.to_path_buf();
+        let cached = tokio::task::spawn_blocking(move || index_cache::load(&cached_root))
+            .await
+            .ok()
+            .flatten();
+        let Some(project) = cached else {
+            return self.build(root).await;
+        };
+        let project = Arc::new(project);
+        self.projects
+            .write()
+            .await
+            .insert(root.to_path_buf(), project.clone());
+        self.stale.write().await.remove(root);
+        Ok(project)
+    }
+
+    /// The index of the project at `root`, compiling it if it is not indexed.
+    pub async fn get_or_build(&self, root: &Path) -> Result<Arc<ProjectIndex>, RunnerError> {
+        if let Some(project) = self.projects.read().await.get(root) {
+            return Ok(project.clone());
+        }
+        self.build(root).await
+    }
+
+    /// The indexed project whose AST includes `uri`: the innermost one containing the file
+    /// when projects are nested. Projects that list the file as a dependency only are used
+    /// when no project owns it.
+    pub async fn project_for(&self, uri: &Url) -> Option<Arc<ProjectIndex>> {
+        let path = uri.to_file_path().ok()?;
+        let projects = self.projects.read().await;
+        let mut candidates = projects.values().filter(|project| project.contains(uri));
+        candidates
+            .clone()
+            .filter(|project| path.starts_with(&project.root))
+            .max_by_key(|project| project.root.components().count())
+            .or_else(|| candidates.next())
+            .cloned()
+    }
+
+    /// Locations of the symbol at `position` in `uri` and of every reference to it in each
+    /// indexed project including the file. A file shared by several projects, like a
+    /// library of a monorepo, gets the references of all of them, its own project's first.
+    pub async fn references(
+        &self,
+        uri: &Url,
+        position: Position,
+        source_bytes: &[u8],
+        documents: &Snapshot,
+    ) -> Vec<Location> {
+        let owner = self.project_for(uri).await;
+        let mut projects: Vec<_> = self
+            .projects()
+            .await
+            .into_iter()
+            .filter(|project| project.contains(uri))
+            .collect();
+        projects.sort_by_key(|project| {
+            owner
+                .as_ref()
+                .is_none_or(|owner| !Arc::ptr_eq(owner, project))
+        });
+
+        let mut locations: Vec<Location> = Vec::new();
+        for project in projects {
+            for location in project.references(uri, position, source_bytes, documents) {
+                if !locations.contains(&location) {
+                    locations.push(location);
+                }
+            }
+        }
+        locations
+    }
+
+    /// Every indexed project, ordered by root.
+    pub async fn projects(&self) -> Vec<Arc<ProjectIndex>> {
+        let mut projects: Vec<_> = self.projects.read().await.values().cloned().collect();
+        projects.sort_by(|a, b| a.root.cmp(&b.root));
+        projects
+    }
+
+    /// Drop the projects made stale by a change to `uri`: the ones including it and the
+    /// one it belongs to. Returns their roots so they can be rebuilt.
+    pub async fn invalidate(&self, uri: &Url) -> Vec<PathBuf> {
+        let owner = uri
+            .to_file_path()
+            .ok()
+            .and_then(|path| find_project_root(&path));
+        let mut projects = self.projects.write().await;
+        let stale: Vec<PathBuf> = projects
+            .values()
+            .filter(|project| project.contains(uri) || owner.as_ref() == Some(&project.root))
+            .map(|project| project.root.clone())
+            .collect();
+        let mut last_built = self.stale.write().await;
+        for root in &stale {
+            if let Some(project) = projects.remove(root) {
+                last_built.insert(root.clone(), project);
+            }
+        }
+        stale
+    }
+
+    /// The indexed project of `uri`, or else the last successful build of a project that
+    /// included the file or owns it, kept while the project doesn't compile. Its offsets
+    /// are those of the files when it was built.
+    pub async fn last_built_for(&self, uri: &Url) -> Option<Arc<ProjectIndex>> {
+        if let Some(project) = self.project_for(uri).await {
+            return Some(project);
+        }
+        let path = uri.to_file_path().ok()?;
+        let stale = self.stale.read().await;
+        stale
+            .values()
+            .filter(|project| project.contains(uri) || path.starts_with(&project.root))
+            .max_by_key(|project| project.root.components().count())
+            .cloned()
+    }
+
+    /// Drop the project at `root`. Returns whether it was indexed.
+    pub async fn remove(&self, root: &Path) -> bool {
+        self.stale.write().await.remove(root);
+        self.projects.write().await.remove(root).is_some()
+    }
+
+    /// Hash of the contents `uri` was indexed with, if an indexed project includes it.
+    pub async fn content_hash(&self, uri: &Url) -> Option<u64> {
+        self.project_for(uri).await?.content_hash(uri)
+    }
+
+    /// Move the entries of `from` to `to` in every project including it, without
+    /// compiling. Returns whether any project had the file.
+    pub async fn rename(&self, from: &Url, to: &Url) -> bool {
+        let mut projects = self.projects.write().await;
+        let mut moved = false;
+        for project in projects.values_mut() {
+            if let Some(renamed) = project.renamed(from, to) {
+                *project = Arc::new(renamed);
+                moved = true;
+            }
+        }
+        moved
+    }
+
+    /// Drop every project. Returns how many were indexed.
+    pub async fn clear(&self) -> usize {
+        self.stale.write().await.clear();
+        let mut projects = self.projects.write().await;
+        let dropped = projects.len();
+        projects.clear();
+        dropped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::BuildOutput;
+    use serde_json::json;
+    use tower_lsp::async_trait;
+
+    fn unit(path: &str, imports: &[&str]) -> Value {
+        let nodes: Vec<Value> = imports
+            .iter()
+            .map(|target| json!({ "nodeType": "ImportDirective", "absolutePath": target }))
+            .collect();
+        ast::mock_source(
+            0,
+            json!({ "nodeType": "SourceUnit", "absolutePath": path, "nodes": nodes }),
+        )
+    }
+
+    /// Serves a fixed project AST whose sources live under the requested root.
+    struct ProjectRunner;
+
+    #[async_trait]
+    impl Runner for ProjectRunner {
+        async fn build(&self, _: &str) -> Result<BuildOutput, RunnerError> {
+            Ok(BuildOutput::default())
+        }
+
+        async fn lint(&self, _: &str) -> Result<Value, RunnerError> {
+            Ok(Value::Null)
+        }
+
+        async fn ast(&self, root: &str) -> Result<Value, RunnerError> {
+            let path = |name: &str| format!("{root}/src/{name}");
+            Ok(json!({
+                "sources": {
+                    "src/Token.sol": unit(&path("Token.sol"), &[]),
+                    "src/Vault.sol": unit(&path("Vault.sol"), &[&path("Token.sol")]),
+                    "src/Router.sol": unit(&path("Router.sol"), &[&path("Vault.sol")]),
+                    "src/Math.sol": unit(&path("Math.sol"), &[])
+                }
+            }))
+        }
+    }
+
+    /// Serves a given AST for each project root.
+    struct SourcesRunner(HashMap<String, Value>);
+
+    #[async_trait]
+    impl Runner for SourcesRunner {
+        async fn build(&self, _: &str) -> Result<BuildOutput, RunnerError> {
+            Ok(BuildOutput::default())
+        }
+
+        async fn lint(&self, _: &str) -> Result<Value, RunnerError> {
+            Ok(Value::Null)
+        }
+
+        async fn ast(&self, root: &str) -> Result<Value, RunnerError> {
+            self.0.get(root).cloned().ok_or(RunnerError::EmptyOutput)
+        }
+    }
+
+    /// Point the accesses of `member` in `value` at the declaration `id`, as solc does for
+    /// members the in-process parse leaves unresolved.
+    fn resolve_member(value: &mut Value, member: &str, id: u64) {
+        match value {
+            Value::Object(fields) => {
+                if fields.get("nodeType").and_then(Value::as_str) == Some("MemberAccess")
+                    && fields.get("memberName").and_then(Value::as_str) == Some(member)
+                {
+                    fields.insert("referencedDeclaration".to_string(), id.into());
+                }
+                for field in fields.values_mut() {
+                    resolve_member(field, member, id);
+                }
+            }
+            Value::Array(items) => {
+                for item in items {
+                    resolve_member(item, member, id);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Fails every compilation.
+    struct FailingRunner;
+
+    #[async_trait]
+    impl Runner for FailingRunner {
+        async fn build(&self, _: &str) -> Result<BuildOutput, RunnerError> {
+            Err(RunnerError::InvalidUrl)
+        }
+
+        async fn lint(&self, _: &str) -> Result<Value, RunnerError> {
+            Err(RunnerError::InvalidUrl)
+        }
+
+        async fn ast(&self, _: &str) -> Result<Value, RunnerError> {
+            Err(RunnerError::InvalidUrl)
+        }
+    }
+
+    #[test]
+    fn test_discover_projects_skips_dependencies() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        for project in ["", "packages/core", "lib/forge-std", ".github/fixture"] {
+            std::fs::create_dir_all(root.join(project)).unwrap();
+            std::fs::write(root.join(project).join("foundry.toml"), "").unwrap();
+        }
+
+        assert_eq!(
+            discover_projects(root),
+            [root.to_path_buf(), root.join("packages/core")]
+        );
+        // A folder inside a project resolves to that project
+        std::fs::create_dir_all(root.join("packages/core/src")).unwrap();
+        assert_eq!(
+            discover_projects(&root.join("packages/core/src")),
+            [root.join("packages/core")]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_index_lookup_importers_and_invalidation() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        std::fs::write(root.join("foundry.toml"), "").unwrap();
+        let uri = |name: &str| Url::from_file_path(root.join("src").join(name)).unwrap();
+
+        let index = WorkspaceIndex::new(Arc::new(ProjectRunner));
+        let project = index.build(&root).await.unwrap();
+        assert_eq!(project.file_count(), 4);
+        assert!(
+            index
+                .project_for(&uri("Vault.sol"))
+                .await
+                .is_some_and(|found| found.root == root)
+        );
+        assert!(index.project_for(&uri("Missing.sol")).await.is_none());
+        assert_eq!(
+            project.importers(&uri("Token.sol")),
+            [uri("Router.sol"), uri("Vault.sol")]
+        );
+        assert!(project.importers(&uri("Math.sol")).is_empty());
+
+        // A new file is not in the AST, but it belongs to the project
+        assert_eq!(
+            index.invalidate(&uri("New.sol")).await,
+            std::slice::from_ref(&root)
+        );
+        assert!(index.projects().await.is_empty());
+        // Until it builds again, navigation falls back on the dropped build
+        assert!(
+            index
+                .last_built_for(&uri("New.sol"))
+                .await
+                .is_some_and(|found| Arc::ptr_eq(&found, &project))
+        );
+        index.get_or_build(&root).await.unwrap();
+        assert_eq!(index.clear().await, 1);
+
+        // The build was saved, and a new server loads it without compiling
+        let index = WorkspaceIndex::new(Arc::new(FailingRunner));
+        assert!(index.build(&root).await.is_err());
+        let project = index.load_or_build(&root).await.unwrap();
+        assert_eq!(project.file_count(), 4);
+        assert!(index.project_for(&uri("Vault.sol")).await.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_references_across_projects() {
+        const INTERFACE: &str = "interface IVault {\n    function deposit() external;\n}\n";
+        let dir = tempfile::tempdir().unwrap();
+        let (core, periphery) = (dir.path().join("core"), dir.path().join("periphery"));
+        let interface_path = core.join("src/IVault.sol").to_string_lossy().into_owned();
+        let caller = |contract: &str| {
+            format!(
+                "import \"{interface_path}\";\ncontract {contract} {{\n    function f(IVault vault) public {{\n        vault.deposit();\n    }}\n}}\n"
+            )
+        };
+        // Locations are computed from the files on disk
+        let write = |path: &Path, source: &str| {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, source).unwrap();
+        };
+        write(Path::new(&interface_path), INTERFACE);
+        let project = |caller_path: &Path, contract: &str| {
+            let caller = caller(contract);
+            write(caller_path, &caller);
+            let caller_path = caller_path.to_string_lossy();
+            let mut ast_data = crate::syntax::parse_files([
+                (interface_path.as_str(), INTERFACE),
+                (caller_path.as_ref(), caller.as_str()),
+            ]);
+            let mut deposit = None;
+            ast::walk(&ast_data, &mut |node| {
+                if node["nodeType"] == "FunctionDefinition" && node["name"] == "deposit" {
+                    deposit = node["id"].as_u64();
+                }
+            });
+            resolve_member(&mut ast_data, "deposit", deposit.unwrap());
+            ast_data
+        };
+        let vault_test = core.join("test/Vault.t.sol");
+        let router = periphery.join("src/Router.sol");
+        let runner = SourcesRunner(HashMap::from([
+            (
+                core.to_string_lossy().into_owned(),
+                project(&vault_test, "VaultTest"),
+            ),
+            (
+                periphery.to_string_lossy().into_owned(),
+                project(&router, "Router"),
+            ),
+        ]));
+
+        let index = WorkspaceIndex::new(Arc::new(runner));
+        index.build(&core).await.unwrap();
+        index.build(&periphery).await.unwrap();
+        let uri = Url::from_file_path(&interface_path).unwrap();
+        let mut files: Vec<Url> = index
+            .references(
+                &uri,
+                Position::new(1, 13),
+                INTERFACE.as_bytes(),
+                &Snapshot::default(),
+            )
+            .await
+            .into_iter()
+            .map(|location| location.uri)
+            .collect();
+        files.sort();
+        let mut expected = vec![
+            uri.clone(),
+            Url::from_file_path(&router).unwrap(),
+            Url::from_file_path(&vault_test).unwrap(),
+        ];
+        expected.sort();
+        assert_eq!(files, expected);
+    }
+
+    #[tokio::test]
+    async fn test_rename_moves_index_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        std::fs::create_dir_all(root.join("src/vault")).unwrap();
+        for name in ["Token.sol", "Vault.sol", "Router.sol", "Math.sol"] {
+            std::fs::write(root.join("src").join(name), format!("// {name}")).unwrap();
+        }
+        let uri = |name: &str| Url::from_file_path(root.join("src").join(name)).unwrap();
+
+        let index = WorkspaceIndex::new(Arc::new(ProjectRunner));
+        index.build(&root).await.unwrap();
+        let hash = content_hash(b"// Vault.sol");
+        assert_eq!(index.content_hash(&uri("Vault.sol")).await, Some(hash));
+
+        let (from, to) = (uri("Vault.sol"), uri("vault/Vault.sol"));
+        assert!(index.rename(&from, &to).await);
+        assert!(!index.rename(&from, &to).await);
+        let project = index.project_for(&to).await.unwrap();
+        assert!(!project.contains(&from));
+        assert_eq!(project.content_hash(&to), Some(hash));
+        assert_eq!(project.importers(&to), [uri("Router.sol")]);
+        assert_eq!(
+            project.importers(&uri("Token.sol")),
+            [uri("Router.sol"), to]
+        );
+        assert!(project.ast["sources"].get("src/vault/Vault.sol").is_some());
+    }
+}
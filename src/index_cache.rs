@@ -0,0 +1,209 @@
+//! Project indexes saved on disk, so a restarted server answers requests without compiling
+//! every project again.
+//!
+//! Each project's AST is written to `forge-lsp/index.json` under the project's forge cache
+//! directory, `cache/` unless `foundry.toml` sets `cache_path`, with the content hash of
+//! every source it was compiled from. A saved index is used only when it was written by
+//! this version of the server, every source still has its hash, and the project's
+//! configuration and set of Solidity files are unchanged: `foundry.toml`,
+//! `remappings.txt`, `FOUNDRY_PROFILE` and the paths of the `.sol` files outside of the
+//! dependency and build directories. Anything else compiles the project as before.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    io::{BufReader, BufWriter},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    annotations::project_sources,
+    index::{ProjectIndex, content_hash},
+    project::ProjectConfig,
+};
+
+/// Version of the format of the saved index. Bumped when the layout of the file or of
+/// what the index reads from the AST changes.
+pub const CACHE_VERSION: u32 = 1;
+
+/// Location of the saved index in the forge cache directory.
+const CACHE_FILE: &str = "forge-lsp/index.json";
+
+#[derive(Serialize)]
+struct SavedIndex<'a> {
+    version: u32,
+    server: &'a str,
+    fingerprint: u64,
+    hashes: &'a HashMap<String, u64>,
+    ast: &'a Value,
+}
+
+#[derive(Deserialize)]
+struct LoadedIndex {
+    version: u32,
+    server: String,
+    fingerprint: u64,
+    hashes: HashMap<String, u64>,
+    ast: Value,
+}
+
+/// Where the index of the project at `root` is saved.
+pub fn cache_file(root: &Path) -> PathBuf {
+    root.join(ProjectConfig::load(root).cache_path)
+        .join(CACHE_FILE)
+}
+
+/// Hash of what decides which sources forge compiles for the project at `root`, and how.
+fn fingerprint(root: &Path) -> u64 {
+    let config = ProjectConfig::load(root);
+    let mut inputs = Vec::new();
+    for file in ["foundry.toml", "remappings.txt"] {
+        inputs.extend(std::fs::read(root.join(file)).unwrap_or_default());
+        inputs.push(0);
+    }
+    inputs.extend(std::env::var("FOUNDRY_PROFILE").unwrap_or_default().bytes());
+    // The walk skips the default dependency and build directories; those `foundry.toml`
+    // moves elsewhere are skipped here
+    for file in project_sources(root) {
+        let relative = file.strip_prefix(root).unwrap_or(&file);
+        if config.is_dependency(&file) || relative.starts_with(&config.cache_path) {
+            continue;
+        }
+        inputs.push(0);
+        inputs.extend(relative.to_string_lossy().replace('\\', "/").bytes());
+    }
+    content_hash(&inputs)
+}
+
+/// Write the index of `project` to its cache file. The file is replaced in one step, so a
+/// server starting meanwhile reads either the previous index or this one.
+pub fn save(project: &ProjectIndex) -> std::io::Result<()> {
+    let path = cache_file(&project.root);
+    let dir = path.parent().unwrap_or(&project.root);
+    std::fs::create_dir_all(dir)?;
+    let saved = SavedIndex {
+        version: CACHE_VERSION,
+        server: env!("CARGO_PKG_VERSION"),
+        fingerprint: fingerprint(&project.root),
+        hashes: project.hashes(),
+        ast: &project.ast,
+    };
+
+    let mut file = tempfile::NamedTempFile::new_in(dir)?;
+    serde_json::to_writer(BufWriter::new(&mut file), &saved).map_err(std::io::Error::other)?;
+    file.persist(&path).map_err(|e| e.error)?;
+    Ok(())
+}
+
+/// The saved index of the project at `root`, if there is one and the project has not
+/// changed since it was written.
+pub fn load(root: &Path) -> Option<ProjectIndex> {
+    let file = std::fs::File::open(cache_file(root)).ok()?;
+    let loaded: LoadedIndex = serde_json::from_reader(BufReader::new(file)).ok()?;
+    if loaded.version != CACHE_VERSION
+        || loaded.server != env!("CARGO_PKG_VERSION")
+        || loaded.fingerprint != fingerprint(root)
+    {
+        return None;
+    }
+    let project = ProjectIndex::new(root.to_path_buf(), loaded.ast);
+    (project.hashes() == &loaded.hashes).then_some(project)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast;
+    use serde_json::json;
+    use tower_lsp::lsp_types::Url;
+
+    fn write(root: &Path, path: &str, contents: &str) {
+        let path = root.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+
+    /// A project of two sources and its index.
+    fn project() -> (tempfile::TempDir, ProjectIndex) {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        write(&root, "foundry.toml", "[profile.default]\n");
+        write(&root, "src/Token.sol", "contract Token {}");
+        write(&root, "src/Vault.sol", "contract Vault {}");
+        let unit = |name: &str| {
+            ast::mock_source(
+                0,
+                json!({ "nodeType": "SourceUnit", "absolutePath": name, "nodes": [] }),
+            )
+        };
+        let ast = json!({
+            "sources": {
+                "src/Token.sol": unit("src/Token.sol"),
+                "src/Vault.sol": unit("src/Vault.sol")
+            }
+        });
+        (dir, ProjectIndex::new(root, ast))
+    }
+
+    #[test]
+    fn test_saved_index_loads_until_the_project_changes() {
+        let (_dir, project) = project();
+        let root = project.root.clone();
+        assert!(load(&root).is_none());
+
+        save(&project).unwrap();
+        assert!(cache_file(&root).starts_with(root.join("cache")));
+        let loaded = load(&root).unwrap();
+        assert_eq!(loaded.file_count(), 2);
+        assert_eq!(*loaded.ast, *project.ast);
+        let vault = Url::from_file_path(root.join("src/Vault.sol")).unwrap();
+        assert_eq!(loaded.content_hash(&vault), project.content_hash(&vault));
+
+        // Edited sources
+        write(&root, "src/Vault.sol", "contract Vault { uint256 x; }");
+        assert!(load(&root).is_none());
+        write(&root, "src/Vault.sol", "contract Vault {}");
+        assert!(load(&root).is_some());
+
+        // New sources, except in dependencies
+        write(&root, "lib/forge-std/src/Test.sol", "");
+        assert!(load(&root).is_some());
+        write(&root, "test/Vault.t.sol", "");
+        assert!(load(&root).is_none());
+        std::fs::remove_file(root.join("test/Vault.t.sol")).unwrap();
+
+        // A changed configuration
+        write(&root, "remappings.txt", "solmate/=lib/solmate/src/\n");
+        assert!(load(&root).is_none());
+    }
+
+    #[test]
+    fn test_saved_index_follows_cache_path_and_version() {
+        let (_dir, project) = project();
+        let root = project.root.clone();
+        write(
+            &root,
+            "foundry.toml",
+            "[profile.default]\ncache_path = \"forge-cache\"\nlibs = [\"dependencies\"]\n",
+        );
+        save(&project).unwrap();
+        assert_eq!(
+            cache_file(&root),
+            root.join("forge-cache/forge-lsp/index.json")
+        );
+        assert!(load(&root).is_some());
+
+        // Sources in the configured dependency and cache directories aren't the project's
+        write(&root, "dependencies/solmate/src/ERC20.sol", "");
+        write(&root, "forge-cache/Stub.sol", "");
+        assert!(load(&root).is_some());
+
+        // An index written by another version of the format is ignored
+        let path = cache_file(&root);
+        let mut saved: Value = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        saved["version"] = (CACHE_VERSION + 1).into();
+        std::fs::write(&path, saved.to_string()).unwrap();
+        assert!(load(&root).is_none());
+    }
+}
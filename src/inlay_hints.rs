@@ -0,0 +1,438 @@
+//! Inlay hints: parameter names at call sites, the types of tuple destructuring targets,
+//! and the names of return variables at `return` statements and after calls dropping their
+//! return values, all read from the AST.
+
+use serde_json::Value;
+use std::collections::HashMap;
+use tower_lsp::lsp_types::{InlayHint, InlayHintKind, InlayHintLabel, Range, Url};
+
+use crate::{
+    ast::{self, name, node_type, span_bounds},
+    config::InlayHintsSettings,
+    goto::bytes_to_pos,
+};
+
+/// Parameters of the function, event or error `declaration`, or the members of the struct
+/// it constructs.
+pub fn parameters(declaration: &Value) -> Option<&Vec<Value>> {
+    match node_type(declaration)? {
+        "FunctionDefinition" | "EventDefinition" | "ErrorDefinition" => {
+            declaration.get("parameters")?.get("parameters")?.as_array()
+        }
+        "StructDefinition" => declaration.get("members")?.as_array(),
+        _ => None,
+    }
+}
+
+/// Whether `argument` already says what it is: an identifier or member named like the
+/// parameter, ignoring leading and trailing underscores.
+pub fn is_self_describing(argument: &Value, parameter: &str) -> bool {
+    let argument_name = match node_type(argument) {
+        Some("Identifier") => Some(name(argument)),
+        Some("MemberAccess") => argument.get("memberName").and_then(Value::as_str),
+        _ => None,
+    };
+    argument_name.is_some_and(|n| n.trim_matches('_') == parameter.trim_matches('_'))
+}
+
+/// `name:` labels before the positional arguments of `call`.
+fn parameter_hints(
+    call: &Value,
+    index: &HashMap<u64, &Value>,
+    hint: &mut impl FnMut(usize, String, InlayHintKind),
+) {
+    let Some(arguments) = call.get("arguments").and_then(Value::as_array) else {
+        return;
+    };
+    let named = call
+        .get("names")
+        .and_then(Value::as_array)
+        .is_some_and(|names| !names.is_empty());
+    let kind = call.get("kind").and_then(Value::as_str);
+    if arguments.is_empty()
+        || named
+        || !matches!(kind, Some("functionCall" | "structConstructorCall"))
+    {
+        return;
+    }
+
+    let Some(expression) = call.get("expression") else {
+        return;
+    };
+    let Some(parameters) = expression
+        .get("referencedDeclaration")
+        .and_then(Value::as_u64)
+        .and_then(|id| index.get(&id))
+        .and_then(|declaration| parameters(declaration))
+    else {
+        return;
+    };
+    // `x.add(y)` on a library function attached with `using for` passes `x` as the first
+    // parameter
+    let bound =
+        node_type(expression) == Some("MemberAccess") && parameters.len() == arguments.len() + 1;
+    let parameters = &parameters[usize::from(bound)..];
+
+    for (argument, parameter) in arguments.iter().zip(parameters) {
+        let parameter_name = name(parameter);
+        if parameter_name.is_empty() {
+            continue;
+        }
+        if is_self_describing(argument, parameter_name) {
+            continue;
+        }
+        if let Some((start, _)) = span_bounds(argument) {
+            hint(
+                start,
+                format!("{parameter_name}:"),
+                InlayHintKind::PARAMETER,
+            );
+        }
+    }
+}
+
+/// `: type` labels after the components of a tuple destructuring assignment.
+fn type_hints(assignment: &Value, hint: &mut impl FnMut(usize, String, InlayHintKind)) {
+    let Some(components) = assignment
+        .get("leftHandSide")
+        .filter(|lhs| node_type(lhs) == Some("TupleExpression"))
+        .and_then(|lhs| lhs.get("components"))
+        .and_then(Value::as_array)
+    else {
+        return;
+    };
+    for component in components.iter().filter(|c| !c.is_null()) {
+        let (Some((_, end)), Some(type_string)) = (
+            span_bounds(component),
+            component
+                .get("typeDescriptions")
+                .and_then(|t| t.get("typeString"))
+                .and_then(Value::as_str),
+        ) else {
+            continue;
+        };
+        hint(end, format!(": {type_string}"), InlayHintKind::TYPE);
+    }
+}
+
+/// `name:` labels before the values a `return` statement of `function` returns, for
+/// functions naming their return variables.
+fn return_hints(function: &Value, hint: &mut impl FnMut(usize, String, InlayHintKind)) {
+    let Some(returns) = function
+        .get("returnParameters")
+        .and_then(|list| list.get("parameters"))
+        .and_then(Value::as_array)
+    else {
+        return;
+    };
+    let Some(body) = function.get("body").filter(|body| !body.is_null()) else {
+        return;
+    };
+    ast::walk(body, &mut |node| {
+        let Some(expression) = node
+            .get("expression")
+            .filter(|_| node_type(node) == Some("Return"))
+        else {
+            return;
+        };
+        let values: Vec<&Value> = match expression.get("components").and_then(Value::as_array) {
+            Some(components) if node_type(expression) == Some("TupleExpression") => {
+                components.iter().collect()
+            }
+            _ => vec![expression],
+        };
+        // `return split();` returns a whole tuple at once
+        if values.len() != returns.len() {
+            return;
+        }
+        for (value, parameter) in values.into_iter().zip(returns) {
+            let return_name = name(parameter);
+            if return_name.is_empty() {
+                continue;
+            }
+            if is_self_describing(value, return_name) {
+                continue;
+            }
+            if let Some((start, _)) = span_bounds(value) {
+                hint(start, format!("{return_name}:"), InlayHintKind::PARAMETER);
+            }
+        }
+    });
+}
+
+/// `-> name` after a call statement dropping the values the called function returns,
+/// with the types of the values it doesn't name.
+fn dropped_return_hints(
+    statement: &Value,
+    index: &HashMap<u64, &Value>,
+    hint: &mut impl FnMut(usize, String, InlayHintKind),
+) {
+    let Some(call) = statement
+        .get("expression")
+        .filter(|call| node_type(call) == Some("FunctionCall"))
+    else {
+        return;
+    };
+    let Some(returns) = returned(call, index) else {
+        return;
+    };
+    let labels: Vec<&str> = returns
+        .iter()
+        .map(|parameter| {
+            Some(name(parameter))
+                .filter(|n| !n.is_empty())
+                .unwrap_or_else(|| {
+                    parameter
+                        .get("typeDescriptions")
+                        .and_then(|t| t.get("typeString"))
+                        .and_then(Value::as_str)
+                        .unwrap_or("_")
+                })
+        })
+        .collect();
+    let label = match labels.as_slice() {
+        [single] => format!("-> {single}"),
+        _ => format!("-> ({})", labels.join(", ")),
+    };
+    if let Some((_, end)) = span_bounds(call) {
+        hint(end, label, InlayHintKind::TYPE);
+    }
+}
+
+/// Return parameters of the function `call` calls, when it returns anything.
+pub fn returned<'a>(call: &Value, index: &HashMap<u64, &'a Value>) -> Option<&'a Vec<Value>> {
+    if !matches!(
+        call.get("kind").and_then(Value::as_str),
+        None | Some("functionCall")
+    ) {
+        return None;
+    }
+    // `vault.deposit{value: 1}(...)`
+    let mut callee = call.get("expression")?;
+    while node_type(callee) == Some("FunctionCallOptions") {
+        callee = callee.get("expression")?;
+    }
+    let function = index.get(&callee.get("referencedDeclaration")?.as_u64()?)?;
+    if node_type(function) != Some("FunctionDefinition") {
+        return None;
+    }
+    function
+        .get("returnParameters")?
+        .get("parameters")?
+        .as_array()
+        .filter(|returns| !returns.is_empty())
+}
+
+/// Inlay hints of `uri` within `range`, as enabled by `settings`.
+pub fn inlay_hints(
+    ast_data: &Value,
+    uri: &Url,
+    source_bytes: &[u8],
+    range: Range,
+    settings: &InlayHintsSettings,
+) -> Vec<InlayHint> {
+    let (Some(sources), Some(source_unit)) =
+        (ast_data.get("sources"), ast::source_unit(ast_data, uri))
+    else {
+        return vec![];
+    };
+    let index = ast::index_nodes(sources);
+
+    let mut hints = Vec::new();
+    let mut hint = |offset: usize, label: String, kind: InlayHintKind| {
+        let Some(position) = bytes_to_pos(source_bytes, offset) else {
+            return;
+        };
+        if position < range.start || position > range.end {
+            return;
+        }
+        // `-> shares` stands apart from the call it follows
+        let padding_left = label.starts_with("->").then_some(true);
+        hints.push(InlayHint {
+            position,
+            label: InlayHintLabel::String(label),
+            kind: Some(kind),
+            text_edits: None,
+            tooltip: None,
+            padding_left,
+            padding_right: Some(kind == InlayHintKind::PARAMETER),
+            data: None,
+        });
+    };
+    ast::walk(source_unit, &mut |node| match node_type(node) {
+        Some("FunctionCall") if settings.parameter_names => {
+            parameter_hints(node, &index, &mut hint)
+        }
+        Some("Assignment") if settings.types => type_hints(node, &mut hint),
+        Some("FunctionDefinition") if settings.return_names => return_hints(node, &mut hint),
+        Some("ExpressionStatement") if settings.return_names => {
+            dropped_return_hints(node, &index, &mut hint)
+        }
+        _ => {}
+    });
+
+    hints.sort_by_key(|hint| hint.position);
+    hints
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tower_lsp::lsp_types::Position;
+
+    const SOURCE: &str = "\
+contract Vault {
+    function deposit(address owner, uint256 amount) public {}
+    function split() public returns (uint256, bool) {}
+    function run(uint256 amount) public {
+        deposit(msg.sender, amount);
+        (amount, ok) = split();
+    }
+}
+";
+
+    fn at(needle: &str, text: &str) -> String {
+        let start = SOURCE.find(needle).unwrap() + needle.find(text).unwrap();
+        format!("{start}:{}:0", text.len())
+    }
+
+    fn parameter(id: u64, name: &str) -> Value {
+        json!({ "id": id, "nodeType": "VariableDeclaration", "name": name })
+    }
+
+    fn typed(node_type: &str, name: &str, src: String, type_string: &str) -> Value {
+        json!({
+            "nodeType": node_type,
+            "name": name,
+            "memberName": name,
+            "src": src,
+            "typeDescriptions": { "typeString": type_string }
+        })
+    }
+
+    fn mock_ast(path: &str) -> Value {
+        let call = "deposit(msg.sender, amount)";
+        let mut deposit = json!({ "id": 1, "nodeType": "FunctionDefinition", "name": "deposit" });
+        deposit["parameters"] =
+            json!({ "parameters": [parameter(2, "owner"), parameter(3, "amount")] });
+        let mut call_node = json!({
+            "nodeType": "FunctionCall",
+            "kind": "functionCall",
+            "names": [],
+            "src": at(call, call),
+            "expression": {
+                "nodeType": "Identifier",
+                "name": "deposit",
+                "referencedDeclaration": 1
+            }
+        });
+        call_node["arguments"] = json!([
+            typed("MemberAccess", "sender", at(call, "msg.sender"), "address"),
+            typed("Identifier", "amount", at(call, "amount"), "uint256")
+        ]);
+
+        let tuple = "(amount, ok) = split()";
+        let mut assignment = json!({ "nodeType": "Assignment", "src": at(tuple, tuple) });
+        assignment["leftHandSide"] = json!({
+            "nodeType": "TupleExpression",
+            "components": [
+                typed("Identifier", "amount", at(tuple, "amount"), "uint256"),
+                typed("Identifier", "ok", at(tuple, "ok"), "bool")
+            ]
+        });
+
+        let mut contract = json!({ "id": 10, "nodeType": "ContractDefinition", "name": "Vault" });
+        contract["nodes"] = json!([deposit, call_node, assignment]);
+        ast::mock_ast(
+            path,
+            json!({
+                "nodeType": "SourceUnit",
+                "absolutePath": path,
+                "nodes": [contract]
+            }),
+        )
+    }
+
+    fn labels(hints: &[InlayHint]) -> Vec<(Position, &str)> {
+        hints
+            .iter()
+            .map(|hint| match &hint.label {
+                InlayHintLabel::String(label) => (hint.position, label.as_str()),
+                _ => panic!("expected a string label"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parameter_and_tuple_type_hints() {
+        let path = "/project/src/Vault.sol";
+        let uri = Url::from_file_path(path).unwrap();
+        let ast = mock_ast(path);
+        let range = Range::new(Position::new(0, 0), Position::new(8, 0));
+        let all = InlayHintsSettings::default();
+
+        let hints = inlay_hints(&ast, &uri, SOURCE.as_bytes(), range, &all);
+        // `amount` passed as `amount` needs no hint
+        assert_eq!(
+            labels(&hints),
+            [
+                (Position::new(4, 16), "owner:"),
+                (Position::new(5, 15), ": uint256"),
+                (Position::new(5, 19), ": bool"),
+            ]
+        );
+        assert_eq!(hints[0].padding_right, Some(true));
+
+        let types_only = InlayHintsSettings {
+            parameter_names: false,
+            ..InlayHintsSettings::default()
+        };
+        let hints = inlay_hints(&ast, &uri, SOURCE.as_bytes(), range, &types_only);
+        assert_eq!(hints.len(), 2);
+
+        let first_line = Range::new(Position::new(4, 0), Position::new(4, 80));
+        let hints = inlay_hints(&ast, &uri, SOURCE.as_bytes(), first_line, &all);
+        assert_eq!(labels(&hints), [(Position::new(4, 16), "owner:")]);
+    }
+
+    #[test]
+    fn test_return_name_hints() {
+        let source = "\
+contract Vault {
+    function split(uint256 x) public returns (uint256 assets, uint256 shares) {
+        uint256 half = x / 2;
+        return (half, shares);
+    }
+    function total() public returns (uint256) {
+        split(1);
+        return 1;
+    }
+    function check() public {
+        total();
+    }
+}
+";
+        let path = "/project/src/Vault.sol";
+        let uri = Url::from_file_path(path).unwrap();
+        let tree = crate::syntax::parse(path, source);
+        let range = Range::new(Position::new(0, 0), Position::new(13, 0));
+        let settings = InlayHintsSettings {
+            parameter_names: false,
+            types: false,
+            return_names: true,
+        };
+
+        let hints = inlay_hints(&tree, &uri, source.as_bytes(), range, &settings);
+        // `shares` returned as `shares` needs no hint, nor does an unnamed return variable
+        assert_eq!(
+            labels(&hints),
+            [
+                (Position::new(3, 16), "assets:"),
+                (Position::new(6, 16), "-> (assets, shares)"),
+                (Position::new(10, 15), "-> uint256"),
+            ]
+        );
+        assert_eq!(hints[1].padding_left, Some(true));
+    }
+}
@@ -0,0 +1,562 @@
+//! Drift between a contract and its interface, `Vault` and `IVault`: external and public
+//! functions of the contract the interface doesn't declare, and declarations of the
+//! interface the contract doesn't implement.
+//!
+//! Both are reported in the contract's file and in the interface's, with a quick fix
+//! adding the missing declaration to the interface or removing the stale one.
+
+use serde_json::Value;
+use std::collections::HashMap;
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString, Range, TextEdit, Url};
+
+use crate::{
+    ast::{self, name, parse_src},
+    code_actions::Fix,
+    edits::EditBuilder,
+    goto::bytes_to_pos,
+    paths,
+};
+
+/// Diagnostic code of a contract function its interface doesn't declare.
+pub const INTERFACE_MISSING_CODE: &str = "interface-missing";
+
+/// Diagnostic code of an interface declaration the contract doesn't implement.
+pub const INTERFACE_STALE_CODE: &str = "interface-stale";
+
+/// A contract or interface and the source unit declaring it.
+#[derive(Clone, Copy)]
+struct Declared<'a> {
+    node: &'a Value,
+    unit: &'a Value,
+    /// Source path as reported by forge.
+    path: &'a str,
+}
+
+fn members(contract: &Value) -> impl Iterator<Item = &Value> {
+    contract
+        .get("nodes")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+}
+
+fn parameters<'a>(function: &'a Value, key: &str) -> &'a [Value] {
+    function
+        .get(key)
+        .and_then(|list| list.get("parameters"))
+        .and_then(Value::as_array)
+        .map_or(&[], Vec::as_slice)
+}
+
+/// Whether `node` is an external or public function, or a public state variable.
+fn is_api(node: &Value) -> bool {
+    let visibility = node.get("visibility").and_then(Value::as_str);
+    match node.get("nodeType").and_then(Value::as_str) {
+        Some("FunctionDefinition") => {
+            node.get("kind").and_then(Value::as_str) == Some("function")
+                && matches!(visibility, Some("external" | "public"))
+        }
+        Some("VariableDeclaration") => visibility == Some("public"),
+        _ => false,
+    }
+}
+
+/// Signature of `node`, like `transfer(address,uint256)`, without data locations.
+fn signature(node: &Value) -> String {
+    let types: Vec<String> = parameters(node, "parameters")
+        .iter()
+        .map(|parameter| {
+            let type_string = parameter
+                .get("typeDescriptions")
+                .and_then(|types| types.get("typeString"))
+                .and_then(Value::as_str)
+                .unwrap_or_default();
+            type_string
+                .split_whitespace()
+                .filter(|word| !matches!(*word, "memory" | "calldata" | "storage" | "pointer"))
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect();
+    format!("{}({})", name(node), types.join(","))
+}
+
+/// Whether `a` and `b` have the same selector, or the same signature when the AST has no
+/// selectors.
+fn same_function(a: &Value, b: &Value) -> bool {
+    let selector = |node| Value::get(node, "functionSelector").and_then(Value::as_str);
+    match (selector(a), selector(b)) {
+        (Some(a), Some(b)) => a == b,
+        _ => signature(a) == signature(b),
+    }
+}
+
+/// Source files, the current one from the buffer and the others read from disk.
+struct Sources<'a> {
+    current_unit: &'a Value,
+    current: &'a str,
+    read: HashMap<String, Option<String>>,
+}
+
+impl<'a> Sources<'a> {
+    fn get(&mut self, declared: &Declared) -> Option<&str> {
+        if std::ptr::eq(declared.unit, self.current_unit) {
+            return Some(self.current);
+        }
+        self.read
+            .entry(declared.path.to_string())
+            .or_insert_with(|| {
+                std::fs::read_to_string(paths::resolve_source_path(declared.path)?).ok()
+            })
+            .as_deref()
+    }
+
+    /// Add `edit` of the file of `declared` to `fix`.
+    fn add_edit(&self, fix: &mut Fix, declared: &Declared, edit: TextEdit) -> Option<()> {
+        if std::ptr::eq(declared.unit, self.current_unit) {
+            fix.edits.push(edit);
+        } else {
+            let uri = paths::path_to_uri(&paths::resolve_source_path(declared.path)?)?;
+            fix.related.entry(uri).or_default().push(edit);
+        }
+        Some(())
+    }
+}
+
+fn text<'s>(source: &'s str, node: &Value) -> Option<&'s str> {
+    let (start, length, _) = parse_src(node.get("src")?.as_str()?)?;
+    source.get(start..start + length)
+}
+
+/// The interface declaration of `function`, written from its source.
+fn declaration_text(source: &str, function: &Value) -> Option<String> {
+    let list = |key: &str| -> Option<String> {
+        let texts: Option<Vec<&str>> = parameters(function, key)
+            .iter()
+            .map(|parameter| text(source, parameter))
+            .collect();
+        Some(texts?.join(", "))
+    };
+    let mut declaration = format!(
+        "function {}({}) external",
+        name(function),
+        list("parameters")?
+    );
+    if let Some(mutability @ ("view" | "pure" | "payable")) =
+        function.get("stateMutability").and_then(Value::as_str)
+    {
+        declaration.push(' ');
+        declaration.push_str(mutability);
+    }
+    if !parameters(function, "returnParameters").is_empty() {
+        declaration.push_str(&format!(" returns ({})", list("returnParameters")?));
+    }
+    declaration.push(';');
+    Some(declaration)
+}
+
+fn line_start(source: &str, offset: usize) -> usize {
+    source[..offset].rfind('\n').map_or(0, |i| i + 1)
+}
+
+/// Edit adding `declaration` as the last member of `interface`.
+fn insert_edit(source: &str, interface: &Value, declaration: &str) -> Option<TextEdit> {
+    let (start, length, _) = parse_src(interface.get("src")?.as_str()?)?;
+    let close = start + length.checked_sub(1)?;
+    if source.as_bytes().get(close) != Some(&b'}') {
+        return None;
+    }
+    let indent = members(interface)
+        .filter_map(|member| parse_src(member.get("src")?.as_str()?))
+        .last()
+        .map(|(member_start, _, _)| {
+            let line = line_start(source, member_start);
+            source[line..member_start].to_string()
+        })
+        .filter(|indent| indent.trim().is_empty())
+        .unwrap_or_else(|| "    ".to_string());
+
+    let close_line = line_start(source, close);
+    let (offset, new_text) = if source[close_line..close].trim().is_empty() && close_line > start {
+        // A blank line after the previous member, none after the opening brace
+        let previous = source[..close_line - 1].trim_end_matches([' ', '\t']);
+        let separator = if previous.ends_with('{') || previous.ends_with("\n") {
+            ""
+        } else {
+            "\n"
+        };
+        (close_line, format!("{separator}{indent}{declaration}\n"))
+    } else {
+        (close, format!("\n{indent}{declaration}\n"))
+    };
+    let mut edits = EditBuilder::new(source);
+    edits.replace(offset, offset, new_text).ok()?;
+    edits.build().pop()
+}
+
+/// Edit removing the lines of `function`, with the doc comment and the blank line right
+/// above it.
+fn remove_edit(source: &str, function: &Value) -> Option<TextEdit> {
+    let (start, length, _) = parse_src(function.get("src")?.as_str()?)?;
+    let mut from = line_start(source, start);
+    let mut blank = false;
+    while from > 0 && !blank {
+        let previous = line_start(source, from - 1);
+        let line = source[previous..from].trim();
+        blank = line.is_empty();
+        if !(blank || line.starts_with("///") || line.starts_with("/**") || line.starts_with('*')) {
+            break;
+        }
+        from = previous;
+    }
+    let end = start + length;
+    let to = source[end..]
+        .find('\n')
+        .map_or(source.len(), |i| end + i + 1);
+    let mut edits = EditBuilder::new(source);
+    edits.replace(from, to, "").ok()?;
+    edits.build().pop()
+}
+
+fn name_range(source: &str, node: &Value) -> Option<Range> {
+    let src = node.get("nameLocation").or_else(|| node.get("src"))?;
+    let (start, length, _) = parse_src(src.as_str()?)?;
+    Some(Range::new(
+        bytes_to_pos(source.as_bytes(), start)?,
+        bytes_to_pos(source.as_bytes(), start + length)?,
+    ))
+}
+
+fn diagnostic(range: Range, code: &str, message: String, fix: Fix) -> Diagnostic {
+    Diagnostic {
+        range,
+        severity: Some(DiagnosticSeverity::WARNING),
+        code: Some(NumberOrString::String(code.to_string())),
+        source: Some("forge-lsp".to_string()),
+        message,
+        data: fix.to_data(),
+        ..Diagnostic::default()
+    }
+}
+
+/// Every contract and interface of the build, by kind and name.
+fn declarations(ast_data: &Value) -> HashMap<(&str, &str), Declared<'_>> {
+    let mut found = HashMap::new();
+    let sources = ast_data.get("sources").and_then(Value::as_object);
+    for (path, contents) in sources.into_iter().flatten() {
+        let Some(unit) = contents
+            .get(0)
+            .and_then(|content| content.get("source_file"))
+            .and_then(|source_file| source_file.get("ast"))
+        else {
+            continue;
+        };
+        let path = unit
+            .get("absolutePath")
+            .and_then(Value::as_str)
+            .unwrap_or(path);
+        for node in members(unit) {
+            if node.get("nodeType").and_then(Value::as_str) != Some("ContractDefinition") {
+                continue;
+            }
+            let kind = node
+                .get("contractKind")
+                .and_then(Value::as_str)
+                .unwrap_or_default();
+            found.insert((kind, name(node)), Declared { node, unit, path });
+        }
+    }
+    found
+}
+
+/// Ids of the contracts `contract` inherits from, directly or not.
+fn bases(contract: &Value, declarations: &HashMap<(&str, &str), Declared>) -> Vec<u64> {
+    let linearized: Vec<u64> = contract
+        .get("linearizedBaseContracts")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_u64)
+        .collect();
+    if !linearized.is_empty() {
+        return linearized;
+    }
+    // The in-process parse only lists the direct bases, by name
+    contract
+        .get("baseContracts")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|base| {
+            let base_name = base.get("baseName")?.get("name")?.as_str()?;
+            declarations
+                .iter()
+                .find(|((_, name), _)| *name == base_name)
+                .and_then(|(_, declared)| declared.node.get("id")?.as_u64())
+        })
+        .collect()
+}
+
+/// Drift between the contracts of `uri` and their interfaces, and between the interfaces
+/// of `uri` and their contracts.
+pub fn interface_diagnostics(ast_data: &Value, uri: &Url, source_bytes: &[u8]) -> Vec<Diagnostic> {
+    let (Ok(current), Some(current_unit)) = (
+        std::str::from_utf8(source_bytes),
+        ast::source_unit(ast_data, uri),
+    ) else {
+        return vec![];
+    };
+    let declarations = declarations(ast_data);
+    let mut sources = Sources {
+        current_unit,
+        current,
+        read: HashMap::new(),
+    };
+
+    let mut diagnostics = Vec::new();
+    for (&(kind, contract_name), &contract) in &declarations {
+        if kind != "contract" {
+            continue;
+        }
+        let interface_name = format!("I{contract_name}");
+        let Some(&interface) = declarations.get(&("interface", interface_name.as_str())) else {
+            continue;
+        };
+        let contract_is_current = std::ptr::eq(contract.unit, current_unit);
+        let interface_is_current = std::ptr::eq(interface.unit, current_unit);
+        if !contract_is_current && !interface_is_current {
+            continue;
+        }
+
+        // Functions declared by any interface the contract inherits aren't missing
+        let bases = bases(contract.node, &declarations);
+        let inherited: Vec<&Value> = declarations
+            .values()
+            .filter(|declared| {
+                declared.node.get("contractKind").and_then(Value::as_str) == Some("interface")
+                    && declared
+                        .node
+                        .get("id")
+                        .and_then(Value::as_u64)
+                        .is_some_and(|id| bases.contains(&id))
+            })
+            .flat_map(|declared| members(declared.node))
+            .collect();
+        let declared_functions: Vec<&Value> = members(interface.node)
+            .chain(inherited.iter().copied())
+            .filter(|member| is_api(member))
+            .collect();
+
+        let missing = members(contract.node).filter(|member| {
+            is_api(member)
+                && member.get("nodeType").and_then(Value::as_str) == Some("FunctionDefinition")
+                // Overrides belong to the interface of their base
+                && member.get("overrides").is_none_or(Value::is_null)
+                && !declared_functions
+                    .iter()
+                    .any(|declared| same_function(member, declared))
+        });
+        for function in missing {
+            let function_name = name(function);
+            let Some(declaration) = sources
+                .get(&contract)
+                .and_then(|source| declaration_text(source, function))
+            else {
+                continue;
+            };
+            let Some(edit) = sources
+                .get(&interface)
+                .and_then(|source| insert_edit(source, interface.node, &declaration))
+            else {
+                continue;
+            };
+            let mut fix = Fix::new(
+                format!("Add `{function_name}` to `{interface_name}`"),
+                vec![],
+            );
+            if sources.add_edit(&mut fix, &interface, edit).is_none() {
+                continue;
+            }
+            let (declared, node, message) = if contract_is_current {
+                (
+                    contract,
+                    function,
+                    format!("`{function_name}` is not declared in `{interface_name}`"),
+                )
+            } else {
+                (
+                    interface,
+                    interface.node,
+                    format!(
+                        "`{interface_name}` is missing `{}` of `{contract_name}`",
+                        signature(function)
+                    ),
+                )
+            };
+            if let Some(range) = sources.get(&declared).and_then(|s| name_range(s, node)) {
+                diagnostics.push(diagnostic(range, INTERFACE_MISSING_CODE, message, fix));
+            }
+        }
+
+        // A contract inheriting its interface can't leave a declaration unimplemented
+        let interface_id = interface.node.get("id").and_then(Value::as_u64);
+        if interface_id.is_some_and(|id| bases.contains(&id)) {
+            continue;
+        }
+        let implemented: Vec<&Value> = members(contract.node).filter(|m| is_api(m)).collect();
+        let stale = members(interface.node).filter(|member| {
+            is_api(member)
+                && !implemented
+                    .iter()
+                    .any(|function| same_function(member, function))
+        });
+        for declaration in stale {
+            let declaration_name = name(declaration);
+            let Some(edit) = sources
+                .get(&interface)
+                .and_then(|source| remove_edit(source, declaration))
+            else {
+                continue;
+            };
+            let mut fix = Fix::new(
+                format!("Remove `{declaration_name}` from `{interface_name}`"),
+                vec![],
+            );
+            if sources.add_edit(&mut fix, &interface, edit).is_none() {
+                continue;
+            }
+            let (declared, node, message) = if interface_is_current {
+                (
+                    interface,
+                    declaration,
+                    format!("`{declaration_name}` is not implemented by `{contract_name}`"),
+                )
+            } else {
+                (
+                    contract,
+                    contract.node,
+                    format!(
+                        "`{contract_name}` does not implement `{}` of `{interface_name}`",
+                        signature(declaration)
+                    ),
+                )
+            };
+            if let Some(range) = sources.get(&declared).and_then(|s| name_range(s, node)) {
+                diagnostics.push(diagnostic(range, INTERFACE_STALE_CODE, message, fix));
+            }
+        }
+    }
+    diagnostics.sort_by_key(|diagnostic| diagnostic.range.start);
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax;
+
+    const INTERFACE: &str = "\
+interface IVault {
+    function deposit(uint256 amount) external;
+
+    /// @notice Retired in v2
+    function sweep() external;
+}
+";
+
+    const VAULT: &str = "\
+contract Vault {
+    uint256 public total;
+
+    function deposit(uint256 amount) external {
+        total += amount;
+    }
+
+    function balanceOf(address account) public view returns (uint256 balance) {}
+
+    function _credit(address account) internal {}
+}
+";
+
+    fn project() -> (tempfile::TempDir, Value, Url, Url) {
+        let dir = tempfile::tempdir().unwrap();
+        let interface = dir.path().join("IVault.sol");
+        let vault = dir.path().join("Vault.sol");
+        std::fs::write(&interface, INTERFACE).unwrap();
+        std::fs::write(&vault, VAULT).unwrap();
+        let ast = syntax::parse_files([
+            (interface.to_str().unwrap(), INTERFACE),
+            (vault.to_str().unwrap(), VAULT),
+        ]);
+        let interface = Url::from_file_path(interface).unwrap();
+        let vault = Url::from_file_path(vault).unwrap();
+        (dir, ast, interface, vault)
+    }
+
+    fn apply(source: &str, edits: &[TextEdit]) -> String {
+        EditBuilder::with_edits(source, edits).unwrap().apply()
+    }
+
+    #[test]
+    fn test_drift_in_the_contract_file() {
+        let (_dir, ast, interface, vault) = project();
+        let diagnostics = interface_diagnostics(&ast, &vault, VAULT.as_bytes());
+        let messages: Vec<&str> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                "`Vault` does not implement `sweep()` of `IVault`",
+                "`balanceOf` is not declared in `IVault`",
+            ]
+        );
+
+        // The fixes only edit the interface
+        let add = Fix::from_diagnostic(&diagnostics[1]).unwrap();
+        assert!(add.edits.is_empty());
+        assert_eq!(add.title, "Add `balanceOf` to `IVault`");
+        assert_eq!(
+            apply(INTERFACE, &add.related[&interface]),
+            "\
+interface IVault {
+    function deposit(uint256 amount) external;
+
+    /// @notice Retired in v2
+    function sweep() external;
+
+    function balanceOf(address account) external view returns (uint256 balance);
+}
+"
+        );
+
+        let remove = Fix::from_diagnostic(&diagnostics[0]).unwrap();
+        assert_eq!(
+            apply(INTERFACE, &remove.related[&interface]),
+            "\
+interface IVault {
+    function deposit(uint256 amount) external;
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_drift_in_the_interface_file() {
+        let (_dir, ast, interface, _) = project();
+        let diagnostics = interface_diagnostics(&ast, &interface, INTERFACE.as_bytes());
+        let found: Vec<(&str, u32)> = diagnostics
+            .iter()
+            .map(|d| (d.message.as_str(), d.range.start.line))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("`IVault` is missing `balanceOf(address)` of `Vault`", 0),
+                ("`sweep` is not implemented by `Vault`", 4),
+            ]
+        );
+        for diagnostic in &diagnostics {
+            let fix = Fix::from_diagnostic(diagnostic).unwrap();
+            assert!(fix.related.is_empty());
+        }
+    }
+}
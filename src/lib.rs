@@ -5,15 +5,88 @@
 
 #![cfg_attr(docsrs, feature(doc_cfg, doc_auto_cfg))]
 
+pub mod analysis;
+pub mod annotations;
+pub mod artifacts;
+pub mod ast;
+pub mod ast_provider;
+pub mod baseline;
+pub mod bindings;
 pub mod build;
+pub mod build_info;
+pub mod call_hierarchy;
+pub mod cheatcodes;
 pub mod cli;
+pub mod client_log;
+pub mod code_actions;
+pub mod completion;
+pub mod config;
+pub mod config_schema;
+pub mod constructor_args;
+pub mod counterexamples;
+pub mod deploy_script;
+pub mod docs;
+pub mod documents;
+pub mod edits;
+pub mod errors;
+pub mod events;
+pub mod expand_type;
+pub mod extract;
+pub mod fallback;
+pub mod file_renames;
+pub mod fix_all;
+pub mod flatten;
+pub mod folding;
+pub mod forge_test;
+pub mod formatting;
+pub mod fuzz_config;
+pub mod gas;
+pub mod git;
 pub mod goto;
+pub mod grammar;
+pub mod header;
+pub mod index;
+pub mod index_cache;
+pub mod hover;
+pub mod i18n;
+pub mod inlay_hints;
+pub mod interface_sync;
 pub mod lint;
+pub mod lsif;
 pub mod lsp;
+pub mod model_checker;
+pub mod mutability;
+pub mod named_args;
+pub mod natspec;
+pub mod paths;
+pub mod preview;
+pub mod profiles;
+pub mod project;
+pub mod progress;
 pub mod references;
 pub mod rename;
+pub mod roles;
 pub mod runner;
+pub mod selection;
+pub mod selectors;
+pub mod semantic_tokens;
+pub mod singleflight;
+pub mod stack_depth;
+pub mod stale;
+pub mod storage_layout;
+pub mod struct_literals;
+pub mod suppressions;
 pub mod symbols;
+pub mod syntax;
+pub mod test_explorer;
+pub mod test_names;
+pub mod trust;
+pub mod tuples;
+pub mod unused;
+pub mod unused_returns;
 pub mod utils;
+pub mod verify;
+pub mod watch;
+pub mod workspace;
 
 pub use lsp::ForgeLsp;
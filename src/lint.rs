@@ -1,37 +1,108 @@
+use crate::{code_actions::Fix, documents::LineIndex, edits::EditBuilder, paths};
 use serde::{Deserialize, Serialize};
-use std::path::Path;
-use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, Position, Range};
+use std::borrow::Cow;
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, Range};
 
+/// Source of the diagnostics of `forge lint`.
+pub const LINT_DIAGNOSTICS_SOURCE: &str = "forge-lint";
+
+/// Applicability of suggestions that are safe to apply without review.
+const MACHINE_APPLICABLE: &str = "MachineApplicable";
+
+/// Range of the bytes of `span` in `content`, the text of its file, with UTF-16 columns.
+fn span_range(span: &ForgeLintSpan, line_index: &LineIndex, content: &str) -> Range {
+    Range::new(
+        line_index.position(content, span.byte_start as usize),
+        line_index.position(content, span.byte_end as usize),
+    )
+}
+
+/// Collect the machine-applicable suggestions of `spans` and `children` for the target
+/// file, with the message of the first diagnostic that suggests one. A suggestion that
+/// overlaps an earlier one is left out.
+fn collect_suggestions<'a>(
+    message: &'a str,
+    spans: &[ForgeLintSpan],
+    children: &'a [ForgeLintChild],
+    in_target: &impl Fn(&str) -> bool,
+    title: &mut Option<&'a str>,
+    edits: &mut EditBuilder,
+) {
+    for span in spans {
+        if let Some(replacement) = &span.suggested_replacement
+            && span.suggestion_applicability.as_deref() == Some(MACHINE_APPLICABLE)
+            && in_target(&span.file_name)
+            && edits
+                .replace(
+                    span.byte_start as usize,
+                    span.byte_end as usize,
+                    replacement.as_ref(),
+                )
+                .is_ok()
+        {
+            title.get_or_insert(message);
+        }
+    }
+    for child in children {
+        collect_suggestions(
+            &child.message,
+            &child.spans,
+            &child.children,
+            in_target,
+            title,
+            edits,
+        );
+    }
+}
+
+/// The fix applying every machine-applicable suggestion of a lint in the target file,
+/// whose text is `content`.
+fn suggested_fix(
+    diagnostic: &ForgeDiagnostic,
+    in_target: &impl Fn(&str) -> bool,
+    content: &str,
+) -> Option<Fix> {
+    let mut title = None;
+    let mut edits = EditBuilder::new(content);
+    collect_suggestions(
+        &diagnostic.message,
+        &diagnostic.spans,
+        &diagnostic.children,
+        in_target,
+        &mut title,
+        &mut edits,
+    );
+    Some(Fix::new(title?, edits.build()))
+}
+
+/// Diagnostics of the `forge lint` output `forge_output` in `target_file`, whose text is
+/// `content`.
 pub fn lint_output_to_diagnostics(
     forge_output: &serde_json::Value,
     target_file: &str,
+    content: &str,
 ) -> Vec<Diagnostic> {
     let mut diagnostics = Vec::new();
+    let line_index = LineIndex::new(content);
+
+    let target_key = paths::canonical_key(target_file);
 
     if let serde_json::Value::Array(items) = forge_output {
         for item in items {
-            if let Ok(forge_diag) = serde_json::from_value::<ForgeDiagnostic>(item.clone()) {
+            // Deserialize from the borrowed value instead of cloning every item
+            if let Ok(forge_diag) = ForgeDiagnostic::deserialize(item) {
                 // Only include diagnostics for the target file
+                let in_target = |file_name: &str| {
+                    file_name == target_file || paths::canonical_key(file_name) == target_key
+                };
                 for span in &forge_diag.spans {
-                    let target_path = Path::new(target_file)
-                        .canonicalize()
-                        .unwrap_or_else(|_| Path::new(target_file).to_path_buf());
-                    let span_path = Path::new(&span.file_name)
-                        .canonicalize()
-                        .unwrap_or_else(|_| Path::new(&span.file_name).to_path_buf());
-                    if target_path == span_path && span.is_primary {
+                    if !span.is_primary {
+                        continue;
+                    }
+                    if in_target(&span.file_name) {
                         let diagnostic = Diagnostic {
-                            range: Range {
-                                start: Position {
-                                    line: (span.line_start - 1),        // LSP is 0-based
-                                    character: (span.column_start - 1), // LSP is 0-based
-                                },
-                                end: Position {
-                                    line: (span.line_end - 1),
-                                    character: (span.column_end - 1),
-                                },
-                            },
-                            severity: Some(match forge_diag.level.as_str() {
+                            range: span_range(span, &line_index, content),
+                            severity: Some(match forge_diag.level.as_ref() {
                                 "error" => DiagnosticSeverity::ERROR,
                                 "warning" => DiagnosticSeverity::WARNING,
                                 "note" => DiagnosticSeverity::INFORMATION,
@@ -39,14 +110,15 @@ pub fn lint_output_to_diagnostics(
                                 _ => DiagnosticSeverity::INFORMATION,
                             }),
                             code: forge_diag.code.as_ref().map(|c| {
-                                tower_lsp::lsp_types::NumberOrString::String(c.code.clone())
+                                tower_lsp::lsp_types::NumberOrString::String(c.code.to_string())
                             }),
                             code_description: None,
-                            source: Some("forge-lint".to_string()),
+                            source: Some(LINT_DIAGNOSTICS_SOURCE.to_string()),
                             message: format!("[forge lint] {}", forge_diag.message),
                             related_information: None,
                             tags: None,
-                            data: None,
+                            data: suggested_fix(&forge_diag, &in_target, content)
+                                .and_then(|fix| fix.to_data()),
                         };
                         diagnostics.push(diagnostic);
                         break; // Only take the first primary span per diagnostic
@@ -59,27 +131,39 @@ pub fn lint_output_to_diagnostics(
     diagnostics
 }
 
+/// A rustc-style JSON diagnostic emitted by `forge lint --json`.
+///
+/// String fields borrow from the parsed output where possible.
 #[derive(Debug, Deserialize, Serialize)]
-pub struct ForgeDiagnostic {
-    #[serde(rename = "$message_type")]
-    pub message_type: String,
-    pub message: String,
-    pub code: Option<ForgeLintCode>,
-    pub level: String,
-    pub spans: Vec<ForgeLintSpan>,
-    pub children: Vec<ForgeLintChild>,
-    pub rendered: Option<String>,
+pub struct ForgeDiagnostic<'a> {
+    #[serde(rename = "$message_type", borrow)]
+    pub message_type: Cow<'a, str>,
+    #[serde(borrow)]
+    pub message: Cow<'a, str>,
+    #[serde(borrow)]
+    pub code: Option<ForgeLintCode<'a>>,
+    #[serde(borrow)]
+    pub level: Cow<'a, str>,
+    #[serde(borrow)]
+    pub spans: Vec<ForgeLintSpan<'a>>,
+    #[serde(borrow)]
+    pub children: Vec<ForgeLintChild<'a>>,
+    #[serde(borrow)]
+    pub rendered: Option<Cow<'a, str>>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
-pub struct ForgeLintCode {
-    pub code: String,
-    pub explanation: Option<String>,
+pub struct ForgeLintCode<'a> {
+    #[serde(borrow)]
+    pub code: Cow<'a, str>,
+    #[serde(borrow)]
+    pub explanation: Option<Cow<'a, str>>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
-pub struct ForgeLintSpan {
-    pub file_name: String,
+pub struct ForgeLintSpan<'a> {
+    #[serde(borrow)]
+    pub file_name: Cow<'a, str>,
     pub byte_start: u32,
     pub byte_end: u32,
     pub line_start: u32,
@@ -87,25 +171,40 @@ pub struct ForgeLintSpan {
     pub column_start: u32,
     pub column_end: u32,
     pub is_primary: bool,
-    pub text: Vec<ForgeLintText>,
-    pub label: Option<String>,
+    #[serde(borrow)]
+    pub text: Vec<ForgeLintText<'a>>,
+    #[serde(borrow)]
+    pub label: Option<Cow<'a, str>>,
+    /// Replacement text for the span suggested by the lint.
+    #[serde(borrow, default)]
+    pub suggested_replacement: Option<Cow<'a, str>>,
+    /// How safe the suggestion is to apply, e.g. `MachineApplicable`.
+    #[serde(borrow, default)]
+    pub suggestion_applicability: Option<Cow<'a, str>>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
-pub struct ForgeLintText {
-    pub text: String,
+pub struct ForgeLintText<'a> {
+    #[serde(borrow)]
+    pub text: Cow<'a, str>,
     pub highlight_start: u32,
     pub highlight_end: u32,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
-pub struct ForgeLintChild {
-    pub message: String,
-    pub code: Option<String>,
-    pub level: String,
-    pub spans: Vec<ForgeLintSpan>,
-    pub children: Vec<ForgeLintChild>,
-    pub rendered: Option<String>,
+pub struct ForgeLintChild<'a> {
+    #[serde(borrow)]
+    pub message: Cow<'a, str>,
+    #[serde(borrow)]
+    pub code: Option<Cow<'a, str>>,
+    #[serde(borrow)]
+    pub level: Cow<'a, str>,
+    #[serde(borrow)]
+    pub spans: Vec<ForgeLintSpan<'a>>,
+    #[serde(borrow)]
+    pub children: Vec<ForgeLintChild<'a>>,
+    #[serde(borrow)]
+    pub rendered: Option<Cow<'a, str>>,
 }
 
 #[cfg(test)]
@@ -113,6 +212,7 @@ mod tests {
     use super::*;
     use crate::runner::{ForgeRunner, Runner};
     use std::fs;
+    use tower_lsp::lsp_types::{Position, TextEdit};
 
     static CONTRACT: &str = r#"// SPDX-License-Identifier: MIT
 pragma solidity ^0.8.29;
@@ -142,7 +242,7 @@ libs = ["lib"]
         let contract_path = src_dir.join("Contract.sol");
         fs::write(&contract_path, contents).expect("failed to write contract");
 
-        let compiler = ForgeRunner;
+        let compiler = ForgeRunner::default();
         (temp_dir, contract_path, compiler)
     }
 
@@ -167,7 +267,7 @@ libs = ["lib"]
         assert!(result.is_ok());
 
         let json_value = result.unwrap();
-        let diagnostics = lint_output_to_diagnostics(&json_value, &file_path);
+        let diagnostics = lint_output_to_diagnostics(&json_value, &file_path, CONTRACT);
         assert!(!diagnostics.is_empty(), "Expected diagnostics");
     }
 
@@ -180,7 +280,7 @@ libs = ["lib"]
         assert!(result.is_ok(), "Expected lint to succeed");
 
         let json_value = result.unwrap();
-        let diagnostics = lint_output_to_diagnostics(&json_value, &file_path);
+        let diagnostics = lint_output_to_diagnostics(&json_value, &file_path, CONTRACT);
         assert!(!diagnostics.is_empty(), "Expected at least one diagnostic");
 
         let first_diag = &diagnostics[0];
@@ -196,4 +296,111 @@ libs = ["lib"]
         assert_eq!(first_diag.range.start.line, 4);
         assert_eq!(first_diag.range.start.character, 13);
     }
+
+    #[test]
+    fn test_lint_output_to_diagnostics_borrowed_parse() {
+        let output = serde_json::json!([{
+            "$message_type": "diagnostic",
+            "message": "function names should use mixedCase",
+            "code": { "code": "mixed-case-function", "explanation": null },
+            "level": "note",
+            "spans": [{
+                "file_name": "src/Contract.sol",
+                "byte_start": 84,
+                "byte_end": 91,
+                "line_start": 5,
+                "line_end": 5,
+                "column_start": 14,
+                "column_end": 21,
+                "is_primary": true,
+                "text": [{ "text": "add_num", "highlight_start": 14, "highlight_end": 21 }],
+                "label": null
+            }],
+            "children": [],
+            "rendered": null
+        }]);
+
+        let diagnostics = lint_output_to_diagnostics(&output, "src/Contract.sol", CONTRACT);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].code,
+            Some(tower_lsp::lsp_types::NumberOrString::String(
+                "mixed-case-function".to_string()
+            ))
+        );
+        assert_eq!(diagnostics[0].range.start, Position::new(4, 13));
+
+        assert!(lint_output_to_diagnostics(&output, "src/Other.sol", CONTRACT).is_empty());
+    }
+
+    #[test]
+    fn test_lint_suggestions_as_fix() {
+        let span = |replacement: &str, applicability: &str| {
+            serde_json::json!({
+                "file_name": "src/Contract.sol",
+                "byte_start": 84,
+                "byte_end": 91,
+                "line_start": 5,
+                "line_end": 5,
+                "column_start": 14,
+                "column_end": 21,
+                "is_primary": true,
+                "text": [],
+                "label": null,
+                "suggested_replacement": replacement,
+                "suggestion_applicability": applicability
+            })
+        };
+        let output = serde_json::json!([{
+            "$message_type": "diagnostic",
+            "message": "function names should use mixedCase",
+            "code": { "code": "mixed-case-function", "explanation": null },
+            "level": "note",
+            "spans": [span("", "Unspecified")],
+            "children": [{
+                "message": "rename to `addNum`",
+                "code": null,
+                "level": "help",
+                "spans": [span("addNum", "MachineApplicable")],
+                "children": [],
+                "rendered": null
+            }, {
+                "message": "or remove it",
+                "code": null,
+                "level": "help",
+                "spans": [span("", "MaybeIncorrect")],
+                "children": [],
+                "rendered": null
+            }],
+            "rendered": null
+        }]);
+
+        let diagnostics = lint_output_to_diagnostics(&output, "src/Contract.sol", CONTRACT);
+        let fix = Fix::from_diagnostic(&diagnostics[0]).expect("machine-applicable fix");
+        assert_eq!(fix.title, "rename to `addNum`");
+        assert_eq!(
+            fix.edits,
+            [TextEdit {
+                range: Range::new(Position::new(4, 13), Position::new(4, 20)),
+                new_text: "addNum".to_string(),
+            }]
+        );
+
+        // Overlapping replacements keep only the first
+        let mut overlapping = output.clone();
+        overlapping[0]["children"][0]["spans"] = serde_json::json!([
+            span("addNum", "MachineApplicable"),
+            span("add", "MachineApplicable")
+        ]);
+        let diagnostics = lint_output_to_diagnostics(&overlapping, "src/Contract.sol", CONTRACT);
+        let fix = Fix::from_diagnostic(&diagnostics[0]).expect("machine-applicable fix");
+        assert_eq!(fix.edits.len(), 1);
+        assert_eq!(fix.edits[0].new_text, "addNum");
+
+        // Lints without suggestions carry no fix
+        let mut plain = output.clone();
+        plain[0]["children"] = serde_json::json!([]);
+        let diagnostics = lint_output_to_diagnostics(&plain, "src/Contract.sol", CONTRACT);
+        assert_eq!(diagnostics[0].data, None);
+    }
 }
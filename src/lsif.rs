@@ -0,0 +1,352 @@
+//! LSIF dump of definitions, references and hovers for a whole project.
+//!
+//! Code review tools and code search platforms consume LSIF offline instead of running a
+//! language server. The dump is written as JSON lines following the LSIF 0.6 format.
+//! See: <https://microsoft.github.io/language-server-protocol/specifications/lsif/0.6.0/specification/>
+
+use serde_json::{Value, json};
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{self, Write},
+    path::Path,
+};
+use tower_lsp::lsp_types::Range;
+
+use crate::{
+    ast::parse_src,
+    goto::{NodeInfo, bytes_to_pos},
+    hover, paths,
+    references::ReferenceIndex,
+};
+
+/// LSIF format version written in the `metaData` vertex.
+pub const LSIF_VERSION: &str = "0.6.0";
+
+/// Writes vertices and edges with sequential ids.
+struct LsifWriter<W> {
+    out: W,
+    next_id: u64,
+}
+
+impl<W: Write> LsifWriter<W> {
+    fn emit(&mut self, kind: &str, label: &str, mut fields: Value) -> io::Result<u64> {
+        let id = self.next_id;
+        self.next_id += 1;
+        fields["id"] = json!(id);
+        fields["type"] = json!(kind);
+        fields["label"] = json!(label);
+        serde_json::to_writer(&mut self.out, &fields)?;
+        self.out.write_all(b"\n")?;
+        Ok(id)
+    }
+
+    fn vertex(&mut self, label: &str, fields: Value) -> io::Result<u64> {
+        self.emit("vertex", label, fields)
+    }
+
+    fn edge(&mut self, label: &str, out_v: u64, in_v: u64) -> io::Result<u64> {
+        self.emit("edge", label, json!({ "outV": out_v, "inV": in_v }))
+    }
+
+    fn edge_many(&mut self, label: &str, out_v: u64, in_vs: &[u64]) -> io::Result<u64> {
+        self.emit("edge", label, json!({ "outV": out_v, "inVs": in_vs }))
+    }
+
+    fn item(
+        &mut self,
+        out_v: u64,
+        in_vs: &[u64],
+        document: u64,
+        property: Option<&str>,
+    ) -> io::Result<u64> {
+        let mut fields = json!({ "outV": out_v, "inVs": in_vs, "document": document });
+        if let Some(property) = property {
+            fields["property"] = json!(property);
+        }
+        self.emit("edge", "item", fields)
+    }
+}
+
+fn is_declaration(node: &NodeInfo) -> bool {
+    node.referenced_declaration.is_none()
+        && node.name_location.is_some()
+        && node.node_type.as_deref().is_some_and(|node_type| {
+            node_type.ends_with("Definition") || node_type == "VariableDeclaration"
+        })
+}
+
+/// Source range that names `node`: the member of a member access, the name of a
+/// declaration, or the whole node.
+fn name_src(node: &NodeInfo) -> &str {
+    node.member_location
+        .as_deref()
+        .or(node.name_location.as_deref())
+        .unwrap_or(&node.src)
+}
+
+fn to_range(source: &[u8], src: &str) -> Option<Range> {
+    let (start, length, _) = parse_src(src)?;
+    Some(Range {
+        start: bytes_to_pos(source, start)?,
+        end: bytes_to_pos(source, start + length)?,
+    })
+}
+
+/// A range vertex and the document it belongs to.
+#[derive(Clone, Copy)]
+struct RangeVertex {
+    id: u64,
+    document: u64,
+}
+
+/// Write an LSIF dump of `ast_data`, a project-wide AST, to `out`.
+pub fn write_dump(ast_data: &Value, project_root: &Path, out: impl Write) -> io::Result<()> {
+    let Some(index) = ReferenceIndex::new(ast_data) else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "AST output has no sources or build info",
+        ));
+    };
+    let mut writer = LsifWriter { out, next_id: 1 };
+
+    let project_root = paths::path_to_uri(project_root)
+        .map(|uri| uri.to_string())
+        .unwrap_or_default();
+    writer.vertex(
+        "metaData",
+        json!({
+            "version": LSIF_VERSION,
+            "projectRoot": project_root,
+            "positionEncoding": "utf-16",
+            "toolInfo": { "name": "forge-lsp", "version": env!("CARGO_PKG_VERSION") }
+        }),
+    )?;
+    let project = writer.vertex("project", json!({ "kind": "solidity" }))?;
+
+    // Sorted for reproducible dumps
+    let files: BTreeMap<&String, &HashMap<u64, NodeInfo>> = index.nodes.iter().collect();
+    let mut documents = Vec::new();
+    let mut ranges: HashMap<u64, RangeVertex> = HashMap::new();
+    let mut sources: HashMap<u64, String> = HashMap::new();
+
+    for (path, file_nodes) in files {
+        let Some(disk_path) = paths::resolve_source_path(path) else {
+            continue;
+        };
+        let (Ok(source), Some(uri)) = (
+            std::fs::read_to_string(&disk_path),
+            paths::path_to_uri(&disk_path),
+        ) else {
+            continue;
+        };
+        let document = writer.vertex(
+            "document",
+            json!({ "uri": uri.to_string(), "languageId": "solidity" }),
+        )?;
+        documents.push(document);
+
+        let mut node_ids: Vec<&u64> = file_nodes.keys().collect();
+        node_ids.sort();
+        let mut document_ranges = Vec::new();
+        for node_id in node_ids {
+            let node = &file_nodes[node_id];
+            if !is_declaration(node) && node.referenced_declaration.is_none() {
+                continue;
+            }
+            let Some(range) = to_range(source.as_bytes(), name_src(node)) else {
+                continue;
+            };
+            let id = writer.vertex("range", json!({ "start": range.start, "end": range.end }))?;
+            document_ranges.push(id);
+            ranges.insert(*node_id, RangeVertex { id, document });
+            if is_declaration(node) {
+                sources.insert(*node_id, source.clone());
+            }
+        }
+        if !document_ranges.is_empty() {
+            writer.edge_many("contains", document, &document_ranges)?;
+        }
+    }
+    if !documents.is_empty() {
+        writer.edge_many("contains", project, &documents)?;
+    }
+
+    // One result set per declaration, shared by the declaration and its references
+    let mut declarations: Vec<(u64, &NodeInfo)> = index
+        .nodes
+        .values()
+        .flat_map(|file_nodes| file_nodes.iter())
+        .filter(|(id, node)| is_declaration(node) && ranges.contains_key(id))
+        .map(|(id, node)| (*id, node))
+        .collect();
+    declarations.sort_by_key(|(id, _)| *id);
+
+    for (declaration_id, declaration) in declarations {
+        let definition = ranges[&declaration_id];
+        let result_set = writer.vertex("resultSet", json!({}))?;
+        writer.edge("next", definition.id, result_set)?;
+
+        let definition_result = writer.vertex("definitionResult", json!({}))?;
+        writer.edge("textDocument/definition", result_set, definition_result)?;
+        writer.item(
+            definition_result,
+            &[definition.id],
+            definition.document,
+            None,
+        )?;
+
+        let mut references: BTreeMap<u64, Vec<u64>> = BTreeMap::new();
+        for reference_id in index.all_refs.get(&declaration_id).into_iter().flatten() {
+            if let Some(reference) = ranges.get(reference_id)
+                && reference.id != definition.id
+            {
+                writer.edge("next", reference.id, result_set)?;
+                references
+                    .entry(reference.document)
+                    .or_default()
+                    .push(reference.id);
+            }
+        }
+        let reference_result = writer.vertex("referenceResult", json!({}))?;
+        writer.edge("textDocument/references", result_set, reference_result)?;
+        writer.item(
+            reference_result,
+            &[definition.id],
+            definition.document,
+            Some("definitions"),
+        )?;
+        for (document, mut range_ids) in references {
+            range_ids.sort();
+            range_ids.dedup();
+            writer.item(reference_result, &range_ids, document, Some("references"))?;
+        }
+
+        let preview = parse_src(&declaration.src).and_then(|(start, length, _)| {
+            hover::signature_preview(&sources[&declaration_id], start, length)
+        });
+        if let Some(preview) = preview {
+            let hover_result = writer.vertex(
+                "hoverResult",
+                json!({
+                    "result": {
+                        "contents": {
+                            "kind": "markdown",
+                            "value": format!("```solidity\n{preview}\n```")
+                        }
+                    }
+                }),
+            )?;
+            writer.edge("textDocument/hover", result_set, hover_result)?;
+        }
+    }
+
+    writer.out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast;
+    use tower_lsp::lsp_types::Url;
+
+    const SOURCE: &str = concat!(
+        "contract Counter {\n",
+        "    uint256 count;\n",
+        "    function inc() public {\n",
+        "        count += 1;\n",
+        "    }\n",
+        "}\n",
+    );
+
+    fn mock_ast(path: &str) -> Value {
+        let at = |needle: &str| SOURCE.find(needle).unwrap();
+        ast::mock_ast(
+            path,
+            json!({
+                "id": 1,
+                "src": format!("0:{}:0", SOURCE.len()),
+                "nodeType": "SourceUnit",
+                "absolutePath": path,
+                "nodes": [{
+                    "id": 2,
+                    "src": format!("0:{}:0", SOURCE.len() - 1),
+                    "nodeType": "ContractDefinition",
+                    "nameLocation": format!("{}:7:0", at("Counter")),
+                    "nodes": [{
+                        "id": 3,
+                        "src": format!("{}:13:0", at("uint256 count")),
+                        "nodeType": "VariableDeclaration",
+                        "nameLocation": format!("{}:5:0", at("count;"))
+                    }, {
+                        "id": 4,
+                        "src": format!("{}:5:0", at("count +=")),
+                        "nodeType": "Identifier",
+                        "referencedDeclaration": 3
+                    }]
+                }]
+            }),
+        )
+    }
+
+    #[test]
+    fn test_write_dump() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("Counter.sol");
+        std::fs::write(&path, SOURCE).unwrap();
+        let ast = mock_ast(path.to_str().unwrap());
+
+        let mut out = Vec::new();
+        write_dump(&ast, dir.path(), &mut out).unwrap();
+        let entries: Vec<Value> = out
+            .split(|b| *b == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| serde_json::from_slice(line).unwrap())
+            .collect();
+        let labelled = |label: &str| -> Vec<&Value> {
+            entries.iter().filter(|e| e["label"] == label).collect()
+        };
+
+        assert_eq!(labelled("metaData")[0]["version"], LSIF_VERSION);
+        let documents = labelled("document");
+        assert_eq!(documents.len(), 1);
+        assert_eq!(
+            documents[0]["uri"],
+            Url::from_file_path(&path).unwrap().to_string()
+        );
+
+        // `Counter`, `count` and the `count` reference
+        let ranges = labelled("range");
+        assert_eq!(ranges.len(), 3);
+        assert_eq!(labelled("resultSet").len(), 2);
+
+        // The reference and the declaration share the declaration's result set
+        let reference = ranges
+            .iter()
+            .find(|r| r["start"]["line"] == 3)
+            .expect("reference range");
+        let declaration = ranges
+            .iter()
+            .find(|r| r["start"]["line"] == 1)
+            .expect("declaration range");
+        let next_of = |range: &Value| {
+            entries
+                .iter()
+                .find(|e| e["label"] == "next" && e["outV"] == range["id"])
+                .map(|e| e["inV"].clone())
+        };
+        assert_eq!(next_of(reference), next_of(declaration));
+
+        let references = entries
+            .iter()
+            .find(|e| e["label"] == "item" && e["property"] == "references")
+            .unwrap();
+        assert_eq!(references["inVs"], json!([reference["id"]]));
+
+        let hovers = labelled("hoverResult");
+        assert!(
+            hovers
+                .iter()
+                .any(|h| h["result"]["contents"]["value"] == "```solidity\nuint256 count\n```")
+        );
+    }
+}
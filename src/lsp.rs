@@ -1,34 +1,139 @@
 use crate::{
-    goto, references, rename, symbols,
-    runner::{ForgeRunner, Runner},
-    utils,
+    analysis::{self, SourceFindings},
+    annotations::{self, Annotation, AnnotationsParams},
+    artifacts::{self, REBUILD_COMMAND, StaleArtifacts},
+    ast,
+    ast_provider::{AstProvider, AstResult},
+    baseline::{BASELINE_COMMAND, BASELINE_FILE, Baseline, Finding},
+    bindings::{self, GENERATE_BINDINGS_COMMAND},
+    build::{self, BUILD_DIAGNOSTICS_SOURCE},
+    build_info::{self, BuildInfoRunner, SizeLimit},
+    call_hierarchy, cheatcodes,
+    client_log::ClientLog,
+    code_actions, completion,
+    config::{DiagnosticsEvent, DiagnosticsSettings, ServerOptions, Settings, TestOnSaveMatch},
+    config_schema,
+    constructor_args::{self, CONSTRUCTOR_ARGUMENTS_CODE},
+    counterexamples, deploy_script,
+    docs::{self, DocPage, PREVIEW_DOCS_COMMAND},
+    documents::DocumentStore,
+    errors::RequestError,
+    events::{self, EXPORT_EVENTS_COMMAND, EventSchema, ExportEventsParams},
+    expand_type::{self, ExpandedType},
+    extract, fallback, file_renames,
+    fix_all::{self, FIX_ALL_COMMAND, FixAllParams, FixAllResult},
+    flatten::{self, FLATTEN_COMMAND, Flattened, LineMapping},
+    folding,
+    forge_test::{self, RUN_TEST_COMMAND, TEST_RUN_COMMAND, TestOutcome, TestRunParams},
+    formatting,
+    fuzz_config::{self, FuzzTargets, Fuzzer, OutputParser, RUN_FUZZER_COMMAND, fuzzer_config},
+    gas::{self, GasReport},
+    git::{self, HeadTracker},
+    goto, header, hover,
+    i18n::{self, Locale},
+    index::{self, ProjectIndex, WorkspaceIndex},
+    inlay_hints,
+    model_checker::{self, MODEL_CHECK_COMMAND},
+    named_args,
+    natspec::{self, NATSPEC_PARAM_CODE},
+    preview::{self, EditPreview, PreviewEditParams, PreviewFile},
+    profiles::{self, ServerStatus},
+    progress::ProgressReporter,
+    project::{self, ProjectConfig},
+    references::{self, GroupedReference},
+    rename::{self, RenameError, RenameScope, ScopedRenameParams},
+    roles::{self, RoleGraph},
+    runner::{CoalescingRunner, ForgeRunner, Runner, RunnerError, TestFilter},
+    selection,
+    selectors::{self, SELECTOR_IMPLEMENTATIONS_COMMAND},
+    semantic_tokens, stack_depth,
+    stale::{self, StaleIndex, StaleIndexParams},
+    storage_layout::{self, InspectedLayout, STORAGE_LAYOUT_COMMAND},
+    struct_literals::{self, STRUCT_FIELD_ORDER_CODE, STRUCT_MISSING_FIELDS_CODE},
+    suppressions, symbols,
+    syntax::{self, SyntaxTrees},
+    test_explorer::{
+        self, DiscoverTestsParams, TestResult, TestSuite, TestsChanged, TestsChangedParams,
+    },
+    test_names::{self, ResolveTestNameParams, TestName},
+    trust::{TrustedRunner, WorkspaceTrust},
+    tuples, utils,
+    verify::{self, VERIFICATION_BUNDLE_COMMAND, VerificationBundle, VerificationBundleParams},
+    watch,
+    workspace::WorkspaceFolders,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{
+    sync::{Mutex, RwLock, mpsc},
+    task::JoinHandle,
 };
-use std::{collections::HashMap, sync::Arc};
-use tokio::sync::RwLock;
 use tower_lsp::{Client, LanguageServer, lsp_types::*};
 
 pub type FileId = usize;
 
-fn byte_offset(content: &str, position: Position) -> Result<usize, String> {
-    let lines: Vec<&str> = content.lines().collect();
-    if position.line as usize >= lines.len() {
-        return Err("Line out of range".to_string());
-    }
-    let mut offset = 0;
-    (0..position.line as usize).for_each(|i| {
-        offset += lines[i].len() + 1; // +1 for \n
-    });
-    offset += position.character as usize;
-    if offset > content.len() {
-        return Err("Character out of range".to_string());
-    }
-    Ok(offset)
-}
+/// Runs build and lint diagnostics for the file URI given as the first argument.
+pub const RUN_DIAGNOSTICS_COMMAND: &str = "forge-lsp.runDiagnostics";
+
+/// Watcher glob for the Solidity sources of the workspace.
+const SOLIDITY_GLOB: &str = "**/*.sol";
+
+/// Drops every cache and re-runs diagnostics for the open documents.
+pub const RELOAD_WORKSPACE_COMMAND: &str = "forge-lsp.reloadWorkspace";
+
+/// Storage layouts of contracts, by project root and `path:Name`.
+type StorageLayouts = HashMap<(PathBuf, String), Arc<InspectedLayout>>;
 
+#[derive(Clone)]
 pub struct ForgeLsp {
     client: Client,
+    /// Log messages to the client, sent in the background.
+    logger: ClientLog,
     compiler: Arc<dyn Runner>,
-    ast_cache: Arc<RwLock<HashMap<String, serde_json::Value>>>,
+    /// Size of build output the compiler parses in full, set from the settings.
+    build_output_limit: SizeLimit,
+    /// Project-wide ASTs of the workspace's Foundry projects.
+    index: Arc<WorkspaceIndex>,
+    ast_provider: Arc<AstProvider>,
+    /// Contents of the documents open in the editor.
+    documents: Arc<DocumentStore>,
+    /// In-process parses of the documents, for features that need no compiler.
+    syntax_trees: Arc<SyntaxTrees>,
+    trust: Arc<WorkspaceTrust>,
+    settings: Arc<RwLock<Settings>>,
+    /// Folders of the workspace, each holding any number of Foundry projects.
+    folders: Arc<RwLock<WorkspaceFolders>>,
+    /// The `locale` of the client's `initialize`.
+    client_locale: Arc<RwLock<Option<String>>>,
+    /// Debounced diagnostics runs waiting for edits to settle, by document.
+    pending_diagnostics: Arc<Mutex<HashMap<Url, JoinHandle<()>>>>,
+    /// Diagnostics of the last build, lint and analysis run, by document.
+    diagnostics: Arc<Mutex<HashMap<Url, Vec<Diagnostic>>>>,
+    /// Results of the last test run of each test file: the failures of `forge-lsp.runTest`,
+    /// and the failures and passes of runs after saves.
+    test_failures: Arc<Mutex<HashMap<Url, Vec<Diagnostic>>>>,
+    /// Last seen `.git/HEAD` contents, to tell branch switches from other writes.
+    heads: Arc<Mutex<HeadTracker>>,
+    /// Recorded findings of each project, by root, read on first use.
+    baselines: Arc<Mutex<HashMap<PathBuf, Arc<Baseline>>>>,
+    /// Gas costs of each project, by root, measured on first use and again after saves.
+    gas_reports: Arc<Mutex<HashMap<PathBuf, Arc<GasReport>>>>,
+    /// `forge inspect` storage layouts, by project root and contract, until the next save.
+    storage_layouts: Arc<Mutex<StorageLayouts>>,
+    /// Findings of the last model checker run of each file.
+    model_findings: Arc<Mutex<HashMap<Url, Vec<Diagnostic>>>>,
+    /// Properties falsified by the last fuzzer run, by file of the property.
+    fuzz_findings: Arc<Mutex<HashMap<Url, Vec<Diagnostic>>>>,
+    /// Out-of-date artifacts of each project, by root, checked after each build of it.
+    stale_artifacts: Arc<Mutex<HashMap<PathBuf, Option<StaleArtifacts>>>>,
+    /// Hash of the ABIs of the last build of each project, by root.
+    abi_fingerprints: Arc<Mutex<HashMap<PathBuf, u64>>>,
+    /// Line mappings of the flattened views returned by the flatten command, by their URI.
+    flattened_views: Arc<Mutex<HashMap<Url, Vec<LineMapping>>>>,
 }
 
 #[allow(dead_code)]
@@ -41,363 +146,2941 @@ struct TextDocumentItem<'a> {
 
 impl ForgeLsp {
     pub fn new(client: Client) -> Self {
-        let compiler = Arc::new(ForgeRunner) as Arc<dyn Runner>;
-        let ast_cache = Arc::new(RwLock::new(HashMap::new()));
+        Self::with_options(client, ServerOptions::default())
+    }
+
+    pub fn with_options(client: Client, options: ServerOptions) -> Self {
+        let trust = Arc::new(WorkspaceTrust::new(client.clone()));
+        let build_output_limit = SizeLimit::default();
+        let compiler: Arc<dyn Runner> = if options.no_subprocess {
+            Arc::new(CoalescingRunner::new(BuildInfoRunner::new(
+                build_output_limit.clone(),
+            )))
+        } else {
+            Arc::new(CoalescingRunner::new(TrustedRunner::new(
+                ForgeRunner::new(build_output_limit.clone()),
+                trust.clone(),
+            )))
+        };
+        let index = Arc::new(WorkspaceIndex::new(compiler.clone()));
+        let ast_provider = Arc::new(AstProvider::with_index(compiler.clone(), index.clone()));
         Self {
+            logger: ClientLog::new(client.clone()),
             client,
             compiler,
-            ast_cache,
+            build_output_limit,
+            index,
+            ast_provider,
+            documents: Arc::new(DocumentStore::new()),
+            syntax_trees: Arc::new(SyntaxTrees::new()),
+            trust,
+            settings: Arc::new(RwLock::new(Settings::default())),
+            folders: Arc::new(RwLock::new(WorkspaceFolders::default())),
+            client_locale: Arc::new(RwLock::new(None)),
+            pending_diagnostics: Arc::new(Mutex::new(HashMap::new())),
+            diagnostics: Arc::new(Mutex::new(HashMap::new())),
+            test_failures: Arc::new(Mutex::new(HashMap::new())),
+            heads: Arc::new(Mutex::new(HeadTracker::default())),
+            baselines: Arc::new(Mutex::new(HashMap::new())),
+            gas_reports: Arc::new(Mutex::new(HashMap::new())),
+            storage_layouts: Arc::new(Mutex::new(HashMap::new())),
+            model_findings: Arc::new(Mutex::new(HashMap::new())),
+            fuzz_findings: Arc::new(Mutex::new(HashMap::new())),
+            stale_artifacts: Arc::new(Mutex::new(HashMap::new())),
+            abi_fingerprints: Arc::new(Mutex::new(HashMap::new())),
+            flattened_views: Arc::new(Mutex::new(HashMap::new())),
         }
     }
 
-    async fn on_change<'a>(&self, params: TextDocumentItem<'a>) {
-        let uri = params.uri.clone();
-        let version = params.version;
+    async fn apply_settings(&self, settings: Settings) {
+        if settings.trusted_workspace {
+            self.trust.grant().await;
+        }
+        self.logger.set_level(settings.log_level);
+        self.build_output_limit
+            .set(settings.large_files.build_output_bytes);
+        *self.settings.write().await = settings;
+    }
 
-        // Get file path for AST caching
-        let file_path = match uri.to_file_path() {
-            Ok(path) => path,
-            Err(_) => {
-                self.client
-                    .log_message(MessageType::ERROR, "Invalid file URI for AST caching")
-                    .await;
-                return;
+    /// Language of the server's messages, from the settings or the client.
+    async fn locale(&self) -> Locale {
+        let settings = self.settings.read().await.locale.clone();
+        let client = self.client_locale.read().await.clone();
+        Locale::negotiate([settings.as_deref(), client.as_deref()])
+    }
+
+    /// The AST of `uri`, fetching it on a miss. Failures the client can act on, like forge
+    /// missing or the file not compiling, are errors; others are logged and give `None`.
+    async fn request_ast(
+        &self,
+        uri: &Url,
+    ) -> tower_lsp::jsonrpc::Result<Option<Arc<serde_json::Value>>> {
+        match self.ast_provider.get_or_fetch(uri).await {
+            Ok(ast_data) => match RequestError::from_build(&ast_data, uri) {
+                Some(error) => Err(error.into()),
+                None => Ok(Some(ast_data)),
+            },
+            Err(e) => match RequestError::from_runner(uri, &e) {
+                Some(error) => Err(error.into()),
+                None => {
+                    self.logger
+                        .log(MessageType::ERROR, format!("Failed to get AST: {e}"));
+                    Ok(None)
+                }
+            },
+        }
+    }
+
+    /// Read the document, preferring unsaved edits over the file on disk, and check that
+    /// `position` is within it. A document that can't be read is logged and gives `None`.
+    async fn read_document(
+        &self,
+        uri: &Url,
+        position: Position,
+    ) -> tower_lsp::jsonrpc::Result<Option<Vec<u8>>> {
+        let source_bytes = match self.documents.read(uri).await {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                self.logger
+                    .log(MessageType::ERROR, format!("Failed to read file: {e}"));
+                return Ok(None);
             }
         };
+        RequestError::check_position(uri, &source_bytes, position)?;
+        Ok(Some(source_bytes))
+    }
 
-        let path_str = match file_path.to_str() {
-            Some(s) => s,
-            None => {
-                self.client
-                    .log_message(MessageType::ERROR, "Invalid file path for AST caching")
-                    .await;
-                return;
+    /// Read the document and get an AST whose offsets match it, logging why either failed
+    /// unless the AST is an error. Forge compiles the file on disk, so a document with
+    /// unsaved edits gets [`Self::current_ast`] instead of a build of the older text.
+    async fn source_and_ast(
+        &self,
+        uri: &Url,
+    ) -> tower_lsp::jsonrpc::Result<Option<(Vec<u8>, Arc<serde_json::Value>)>> {
+        let source_bytes = match self.documents.read(uri).await {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                self.logger
+                    .log(MessageType::ERROR, format!("Failed to read file: {e}"));
+                return Ok(None);
             }
         };
+        let on_disk = match uri.to_file_path() {
+            Ok(path) => tokio::fs::read(&path).await.ok(),
+            Err(()) => None,
+        };
+        let ast_data = if on_disk.as_deref() == Some(source_bytes.as_slice()) {
+            self.request_ast(uri).await?
+        } else {
+            self.current_ast(uri, &source_bytes).await
+        };
+        Ok(ast_data.map(|ast_data| (source_bytes, ast_data)))
+    }
 
-        let (lint_result, build_result, ast_result) = tokio::join!(
-            self.compiler.get_lint_diagnostics(&uri),
-            self.compiler.get_build_diagnostics(&uri),
-            self.compiler.ast(path_str)
-        );
+    /// The AST of the whole project of `uri` when it is indexed, or else of the build of
+    /// `uri`, as [`Self::request_ast`].
+    async fn project_ast(
+        &self,
+        uri: &Url,
+    ) -> tower_lsp::jsonrpc::Result<Option<Arc<serde_json::Value>>> {
+        if let Some(project) = self.index.project_for(uri).await {
+            return Ok(Some(project.ast.clone()));
+        }
+        self.request_ast(uri).await
+    }
 
-        // Cache the AST data
-        if let Ok(ast_data) = ast_result {
-            let mut cache = self.ast_cache.write().await;
-            cache.insert(uri.to_string(), ast_data);
-            self.client
-                .log_message(MessageType::INFO, "AST data cached successfully")
-                .await;
-        } else if let Err(e) = ast_result {
-            self.client
-                .log_message(
-                    MessageType::WARNING,
-                    format!("Failed to cache AST data: {e}"),
-                )
-                .await;
+    /// Root of the Foundry project of `uri`, or the workspace folder containing it when it
+    /// is in none.
+    async fn project_root(&self, uri: &Url) -> Option<PathBuf> {
+        let path = uri.to_file_path().ok()?;
+        match build_info::find_project_root(&path) {
+            Some(root) => Some(root),
+            None => self.folders.read().await.folder_for(&path),
         }
+    }
 
-        let mut all_diagnostics = vec![];
+    /// Read the document and parse it in process. Unlike [`Self::source_and_ast`] this never
+    /// runs the compiler.
+    async fn source_and_syntax(&self, uri: &Url) -> Option<(Vec<u8>, Arc<serde_json::Value>)> {
+        let source_bytes = self.documents.read(uri).await.ok()?;
+        let tree = self.syntax_trees.get(uri, &source_bytes).await?;
+        Some((source_bytes, tree))
+    }
 
-        match lint_result {
-            Ok(mut lints) => {
-                self.client
-                    .log_message(
-                        MessageType::INFO,
-                        format!("Found {} linting diagnostics", lints.len()),
-                    )
-                    .await;
-                all_diagnostics.append(&mut lints);
-            }
-            Err(e) => {
-                self.client
-                    .log_message(
-                        MessageType::WARNING,
-                        format!("Forge linting diagnostics failed: {e}"),
-                    )
-                    .await;
-            }
+    /// An AST whose offsets match the buffer `source_bytes` of `uri`, without compiling: the
+    /// indexed project when it was compiled from the same text, else the build cached since
+    /// the last edit, else the in-process parse.
+    async fn current_ast(&self, uri: &Url, source_bytes: &[u8]) -> Option<Arc<serde_json::Value>> {
+        if let Some(project) = self.index.project_for(uri).await
+            && project.content_hash(uri) == Some(index::content_hash(source_bytes))
+        {
+            return Some(project.ast.clone());
         }
+        match self.ast_provider.get(uri).await {
+            Some(ast_data) => Some(ast_data),
+            None => self.syntax_trees.get(uri, source_bytes).await,
+        }
+    }
 
-        match build_result {
-            Ok(mut builds) => {
-                self.client
-                    .log_message(
-                        MessageType::INFO,
-                        format!("Found {} build diagnostics", builds.len()),
-                    )
-                    .await;
-                all_diagnostics.append(&mut builds);
-            }
-            Err(e) => {
-                self.client
-                    .log_message(
-                        MessageType::WARNING,
-                        format!("Forge build diagnostics failed: {e}"),
-                    )
-                    .await;
+    /// The files `files` of the results of a `method` request in `uri` that changed since
+    /// the AST answering it was built, reported to the client with a `forge-lsp/staleIndex`
+    /// notification when there are any.
+    async fn report_stale(&self, method: &str, uri: &Url, files: Vec<Url>) -> Vec<Url> {
+        let mut stale_files = Vec::new();
+        for file in files {
+            let Ok(current) = self.documents.read(&file).await else {
+                continue;
+            };
+            let built = self.index.content_hash(&file).await;
+            let on_disk = || std::fs::read(file.to_file_path().ok()?).ok();
+            if stale::is_stale(&current, built, on_disk) {
+                stale_files.push(file);
             }
         }
-
-        self.client
-            .publish_diagnostics(uri, all_diagnostics, version)
-            .await;
+        if !stale_files.is_empty() {
+            self.logger.log(
+                MessageType::INFO,
+                format!(
+                    "{method} answered from an outdated index for {} files",
+                    stale_files.len()
+                ),
+            );
+            self.client
+                .send_notification::<StaleIndex>(StaleIndexParams {
+                    method: method.to_string(),
+                    uri: uri.clone(),
+                    stale_files: stale_files.clone(),
+                })
+                .await;
+        }
+        stale_files
     }
 
-    async fn apply_workspace_edit(&self, workspace_edit: &WorkspaceEdit) -> Result<(), String> {
-        if let Some(changes) = &workspace_edit.changes {
-            for (uri, edits) in changes {
-                let path = uri.to_file_path().map_err(|_| "Invalid URI".to_string())?;
-                let mut content = std::fs::read_to_string(&path).map_err(|e| e.to_string())?;
+    /// Versions of the open documents whose text their AST was built from. Edits of the
+    /// others are computed from offsets of an outdated text, so they carry no version
+    /// rather than the version of a buffer they weren't made against.
+    async fn built_versions(&self) -> HashMap<Url, i32> {
+        let mut versions = HashMap::new();
+        for (file, version) in self.documents.versions().await {
+            let Ok(current) = self.documents.read(&file).await else {
+                continue;
+            };
+            let built = self.index.content_hash(&file).await;
+            let on_disk = || std::fs::read(file.to_file_path().ok()?).ok();
+            if !stale::is_stale(&current, built, on_disk) {
+                versions.insert(file, version);
+            }
+        }
+        versions
+    }
 
-                // Sort edits by start position descending to avoid offset issues
-                let mut sorted_edits = edits.clone();
-                sorted_edits.sort_by(|a, b| b.range.start.cmp(&a.range.start));
+    /// The in-process parse of `uri` and the index of its project, when `uri` is a Foundry
+    /// script. The script itself need not be indexed yet.
+    async fn script_syntax(
+        &self,
+        uri: &Url,
+    ) -> Option<(Vec<u8>, Arc<serde_json::Value>, Arc<ProjectIndex>)> {
+        let path = uri.to_file_path().ok()?;
+        let config = ProjectConfig::find(&path).filter(|config| config.is_script(&path))?;
+        let project = match self.index.project_for(uri).await {
+            Some(project) => project,
+            None => self
+                .index
+                .projects()
+                .await
+                .into_iter()
+                .find(|project| project.root == config.root)?,
+        };
+        let (source_bytes, tree) = self.source_and_syntax(uri).await?;
+        Some((source_bytes, tree, project))
+    }
 
-                for edit in sorted_edits {
-                    let start_byte = byte_offset(&content, edit.range.start)?;
-                    let end_byte = byte_offset(&content, edit.range.end)?;
-                    content.replace_range(start_byte..end_byte, &edit.new_text);
-                }
+    /// Checks of the struct literals and the `@param` tags of `uri` in its in-process parse.
+    async fn syntax_checks(&self, uri: &Url) -> Vec<Diagnostic> {
+        let Some((source_bytes, tree)) = self.source_and_syntax(uri).await else {
+            return vec![];
+        };
+        let project = self.index.project_for(uri).await;
+        let mut checks = struct_literals::struct_literal_diagnostics(
+            &tree,
+            uri,
+            &source_bytes,
+            project.as_ref().map(|project| project.ast.as_ref()),
+        );
+        checks.extend(natspec::param_tag_diagnostics(&tree, uri, &source_bytes));
+        checks
+    }
 
-                std::fs::write(&path, &content).map_err(|e| e.to_string())?;
+    /// Check the struct literals and `@param` tags of `uri`, and the constructor arguments of
+    /// its `new` calls when it is a script, right after an edit, replacing the previous checks
+    /// among its last diagnostics. The next compile replaces the constructor checks with the
+    /// compiler's errors and runs the other checks again.
+    async fn check_while_editing(&self, uri: &Url, version: i32) {
+        let mut checks = self.syntax_checks(uri).await;
+        if let Some((source_bytes, tree, project)) = self.script_syntax(uri).await {
+            checks.extend(constructor_args::argument_count_diagnostics(
+                &tree,
+                uri,
+                &source_bytes,
+                &project.ast,
+            ));
+        }
+        if let Ok(source_bytes) = self.documents.read(uri).await {
+            suppressions::retain_unsuppressed(&mut checks, &String::from_utf8_lossy(&source_bytes));
+        }
+        let codes: Vec<Option<NumberOrString>> = [
+            CONSTRUCTOR_ARGUMENTS_CODE,
+            STRUCT_MISSING_FIELDS_CODE,
+            STRUCT_FIELD_ORDER_CODE,
+            NATSPEC_PARAM_CODE,
+        ]
+        .into_iter()
+        .map(|code| Some(NumberOrString::String(code.to_string())))
+        .collect();
+        {
+            let mut diagnostics = self.diagnostics.lock().await;
+            let last = diagnostics.entry(uri.clone()).or_default();
+            if checks.is_empty()
+                && last
+                    .iter()
+                    .all(|diagnostic| !codes.contains(&diagnostic.code))
+            {
+                return;
             }
+            last.retain(|diagnostic| !codes.contains(&diagnostic.code));
+            last.extend(checks);
         }
-        Ok(())
+        self.publish_diagnostics(uri.clone(), Some(version)).await;
     }
-}
 
-#[tower_lsp::async_trait]
-impl LanguageServer for ForgeLsp {
-    async fn initialize(
+    /// Handler for the `forge-lsp/expandType` custom request.
+    pub async fn expand_type(
         &self,
-        _: InitializeParams,
-    ) -> tower_lsp::jsonrpc::Result<InitializeResult> {
-        Ok(InitializeResult {
-            server_info: Some(ServerInfo {
-                name: "forge lsp".to_string(),
-                version: Some("0.0.1".to_string()),
-            }),
-            capabilities: ServerCapabilities {
-                definition_provider: Some(OneOf::Left(true)),
-                declaration_provider: Some(DeclarationCapability::Simple(true)),
-                references_provider: Some(OneOf::Left(true)),
-                rename_provider: Some(OneOf::Left(true)),
-                workspace_symbol_provider: Some(OneOf::Left(true)),
-                document_symbol_provider: Some(OneOf::Left(true)),
-                text_document_sync: Some(TextDocumentSyncCapability::Kind(
-                    TextDocumentSyncKind::FULL,
-                )),
-                ..ServerCapabilities::default()
-            },
-        })
+        params: TextDocumentPositionParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<ExpandedType>> {
+        self.logger
+            .log(MessageType::INFO, "Got a forge-lsp/expandType request");
+
+        let uri = params.text_document.uri;
+        let Some((source_bytes, ast_data)) = self.source_and_ast(&uri).await? else {
+            return Ok(None);
+        };
+        RequestError::check_position(&uri, &source_bytes, params.position)?;
+        Ok(expand_type::expand_type(
+            &ast_data,
+            &uri,
+            params.position,
+            &source_bytes,
+        ))
     }
 
-    async fn initialized(&self, _: InitializedParams) {
-        self.client
-            .log_message(MessageType::INFO, "lsp server initialized!")
+    /// Handler for the `forge-lsp/groupedReferences` custom request.
+    pub async fn grouped_references(
+        &self,
+        params: ReferenceParams,
+    ) -> tower_lsp::jsonrpc::Result<Vec<GroupedReference>> {
+        self.logger.log(
+            MessageType::INFO,
+            "Got a forge-lsp/groupedReferences request",
+        );
+
+        let uri = params.text_document_position.text_document.uri;
+        let position = params.text_document_position.position;
+        let Ok(source_bytes) = self.documents.read(&uri).await else {
+            return Ok(vec![]);
+        };
+        RequestError::check_position(&uri, &source_bytes, position)?;
+        let Some(ast_data) = self.request_ast(&uri).await? else {
+            return Ok(vec![]);
+        };
+        let documents = self.documents.snapshot().await;
+        let mut references =
+            references::grouped_references(&ast_data, &uri, position, &source_bytes, &documents);
+        let locations: Vec<Location> = references
+            .iter()
+            .map(|reference| reference.location.clone())
+            .collect();
+        let stale_files = self
+            .report_stale(
+                references::GROUPED_REFERENCES_METHOD,
+                &uri,
+                stale::result_files(&uri, &locations),
+            )
             .await;
+        for reference in &mut references {
+            reference.stale = stale_files.contains(&reference.location.uri);
+        }
+        Ok(references)
     }
 
-    async fn shutdown(&self) -> tower_lsp::jsonrpc::Result<()> {
-        self.client
-            .log_message(MessageType::INFO, "lsp server shutting down")
-            .await;
-        Ok(())
+    /// Handler for the `forge-lsp/status` custom request.
+    pub async fn status(&self) -> tower_lsp::jsonrpc::Result<ServerStatus> {
+        self.logger
+            .log(MessageType::INFO, "Got a forge-lsp/status request");
+
+        let roots = self.folders.read().await.projects();
+        Ok(profiles::status(&roots))
     }
 
-    async fn did_open(&self, params: DidOpenTextDocumentParams) {
-        self.client
-            .log_message(MessageType::INFO, "file opened")
-            .await;
+    /// Handler for the `forge-lsp/configurationSchema` custom request.
+    pub async fn configuration_schema(&self) -> tower_lsp::jsonrpc::Result<serde_json::Value> {
+        self.logger.log(
+            MessageType::INFO,
+            "Got a forge-lsp/configurationSchema request",
+        );
 
-        self.on_change(TextDocumentItem {
-            uri: params.text_document.uri,
-            text: &params.text_document.text,
-            version: Some(params.text_document.version),
-        })
-        .await
+        Ok(config_schema::configuration_schema())
     }
 
-    async fn did_change(&self, params: DidChangeTextDocumentParams) {
-        self.client
-            .log_message(MessageType::INFO, "file changed")
-            .await;
+    /// Handler for the `forge-lsp/roleGraph` custom request.
+    pub async fn role_graph(&self) -> tower_lsp::jsonrpc::Result<RoleGraph> {
+        self.logger
+            .log(MessageType::INFO, "Got a forge-lsp/roleGraph request");
 
-        // Invalidate cached AST data for the changed file
-        let uri = params.text_document.uri;
-        let mut cache = self.ast_cache.write().await;
-        if cache.remove(&uri.to_string()).is_some() {
-            self.client
-                .log_message(
-                    MessageType::INFO,
-                    "Invalidated cached AST data for changed file",
-                )
-                .await;
+        if self.index.projects().await.is_empty() {
+            self.index_workspace(true).await;
         }
+        let mut graph = RoleGraph::default();
+        for project in self.index.projects().await {
+            graph
+                .roles
+                .extend(roles::role_graph(&project.ast, &project.root).roles);
+        }
+        Ok(graph)
     }
 
-    async fn did_save(&self, params: DidSaveTextDocumentParams) {
-        self.client
-            .log_message(MessageType::INFO, "file saved - running diagnostics")
-            .await;
+    /// Handler for the `forge-lsp/resolveTestName` custom request.
+    pub async fn resolve_test_name(
+        &self,
+        params: ResolveTestNameParams,
+    ) -> tower_lsp::jsonrpc::Result<Vec<Location>> {
+        self.logger
+            .log(MessageType::INFO, "Got a forge-lsp/resolveTestName request");
 
-        // Run diagnostics on save, regardless of whether text is provided
-        // If text is provided, use it; otherwise read from file system
-        let text_content = if let Some(text) = params.text {
-            text
-        } else {
-            // Read the file from disk since many LSP clients don't send text on save
-            match std::fs::read_to_string(params.text_document.uri.path()) {
-                Ok(content) => content,
-                Err(e) => {
-                    self.client
-                        .log_message(
-                            MessageType::ERROR,
-                            format!("Failed to read file on save: {e}"),
-                        )
-                        .await;
-                    return;
-                }
-            }
+        let Some(name) = TestName::parse(&params.name) else {
+            return Ok(vec![]);
         };
+        if self.index.projects().await.is_empty() {
+            self.index_workspace(true).await;
+        }
+        Ok(self
+            .index
+            .projects()
+            .await
+            .iter()
+            .flat_map(|project| test_names::resolve_test_name(&project.ast, &project.root, &name))
+            .collect())
+    }
 
-        let item = TextDocumentItem {
-            uri: params.text_document.uri,
-            text: &text_content,
-            version: None,
-        };
+    /// Handler for the `forgeLsp/discoverTests` custom request.
+    pub async fn discover_tests(
+        &self,
+        params: DiscoverTestsParams,
+    ) -> tower_lsp::jsonrpc::Result<Vec<TestSuite>> {
+        self.logger
+            .log(MessageType::INFO, "Got a forgeLsp/discoverTests request");
 
-        // Always run diagnostics on save to reflect the current file state
-        self.on_change(item).await;
-        _ = self.client.semantic_tokens_refresh().await;
+        if let Some(uri) = &params.uri
+            && self.project_root(uri).await.is_none()
+        {
+            return Err(RequestError::OutsideWorkspace { uri: uri.clone() }.into());
+        }
+        if self.index.projects().await.is_empty() {
+            self.index_workspace(true).await;
+        }
+        let mut suites: Vec<TestSuite> = self
+            .index
+            .projects()
+            .await
+            .iter()
+            .flat_map(|project| test_explorer::discover_tests(&project.ast, &project.root))
+            .collect();
+        if let Some(uri) = &params.uri {
+            suites.retain(|suite| suite.location.uri == *uri);
+        }
+        Ok(suites)
     }
 
-    async fn did_close(&self, _: DidCloseTextDocumentParams) {
-        self.client
-            .log_message(MessageType::INFO, "file closed")
-            .await;
-    }
+    /// Handler for the `forgeLsp/runTests` custom request.
+    pub async fn run_tests(
+        &self,
+        params: TestRunParams,
+    ) -> tower_lsp::jsonrpc::Result<Vec<TestResult>> {
+        self.logger
+            .log(MessageType::INFO, "Got a forgeLsp/runTests request");
 
-    async fn did_change_configuration(&self, _: DidChangeConfigurationParams) {
-        self.client
-            .log_message(MessageType::INFO, "configuration changed!")
+        let output = self.stream_tests(&params).await?;
+        self.publish_test_failures(params.uri, &forge_test::outcomes(&output))
             .await;
+        Ok(test_explorer::test_results(&output))
     }
 
-    async fn did_change_workspace_folders(&self, _: DidChangeWorkspaceFoldersParams) {
-        self.client
-            .log_message(MessageType::INFO, "workspace folders changed!")
-            .await;
+    /// Handler for the `forge-lsp/scopedRename` custom request.
+    pub async fn scoped_rename(
+        &self,
+        params: ScopedRenameParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<WorkspaceEdit>> {
+        self.logger
+            .log(MessageType::INFO, "Got a forge-lsp/scopedRename request");
+        self.rename_edit(&params.rename, params.scope).await
     }
 
-    async fn did_change_watched_files(&self, _: DidChangeWatchedFilesParams) {
-        self.client
-            .log_message(MessageType::INFO, "watched files have changed!")
-            .await;
+    /// Handler for the `forge-lsp/previewEdit` custom request.
+    pub async fn preview_edit(
+        &self,
+        params: PreviewEditParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<EditPreview>> {
+        self.logger
+            .log(MessageType::INFO, "Got a forge-lsp/previewEdit request");
+
+        let edit = match params {
+            PreviewEditParams::Rename(params) => {
+                match self.rename_edit(&params, RenameScope::Workspace).await? {
+                    Some(edit) => edit,
+                    None => return Ok(None),
+                }
+            }
+            PreviewEditParams::Edit(edit) => edit,
+        };
+        let changes = preview::text_edits(edit);
+
+        let folders = self.folders.read().await.clone();
+        let mut texts = Vec::new();
+        for (uri, edits) in &changes {
+            let Ok(source_bytes) = self.documents.read(uri).await else {
+                return Err(tower_lsp::jsonrpc::Error::invalid_params(format!(
+                    "Cannot read {uri}"
+                )));
+            };
+            let path = uri
+                .to_file_path()
+                .map(|path| match folders.folder_for(&path) {
+                    Some(folder) => path
+                        .strip_prefix(folder)
+                        .unwrap_or(&path)
+                        .display()
+                        .to_string(),
+                    None => path.display().to_string(),
+                })
+                .unwrap_or_else(|_| uri.to_string());
+            let text = String::from_utf8_lossy(&source_bytes).into_owned();
+            texts.push((path, text, edits));
+        }
+
+        let files = texts
+            .iter()
+            .map(|(path, text, edits)| PreviewFile {
+                path: path.clone(),
+                text,
+                edits,
+            })
+            .collect();
+        preview::preview(files)
+            .map(Some)
+            .map_err(|e| tower_lsp::jsonrpc::Error::invalid_params(e.to_string()))
     }
 
-    async fn goto_definition(
+    /// Handler for the `forge-lsp/annotations` custom request.
+    pub async fn annotations(
         &self,
-        params: GotoDefinitionParams,
-    ) -> tower_lsp::jsonrpc::Result<Option<GotoDefinitionResponse>> {
-        self.client
-            .log_message(MessageType::INFO, "Got a textDocument/definition request")
-            .await;
+        params: AnnotationsParams,
+    ) -> tower_lsp::jsonrpc::Result<Vec<Annotation>> {
+        self.logger
+            .log(MessageType::INFO, "Got a forge-lsp/annotations request");
 
-        let uri = params.text_document_position_params.text_document.uri;
-        let position = params.text_document_position_params.position;
+        if let Some(document) = params.text_document {
+            let uri = document.uri;
+            let Some((source_bytes, ast_data)) = self.source_and_ast(&uri).await? else {
+                return Ok(vec![]);
+            };
+            return Ok(annotations::annotations(
+                Some(&ast_data),
+                &uri,
+                &source_bytes,
+            ));
+        }
 
-        // Get the file path from URI
-        let file_path = match uri.to_file_path() {
-            Ok(path) => path,
-            Err(_) => {
-                self.client
-                    .log_message(MessageType::ERROR, "Invalid file URI")
-                    .await;
-                return Ok(None);
+        let roots = self.folders.read().await.projects();
+        let mut found = vec![];
+        for root in roots {
+            // Without an AST the annotations are still listed, just not anchored to symbols
+            let ast_data = match self.index.get_or_build(&root).await {
+                Ok(project) => Some(project.ast.clone()),
+                Err(e) => {
+                    self.logger.log(
+                        MessageType::WARNING,
+                        format!("Failed to get AST data for annotations: {e}"),
+                    );
+                    None
+                }
+            };
+            for path in annotations::project_sources(&root) {
+                let Ok(uri) = Url::from_file_path(&path) else {
+                    continue;
+                };
+                if let Ok(source_bytes) = self.documents.read(&uri).await {
+                    found.extend(annotations::annotations(
+                        ast_data.as_deref(),
+                        &uri,
+                        &source_bytes,
+                    ));
+                }
             }
-        };
+        }
+        Ok(found)
+    }
 
-        // Read the source file
-        let source_bytes = match std::fs::read(&file_path) {
+    /// Functions across the workspace sharing the selector of the function at `position`.
+    async fn selector_implementations(&self, uri: &Url, position: Position) -> Vec<Location> {
+        let source_bytes = match self.documents.read(uri).await {
             Ok(bytes) => bytes,
             Err(e) => {
-                self.client
-                    .log_message(MessageType::ERROR, format!("Failed to read file: {e}"))
-                    .await;
-                return Ok(None);
+                self.logger
+                    .log(MessageType::ERROR, format!("Failed to read file: {e}"));
+                return vec![];
             }
         };
+        let Some(root) = self.project_root(uri).await else {
+            self.logger.log(
+                MessageType::ERROR,
+                format!("{uri} is outside of the workspace"),
+            );
+            return vec![];
+        };
 
-        // Try to get AST data from cache first
-        let ast_data = {
-            let cache = self.ast_cache.read().await;
-            if let Some(cached_ast) = cache.get(&uri.to_string()) {
-                self.client
-                    .log_message(MessageType::INFO, "Using cached AST data")
-                    .await;
-                cached_ast.clone()
-            } else {
-                // Cache miss - get AST data and cache it
-                drop(cache); // Release read lock
-
-                let path_str = match file_path.to_str() {
-                    Some(s) => s,
-                    None => {
-                        self.client
-                            .log_message(MessageType::ERROR, "Invalid file path")
-                            .await;
-                        return Ok(None);
-                    }
+        match self.index.get_or_build(&root).await {
+            Ok(project) => {
+                selectors::selector_implementations(&project.ast, uri, position, &source_bytes)
+            }
+            Err(e) => {
+                self.logger.log(
+                    MessageType::WARNING,
+                    format!("Failed to get AST data for selector search: {e}"),
+                );
+                vec![]
+            }
+        }
+    }
+
+    /// Generate the documentation of `uri` with `forge doc` and return the pages of the
+    /// contract at `position`, or all pages of the file.
+    async fn preview_docs(
+        &self,
+        uri: &Url,
+        position: Option<Position>,
+    ) -> tower_lsp::jsonrpc::Result<Vec<DocPage>> {
+        let internal_error = |message: String| tower_lsp::jsonrpc::Error {
+            code: tower_lsp::jsonrpc::ErrorCode::InternalError,
+            message: message.into(),
+            data: None,
+        };
+
+        let path = uri.to_file_path().map_err(|_| {
+            tower_lsp::jsonrpc::Error::invalid_params(format!("{uri} is not a file URI"))
+        })?;
+        let outside = || RequestError::OutsideWorkspace { uri: uri.clone() };
+        let root = self.project_root(uri).await.ok_or_else(outside)?;
+        let relative = path.strip_prefix(&root).map_err(|_| outside())?;
+
+        let out = tempfile::tempdir()
+            .map_err(|e| internal_error(format!("Failed to create output directory: {e}")))?;
+        self.compiler
+            .doc(&root.to_string_lossy(), &out.path().to_string_lossy())
+            .await
+            .map_err(|e| internal_error(format!("Failed to generate documentation: {e}")))?;
+        let pages = docs::read_pages(&docs::pages_dir(out.path(), relative)).map_err(|e| {
+            internal_error(format!(
+                "No documentation generated for {}: {e}",
+                relative.display()
+            ))
+        })?;
+
+        // Without a position every page of the file is returned
+        let source = match position {
+            Some(_) => self.documents.read(uri).await.ok(),
+            None => None,
+        }
+        .map(|bytes| String::from_utf8_lossy(&bytes).into_owned());
+        let contract = source
+            .as_deref()
+            .zip(position)
+            .and_then(|(source, position)| {
+                let offset =
+                    utils::position_to_byte_offset(source, position.line, position.character);
+                docs::contract_at(source, offset)
+            });
+        Ok(docs::select_pages(pages, contract))
+    }
+
+    async fn flatten_file(&self, uri: &Url) -> tower_lsp::jsonrpc::Result<Flattened> {
+        let internal_error = |message: String| tower_lsp::jsonrpc::Error {
+            code: tower_lsp::jsonrpc::ErrorCode::InternalError,
+            message: message.into(),
+            data: None,
+        };
+
+        let path = uri.to_file_path().map_err(|_| {
+            tower_lsp::jsonrpc::Error::invalid_params(format!("{uri} is not a file URI"))
+        })?;
+        let root = self
+            .project_root(uri)
+            .await
+            .ok_or_else(|| RequestError::OutsideWorkspace { uri: uri.clone() })?;
+        let view = flatten::flattened_uri(uri)
+            .ok_or_else(|| internal_error(format!("No flattened view for {uri}")))?;
+        let source = self
+            .compiler
+            .flatten(&root.to_string_lossy(), &path.to_string_lossy())
+            .await
+            .map_err(|e| internal_error(format!("Failed to flatten {}: {e}", path.display())))?;
+        let mappings =
+            flatten::line_mappings(&source, &root, |path| std::fs::read_to_string(path).ok());
+        self.flattened_views
+            .lock()
+            .await
+            .insert(view.clone(), mappings.clone());
+        Ok(Flattened {
+            uri: view,
+            source,
+            mappings,
+        })
+    }
+
+    /// The file and position a position of a flattened view was copied from, or `uri` and
+    /// `position` themselves in other documents.
+    async fn flattened_origin(&self, uri: Url, position: Position) -> (Url, Position) {
+        let views = self.flattened_views.lock().await;
+        views
+            .get(&uri)
+            .and_then(|mappings| flatten::original_position(mappings, position))
+            .unwrap_or((uri, position))
+    }
+
+    /// The buffer of `uri` and its `forge fmt` output, logging why when it cannot be formatted.
+    async fn format_buffer(&self, uri: &Url) -> Option<(String, String)> {
+        let source = match self.documents.read(uri).await {
+            Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+            Err(e) => {
+                self.logger
+                    .log(MessageType::ERROR, format!("Failed to read file: {e}"));
+                return None;
+            }
+        };
+        let path = uri.to_file_path().ok()?;
+        // Format with the settings of the project the file belongs to
+        let root = build_info::find_project_root(&path)
+            .or_else(|| path.parent().map(std::path::Path::to_path_buf))?;
+
+        match self.compiler.fmt(&root.to_string_lossy(), &source).await {
+            Ok(formatted) => Some((source, formatted)),
+            Err(e) => {
+                self.logger
+                    .log(MessageType::WARNING, format!("Failed to format {uri}: {e}"));
+                None
+            }
+        }
+    }
+
+    /// Whether the configured trigger policy runs diagnostics for `event`.
+    async fn diagnostics_enabled(&self, event: DiagnosticsEvent) -> bool {
+        let settings = self.settings.read().await;
+        settings.diagnostics.trigger.runs_on(event)
+    }
+
+    /// Whether the document at `uri`, of `len` bytes, is over its `largeFiles` size: it gets
+    /// no semantic tokens, and its diagnostics only run debounced.
+    async fn is_large_file(&self, uri: &Url, len: usize) -> bool {
+        let Ok(path) = uri.to_file_path() else {
+            return false;
+        };
+        let relative = build_info::find_project_root(&path)
+            .and_then(|root| Some(path.strip_prefix(root).ok()?.to_owned()))
+            .unwrap_or(path);
+        let settings = self.settings.read().await;
+        settings
+            .large_files
+            .is_large(&relative.to_string_lossy().replace('\\', "/"), len)
+    }
+
+    /// Run diagnostics for `uri` once no further edits arrive within the debounce period.
+    async fn schedule_diagnostics(&self, uri: Url, text: String, version: Option<i32>) {
+        let delay = Duration::from_millis(self.settings.read().await.diagnostics.debounce_ms);
+        let server = self.clone();
+        let task_uri = uri.clone();
+        let handle = tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+            server.pending_diagnostics.lock().await.remove(&task_uri);
+            server
+                .on_change(TextDocumentItem {
+                    uri: task_uri,
+                    text: &text,
+                    version,
+                })
+                .await;
+        });
+
+        if let Some(previous) = self.pending_diagnostics.lock().await.insert(uri, handle) {
+            previous.abort();
+        }
+    }
+
+    /// Drop a debounced diagnostics run for `uri` that has not started yet.
+    async fn cancel_pending_diagnostics(&self, uri: &Url) {
+        if let Some(pending) = self.pending_diagnostics.lock().await.remove(uri) {
+            pending.abort();
+        }
+    }
+
+    async fn on_change<'a>(&self, params: TextDocumentItem<'a>) {
+        let uri = params.uri.clone();
+        let version = params.version;
+
+        // Fuzzer configurations are checked against the index, not compiled
+        if let Some((fuzzer, root)) = fuzzer_config(&uri) {
+            self.check_fuzz_config(&uri, fuzzer, &root, params.text, version)
+                .await;
+            return;
+        }
+
+        let (lint_result, (build_result, ast_result)) =
+            tokio::join!(self.compiler.get_lint_diagnostics(&uri), self.compile(&uri));
+
+        let mut all_diagnostics = self.syntax_checks(&uri).await;
+
+        if self.settings.read().await.diagnostics.annotations
+            && let Ok(source_bytes) = self.documents.read(&uri).await
+        {
+            all_diagnostics.extend(annotations::annotation_diagnostics(&uri, &source_bytes));
+        }
+
+        // The provider caches the fresh AST data
+        match ast_result {
+            Ok(ast_data) => {
+                self.logger
+                    .log(MessageType::INFO, "AST data cached successfully");
+                if let Ok(source_bytes) = self.documents.read(&uri).await {
+                    let settings = self.settings.read().await.diagnostics.clone();
+                    all_diagnostics.extend(analysis::analysis_diagnostics(
+                        &ast_data,
+                        &uri,
+                        &source_bytes,
+                        &settings,
+                    ));
+                }
+            }
+            Err(e) => {
+                self.logger.log(
+                    MessageType::WARNING,
+                    format!("Failed to cache AST data: {e}"),
+                );
+            }
+        }
+
+        match lint_result {
+            Ok(mut lints) => {
+                self.logger.log(
+                    MessageType::INFO,
+                    format!("Found {} linting diagnostics", lints.len()),
+                );
+                all_diagnostics.append(&mut lints);
+            }
+            Err(e) => {
+                self.logger.log(
+                    MessageType::WARNING,
+                    format!("Forge linting diagnostics failed: {e}"),
+                );
+            }
+        }
+
+        match build_result {
+            Ok(mut builds) => {
+                self.logger.log(
+                    MessageType::INFO,
+                    format!("Found {} build diagnostics", builds.len()),
+                );
+                all_diagnostics.append(&mut builds);
+            }
+            Err(e) => {
+                self.logger.log(
+                    MessageType::WARNING,
+                    format!("Forge build diagnostics failed: {e}"),
+                );
+            }
+        }
+
+        if let Ok(source_bytes) = self.documents.read(&uri).await {
+            suppressions::retain_unsuppressed(
+                &mut all_diagnostics,
+                &String::from_utf8_lossy(&source_bytes),
+            );
+        }
+        self.diagnostics
+            .lock()
+            .await
+            .insert(uri.clone(), all_diagnostics);
+        self.publish_diagnostics(uri, version).await;
+    }
+
+    /// Build diagnostics and the AST of `uri`. Forge compiles the whole project either way,
+    /// so a file of a Foundry project is answered from its indexed project, or built with
+    /// the rest of the project in a single run shared with the index, whose diagnostics also
+    /// go to the other open documents of the project. Other files are compiled on their own.
+    async fn compile(&self, uri: &Url) -> (Result<Vec<Diagnostic>, RunnerError>, AstResult) {
+        let root = uri
+            .to_file_path()
+            .ok()
+            .and_then(|path| build_info::find_project_root(&path));
+        if let Some(root) = root {
+            self.ast_provider.invalidate(uri).await;
+            // The index is dropped when a file changes on disk, so an indexed project is
+            // still the build of the files forge would compile
+            if let Some(project) = self
+                .index
+                .project_for(uri)
+                .await
+                .filter(|project| project.root == root)
+            {
+                return (
+                    Self::project_build_diagnostics(&project, uri).await,
+                    Ok(project.ast.clone()),
+                );
+            }
+            match self.index.build(&root).await {
+                Ok(project) => {
+                    self.publish_project_build(&project, uri).await;
+                    self.on_project_built(&project).await;
+                    let diagnostics = Self::project_build_diagnostics(&project, uri).await;
+                    return (diagnostics, Ok(project.ast.clone()));
+                }
+                Err(e) => {
+                    self.logger.log(
+                        MessageType::WARNING,
+                        format!(
+                            "Failed to build {}, compiling the file alone: {e}",
+                            root.display()
+                        ),
+                    );
+                }
+            }
+        }
+        let (mut diagnostics, ast) = tokio::join!(
+            self.compiler.get_build_diagnostics(uri),
+            self.ast_provider.refresh(uri)
+        );
+        if let (Ok(diagnostics), Ok(ast_data)) = (&mut diagnostics, &ast)
+            && let Ok(path) = uri.to_file_path()
+            && let Ok(content) = tokio::fs::read(&path).await
+        {
+            stack_depth::explain_stack_too_deep(diagnostics, ast_data, uri, &content);
+        }
+        (diagnostics, ast)
+    }
+
+    /// The diagnostics of `uri` in the build of `project`, whose offsets are those of the
+    /// file on disk.
+    async fn project_build_diagnostics(
+        project: &ProjectIndex,
+        uri: &Url,
+    ) -> Result<Vec<Diagnostic>, RunnerError> {
+        let path = uri.to_file_path().map_err(|_| RunnerError::InvalidUrl)?;
+        let content = tokio::fs::read_to_string(&path)
+            .await
+            .map_err(|_| RunnerError::ReadError)?;
+        let mut diagnostics =
+            build::project_build_diagnostics(&project.ast, &project.root, &path, &content);
+        stack_depth::explain_stack_too_deep(
+            &mut diagnostics,
+            &project.ast,
+            uri,
+            content.as_bytes(),
+        );
+        Ok(diagnostics)
+    }
+
+    /// Replace the build diagnostics of the open documents of `project` other than `built`
+    /// with those of its fresh build, keeping their lints and analyses until their own next
+    /// run.
+    async fn publish_project_build(&self, project: &ProjectIndex, built: &Url) {
+        let source = Some(BUILD_DIAGNOSTICS_SOURCE.to_string());
+        for (uri, version) in self.documents.versions().await {
+            if &uri == built
+                || !uri
+                    .to_file_path()
+                    .is_ok_and(|path| path.starts_with(&project.root))
+            {
+                continue;
+            }
+            let Ok(fresh) = Self::project_build_diagnostics(project, &uri).await else {
+                continue;
+            };
+            {
+                let mut diagnostics = self.diagnostics.lock().await;
+                let last = diagnostics.entry(uri.clone()).or_default();
+                last.retain(|diagnostic| diagnostic.source != source);
+                last.extend(fresh);
+            }
+            self.publish_diagnostics(uri, Some(version)).await;
+        }
+    }
+
+    /// Publish the last diagnostics of `uri` together with its test failures, as the
+    /// diagnostics settings filter them.
+    async fn publish_diagnostics(&self, uri: Url, version: Option<i32>) {
+        let mut diagnostics = self
+            .diagnostics
+            .lock()
+            .await
+            .get(&uri)
+            .cloned()
+            .unwrap_or_default();
+        self.retain_new_findings(&uri, &mut diagnostics).await;
+        if let Some(failures) = self.test_failures.lock().await.get(&uri) {
+            diagnostics.extend(failures.iter().cloned());
+        }
+        if let Some(findings) = self.model_findings.lock().await.get(&uri) {
+            diagnostics.extend(findings.iter().cloned());
+        }
+        if let Some(findings) = self.fuzz_findings.lock().await.get(&uri) {
+            diagnostics.extend(findings.iter().cloned());
+        }
+        let settings = self.settings.read().await.diagnostics.clone();
+        let excluded = uri.to_file_path().ok().is_some_and(|path| {
+            build_info::find_project_root(&path)
+                .and_then(|root| Some(path.strip_prefix(root).ok()?.to_owned()))
+                .is_some_and(|relative| {
+                    settings.excludes(&relative.to_string_lossy().replace('\\', "/"))
+                })
+        });
+        if excluded {
+            diagnostics.clear();
+        }
+        settings.apply(&mut diagnostics);
+        i18n::localize_diagnostics(&mut diagnostics, self.locale().await);
+        self.client
+            .publish_diagnostics(uri, diagnostics, version)
+            .await;
+    }
+
+    /// The baseline of the project at `root`.
+    async fn baseline(&self, root: &Path) -> Arc<Baseline> {
+        self.baselines
+            .lock()
+            .await
+            .entry(root.to_path_buf())
+            .or_insert_with(|| Arc::new(Baseline::load(root)))
+            .clone()
+    }
+
+    /// Drop the findings of `uri` its project's baseline records.
+    async fn retain_new_findings(&self, uri: &Url, diagnostics: &mut Vec<Diagnostic>) {
+        let Some((root, path)) = uri
+            .to_file_path()
+            .ok()
+            .and_then(|path| Some((build_info::find_project_root(&path)?, path)))
+        else {
+            return;
+        };
+        let baseline = self.baseline(&root).await;
+        if baseline.is_empty() {
+            return;
+        }
+        if let Ok(source_bytes) = self.documents.read(uri).await {
+            baseline.retain_new(
+                &root,
+                &path,
+                diagnostics,
+                &String::from_utf8_lossy(&source_bytes),
+            );
+        }
+    }
+
+    /// Text of the documents open in the editor.
+    async fn open_documents(&self) -> HashMap<Url, String> {
+        let mut open = HashMap::new();
+        for (uri, _) in self.documents.versions().await {
+            if let Some(text) = self.documents.get(&uri).await {
+                open.insert(uri, text);
+            }
+        }
+        open
+    }
+
+    /// Findings of the server's analyses of every source of the indexed projects, indexing
+    /// the workspace first if nothing is indexed yet.
+    async fn workspace_findings(&self, settings: &DiagnosticsSettings) -> Vec<SourceFindings> {
+        if self.index.projects().await.is_empty() {
+            self.index_workspace(true).await;
+        }
+        let open = self.open_documents().await;
+        self.index
+            .projects()
+            .await
+            .iter()
+            .flat_map(|project| {
+                analysis::project_findings(&project.root, &project.ast, &open, settings)
+            })
+            .collect()
+    }
+
+    /// Record the current findings of every indexed project in its baseline, and republish
+    /// the diagnostics of the documents without them. Returns how many findings were
+    /// recorded.
+    async fn record_baseline(&self) -> usize {
+        let settings = self.settings.read().await.diagnostics.clone();
+        let mut by_root: HashMap<PathBuf, Vec<Finding>> = self
+            .index
+            .projects()
+            .await
+            .iter()
+            .map(|project| (project.root.clone(), Vec::new()))
+            .collect();
+        for file in self.workspace_findings(&settings).await {
+            by_root.entry(file.root.clone()).or_default().extend(
+                file.diagnostics.iter().filter_map(|diagnostic| {
+                    Finding::new(&file.root, &file.path, diagnostic, &file.source)
+                }),
+            );
+        }
+
+        let mut recorded = 0;
+        for (root, findings) in by_root {
+            let baseline = Baseline::new(findings);
+            if let Err(e) = baseline.save(&root) {
+                self.logger.log(
+                    MessageType::ERROR,
+                    format!("Failed to write {}: {e}", Baseline::path(&root).display()),
+                );
+                continue;
+            }
+            recorded += baseline.len();
+            self.baselines.lock().await.insert(root, Arc::new(baseline));
+        }
+
+        for (uri, version) in self.documents.versions().await {
+            self.publish_diagnostics(uri, Some(version)).await;
+        }
+        recorded
+    }
+
+    /// One edit fixing every fixable finding of `params.rule` in the indexed projects.
+    /// Every analysis runs regardless of the settings, and the projects are linted when
+    /// the rule is not one of the server's.
+    async fn fix_all(&self, params: &FixAllParams) -> FixAllResult {
+        let settings = DiagnosticsSettings {
+            natspec: true,
+            mutability: true,
+            interfaces: true,
+            ..self.settings.read().await.diagnostics.clone()
+        };
+        let mut findings = self.workspace_findings(&settings).await;
+        if !analysis::ANALYSIS_CODES.contains(&params.rule.as_str()) {
+            let mut roots: Vec<PathBuf> = findings.iter().map(|file| file.root.clone()).collect();
+            roots.dedup();
+            for root in roots {
+                let output = match self.compiler.lint(&root.to_string_lossy()).await {
+                    Ok(output) => output,
+                    Err(e) => {
+                        self.logger.log(
+                            MessageType::WARNING,
+                            format!("Failed to lint {}: {e}", root.display()),
+                        );
+                        continue;
+                    }
+                };
+                fix_all::add_lint_findings(&mut findings, &root, &output);
+            }
+        }
+        fix_all::fix_all(&findings, params, &|uri: &Url| {
+            std::fs::read_to_string(uri.to_file_path().ok()?).ok()
+        })
+    }
+
+    /// The gas costs of the project at `root`, when `gasEstimates` is enabled. The first
+    /// request measures them in the background and gets none.
+    async fn gas_report(&self, root: &Path) -> Option<Arc<GasReport>> {
+        if !self.settings.read().await.gas_estimates {
+            return None;
+        }
+        let mut reports = self.gas_reports.lock().await;
+        if let Some(report) = reports.get(root) {
+            return Some(report.clone());
+        }
+        // Later requests see no costs instead of measuring again until this run finishes
+        reports.insert(root.to_path_buf(), Arc::new(GasReport::default()));
+        let server = self.clone();
+        let root = root.to_path_buf();
+        tokio::spawn(async move { server.measure_gas(root).await });
+        None
+    }
+
+    /// Measure the gas costs of the project at `root`, keeping the compiler estimates of
+    /// the functions the tests don't call, and refresh the lenses showing them.
+    async fn measure_gas(&self, root: PathBuf) {
+        let root_str = root.to_string_lossy();
+        let mut report = GasReport::default();
+        match self.compiler.gas_estimates(&root_str).await {
+            Ok(output) => report = GasReport::from_build_output(&root, &output),
+            Err(e) => {
+                self.logger.log(
+                    MessageType::WARNING,
+                    format!("Failed to estimate gas of {}: {e}", root.display()),
+                );
+            }
+        }
+        match self.compiler.gas_report(&root_str).await {
+            Ok(output) => report.merge(GasReport::from_gas_report(&root, &output)),
+            Err(e) => {
+                self.logger.log(
+                    MessageType::WARNING,
+                    format!("Failed to measure gas of {}: {e}", root.display()),
+                );
+            }
+        }
+        self.gas_reports.lock().await.insert(root, Arc::new(report));
+        let _ = self.client.code_lens_refresh().await;
+    }
+
+    /// Measure the gas costs of the project of the saved `uri` again, if they were shown.
+    /// The previous costs stay until the new ones are in.
+    async fn remeasure_gas(&self, uri: &Url) {
+        let Some(root) = uri
+            .to_file_path()
+            .ok()
+            .and_then(|path| build_info::find_project_root(&path))
+        else {
+            return;
+        };
+        if !self.settings.read().await.gas_estimates
+            || !self.gas_reports.lock().await.contains_key(&root)
+        {
+            return;
+        }
+        let server = self.clone();
+        tokio::spawn(async move { server.measure_gas(root).await });
+    }
+
+    /// The storage layout of the contract called `name` in `path`, of the project at
+    /// `root`, from `forge inspect`.
+    async fn storage_layout(
+        &self,
+        root: &Path,
+        path: &Path,
+        name: &str,
+    ) -> Result<Arc<InspectedLayout>, RunnerError> {
+        let relative = path.strip_prefix(root).unwrap_or(path);
+        let contract = format!("{}:{name}", relative.to_string_lossy());
+        let key = (root.to_path_buf(), contract.clone());
+        if let Some(layout) = self.storage_layouts.lock().await.get(&key) {
+            return Ok(layout.clone());
+        }
+        let output = self
+            .compiler
+            .storage_layout(&root.to_string_lossy(), &contract)
+            .await?;
+        let layout = InspectedLayout::parse(&contract, &output).ok_or_else(|| {
+            RunnerError::CommandFailed(format!("no storage layout for {contract}"))
+        })?;
+        let layout = Arc::new(layout);
+        self.storage_layouts
+            .lock()
+            .await
+            .insert(key, layout.clone());
+        Ok(layout)
+    }
+
+    /// Start over from a clean slate, e.g. after switching branches. Forge reads
+    /// `foundry.toml` and the remappings on every run, so dropping the server's own state
+    /// is enough for the next runs to see the new configuration.
+    async fn reload_workspace(&self) {
+        let progress = ProgressReporter::begin(&self.client, "Reloading workspace").await;
+        for (_, pending) in self.pending_diagnostics.lock().await.drain() {
+            pending.abort();
+        }
+        let dropped = self.ast_provider.clear().await;
+        let projects = self.index.clear().await;
+        self.baselines.lock().await.clear();
+        self.gas_reports.lock().await.clear();
+        self.storage_layouts.lock().await.clear();
+        self.logger.log(
+            MessageType::INFO,
+            format!(
+                "Reloading workspace, dropped {dropped} cached ASTs and {projects} indexed projects"
+            ),
+        );
+        self.index_workspace(false).await;
+
+        let open = self.documents.versions().await;
+        // Closed documents keep no diagnostics, open ones get fresh diagnostics below
+        let mut stale = HashSet::new();
+        stale.extend(self.diagnostics.lock().await.drain().map(|(uri, _)| uri));
+        stale.extend(self.test_failures.lock().await.drain().map(|(uri, _)| uri));
+        stale.extend(self.model_findings.lock().await.drain().map(|(uri, _)| uri));
+        stale.extend(self.fuzz_findings.lock().await.drain().map(|(uri, _)| uri));
+        for (uri, _) in &open {
+            stale.remove(uri);
+        }
+        for uri in stale {
+            self.client.publish_diagnostics(uri, vec![], None).await;
+        }
+
+        let total = open.len();
+        for (done, (uri, version)) in open.into_iter().enumerate() {
+            let name = uri
+                .path_segments()
+                .and_then(|mut s| s.next_back())
+                .unwrap_or_default();
+            progress
+                .report(
+                    format!("{name} ({}/{total})", done + 1),
+                    (done * 100 / total) as u32,
+                )
+                .await;
+            self.on_change(TextDocumentItem {
+                uri,
+                text: "",
+                version: Some(version),
+            })
+            .await;
+        }
+        progress
+            .end(format!("Reindexed {total} open documents"))
+            .await;
+    }
+
+    /// Compile every Foundry project of the workspace into the index. With `use_saved`, a
+    /// project unchanged since the last run is loaded from the index saved then instead.
+    async fn index_workspace(&self, use_saved: bool) {
+        let roots = self.folders.read().await.projects();
+        self.index_projects(roots, use_saved).await;
+    }
+
+    /// Compile the projects at `roots` into the index, loading the ones unchanged since the
+    /// last run from their saved index with `use_saved`.
+    async fn index_projects(&self, roots: Vec<PathBuf>, use_saved: bool) {
+        let progress = ProgressReporter::begin(&self.client, "Indexing workspace").await;
+
+        let total = roots.len();
+        let mut files = 0;
+        for (done, root) in roots.into_iter().enumerate() {
+            progress
+                .report(
+                    format!("{} ({}/{total})", root.display(), done + 1),
+                    (done * 100 / total) as u32,
+                )
+                .await;
+            let indexed = if use_saved {
+                self.index.load_or_build(&root).await
+            } else {
+                self.index.build(&root).await
+            };
+            self.check_artifacts(&root).await;
+            match indexed {
+                Ok(project) => {
+                    files += project.file_count();
+                    self.on_project_built(&project).await;
+                }
+                Err(e) => {
+                    self.logger.log(
+                        MessageType::WARNING,
+                        format!("Failed to index {}: {e}", root.display()),
+                    );
+                }
+            }
+        }
+        progress
+            .end(format!("Indexed {total} projects, {files} files"))
+            .await;
+    }
+
+    /// Drop the indexed projects a change to `uri` on disk makes stale and rebuild them in
+    /// the background. Until then their files are compiled on demand.
+    async fn reindex(&self, uri: &Url) {
+        let stale = self.index.invalidate(uri).await;
+        if stale.is_empty() {
+            return;
+        }
+        let server = self.clone();
+        tokio::spawn(async move { server.rebuild_projects(stale).await });
+    }
+
+    async fn rebuild_projects(&self, roots: Vec<PathBuf>) {
+        for root in roots {
+            match self.index.build(&root).await {
+                Ok(project) => self.on_project_built(&project).await,
+                Err(e) => {
+                    self.logger.log(
+                        MessageType::WARNING,
+                        format!("Failed to reindex {}: {e}", root.display()),
+                    );
+                }
+            }
+            self.check_artifacts(&root).await;
+        }
+    }
+
+    /// Compare the artifacts of the project at `root` with its sources, publishing the
+    /// result on its `foundry.toml` and refreshing the lenses when it changed.
+    async fn check_artifacts(&self, root: &Path) -> Option<StaleArtifacts> {
+        let stale = artifacts::stale_artifacts(root);
+        let previous = self
+            .stale_artifacts
+            .lock()
+            .await
+            .insert(root.to_path_buf(), stale.clone());
+        let checked = previous.is_some();
+        if previous.flatten() != stale {
+            if let Ok(uri) = Url::from_file_path(root.join("foundry.toml")) {
+                let diagnostics = stale.iter().map(StaleArtifacts::diagnostic).collect();
+                self.client
+                    .publish_diagnostics(uri, diagnostics, None)
+                    .await;
+            }
+            // The first check is made for the lenses being requested
+            if checked {
+                let _ = self.client.code_lens_refresh().await;
+            }
+        }
+        stale
+    }
+
+    /// The out-of-date artifacts of the project at `root`, checked on first use.
+    async fn stale_artifacts(&self, root: &Path) -> Option<StaleArtifacts> {
+        let checked = self.stale_artifacts.lock().await.get(root).cloned();
+        match checked {
+            Some(stale) => stale,
+            None => self.check_artifacts(root).await,
+        }
+    }
+
+    /// Build the project at `root` with forge, for the "Rebuild now" lens.
+    async fn rebuild_artifacts(&self, root: &Path) {
+        let progress = ProgressReporter::begin(&self.client, "Rebuilding artifacts").await;
+        let result = self.compiler.rebuild(&root.to_string_lossy()).await;
+        progress.end(format!("Rebuilt {}", root.display())).await;
+        if let Err(e) = result {
+            self.client
+                .show_message(MessageType::ERROR, format!("forge build failed: {e}"))
+                .await;
+        }
+        self.check_artifacts(root).await;
+    }
+
+    /// Record the ABIs of a fresh build of `project`, and generate its bindings again in the
+    /// background when they changed and `bindings.onAbiChange` is set.
+    async fn on_project_built(&self, project: &ProjectIndex) {
+        if let Ok(root) = Url::from_file_path(&project.root) {
+            self.client
+                .send_notification::<TestsChanged>(TestsChangedParams { root })
+                .await;
+        }
+        let Some(fingerprint) = bindings::abi_fingerprint(&project.ast) else {
+            return;
+        };
+        let previous = self
+            .abi_fingerprints
+            .lock()
+            .await
+            .insert(project.root.clone(), fingerprint);
+        if previous.is_some_and(|previous| previous != fingerprint)
+            && self.settings.read().await.bindings.on_abi_change
+        {
+            let server = self.clone();
+            let roots = vec![project.root.clone()];
+            tokio::spawn(async move { server.generate_bindings(roots).await });
+        }
+    }
+
+    /// Generate the bindings of the projects at `roots` with `forge bind`.
+    async fn generate_bindings(&self, roots: Vec<PathBuf>) {
+        let progress = ProgressReporter::begin(&self.client, "Generating bindings").await;
+        let args = bindings::bind_args(&self.settings.read().await.bindings);
+        let total = roots.len();
+        let mut failed = Vec::new();
+        for (done, root) in roots.iter().enumerate() {
+            progress
+                .report(
+                    format!("{} ({}/{total})", root.display(), done + 1),
+                    (done * 100 / total) as u32,
+                )
+                .await;
+            if let Err(e) = self.compiler.bind(&root.to_string_lossy(), &args).await {
+                self.logger.log(
+                    MessageType::ERROR,
+                    format!("forge bind failed in {}: {e}", root.display()),
+                );
+                failed.push(root.display().to_string());
+            }
+        }
+        progress
+            .end(format!(
+                "Generated the bindings of {} projects",
+                total - failed.len()
+            ))
+            .await;
+        if !failed.is_empty() {
+            self.client
+                .show_message(
+                    MessageType::ERROR,
+                    format!("forge bind failed in {}", failed.join(", ")),
+                )
+                .await;
+        }
+    }
+
+    /// Reindex in the background when a watched HEAD file shows a branch switch.
+    async fn on_head_change(&self, changes: &[FileEvent]) {
+        let switched = {
+            let mut heads = self.heads.lock().await;
+            changes
+                .iter()
+                .filter(|change| git::is_head(&change.uri))
+                .filter_map(|change| heads.update(&change.uri.to_file_path().ok()?))
+                .last()
+        };
+        let Some(head) = switched else {
+            return;
+        };
+
+        self.logger.log(
+            MessageType::INFO,
+            format!("HEAD moved to {}, reindexing", git::describe(&head)),
+        );
+        let server = self.clone();
+        tokio::spawn(async move { server.reload_workspace().await });
+    }
+
+    /// Drop the index entries and cached ASTs of the projects whose configuration,
+    /// dependencies or build info changed, then rebuild them in the background and re-check
+    /// their open documents.
+    async fn on_project_files_change(&self, changes: &[FileEvent]) {
+        let folders = self.folders.read().await.clone();
+        let mut roots: Vec<PathBuf> = changes
+            .iter()
+            .filter_map(|change| change.uri.to_file_path().ok())
+            .filter_map(|path| Some((folders.folder_for(&path)?, path)))
+            .filter(|(folder, path)| watch::is_project_file(folder, path))
+            .filter_map(|(folder, path)| watch::affected_root(&folder, &path))
+            .collect();
+        roots.sort();
+        roots.dedup();
+        if roots.is_empty() {
+            return;
+        }
+
+        for root in &roots {
+            self.index.remove(root).await;
+            let dropped = self.ast_provider.invalidate_under(root).await;
+            self.logger.log(
+                MessageType::INFO,
+                format!(
+                    "Project files of {} changed, dropped {dropped} cached ASTs",
+                    root.display()
+                ),
+            );
+        }
+        // A deleted project stays out of the index
+        roots.retain(|root| root.join("foundry.toml").is_file());
+        let server = self.clone();
+        tokio::spawn(async move {
+            server.rebuild_projects(roots.clone()).await;
+            for (uri, version) in server.documents.versions().await {
+                if uri
+                    .to_file_path()
+                    .is_ok_and(|path| roots.iter().any(|root| path.starts_with(root)))
+                {
+                    server
+                        .on_change(TextDocumentItem {
+                            uri,
+                            text: "",
+                            version: Some(version),
+                        })
+                        .await;
+                }
+            }
+        });
+    }
+
+    /// Forget deleted files, clear their diagnostics and re-check the open files that
+    /// imported them, which no longer compile.
+    async fn on_deleted(&self, uris: Vec<Url>) {
+        let mut affected = HashSet::new();
+        for uri in &uris {
+            if let Some(project) = self.index.project_for(uri).await {
+                affected.extend(project.importers(uri));
+            }
+            affected.extend(self.ast_provider.dependents(uri).await);
+            self.reindex(uri).await;
+            self.ast_provider.invalidate(uri).await;
+            self.cancel_pending_diagnostics(uri).await;
+            self.diagnostics.lock().await.remove(uri);
+            self.test_failures.lock().await.remove(uri);
+            self.client
+                .publish_diagnostics(uri.clone(), vec![], None)
+                .await;
+        }
+        for uri in &uris {
+            affected.remove(uri);
+        }
+
+        let open = self.documents.versions().await;
+        for uri in affected {
+            self.ast_provider.invalidate(&uri).await;
+            let Some(&(_, version)) = open.iter().find(|(open_uri, _)| *open_uri == uri) else {
+                continue;
+            };
+            self.on_change(TextDocumentItem {
+                uri,
+                text: "",
+                version: Some(version),
+            })
+            .await;
+        }
+    }
+
+    /// Pair deleted and created files of one batch of watched-file events that have the
+    /// same contents: a move or rename. Paired files are removed from `deleted`.
+    async fn detect_renames(&self, deleted: &mut Vec<Url>, created: &[Url]) -> Vec<(Url, Url)> {
+        let mut renames = Vec::new();
+        for to in created {
+            let Some(contents) = to
+                .to_file_path()
+                .ok()
+                .and_then(|path| std::fs::read(path).ok())
+            else {
+                continue;
+            };
+            let hash = index::content_hash(&contents);
+            let mut position = None;
+            for (i, from) in deleted.iter().enumerate() {
+                if self.index.content_hash(from).await == Some(hash) {
+                    position = Some(i);
+                    break;
+                }
+            }
+            if let Some(i) = position {
+                renames.push((deleted.remove(i), to.clone()));
+            }
+        }
+        renames
+    }
+
+    /// Move the index entries and diagnostics of a file that moved without changing, instead
+    /// of recompiling its project.
+    async fn on_renamed(&self, from: Url, to: Url) {
+        self.logger
+            .log(MessageType::INFO, format!("{from} moved to {to}"));
+        if !self.index.rename(&from, &to).await {
+            self.reindex(&to).await;
+        }
+        self.ast_provider.invalidate(&from).await;
+        self.cancel_pending_diagnostics(&from).await;
+
+        let diagnostics = self.diagnostics.lock().await.remove(&from);
+        if let Some(diagnostics) = diagnostics {
+            self.diagnostics
+                .lock()
+                .await
+                .insert(to.clone(), diagnostics);
+        }
+        let failures = self.test_failures.lock().await.remove(&from);
+        if let Some(failures) = failures {
+            self.test_failures.lock().await.insert(to.clone(), failures);
+        }
+        self.client.publish_diagnostics(from, vec![], None).await;
+        let version = self
+            .documents
+            .versions()
+            .await
+            .into_iter()
+            .find_map(|(uri, version)| (uri == to).then_some(version));
+        self.publish_diagnostics(to, version).await;
+    }
+
+    /// Ask the client to report changes of the repository HEAD and created and deleted
+    /// Solidity files, and record the current HEAD.
+    async fn watch_files(&self) {
+        let folders = self.folders.read().await.folders();
+        for folder in folders {
+            self.heads.lock().await.update(&git::head_file(&folder));
+        }
+
+        let mut watchers = vec![
+            FileSystemWatcher {
+                glob_pattern: GlobPattern::String(git::HEAD_GLOB.to_string()),
+                kind: None,
+            },
+            FileSystemWatcher {
+                glob_pattern: GlobPattern::String(SOLIDITY_GLOB.to_string()),
+                // A delete and a create with the same contents is a move
+                kind: Some(WatchKind::Create | WatchKind::Delete),
+            },
+        ];
+        watchers.extend(watch::PROJECT_GLOBS.map(|glob| FileSystemWatcher {
+            glob_pattern: GlobPattern::String(glob.to_string()),
+            kind: None,
+        }));
+        let options = DidChangeWatchedFilesRegistrationOptions { watchers };
+        let registration = Registration {
+            id: "forge-lsp/watched-files".to_string(),
+            method: "workspace/didChangeWatchedFiles".to_string(),
+            register_options: serde_json::to_value(options).ok(),
+        };
+        if let Err(e) = self.client.register_capability(vec![registration]).await {
+            self.logger.log(
+                MessageType::WARNING,
+                format!("Could not watch files, branch switches need a reload: {e}"),
+            );
+        }
+    }
+
+    /// The project root of the test file `uri` and the file's path relative to it.
+    async fn test_file(&self, uri: &Url) -> Result<(PathBuf, String), RequestError> {
+        let outside = || RequestError::OutsideWorkspace { uri: uri.clone() };
+        let path = uri.to_file_path().map_err(|_| outside())?;
+        let root = self.project_root(uri).await.ok_or_else(outside)?;
+        let relative = path.strip_prefix(&root).map_err(|_| outside())?;
+        let relative = relative.to_string_lossy().into_owned();
+        Ok((root, relative))
+    }
+
+    /// Run the tests of `contract` in `uri`, or only `test`, and report the results.
+    async fn run_test(&self, uri: Url, contract: &str, test: Option<&str>) {
+        let (root, relative) = match self.test_file(&uri).await {
+            Ok(file) => file,
+            Err(e) => {
+                self.logger.log(MessageType::ERROR, e.to_string());
+                return;
+            }
+        };
+
+        let filter = TestFilter {
+            path: &relative,
+            contract,
+            test,
+        };
+        let output = match self.compiler.test(&root.to_string_lossy(), filter).await {
+            Ok(output) => output,
+            Err(e) => {
+                self.client
+                    .show_message(MessageType::ERROR, format!("forge test failed: {e}"))
+                    .await;
+                return;
+            }
+        };
+        self.report_test_outcomes(uri, contract, &forge_test::outcomes(&output))
+            .await;
+    }
+
+    /// Run the tests of a contract like [`Self::run_test`] with the traces of the failures,
+    /// showing the lines forge prints as progress, and report each failure on its failing
+    /// call.
+    async fn run_test_streamed(&self, params: TestRunParams) {
+        match self.stream_tests(&params).await {
+            Ok(output) => {
+                let outcomes = forge_test::outcomes(&output);
+                self.report_test_outcomes(params.uri, &params.contract, &outcomes)
+                    .await;
+            }
+            Err(e) => {
+                self.client
+                    .show_message(
+                        MessageType::ERROR,
+                        format!("forge test failed: {}", e.message),
+                    )
+                    .await;
+            }
+        }
+    }
+
+    /// Run the tests `params` selects with the traces of the failures, showing the lines
+    /// forge prints as progress, and return forge's results.
+    async fn stream_tests(
+        &self,
+        params: &TestRunParams,
+    ) -> tower_lsp::jsonrpc::Result<serde_json::Value> {
+        let internal_error = |message: String| tower_lsp::jsonrpc::Error {
+            code: tower_lsp::jsonrpc::ErrorCode::InternalError,
+            message: message.into(),
+            data: None,
+        };
+
+        let (root, relative) = self.test_file(&params.uri).await?;
+
+        let title = match &params.test {
+            Some(test) => format!("Running {}::{test}", params.contract),
+            None => format!("Running {}", params.contract),
+        };
+        let progress = ProgressReporter::begin(&self.client, &title).await;
+        let (lines, mut printed) = mpsc::unbounded_channel();
+        let compiler = self.compiler.clone();
+        let run = {
+            let (contract, test) = (params.contract.clone(), params.test.clone());
+            tokio::spawn(async move {
+                let filter = TestFilter {
+                    path: &relative,
+                    contract: &contract,
+                    test: test.as_deref(),
+                };
+                compiler
+                    .test_streamed(&root.to_string_lossy(), filter, lines)
+                    .await
+            })
+        };
+        while let Some(line) = printed.recv().await {
+            let line = line.trim();
+            if !line.is_empty() {
+                progress.message(line).await;
+            }
+        }
+
+        let output = match run.await {
+            Ok(Ok(output)) => output,
+            Ok(Err(e)) => {
+                progress.end("forge test failed").await;
+                return Err(match RequestError::from_runner(&params.uri, &e) {
+                    Some(error) => error.into(),
+                    None => internal_error(e.to_string()),
+                });
+            }
+            Err(e) => {
+                progress.end("forge test failed").await;
+                return Err(internal_error(format!("test task failed: {e}")));
+            }
+        };
+        progress
+            .end(forge_test::summary(&forge_test::outcomes(&output)))
+            .await;
+        Ok(output)
+    }
+
+    /// Log the `outcomes` of a run of the tests of `contract` in `uri`, show their summary
+    /// and publish the failures.
+    async fn report_test_outcomes(&self, uri: Url, contract: &str, outcomes: &[TestOutcome]) {
+        for outcome in outcomes {
+            let status = if outcome.passed { "PASS" } else { "FAIL" };
+            self.logger.log(
+                MessageType::INFO,
+                format!("[{status}] {}::{}", outcome.contract, outcome.test),
+            );
+        }
+        let summary = forge_test::summary(outcomes);
+        let failed = outcomes.iter().any(|outcome| !outcome.passed);
+        let severity = if failed {
+            MessageType::WARNING
+        } else {
+            MessageType::INFO
+        };
+        self.client
+            .show_message(severity, format!("{contract}: {summary}"))
+            .await;
+        self.publish_test_failures(uri, outcomes).await;
+    }
+
+    /// Publish the failures among `outcomes` of tests of `uri` on their test functions,
+    /// replacing those of the previous run.
+    async fn publish_test_failures(&self, uri: Url, outcomes: &[TestOutcome]) {
+        let failures = match (
+            self.documents.read(&uri).await,
+            self.ast_provider.get_or_fetch(&uri).await,
+        ) {
+            (Ok(source_bytes), Ok(ast_data)) => {
+                let mut failures =
+                    forge_test::failure_diagnostics(&ast_data, &uri, &source_bytes, outcomes);
+                failures.extend(counterexamples::counterexample_diagnostics(
+                    &ast_data,
+                    &uri,
+                    &source_bytes,
+                    outcomes,
+                    |contract| counterexamples::artifact_abi(&uri, contract),
+                ));
+                failures
+            }
+            _ => vec![],
+        };
+        self.test_failures
+            .lock()
+            .await
+            .insert(uri.clone(), failures);
+        self.publish_diagnostics(uri, None).await;
+    }
+
+    /// Run the tests `testOnSave.match` selects for the saved `uri`, publishing their
+    /// results on the test functions.
+    async fn test_on_save(&self, uri: Url) {
+        let matching = self.settings.read().await.test_on_save.matching;
+        if matching == TestOnSaveMatch::Off {
+            return;
+        }
+        let Some(root) = uri
+            .to_file_path()
+            .ok()
+            .and_then(|path| build_info::find_project_root(&path))
+        else {
+            return;
+        };
+        let project = match self.index.get_or_build(&root).await {
+            Ok(project) => project,
+            Err(e) => {
+                self.logger.log(
+                    MessageType::WARNING,
+                    format!("Not running tests after saving {uri}: {e}"),
+                );
+                return;
+            }
+        };
+        let mut files = vec![uri.clone()];
+        if matching == TestOnSaveMatch::Imports {
+            files.extend(project.importers(&uri));
+        }
+
+        let root_str = root.to_string_lossy();
+        let mut all_outcomes = Vec::new();
+        for file in files {
+            let suites = forge_test::test_suites(&project.ast, &file);
+            let Some(relative) = file
+                .to_file_path()
+                .ok()
+                .and_then(|path| Some(path.strip_prefix(&root).ok()?.to_path_buf()))
+                .filter(|_| !suites.is_empty())
+            else {
+                continue;
+            };
+            let mut outcomes = Vec::new();
+            for contract in suites {
+                let filter = TestFilter {
+                    path: &relative.to_string_lossy(),
+                    contract,
+                    test: None,
+                };
+                match self.compiler.test(&root_str, filter).await {
+                    Ok(output) => outcomes.extend(forge_test::outcomes(&output)),
+                    Err(e) => {
+                        self.logger.log(
+                            MessageType::WARNING,
+                            format!("forge test of {contract} failed: {e}"),
+                        );
+                    }
+                }
+            }
+            let results = match self.documents.read(&file).await {
+                Ok(source_bytes) => {
+                    let mut results = forge_test::outcome_diagnostics(
+                        &project.ast,
+                        &file,
+                        &source_bytes,
+                        &outcomes,
+                        true,
+                    );
+                    results.extend(counterexamples::counterexample_diagnostics(
+                        &project.ast,
+                        &file,
+                        &source_bytes,
+                        &outcomes,
+                        |contract| counterexamples::artifact_abi(&file, contract),
+                    ));
+                    results
+                }
+                Err(_) => vec![],
+            };
+            self.test_failures
+                .lock()
+                .await
+                .insert(file.clone(), results);
+            self.publish_diagnostics(file, None).await;
+            all_outcomes.extend(outcomes);
+        }
+
+        if all_outcomes.is_empty() {
+            return;
+        }
+        let summary = forge_test::summary(&all_outcomes);
+        if all_outcomes.iter().any(|outcome| !outcome.passed) {
+            self.client
+                .show_message(MessageType::WARNING, format!("Tests after save: {summary}"))
+                .await;
+        } else {
+            self.logger
+                .log(MessageType::INFO, format!("Tests after save: {summary}"));
+        }
+    }
+
+    /// The events of the contracts `params` selects, from their artifacts, written to
+    /// `params.output` when given.
+    async fn export_events(&self, params: &ExportEventsParams) -> Result<EventSchema, String> {
+        let path = params
+            .uri
+            .to_file_path()
+            .map_err(|_| format!("{} is not a file", params.uri))?;
+        let config = ProjectConfig::find(&path)
+            .ok_or_else(|| format!("{} is not in a Foundry project", path.display()))?;
+        let contracts = if params.contracts.is_empty() {
+            self.source_and_syntax(&params.uri)
+                .await
+                .map(|(_, tree)| {
+                    ast::source_unit(&tree, &params.uri)
+                        .and_then(|unit| unit.get("nodes")?.as_array())
+                        .into_iter()
+                        .flatten()
+                        .filter(|node| node["nodeType"] == "ContractDefinition")
+                        .filter_map(|node| Some(node["name"].as_str()?.to_string()))
+                        .collect()
+                })
+                .unwrap_or_default()
+        } else {
+            params.contracts.clone()
+        };
+        if contracts.is_empty() {
+            return Err(format!("{} declares no contracts", params.uri));
+        }
+        if artifacts::stale_artifacts(&config.root).is_some() {
+            self.logger.log(
+                MessageType::WARNING,
+                format!(
+                    "Artifacts in {}/ are out of date, the exported events may be too",
+                    config.out
+                ),
+            );
+        }
+
+        let out = config.root.join(&config.out);
+        let schema = EventSchema {
+            contracts: contracts
+                .iter()
+                .map(|contract| events::contract_events(&config.root, &out, &path, contract))
+                .collect::<Result<_, _>>()?,
+        };
+        if let Some(output) = &params.output {
+            let output = config.root.join(output);
+            let text = serde_json::to_string_pretty(&schema).map_err(|e| e.to_string())?;
+            std::fs::write(&output, text + "\n")
+                .map_err(|e| format!("Failed to write {}: {e}", output.display()))?;
+            self.client
+                .show_message(
+                    MessageType::INFO,
+                    format!("forge-lsp: wrote the events to {}", output.display()),
+                )
+                .await;
+        }
+        Ok(schema)
+    }
+
+    /// Assemble the verification bundle of a contract from the artifacts of the last build,
+    /// writing it to its directory when one is given.
+    async fn verification_bundle(
+        &self,
+        params: &VerificationBundleParams,
+    ) -> Result<VerificationBundle, String> {
+        let path = params
+            .uri
+            .to_file_path()
+            .map_err(|_| format!("{} is not a file", params.uri))?;
+        let config = ProjectConfig::find(&path)
+            .ok_or_else(|| format!("{} is not in a Foundry project", path.display()))?;
+        if artifacts::stale_artifacts(&config.root).is_some() {
+            self.logger.log(
+                MessageType::WARNING,
+                format!(
+                    "Artifacts in {}/ are out of date, the bundle may not match the sources",
+                    config.out
+                ),
+            );
+        }
+
+        let out = config.root.join(&config.out);
+        let bundle = verify::verification_bundle(&config.root, &out, &path, &params.contract)?;
+        if let Some(directory) = &params.directory {
+            let directory = config.root.join(directory);
+            verify::write_bundle(&bundle, &directory)?;
+            self.client
+                .show_message(
+                    MessageType::INFO,
+                    format!(
+                        "forge-lsp: wrote the verification bundle of {} to {}",
+                        bundle.contract,
+                        directory.display()
+                    ),
+                )
+                .await;
+        }
+        Ok(bundle)
+    }
+
+    /// Check the contracts called `contracts` in `uri` with solc's SMTChecker, replacing the
+    /// file's previous findings.
+    async fn model_check(&self, uri: Url, contracts: &[String]) {
+        let Ok(path) = uri.to_file_path() else {
+            return;
+        };
+        let Some(root) = build_info::find_project_root(&path) else {
+            return;
+        };
+        let relative = path.strip_prefix(&root).unwrap_or(&path).to_string_lossy();
+        let selected: Vec<(&str, &str)> = contracts
+            .iter()
+            .map(|name| (relative.as_ref(), name.as_str()))
+            .collect();
+        let config = {
+            let settings = self.settings.read().await;
+            model_checker::model_checker_config(&settings.model_checker, &selected)
+        };
+
+        let progress = ProgressReporter::begin(&self.client, "Running the SMTChecker").await;
+        let output = self
+            .compiler
+            .model_check(&root.to_string_lossy(), &config)
+            .await;
+        progress
+            .end(format!("Checked {}", contracts.join(", ")))
+            .await;
+        let output = match output {
+            Ok(output) => output,
+            Err(e) => {
+                self.client
+                    .show_message(MessageType::ERROR, format!("SMTChecker run failed: {e}"))
+                    .await;
+                return;
+            }
+        };
+        let findings = match self.documents.read(&uri).await {
+            Ok(source_bytes) => {
+                model_checker::model_checker_diagnostics(&output, &root, &path, &source_bytes)
+            }
+            Err(_) => vec![],
+        };
+        self.logger.log(
+            MessageType::INFO,
+            format!("SMTChecker: {} findings in {relative}", findings.len()),
+        );
+        self.model_findings
+            .lock()
+            .await
+            .insert(uri.clone(), findings);
+        self.publish_diagnostics(uri, None).await;
+    }
+
+    /// Check the contracts of the saved `uri` that `modelChecker.contracts` selects.
+    async fn model_check_on_save(&self, uri: &Url) {
+        let Some((root, path)) = uri.to_file_path().ok().and_then(|path| {
+            let root = build_info::find_project_root(&path)?;
+            Some((root, path))
+        }) else {
+            return;
+        };
+        let relative = path.strip_prefix(&root).unwrap_or(&path).to_string_lossy();
+        let contracts: Vec<String> = self
+            .settings
+            .read()
+            .await
+            .model_checker
+            .contracts
+            .iter()
+            .filter_map(|selected| {
+                let (file, name) = selected.rsplit_once(':')?;
+                (file.trim_start_matches("./") == relative).then(|| name.to_string())
+            })
+            .collect();
+        if contracts.is_empty() {
+            return;
+        }
+        let server = self.clone();
+        let uri = uri.clone();
+        tokio::spawn(async move { server.model_check(uri, &contracts).await });
+    }
+
+    /// The fuzz targets of the project at `root`, if it can be indexed.
+    async fn fuzz_targets(&self, root: &Path) -> Option<(Arc<ProjectIndex>, FuzzTargets)> {
+        match self.index.get_or_build(root).await {
+            Ok(project) => {
+                let targets = FuzzTargets::new(&project.ast);
+                Some((project, targets))
+            }
+            Err(e) => {
+                self.logger.log(
+                    MessageType::WARNING,
+                    format!("Failed to index {}: {e}", root.display()),
+                );
+                None
+            }
+        }
+    }
+
+    /// Check the contract and function references of the fuzzer configuration `uri`, whose
+    /// text is `text`, replacing its last diagnostics.
+    async fn check_fuzz_config(
+        &self,
+        uri: &Url,
+        fuzzer: Fuzzer,
+        root: &Path,
+        text: &str,
+        version: Option<i32>,
+    ) {
+        let Some((_, targets)) = self.fuzz_targets(root).await else {
+            return;
+        };
+        let diagnostics = fuzz_config::config_diagnostics(fuzzer, text, &targets);
+        self.diagnostics
+            .lock()
+            .await
+            .insert(uri.clone(), diagnostics);
+        self.publish_diagnostics(uri.clone(), version).await;
+    }
+
+    /// Replace the findings of the last fuzzer run with the failed properties of `parser`.
+    async fn publish_fuzz_findings(
+        &self,
+        parser: &OutputParser,
+        fuzzer: Fuzzer,
+        project: &ProjectIndex,
+    ) {
+        let findings = fuzz_config::violation_diagnostics(
+            fuzzer,
+            &parser.results,
+            &project.ast,
+            &project.root,
+        );
+        let mut uris: HashSet<Url> = findings.keys().cloned().collect();
+        {
+            let mut last = self.fuzz_findings.lock().await;
+            uris.extend(last.drain().map(|(uri, _)| uri));
+            last.extend(findings);
+        }
+        for uri in uris {
+            self.publish_diagnostics(uri, None).await;
+        }
+    }
+
+    /// Run the fuzzer of the configuration `config`, on `contract` only when given,
+    /// reporting each falsified property as soon as the fuzzer prints it.
+    async fn run_fuzzer(
+        &self,
+        config: Url,
+        fuzzer: Fuzzer,
+        root: PathBuf,
+        contract: Option<String>,
+    ) {
+        let Ok(config_path) = config.to_file_path() else {
+            return;
+        };
+        let Some((project, _)) = self.fuzz_targets(&root).await else {
+            return;
+        };
+
+        let progress =
+            ProgressReporter::begin(&self.client, &format!("Running {}", fuzzer.name())).await;
+        let (lines, mut output) = mpsc::unbounded_channel();
+        let compiler = self.compiler.clone();
+        let run = tokio::spawn(async move {
+            compiler
+                .run_fuzzer(
+                    &root.to_string_lossy(),
+                    fuzzer,
+                    &config_path.to_string_lossy(),
+                    contract.as_deref(),
+                    lines,
+                )
+                .await
+        });
+
+        let mut parser = OutputParser::new(fuzzer);
+        self.publish_fuzz_findings(&parser, fuzzer, &project).await;
+        while let Some(line) = output.recv().await {
+            if parser.push_line(&line) {
+                self.publish_fuzz_findings(&parser, fuzzer, &project).await;
+            }
+        }
+
+        let failed = parser.results.iter().filter(|result| result.failed).count();
+        let summary = format!("{failed} of {} properties falsified", parser.results.len());
+        progress.end(summary.clone()).await;
+        match run.await {
+            Ok(Ok(passed)) => {
+                let kind = if passed && failed == 0 {
+                    MessageType::INFO
+                } else {
+                    MessageType::WARNING
                 };
+                self.client
+                    .show_message(kind, format!("{}: {summary}", fuzzer.name()))
+                    .await;
+            }
+            Ok(Err(e)) => {
+                self.client
+                    .show_message(
+                        MessageType::ERROR,
+                        format!("Failed to run {}: {e}", fuzzer.name()),
+                    )
+                    .await;
+            }
+            Err(e) => {
+                self.logger
+                    .log(MessageType::ERROR, format!("Fuzzer task failed: {e}"));
+            }
+        }
+    }
+
+    /// The edit of a rename within `scope`, validated but not applied, as one versioned edit
+    /// per file. With `rename.confirmOverrides`, the edits renaming the functions of the
+    /// inheritance chain are annotated for the client to confirm.
+    async fn rename_edit(
+        &self,
+        params: &RenameParams,
+        scope: RenameScope,
+    ) -> tower_lsp::jsonrpc::Result<Option<WorkspaceEdit>> {
+        let uri = &params.text_document_position.text_document.uri;
+        let position = params.text_document_position.position;
+        let new_name = params.new_name.clone();
+
+        let Some(source_bytes) = self.read_document(uri, position).await? else {
+            return Ok(None);
+        };
+
+        // Get the current identifier at the position
+        let current_identifier = match rename::prepare_rename(&source_bytes, position) {
+            Ok((_, id)) => id,
+            Err(RenameError::NotIdentifier) => {
+                self.logger
+                    .log(MessageType::INFO, "No identifier found at position");
+                return Ok(None);
+            }
+            Err(e) => return Err(tower_lsp::jsonrpc::Error::invalid_params(e.to_string())),
+        };
+
+        // Validate the new name
+        if let Err(e) = rename::validate_new_name(&new_name) {
+            return Err(tower_lsp::jsonrpc::Error::invalid_params(e.to_string()));
+        }
+
+        // If the new name is the same as the current identifier, no change needed
+        if new_name == current_identifier {
+            self.logger.log(
+                MessageType::INFO,
+                "New name is the same as current identifier",
+            );
+            return Ok(None);
+        }
+
+        let Some(ast_data) = self.request_ast(uri).await? else {
+            return Ok(None);
+        };
+
+        let settings = self.settings.read().await.rename.clone();
+        let documents = self.documents.snapshot().await;
+        let renamed = rename::rename_with_overrides(
+            &ast_data,
+            uri,
+            position,
+            &source_bytes,
+            new_name,
+            settings.overrides,
+            &documents,
+        );
+        let (edit, overrides) = match renamed {
+            Some((edit, overrides)) => {
+                let scoped =
+                    rename::restrict_to_scope(edit, scope, &ast_data, uri, position, &source_bytes);
+                if scoped.is_none() {
+                    return Err(tower_lsp::jsonrpc::Error::invalid_params(
+                        "The cursor is not inside a contract",
+                    ));
+                }
+                (scoped, overrides)
+            }
+            None => (None, vec![]),
+        };
+
+        // Definitions reached in dependencies are read-only: renaming one would edit
+        // vendored code the next `forge update` overwrites
+        let dependency = edit
+            .iter()
+            .flat_map(|edit| edit.changes.iter().flat_map(|changes| changes.keys()))
+            .filter_map(|uri| uri.to_file_path().ok())
+            .find(|path| {
+                ProjectConfig::find(path).is_some_and(|config| config.is_dependency(path))
+            });
+        if let Some(path) = dependency {
+            return Err(tower_lsp::jsonrpc::Error::invalid_params(format!(
+                "Rename would edit the dependency file {}",
+                path.display()
+            )));
+        }
+
+        if edit.is_none() {
+            self.logger
+                .log(MessageType::INFO, "No locations found for renaming");
+        }
+
+        // The client applies every file's edits, so renamed files show as unsaved buffers and
+        // one undo reverts the rename
+        let versions = self.built_versions().await;
+        Ok(edit.map(|edit| {
+            let edit = rename::versioned_edit(edit, &versions);
+            if settings.confirm_overrides {
+                rename::annotate_overrides(edit, &overrides)
+            } else {
+                edit
+            }
+        }))
+    }
+}
+
+#[tower_lsp::async_trait]
+impl LanguageServer for ForgeLsp {
+    async fn initialize(
+        &self,
+        params: InitializeParams,
+    ) -> tower_lsp::jsonrpc::Result<InitializeResult> {
+        self.apply_settings(Settings::from_value(params.initialization_options.as_ref()))
+            .await;
+        *self.folders.write().await = WorkspaceFolders::from_params(&params);
+        *self.client_locale.write().await = params.locale.clone();
+
+        Ok(InitializeResult {
+            server_info: Some(ServerInfo {
+                name: "forge lsp".to_string(),
+                version: Some("0.0.1".to_string()),
+            }),
+            capabilities: ServerCapabilities {
+                definition_provider: Some(OneOf::Left(true)),
+                declaration_provider: Some(DeclarationCapability::Simple(true)),
+                implementation_provider: Some(ImplementationProviderCapability::Simple(true)),
+                linked_editing_range_provider: Some(LinkedEditingRangeServerCapabilities::Simple(
+                    true,
+                )),
+                hover_provider: Some(HoverProviderCapability::Simple(true)),
+                completion_provider: Some(CompletionOptions {
+                    trigger_characters: Some(
+                        completion::TRIGGER_CHARACTERS
+                            .iter()
+                            .chain(natspec::TRIGGER_CHARACTERS)
+                            .map(|c| c.to_string())
+                            .collect(),
+                    ),
+                    ..CompletionOptions::default()
+                }),
+                references_provider: Some(OneOf::Left(true)),
+                document_highlight_provider: Some(OneOf::Left(true)),
+                call_hierarchy_provider: Some(CallHierarchyServerCapability::Simple(true)),
+                rename_provider: Some(OneOf::Right(RenameOptions {
+                    prepare_provider: Some(true),
+                    work_done_progress_options: WorkDoneProgressOptions::default(),
+                })),
+                workspace_symbol_provider: Some(OneOf::Left(true)),
+                document_symbol_provider: Some(OneOf::Left(true)),
+                folding_range_provider: Some(FoldingRangeProviderCapability::Simple(true)),
+                selection_range_provider: Some(SelectionRangeProviderCapability::Simple(true)),
+                semantic_tokens_provider: Some(
+                    SemanticTokensServerCapabilities::SemanticTokensOptions(
+                        SemanticTokensOptions {
+                            legend: semantic_tokens::legend(),
+                            full: Some(SemanticTokensFullOptions::Bool(true)),
+                            range: Some(true),
+                            ..SemanticTokensOptions::default()
+                        },
+                    ),
+                ),
+                inlay_hint_provider: Some(OneOf::Left(true)),
+                workspace: Some(WorkspaceServerCapabilities {
+                    workspace_folders: Some(WorkspaceFoldersServerCapabilities {
+                        supported: Some(true),
+                        change_notifications: Some(OneOf::Left(true)),
+                    }),
+                    file_operations: Some(WorkspaceFileOperationsServerCapabilities {
+                        did_delete: Some(FileOperationRegistrationOptions {
+                            filters: vec![FileOperationFilter {
+                                scheme: Some("file".to_string()),
+                                pattern: FileOperationPattern {
+                                    glob: SOLIDITY_GLOB.to_string(),
+                                    matches: Some(FileOperationPatternKind::File),
+                                    options: None,
+                                },
+                            }],
+                        }),
+                        // Directories too, for the Solidity files moving with them
+                        will_rename: Some(FileOperationRegistrationOptions {
+                            filters: vec![
+                                FileOperationFilter {
+                                    scheme: Some("file".to_string()),
+                                    pattern: FileOperationPattern {
+                                        glob: SOLIDITY_GLOB.to_string(),
+                                        matches: Some(FileOperationPatternKind::File),
+                                        options: None,
+                                    },
+                                },
+                                FileOperationFilter {
+                                    scheme: Some("file".to_string()),
+                                    pattern: FileOperationPattern {
+                                        glob: "**".to_string(),
+                                        matches: Some(FileOperationPatternKind::Folder),
+                                        options: None,
+                                    },
+                                },
+                            ],
+                        }),
+                        ..WorkspaceFileOperationsServerCapabilities::default()
+                    }),
+                }),
+                code_lens_provider: Some(CodeLensOptions {
+                    resolve_provider: Some(false),
+                }),
+                document_formatting_provider: Some(OneOf::Left(true)),
+                document_range_formatting_provider: Some(OneOf::Left(true)),
+                document_on_type_formatting_provider: Some(DocumentOnTypeFormattingOptions {
+                    first_trigger_character: formatting::ON_TYPE_TRIGGER_CHARACTERS[0].to_string(),
+                    more_trigger_character: Some(
+                        formatting::ON_TYPE_TRIGGER_CHARACTERS[1..]
+                            .iter()
+                            .map(|c| c.to_string())
+                            .collect(),
+                    ),
+                }),
+                code_action_provider: Some(CodeActionProviderCapability::Options(
+                    CodeActionOptions {
+                        code_action_kinds: Some(vec![
+                            CodeActionKind::QUICKFIX,
+                            CodeActionKind::REFACTOR,
+                            CodeActionKind::REFACTOR_EXTRACT,
+                            CodeActionKind::REFACTOR_REWRITE,
+                        ]),
+                        ..CodeActionOptions::default()
+                    },
+                )),
+                execute_command_provider: Some(ExecuteCommandOptions {
+                    commands: vec![
+                        RUN_DIAGNOSTICS_COMMAND.to_string(),
+                        RELOAD_WORKSPACE_COMMAND.to_string(),
+                        SELECTOR_IMPLEMENTATIONS_COMMAND.to_string(),
+                        PREVIEW_DOCS_COMMAND.to_string(),
+                        RUN_TEST_COMMAND.to_string(),
+                        TEST_RUN_COMMAND.to_string(),
+                        BASELINE_COMMAND.to_string(),
+                        FIX_ALL_COMMAND.to_string(),
+                        STORAGE_LAYOUT_COMMAND.to_string(),
+                        REBUILD_COMMAND.to_string(),
+                        MODEL_CHECK_COMMAND.to_string(),
+                        RUN_FUZZER_COMMAND.to_string(),
+                        EXPORT_EVENTS_COMMAND.to_string(),
+                        GENERATE_BINDINGS_COMMAND.to_string(),
+                        FLATTEN_COMMAND.to_string(),
+                        VERIFICATION_BUNDLE_COMMAND.to_string(),
+                    ],
+                    ..ExecuteCommandOptions::default()
+                }),
+                text_document_sync: Some(TextDocumentSyncCapability::Options(
+                    TextDocumentSyncOptions {
+                        open_close: Some(true),
+                        change: Some(TextDocumentSyncKind::INCREMENTAL),
+                        save: Some(TextDocumentSyncSaveOptions::Supported(true)),
+                        ..TextDocumentSyncOptions::default()
+                    },
+                )),
+                ..ServerCapabilities::default()
+            },
+        })
+    }
+
+    async fn initialized(&self, _: InitializedParams) {
+        self.logger
+            .log(MessageType::INFO, "lsp server initialized!");
+
+        self.watch_files().await;
+        let server = self.clone();
+        tokio::spawn(async move { server.index_workspace(true).await });
+    }
+
+    async fn shutdown(&self) -> tower_lsp::jsonrpc::Result<()> {
+        self.logger
+            .log(MessageType::INFO, "lsp server shutting down");
+        Ok(())
+    }
+
+    async fn did_open(&self, params: DidOpenTextDocumentParams) {
+        self.logger.log(MessageType::INFO, "file opened");
+
+        self.documents
+            .open(
+                params.text_document.uri.clone(),
+                params.text_document.text.clone(),
+                params.text_document.version,
+            )
+            .await;
+
+        let uri = params.text_document.uri;
+        let text = params.text_document.text;
+        let large = self.is_large_file(&uri, text.len()).await;
+        if large {
+            self.logger.log(
+                MessageType::INFO,
+                format!(
+                    "{uri} is a large file of {} bytes: semantic tokens are off and \
+                         diagnostics are debounced",
+                    text.len()
+                ),
+            );
+        }
+        if !self.diagnostics_enabled(DiagnosticsEvent::Open).await {
+            return;
+        }
+
+        let version = params.text_document.version;
+        if large {
+            self.schedule_diagnostics(uri, text, Some(version)).await;
+            return;
+        }
+        self.on_change(TextDocumentItem {
+            uri,
+            text: &text,
+            version: Some(version),
+        })
+        .await
+    }
+
+    async fn did_change(&self, params: DidChangeTextDocumentParams) {
+        self.logger.log(MessageType::INFO, "file changed");
+
+        // Invalidate cached AST data for the changed file
+        let uri = params.text_document.uri;
+        if self.ast_provider.invalidate(&uri).await {
+            self.logger.log(
+                MessageType::INFO,
+                "Invalidated cached AST data for changed file",
+            );
+        }
+
+        let version = params.text_document.version;
+        let Some(text) = self
+            .documents
+            .change(&uri, version, params.content_changes)
+            .await
+        else {
+            self.logger.log(
+                MessageType::WARNING,
+                "Change to a document that is not open",
+            );
+            return;
+        };
+
+        if !self.is_large_file(&uri, text.len()).await {
+            self.check_while_editing(&uri, version).await;
+        }
+        if self.diagnostics_enabled(DiagnosticsEvent::Change).await {
+            self.schedule_diagnostics(uri, text, Some(version)).await;
+        }
+    }
+
+    async fn did_save(&self, params: DidSaveTextDocumentParams) {
+        self.logger.log(MessageType::INFO, "file saved");
+
+        // A save supersedes any debounced run from the edits leading up to it
+        self.cancel_pending_diagnostics(&params.text_document.uri)
+            .await;
+        self.reindex(&params.text_document.uri).await;
+        self.remeasure_gas(&params.text_document.uri).await;
+        let server = self.clone();
+        let uri = params.text_document.uri.clone();
+        tokio::spawn(async move { server.test_on_save(uri).await });
+        self.model_check_on_save(&params.text_document.uri).await;
+        if let Some(root) = params
+            .text_document
+            .uri
+            .to_file_path()
+            .ok()
+            .and_then(|path| build_info::find_project_root(&path))
+        {
+            self.storage_layouts
+                .lock()
+                .await
+                .retain(|(layout_root, _), _| *layout_root != root);
+        }
+        if !self.diagnostics_enabled(DiagnosticsEvent::Save).await {
+            return;
+        }
+
+        // Run diagnostics on save, regardless of whether text is provided
+        // If text is provided, use it; otherwise read from file system
+        let text_content = if let Some(text) = params.text {
+            text
+        } else {
+            // Read the file from disk since many LSP clients don't send text on save
+            // `Url::to_file_path` decodes the URI, unlike `Url::path` on e.g. `/C:/...`
+            let content = params
+                .text_document
+                .uri
+                .to_file_path()
+                .map_err(|_| "invalid file URI".to_string())
+                .and_then(|path| std::fs::read_to_string(path).map_err(|e| e.to_string()));
+            match content {
+                Ok(content) => content,
+                Err(e) => {
+                    self.logger.log(
+                        MessageType::ERROR,
+                        format!("Failed to read file on save: {e}"),
+                    );
+                    return;
+                }
+            }
+        };
+
+        let uri = params.text_document.uri;
+        if self.is_large_file(&uri, text_content.len()).await {
+            self.schedule_diagnostics(uri, text_content, None).await;
+            return;
+        }
+        let item = TextDocumentItem {
+            uri,
+            text: &text_content,
+            version: None,
+        };
+
+        // Always run diagnostics on save to reflect the current file state
+        self.on_change(item).await;
+        _ = self.client.semantic_tokens_refresh().await;
+    }
+
+    async fn did_close(&self, params: DidCloseTextDocumentParams) {
+        self.logger.log(MessageType::INFO, "file closed");
+
+        // Reads fall back to the file on disk from here on
+        self.cancel_pending_diagnostics(&params.text_document.uri)
+            .await;
+        self.documents.close(&params.text_document.uri).await;
+        self.syntax_trees.remove(&params.text_document.uri).await;
+    }
+
+    async fn did_change_configuration(&self, params: DidChangeConfigurationParams) {
+        let settings = Settings::from_value(Some(&params.settings));
+        self.logger.log(
+            MessageType::INFO,
+            format!(
+                "configuration changed: diagnostics trigger {:?}",
+                settings.diagnostics.trigger
+            ),
+        );
+        let (hints_changed, diagnostics_changed) = {
+            let current = self.settings.read().await;
+            (
+                current.inlay_hints != settings.inlay_hints,
+                current.diagnostics != settings.diagnostics,
+            )
+        };
+        self.apply_settings(settings).await;
+        if hints_changed {
+            // Clients only ask for hints again when told to
+            let _ = self.client.inlay_hint_refresh().await;
+        }
+        if diagnostics_changed {
+            // The last diagnostics are kept unfiltered, so they are published again as is
+            for (uri, version) in self.documents.versions().await {
+                self.publish_diagnostics(uri, Some(version)).await;
+            }
+        }
+    }
+
+    async fn did_change_workspace_folders(&self, params: DidChangeWorkspaceFoldersParams) {
+        self.logger
+            .log(MessageType::INFO, "workspace folders changed!");
+
+        let (before, after) = {
+            let mut folders = self.folders.write().await;
+            let before = folders.projects();
+            folders.change(&params.event);
+            (before, folders.projects())
+        };
+        for root in before.iter().filter(|root| !after.contains(root)) {
+            self.index.remove(root).await;
+            let stale = self.stale_artifacts.lock().await.remove(root).flatten();
+            if stale.is_some()
+                && let Ok(uri) = Url::from_file_path(root.join("foundry.toml"))
+            {
+                self.client.publish_diagnostics(uri, vec![], None).await;
+            }
+        }
+        for folder in params.event.added.iter() {
+            if let Ok(folder) = folder.uri.to_file_path() {
+                self.heads.lock().await.update(&git::head_file(&folder));
+            }
+        }
+
+        let added: Vec<PathBuf> = after
+            .into_iter()
+            .filter(|root| !before.contains(root))
+            .collect();
+        if !added.is_empty() {
+            let server = self.clone();
+            tokio::spawn(async move { server.index_projects(added, true).await });
+        }
+    }
+
+    async fn did_change_watched_files(&self, params: DidChangeWatchedFilesParams) {
+        self.logger
+            .log(MessageType::INFO, "watched files have changed!");
+
+        self.on_head_change(&params.changes).await;
+        self.on_project_files_change(&params.changes).await;
+        let solidity = |typ: FileChangeType| -> Vec<Url> {
+            params
+                .changes
+                .iter()
+                .filter(|change| change.typ == typ && change.uri.path().ends_with(".sol"))
+                .map(|change| change.uri.clone())
+                .collect()
+        };
+        let mut deleted = solidity(FileChangeType::DELETED);
+        let created = solidity(FileChangeType::CREATED);
+        for (from, to) in self.detect_renames(&mut deleted, &created).await {
+            self.on_renamed(from, to).await;
+        }
+        if !deleted.is_empty() {
+            self.on_deleted(deleted).await;
+        }
+    }
+
+    async fn did_delete_files(&self, params: DeleteFilesParams) {
+        self.logger.log(MessageType::INFO, "files deleted");
+
+        let deleted = params
+            .files
+            .iter()
+            .filter_map(|file| Url::parse(&file.uri).ok())
+            .collect();
+        self.on_deleted(deleted).await;
+    }
 
-                match self.compiler.ast(path_str).await {
-                    Ok(data) => {
-                        self.client
-                            .log_message(MessageType::INFO, "Fetched and caching new AST data")
-                            .await;
+    async fn will_rename_files(
+        &self,
+        params: RenameFilesParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<WorkspaceEdit>> {
+        self.logger
+            .log(MessageType::INFO, "Got a workspace/willRenameFiles request");
 
-                        // Cache the new AST data
-                        let mut cache = self.ast_cache.write().await;
-                        cache.insert(uri.to_string(), data.clone());
-                        data
-                    }
-                    Err(e) => {
-                        self.client
-                            .log_message(MessageType::ERROR, format!("Failed to get AST: {e}"))
-                            .await;
-                        return Ok(None);
-                    }
-                }
-            }
+        let path = |uri: &str| Url::parse(uri).ok()?.to_file_path().ok();
+        let moves: Vec<(PathBuf, PathBuf)> = params
+            .files
+            .iter()
+            .filter_map(|file| Some((path(&file.old_uri)?, path(&file.new_uri)?)))
+            .flat_map(|(from, to)| file_renames::moved_files(&from, &to))
+            .collect();
+
+        // Open files are edited as the client has them
+        let documents = self.documents.snapshot().await;
+        let changes = file_renames::import_edits(&moves, |path| {
+            String::from_utf8(documents.read(path)?).ok()
+        });
+        let edited: usize = changes.values().map(Vec::len).sum();
+        self.logger.log(
+            MessageType::INFO,
+            format!("Updating {edited} imports in {} files", changes.len()),
+        );
+        if changes.is_empty() {
+            return Ok(None);
+        }
+
+        let versions: HashMap<Url, i32> = self.documents.versions().await.into_iter().collect();
+        let edit = WorkspaceEdit {
+            changes: Some(changes),
+            ..WorkspaceEdit::default()
         };
+        Ok(Some(rename::versioned_edit(edit, &versions)))
+    }
 
-        // Use goto_declaration function (same logic for both definition and declaration)
-        if let Some(location) = goto::goto_declaration(&ast_data, &uri, position, &source_bytes) {
-            self.client
-                .log_message(
-                    MessageType::INFO,
-                    format!(
-                        "Found definition at {}:{}",
-                        location.uri, location.range.start.line
-                    ),
+    async fn goto_definition(
+        &self,
+        params: GotoDefinitionParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<GotoDefinitionResponse>> {
+        self.logger
+            .log(MessageType::INFO, "Got a textDocument/definition request");
+
+        let (uri, position) = self
+            .flattened_origin(
+                params.text_document_position_params.text_document.uri,
+                params.text_document_position_params.position,
+            )
+            .await;
+
+        let Some(source_bytes) = self.read_document(&uri, position).await? else {
+            return Ok(None);
+        };
+
+        // Import strings resolve through the project's remappings, without an AST
+        if let Ok(path) = uri.to_file_path()
+            && let Some(location) =
+                project::goto_import(&path, &String::from_utf8_lossy(&source_bytes), position)
+        {
+            return Ok(Some(GotoDefinitionResponse::from(location)));
+        }
+
+        // So do the cheatcodes and console functions of forge-std, by name
+        if let Ok(path) = uri.to_file_path() {
+            let mut locations = cheatcodes::cheatcode_locations(
+                &String::from_utf8_lossy(&source_bytes),
+                position,
+                &path,
+            );
+            match locations.len() {
+                0 => {}
+                1 => return Ok(locations.pop().map(GotoDefinitionResponse::from)),
+                _ => return Ok(Some(GotoDefinitionResponse::Array(locations))),
+            }
+        }
+
+        // Before the compiler has an AST for the file, answer within the file from the
+        // in-process parse and compile in the background for the next request
+        let location = match self.ast_provider.available(&uri).await {
+            Some(ast_data) => {
+                let documents = self.documents.snapshot().await;
+                let location =
+                    goto::goto_declaration(&ast_data, &uri, position, &source_bytes, &documents);
+                let locations: Vec<Location> = location.iter().cloned().collect();
+                self.report_stale(
+                    "textDocument/definition",
+                    &uri,
+                    stale::result_files(&uri, &locations),
                 )
                 .await;
-            Ok(Some(GotoDefinitionResponse::from(location)))
-        } else {
-            self.client
-                .log_message(MessageType::INFO, "No definition found")
-                .await;
-            // Fallback to current position
-            let location = Location {
-                uri,
-                range: Range {
-                    start: position,
-                    end: position,
-                },
-            };
-            Ok(Some(GotoDefinitionResponse::from(location)))
+                location
+            }
+            None => {
+                let ast_provider = self.ast_provider.clone();
+                let background_uri = uri.clone();
+                tokio::spawn(async move {
+                    _ = ast_provider.get_or_fetch(&background_uri).await;
+                });
+                self.syntax_trees
+                    .get(&uri, &source_bytes)
+                    .await
+                    .and_then(|tree| syntax::goto_definition(&tree, &uri, position, &source_bytes))
+            }
+        };
+
+        if let Some(location) = location {
+            self.logger.log(
+                MessageType::INFO,
+                format!(
+                    "Found definition at {}:{}",
+                    location.uri, location.range.start.line
+                ),
+            );
+            return Ok(Some(GotoDefinitionResponse::from(location)));
+        }
+
+        // Neither parses: scan the buffer and look in the last successful build
+        let project = self.index.last_built_for(&uri).await;
+        let mut candidates =
+            fallback::definitions(&uri, &source_bytes, position, project.as_deref());
+        // Only the declarations found in other files come from the last build
+        let built: Vec<Url> = stale::result_files(&uri, &candidates)
+            .into_iter()
+            .skip(1)
+            .collect();
+        self.report_stale("textDocument/definition", &uri, built)
+            .await;
+        self.logger.log(
+            MessageType::INFO,
+            format!("Found {} definitions by scanning tokens", candidates.len()),
+        );
+        match candidates.len() {
+            0 => Ok(None),
+            1 => Ok(Some(GotoDefinitionResponse::from(candidates.remove(0)))),
+            _ => Ok(Some(GotoDefinitionResponse::Array(candidates))),
         }
     }
 
@@ -405,447 +3088,497 @@ impl LanguageServer for ForgeLsp {
         &self,
         params: request::GotoDeclarationParams,
     ) -> tower_lsp::jsonrpc::Result<Option<request::GotoDeclarationResponse>> {
-        self.client
-            .log_message(MessageType::INFO, "Got a textDocument/declaration request")
-            .await;
+        self.logger
+            .log(MessageType::INFO, "Got a textDocument/declaration request");
 
         let uri = params.text_document_position_params.text_document.uri;
         let position = params.text_document_position_params.position;
 
-        // Get the file path from URI
-        let file_path = match uri.to_file_path() {
-            Ok(path) => path,
-            Err(_) => {
-                self.client
-                    .log_message(MessageType::ERROR, "Invalid file URI")
-                    .await;
-                return Ok(None);
-            }
+        let Some(source_bytes) = self.read_document(&uri, position).await? else {
+            return Ok(None);
         };
 
-        // Read the source file
-        let source_bytes = match std::fs::read(&file_path) {
-            Ok(bytes) => bytes,
-            Err(e) => {
-                self.client
-                    .log_message(MessageType::ERROR, format!("Failed to read file: {e}"))
-                    .await;
-                return Ok(None);
-            }
+        let Some(ast_data) = self.request_ast(&uri).await? else {
+            return Ok(None);
         };
 
-        // Try to get AST data from cache first
-        let ast_data = {
-            let cache = self.ast_cache.read().await;
-            if let Some(cached_ast) = cache.get(&uri.to_string()) {
-                self.client
-                    .log_message(MessageType::INFO, "Using cached AST data")
-                    .await;
-                cached_ast.clone()
-            } else {
-                // Cache miss - get AST data and cache it
-                drop(cache); // Release read lock
-
-                let path_str = match file_path.to_str() {
-                    Some(s) => s,
-                    None => {
-                        self.client
-                            .log_message(MessageType::ERROR, "Invalid file path")
-                            .await;
-                        return Ok(None);
-                    }
-                };
+        let documents = self.documents.snapshot().await;
+        let location = goto::goto_declaration(&ast_data, &uri, position, &source_bytes, &documents);
+        let locations: Vec<Location> = location.iter().cloned().collect();
+        self.report_stale(
+            "textDocument/declaration",
+            &uri,
+            stale::result_files(&uri, &locations),
+        )
+        .await;
+        if let Some(location) = location {
+            self.logger.log(
+                MessageType::INFO,
+                format!(
+                    "Found declaration at {}:{}",
+                    location.uri, location.range.start.line
+                ),
+            );
+            Ok(Some(request::GotoDeclarationResponse::from(location)))
+        } else {
+            self.logger.log(MessageType::INFO, "No declaration found");
+            Ok(None)
+        }
+    }
 
-                match self.compiler.ast(path_str).await {
-                    Ok(data) => {
-                        self.client
-                            .log_message(MessageType::INFO, "Fetched and caching new AST data")
-                            .await;
+    async fn goto_implementation(
+        &self,
+        params: request::GotoImplementationParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<request::GotoImplementationResponse>> {
+        self.logger.log(
+            MessageType::INFO,
+            "Got a textDocument/implementation request",
+        );
 
-                        // Cache the new AST data
-                        let mut cache = self.ast_cache.write().await;
-                        cache.insert(uri.to_string(), data.clone());
-                        data
-                    }
-                    Err(e) => {
-                        self.client
-                            .log_message(MessageType::ERROR, format!("Failed to get AST: {e}"))
-                            .await;
-                        return Ok(None);
+        let uri = params.text_document_position_params.text_document.uri;
+        let position = params.text_document_position_params.position;
+        // Roles are granted anywhere in the project, so only the project AST sees them all
+        let (Some(project), Ok(source_bytes)) = (
+            self.index.project_for(&uri).await,
+            self.documents.read(&uri).await,
+        ) else {
+            return Ok(None);
+        };
+        RequestError::check_position(&uri, &source_bytes, position)?;
+        let Some(symbol) = ast::symbol_at_position(&project.ast, &uri, position, &source_bytes)
+        else {
+            return Ok(None);
+        };
+        let sites = roles::grant_sites(&project.ast, &project.root, symbol.declaration_id);
+        self.report_stale(
+            "textDocument/implementation",
+            &uri,
+            stale::result_files(&uri, sites.as_deref().unwrap_or_default()),
+        )
+        .await;
+        Ok(sites.map(request::GotoImplementationResponse::Array))
+    }
+
+    async fn hover(&self, params: HoverParams) -> tower_lsp::jsonrpc::Result<Option<Hover>> {
+        self.logger
+            .log(MessageType::INFO, "Got a textDocument/hover request");
+
+        let requested = params.text_document_position_params.text_document.uri;
+        let (uri, position) = self
+            .flattened_origin(
+                requested.clone(),
+                params.text_document_position_params.position,
+            )
+            .await;
+        if uri != requested {
+            // The hover is computed in the original file, whose ranges the view doesn't share
+            return Ok(Box::pin(self.hover(HoverParams {
+                text_document_position_params: TextDocumentPositionParams::new(
+                    TextDocumentIdentifier::new(uri),
+                    position,
+                ),
+                work_done_progress_params: params.work_done_progress_params,
+            }))
+            .await?
+            .map(|hover| Hover {
+                range: None,
+                ..hover
+            }));
+        }
+
+        // The license, pragma, cheatcode and rule hovers need no build
+        if let Ok(source_bytes) = self.documents.read(&uri).await {
+            RequestError::check_position(&uri, &source_bytes, position)?;
+            let source = String::from_utf8_lossy(&source_bytes);
+            let path = uri.to_file_path().ok();
+            if let Some(path) = &path
+                && path.file_name().is_some_and(|name| name == "foundry.toml")
+            {
+                return Ok(path
+                    .parent()
+                    .and_then(|root| profiles::profile_hover(&source, position, root)));
+            }
+            if let Some(hover) = path
+                .as_deref()
+                .and_then(|path| cheatcodes::cheatcode_hover(&source, position, path))
+            {
+                return Ok(Some(hover));
+            }
+            let root = path.and_then(|path| build_info::find_project_root(&path));
+            if let Some(hover) = header::header_hover(&source, position, root.as_deref()) {
+                return Ok(Some(hover));
+            }
+            if let Some(hover) = i18n::rule_hover(&source, position, self.locale().await) {
+                return Ok(Some(hover));
+            }
+        }
+
+        let Some((source_bytes, ast_data)) = self.source_and_ast(&uri).await? else {
+            return Ok(None);
+        };
+        // Between the names of a tuple there is no symbol, only the values it takes apart
+        let mut hover = hover::hover(&ast_data, &uri, position, &source_bytes)
+            .or_else(|| tuples::tuple_hover(&ast_data, &uri, position, &source_bytes));
+        if let Some(Hover {
+            contents: HoverContents::Markup(markup),
+            ..
+        }) = &mut hover
+            && let Some(root) = uri
+                .to_file_path()
+                .ok()
+                .and_then(|path| build_info::find_project_root(&path))
+            && let Some(report) = self.gas_report(&root).await
+            && let Some(section) =
+                gas::gas_hover_section(&report, &ast_data, &uri, &root, position, &source_bytes)
+        {
+            markup.value.push_str("\n\n");
+            markup.value.push_str(&section);
+        }
+        if let Some(Hover {
+            contents: HoverContents::Markup(markup),
+            ..
+        }) = &mut hover
+            && self.settings.read().await.storage_layout_hovers
+            && let Ok(path) = uri.to_file_path()
+            && let Some(root) = build_info::find_project_root(&path)
+            && let Some((contract, label)) =
+                storage_layout::state_variable_at(&ast_data, &uri, position, &source_bytes)
+        {
+            match self.storage_layout(&root, &path, contract).await {
+                Ok(layout) => {
+                    if let Some(section) =
+                        storage_layout::storage_hover_section(&layout, contract, label)
+                    {
+                        markup.value.push_str("\n\n");
+                        markup.value.push_str(&section);
                     }
                 }
+                Err(e) => {
+                    self.logger.log(
+                        MessageType::WARNING,
+                        format!("Failed to inspect the storage layout of {contract}: {e}"),
+                    );
+                }
             }
-        };
-
-        // Use goto_declaration function
-        if let Some(location) = goto::goto_declaration(&ast_data, &uri, position, &source_bytes) {
-            self.client
-                .log_message(
-                    MessageType::INFO,
-                    format!(
-                        "Found declaration at {}:{}",
-                        location.uri, location.range.start.line
-                    ),
-                )
-                .await;
-            Ok(Some(request::GotoDeclarationResponse::from(location)))
-        } else {
-            self.client
-                .log_message(MessageType::INFO, "No declaration found")
-                .await;
-            // Fallback to current position
-            let location = Location {
-                uri,
-                range: Range {
-                    start: position,
-                    end: position,
-                },
-            };
-            Ok(Some(request::GotoDeclarationResponse::from(location)))
         }
+        Ok(hover)
     }
 
-    async fn references(
+    async fn completion(
         &self,
-        params: ReferenceParams,
-    ) -> tower_lsp::jsonrpc::Result<Option<Vec<Location>>> {
-        self.client
-            .log_message(MessageType::INFO, "Got a textDocument/references request")
-            .await;
+        params: CompletionParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<CompletionResponse>> {
+        self.logger
+            .log(MessageType::INFO, "Got a textDocument/completion request");
 
         let uri = params.text_document_position.text_document.uri;
         let position = params.text_document_position.position;
 
-        // Get the file path from URI
-        let file_path = match uri.to_file_path() {
-            Ok(path) => path,
-            Err(_) => {
-                self.client
-                    .log_message(MessageType::ERROR, "Invalid file URI")
-                    .await;
+        if let Some((fuzzer, root)) = fuzzer_config(&uri) {
+            let (Ok(source_bytes), Some((_, targets))) = (
+                self.documents.read(&uri).await,
+                self.fuzz_targets(&root).await,
+            ) else {
                 return Ok(None);
+            };
+            let source = String::from_utf8_lossy(&source_bytes);
+            let items = fuzz_config::config_completions(fuzzer, &source, position, &targets);
+            return Ok((!items.is_empty()).then_some(CompletionResponse::Array(items)));
+        }
+
+        // Constructor arguments in scripts complete from the index, without compiling
+        if let Some((source_bytes, _, project)) = self.script_syntax(&uri).await {
+            let items =
+                constructor_args::constructor_completions(&project.ast, position, &source_bytes);
+            if !items.is_empty() {
+                return Ok(Some(CompletionResponse::Array(items)));
             }
-        };
+        }
 
-        // Read the source file
-        let source_bytes = match std::fs::read(&file_path) {
-            Ok(bytes) => bytes,
-            Err(e) => {
-                self.client
-                    .log_message(MessageType::ERROR, format!("Failed to read file: {e}"))
-                    .await;
-                return Ok(None);
+        // Doc comments complete from the buffer's syntax tree; the NatSpec trigger characters
+        // only complete there
+        if let Some((source_bytes, tree)) = self.source_and_syntax(&uri).await {
+            let items = natspec::natspec_completions(&tree, &uri, position, &source_bytes);
+            if !items.is_empty() {
+                return Ok(Some(CompletionResponse::Array(items)));
             }
+        }
+        let trigger = params.context.and_then(|context| context.trigger_character);
+        if trigger.is_some_and(|trigger| natspec::TRIGGER_CHARACTERS.contains(&&*trigger)) {
+            return Ok(None);
+        }
+
+        let Some((source_bytes, ast_data)) = self.source_and_ast(&uri).await? else {
+            return Ok(None);
         };
+        RequestError::check_position(&uri, &source_bytes, position)?;
+        let items = completion::completions(&ast_data, &uri, position, &source_bytes);
+        if items.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(CompletionResponse::Array(items)))
+        }
+    }
 
-        // Try to get AST data from cache first
-        let ast_data = {
-            let cache = self.ast_cache.read().await;
-            if let Some(cached_ast) = cache.get(&uri.to_string()) {
-                self.client
-                    .log_message(MessageType::INFO, "Using cached AST data")
-                    .await;
-                cached_ast.clone()
-            } else {
-                // Cache miss - get AST data and cache it
-                drop(cache); // Release read lock
-
-                let path_str = match file_path.to_str() {
-                    Some(s) => s,
-                    None => {
-                        self.client
-                            .log_message(MessageType::ERROR, "Invalid file path")
-                            .await;
-                        return Ok(None);
-                    }
-                };
+    async fn document_highlight(
+        &self,
+        params: DocumentHighlightParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<Vec<DocumentHighlight>>> {
+        self.logger.log(
+            MessageType::INFO,
+            "Got a textDocument/documentHighlight request",
+        );
 
-                match self.compiler.ast(path_str).await {
-                    Ok(data) => {
-                        self.client
-                            .log_message(MessageType::INFO, "Fetched and caching new AST data")
-                            .await;
+        let uri = params.text_document_position_params.text_document.uri;
+        let position = params.text_document_position_params.position;
 
-                        // Cache the new AST data
-                        let mut cache = self.ast_cache.write().await;
-                        cache.insert(uri.to_string(), data.clone());
-                        data
-                    }
-                    Err(e) => {
-                        self.client
-                            .log_message(MessageType::ERROR, format!("Failed to get AST: {e}"))
-                            .await;
-                        return Ok(None);
-                    }
-                }
-            }
+        let Some(source_bytes) = self.read_document(&uri, position).await? else {
+            return Ok(None);
         };
 
-        // Use goto_references function to find all references
-        let locations = references::goto_references(&ast_data, &uri, position, &source_bytes);
+        // Highlighting follows the cursor, so it never waits for a compile: indexed projects
+        // keep their reference graph, other files use the last AST or the in-process parse
+        let highlights = if let Some(project) = self.index.project_for(&uri).await {
+            project.highlights(&uri, position, &source_bytes)
+        } else if let Some(ast_data) = self.ast_provider.available(&uri).await {
+            references::document_highlights(&ast_data, &uri, position, &source_bytes)
+        } else if let Some(tree) = self.syntax_trees.get(&uri, &source_bytes).await {
+            references::document_highlights(&tree, &uri, position, &source_bytes)
+        } else {
+            vec![]
+        };
+        Ok((!highlights.is_empty()).then_some(highlights))
+    }
 
-        if locations.is_empty() {
-            self.client
-                .log_message(MessageType::INFO, "No references found")
-                .await;
-            Ok(None)
+    async fn linked_editing_range(
+        &self,
+        params: LinkedEditingRangeParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<LinkedEditingRanges>> {
+        self.logger.log(
+            MessageType::INFO,
+            "Got a textDocument/linkedEditingRange request",
+        );
+
+        let uri = params.text_document_position_params.text_document.uri;
+        let position = params.text_document_position_params.position;
+        let Some(source_bytes) = self.read_document(&uri, position).await? else {
+            return Ok(None);
+        };
+
+        // Only compiled ASTs resolve every occurrence, so the in-process parse isn't used:
+        // an occurrence it misses would be left out of the edit
+        Ok(if let Some(project) = self.index.project_for(&uri).await {
+            project.linked_editing_ranges(&uri, position, &source_bytes)
+        } else if let Some(ast_data) = self.ast_provider.available(&uri).await {
+            references::linked_editing_ranges(&ast_data, &uri, position, &source_bytes)
         } else {
-            self.client
-                .log_message(
-                    MessageType::INFO,
-                    format!("Found {} references", locations.len()),
-                )
-                .await;
-            Ok(Some(locations))
-        }
+            None
+        })
     }
 
-    async fn rename(
+    async fn references(
         &self,
-        params: RenameParams,
-    ) -> tower_lsp::jsonrpc::Result<Option<WorkspaceEdit>> {
-        self.client
-            .log_message(MessageType::INFO, "Got a textDocument/rename request")
-            .await;
+        params: ReferenceParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<Vec<Location>>> {
+        self.logger
+            .log(MessageType::INFO, "Got a textDocument/references request");
 
-        let uri = params.text_document_position.text_document.uri;
-        let position = params.text_document_position.position;
-        let new_name = params.new_name;
+        let (uri, position) = self
+            .flattened_origin(
+                params.text_document_position.text_document.uri,
+                params.text_document_position.position,
+            )
+            .await;
 
-        // Get the file path from URI
-        let file_path = match uri.to_file_path() {
-            Ok(path) => path,
-            Err(_) => {
-                self.client
-                    .log_message(MessageType::ERROR, "Invalid file URI")
-                    .await;
-                return Ok(None);
-            }
+        let Some(source_bytes) = self.read_document(&uri, position).await? else {
+            return Ok(None);
         };
 
-        // Read the source file
-        let source_bytes = match std::fs::read(&file_path) {
-            Ok(bytes) => bytes,
-            Err(e) => {
-                self.client
-                    .log_message(MessageType::ERROR, format!("Failed to read file: {e}"))
-                    .await;
+        // Indexed projects keep their reference graph. A file of a project that isn't indexed
+        // yet gets it indexed rather than compiled alone, which would only see its imports
+        if self.index.project_for(&uri).await.is_none()
+            && let Some(root) = uri
+                .to_file_path()
+                .ok()
+                .and_then(|path| build_info::find_project_root(&path))
+            && let Err(e) = self.index.get_or_build(&root).await
+        {
+            self.logger.log(
+                MessageType::WARNING,
+                format!("Failed to index {}: {e}", root.display()),
+            );
+        }
+        let documents = self.documents.snapshot().await;
+        let locations = if self.index.project_for(&uri).await.is_some() {
+            self.index
+                .references(&uri, position, &source_bytes, &documents)
+                .await
+        } else {
+            let Some(ast_data) = self.request_ast(&uri).await? else {
                 return Ok(None);
-            }
+            };
+            references::goto_references(&ast_data, &uri, position, &source_bytes, &documents)
         };
+        self.report_stale(
+            "textDocument/references",
+            &uri,
+            stale::result_files(&uri, &locations),
+        )
+        .await;
 
-        // Get the current identifier at the position
-        let current_identifier = match rename::get_identifier_at_position(&source_bytes, position) {
-            Some(id) => id,
-            None => {
-                self.client
-                    .log_message(MessageType::INFO, "No identifier found at position")
-                    .await;
-                return Ok(None);
-            }
+        if locations.is_empty() {
+            self.logger.log(MessageType::INFO, "No references found");
+            Ok(None)
+        } else {
+            self.logger.log(
+                MessageType::INFO,
+                format!("Found {} references", locations.len()),
+            );
+            Ok(Some(locations))
+        }
+    }
+
+    async fn prepare_call_hierarchy(
+        &self,
+        params: CallHierarchyPrepareParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<Vec<CallHierarchyItem>>> {
+        self.logger.log(
+            MessageType::INFO,
+            "Got a textDocument/prepareCallHierarchy request",
+        );
+
+        let uri = params.text_document_position_params.text_document.uri;
+        let position = params.text_document_position_params.position;
+        let Some(source_bytes) = self.read_document(&uri, position).await? else {
+            return Ok(None);
+        };
+        let Some(ast_data) = self.project_ast(&uri).await? else {
+            return Ok(None);
         };
+        Ok(
+            call_hierarchy::prepare(&ast_data, &uri, position, &source_bytes)
+                .map(|item| vec![item]),
+        )
+    }
 
-        // Validate the new name
-        if !utils::is_valid_solidity_identifier(&new_name) {
-            return Err(tower_lsp::jsonrpc::Error::invalid_params(
-                "New name is not a valid Solidity identifier",
-            ));
-        }
+    async fn incoming_calls(
+        &self,
+        params: CallHierarchyIncomingCallsParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<Vec<CallHierarchyIncomingCall>>> {
+        self.logger.log(
+            MessageType::INFO,
+            "Got a callHierarchy/incomingCalls request",
+        );
 
-        // If the new name is the same as the current identifier, no change needed
-        if new_name == current_identifier {
-            self.client
-                .log_message(
-                    MessageType::INFO,
-                    "New name is the same as current identifier",
-                )
-                .await;
+        let Some(ast_data) = self.project_ast(&params.item.uri).await? else {
             return Ok(None);
-        }
+        };
+        let calls = call_hierarchy::incoming_calls(&ast_data, &params.item);
+        Ok((!calls.is_empty()).then_some(calls))
+    }
 
-        // Try to get AST data from cache first
-        let ast_data = {
-            let cache = self.ast_cache.read().await;
-            if let Some(cached_ast) = cache.get(&uri.to_string()) {
-                self.client
-                    .log_message(MessageType::INFO, "Using cached AST data")
-                    .await;
-                cached_ast.clone()
-            } else {
-                // Cache miss - get AST data and cache it
-                drop(cache); // Release read lock
-
-                let path_str = match file_path.to_str() {
-                    Some(s) => s,
-                    None => {
-                        self.client
-                            .log_message(MessageType::ERROR, "Invalid file path")
-                            .await;
-                        return Ok(None);
-                    }
-                };
+    async fn outgoing_calls(
+        &self,
+        params: CallHierarchyOutgoingCallsParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<Vec<CallHierarchyOutgoingCall>>> {
+        self.logger.log(
+            MessageType::INFO,
+            "Got a callHierarchy/outgoingCalls request",
+        );
+
+        let Some(ast_data) = self.project_ast(&params.item.uri).await? else {
+            return Ok(None);
+        };
+        let calls = call_hierarchy::outgoing_calls(&ast_data, &params.item);
+        Ok((!calls.is_empty()).then_some(calls))
+    }
 
-                match self.compiler.ast(path_str).await {
-                    Ok(data) => {
-                        self.client
-                            .log_message(MessageType::INFO, "Fetched and caching new AST data")
-                            .await;
+    async fn prepare_rename(
+        &self,
+        params: TextDocumentPositionParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<PrepareRenameResponse>> {
+        self.logger.log(
+            MessageType::INFO,
+            "Got a textDocument/prepareRename request",
+        );
 
-                        // Cache the new AST data
-                        let mut cache = self.ast_cache.write().await;
-                        cache.insert(uri.to_string(), data.clone());
-                        data
-                    }
-                    Err(e) => {
-                        self.client
-                            .log_message(MessageType::ERROR, format!("Failed to get AST: {e}"))
-                            .await;
-                        return Ok(None);
-                    }
-                }
-            }
+        let Some(source_bytes) = self
+            .read_document(&params.text_document.uri, params.position)
+            .await?
+        else {
+            return Ok(None);
         };
 
-        // Use the rename_symbol function to handle the rename logic
-        match rename::rename_symbol(&ast_data, &uri, position, &source_bytes, new_name) {
-            Some(workspace_edit) => {
-                self.client
-                    .log_message(
-                        MessageType::INFO,
-                        format!(
-                            "Created rename edit with {} changes",
-                            workspace_edit
-                                .changes
-                                .as_ref()
-                                .map(|c| c.values().map(|v| v.len()).sum::<usize>())
-                                .unwrap_or(0)
-                        ),
-                    )
-                    .await;
+        match rename::prepare_rename(&source_bytes, params.position) {
+            Ok((range, placeholder)) => Ok(Some(PrepareRenameResponse::RangeWithPlaceholder {
+                range,
+                placeholder,
+            })),
+            Err(e) => Err(tower_lsp::jsonrpc::Error::invalid_params(e.to_string())),
+        }
+    }
 
-                // Separate changes: apply server-side for other files, return client-side for current file
-                let mut server_changes = HashMap::new();
-                let mut client_changes = HashMap::new();
-
-                if let Some(changes) = &workspace_edit.changes {
-                    for (file_uri, edits) in changes {
-                        if file_uri == &uri {
-                            client_changes.insert(file_uri.clone(), edits.clone());
-                        } else {
-                            server_changes.insert(file_uri.clone(), edits.clone());
-                        }
-                    }
-                }
+    async fn rename(
+        &self,
+        params: RenameParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<WorkspaceEdit>> {
+        self.logger
+            .log(MessageType::INFO, "Got a textDocument/rename request");
 
-                // Apply edits for other files server-side
-                if !server_changes.is_empty() {
-                    let server_edit = WorkspaceEdit {
-                        changes: Some(server_changes.clone()),
-                        ..Default::default()
-                    };
-                    if let Err(e) = self.apply_workspace_edit(&server_edit).await {
-                        self.client
-                            .log_message(
-                                MessageType::ERROR,
-                                format!("Failed to apply server-side rename edits: {}", e),
-                            )
-                            .await;
-                        return Ok(None);
-                    }
-                    self.client
-                        .log_message(
-                            MessageType::INFO,
-                            "Applied server-side rename edits and saved other files",
-                        )
-                        .await;
+        let Some(workspace_edit) = self.rename_edit(&params, RenameScope::Workspace).await? else {
+            return Ok(None);
+        };
 
-                    // Invalidate AST cache for modified files
-                    let mut cache = self.ast_cache.write().await;
-                    for uri in server_changes.keys() {
-                        cache.remove(uri.as_str());
-                    }
-                }
+        let changes = match &workspace_edit.document_changes {
+            Some(DocumentChanges::Edits(edits)) => edits.iter().map(|edit| edit.edits.len()).sum(),
+            _ => 0,
+        };
+        self.logger.log(
+            MessageType::INFO,
+            format!("Created rename edit with {changes} changes"),
+        );
 
-                // Return edits for the current file to be applied client-side
-                if client_changes.is_empty() {
-                    Ok(None)
-                } else {
-                    let client_edit = WorkspaceEdit {
-                        changes: Some(client_changes),
-                        ..Default::default()
-                    };
-                    Ok(Some(client_edit))
-                }
-            }
-            None => {
-                self.client
-                    .log_message(MessageType::INFO, "No locations found for renaming")
-                    .await;
-                Ok(None)
-            }
-        }
+        Ok(Some(workspace_edit))
     }
 
     async fn symbol(
         &self,
         params: WorkspaceSymbolParams,
     ) -> tower_lsp::jsonrpc::Result<Option<Vec<SymbolInformation>>> {
-        self.client
-            .log_message(MessageType::INFO, "Got a workspace/symbol request")
-            .await;
-
-        // Get AST data from cache or build it
-        // For workspace symbols, we need to get AST data for all files
-        // Since we don't have a specific file, we'll need to build all files
+        self.logger
+            .log(MessageType::INFO, "Got a workspace/symbol request");
 
-        // For now, let's try to get AST data by building the current directory
-        // This is a simplified approach - in a full implementation, we'd want to
-        // cache AST data for all files in the workspace
-
-        let current_dir = std::env::current_dir().ok();
-        let ast_data = if let Some(dir) = current_dir {
-            let path_str = dir.to_str().unwrap_or(".");
-            match self.compiler.ast(path_str).await {
-                Ok(data) => data,
-                Err(e) => {
-                    self.client
-                        .log_message(
-                            MessageType::WARNING,
-                            format!("Failed to get AST data for workspace symbols: {e}"),
-                        )
-                        .await;
-                    return Ok(None);
-                }
-            }
-        } else {
-            self.client
-                .log_message(MessageType::ERROR, "Could not get current directory")
-                .await;
+        // Symbols come from the project index, built here if indexing has not finished
+        let mut projects = self.index.projects().await;
+        if projects.is_empty() {
+            self.index_workspace(true).await;
+            projects = self.index.projects().await;
+        }
+        if projects.is_empty() {
             return Ok(None);
-        };
+        }
 
-        let mut all_symbols = symbols::extract_symbols(&ast_data);
+        let mut all_symbols: Vec<SymbolInformation> = projects
+            .iter()
+            .flat_map(|project| symbols::extract_symbols(&project.ast))
+            .collect();
 
         // Filter symbols based on query if provided
         if !params.query.is_empty() {
             let query = params.query.to_lowercase();
             all_symbols.retain(|symbol| {
-                symbol.name.to_lowercase().contains(&query)
-            });
+            symbol.name.to_lowercase().contains(&query)
+        });
         }
 
         if all_symbols.is_empty() {
-            self.client
-                .log_message(MessageType::INFO, "No symbols found")
-                .await;
+            self.logger.log(MessageType::INFO, "No symbols found");
             Ok(None)
         } else {
-            self.client
-                .log_message(
-                    MessageType::INFO,
-                    format!("Found {} symbols", all_symbols.len()),
-                )
-                .await;
+            self.logger.log(
+                MessageType::INFO,
+                format!("Found {} symbols", all_symbols.len()),
+            );
             Ok(Some(all_symbols))
         }
     }
@@ -854,9 +3587,10 @@ impl LanguageServer for ForgeLsp {
         &self,
         params: DocumentSymbolParams,
     ) -> tower_lsp::jsonrpc::Result<Option<DocumentSymbolResponse>> {
-        self.client
-            .log_message(MessageType::INFO, "Got a textDocument/documentSymbol request")
-            .await;
+        self.logger.log(
+            MessageType::INFO,
+            "Got a textDocument/documentSymbol request",
+        );
 
         let uri = params.text_document.uri;
 
@@ -864,9 +3598,7 @@ impl LanguageServer for ForgeLsp {
         let file_path = match uri.to_file_path() {
             Ok(path) => path,
             Err(_) => {
-                self.client
-                    .log_message(MessageType::ERROR, "Invalid file URI")
-                    .await;
+                self.logger.log(MessageType::ERROR, "Invalid file URI");
                 return Ok(None);
             }
         };
@@ -874,57 +3606,679 @@ impl LanguageServer for ForgeLsp {
         let path_str = match file_path.to_str() {
             Some(s) => s,
             None => {
-                self.client
-                    .log_message(MessageType::ERROR, "Invalid file path")
-                    .await;
+                self.logger.log(MessageType::ERROR, "Invalid file path");
                 return Ok(None);
             }
         };
 
-        // Get AST data for this specific file
-        let ast_data = match self.compiler.ast(path_str).await {
-            Ok(data) => data,
-            Err(e) => {
-                self.client
-                    .log_message(
+        // The in-process parse follows unsaved edits; the compiler's AST covers files the
+        // parser rejects
+        let syntax_symbols = self
+            .source_and_syntax(&uri)
+            .await
+            .and_then(|(source, tree)| {
+                let unit = ast::source_unit(&tree, &uri)?;
+                Some(symbols::outline(unit, &String::from_utf8_lossy(&source)))
+            });
+        let symbols = match syntax_symbols {
+            Some(symbols) => symbols,
+            None => match self.ast_provider.get_or_fetch(&uri).await {
+                Ok(ast_data) => symbols::extract_document_symbols(&ast_data, path_str),
+                Err(e) => {
+                    self.logger.log(
                         MessageType::WARNING,
-                        format!("Failed to get AST data for document symbols: {e}"),
-                    )
-                    .await;
-                return Ok(None);
-            }
+                        format!(
+                            "Failed to get AST data for document symbols, scanning tokens: {e}"
+                        ),
+                    );
+                    match self.documents.read(&uri).await {
+                        Ok(source) => fallback::document_symbols(&String::from_utf8_lossy(&source)),
+                        Err(_) => return Ok(None),
+                    }
+                }
+            },
         };
 
-        let symbols = symbols::extract_document_symbols(&ast_data, path_str);
-
         if symbols.is_empty() {
-            self.client
-                .log_message(MessageType::INFO, "No document symbols found")
-                .await;
+            self.logger
+                .log(MessageType::INFO, "No document symbols found");
             Ok(None)
         } else {
-            self.client
-                .log_message(
-                    MessageType::INFO,
-                    format!("Found {} document symbols", symbols.len()),
-                )
-                .await;
+            self.logger.log(
+                MessageType::INFO,
+                format!("Found {} document symbols", symbols.len()),
+            );
             Ok(Some(DocumentSymbolResponse::Nested(symbols)))
         }
     }
 
+    async fn folding_range(
+        &self,
+        params: FoldingRangeParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<Vec<FoldingRange>>> {
+        let uri = params.text_document.uri;
+        if let Some((source_bytes, tree)) = self.source_and_syntax(&uri).await {
+            return Ok(Some(folding::folding_ranges(&tree, &uri, &source_bytes)));
+        }
+        // Comments fold even before the buffer has parsed once
+        let Ok(source_bytes) = self.documents.read(&uri).await else {
+            return Ok(None);
+        };
+        Ok(Some(folding::folding_ranges(
+            &serde_json::Value::Null,
+            &uri,
+            &source_bytes,
+        )))
+    }
+
+    async fn selection_range(
+        &self,
+        params: SelectionRangeParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<Vec<SelectionRange>>> {
+        let uri = params.text_document.uri;
+        let Some((source_bytes, tree)) = self.source_and_syntax(&uri).await else {
+            return Ok(None);
+        };
+        Ok(Some(selection::selection_ranges(
+            &tree,
+            &uri,
+            &source_bytes,
+            &params.positions,
+        )))
+    }
+
+    async fn semantic_tokens_full(
+        &self,
+        params: SemanticTokensParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<SemanticTokensResult>> {
+        self.logger.log(
+            MessageType::INFO,
+            "Got a textDocument/semanticTokens/full request",
+        );
+
+        let uri = params.text_document.uri;
+        if let Ok(source_bytes) = self.documents.read(&uri).await
+            && self.is_large_file(&uri, source_bytes.len()).await
+        {
+            return Ok(None);
+        }
+        let Some((source_bytes, ast_data)) = self.source_and_ast(&uri).await? else {
+            return Ok(None);
+        };
+        let data = semantic_tokens::semantic_tokens(&ast_data, &uri, &source_bytes, None);
+        Ok(Some(SemanticTokensResult::Tokens(SemanticTokens {
+            result_id: None,
+            data,
+        })))
+    }
+
+    async fn semantic_tokens_range(
+        &self,
+        params: SemanticTokensRangeParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<SemanticTokensRangeResult>> {
+        self.logger.log(
+            MessageType::INFO,
+            "Got a textDocument/semanticTokens/range request",
+        );
+
+        let uri = params.text_document.uri;
+        if let Ok(source_bytes) = self.documents.read(&uri).await
+            && self.is_large_file(&uri, source_bytes.len()).await
+        {
+            return Ok(None);
+        }
+        let Some((source_bytes, ast_data)) = self.source_and_ast(&uri).await? else {
+            return Ok(None);
+        };
+        let data =
+            semantic_tokens::semantic_tokens(&ast_data, &uri, &source_bytes, Some(params.range));
+        Ok(Some(SemanticTokensRangeResult::Tokens(SemanticTokens {
+            result_id: None,
+            data,
+        })))
+    }
+
+    async fn inlay_hint(
+        &self,
+        params: InlayHintParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<Vec<InlayHint>>> {
+        self.logger
+            .log(MessageType::INFO, "Got a textDocument/inlayHint request");
+
+        let settings = self.settings.read().await.inlay_hints.clone();
+        if !settings.parameter_names && !settings.types && !settings.return_names {
+            return Ok(None);
+        }
+        let uri = params.text_document.uri;
+        let mut hints = Vec::new();
+        if settings.parameter_names
+            && let Some((source_bytes, tree, project)) = self.script_syntax(&uri).await
+        {
+            hints.extend(constructor_args::constructor_hints(
+                &tree,
+                &uri,
+                &source_bytes,
+                params.range,
+                &project.ast,
+            ));
+        }
+        if let Ok(Some((source_bytes, ast_data))) = self.source_and_ast(&uri).await {
+            hints.extend(inlay_hints::inlay_hints(
+                &ast_data,
+                &uri,
+                &source_bytes,
+                params.range,
+                &settings,
+            ));
+        }
+        hints.sort_by_key(|hint| hint.position);
+        Ok(Some(hints))
+    }
+
+    async fn code_lens(
+        &self,
+        params: CodeLensParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<Vec<CodeLens>>> {
+        self.logger
+            .log(MessageType::INFO, "Got a textDocument/codeLens request");
+
+        let uri = params.text_document.uri;
+        let Some((source_bytes, ast_data)) = self.source_and_ast(&uri).await? else {
+            return Ok(None);
+        };
+        let mut lenses = forge_test::test_lenses(&ast_data, &uri, &source_bytes);
+        lenses.extend(stack_depth::stack_lenses(&ast_data, &uri, &source_bytes));
+        let root = uri
+            .to_file_path()
+            .ok()
+            .and_then(|path| build_info::find_project_root(&path));
+        if let Some(root) = &root
+            && let Some(stale) = self.stale_artifacts(root).await
+        {
+            lenses.insert(0, stale.lens());
+        }
+        if let Some(root) = root
+            && let Some(report) = self.gas_report(&root).await
+        {
+            lenses.extend(gas::gas_lenses(
+                &report,
+                &ast_data,
+                &uri,
+                &root,
+                &source_bytes,
+            ));
+        }
+        Ok((!lenses.is_empty()).then_some(lenses))
+    }
+
+    async fn code_action(
+        &self,
+        params: CodeActionParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<CodeActionResponse>> {
+        self.logger
+            .log(MessageType::INFO, "Got a textDocument/codeAction request");
+
+        let uri = params.text_document.uri;
+        let Ok(source_bytes) = self.documents.read(&uri).await else {
+            return Ok(None);
+        };
+        let mut actions = code_actions::quick_fixes(
+            &uri,
+            &String::from_utf8_lossy(&source_bytes),
+            &params.context.diagnostics,
+        );
+
+        // Refactorings follow the cursor, so like highlights they never wait for a compile
+        let only = params.context.only.as_deref();
+        let deploy = code_actions::requested(only, &CodeActionKind::REFACTOR);
+        let rewrite = code_actions::requested(only, &CodeActionKind::REFACTOR_REWRITE);
+        let extract = code_actions::requested(only, &CodeActionKind::REFACTOR_EXTRACT);
+        if (deploy || rewrite || extract)
+            && let Some(ast_data) = self.current_ast(&uri, &source_bytes).await
+        {
+            let position = params.range.start;
+            if extract {
+                actions.extend(extract::extract_function_action(
+                    &ast_data,
+                    &uri,
+                    params.range,
+                    &source_bytes,
+                ));
+            }
+            if rewrite {
+                actions.extend(named_args::named_arguments_action(
+                    &ast_data,
+                    &uri,
+                    position,
+                    &source_bytes,
+                ));
+                actions.extend(tuples::destructure_action(
+                    &ast_data,
+                    &uri,
+                    position,
+                    &source_bytes,
+                ));
+            }
+            if deploy
+                && let Ok(path) = uri.to_file_path()
+                && let Some(config) = ProjectConfig::find(&path)
+            {
+                actions.extend(deploy_script::deploy_script_action(
+                    &ast_data,
+                    &uri,
+                    position,
+                    &source_bytes,
+                    &config,
+                ));
+            }
+        }
+        Ok((!actions.is_empty()).then_some(actions))
+    }
+
+    async fn formatting(
+        &self,
+        params: DocumentFormattingParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<Vec<TextEdit>>> {
+        self.logger
+            .log(MessageType::INFO, "Got a textDocument/formatting request");
+
+        let Some((source, formatted)) = self.format_buffer(&params.text_document.uri).await else {
+            return Ok(None);
+        };
+        Ok(Some(formatting::document_edits(&source, &formatted)))
+    }
+
+    async fn range_formatting(
+        &self,
+        params: DocumentRangeFormattingParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<Vec<TextEdit>>> {
+        self.logger.log(
+            MessageType::INFO,
+            "Got a textDocument/rangeFormatting request",
+        );
+
+        let Some((source, formatted)) = self.format_buffer(&params.text_document.uri).await else {
+            return Ok(None);
+        };
+        Ok(Some(formatting::range_edits(
+            &source,
+            &formatted,
+            params.range,
+        )))
+    }
+
+    async fn on_type_formatting(
+        &self,
+        params: DocumentOnTypeFormattingParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<Vec<TextEdit>>> {
+        self.logger.log(
+            MessageType::INFO,
+            "Got a textDocument/onTypeFormatting request",
+        );
+
+        let uri = params.text_document_position.text_document.uri;
+        let Ok(source_bytes) = self.documents.read(&uri).await else {
+            return Ok(None);
+        };
+        let settings = uri
+            .to_file_path()
+            .ok()
+            .and_then(|path| ProjectConfig::find(&path))
+            .map(|config| config.fmt)
+            .unwrap_or_default();
+        let edits = formatting::on_type_edits(
+            &String::from_utf8_lossy(&source_bytes),
+            params.text_document_position.position,
+            &params.ch,
+            &settings,
+        );
+        Ok((!edits.is_empty()).then_some(edits))
+    }
+
     async fn execute_command(
         &self,
-        _: ExecuteCommandParams,
+        params: ExecuteCommandParams,
     ) -> tower_lsp::jsonrpc::Result<Option<serde_json::Value>> {
-        self.client
-            .log_message(MessageType::INFO, "command executed!")
+        self.logger.log(MessageType::INFO, "command executed!");
+
+        if params.command == RUN_DIAGNOSTICS_COMMAND {
+            let uri = params
+                .arguments
+                .into_iter()
+                .next()
+                .and_then(|arg| serde_json::from_value::<Url>(arg).ok());
+            let Some(uri) = uri else {
+                return Err(tower_lsp::jsonrpc::Error::invalid_params(format!(
+                    "{RUN_DIAGNOSTICS_COMMAND} expects a file URI argument"
+                )));
+            };
+
+            self.cancel_pending_diagnostics(&uri).await;
+            // Forge compiles the file on disk, so no buffer text is needed
+            self.on_change(TextDocumentItem {
+                uri,
+                text: "",
+                version: None,
+            })
             .await;
+            return Ok(None);
+        }
+
+        if params.command == RELOAD_WORKSPACE_COMMAND {
+            self.reload_workspace().await;
+            self.client
+                .show_message(MessageType::INFO, "forge-lsp: workspace reloaded")
+                .await;
+            return Ok(None);
+        }
+
+        if params.command == BASELINE_COMMAND {
+            let recorded = self.record_baseline().await;
+            self.client
+                .show_message(
+                    MessageType::INFO,
+                    format!("forge-lsp: recorded {recorded} findings in {BASELINE_FILE}"),
+                )
+                .await;
+            return Ok(None);
+        }
+
+        if params.command == FIX_ALL_COMMAND {
+            let Some(fix_params) = params
+                .arguments
+                .into_iter()
+                .next()
+                .and_then(|arg| serde_json::from_value::<FixAllParams>(arg).ok())
+            else {
+                return Err(tower_lsp::jsonrpc::Error::invalid_params(format!(
+                    "{FIX_ALL_COMMAND} expects {{\"rule\": ..., \"path\": ...}}"
+                )));
+            };
+            let result = self.fix_all(&fix_params).await;
+            if result.fixed > 0
+                && let Err(e) = self.client.apply_edit(result.edit.clone()).await
+            {
+                self.logger
+                    .log(MessageType::ERROR, format!("Failed to apply fixes: {e}"));
+            }
+            self.client
+                .show_message(
+                    MessageType::INFO,
+                    format!(
+                        "forge-lsp: fixed {} `{}` findings, skipped {} overlapping fixes",
+                        result.fixed, fix_params.rule, result.skipped
+                    ),
+                )
+                .await;
+            return Ok(serde_json::to_value(result).ok());
+        }
+
+        if params.command == SELECTOR_IMPLEMENTATIONS_COMMAND {
+            let mut arguments = params.arguments.into_iter();
+            let uri = arguments
+                .next()
+                .and_then(|arg| serde_json::from_value::<Url>(arg).ok());
+            let position = arguments
+                .next()
+                .and_then(|arg| serde_json::from_value::<Position>(arg).ok());
+            let (Some(uri), Some(position)) = (uri, position) else {
+                return Err(tower_lsp::jsonrpc::Error::invalid_params(format!(
+                    "{SELECTOR_IMPLEMENTATIONS_COMMAND} expects a file URI and a position"
+                )));
+            };
+            let locations = self.selector_implementations(&uri, position).await;
+            return Ok(serde_json::to_value(locations).ok());
+        }
+
+        if params.command == RUN_TEST_COMMAND {
+            let mut arguments = params.arguments.into_iter();
+            let uri = arguments
+                .next()
+                .and_then(|arg| serde_json::from_value::<Url>(arg).ok());
+            let contract = arguments
+                .next()
+                .and_then(|arg| serde_json::from_value::<String>(arg).ok());
+            let test = arguments
+                .next()
+                .and_then(|arg| serde_json::from_value::<String>(arg).ok());
+            let (Some(uri), Some(contract)) = (uri, contract) else {
+                return Err(tower_lsp::jsonrpc::Error::invalid_params(format!(
+                    "{RUN_TEST_COMMAND} expects a file URI, a contract and an optional test"
+                )));
+            };
+            self.run_test(uri, &contract, test.as_deref()).await;
+            return Ok(None);
+        }
+
+        if params.command == TEST_RUN_COMMAND {
+            let Some(run_params) = params
+                .arguments
+                .into_iter()
+                .next()
+                .and_then(|arg| serde_json::from_value::<TestRunParams>(arg).ok())
+            else {
+                return Err(tower_lsp::jsonrpc::Error::invalid_params(format!(
+                    "{TEST_RUN_COMMAND} expects {{\"uri\": ..., \"contract\": ..., \"test\": ...}}"
+                )));
+            };
+            let server = self.clone();
+            tokio::spawn(async move { server.run_test_streamed(run_params).await });
+            return Ok(None);
+        }
+
+        if params.command == MODEL_CHECK_COMMAND {
+            let mut arguments = params.arguments.into_iter();
+            let uri = arguments
+                .next()
+                .and_then(|arg| serde_json::from_value::<Url>(arg).ok());
+            let contract = arguments
+                .next()
+                .and_then(|arg| serde_json::from_value::<String>(arg).ok());
+            let Some(uri) = uri else {
+                return Err(tower_lsp::jsonrpc::Error::invalid_params(format!(
+                    "{MODEL_CHECK_COMMAND} expects a file URI and an optional contract"
+                )));
+            };
+            let contracts = match contract {
+                Some(contract) => vec![contract],
+                None => self
+                    .source_and_ast(&uri)
+                    .await?
+                    .map(|(_, ast_data)| {
+                        ast::source_unit(&ast_data, &uri)
+                            .and_then(|unit| unit.get("nodes")?.as_array())
+                            .into_iter()
+                            .flatten()
+                            .filter(|node| {
+                                node["nodeType"] == "ContractDefinition"
+                                    && node["contractKind"] == "contract"
+                            })
+                            .filter_map(|node| Some(node["name"].as_str()?.to_string()))
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+            };
+            if contracts.is_empty() {
+                return Err(tower_lsp::jsonrpc::Error::invalid_params(format!(
+                    "{uri} declares no contracts to check"
+                )));
+            }
+            self.model_check(uri, &contracts).await;
+            return Ok(None);
+        }
+
+        if params.command == RUN_FUZZER_COMMAND {
+            let mut arguments = params.arguments.into_iter();
+            let uri = arguments
+                .next()
+                .and_then(|arg| serde_json::from_value::<Url>(arg).ok());
+            let contract = arguments
+                .next()
+                .and_then(|arg| serde_json::from_value::<String>(arg).ok());
+            let Some((uri, (fuzzer, root))) =
+                uri.and_then(|uri| Some((uri.clone(), fuzzer_config(&uri)?)))
+            else {
+                return Err(tower_lsp::jsonrpc::Error::invalid_params(format!(
+                    "{RUN_FUZZER_COMMAND} expects the URI of an echidna or medusa configuration \
+                     and an optional contract"
+                )));
+            };
+            let server = self.clone();
+            tokio::spawn(async move { server.run_fuzzer(uri, fuzzer, root, contract).await });
+            return Ok(None);
+        }
+
+        if params.command == EXPORT_EVENTS_COMMAND {
+            let Some(export_params) = params
+                .arguments
+                .into_iter()
+                .next()
+                .and_then(|arg| serde_json::from_value::<ExportEventsParams>(arg).ok())
+            else {
+                return Err(tower_lsp::jsonrpc::Error::invalid_params(format!(
+                    "{EXPORT_EVENTS_COMMAND} expects {{\"uri\": ..., \"contracts\": [...], \"output\": ...}}"
+                )));
+            };
+            let schema = self
+                .export_events(&export_params)
+                .await
+                .map_err(|message| tower_lsp::jsonrpc::Error {
+                    code: tower_lsp::jsonrpc::ErrorCode::InternalError,
+                    message: message.into(),
+                    data: None,
+                })?;
+            return Ok(serde_json::to_value(schema).ok());
+        }
+
+        if params.command == VERIFICATION_BUNDLE_COMMAND {
+            let Some(bundle_params) = params
+                .arguments
+                .into_iter()
+                .next()
+                .and_then(|arg| serde_json::from_value::<VerificationBundleParams>(arg).ok())
+            else {
+                return Err(tower_lsp::jsonrpc::Error::invalid_params(format!(
+                    "{VERIFICATION_BUNDLE_COMMAND} expects {{\"uri\": ..., \"contract\": ..., \"directory\": ...}}"
+                )));
+            };
+            let bundle = self
+                .verification_bundle(&bundle_params)
+                .await
+                .map_err(|message| tower_lsp::jsonrpc::Error {
+                    code: tower_lsp::jsonrpc::ErrorCode::InternalError,
+                    message: message.into(),
+                    data: None,
+                })?;
+            return Ok(serde_json::to_value(bundle).ok());
+        }
+
+        if params.command == GENERATE_BINDINGS_COMMAND {
+            let uri = params
+                .arguments
+                .into_iter()
+                .next()
+                .and_then(|arg| serde_json::from_value::<Url>(arg).ok());
+            let roots = match uri {
+                Some(uri) => {
+                    let root = uri
+                        .to_file_path()
+                        .ok()
+                        .and_then(|path| build_info::find_project_root(&path));
+                    let Some(root) = root else {
+                        return Err(tower_lsp::jsonrpc::Error::invalid_params(format!(
+                            "{uri} is not in a Foundry project"
+                        )));
+                    };
+                    vec![root]
+                }
+                None => self.folders.read().await.projects(),
+            };
+            let server = self.clone();
+            tokio::spawn(async move { server.generate_bindings(roots).await });
+            return Ok(None);
+        }
+
+        if params.command == REBUILD_COMMAND {
+            let Some(root) = params
+                .arguments
+                .into_iter()
+                .next()
+                .and_then(|arg| serde_json::from_value::<PathBuf>(arg).ok())
+            else {
+                return Err(tower_lsp::jsonrpc::Error::invalid_params(format!(
+                    "{REBUILD_COMMAND} expects a project root"
+                )));
+            };
+            self.rebuild_artifacts(&root).await;
+            return Ok(None);
+        }
+
+        if params.command == STORAGE_LAYOUT_COMMAND {
+            let mut arguments = params.arguments.into_iter();
+            let uri = arguments
+                .next()
+                .and_then(|arg| serde_json::from_value::<Url>(arg).ok());
+            let contract = arguments
+                .next()
+                .and_then(|arg| serde_json::from_value::<String>(arg).ok());
+            let (Some((uri, path)), Some(contract)) = (
+                uri.and_then(|uri| Some((uri.clone(), uri.to_file_path().ok()?))),
+                contract,
+            ) else {
+                return Err(tower_lsp::jsonrpc::Error::invalid_params(format!(
+                    "{STORAGE_LAYOUT_COMMAND} expects a file URI and a contract"
+                )));
+            };
+            let root = self.project_root(&uri).await.unwrap_or_default();
+            let layout = self
+                .storage_layout(&root, &path, &contract)
+                .await
+                .map_err(|e| tower_lsp::jsonrpc::Error {
+                    code: tower_lsp::jsonrpc::ErrorCode::InternalError,
+                    message: format!("forge inspect failed: {e}").into(),
+                    data: None,
+                })?;
+            let mut value = serde_json::to_value(layout.as_ref()).unwrap_or_default();
+            value["markdown"] = layout.to_markdown().into();
+            return Ok(Some(value));
+        }
+
+        if params.command == FLATTEN_COMMAND {
+            let uri = params
+                .arguments
+                .into_iter()
+                .next()
+                .and_then(|arg| serde_json::from_value::<Url>(arg).ok());
+            let Some(uri) = uri else {
+                return Err(tower_lsp::jsonrpc::Error::invalid_params(format!(
+                    "{FLATTEN_COMMAND} expects a file URI argument"
+                )));
+            };
+            let flattened = self.flatten_file(&uri).await?;
+            return Ok(serde_json::to_value(flattened).ok());
+        }
+
+        if params.command == PREVIEW_DOCS_COMMAND {
+            let mut arguments = params.arguments.into_iter();
+            let uri = arguments
+                .next()
+                .and_then(|arg| serde_json::from_value::<Url>(arg).ok());
+            let position = arguments
+                .next()
+                .and_then(|arg| serde_json::from_value::<Position>(arg).ok());
+            let Some(uri) = uri else {
+                return Err(tower_lsp::jsonrpc::Error::invalid_params(format!(
+                    "{PREVIEW_DOCS_COMMAND} expects a file URI and an optional position"
+                )));
+            };
+            let pages = self.preview_docs(&uri, position).await?;
+            return Ok(serde_json::to_value(pages).ok());
+        }
 
         match self.client.apply_edit(WorkspaceEdit::default()).await {
-            Ok(res) if res.applied => self.client.log_message(MessageType::INFO, "applied").await,
-            Ok(_) => self.client.log_message(MessageType::INFO, "rejected").await,
-            Err(err) => self.client.log_message(MessageType::ERROR, err).await,
+            Ok(res) if res.applied => self.logger.log(MessageType::INFO, "applied"),
+            Ok(_) => self.logger.log(MessageType::INFO, "rejected"),
+            Err(err) => self.logger.log(MessageType::ERROR, err.to_string()),
         }
         Ok(None)
     }
@@ -0,0 +1,277 @@
+//! Findings of solc's SMTChecker.
+//!
+//! The model checker is too slow to run on every build, so it only runs for the contracts
+//! the `modelChecker.contracts` setting selects, after each save of their file, and for
+//! the contracts `forge-lsp.modelCheck` is asked to check. Forge passes the settings to
+//! solc from `FOUNDRY_MODEL_CHECKER`, and the checker reports its findings among the
+//! compiler warnings, with the counterexample and the transaction trace reaching it in the
+//! message. They become diagnostics carrying both as related information.
+
+use serde_json::Value;
+use std::{collections::BTreeMap, path::Path};
+use tower_lsp::lsp_types::{
+    Diagnostic, DiagnosticRelatedInformation, DiagnosticSeverity, Location, NumberOrString, Range,
+    Url,
+};
+
+use crate::{config::ModelCheckerSettings, goto::bytes_to_pos};
+
+/// Checks contracts of a file with the SMTChecker. Arguments: the file URI and optionally
+/// a contract name, every contract of the file otherwise.
+pub const MODEL_CHECK_COMMAND: &str = "forge-lsp.modelCheck";
+
+/// Source of the diagnostics of the model checker.
+pub const MODEL_CHECKER_SOURCE: &str = "smtchecker";
+
+/// Prefixes of the messages of the two engines of the model checker.
+const ENGINE_PREFIXES: [&str; 2] = ["CHC: ", "BMC: "];
+
+/// Quote `text` as a TOML basic string.
+fn toml_string(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn toml_array<'a>(items: impl IntoIterator<Item = &'a str>) -> String {
+    let items: Vec<String> = items.into_iter().map(toml_string).collect();
+    format!("[{}]", items.join(", "))
+}
+
+/// The `model_checker` table checking `contracts`, `(path, name)` pairs with paths
+/// relative to the project root, as an inline TOML table for `FOUNDRY_MODEL_CHECKER`.
+pub fn model_checker_config(settings: &ModelCheckerSettings, contracts: &[(&str, &str)]) -> String {
+    let mut by_path: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for (path, name) in contracts {
+        by_path.entry(path).or_default().push(name);
+    }
+    let contracts: Vec<String> = by_path
+        .into_iter()
+        .map(|(path, names)| format!("{} = {}", toml_string(path), toml_array(names)))
+        .collect();
+
+    let mut fields = vec![
+        format!("engine = {}", toml_string(&settings.engine)),
+        format!("contracts = {{ {} }}", contracts.join(", ")),
+    ];
+    if !settings.targets.is_empty() {
+        fields.push(format!(
+            "targets = {}",
+            toml_array(settings.targets.iter().map(String::as_str))
+        ));
+    }
+    if let Some(timeout) = settings.timeout {
+        fields.push(format!("timeout = {timeout}"));
+    }
+    format!("{{ {} }}", fields.join(", "))
+}
+
+/// The parts of a finding's message.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Finding<'a> {
+    headline: &'a str,
+    counterexample: Vec<&'a str>,
+    trace: Vec<&'a str>,
+}
+
+fn parse_message(message: &str) -> Finding<'_> {
+    let mut lines = message.lines();
+    let mut finding = Finding {
+        headline: lines.next().unwrap_or_default().trim(),
+        ..Finding::default()
+    };
+    let mut section = None;
+    for line in lines {
+        let line = line.trim();
+        match line {
+            "Counterexample:" => section = Some(&mut finding.counterexample),
+            "Transaction trace:" => section = Some(&mut finding.trace),
+            // Solc leaves a blank line after the heading when there is no state to show
+            "" if section.as_ref().is_some_and(|lines| !lines.is_empty()) => section = None,
+            "" => {}
+            line => {
+                if let Some(section) = section.as_mut() {
+                    section.push(line);
+                }
+            }
+        }
+    }
+    finding
+}
+
+fn location_range(source_bytes: &[u8], location: &Value) -> Option<Range> {
+    let start = usize::try_from(location.get("start")?.as_i64()?).ok()?;
+    let end = usize::try_from(location.get("end")?.as_i64()?).unwrap_or(start);
+    Some(Range::new(
+        bytes_to_pos(source_bytes, start)?,
+        bytes_to_pos(source_bytes, end.max(start))?,
+    ))
+}
+
+/// Diagnostics of the model checker findings in the file at `path`, whose text is
+/// `source_bytes`, in the `forge build --json` output `output` of the project at `root`.
+pub fn model_checker_diagnostics(
+    output: &Value,
+    root: &Path,
+    path: &Path,
+    source_bytes: &[u8],
+) -> Vec<Diagnostic> {
+    let Ok(uri) = Url::from_file_path(path) else {
+        return vec![];
+    };
+    let in_file = |location: &Value| {
+        location
+            .get("file")
+            .and_then(Value::as_str)
+            .is_some_and(|file| root.join(file) == path)
+    };
+
+    let errors = output.get("errors").and_then(Value::as_array);
+    let mut diagnostics = Vec::new();
+    for error in errors.into_iter().flatten() {
+        let message = error
+            .get("message")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        if !ENGINE_PREFIXES
+            .iter()
+            .any(|prefix| message.starts_with(prefix))
+        {
+            continue;
+        }
+        let Some(location) = error.get("sourceLocation").filter(|loc| in_file(loc)) else {
+            continue;
+        };
+        let Some(range) = location_range(source_bytes, location) else {
+            continue;
+        };
+
+        let finding = parse_message(message);
+        let related = |message: String| DiagnosticRelatedInformation {
+            location: Location::new(uri.clone(), range),
+            message,
+        };
+        let mut related_information = Vec::new();
+        if !finding.counterexample.is_empty() {
+            related_information.push(related(format!(
+                "Counterexample: {}",
+                finding.counterexample.join(", ")
+            )));
+        }
+        if !finding.trace.is_empty() {
+            related_information.push(related(format!(
+                "Transaction trace: {}",
+                finding.trace.join(" -> ")
+            )));
+        }
+        let secondary = error
+            .get("secondarySourceLocations")
+            .and_then(Value::as_array);
+        for location in secondary.into_iter().flatten().filter(|loc| in_file(loc)) {
+            if let Some(range) = location_range(source_bytes, location) {
+                related_information.push(DiagnosticRelatedInformation {
+                    location: Location::new(uri.clone(), range),
+                    message: location
+                        .get("message")
+                        .and_then(Value::as_str)
+                        .unwrap_or("Related location")
+                        .to_string(),
+                });
+            }
+        }
+
+        diagnostics.push(Diagnostic {
+            range,
+            severity: Some(DiagnosticSeverity::WARNING),
+            code: error
+                .get("errorCode")
+                .and_then(Value::as_str)
+                .map(|code| NumberOrString::String(code.to_string())),
+            source: Some(MODEL_CHECKER_SOURCE.to_string()),
+            message: finding.headline.to_string(),
+            related_information: (!related_information.is_empty()).then_some(related_information),
+            ..Diagnostic::default()
+        });
+    }
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_model_checker_config() {
+        let settings = ModelCheckerSettings {
+            targets: vec!["assert".to_string(), "overflow".to_string()],
+            timeout: Some(10000),
+            ..ModelCheckerSettings::default()
+        };
+        assert_eq!(
+            model_checker_config(
+                &settings,
+                &[
+                    ("src/Vault.sol", "Vault"),
+                    ("src/Vault.sol", "Pool"),
+                    ("src/Token.sol", "Token")
+                ]
+            ),
+            "{ engine = \"chc\", contracts = { \"src/Token.sol\" = [\"Token\"], \
+             \"src/Vault.sol\" = [\"Vault\", \"Pool\"] }, targets = [\"assert\", \"overflow\"], \
+             timeout = 10000 }"
+        );
+    }
+
+    #[test]
+    fn test_model_checker_diagnostics() {
+        let source = "contract Vault {\n    function add(uint8 x) public pure returns (uint8) {\n        return x + 1;\n    }\n}\n";
+        let start = source.find("x + 1").unwrap();
+        let output = json!({
+            "errors": [
+                {
+                    "component": "general",
+                    "errorCode": "4984",
+                    "severity": "warning",
+                    "type": "Warning",
+                    "message": "CHC: Overflow (resulting value larger than 255) happens here.\nCounterexample:\n\nx = 255\n = 0\n\nTransaction trace:\nVault.constructor()\nVault.add(255)",
+                    "sourceLocation": { "file": "src/Vault.sol", "start": start, "end": start + 5 }
+                },
+                {
+                    "errorCode": "2072",
+                    "severity": "warning",
+                    "message": "Unused local variable.",
+                    "sourceLocation": { "file": "src/Vault.sol", "start": 0, "end": 8 }
+                },
+                {
+                    "errorCode": "6328",
+                    "severity": "warning",
+                    "message": "CHC: Assertion violation happens here.",
+                    "sourceLocation": { "file": "src/Other.sol", "start": 0, "end": 8 }
+                }
+            ]
+        });
+
+        let root = Path::new("/project");
+        let path = root.join("src/Vault.sol");
+        let diagnostics = model_checker_diagnostics(&output, root, &path, source.as_bytes());
+        assert_eq!(diagnostics.len(), 1);
+        let diagnostic = &diagnostics[0];
+        assert_eq!(
+            diagnostic.message,
+            "CHC: Overflow (resulting value larger than 255) happens here."
+        );
+        assert_eq!(diagnostic.range.start.line, 2);
+        let related: Vec<&str> = diagnostic
+            .related_information
+            .iter()
+            .flatten()
+            .map(|info| info.message.as_str())
+            .collect();
+        assert_eq!(
+            related,
+            [
+                "Counterexample: x = 255, = 0",
+                "Transaction trace: Vault.constructor() -> Vault.add(255)"
+            ]
+        );
+    }
+}
@@ -0,0 +1,844 @@
+//! State mutability inferred from function bodies: functions that could be declared `view`
+//! or `pure` where solc says nothing, and external calls view functions make with
+//! `STATICCALL`.
+//!
+//! solc warns about non-virtual functions declared looser than their bodies need. Virtual
+//! functions are reported here when no override in the build needs more, and so are
+//! overrides whose interface declarations could be tightened along with them. The quick fix
+//! updates the signature and those declarations.
+
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString, Range, TextEdit, Url};
+
+use crate::{
+    ast::{self, name, node_type, parse_src},
+    code_actions::Fix,
+    edits::EditBuilder,
+    goto::{Access, bytes_to_pos, collect_accesses},
+    paths,
+};
+
+/// Diagnostic code of a function declared looser than it needs to be.
+pub const MUTABILITY_CODE: &str = "state-mutability";
+
+/// Diagnostic code of an external call a view function makes with `STATICCALL`.
+pub const STATICCALL_CODE: &str = "staticcall";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Mutability {
+    Pure,
+    View,
+    NonPayable,
+    Payable,
+}
+
+impl Mutability {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "pure" => Some(Self::Pure),
+            "view" => Some(Self::View),
+            "nonpayable" => Some(Self::NonPayable),
+            "payable" => Some(Self::Payable),
+            _ => None,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Pure => "pure",
+            Self::View => "view",
+            Self::NonPayable => "nonpayable",
+            Self::Payable => "payable",
+        }
+    }
+}
+
+fn type_string(node: &Value) -> Option<&str> {
+    node.get("typeDescriptions")?.get("typeString")?.as_str()
+}
+
+/// Mutability of a function type like `function (uint256) view external returns (bool)`.
+fn function_type_mutability(type_string: &str) -> Option<Mutability> {
+    let rest = type_string.strip_prefix("function")?;
+    let open = rest.find('(')?;
+    let mut depth = 0;
+    let mut close = None;
+    for (i, c) in rest[open..].char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    close = Some(open + i);
+                    break;
+                }
+            }
+            _ => {}
+        }
+    }
+    let attributes = rest[close? + 1..].split(" returns ").next()?;
+    let words: Vec<&str> = attributes.split_whitespace().collect();
+    Some(
+        ["pure", "view", "payable"]
+            .into_iter()
+            .find(|word| words.contains(word))
+            .and_then(Mutability::parse)
+            .unwrap_or(Mutability::NonPayable),
+    )
+}
+
+/// Mutability a function or public state variable is declared with.
+fn declared(node: &Value) -> Option<Mutability> {
+    match node_type(node) {
+        Some("FunctionDefinition") => Mutability::parse(node.get("stateMutability")?.as_str()?),
+        // Public state variables override with their getters
+        Some("VariableDeclaration") => Some(Mutability::View),
+        _ => None,
+    }
+}
+
+fn ids(node: &Value, key: &str) -> Vec<u64> {
+    node.get(key)
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_u64)
+        .collect()
+}
+
+/// The functions of the build and how they override each other.
+struct Functions<'a> {
+    nodes: HashMap<u64, &'a Value>,
+    /// Source path of each function, as reported by forge.
+    paths: HashMap<u64, &'a str>,
+    /// Ids of the functions and public state variables overriding each function.
+    overrides: HashMap<u64, Vec<u64>>,
+}
+
+impl<'a> Functions<'a> {
+    fn new(ast_data: &'a Value) -> Self {
+        let mut functions = Self {
+            nodes: HashMap::new(),
+            paths: HashMap::new(),
+            overrides: HashMap::new(),
+        };
+        let sources = ast_data.get("sources").and_then(Value::as_object);
+        for (path, contents) in sources.into_iter().flatten() {
+            let Some(source_ast) = contents
+                .get(0)
+                .and_then(|content| content.get("source_file"))
+                .and_then(|source_file| source_file.get("ast"))
+            else {
+                continue;
+            };
+            let path = source_ast
+                .get("absolutePath")
+                .and_then(Value::as_str)
+                .unwrap_or(path);
+            ast::walk(source_ast, &mut |node| {
+                let Some(id) = node.get("id").and_then(Value::as_u64) else {
+                    return;
+                };
+                functions.nodes.insert(id, node);
+                if node_type(node) == Some("FunctionDefinition") {
+                    functions.paths.insert(id, path);
+                }
+                for base in ids(node, "baseFunctions") {
+                    functions.overrides.entry(base).or_default().push(id);
+                }
+            });
+        }
+        functions
+    }
+
+    fn declaration(&self, node: &Value) -> Option<&'a Value> {
+        let id = node.get("referencedDeclaration")?.as_u64()?;
+        self.nodes.get(&id).copied()
+    }
+
+    /// Every function overriding `id`, directly or not.
+    fn descendants(&self, id: u64) -> Vec<u64> {
+        let mut found = Vec::new();
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            for &child in self.overrides.get(&id).into_iter().flatten() {
+                if !found.contains(&child) {
+                    found.push(child);
+                    stack.push(child);
+                }
+            }
+        }
+        found
+    }
+
+    /// Every function `id` overrides, directly or not.
+    fn ancestors(&self, id: u64) -> Vec<u64> {
+        let mut found = Vec::new();
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            let Some(node) = self.nodes.get(&id) else {
+                continue;
+            };
+            for base in ids(node, "baseFunctions") {
+                if !found.contains(&base) {
+                    found.push(base);
+                    stack.push(base);
+                }
+            }
+        }
+        found
+    }
+
+    /// Mutability of what `callee` calls, or `None` when it can't be told.
+    fn callee_mutability(&self, callee: &Value) -> Option<Mutability> {
+        if let Some(mutability) = type_string(callee).and_then(function_type_mutability) {
+            return Some(mutability);
+        }
+        if node_type(callee) == Some("ElementaryTypeNameExpression") {
+            return Some(Mutability::Pure);
+        }
+        let declaration = self.declaration(callee)?;
+        match node_type(declaration) {
+            Some("FunctionDefinition") => declared(declaration),
+            Some("VariableDeclaration") => match declaration.get("typeName") {
+                Some(function_type) if node_type(function_type) == Some("FunctionTypeName") => {
+                    Mutability::parse(function_type.get("stateMutability")?.as_str()?)
+                }
+                _ => Some(Mutability::View),
+            },
+            // Type conversions, struct constructors, events and errors
+            Some(
+                "ContractDefinition"
+                | "StructDefinition"
+                | "EnumDefinition"
+                | "UserDefinedValueTypeDefinition"
+                | "EventDefinition"
+                | "ErrorDefinition",
+            ) => Some(Mutability::Pure),
+            _ => None,
+        }
+    }
+
+    /// Mutability `node` alone needs, or `None` when it can't be told.
+    fn requirement(&self, node: &Value, accesses: &HashMap<u64, Access>) -> Option<Mutability> {
+        let access = node
+            .get("id")
+            .and_then(Value::as_u64)
+            .and_then(|id| accesses.get(&id));
+        match node_type(node) {
+            Some("InlineAssembly") => None,
+            Some("EmitStatement" | "NewExpression") => Some(Mutability::NonPayable),
+            Some("FunctionCallOptions") => {
+                let names = node.get("names").and_then(Value::as_array);
+                let sends_value = names.is_some_and(|names| names.iter().any(|n| n == "value"));
+                Some(if sends_value {
+                    Mutability::NonPayable
+                } else {
+                    Mutability::Pure
+                })
+            }
+            Some("FunctionCall") => {
+                if !matches!(
+                    node.get("kind").and_then(Value::as_str),
+                    None | Some("functionCall")
+                ) {
+                    return Some(Mutability::Pure);
+                }
+                let mut callee = node.get("expression")?;
+                if node_type(callee) == Some("FunctionCallOptions") {
+                    callee = callee.get("expression")?;
+                }
+                // Calling a payable function without value needs no more than a call
+                self.callee_mutability(callee)
+                    .map(|mutability| mutability.min(Mutability::NonPayable))
+            }
+            Some("Identifier") => {
+                if name(node) == "this" {
+                    return Some(Mutability::View);
+                }
+                let Some(declaration) = self.declaration(node) else {
+                    return Some(Mutability::Pure);
+                };
+                if node_type(declaration) != Some("VariableDeclaration") {
+                    return Some(Mutability::Pure);
+                }
+                let state = declaration.get("stateVariable") == Some(&Value::Bool(true));
+                let storage =
+                    declaration.get("storageLocation").and_then(Value::as_str) == Some("storage");
+                let constant =
+                    declaration.get("mutability").and_then(Value::as_str) == Some("constant");
+                Some(match access {
+                    _ if constant || !(state || storage) => Mutability::Pure,
+                    Some(Access::Write) => Mutability::NonPayable,
+                    _ => Mutability::View,
+                })
+            }
+            Some("MemberAccess") => {
+                let expression = node.get("expression")?;
+                let member = node.get("memberName").and_then(Value::as_str);
+                let environment = node_type(expression) == Some("Identifier");
+                Some(match (name(expression), member) {
+                    ("msg", Some("data" | "sig")) if environment => Mutability::Pure,
+                    ("msg" | "block" | "tx", _) if environment => Mutability::View,
+                    (_, Some("balance" | "code" | "codehash"))
+                        if type_string(expression).is_some_and(|t| t.starts_with("address")) =>
+                    {
+                        Mutability::View
+                    }
+                    _ => Mutability::Pure,
+                })
+            }
+            _ => Some(Mutability::Pure),
+        }
+    }
+
+    /// Mutability the body of `function` needs, or `None` when it can't be told, like for
+    /// functions with modifiers or inline assembly.
+    fn required(&self, function: &Value) -> Option<Mutability> {
+        let has_modifiers = function
+            .get("modifiers")
+            .and_then(Value::as_array)
+            .is_some_and(|modifiers| !modifiers.is_empty());
+        if has_modifiers {
+            return None;
+        }
+        let body = function.get("body").filter(|body| !body.is_null())?;
+        let mut accesses = HashMap::new();
+        collect_accesses(body, Access::Read, &mut accesses);
+        let mut required = Some(Mutability::Pure);
+        ast::walk(body, &mut |node| {
+            required = required
+                .zip(self.requirement(node, &accesses))
+                .map(|(a, b)| a.max(b));
+        });
+        required
+    }
+
+    /// Whether `id` can be declared `to` when the functions in `changed` are too: it needs
+    /// no more, and the overrides that aren't changed are declared at least as strictly.
+    fn can_tighten(&self, id: u64, to: Mutability, changed: &HashSet<u64>) -> bool {
+        let Some(node) = self.nodes.get(&id) else {
+            return false;
+        };
+        let implemented = node.get("implemented") == Some(&Value::Bool(true));
+        if implemented && self.required(node).is_none_or(|required| required > to) {
+            return false;
+        }
+        self.descendants(id)
+            .into_iter()
+            .filter(|descendant| !changed.contains(descendant))
+            .all(|descendant| {
+                self.nodes
+                    .get(&descendant)
+                    .and_then(|node| declared(node))
+                    .is_some_and(|declared| declared <= to)
+            })
+    }
+}
+
+/// Edit declaring the function `node` of `source` as `to`: the mutability it has is
+/// replaced, or `to` is added after the visibility, or else after the parameters.
+fn mutability_edit(source: &str, node: &Value, to: Mutability) -> Option<TextEdit> {
+    let (start, length, _) = parse_src(node.get("src")?.as_str()?)?;
+    let header_start = node
+        .get("nameLocation")
+        .and_then(Value::as_str)
+        .and_then(parse_src)
+        .map_or(start, |(name_start, name_length, _)| {
+            name_start + name_length
+        });
+    let header_end = node
+        .get("body")
+        .and_then(|body| body.get("src"))
+        .and_then(Value::as_str)
+        .and_then(parse_src)
+        .map_or(start + length, |(body_start, _, _)| body_start)
+        .min(source.len());
+    let header = source.get(header_start..header_end)?;
+
+    // Words of the header outside of parentheses, after the parameters
+    let mut depth = 0;
+    let mut parameters_end = None;
+    let mut words: Vec<(usize, &str)> = Vec::new();
+    let mut word_start = None;
+    for (i, c) in header.char_indices().chain([(header.len(), ' ')]) {
+        let is_word = c.is_ascii_alphanumeric() || c == '_' || c == '$';
+        if is_word {
+            word_start.get_or_insert(i);
+            continue;
+        }
+        if let Some(word) = word_start.take()
+            && depth == 0
+            && parameters_end.is_some()
+        {
+            words.push((word, &header[word..i]));
+        }
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    parameters_end.get_or_insert(i + 1);
+                }
+            }
+            _ => {}
+        }
+    }
+    let words: Vec<_> = words
+        .into_iter()
+        .take_while(|(_, word)| *word != "returns")
+        .collect();
+
+    let mut edits = EditBuilder::new(source);
+    if let Some((offset, word)) = words
+        .iter()
+        .find(|(_, word)| Mutability::parse(word).is_some())
+    {
+        let offset = header_start + offset;
+        edits
+            .replace(offset, offset + word.len(), to.as_str())
+            .ok()?;
+    } else {
+        let after = words
+            .iter()
+            .find(|(_, word)| matches!(*word, "external" | "public" | "internal" | "private"))
+            .map_or(parameters_end?, |(offset, word)| offset + word.len());
+        let offset = header_start + after;
+        edits
+            .replace(offset, offset, format!(" {}", to.as_str()))
+            .ok()?;
+    }
+    edits.build().pop()
+}
+
+fn node_range(source: &[u8], src: &str) -> Option<Range> {
+    let (start, length, _) = parse_src(src)?;
+    Some(Range::new(
+        bytes_to_pos(source, start)?,
+        bytes_to_pos(source, start + length)?,
+    ))
+}
+
+/// Range of the name of `node`, or of all of it.
+fn name_range(source: &[u8], node: &Value) -> Option<Range> {
+    let src = node.get("nameLocation").or_else(|| node.get("src"))?;
+    node_range(source, src.as_str()?)
+}
+
+/// The tightest mutability `function` can be declared with and the functions it overrides
+/// that can be declared so along with it, if tighter than it is declared.
+fn tightening(functions: &Functions, id: u64, function: &Value) -> Option<(Mutability, Vec<u64>)> {
+    let current = declared(function)?;
+    if !matches!(current, Mutability::NonPayable | Mutability::View)
+        || function.get("kind").and_then(Value::as_str) != Some("function")
+    {
+        return None;
+    }
+    let has_statements = function
+        .get("body")
+        .and_then(|body| body.get("statements"))
+        .and_then(Value::as_array)
+        .is_some_and(|statements| !statements.is_empty());
+    if !has_statements {
+        return None;
+    }
+
+    // Overrides keep their declarations, so the function can't be tighter than them
+    let mut to = functions.required(function)?;
+    for descendant in functions.descendants(id) {
+        to = to.max(declared(functions.nodes.get(&descendant)?)?);
+    }
+    if to >= current {
+        return None;
+    }
+
+    let mut changed: HashSet<u64> = functions
+        .ancestors(id)
+        .into_iter()
+        .filter(|base| {
+            let base = functions.nodes.get(base);
+            base.and_then(|base| declared(base))
+                .is_some_and(|declared| declared > to)
+        })
+        .collect();
+    changed.insert(id);
+    // A base that can't change keeps the bases above it as they are
+    while let Some(&stuck) = changed
+        .iter()
+        .find(|&&base| base != id && !functions.can_tighten(base, to, &changed))
+    {
+        changed.remove(&stuck);
+        for ancestor in functions.ancestors(stuck) {
+            changed.remove(&ancestor);
+        }
+    }
+    if !functions.can_tighten(id, to, &changed) {
+        return None;
+    }
+    changed.remove(&id);
+    let mut bases: Vec<u64> = changed.into_iter().collect();
+    bases.sort_unstable();
+    Some((to, bases))
+}
+
+/// Add to `fix` the edits declaring `bases` as `to`, those of other files than `uri` as
+/// related edits.
+fn add_base_edits(
+    fix: &mut Fix,
+    functions: &Functions,
+    bases: &[u64],
+    to: Mutability,
+    uri: &Url,
+    source: &str,
+) -> Option<()> {
+    let mut sources: HashMap<Url, String> = HashMap::new();
+    for base in bases {
+        let path = paths::resolve_source_path(functions.paths.get(base)?)?;
+        let base_uri = paths::path_to_uri(&path)?;
+        let node = functions.nodes.get(base)?;
+        if paths::uri_to_key(&base_uri) == paths::uri_to_key(uri) {
+            fix.edits.push(mutability_edit(source, node, to)?);
+            continue;
+        }
+        if !sources.contains_key(&base_uri) {
+            sources.insert(base_uri.clone(), std::fs::read_to_string(&path).ok()?);
+        }
+        let edit = mutability_edit(&sources[&base_uri], node, to)?;
+        fix.related.entry(base_uri).or_default().push(edit);
+    }
+    Some(())
+}
+
+/// Hints on the calls `function`, a view function, makes to functions declared without a
+/// body, which run with `STATICCALL` and revert if the called contract modifies state.
+fn staticcall_hints(
+    functions: &Functions,
+    function: &Value,
+    source_bytes: &[u8],
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let Some(body) = function.get("body") else {
+        return;
+    };
+    ast::walk(body, &mut |node| {
+        if node_type(node) != Some("FunctionCall")
+            || !matches!(
+                node.get("kind").and_then(Value::as_str),
+                None | Some("functionCall")
+            )
+        {
+            return;
+        }
+        let Some(mut callee) = node.get("expression") else {
+            return;
+        };
+        if node_type(callee) == Some("FunctionCallOptions")
+            && let Some(expression) = callee.get("expression")
+        {
+            callee = expression;
+        }
+        if node_type(callee) != Some("MemberAccess") {
+            return;
+        }
+        let Some(declaration) = functions.declaration(callee) else {
+            return;
+        };
+        if node_type(declaration) != Some("FunctionDefinition")
+            || declaration.get("implemented") != Some(&Value::Bool(false))
+        {
+            return;
+        }
+        let src = callee.get("memberLocation").or_else(|| node.get("src"));
+        let Some(range) = src
+            .and_then(Value::as_str)
+            .and_then(|src| node_range(source_bytes, src))
+        else {
+            return;
+        };
+        diagnostics.push(Diagnostic {
+            range,
+            severity: Some(DiagnosticSeverity::HINT),
+            code: Some(NumberOrString::String(STATICCALL_CODE.to_string())),
+            source: Some("forge-lsp".to_string()),
+            message: format!(
+                "`{}` is called with STATICCALL from the view function `{}`, and reverts if \
+                 the called contract modifies state",
+                name(declaration),
+                name(function)
+            ),
+            ..Diagnostic::default()
+        });
+    });
+}
+
+/// Functions of `uri` that could be declared `view` or `pure` where solc doesn't say so,
+/// and the external calls of its view functions.
+pub fn mutability_diagnostics(ast_data: &Value, uri: &Url, source_bytes: &[u8]) -> Vec<Diagnostic> {
+    let (Ok(source), Some(source_unit)) = (
+        std::str::from_utf8(source_bytes),
+        ast::source_unit(ast_data, uri),
+    ) else {
+        return vec![];
+    };
+    let functions = Functions::new(ast_data);
+
+    let mut diagnostics = Vec::new();
+    ast::walk(source_unit, &mut |function| {
+        if node_type(function) != Some("FunctionDefinition") {
+            return;
+        }
+        if declared(function) == Some(Mutability::View) {
+            staticcall_hints(&functions, function, source_bytes, &mut diagnostics);
+        }
+        let Some(id) = function.get("id").and_then(Value::as_u64) else {
+            return;
+        };
+        let Some((to, bases)) = tightening(&functions, id, function) else {
+            return;
+        };
+        // solc already warns about the others
+        let is_virtual = function.get("virtual") == Some(&Value::Bool(true));
+        if !is_virtual && bases.is_empty() {
+            return;
+        }
+        let function_name = name(function);
+        let (Some(edit), Some(range)) = (
+            mutability_edit(source, function, to),
+            name_range(source_bytes, function),
+        ) else {
+            return;
+        };
+        let mut fix = Fix::new(
+            format!("Declare `{function_name}` as `{}`", to.as_str()),
+            vec![edit],
+        );
+        if add_base_edits(&mut fix, &functions, &bases, to, uri, source).is_none() {
+            return;
+        }
+
+        let mut message = format!("`{function_name}` could be declared `{}`", to.as_str());
+        if !bases.is_empty() {
+            let declarations = if bases.len() == 1 {
+                "declaration"
+            } else {
+                "declarations"
+            };
+            message.push_str(&format!(
+                ", with the {} {declarations} it overrides",
+                bases.len()
+            ));
+        }
+        diagnostics.push(Diagnostic {
+            range,
+            severity: Some(DiagnosticSeverity::INFORMATION),
+            code: Some(NumberOrString::String(MUTABILITY_CODE.to_string())),
+            source: Some("forge-lsp".to_string()),
+            message,
+            data: fix.to_data(),
+            ..Diagnostic::default()
+        });
+    });
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax;
+
+    const INTERFACE: &str = "\
+interface IVault {
+    function total() external returns (uint256);
+
+    function price() external view returns (uint256);
+}
+";
+
+    const VAULT: &str = "\
+contract Vault is IVault {
+    uint256 internal stored;
+    uint256 constant SCALE = 10;
+
+    function total() external virtual override returns (uint256) {
+        return stored;
+    }
+
+    function scale(uint256 x) public view virtual returns (uint256) {
+        return x * SCALE;
+    }
+
+    function bump() public virtual {
+        stored += 1;
+    }
+
+    function twice(uint256 x) public virtual returns (uint256) {
+        return double(x);
+    }
+
+    function double(uint256 x) internal pure returns (uint256) {
+        return x * 2;
+    }
+
+    function price() external view returns (uint256) {
+        return stored * SCALE;
+    }
+
+    function report(IVault other) external view returns (uint256) {
+        return other.price();
+    }
+}
+
+contract Child is Vault {
+    function scale(uint256 x) public view override returns (uint256) {
+        return stored + x;
+    }
+}
+";
+
+    /// The id of the function `name` of `contract`.
+    fn function_id(ast: &Value, contract: &str, function: &str) -> u64 {
+        let mut found = None;
+        ast::walk(ast, &mut |node| {
+            if node_type(node) == Some("ContractDefinition") && name(node) == contract {
+                ast::walk(node, &mut |member| {
+                    if node_type(member) == Some("FunctionDefinition") && name(member) == function {
+                        found = member["id"].as_u64();
+                    }
+                });
+            }
+        });
+        found.unwrap()
+    }
+
+    fn set(ast: &mut Value, id: u64, key: &str, value: &Value) {
+        match ast {
+            Value::Object(map) if map.get("id").and_then(Value::as_u64) == Some(id) => {
+                map.insert(key.to_string(), value.clone());
+            }
+            Value::Object(map) => map
+                .values_mut()
+                .for_each(|child| set(child, id, key, value)),
+            Value::Array(items) => items
+                .iter_mut()
+                .for_each(|child| set(child, id, key, value)),
+            _ => {}
+        }
+    }
+
+    /// Build of `src/IVault.sol` and `src/Vault.sol`, with the overrides and the call of
+    /// `IVault.price` solc would resolve.
+    fn project() -> (tempfile::TempDir, Value, Url, Url) {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("src")).unwrap();
+        let interface = dir.path().join("src/IVault.sol");
+        let vault = dir.path().join("src/Vault.sol");
+        std::fs::write(&interface, INTERFACE).unwrap();
+        std::fs::write(&vault, VAULT).unwrap();
+        let (interface_path, vault_path) = (
+            interface.to_string_lossy().to_string(),
+            vault.to_string_lossy().to_string(),
+        );
+        let mut ast = syntax::parse_files([
+            (interface_path.as_str(), INTERFACE),
+            (vault_path.as_str(), VAULT),
+        ]);
+        let bases = [
+            (("Vault", "total"), ("IVault", "total")),
+            (("Child", "scale"), ("Vault", "scale")),
+        ];
+        for ((contract, function), (base_contract, base)) in bases {
+            let id = function_id(&ast, contract, function);
+            let base = function_id(&ast, base_contract, base);
+            set(&mut ast, id, "baseFunctions", &serde_json::json!([base]));
+        }
+        let mut call = None;
+        ast::walk(&ast, &mut |node| {
+            if node_type(node) == Some("MemberAccess") && node["memberName"] == "price" {
+                call = node["id"].as_u64();
+            }
+        });
+        let price = function_id(&ast, "IVault", "price");
+        set(
+            &mut ast,
+            call.unwrap(),
+            "referencedDeclaration",
+            &price.into(),
+        );
+        let interface = Url::from_file_path(interface).unwrap();
+        let vault = Url::from_file_path(vault).unwrap();
+        (dir, ast, interface, vault)
+    }
+
+    fn apply(source: &str, edits: &[TextEdit]) -> String {
+        EditBuilder::with_edits(source, edits).unwrap().apply()
+    }
+
+    #[test]
+    fn test_function_type_mutability() {
+        let cases = [
+            (
+                "function (uint256) view external returns (uint256)",
+                Some(Mutability::View),
+            ),
+            (
+                "function (bytes memory) pure returns (bytes32)",
+                Some(Mutability::Pure),
+            ),
+            ("function (uint256)", Some(Mutability::NonPayable)),
+            (
+                "function (function () view) returns (bool)",
+                Some(Mutability::NonPayable),
+            ),
+            ("function () payable external", Some(Mutability::Payable)),
+            ("type(contract IVault)", None),
+        ];
+        for (type_string, expected) in cases {
+            assert_eq!(
+                function_type_mutability(type_string),
+                expected,
+                "{type_string}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_looser_than_needed_virtual_functions() {
+        let (_dir, ast, interface, vault) = project();
+        let diagnostics = mutability_diagnostics(&ast, &vault, VAULT.as_bytes());
+        let messages: Vec<&str> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+        // `scale` stays `view` for its override, and `bump` writes state
+        assert_eq!(
+            messages,
+            [
+                "`total` could be declared `view`, with the 1 declaration it overrides",
+                "`twice` could be declared `pure`",
+                "`price` is called with STATICCALL from the view function `report`, and \
+                 reverts if the called contract modifies state",
+            ]
+        );
+
+        let fix = Fix::from_diagnostic(&diagnostics[0]).unwrap();
+        assert!(
+            apply(VAULT, &fix.edits)
+                .contains("function total() external view virtual override returns (uint256) {")
+        );
+        assert_eq!(
+            apply(INTERFACE, &fix.related[&interface]),
+            INTERFACE.replace("external returns", "external view returns")
+        );
+
+        let fix = Fix::from_diagnostic(&diagnostics[1]).unwrap();
+        assert!(fix.related.is_empty());
+        assert!(apply(VAULT, &fix.edits).contains("function twice(uint256 x) public pure virtual"));
+    }
+
+    #[test]
+    fn test_mutability_edit_replaces_the_keyword() {
+        let (_dir, ast, _, _) = project();
+        let functions = Functions::new(&ast);
+        let scale = functions.nodes[&function_id(&ast, "Vault", "scale")];
+        let edit = mutability_edit(VAULT, scale, Mutability::Pure).unwrap();
+        assert!(apply(VAULT, &[edit]).contains("function scale(uint256 x) public pure virtual"));
+    }
+}
@@ -0,0 +1,214 @@
+//! Rewriting the call under the cursor between positional and named arguments:
+//! `transfer(to, 1)` and `transfer({to: to, amount: 1})`, with the names of the parameters
+//! of the called function, error or struct.
+
+use serde_json::Value;
+use std::collections::HashMap;
+use tower_lsp::lsp_types::{
+    CodeAction, CodeActionKind, CodeActionOrCommand, Position, TextEdit, Url, WorkspaceEdit,
+};
+
+use crate::{
+    ast::{self, children, node_type, span_bounds},
+    edits::EditBuilder,
+    goto::pos_to_bytes,
+    inlay_hints::parameters,
+};
+
+/// The innermost call containing `offset`.
+fn call_at(unit: &Value, offset: usize) -> Option<&Value> {
+    let mut innermost: Option<(&Value, usize)> = None;
+    ast::walk(unit, &mut |node| {
+        if node_type(node) != Some("FunctionCall") {
+            return;
+        }
+        if let Some((start, end)) = span_bounds(node)
+            && (start..=end).contains(&offset)
+            && innermost.is_none_or(|(_, length)| end - start < length)
+        {
+            innermost = Some((node, end - start));
+        }
+    });
+    innermost.map(|(call, _)| call)
+}
+
+/// Names of the parameters `call` passes its arguments to, in declaration order. Only
+/// calls of functions, errors and structs taking one argument per parameter qualify, and
+/// every parameter must be named.
+fn parameter_names<'a>(call: &Value, index: &HashMap<u64, &'a Value>) -> Option<Vec<&'a str>> {
+    if !matches!(
+        call.get("kind").and_then(Value::as_str),
+        None | Some("functionCall" | "structConstructorCall")
+    ) {
+        return None;
+    }
+    // `pay{value: 1}(...)`
+    let mut callee = call.get("expression")?;
+    while node_type(callee) == Some("FunctionCallOptions") {
+        callee = callee.get("expression")?;
+    }
+    let declaration = index.get(&callee.get("referencedDeclaration")?.as_u64()?)?;
+    if !matches!(
+        node_type(declaration),
+        Some("FunctionDefinition" | "ErrorDefinition" | "StructDefinition")
+    ) {
+        return None;
+    }
+    let names: Vec<&str> = parameters(declaration)?
+        .iter()
+        .map(|parameter| parameter.get("name").and_then(Value::as_str))
+        .collect::<Option<_>>()?;
+    let arguments = children(call, "arguments").count();
+    (arguments > 0 && arguments == names.len() && names.iter().all(|name| !name.is_empty()))
+        .then_some(names)
+}
+
+/// Edits naming the positional arguments of `call`.
+fn to_named(source: &str, call: &Value, names: &[&str]) -> Option<Vec<TextEdit>> {
+    let arguments: Vec<(usize, usize)> = children(call, "arguments")
+        .map(span_bounds)
+        .collect::<Option<_>>()?;
+    let mut builder = EditBuilder::new(source);
+    for (i, ((start, _), name)) in arguments.iter().zip(names).enumerate() {
+        let brace = if i == 0 { "{" } else { "" };
+        builder.insert(*start, format!("{brace}{name}: ")).ok()?;
+    }
+    builder.insert(arguments.last()?.1, "}").ok()?;
+    Some(builder.build())
+}
+
+/// Edits passing the named arguments of `call` positionally, in declaration order.
+fn to_positional(source: &str, call: &Value, names: &[&str]) -> Option<Vec<TextEdit>> {
+    let given: Vec<&str> = children(call, "names")
+        .map(Value::as_str)
+        .collect::<Option<_>>()?;
+    let arguments: Vec<(usize, usize)> = children(call, "arguments")
+        .map(span_bounds)
+        .collect::<Option<_>>()?;
+    let by_name: HashMap<&str, (usize, usize)> = given
+        .iter()
+        .copied()
+        .zip(arguments.iter().copied())
+        .collect();
+    let texts: Vec<&str> = names
+        .iter()
+        .map(|name| {
+            let (start, end) = by_name.get(name)?;
+            source.get(*start..*end)
+        })
+        .collect::<Option<_>>()?;
+
+    let (_, callee_end) = span_bounds(call.get("expression")?)?;
+    let (_, call_end) = span_bounds(call)?;
+    let open = callee_end + source.get(callee_end..)?.find('{')?;
+    let close = open + source.get(open..call_end)?.rfind('}')?;
+    let mut builder = EditBuilder::new(source);
+    builder.replace(open, close + 1, texts.join(", ")).ok()?;
+    Some(builder.build())
+}
+
+/// "Use named arguments" or "Use positional arguments" for the call at `position` in
+/// `uri`.
+pub fn named_arguments_action(
+    ast_data: &Value,
+    uri: &Url,
+    position: Position,
+    source_bytes: &[u8],
+) -> Option<CodeActionOrCommand> {
+    let sources = ast_data.get("sources")?;
+    let unit = ast::source_unit(ast_data, uri)?;
+    let index = ast::index_nodes(sources);
+    let call = call_at(unit, pos_to_bytes(source_bytes, position))?;
+    let names = parameter_names(call, &index)?;
+    let source = String::from_utf8_lossy(source_bytes);
+
+    let named = children(call, "names").next().is_some();
+    let (title, edits) = if named {
+        (
+            "Use positional arguments",
+            to_positional(&source, call, &names)?,
+        )
+    } else {
+        ("Use named arguments", to_named(&source, call, &names)?)
+    };
+    Some(CodeActionOrCommand::CodeAction(CodeAction {
+        title: title.to_string(),
+        kind: Some(CodeActionKind::REFACTOR_REWRITE),
+        edit: Some(WorkspaceEdit {
+            changes: Some(HashMap::from([(uri.clone(), edits)])),
+            ..WorkspaceEdit::default()
+        }),
+        ..CodeAction::default()
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax;
+
+    const SOURCE: &str = "\
+contract Vault {
+    struct Limit { uint256 cap; bool strict; }
+    event Moved(address to, uint256 amount);
+
+    function transfer(address to, uint256 amount, bool force) public {}
+
+    function run(address owner) public {
+        transfer(owner, 1, true);
+        transfer({force: false, to: owner, amount: 2});
+        Limit memory limit = Limit(10, false);
+        emit Moved(owner, 1);
+        transfer(owner, 1);
+    }
+}
+";
+
+    fn rewrite(line: u32, character: u32) -> Option<(String, String)> {
+        let path = "/project/src/Vault.sol";
+        let uri = Url::from_file_path(path).unwrap();
+        let tree = syntax::parse(path, SOURCE);
+        let CodeActionOrCommand::CodeAction(action) = named_arguments_action(
+            &tree,
+            &uri,
+            Position::new(line, character),
+            SOURCE.as_bytes(),
+        )?
+        else {
+            panic!("expected a code action");
+        };
+        let edits = &action.edit.unwrap().changes.unwrap()[&uri];
+        let text = EditBuilder::with_edits(SOURCE, edits).unwrap().apply();
+        let line = text.lines().nth(line as usize).unwrap().trim().to_string();
+        Some((action.title, line))
+    }
+
+    #[test]
+    fn test_positional_and_named_arguments_rewrite() {
+        assert_eq!(
+            rewrite(7, 20),
+            Some((
+                "Use named arguments".to_string(),
+                "transfer({to: owner, amount: 1, force: true});".to_string()
+            ))
+        );
+        assert_eq!(
+            rewrite(8, 10),
+            Some((
+                "Use positional arguments".to_string(),
+                "transfer(owner, 2, false);".to_string()
+            ))
+        );
+        assert_eq!(
+            rewrite(9, 35),
+            Some((
+                "Use named arguments".to_string(),
+                "Limit memory limit = Limit({cap: 10, strict: false});".to_string()
+            ))
+        );
+
+        // Events, and calls whose arguments don't match the parameters, are left alone
+        assert_eq!(rewrite(10, 15), None);
+        assert_eq!(rewrite(11, 15), None);
+    }
+}
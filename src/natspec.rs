@@ -0,0 +1,876 @@
+//! NatSpec coverage of the public API: `@notice`, `@param` and `@return` on the
+//! external and public functions of the project's `src/` directory.
+//!
+//! Each missing piece is reported on the name it documents, and every diagnostic
+//! carries a [`Fix`] inserting the stub for all pieces its function is missing.
+//!
+//! While editing, doc comments are checked and completed from the buffer's syntax tree:
+//! `@param` tags naming no parameter are reported, and completion in a comment above a
+//! declaration offers the tags it still misses, `@param` tags named after its parameters.
+
+use serde_json::Value;
+use std::path::Path;
+use tower_lsp::lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionTextEdit, Diagnostic, DiagnosticSeverity,
+    InsertTextFormat, NumberOrString, Position, Range, TextEdit, Url,
+};
+
+use crate::{
+    ast::{self, name, node_type, parse_src, span_bounds},
+    build_info::find_project_root,
+    code_actions::Fix,
+    documents::LineIndex,
+    edits::EditBuilder,
+    goto::bytes_to_pos,
+    hover,
+};
+
+/// Diagnostic code of missing NatSpec.
+pub const NATSPEC_CODE: &str = "natspec-missing";
+
+/// Directory of the project's own sources, relative to the project root.
+const SOURCES_DIR: &str = "src";
+
+/// Whether `path` is a source of the project rather than a test, script or dependency.
+fn is_project_source(path: &Path) -> bool {
+    find_project_root(path)
+        .and_then(|root| {
+            path.strip_prefix(root)
+                .ok()
+                .map(|p| p.starts_with(SOURCES_DIR))
+        })
+        .unwrap_or(false)
+}
+
+fn is_public_api(function: &Value) -> bool {
+    function.get("kind").and_then(Value::as_str) == Some("function")
+        && matches!(
+            function.get("visibility").and_then(Value::as_str),
+            Some("public" | "external")
+        )
+}
+
+fn parameters<'a>(function: &'a Value, key: &str) -> &'a [Value] {
+    function
+        .get(key)
+        .and_then(|list| list.get("parameters"))
+        .and_then(Value::as_array)
+        .map_or(&[], Vec::as_slice)
+}
+
+/// A missing NatSpec tag of a function and the node it documents.
+struct Missing<'a> {
+    tag: String,
+    node: &'a Value,
+    /// Use the node's `nameLocation` rather than its whole `src`
+    at_name: bool,
+}
+
+fn missing_tags<'a>(
+    function: &'a Value,
+    natspec: &hover::NatSpec,
+    params: &'a [Value],
+    returns: &'a [Value],
+) -> Vec<Missing<'a>> {
+    let mut missing = Vec::new();
+    if natspec.notice.is_none() {
+        missing.push(Missing {
+            tag: "@notice".to_string(),
+            node: function,
+            at_name: true,
+        });
+    }
+    let documented: Vec<&str> = natspec
+        .params
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect();
+    for param in params {
+        let param_name = name(param);
+        if !param_name.is_empty() && !documented.contains(&param_name) {
+            missing.push(Missing {
+                tag: format!("@param {param_name}"),
+                node: param,
+                at_name: true,
+            });
+        }
+    }
+    for returned in returns.iter().skip(natspec.returns.len()) {
+        let tag = match name(returned) {
+            "" => "@return".to_string(),
+            return_name => format!("@return {return_name}"),
+        };
+        missing.push(Missing {
+            tag,
+            node: returned,
+            at_name: false,
+        });
+    }
+    missing
+}
+
+fn line_start(source: &str, offset: usize) -> usize {
+    source[..offset].rfind('\n').map_or(0, |i| i + 1)
+}
+
+/// Edit inserting `tags` into the doc comment of the function starting at `start`, or a
+/// new `///` comment above it when there is none.
+fn stub_edit(source: &str, start: usize, tags: &[String]) -> Option<TextEdit> {
+    let function_line = line_start(source, start);
+    let indent: String = source[function_line..start]
+        .chars()
+        .take_while(|c| c.is_whitespace())
+        .collect();
+
+    let previous_line = source[..function_line.saturating_sub(1)]
+        .rfind('\n')
+        .map_or(0, |i| i + 1);
+    let previous = source.get(previous_line..function_line.saturating_sub(1))?;
+    let is_block = function_line > 0
+        && previous.trim_end().ends_with("*/")
+        && !hover::doc_comment_above(source, start).is_empty();
+
+    let (start, end, new_text) = if !is_block {
+        let lines: String = tags
+            .iter()
+            .map(|tag| format!("{indent}/// {tag}\n"))
+            .collect();
+        (function_line, function_line, lines)
+    } else if previous.trim() == "*/" {
+        // Before the line closing the block
+        let lines: String = tags
+            .iter()
+            .map(|tag| format!("{indent} * {tag}\n"))
+            .collect();
+        (previous_line, previous_line, lines)
+    } else {
+        // `/** ... */` on one line: continue the block and close it on its own line
+        let close = previous_line + previous.trim_end().len() - 2;
+        let text_end = previous_line + source[previous_line..close].trim_end().len();
+        let lines: String = tags
+            .iter()
+            .map(|tag| format!("\n{indent} * {tag}"))
+            .collect();
+        (text_end, close, format!("{lines}\n{indent} "))
+    };
+
+    let mut edits = EditBuilder::new(source);
+    edits.replace(start, end, new_text).ok()?;
+    edits.build().pop()
+}
+
+fn node_range(source: &[u8], src: &str) -> Option<Range> {
+    let (start, length, _) = parse_src(src)?;
+    Some(Range::new(
+        bytes_to_pos(source, start)?,
+        bytes_to_pos(source, start + length)?,
+    ))
+}
+
+/// Missing NatSpec on the external and public functions of `uri`, if it is in `src/`.
+pub fn natspec_diagnostics(ast_data: &Value, uri: &Url, source_bytes: &[u8]) -> Vec<Diagnostic> {
+    let Ok(path) = uri.to_file_path() else {
+        return vec![];
+    };
+    let (true, Ok(source), Some(source_unit)) = (
+        is_project_source(&path),
+        std::str::from_utf8(source_bytes),
+        ast::source_unit(ast_data, uri),
+    ) else {
+        return vec![];
+    };
+
+    let mut functions = Vec::new();
+    ast::walk(source_unit, &mut |node| {
+        if node.get("nodeType").and_then(Value::as_str) == Some("FunctionDefinition")
+            && is_public_api(node)
+        {
+            functions.push(node);
+        }
+    });
+
+    let mut diagnostics = Vec::new();
+    for function in functions {
+        let function_name = name(function);
+        let Some((start, _)) = span_bounds(function).filter(|_| !function_name.is_empty()) else {
+            continue;
+        };
+        // Overrides without documentation inherit the documentation of the base function
+        let has_docs = function
+            .get("documentation")
+            .is_some_and(|doc| !doc.is_null())
+            || !hover::doc_comment_above(source, start).is_empty();
+        if !has_docs && function.get("overrides").is_some_and(|o| !o.is_null()) {
+            continue;
+        }
+        let natspec = hover::natspec(Some(function), source, start);
+        if natspec.inheritdoc.is_some() {
+            continue;
+        }
+
+        let params = parameters(function, "parameters");
+        let returns = parameters(function, "returnParameters");
+        let missing = missing_tags(function, &natspec, params, returns);
+        if missing.is_empty() {
+            continue;
+        }
+        let tags: Vec<String> = missing.iter().map(|m| m.tag.clone()).collect();
+        let Some(edit) = stub_edit(source, start, &tags) else {
+            continue;
+        };
+        let data = Fix::new("Add NatSpec stub", vec![edit]).to_data();
+
+        for Missing { tag, node, at_name } in missing {
+            let src = if at_name {
+                node.get("nameLocation").or_else(|| node.get("src"))
+            } else {
+                node.get("src")
+            };
+            let Some(range) = src
+                .and_then(Value::as_str)
+                .and_then(|src| node_range(source_bytes, src))
+            else {
+                continue;
+            };
+            diagnostics.push(Diagnostic {
+                range,
+                severity: Some(DiagnosticSeverity::WARNING),
+                code: Some(NumberOrString::String(NATSPEC_CODE.to_string())),
+                source: Some("forge-lsp".to_string()),
+                message: format!("`{function_name}` is missing `{tag}`"),
+                data: data.clone(),
+                ..Diagnostic::default()
+            });
+        }
+    }
+    diagnostics
+}
+
+/// Characters starting NatSpec completion: the `@` of a tag, and the `/` of `///`.
+pub const TRIGGER_CHARACTERS: &[&str] = &["@", "/"];
+
+/// Diagnostic code of a `@param` tag naming no parameter.
+pub const NATSPEC_PARAM_CODE: &str = "natspec-param";
+
+/// Declarations whose NatSpec documents parameters.
+const WITH_PARAMETERS: &[&str] = &[
+    "FunctionDefinition",
+    "ModifierDefinition",
+    "EventDefinition",
+    "ErrorDefinition",
+];
+
+fn type_string(node: &Value) -> Option<&str> {
+    node.get("typeDescriptions")?.get("typeString")?.as_str()
+}
+
+fn src_start(node: &Value) -> Option<usize> {
+    let (start, _, _) = parse_src(node.get("src")?.as_str()?)?;
+    Some(start)
+}
+
+/// Byte range of the doc comment ending on the line above `start`: consecutive `///` lines,
+/// or a `/** */` block.
+fn doc_comment_range(source: &str, start: usize) -> Option<(usize, usize)> {
+    let line = line_start(source, start);
+    let above_end = line.checked_sub(1)?;
+    let above_start = line_start(source, above_end);
+    let above = source[above_start..above_end].trim();
+    if above.starts_with("///") {
+        let mut first = above_start;
+        while first > 0 {
+            let previous = line_start(source, first - 1);
+            if !source[previous..first - 1].trim_start().starts_with("///") {
+                break;
+            }
+            first = previous;
+        }
+        Some((first, above_end))
+    } else if above.ends_with("*/") {
+        let close = above_start + source[above_start..above_end].rfind("*/")?;
+        let open = source[..close].rfind("/*")?;
+        source[open..]
+            .starts_with("/**")
+            .then_some((open, above_end))
+    } else {
+        None
+    }
+}
+
+/// The names `@param` tags in `source[start..end]` document, with their byte ranges.
+fn param_tags(source: &str, (start, end): (usize, usize)) -> Vec<(&str, usize, usize)> {
+    let comment = &source[start..end];
+    comment
+        .match_indices("@param")
+        .filter_map(|(at, tag)| {
+            let rest = &comment[at + tag.len()..];
+            let name = rest.split_whitespace().next()?;
+            let offset = start + at + tag.len() + rest.find(name)?;
+            rest.starts_with(char::is_whitespace)
+                .then_some((name, offset, offset + name.len()))
+        })
+        .collect()
+}
+
+/// The `@param` tags of the doc comments of `tree`, the syntax tree of the buffer
+/// `source_bytes` of `uri`, naming none of the parameters of the declaration they document,
+/// which solc rejects. When a single parameter is undocumented, the fix renames the tag to it.
+pub fn param_tag_diagnostics(tree: &Value, uri: &Url, source_bytes: &[u8]) -> Vec<Diagnostic> {
+    let (Ok(source), Some(source_unit)) = (
+        std::str::from_utf8(source_bytes),
+        ast::source_unit(tree, uri),
+    ) else {
+        return vec![];
+    };
+    let mut diagnostics = Vec::new();
+    ast::walk(source_unit, &mut |node| {
+        if !node_type(node).is_some_and(|kind| WITH_PARAMETERS.contains(&kind)) {
+            return;
+        }
+        let Some(comment) = src_start(node).and_then(|start| doc_comment_range(source, start))
+        else {
+            return;
+        };
+        let names: Vec<&str> = parameters(node, "parameters")
+            .iter()
+            .map(name)
+            .filter(|name| !name.is_empty())
+            .collect();
+        let tags = param_tags(source, comment);
+        let undocumented: Vec<&str> = names
+            .iter()
+            .copied()
+            .filter(|name| tags.iter().all(|(tag, _, _)| tag != name))
+            .collect();
+        let declaration = match (name(node), node_type(node)) {
+            ("", Some("FunctionDefinition")) => "function",
+            ("", _) => "declaration",
+            (declaration, _) => declaration,
+        };
+        for (tag, start, end) in tags {
+            if names.contains(&tag) {
+                continue;
+            }
+            let (Some(start), Some(end)) = (
+                bytes_to_pos(source_bytes, start),
+                bytes_to_pos(source_bytes, end),
+            ) else {
+                continue;
+            };
+            let range = Range::new(start, end);
+            let data = match undocumented[..] {
+                [parameter] => Fix::new(
+                    format!("Rename to `{parameter}`"),
+                    vec![TextEdit::new(range, parameter.to_string())],
+                )
+                .to_data(),
+                _ => None,
+            };
+            diagnostics.push(Diagnostic {
+                range,
+                severity: Some(DiagnosticSeverity::WARNING),
+                code: Some(NumberOrString::String(NATSPEC_PARAM_CODE.to_string())),
+                source: Some("forge-lsp".to_string()),
+                message: format!("`{declaration}` has no parameter `{tag}`"),
+                data,
+                ..Diagnostic::default()
+            });
+        }
+    });
+    diagnostics
+}
+
+/// A tag offered by NatSpec completion.
+struct TagItem {
+    label: String,
+    snippet: String,
+    detail: Option<String>,
+}
+
+fn tag(label: impl Into<String>, snippet: impl Into<String>) -> TagItem {
+    TagItem {
+        label: label.into(),
+        snippet: snippet.into(),
+        detail: None,
+    }
+}
+
+/// The tags documenting `node` that `comment` doesn't have yet.
+fn missing_tag_items(tree_unit: &Value, node: &Value, comment: &str) -> Vec<TagItem> {
+    let kind = node_type(node);
+    let has = |tag: &str| comment.contains(tag);
+    let mut items = Vec::new();
+    if kind == Some("ContractDefinition") {
+        for tag_name in ["@title", "@author"] {
+            if !has(tag_name) {
+                items.push(tag(tag_name, format!("{tag_name} ${{1}}")));
+            }
+        }
+    }
+    if !has("@notice") {
+        items.push(tag("@notice", "@notice ${1}"));
+    }
+    items.push(tag("@dev", "@dev ${1}"));
+    if kind == Some("ContractDefinition") {
+        return items;
+    }
+
+    let documented: Vec<&str> = comment
+        .split("@param")
+        .skip(1)
+        .filter_map(|rest| rest.split_whitespace().next())
+        .collect();
+    for parameter in parameters(node, "parameters") {
+        let parameter_name = name(parameter);
+        if parameter_name.is_empty() || documented.contains(&parameter_name) {
+            continue;
+        }
+        items.push(TagItem {
+            detail: type_string(parameter).map(str::to_string),
+            ..tag(
+                format!("@param {parameter_name}"),
+                format!("@param {parameter_name} ${{1}}"),
+            )
+        });
+    }
+    let returned = comment.matches("@return").count();
+    for (i, parameter) in parameters(node, "returnParameters").iter().enumerate() {
+        if i < returned {
+            continue;
+        }
+        let (label, snippet) = match name(parameter) {
+            "" => ("@return".to_string(), "@return ${1}".to_string()),
+            return_name => (
+                format!("@return {return_name}"),
+                format!("@return {return_name} ${{1}}"),
+            ),
+        };
+        items.push(TagItem {
+            detail: type_string(parameter).map(str::to_string),
+            ..tag(label, snippet)
+        });
+    }
+
+    // `@inheritdoc` names a base of the enclosing contract
+    if kind == Some("FunctionDefinition") && !has("@inheritdoc") {
+        let start = src_start(node);
+        let mut bases = Vec::new();
+        ast::walk(tree_unit, &mut |contract| {
+            if node_type(contract) == Some("ContractDefinition")
+                && start.is_some_and(|start| ast::contains(contract, start))
+            {
+                bases = contract
+                    .get("baseContracts")
+                    .and_then(Value::as_array)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|base| base["baseName"]["name"].as_str())
+                    .collect();
+            }
+        });
+        if !bases.is_empty() {
+            items.push(tag(
+                "@inheritdoc",
+                format!("@inheritdoc ${{1|{}|}}", bases.join(",")),
+            ));
+        }
+    }
+    items
+}
+
+/// Completions of NatSpec tags in the doc comment at `position` of the buffer `source_bytes`
+/// of `uri`, whose syntax tree is `tree`: the tags the declaration below it still misses,
+/// `@param` tags named after its parameters, and on an empty comment a stub of all of them.
+pub fn natspec_completions(
+    tree: &Value,
+    uri: &Url,
+    position: Position,
+    source_bytes: &[u8],
+) -> Vec<CompletionItem> {
+    let (Ok(source), Some(source_unit)) = (
+        std::str::from_utf8(source_bytes),
+        ast::source_unit(tree, uri),
+    ) else {
+        return vec![];
+    };
+    // Columns are UTF-16, so the cursor is found with the document's line index
+    let cursor = LineIndex::new(source).offset(source, position);
+    let line = line_start(source, cursor);
+    let before = &source[line..cursor];
+    let indent: String = before.chars().take_while(|c| c.is_whitespace()).collect();
+
+    // The continuation of a new line of the comment, and the comment's end
+    let (continuation, comment_end, marker) = if before.trim_start().starts_with("///") {
+        let mut end = source[cursor..]
+            .find('\n')
+            .map_or(source.len(), |i| cursor + i);
+        while let Some(next) = source.get(end + 1..)
+            && next.trim_start().starts_with("///")
+            && !next.trim_start().starts_with("////")
+        {
+            end = next.find('\n').map_or(source.len(), |i| end + 1 + i);
+        }
+        (format!("\n{indent}/// "), end, line + indent.len() + 3)
+    } else {
+        let Some(open) = source[..cursor]
+            .rfind("/**")
+            .filter(|&open| !source[open..cursor].contains("*/"))
+        else {
+            return vec![];
+        };
+        let Some(close) = source[cursor..].find("*/") else {
+            return vec![];
+        };
+        let open_indent = &source[line_start(source, open)..open];
+        let indent: String = open_indent
+            .chars()
+            .take_while(|c| c.is_whitespace())
+            .collect();
+        let marker = if line_start(source, open) == line {
+            open + 3
+        } else {
+            line + before.find('*').map_or(before.len(), |star| star + 1)
+        };
+        (format!("\n{indent} * "), cursor + close + 2, marker)
+    };
+
+    // The declaration right below the comment
+    let documented = source[comment_end..]
+        .find(|c: char| !c.is_whitespace())
+        .map(|offset| comment_end + offset);
+    let mut node = None;
+    ast::walk(source_unit, &mut |candidate| {
+        let kind = node_type(candidate);
+        if (kind.is_some_and(|kind| WITH_PARAMETERS.contains(&kind))
+            || kind == Some("ContractDefinition"))
+            && documented.is_some_and(|start| src_start(candidate) == Some(start))
+        {
+            node = Some(candidate);
+        }
+    });
+    let Some(node) = node else {
+        return vec![];
+    };
+    let start =
+        doc_comment_range(source, documented.unwrap_or(cursor)).map_or(line, |(start, _)| start);
+    let comment = &source[start..comment_end];
+
+    // The tag being typed, `@` included, is replaced
+    let word_start = cursor - before.len()
+        + before
+            .trim_end_matches(|c: char| c.is_ascii_alphanumeric())
+            .len();
+    let replace_start = match source[..word_start].ends_with('@') {
+        true => word_start - 1,
+        false => word_start,
+    };
+    let column = |offset: usize| source[line..offset].encode_utf16().count() as u32;
+    let range = Range::new(
+        Position::new(position.line, column(replace_start)),
+        Position::new(position.line, column(cursor)),
+    );
+    let tags = missing_tag_items(source_unit, node, comment);
+
+    let mut items = Vec::new();
+    // An empty comment gets a stub of every missing tag
+    let empty = !comment.contains('@') && source[marker.min(cursor)..cursor].trim().is_empty();
+    if empty && node_type(node) != Some("ContractDefinition") {
+        let stub: Vec<String> = tags
+            .iter()
+            .filter(|item| item.label != "@dev" && item.label != "@inheritdoc")
+            .enumerate()
+            .map(|(i, item)| item.snippet.replace("${1", &format!("${{{}", i + 1)))
+            .collect();
+        items.push(CompletionItem {
+            label: "NatSpec stub".to_string(),
+            kind: Some(CompletionItemKind::SNIPPET),
+            detail: Some(name(node))
+                .filter(|declaration| !declaration.is_empty())
+                .map(|declaration| format!("Document `{declaration}`")),
+            text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(
+                range,
+                stub.join(&continuation),
+            ))),
+            insert_text_format: Some(InsertTextFormat::SNIPPET),
+            sort_text: Some("0".to_string()),
+            ..CompletionItem::default()
+        });
+    }
+    for (i, item) in tags.into_iter().enumerate() {
+        items.push(CompletionItem {
+            filter_text: Some(item.label.clone()),
+            label: item.label,
+            kind: Some(CompletionItemKind::KEYWORD),
+            detail: item.detail,
+            text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(range, item.snippet))),
+            insert_text_format: Some(InsertTextFormat::SNIPPET),
+            sort_text: Some(format!("1{i:02}")),
+            ..CompletionItem::default()
+        });
+    }
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tower_lsp::lsp_types::Position;
+
+    fn position_of(source: &str, needle: &str) -> Position {
+        bytes_to_pos(source.as_bytes(), source.find(needle).unwrap()).unwrap()
+    }
+
+    fn variable(source: &str, declaration: &str, name: &str) -> Value {
+        let start = source.find(declaration).unwrap();
+        json!({
+            "nodeType": "VariableDeclaration",
+            "name": name,
+            "src": format!("{start}:{}:0", declaration.len()),
+            "nameLocation": format!("{}:{}:0", start + declaration.len() - name.len(), name.len())
+        })
+    }
+
+    fn mock_ast(path: &str, source: &str, documentation: Option<&str>) -> Value {
+        let start = source.find("function").unwrap();
+        let mut function = json!({
+            "nodeType": "FunctionDefinition",
+            "kind": "function",
+            "name": "withdraw",
+            "visibility": "external",
+            "src": format!("{start}:{}:0", source.len() - start - 3),
+            "nameLocation": format!("{}:8:0", source.find("withdraw").unwrap())
+        });
+        let amount = variable(source, "uint256 amount", "amount");
+        function["parameters"] = json!({ "parameters": [amount] });
+        function["returnParameters"] = json!({ "parameters": [variable(source, "bool", "")] });
+        if let Some(text) = documentation {
+            function["documentation"] = json!({ "text": text });
+        }
+        ast::mock_ast(
+            path,
+            json!({
+                "nodeType": "SourceUnit",
+                "absolutePath": path,
+                "nodes": [{ "nodeType": "ContractDefinition", "nodes": [function] }]
+            }),
+        )
+    }
+
+    fn project_file(source: &str) -> (tempfile::TempDir, Url) {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("foundry.toml"), "").unwrap();
+        std::fs::create_dir(dir.path().join("src")).unwrap();
+        let path = dir.path().join("src/Vault.sol");
+        std::fs::write(&path, source).unwrap();
+        (dir, Url::from_file_path(path).unwrap())
+    }
+
+    fn apply(source: &str, edit: &TextEdit) -> String {
+        let start = crate::goto::pos_to_bytes(source.as_bytes(), edit.range.start);
+        let end = crate::goto::pos_to_bytes(source.as_bytes(), edit.range.end);
+        format!("{}{}{}", &source[..start], edit.new_text, &source[end..])
+    }
+
+    #[test]
+    fn test_missing_natspec_and_stub() {
+        let source = concat!(
+            "contract Vault {\n",
+            "    function withdraw(uint256 amount) external returns (bool) {\n",
+            "    }\n",
+            "}\n",
+        );
+        let (_dir, uri) = project_file(source);
+        let ast = mock_ast(uri.path(), source, None);
+
+        let diagnostics = natspec_diagnostics(&ast, &uri, source.as_bytes());
+        let found: Vec<_> = diagnostics
+            .iter()
+            .map(|d| (d.message.as_str(), d.range.start))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (
+                    "`withdraw` is missing `@notice`",
+                    position_of(source, "withdraw")
+                ),
+                (
+                    "`withdraw` is missing `@param amount`",
+                    position_of(source, "amount")
+                ),
+                (
+                    "`withdraw` is missing `@return`",
+                    position_of(source, "bool")
+                ),
+            ]
+        );
+
+        let fix = Fix::from_diagnostic(&diagnostics[1]).unwrap();
+        assert_eq!(fix.title, "Add NatSpec stub");
+        assert_eq!(
+            apply(source, &fix.edits[0]),
+            concat!(
+                "contract Vault {\n",
+                "    /// @notice\n",
+                "    /// @param amount\n",
+                "    /// @return\n",
+                "    function withdraw(uint256 amount) external returns (bool) {\n",
+                "    }\n",
+                "}\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_partial_block_natspec() {
+        let source = concat!(
+            "contract Vault {\n",
+            "    /** @notice Withdraw funds */\n",
+            "    function withdraw(uint256 amount) external returns (bool) {\n",
+            "    }\n",
+            "}\n",
+        );
+        let (dir, uri) = project_file(source);
+        let ast = mock_ast(uri.path(), source, Some("@notice Withdraw funds"));
+
+        let diagnostics = natspec_diagnostics(&ast, &uri, source.as_bytes());
+        assert_eq!(diagnostics.len(), 2);
+        let fix = Fix::from_diagnostic(&diagnostics[0]).unwrap();
+        assert_eq!(
+            apply(source, &fix.edits[0]),
+            concat!(
+                "contract Vault {\n",
+                "    /** @notice Withdraw funds\n",
+                "     * @param amount\n",
+                "     * @return\n",
+                "     */\n",
+                "    function withdraw(uint256 amount) external returns (bool) {\n",
+                "    }\n",
+                "}\n",
+            )
+        );
+
+        // Documented elsewhere, or outside `src/`
+        let ast = mock_ast(uri.path(), source, Some("@inheritdoc IVault"));
+        assert!(natspec_diagnostics(&ast, &uri, source.as_bytes()).is_empty());
+        let script = Url::from_file_path(dir.path().join("Vault.sol")).unwrap();
+        let ast = mock_ast(script.path(), source, None);
+        assert!(natspec_diagnostics(&ast, &script, source.as_bytes()).is_empty());
+    }
+
+    const DOCUMENTED: &str = "\
+contract Vault is IVault, Pausable {
+    /// @notice Deposit assets
+    /// @param amount The assets
+    function deposit(uint256 assets, address receiver) external returns (uint256 shares) {}
+
+    ///
+    function withdraw(uint256 assets) external returns (bool) {}
+
+    /** @param who The account */
+    event Paused(address account);
+}
+";
+
+    fn tree_of(source: &str) -> (Value, Url) {
+        let path = "/project/src/Vault.sol";
+        (
+            crate::syntax::parse(path, source),
+            Url::from_file_path(path).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_param_tag_diagnostics() {
+        let (tree, uri) = tree_of(DOCUMENTED);
+        let diagnostics = param_tag_diagnostics(&tree, &uri, DOCUMENTED.as_bytes());
+        let messages: Vec<&str> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                "`deposit` has no parameter `amount`",
+                "`Paused` has no parameter `who`"
+            ]
+        );
+        assert_eq!(
+            diagnostics[0].range.start,
+            position_of(DOCUMENTED, "amount")
+        );
+        // Two parameters are undocumented, which the tag could mean either of
+        assert_eq!(Fix::from_diagnostic(&diagnostics[0]), None);
+        let fix = Fix::from_diagnostic(&diagnostics[1]).unwrap();
+        assert_eq!(fix.title, "Rename to `account`");
+        assert!(apply(DOCUMENTED, &fix.edits[0]).contains("/** @param account The account */"));
+    }
+
+    #[test]
+    fn test_natspec_completions() {
+        let (tree, uri) = tree_of(DOCUMENTED);
+        let labels = |position: Position| -> Vec<String> {
+            natspec_completions(&tree, &uri, position, DOCUMENTED.as_bytes())
+                .into_iter()
+                .map(|item| item.label)
+                .collect()
+        };
+        let line = |needle: &str| position_of(DOCUMENTED, needle).line;
+
+        // After a tag of a documented function, the missing ones
+        let at = Position::new(line("@param amount"), 32);
+        assert_eq!(
+            labels(at),
+            [
+                "@dev",
+                "@param assets",
+                "@param receiver",
+                "@return shares",
+                "@inheritdoc"
+            ]
+        );
+
+        // An empty comment gets the stub too
+        let empty = Position::new(line("    ///\n"), 7);
+        let items = natspec_completions(&tree, &uri, empty, DOCUMENTED.as_bytes());
+        assert_eq!(items[0].label, "NatSpec stub");
+        let Some(CompletionTextEdit::Edit(edit)) = &items[0].text_edit else {
+            panic!("stub without edit");
+        };
+        assert_eq!(
+            edit.new_text,
+            "@notice ${1}\n    /// @param assets ${2}\n    /// @return ${3}"
+        );
+        let Some(CompletionTextEdit::Edit(edit)) = &items.last().unwrap().text_edit else {
+            panic!("tag without edit");
+        };
+        assert_eq!(edit.new_text, "@inheritdoc ${1|IVault,Pausable|}");
+
+        // Outside doc comments there are none
+        assert!(labels(position_of(DOCUMENTED, "external")).is_empty());
+    }
+
+    #[test]
+    fn test_natspec_completions_non_ascii() {
+        let source = DOCUMENTED.replace("Deposit assets", "Dépôt é @");
+        let (tree, uri) = tree_of(&source);
+        let line = position_of(&source, "Dépôt").line;
+        // Columns within and past the multibyte characters
+        for character in 0..30 {
+            natspec_completions(
+                &tree,
+                &uri,
+                Position::new(line, character),
+                source.as_bytes(),
+            );
+        }
+
+        // After the `@`, in UTF-16 columns: `    /// @notice Dépôt é @`
+        let items = natspec_completions(&tree, &uri, Position::new(line, 25), source.as_bytes());
+        let Some(CompletionTextEdit::Edit(edit)) = &items[0].text_edit else {
+            panic!("tag without edit");
+        };
+        assert_eq!(
+            edit.range,
+            Range::new(Position::new(line, 24), Position::new(line, 25))
+        );
+    }
+}
@@ -0,0 +1,241 @@
+//! Path and URI normalization.
+//!
+//! Forge reports source paths exactly as the compiler saw them, while editors send
+//! `file://` URIs. On Windows the two disagree in separators (`\` vs `/`), drive-letter
+//! casing (`C:` vs `c:`), verbatim prefixes (`\\?\C:\...`) and UNC hosts
+//! (`file://server/share/...`). Every lookup into AST path maps goes through a
+//! normalized key so these spellings all resolve to the same file.
+//!
+//! Symlinks are a second source of mismatches: with a symlinked `lib/` or a pnpm-style
+//! package store, forge reports the path it was given while the editor may open the real
+//! location (or the other way round). [`canonical_key`] resolves symlinks so both spellings
+//! can be registered under, and looked up by, one key.
+
+use percent_encoding::percent_decode_str;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+use tower_lsp::lsp_types::Url;
+
+/// Normalize a file system path into a lookup key: forward slashes only, no verbatim
+/// prefix, and a lowercase drive letter.
+pub fn normalize_path(path: &str) -> String {
+    let mut path = path.replace('\\', "/");
+
+    // Verbatim prefixes, as returned by `std::fs::canonicalize` on Windows
+    if let Some(rest) = path.strip_prefix("//?/UNC/") {
+        path = format!("//{rest}");
+    } else if let Some(rest) = path.strip_prefix("//?/") {
+        path = rest.to_string();
+    }
+
+    // URI paths spell drive paths as `/C:/...`
+    if path.starts_with('/') && has_drive_prefix(&path[1..]) {
+        path.remove(0);
+    }
+
+    if has_drive_prefix(&path) {
+        path[..1].make_ascii_lowercase();
+    }
+
+    path
+}
+
+fn has_drive_prefix(path: &str) -> bool {
+    let bytes = path.as_bytes();
+    bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':'
+}
+
+/// Lookup key for a `file://` URI. Returns `None` for other schemes.
+pub fn uri_to_key(uri: &Url) -> Option<String> {
+    if uri.scheme() != "file" {
+        return None;
+    }
+
+    let path = match uri.to_file_path() {
+        Ok(path) => path.to_string_lossy().into_owned(),
+        // Forms the host platform cannot represent natively, e.g. UNC URIs on unix
+        Err(()) => {
+            let path = percent_decode_str(uri.path()).decode_utf8_lossy();
+            match uri.host_str() {
+                Some(host) if !host.is_empty() => format!("//{host}{path}"),
+                _ => path.into_owned(),
+            }
+        }
+    };
+
+    Some(normalize_path(&path))
+}
+
+/// Lookup key for a string that is either a `file://` URI or a plain path.
+pub fn to_key(uri_or_path: &str) -> String {
+    match Url::parse(uri_or_path) {
+        Ok(uri) if uri.scheme() == "file" => {
+            uri_to_key(&uri).unwrap_or_else(|| normalize_path(uri_or_path))
+        }
+        _ => normalize_path(uri_or_path),
+    }
+}
+
+/// Resolve a source path reported by forge to a path on disk. Relative paths are
+/// relative to the directory forge ran in.
+pub fn resolve_source_path(path: &str) -> Option<PathBuf> {
+    let path = Path::new(path);
+    if path.is_absolute() {
+        Some(path.to_path_buf())
+    } else {
+        Some(std::env::current_dir().ok()?.join(path))
+    }
+}
+
+/// Canonical lookup key for a path: relative paths are resolved against the working
+/// directory, symlinks are resolved if the file exists, and the result is normalized.
+pub fn canonical_key(path: &str) -> String {
+    let Some(resolved) = resolve_source_path(path) else {
+        return normalize_path(path);
+    };
+    let canonical = std::fs::canonicalize(&resolved).unwrap_or(resolved);
+    normalize_path(&canonical.to_string_lossy())
+}
+
+/// Look up a file in a map keyed by normalized and canonical paths (see
+/// [`normalize_path`] and [`canonical_key`]). Tries the normalized spelling first and only
+/// touches the file system when that misses.
+pub fn lookup_path<'a, V>(map: &'a HashMap<String, V>, uri_or_path: &str) -> Option<&'a V> {
+    if let Some(value) = map.get(&to_key(uri_or_path)) {
+        return Some(value);
+    }
+
+    let path = match Url::parse(uri_or_path) {
+        Ok(uri) if uri.scheme() == "file" => uri.to_file_path().ok()?,
+        _ => PathBuf::from(uri_or_path),
+    };
+    map.get(&canonical_key(&path.to_string_lossy()))
+}
+
+/// Build a `file://` URI for a path on disk, dropping any Windows verbatim prefix first
+/// so editors receive the same URI they sent.
+pub fn path_to_uri(path: &Path) -> Option<Url> {
+    let display = path.to_string_lossy();
+    let stripped = display
+        .strip_prefix(r"\\?\UNC\")
+        .map(|rest| PathBuf::from(format!(r"\\{rest}")))
+        .or_else(|| display.strip_prefix(r"\\?\").map(PathBuf::from));
+
+    Url::from_file_path(stripped.as_deref().unwrap_or(path)).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_path() {
+        assert_eq!(
+            normalize_path("/home/user/src/A.sol"),
+            "/home/user/src/A.sol"
+        );
+        assert_eq!(normalize_path(r"src\lib\A.sol"), "src/lib/A.sol");
+        assert_eq!(normalize_path(r"C:\Users\dev\A.sol"), "c:/Users/dev/A.sol");
+        assert_eq!(normalize_path("c:/Users/dev/A.sol"), "c:/Users/dev/A.sol");
+        assert_eq!(normalize_path("/C:/Users/dev/A.sol"), "c:/Users/dev/A.sol");
+        assert_eq!(
+            normalize_path(r"\\?\C:\Users\dev\A.sol"),
+            "c:/Users/dev/A.sol"
+        );
+        assert_eq!(
+            normalize_path(r"\\?\UNC\server\share\A.sol"),
+            "//server/share/A.sol"
+        );
+        assert_eq!(
+            normalize_path(r"\\server\share\A.sol"),
+            "//server/share/A.sol"
+        );
+    }
+
+    #[test]
+    fn test_uri_to_key() {
+        let uri = Url::parse("file:///home/user/my%20project/A.sol").unwrap();
+        assert_eq!(
+            uri_to_key(&uri).as_deref(),
+            Some("/home/user/my project/A.sol")
+        );
+
+        let drive = Url::parse("file:///C:/Users/dev/A.sol").unwrap();
+        let encoded = Url::parse("file:///c%3A/Users/dev/A.sol").unwrap();
+        assert_eq!(uri_to_key(&drive).as_deref(), Some("c:/Users/dev/A.sol"));
+        assert_eq!(uri_to_key(&drive), uri_to_key(&encoded));
+
+        let unc = Url::parse("file://server/share/A.sol").unwrap();
+        assert_eq!(uri_to_key(&unc).as_deref(), Some("//server/share/A.sol"));
+
+        let untitled = Url::parse("untitled:Untitled-1").unwrap();
+        assert_eq!(uri_to_key(&untitled), None);
+    }
+
+    #[test]
+    fn test_to_key_accepts_uris_and_paths() {
+        assert_eq!(to_key("file:///C:/dev/A.sol"), to_key(r"C:\dev\A.sol"));
+        assert_eq!(to_key("file:///tmp/A.sol"), "/tmp/A.sol");
+        assert_eq!(to_key("src/A.sol"), "src/A.sol");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_canonical_key_resolves_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = dir.path().join("store/dep@1.0.0");
+        std::fs::create_dir_all(&store).unwrap();
+        std::fs::write(store.join("A.sol"), "contract A {}").unwrap();
+        std::fs::create_dir_all(dir.path().join("lib")).unwrap();
+        std::os::unix::fs::symlink(&store, dir.path().join("lib/dep")).unwrap();
+
+        let real = store.join("A.sol");
+        let linked = dir.path().join("lib/dep/A.sol");
+        let real_key = canonical_key(&real.to_string_lossy());
+        assert_eq!(canonical_key(&linked.to_string_lossy()), real_key);
+
+        // Forge reported the symlinked path, the editor opened the real file
+        let map = HashMap::from([
+            (normalize_path(&linked.to_string_lossy()), "A"),
+            (canonical_key(&linked.to_string_lossy()), "A"),
+        ]);
+        let uri = Url::from_file_path(&real).unwrap();
+        assert_eq!(lookup_path(&map, uri.as_str()), Some(&"A"));
+
+        // Missing files fall back to the normalized path
+        let missing = dir.path().join("lib/dep/Missing.sol");
+        assert_eq!(
+            canonical_key(&missing.to_string_lossy()),
+            normalize_path(&missing.to_string_lossy())
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_path_to_uri_unix() {
+        let uri = path_to_uri(Path::new("/tmp/my project/A.sol")).unwrap();
+        assert_eq!(uri.as_str(), "file:///tmp/my%20project/A.sol");
+        assert_eq!(uri_to_key(&uri).as_deref(), Some("/tmp/my project/A.sol"));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_path_to_uri_windows() {
+        let uri = path_to_uri(Path::new(r"\\?\C:\dev\A.sol")).unwrap();
+        assert_eq!(uri.as_str(), "file:///C:/dev/A.sol");
+        assert_eq!(uri_to_key(&uri).as_deref(), Some("c:/dev/A.sol"));
+
+        let unc = path_to_uri(Path::new(r"\\?\UNC\server\share\A.sol")).unwrap();
+        assert_eq!(uri_to_key(&unc).as_deref(), Some("//server/share/A.sol"));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_resolve_source_path_windows() {
+        let resolved = resolve_source_path(r"C:\dev\A.sol").unwrap();
+        assert!(resolved.is_absolute());
+        assert_eq!(normalize_path(&resolved.to_string_lossy()), "c:/dev/A.sol");
+    }
+}
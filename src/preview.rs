@@ -0,0 +1,297 @@
+//! `forge-lsp/previewEdit`: the changes a rename or code action would make, as a unified
+//! diff, without applying them. Renames touching many files can be reviewed before any
+//! file is written.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tower_lsp::lsp_types::{
+    DocumentChangeOperation, DocumentChanges, OneOf, RenameParams, TextEdit, Url, WorkspaceEdit,
+};
+
+use crate::{
+    edits::{EditBuilder, EditError},
+    formatting::{Hunk, hunks},
+};
+
+/// Name of the custom request.
+pub const PREVIEW_EDIT_METHOD: &str = "forge-lsp/previewEdit";
+
+/// Unchanged lines shown around each change.
+const CONTEXT: usize = 3;
+
+/// What to preview: `{"rename": RenameParams}` or `{"edit": WorkspaceEdit}`, e.g. the edit
+/// of a code action.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PreviewEditParams {
+    Rename(RenameParams),
+    Edit(WorkspaceEdit),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EditPreview {
+    /// Unified diff of the changed files, in path order.
+    pub diff: String,
+    /// Number of files changed.
+    pub files: usize,
+    /// Number of edits, after merging edits that touch.
+    pub edits: usize,
+}
+
+/// The document a preview changes.
+pub struct PreviewFile<'a> {
+    /// Path shown in the diff headers.
+    pub path: String,
+    pub text: &'a str,
+    pub edits: &'a [TextEdit],
+}
+
+fn push_line(diff: &mut String, marker: char, line: &str) {
+    diff.push(marker);
+    diff.push_str(line);
+    if !line.ends_with('\n') {
+        diff.push_str("\n\\ No newline at end of file\n");
+    }
+}
+
+/// `start,count` of a hunk header, 1-based unless the range is empty.
+fn header_range(start: usize, count: usize) -> String {
+    if count == 0 {
+        format!("{start},0")
+    } else {
+        format!("{},{count}", start + 1)
+    }
+}
+
+/// Unified diff turning `old` into `new`, labelled with `path`. Empty if they are equal.
+pub fn unified_diff(path: &str, old: &str, new: &str) -> String {
+    let old_lines: Vec<&str> = old.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = new.split_inclusive('\n').collect();
+    let changes = hunks(&old_lines, &new_lines);
+    if changes.is_empty() {
+        return String::new();
+    }
+
+    // Changes whose context would overlap share a hunk
+    let mut groups: Vec<Vec<&Hunk>> = Vec::new();
+    for change in &changes {
+        match groups.last_mut() {
+            Some(group) if change.start <= group.last().unwrap().end + 2 * CONTEXT => {
+                group.push(change)
+            }
+            _ => groups.push(vec![change]),
+        }
+    }
+
+    let mut diff = format!("--- a/{path}\n+++ b/{path}\n");
+    // Lines added minus lines removed by the groups so far
+    let mut delta: isize = 0;
+    for group in groups {
+        let from = group[0].start.saturating_sub(CONTEXT);
+        let to = (group.last().unwrap().end + CONTEXT).min(old_lines.len());
+        let group_delta: isize = group
+            .iter()
+            .map(|change| change.lines.len() as isize - (change.end - change.start) as isize)
+            .sum();
+        let old_count = to - from;
+        let new_count = (old_count as isize + group_delta) as usize;
+        let new_from = (from as isize + delta) as usize;
+        diff.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            header_range(from, old_count),
+            header_range(new_from, new_count)
+        ));
+
+        let mut line = from;
+        for change in group {
+            for context in &old_lines[line..change.start] {
+                push_line(&mut diff, ' ', context);
+            }
+            for removed in &old_lines[change.start..change.end] {
+                push_line(&mut diff, '-', removed);
+            }
+            for added in &change.lines {
+                push_line(&mut diff, '+', added);
+            }
+            line = change.end;
+        }
+        for context in &old_lines[line..to] {
+            push_line(&mut diff, ' ', context);
+        }
+        delta += group_delta;
+    }
+    diff
+}
+
+/// The text edits of `edit` by file, whether given as `changes` or as `documentChanges`.
+/// File operations are not previewed.
+pub fn text_edits(edit: WorkspaceEdit) -> HashMap<Url, Vec<TextEdit>> {
+    let mut changes = edit.changes.unwrap_or_default();
+    let document_edits = match edit.document_changes {
+        Some(DocumentChanges::Edits(edits)) => edits,
+        Some(DocumentChanges::Operations(operations)) => operations
+            .into_iter()
+            .filter_map(|operation| match operation {
+                DocumentChangeOperation::Edit(edit) => Some(edit),
+                DocumentChangeOperation::Op(_) => None,
+            })
+            .collect(),
+        None => vec![],
+    };
+    for document_edit in document_edits {
+        changes
+            .entry(document_edit.text_document.uri)
+            .or_default()
+            .extend(document_edit.edits.into_iter().map(|edit| match edit {
+                OneOf::Left(edit) => edit,
+                OneOf::Right(annotated) => annotated.text_edit,
+            }));
+    }
+    changes
+}
+
+/// Preview of applying the edits of `files`, which are shown in path order.
+pub fn preview(mut files: Vec<PreviewFile>) -> Result<EditPreview, EditError> {
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    let mut preview = EditPreview::default();
+    for file in files {
+        let builder = EditBuilder::with_edits(file.text, file.edits)?;
+        let edits = builder.clone().build();
+        let diff = unified_diff(&file.path, file.text, &builder.apply());
+        if diff.is_empty() {
+            continue;
+        }
+        preview.diff.push_str(&diff);
+        preview.files += 1;
+        preview.edits += edits.len();
+    }
+    Ok(preview)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower_lsp::lsp_types::{Position, Range};
+
+    const SOURCE: &str = "\
+contract Vault {
+    uint256 total;
+
+    function deposit(uint256 amount) public {
+        total += amount;
+    }
+
+    function balance() public view returns (uint256) {
+        return total;
+    }
+}";
+
+    fn rename(text: &str, from: &str, to: &str) -> Vec<TextEdit> {
+        let mut edits = EditBuilder::new(text);
+        for (start, _) in text.match_indices(from) {
+            edits.replace(start, start + from.len(), to).unwrap();
+        }
+        edits.build()
+    }
+
+    #[test]
+    fn test_unified_diff_of_a_rename() {
+        let edits = rename(SOURCE, "total", "supply");
+        let preview = preview(vec![PreviewFile {
+            path: "src/Vault.sol".to_string(),
+            text: SOURCE,
+            edits: &edits,
+        }])
+        .unwrap();
+        assert_eq!(preview.files, 1);
+        assert_eq!(preview.edits, 3);
+        assert_eq!(
+            preview.diff.lines().collect::<Vec<_>>(),
+            [
+                "--- a/src/Vault.sol",
+                "+++ b/src/Vault.sol",
+                "@@ -1,11 +1,11 @@",
+                " contract Vault {",
+                "-    uint256 total;",
+                "+    uint256 supply;",
+                " ",
+                "     function deposit(uint256 amount) public {",
+                "-        total += amount;",
+                "+        supply += amount;",
+                "     }",
+                " ",
+                "     function balance() public view returns (uint256) {",
+                "-        return total;",
+                "+        return supply;",
+                "     }",
+                " }",
+                "\\ No newline at end of file",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_distant_changes_get_separate_hunks() {
+        let old: String = (1..=20).map(|i| format!("line {i}\n")).collect();
+        let new = old
+            .replace("line 2\n", "line two\n")
+            .replace("line 18\n", "");
+        assert_eq!(
+            unified_diff("a.sol", &old, &new),
+            "\
+--- a/a.sol
++++ b/a.sol
+@@ -1,5 +1,5 @@
+ line 1
+-line 2
++line two
+ line 3
+ line 4
+ line 5
+@@ -15,6 +15,5 @@
+ line 15
+ line 16
+ line 17
+-line 18
+ line 19
+ line 20
+"
+        );
+        assert_eq!(unified_diff("a.sol", &old, &old), "");
+    }
+
+    #[test]
+    fn test_overlapping_edits_fail_the_preview() {
+        let edit = |start: u32, end: u32| TextEdit {
+            range: Range::new(Position::new(1, start), Position::new(1, end)),
+            new_text: "x".to_string(),
+        };
+        let edits = [edit(4, 11), edit(8, 18)];
+        let result = preview(vec![PreviewFile {
+            path: "src/Vault.sol".to_string(),
+            text: SOURCE,
+            edits: &edits,
+        }]);
+        assert!(matches!(result, Err(EditError::Overlap { .. })));
+    }
+
+    #[test]
+    fn test_text_edits_of_document_changes() {
+        let uri = Url::from_file_path("/project/src/Vault.sol").unwrap();
+        let edits = rename(SOURCE, "total", "supply");
+        let edit = WorkspaceEdit {
+            document_changes: Some(DocumentChanges::Edits(vec![
+                tower_lsp::lsp_types::TextDocumentEdit {
+                    text_document: tower_lsp::lsp_types::OptionalVersionedTextDocumentIdentifier {
+                        uri: uri.clone(),
+                        version: Some(3),
+                    },
+                    edits: edits.iter().cloned().map(OneOf::Left).collect(),
+                },
+            ])),
+            ..Default::default()
+        };
+        assert_eq!(text_edits(edit), HashMap::from([(uri, edits)]));
+    }
+}
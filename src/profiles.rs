@@ -0,0 +1,139 @@
+//! Which Foundry profile the server compiles with, and its compiler settings.
+//!
+//! Forge picks the profile from `FOUNDRY_PROFILE`, so a terminal exporting
+//! `FOUNDRY_PROFILE=ci` and an editor started without it compile with different settings
+//! and report different diagnostics. `forge-lsp/status` returns the profile and compiler settings of
+//! each project as the server sees them, and hovering a `[profile.*]` header of
+//! `foundry.toml` shows the settings the profile compiles with.
+
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use tower_lsp::lsp_types::{Hover, HoverContents, MarkupContent, MarkupKind, Position, Range};
+
+use crate::project::{self, CompilerSettings, ProjectConfig};
+
+/// Name of the custom request.
+pub const STATUS_METHOD: &str = "forge-lsp/status";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProjectStatus {
+    pub root: PathBuf,
+    pub compiler: CompilerSettings,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerStatus {
+    /// `FOUNDRY_PROFILE` of the server process, if set.
+    pub foundry_profile: Option<String>,
+    pub projects: Vec<ProjectStatus>,
+}
+
+/// The status of the projects at `roots`.
+pub fn status(roots: &[PathBuf]) -> ServerStatus {
+    ServerStatus {
+        foundry_profile: std::env::var("FOUNDRY_PROFILE").ok(),
+        projects: roots
+            .iter()
+            .map(|root| ProjectStatus {
+                root: root.clone(),
+                compiler: ProjectConfig::load(root).compiler,
+            })
+            .collect(),
+    }
+}
+
+/// The profile named by a `[profile.<name>]` or `[profile.<name>.<table>]` header.
+fn profile_header(line: &str) -> Option<&str> {
+    let table = line.trim().strip_prefix('[')?.split(']').next()?;
+    let name = table.trim().strip_prefix("profile.")?;
+    let name = name.split('.').next()?.trim().trim_matches('"');
+    (!name.is_empty()).then_some(name)
+}
+
+/// The settings as a Markdown list.
+fn to_markdown(settings: &CompilerSettings, active: &str) -> String {
+    let status = if settings.profile == active {
+        "active for the language server".to_string()
+    } else {
+        format!("the language server compiles with `{active}`")
+    };
+    let optimizer = if settings.optimizer {
+        format!("enabled, {} runs", settings.optimizer_runs)
+    } else {
+        "disabled".to_string()
+    };
+    let value = |value: &Option<String>, unset: &str| match value {
+        Some(value) => format!("`{value}`"),
+        None => unset.to_string(),
+    };
+    [
+        format!("**Profile `{}`** ({status})", settings.profile),
+        String::new(),
+        format!(
+            "- solc: {}",
+            value(&settings.solc, "detected from the pragmas")
+        ),
+        format!("- optimizer: {optimizer}"),
+        format!("- via IR: {}", if settings.via_ir { "yes" } else { "no" }),
+        format!(
+            "- EVM version: {}",
+            value(&settings.evm_version, "forge's default")
+        ),
+    ]
+    .join("\n")
+}
+
+/// Hover on a profile header of the `foundry.toml` of the project at `root`, whose text
+/// is `source`.
+pub fn profile_hover(source: &str, position: Position, root: &Path) -> Option<Hover> {
+    let line = source.lines().nth(position.line as usize)?;
+    let name = profile_header(line)?;
+    let settings = CompilerSettings::load(root, name);
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: to_markdown(&settings, &project::active_profile()),
+        }),
+        range: Some(Range::new(
+            Position::new(position.line, 0),
+            Position::new(position.line, line.len() as u32),
+        )),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile_headers() {
+        assert_eq!(profile_header("[profile.default]"), Some("default"));
+        assert_eq!(profile_header("  [profile.ci.fuzz] # runs"), Some("ci"));
+        assert_eq!(profile_header("[profile.\"ci\"]"), Some("ci"));
+        assert_eq!(profile_header("[rpc_endpoints]"), None);
+        assert_eq!(profile_header("[profile.]"), None);
+    }
+
+    #[test]
+    fn test_profile_hover() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = "[profile.default]\noptimizer = true\n\n[profile.ci]\nvia_ir = true\n";
+        std::fs::write(dir.path().join("foundry.toml"), source).unwrap();
+
+        let hover = profile_hover(source, Position::new(3, 3), dir.path()).unwrap();
+        let HoverContents::Markup(markup) = hover.contents else {
+            panic!("expected markup");
+        };
+        assert!(markup.value.starts_with("**Profile `ci`**"));
+        assert!(markup.value.contains("- optimizer: enabled, 200 runs"));
+        assert!(markup.value.contains("- via IR: yes"));
+        assert!(markup.value.contains("- solc: detected from the pragmas"));
+        assert!(profile_hover(source, Position::new(1, 0), dir.path()).is_none());
+
+        let status = status(&[dir.path().to_path_buf()]);
+        assert_eq!(status.projects.len(), 1);
+        assert!(status.projects[0].compiler.optimizer);
+    }
+}
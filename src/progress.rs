@@ -0,0 +1,85 @@
+//! `window/workDoneProgress` reporting for long-running server work.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use tower_lsp::{
+    Client,
+    lsp_types::{
+        NumberOrString, ProgressParams, ProgressParamsValue, WorkDoneProgress,
+        WorkDoneProgressBegin, WorkDoneProgressCreateParams, WorkDoneProgressEnd,
+        WorkDoneProgressReport, notification::Progress, request::WorkDoneProgressCreate,
+    },
+};
+
+static NEXT_TOKEN: AtomicU64 = AtomicU64::new(0);
+
+/// A progress notification sequence shown by the client. When the client does not support
+/// server-initiated progress every call is a no-op.
+pub struct ProgressReporter {
+    client: Client,
+    token: Option<NumberOrString>,
+}
+
+impl ProgressReporter {
+    /// Create a progress token and show `title`.
+    pub async fn begin(client: &Client, title: &str) -> Self {
+        let id = NEXT_TOKEN.fetch_add(1, Ordering::Relaxed);
+        let token = NumberOrString::String(format!("forge-lsp/progress/{id}"));
+        let created = client
+            .send_request::<WorkDoneProgressCreate>(WorkDoneProgressCreateParams {
+                token: token.clone(),
+            })
+            .await
+            .is_ok();
+
+        let reporter = Self {
+            client: client.clone(),
+            token: created.then_some(token),
+        };
+        reporter
+            .send(WorkDoneProgress::Begin(WorkDoneProgressBegin {
+                title: title.to_string(),
+                cancellable: Some(false),
+                percentage: Some(0),
+                ..WorkDoneProgressBegin::default()
+            }))
+            .await;
+        reporter
+    }
+
+    async fn send(&self, progress: WorkDoneProgress) {
+        let Some(token) = self.token.clone() else {
+            return;
+        };
+        self.client
+            .send_notification::<Progress>(ProgressParams {
+                token,
+                value: ProgressParamsValue::WorkDone(progress),
+            })
+            .await;
+    }
+
+    pub async fn report(&self, message: impl Into<String>, percentage: u32) {
+        self.send(WorkDoneProgress::Report(WorkDoneProgressReport {
+            message: Some(message.into()),
+            percentage: Some(percentage.min(100)),
+            ..WorkDoneProgressReport::default()
+        }))
+        .await;
+    }
+
+    /// Report `message` for work whose completion can't be estimated.
+    pub async fn message(&self, message: impl Into<String>) {
+        self.send(WorkDoneProgress::Report(WorkDoneProgressReport {
+            message: Some(message.into()),
+            ..WorkDoneProgressReport::default()
+        }))
+        .await;
+    }
+
+    pub async fn end(self, message: impl Into<String>) {
+        self.send(WorkDoneProgress::End(WorkDoneProgressEnd {
+            message: Some(message.into()),
+        }))
+        .await;
+    }
+}
@@ -0,0 +1,636 @@
+//! Foundry project layout: the source, script and library directories, the import remappings,
+//! the RPC endpoints, the compiler settings and the formatter indentation of a project, read
+//! from `foundry.toml` and `remappings.txt`, and import resolution with them.
+
+use std::path::{Component, Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::{Location, Position, Range, Url};
+
+use crate::build_info::find_project_root;
+
+/// A remapping `context:prefix=path`: imports starting with `prefix`, from files under
+/// `context`, resolve to `path` instead.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Remapping {
+    pub context: Option<String>,
+    pub prefix: String,
+    pub path: String,
+}
+
+impl Remapping {
+    /// Parse a remapping as written in `foundry.toml` or `remappings.txt`.
+    pub fn parse(remapping: &str) -> Option<Self> {
+        let (left, path) = remapping.trim().split_once('=')?;
+        let (context, prefix) = match left.split_once(':') {
+            Some((context, prefix)) => (Some(context.to_string()), prefix),
+            None => (None, left),
+        };
+        (!prefix.is_empty()).then(|| Self {
+            context: context.filter(|context| !context.is_empty()),
+            prefix: prefix.to_string(),
+            path: path.to_string(),
+        })
+    }
+}
+
+/// The settings of a `[profile.*]` table of `foundry.toml` used here.
+#[derive(Debug, Default, Deserialize)]
+struct Profile {
+    src: Option<String>,
+    script: Option<String>,
+    libs: Option<Vec<String>>,
+    remappings: Option<Vec<String>>,
+    cache_path: Option<String>,
+    out: Option<String>,
+    #[serde(alias = "solc_version")]
+    solc: Option<String>,
+    optimizer: Option<bool>,
+    optimizer_runs: Option<u64>,
+    via_ir: Option<bool>,
+    evm_version: Option<String>,
+    fmt: Option<FmtTable>,
+}
+
+/// The settings of a `[fmt]` table used here.
+#[derive(Debug, Clone, Default, Deserialize)]
+struct FmtTable {
+    tab_width: Option<usize>,
+    /// `space` or `tab`.
+    style: Option<String>,
+}
+
+impl Profile {
+    /// These settings, with those left unset taken from `base`.
+    fn or(self, base: Self) -> Self {
+        Self {
+            src: self.src.or(base.src),
+            script: self.script.or(base.script),
+            libs: self.libs.or(base.libs),
+            remappings: self.remappings.or(base.remappings),
+            cache_path: self.cache_path.or(base.cache_path),
+            out: self.out.or(base.out),
+            solc: self.solc.or(base.solc),
+            optimizer: self.optimizer.or(base.optimizer),
+            optimizer_runs: self.optimizer_runs.or(base.optimizer_runs),
+            via_ir: self.via_ir.or(base.via_ir),
+            evm_version: self.evm_version.or(base.evm_version),
+            fmt: self.fmt.or(base.fmt),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct FoundryToml {
+    #[serde(default)]
+    profile: std::collections::BTreeMap<String, Profile>,
+    /// Endpoints are URLs or tables with an `endpoint` key; only their names are used.
+    #[serde(default)]
+    rpc_endpoints: std::collections::BTreeMap<String, toml::Value>,
+    /// The top-level `[fmt]` table, for profiles without one of their own.
+    fmt: Option<FmtTable>,
+}
+
+impl FoundryToml {
+    fn read(root: &Path) -> Self {
+        std::fs::read_to_string(root.join("foundry.toml"))
+            .ok()
+            .and_then(|text| toml::from_str(&text).ok())
+            .unwrap_or_default()
+    }
+
+    /// The settings of the profile `name` over those of the default profile.
+    fn take_profile(&mut self, name: &str) -> Profile {
+        let default = self.profile.remove("default").unwrap_or_default();
+        match self.profile.remove(name) {
+            Some(profile) => profile.or(default),
+            None => default,
+        }
+    }
+}
+
+/// Name of the profile forge runs with: `FOUNDRY_PROFILE`, or `default`.
+pub fn active_profile() -> String {
+    std::env::var("FOUNDRY_PROFILE")
+        .ok()
+        .filter(|name| !name.is_empty())
+        .unwrap_or("default".to_string())
+}
+
+/// The settings of a profile that change what solc compiles, and so the diagnostics.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompilerSettings {
+    pub profile: String,
+    /// Pinned solc version or path, `None` when forge detects it from the pragmas.
+    pub solc: Option<String>,
+    pub optimizer: bool,
+    pub optimizer_runs: u64,
+    pub via_ir: bool,
+    /// `None` for forge's default EVM version.
+    pub evm_version: Option<String>,
+}
+
+impl CompilerSettings {
+    fn new(name: &str, profile: &Profile) -> Self {
+        Self {
+            profile: name.to_string(),
+            solc: profile.solc.clone(),
+            optimizer: profile.optimizer.unwrap_or(false),
+            optimizer_runs: profile.optimizer_runs.unwrap_or(200),
+            via_ir: profile.via_ir.unwrap_or(false),
+            evm_version: profile.evm_version.clone(),
+        }
+    }
+
+    /// The compiler settings of the profile `name` of the project at `root`.
+    pub fn load(root: &Path, name: &str) -> Self {
+        Self::new(name, &FoundryToml::read(root).take_profile(name))
+    }
+}
+
+/// How `forge fmt` indents a project's sources.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FmtSettings {
+    /// Columns of one indentation level.
+    pub tab_width: usize,
+    /// Whether levels are indented with tabs instead of spaces.
+    pub tabs: bool,
+}
+
+impl Default for FmtSettings {
+    fn default() -> Self {
+        Self {
+            tab_width: 4,
+            tabs: false,
+        }
+    }
+}
+
+impl FmtSettings {
+    fn new(table: Option<FmtTable>) -> Self {
+        let table = table.unwrap_or_default();
+        Self {
+            tab_width: table.tab_width.unwrap_or(4),
+            tabs: table.style.as_deref() == Some("tab"),
+        }
+    }
+
+    /// The leading whitespace of `level` indentation levels.
+    pub fn indent(&self, level: usize) -> String {
+        if self.tabs {
+            "\t".repeat(level)
+        } else {
+            " ".repeat(level * self.tab_width)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProjectConfig {
+    pub root: PathBuf,
+    /// Source directory, relative to the root.
+    pub src: String,
+    /// Script directory, relative to the root.
+    pub script: String,
+    /// Library directories, relative to the root.
+    pub libs: Vec<String>,
+    /// Cache directory of forge, relative to the root.
+    pub cache_path: String,
+    /// Artifacts directory of forge, relative to the root.
+    pub out: String,
+    /// Remappings in priority order: `foundry.toml`, `remappings.txt`, then one per library
+    /// in the library directories, as forge derives them.
+    pub remappings: Vec<Remapping>,
+    /// Names of the `[rpc_endpoints]`, sorted.
+    pub rpc_endpoints: Vec<String>,
+    /// Compiler settings of the active profile.
+    pub compiler: CompilerSettings,
+    /// Formatter indentation of the active profile.
+    pub fmt: FmtSettings,
+}
+
+impl ProjectConfig {
+    /// The configuration of the project containing `path`, if it is in one.
+    pub fn find(path: &Path) -> Option<Self> {
+        find_project_root(path).map(|root| Self::load(&root))
+    }
+
+    /// The configuration of the project at `root`. Settings of the profile named by
+    /// `FOUNDRY_PROFILE` override those of the default profile; a missing or invalid
+    /// `foundry.toml` gives forge's defaults.
+    pub fn load(root: &Path) -> Self {
+        let mut config = FoundryToml::read(root);
+        let name = active_profile();
+        let profile = config.take_profile(&name);
+        let compiler = CompilerSettings::new(&name, &profile);
+        let fmt = FmtSettings::new(profile.fmt.or(config.fmt.take()));
+
+        let src = profile.src.unwrap_or("src".to_string());
+        let script = profile.script.unwrap_or("script".to_string());
+        let libs = profile.libs.unwrap_or(vec!["lib".to_string()]);
+        let cache_path = profile.cache_path.unwrap_or("cache".to_string());
+        let out = profile.out.unwrap_or("out".to_string());
+        let mut remappings: Vec<Remapping> = profile
+            .remappings
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|remapping| Remapping::parse(remapping))
+            .collect();
+        if let Ok(text) = std::fs::read_to_string(root.join("remappings.txt")) {
+            remappings.extend(text.lines().filter_map(Remapping::parse));
+        }
+        remappings.extend(lib_remappings(root, &libs));
+
+        Self {
+            root: root.to_path_buf(),
+            src,
+            script,
+            libs,
+            cache_path,
+            out,
+            remappings,
+            rpc_endpoints: config.rpc_endpoints.into_keys().collect(),
+            compiler,
+            fmt,
+        }
+    }
+
+    /// Whether `path` is a Foundry script: it is under the script directory.
+    pub fn is_script(&self, path: &Path) -> bool {
+        path.strip_prefix(&self.root)
+            .is_ok_and(|relative| relative.starts_with(&self.script))
+    }
+
+    /// Whether `path` belongs to a dependency: it is under a library directory or a
+    /// `node_modules` directory of the project. Dependencies are read-only to the server.
+    pub fn is_dependency(&self, path: &Path) -> bool {
+        let Ok(relative) = path.strip_prefix(&self.root) else {
+            return false;
+        };
+        self.libs.iter().any(|lib| relative.starts_with(lib))
+            || relative
+                .components()
+                .any(|component| component.as_os_str() == "node_modules")
+    }
+
+    /// The file `import` refers to from `importer`, if it exists. Relative imports resolve
+    /// against the importing file; others through the longest matching remapping, then
+    /// against the root and the library directories.
+    pub fn resolve_import(&self, importer: &Path, import: &str) -> Option<PathBuf> {
+        if import.starts_with("./") || import.starts_with("../") {
+            let path = normalize(&importer.parent()?.join(import));
+            return path.is_file().then_some(path);
+        }
+
+        let remapped = self.remapping_for(importer, import).map(|remapping| {
+            self.root.join(format!(
+                "{}{}",
+                remapping.path,
+                &import[remapping.prefix.len()..]
+            ))
+        });
+
+        remapped
+            .into_iter()
+            .chain(std::iter::once(self.root.join(import)))
+            .chain(self.libs.iter().map(|lib| self.root.join(lib).join(import)))
+            .map(|path| normalize(&path))
+            .find(|path| path.is_file())
+    }
+
+    /// The remapping a non-relative `import` from `importer` goes through: the first of the
+    /// longest prefixes matching it whose context includes the importer.
+    pub fn remapping_for(&self, importer: &Path, import: &str) -> Option<&Remapping> {
+        let relative_importer = importer.strip_prefix(&self.root).unwrap_or(importer);
+        self.remappings
+            .iter()
+            .filter(|remapping| import.starts_with(&remapping.prefix))
+            .filter(|remapping| {
+                remapping
+                    .context
+                    .as_ref()
+                    .is_none_or(|context| relative_importer.starts_with(context))
+            })
+            .rev()
+            .max_by_key(|remapping| remapping.prefix.len())
+    }
+}
+
+/// `name/=lib/name/src/` for each library with a `src` directory, `name/=lib/name/` for the
+/// others.
+fn lib_remappings(root: &Path, libs: &[String]) -> Vec<Remapping> {
+    let mut remappings = Vec::new();
+    for lib in libs {
+        let Ok(entries) = std::fs::read_dir(root.join(lib)) else {
+            continue;
+        };
+        let mut names: Vec<String> = entries
+            .flatten()
+            .filter(|entry| entry.path().is_dir())
+            .filter_map(|entry| entry.file_name().to_str().map(str::to_string))
+            .collect();
+        names.sort();
+        for name in names {
+            let dir = format!("{}/{name}/", lib.trim_end_matches('/'));
+            let path = if root.join(&dir).join("src").is_dir() {
+                format!("{dir}src/")
+            } else {
+                dir
+            };
+            remappings.push(Remapping {
+                context: None,
+                prefix: format!("{name}/"),
+                path,
+            });
+        }
+    }
+    remappings
+}
+
+/// `path` with `.` and `..` components resolved lexically.
+pub(crate) fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+/// `to` relative to the directory `from`, both absolute, as an import path: starting with
+/// `./` or `../`.
+pub fn relative_path(from: &Path, to: &Path) -> Option<String> {
+    let from: Vec<Component> = from.components().collect();
+    let to: Vec<Component> = to.components().collect();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+    if common == 0 {
+        return None;
+    }
+    let mut relative = PathBuf::new();
+    for _ in common..from.len() {
+        relative.push("..");
+    }
+    relative.extend(&to[common..]);
+    let relative = relative.to_str()?.replace('\\', "/");
+    Some(if relative.starts_with("..") {
+        relative
+    } else {
+        format!("./{relative}")
+    })
+}
+
+/// The import path under `position`, when it is inside the string of an import directive.
+pub fn import_at(source: &str, position: Position) -> Option<&str> {
+    let line = source.lines().nth(position.line as usize)?;
+    let code = line.trim_start();
+    if !code.starts_with("import") && !code.starts_with('}') {
+        return None;
+    }
+    let column = position.character as usize;
+    let mut quotes = line.match_indices(['"', '\'']).map(|(i, _)| i);
+    while let (Some(open), Some(close)) = (quotes.next(), quotes.next()) {
+        if open < column && column <= close {
+            return Some(&line[open + 1..close]);
+        }
+    }
+    None
+}
+
+/// Location of the file imported by the import string under `position` of `path`.
+pub fn goto_import(path: &Path, source: &str, position: Position) -> Option<Location> {
+    let import = import_at(source, position)?;
+    let resolved = match ProjectConfig::find(path) {
+        Some(config) => config.resolve_import(path, import)?,
+        None => {
+            let resolved = normalize(&path.parent()?.join(import));
+            resolved.is_file().then_some(resolved)?
+        }
+    };
+    Some(Location {
+        uri: Url::from_file_path(resolved).ok()?,
+        range: Range::default(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn write(root: &Path, path: &str, contents: &str) {
+        let path = root.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    fn project() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        write(
+            root,
+            "foundry.toml",
+            r#"[profile.default]
+src = "contracts"
+libs = ["lib", "node_modules"]
+remappings = [
+    "@openzeppelin/=node_modules/@openzeppelin/",
+    "test/:forge-std/=lib/forge-std-fork/src/",
+]
+script = "scripts"
+
+[rpc_endpoints]
+sepolia = "${SEPOLIA_RPC_URL}"
+mainnet = { endpoint = "${MAINNET_RPC_URL}", retries = 3 }
+
+[fmt]
+tab_width = 2
+"#,
+        );
+        write(root, "remappings.txt", "solmate/=lib/solmate/src/\n");
+        write(
+            root,
+            "node_modules/@openzeppelin/contracts/token/ERC20/ERC20.sol",
+            "",
+        );
+        write(root, "lib/forge-std/src/Test.sol", "");
+        write(root, "lib/forge-std-fork/src/Test.sol", "");
+        write(root, "lib/solmate/src/tokens/ERC20.sol", "");
+        write(root, "lib/plain/Plain.sol", "");
+        write(root, "contracts/Vault.sol", "");
+        write(root, "contracts/utils/Math.sol", "");
+        dir
+    }
+
+    #[test]
+    fn test_remapping_parse() {
+        assert_eq!(
+            Remapping::parse("test/:forge-std/=lib/forge-std/src/"),
+            Some(Remapping {
+                context: Some("test/".to_string()),
+                prefix: "forge-std/".to_string(),
+                path: "lib/forge-std/src/".to_string(),
+            })
+        );
+        assert_eq!(Remapping::parse("no equals"), None);
+        assert_eq!(Remapping::parse("=lib/"), None);
+    }
+
+    #[test]
+    fn test_config_reads_foundry_toml_and_remappings_txt() {
+        let dir = project();
+        let config = ProjectConfig::find(&dir.path().join("contracts/Vault.sol")).unwrap();
+        assert_eq!(config.src, "contracts");
+        assert_eq!(config.script, "scripts");
+        assert_eq!(config.libs, ["lib", "node_modules"]);
+        assert_eq!(config.cache_path, "cache");
+        assert_eq!(config.out, "out");
+        assert_eq!(config.rpc_endpoints, ["mainnet", "sepolia"]);
+        assert_eq!(config.fmt.indent(2), "    ");
+        let prefixes: Vec<&str> = config
+            .remappings
+            .iter()
+            .map(|remapping| remapping.prefix.as_str())
+            .collect();
+        assert_eq!(prefixes[..3], ["@openzeppelin/", "forge-std/", "solmate/"]);
+        assert!(config.remappings.contains(&Remapping {
+            context: None,
+            prefix: "plain/".to_string(),
+            path: "lib/plain/".to_string(),
+        }));
+    }
+
+    #[test]
+    fn test_compiler_settings_of_profiles() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        write(
+            root,
+            "foundry.toml",
+            r#"[profile.default]
+solc_version = "0.8.24"
+optimizer = true
+evm_version = "cancun"
+
+[profile.ci]
+optimizer_runs = 10000
+via_ir = true
+
+[profile.ci.fuzz]
+runs = 5000
+"#,
+        );
+
+        let ci = CompilerSettings::load(root, "ci");
+        assert_eq!(
+            ci,
+            CompilerSettings {
+                profile: "ci".to_string(),
+                solc: Some("0.8.24".to_string()),
+                optimizer: true,
+                optimizer_runs: 10000,
+                via_ir: true,
+                evm_version: Some("cancun".to_string()),
+            }
+        );
+        let default = CompilerSettings::load(root, "default");
+        assert_eq!((default.optimizer_runs, default.via_ir), (200, false));
+        // A missing profile compiles with the default one
+        assert_eq!(
+            CompilerSettings::load(root, "missing").solc.as_deref(),
+            Some("0.8.24")
+        );
+        assert!(!CompilerSettings::load(&root.join("none"), "default").optimizer);
+    }
+
+    #[test]
+    fn test_dependencies_are_under_libs_or_node_modules() {
+        let dir = project();
+        let root = dir.path();
+        let config = ProjectConfig::load(root);
+        assert!(config.is_dependency(&root.join("lib/forge-std/src/Test.sol")));
+        assert!(config.is_dependency(&root.join("node_modules/@openzeppelin/contracts/a.sol")));
+        assert!(config.is_dependency(&root.join("packages/x/node_modules/y/a.sol")));
+        assert!(!config.is_dependency(&root.join("contracts/Vault.sol")));
+        assert!(!config.is_dependency(Path::new("/elsewhere/lib/a.sol")));
+        assert!(config.is_script(&root.join("scripts/Deploy.s.sol")));
+        assert!(!config.is_script(&root.join("contracts/Vault.sol")));
+    }
+
+    #[test]
+    fn test_resolve_import() {
+        let dir = project();
+        let root = dir.path();
+        let config = ProjectConfig::load(root);
+        let vault = root.join("contracts/Vault.sol");
+        let resolve = |importer: &Path, import: &str| {
+            config
+                .resolve_import(importer, import)
+                .map(|path| path.strip_prefix(root).unwrap().to_path_buf())
+        };
+
+        assert_eq!(
+            resolve(&vault, "@openzeppelin/contracts/token/ERC20/ERC20.sol"),
+            Some("node_modules/@openzeppelin/contracts/token/ERC20/ERC20.sol".into())
+        );
+        assert_eq!(
+            resolve(&vault, "solmate/tokens/ERC20.sol"),
+            Some("lib/solmate/src/tokens/ERC20.sol".into())
+        );
+        // The contextual remapping only applies to files under `test/`
+        assert_eq!(
+            resolve(&vault, "forge-std/Test.sol"),
+            Some("lib/forge-std/src/Test.sol".into())
+        );
+        assert_eq!(
+            resolve(&root.join("test/Vault.t.sol"), "forge-std/Test.sol"),
+            Some("lib/forge-std-fork/src/Test.sol".into())
+        );
+        assert_eq!(
+            resolve(&root.join("contracts/utils/Math.sol"), "../Vault.sol"),
+            Some("contracts/Vault.sol".into())
+        );
+        assert_eq!(
+            resolve(&vault, "contracts/utils/Math.sol"),
+            Some("contracts/utils/Math.sol".into())
+        );
+        assert_eq!(resolve(&vault, "./Missing.sol"), None);
+    }
+
+    #[test]
+    fn test_goto_import() {
+        let dir = project();
+        let root = dir.path();
+        let source = "\
+import {ERC20} from \"@openzeppelin/contracts/token/ERC20/ERC20.sol\";
+import './utils/Math.sol';
+contract Vault {}
+";
+        assert_eq!(
+            import_at(source, Position::new(0, 25)),
+            Some("@openzeppelin/contracts/token/ERC20/ERC20.sol")
+        );
+        assert_eq!(import_at(source, Position::new(0, 9)), None);
+        assert_eq!(import_at(source, Position::new(2, 3)), None);
+
+        let vault = root.join("contracts/Vault.sol");
+        let location = goto_import(&vault, source, Position::new(0, 25)).unwrap();
+        assert_eq!(
+            location.uri,
+            Url::from_file_path(
+                root.join("node_modules/@openzeppelin/contracts/token/ERC20/ERC20.sol")
+            )
+            .unwrap()
+        );
+        let location = goto_import(&vault, source, Position::new(1, 12)).unwrap();
+        assert_eq!(
+            location.uri,
+            Url::from_file_path(root.join("contracts/utils/Math.sol")).unwrap()
+        );
+    }
+}
@@ -1,8 +1,16 @@
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::{HashMap, HashSet};
-use tower_lsp::lsp_types::{Location, Position, Range, Url};
+use tower_lsp::lsp_types::{
+    DocumentHighlight, DocumentHighlightKind, LinkedEditingRanges, Location, Position, Range, Url,
+};
 
-use crate::goto::{NodeInfo, bytes_to_pos, cache_ids, pos_to_bytes};
+use crate::{
+    ast::parse_src,
+    documents::Snapshot,
+    goto::{Access, NodeInfo, bytes_to_pos, cache_ids, pos_to_bytes},
+    paths,
+};
 
 /// Build a map of all reference relationships in the AST
 /// Returns a HashMap where keys are node IDs and values are vectors of related node IDs
@@ -51,11 +59,12 @@ pub fn byte_to_id(
     refs.keys().min().map(|min_diff| refs[min_diff])
 }
 
-/// Convert a node ID to a Location for LSP
+/// Convert a node ID to a Location for LSP, reading its file from `documents`
 pub fn id_to_location(
     nodes: &HashMap<String, HashMap<u64, NodeInfo>>,
     id_to_path: &HashMap<String, String>,
     node_id: u64,
+    documents: &Snapshot,
 ) -> Option<Location> {
     // Find the file containing this node
     let mut target_node: Option<&NodeInfo> = None;
@@ -90,17 +99,13 @@ pub fn id_to_location(
     let file_path = id_to_path.get(file_id)?;
 
     // Read the file to convert byte positions to line/column
-    let absolute_path = if std::path::Path::new(file_path).is_absolute() {
-        std::path::PathBuf::from(file_path)
-    } else {
-        std::env::current_dir().ok()?.join(file_path)
-    };
+    let absolute_path = paths::resolve_source_path(file_path)?;
 
-    let source_bytes = std::fs::read(&absolute_path).ok()?;
+    let source_bytes = documents.read(&absolute_path)?;
     let start_pos = bytes_to_pos(&source_bytes, byte_offset)?;
     let end_pos = bytes_to_pos(&source_bytes, byte_offset + length)?;
 
-    let uri = Url::from_file_path(&absolute_path).ok()?;
+    let uri = paths::path_to_uri(&absolute_path)?;
 
     Some(Location {
         uri,
@@ -111,120 +116,438 @@ pub fn id_to_location(
     })
 }
 
+/// An `import {Foo as Bar} from "..."` alias. `Bar` refers to `Foo`, but only within the
+/// importing file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportAlias {
+    pub local: String,
+    /// Id of the aliased declaration.
+    pub declaration: u64,
+    /// Normalized absolute path of the importing file.
+    pub file: String,
+    /// `src` of the alias name after `as`, when solc reports it.
+    pub name_location: Option<String>,
+}
+
+/// Collect the symbol aliases of every import directive.
+pub fn import_aliases(sources: &Value) -> Vec<ImportAlias> {
+    let mut aliases = Vec::new();
+    let Some(sources) = sources.as_object() else {
+        return aliases;
+    };
+
+    for (path, contents) in sources {
+        let Some(ast) = contents
+            .get(0)
+            .and_then(|content| content.get("source_file"))
+            .and_then(|source_file| source_file.get("ast"))
+        else {
+            continue;
+        };
+        let file = paths::normalize_path(
+            ast.get("absolutePath")
+                .and_then(|v| v.as_str())
+                .unwrap_or(path),
+        );
+
+        let imports = ast
+            .get("nodes")
+            .and_then(|v| v.as_array())
+            .into_iter()
+            .flatten()
+            .filter(|node| {
+                node.get("nodeType").and_then(|v| v.as_str()) == Some("ImportDirective")
+            });
+        for import in imports {
+            let symbol_aliases = import
+                .get("symbolAliases")
+                .and_then(|v| v.as_array())
+                .into_iter()
+                .flatten();
+            aliases.extend(symbol_aliases.filter_map(|alias| {
+                Some(ImportAlias {
+                    local: alias.get("local")?.as_str()?.to_string(),
+                    declaration: alias
+                        .get("foreign")?
+                        .get("referencedDeclaration")?
+                        .as_u64()?,
+                    file: file.clone(),
+                    name_location: alias
+                        .get("nameLocation")
+                        .and_then(|v| v.as_str())
+                        .map(|s| s.to_string()),
+                })
+            }));
+        }
+    }
+
+    aliases
+}
+
+/// Node and reference lookups over an AST, built once per request.
+pub struct ReferenceIndex {
+    pub nodes: HashMap<String, HashMap<u64, NodeInfo>>,
+    pub path_to_abs: HashMap<String, String>,
+    pub id_to_path: HashMap<String, String>,
+    pub all_refs: HashMap<u64, Vec<u64>>,
+    pub aliases: Vec<ImportAlias>,
+}
+
+impl ReferenceIndex {
+    pub fn new(ast_data: &Value) -> Option<Self> {
+        let sources = ast_data.get("sources")?;
+        let id_to_path = ast_data
+            .get("build_infos")?
+            .as_array()?
+            .first()?
+            .get("source_id_to_path")?
+            .as_object()?
+            .iter()
+            .map(|(k, v)| (k.clone(), v.as_str().unwrap_or("").to_string()))
+            .collect();
+
+        let (nodes, path_to_abs) = cache_ids(sources);
+        let all_refs = all_references(&nodes);
+        Some(Self {
+            nodes,
+            path_to_abs,
+            id_to_path,
+            all_refs,
+            aliases: import_aliases(sources),
+        })
+    }
+
+    /// The declaration targeted at `position`: the declaration a usage refers to, or the
+    /// node itself.
+    pub fn target_at(
+        &self,
+        file_uri: &Url,
+        position: Position,
+        source_bytes: &[u8],
+    ) -> Option<u64> {
+        let abs_path = paths::lookup_path(&self.path_to_abs, file_uri.as_str())?;
+        let byte_position = pos_to_bytes(source_bytes, position);
+        let node_id = byte_to_id(&self.nodes, abs_path, byte_position)?;
+
+        // If this node references a declaration, use the declaration as the target
+        // This ensures we get ALL references to the same symbol
+        let node_info = self.nodes.get(abs_path)?.get(&node_id);
+        Some(
+            node_info
+                .and_then(|node_info| node_info.referenced_declaration)
+                .unwrap_or(node_id),
+        )
+    }
+
+    /// Locations of the declaration `target_node_id` and of every reference to it, with
+    /// positions in the texts of `documents`.
+    pub fn locations(&self, target_node_id: u64, documents: &Snapshot) -> Vec<Location> {
+        self.located(target_node_id, documents)
+            .into_iter()
+            .map(|(_, location)| location)
+            .collect()
+    }
+
+    /// [`Self::locations`], with the id of the node at each location.
+    pub fn located(&self, target_node_id: u64, documents: &Snapshot) -> Vec<(u64, Location)> {
+        // Always include the target node itself (the declaration)
+        let mut results = HashSet::new();
+        results.insert(target_node_id);
+        if let Some(refs) = self.all_refs.get(&target_node_id) {
+            results.extend(refs.iter().copied());
+        }
+
+        // Convert node IDs to locations, dropping duplicates
+        let mut seen = HashSet::new();
+        results
+            .into_iter()
+            .filter_map(|id| {
+                let location = id_to_location(&self.nodes, &self.id_to_path, id, documents)?;
+                Some((id, location))
+            })
+            .filter(|(_, location)| {
+                seen.insert((
+                    location.uri.clone(),
+                    location.range.start.line,
+                    location.range.start.character,
+                    location.range.end.line,
+                    location.range.end.character,
+                ))
+            })
+            .collect()
+    }
+}
+
 /// Find all references to a symbol at the given position
 pub fn goto_references(
     ast_data: &Value,
     file_uri: &Url,
     position: Position,
     source_bytes: &[u8],
+    documents: &Snapshot,
 ) -> Vec<Location> {
-    let sources = match ast_data.get("sources") {
-        Some(s) => s,
-        None => return vec![],
-    };
-
-    let build_infos = match ast_data.get("build_infos").and_then(|v| v.as_array()) {
-        Some(infos) => infos,
-        None => return vec![],
-    };
-
-    let first_build_info = match build_infos.first() {
-        Some(info) => info,
-        None => return vec![],
+    let Some(index) = ReferenceIndex::new(ast_data) else {
+        return vec![];
     };
+    match index.target_at(file_uri, position, source_bytes) {
+        Some(target_node_id) => index.locations(target_node_id, documents),
+        None => vec![],
+    }
+}
 
-    let id_to_path = match first_build_info
-        .get("source_id_to_path")
-        .and_then(|v| v.as_object())
-    {
-        Some(map) => map,
-        None => return vec![],
-    };
+impl ReferenceIndex {
+    /// Highlights of the symbol at `position` within its file: the declaration as text,
+    /// reads and calls as reads, and writes as writes. Ranges are computed against
+    /// `source_bytes`, so no file is read.
+    pub fn highlights(
+        &self,
+        file_uri: &Url,
+        position: Position,
+        source_bytes: &[u8],
+    ) -> Vec<DocumentHighlight> {
+        let Some(target) = self.target_at(file_uri, position, source_bytes) else {
+            return vec![];
+        };
+        let Some(file_nodes) = paths::lookup_path(&self.path_to_abs, file_uri.as_str())
+            .and_then(|abs_path| self.nodes.get(abs_path))
+        else {
+            return vec![];
+        };
 
-    let id_to_path_map: HashMap<String, String> = id_to_path
-        .iter()
-        .map(|(k, v)| (k.clone(), v.as_str().unwrap_or("").to_string()))
-        .collect();
+        let mut ids: Vec<u64> = self.all_refs.get(&target).cloned().unwrap_or_default();
+        ids.push(target);
+        ids.sort_unstable();
+        ids.dedup();
+        let mut highlights: Vec<DocumentHighlight> = ids
+            .into_iter()
+            .filter_map(|id| {
+                let node = file_nodes.get(&id)?;
+                let (start, length, _) =
+                    parse_src(node.name_location.as_deref().unwrap_or(&node.src))?;
+                Some(DocumentHighlight {
+                    range: Range::new(
+                        bytes_to_pos(source_bytes, start)?,
+                        bytes_to_pos(source_bytes, start + length)?,
+                    ),
+                    kind: Some(match self.kind(id, target) {
+                        ReferenceKind::Declaration => DocumentHighlightKind::TEXT,
+                        ReferenceKind::Write => DocumentHighlightKind::WRITE,
+                        ReferenceKind::Read | ReferenceKind::Call => DocumentHighlightKind::READ,
+                    }),
+                })
+            })
+            .collect();
+        highlights.sort_by_key(|highlight| highlight.range.start);
+        highlights.dedup_by_key(|highlight| highlight.range);
+        highlights
+    }
+}
 
-    let (nodes, path_to_abs) = cache_ids(sources);
-    let all_refs = all_references(&nodes);
+/// [`ReferenceIndex::highlights`] over `ast_data`.
+pub fn document_highlights(
+    ast_data: &Value,
+    file_uri: &Url,
+    position: Position,
+    source_bytes: &[u8],
+) -> Vec<DocumentHighlight> {
+    ReferenceIndex::new(ast_data)
+        .map(|index| index.highlights(file_uri, position, source_bytes))
+        .unwrap_or_default()
+}
 
-    // Get the file path and convert to absolute path
-    let path = match file_uri.to_file_path() {
-        Ok(p) => p,
-        Err(_) => return vec![],
-    };
+/// Characters of a Solidity identifier, for clients to tell which edits keep the linked
+/// ranges linked.
+const IDENTIFIER_PATTERN: &str = "[a-zA-Z$_][a-zA-Z0-9$_]*";
+
+impl ReferenceIndex {
+    /// The ranges of the symbol at `position` to edit together, if every occurrence is in
+    /// `file_uri`: renaming a local variable, a parameter or a private helper in place
+    /// renames it throughout. Symbols used from other files are left to rename, as are
+    /// occurrences that aren't the bare name, like member accesses, or that moved since
+    /// the AST was built.
+    pub fn linked_editing_ranges(
+        &self,
+        file_uri: &Url,
+        position: Position,
+        source_bytes: &[u8],
+    ) -> Option<LinkedEditingRanges> {
+        let target = self.target_at(file_uri, position, source_bytes)?;
+        let file_nodes = paths::lookup_path(&self.path_to_abs, file_uri.as_str())
+            .and_then(|abs_path| self.nodes.get(abs_path))?;
+        let (start, length, _) = parse_src(file_nodes.get(&target)?.name_location.as_deref()?)?;
+        let name = source_bytes.get(start..start + length)?;
+        if name.is_empty() {
+            return None;
+        }
 
-    let path_str = match path.to_str() {
-        Some(s) => s,
-        None => return vec![],
-    };
+        let mut ids: Vec<u64> = self.all_refs.get(&target).cloned().unwrap_or_default();
+        ids.push(target);
+        ids.sort_unstable();
+        ids.dedup();
+        let mut ranges = Vec::new();
+        for id in ids {
+            let node = file_nodes.get(&id)?;
+            let (start, length, _) = parse_src(node.name_location.as_deref().unwrap_or(&node.src))?;
+            if source_bytes.get(start..start + length)? != name {
+                return None;
+            }
+            ranges.push(Range::new(
+                bytes_to_pos(source_bytes, start)?,
+                bytes_to_pos(source_bytes, start + length)?,
+            ));
+        }
+        ranges.sort_by_key(|range| range.start);
+        ranges.dedup();
+        Some(LinkedEditingRanges {
+            ranges,
+            word_pattern: Some(IDENTIFIER_PATTERN.to_string()),
+        })
+    }
+}
 
-    let abs_path = match path_to_abs.get(path_str) {
-        Some(ap) => ap,
-        None => return vec![],
-    };
+/// [`ReferenceIndex::linked_editing_ranges`] over `ast_data`.
+pub fn linked_editing_ranges(
+    ast_data: &Value,
+    file_uri: &Url,
+    position: Position,
+    source_bytes: &[u8],
+) -> Option<LinkedEditingRanges> {
+    ReferenceIndex::new(ast_data)?.linked_editing_ranges(file_uri, position, source_bytes)
+}
 
-    // Convert position to byte offset
-    let byte_position = pos_to_bytes(source_bytes, position);
+/// Name of the custom request.
+pub const GROUPED_REFERENCES_METHOD: &str = "forge-lsp/groupedReferences";
+
+/// How a reference uses the symbol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ReferenceKind {
+    /// The declaration itself.
+    Declaration,
+    Read,
+    /// See [`Access::Write`].
+    Write,
+    Call,
+}
 
-    // Find the node ID at this position
-    let node_id = match byte_to_id(&nodes, abs_path, byte_position) {
-        Some(id) => id,
-        None => return vec![],
-    };
+/// A reference with the context clients group references by.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupedReference {
+    pub location: Location,
+    /// Contract, interface or library the reference is in, if any.
+    pub contract: Option<String>,
+    /// Function or modifier the reference is in, if any. Constructors, fallback and
+    /// receive functions are named by their kind.
+    pub function: Option<String>,
+    pub kind: ReferenceKind,
+    /// Whether the file changed since the AST was built, so the location may be off.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub stale: bool,
+}
 
-    // Determine the target node ID for finding references
-    // If this is a usage node, get its declaration; otherwise use the node itself
-    let target_node_id = {
-        let file_nodes = match nodes.get(abs_path) {
-            Some(nodes) => nodes,
-            None => return vec![],
-        };
+/// Where a node sits: its enclosing contract and function.
+#[derive(Debug, Clone, Default)]
+struct NodeContext {
+    contract: Option<String>,
+    function: Option<String>,
+}
 
-        if let Some(node_info) = file_nodes.get(&node_id) {
-            // If this node references a declaration, use the declaration as the target
-            // This ensures we get ALL references to the same symbol
-            node_info.referenced_declaration.unwrap_or(node_id)
-        } else {
-            node_id
+fn collect_contexts(node: &Value, context: &NodeContext, contexts: &mut HashMap<u64, NodeContext>) {
+    let Value::Object(map) = node else {
+        if let Value::Array(items) = node {
+            items
+                .iter()
+                .for_each(|item| collect_contexts(item, context, contexts));
         }
+        return;
     };
 
-    // Get all references for the target node (declaration)
-    let mut results = HashSet::new();
-    results.insert(target_node_id); // Always include the target node itself (the declaration)
-
-    // Add any references to this node
-    if let Some(refs) = all_refs.get(&target_node_id) {
-        results.extend(refs.iter().copied());
+    let name = map.get("name").and_then(Value::as_str);
+    let mut inner = context.clone();
+    match map.get("nodeType").and_then(Value::as_str) {
+        Some("ContractDefinition") => inner.contract = name.map(str::to_string),
+        Some("FunctionDefinition" | "ModifierDefinition") => {
+            inner.function = name
+                .filter(|name| !name.is_empty())
+                .map(str::to_string)
+                .or_else(|| map.get("kind").and_then(Value::as_str).map(str::to_string));
+        }
+        _ => {}
+    }
+    if let Some(id) = map.get("id").and_then(Value::as_u64) {
+        contexts.insert(id, inner.clone());
     }
+    map.values()
+        .for_each(|child| collect_contexts(child, &inner, contexts));
+}
 
-    // Convert node IDs to locations
-    let mut locations = Vec::new();
-    for id in results {
-        if let Some(location) = id_to_location(&nodes, &id_to_path_map, id) {
-            locations.push(location);
+impl ReferenceIndex {
+    /// How the node `id` uses the declaration `target`.
+    fn kind(&self, id: u64, target: u64) -> ReferenceKind {
+        if id == target {
+            return ReferenceKind::Declaration;
+        }
+        match self
+            .nodes
+            .values()
+            .find_map(|file_nodes| file_nodes.get(&id))
+            .map(|node| node.access)
+        {
+            Some(Access::Write) => ReferenceKind::Write,
+            Some(Access::Call) => ReferenceKind::Call,
+            _ => ReferenceKind::Read,
         }
     }
+}
 
-    // Remove duplicate locations
-    let mut unique_locations = Vec::new();
-    let mut seen = std::collections::HashSet::new();
-    for location in locations {
-        let key = (
-            location.uri.clone(),
-            location.range.start.line,
-            location.range.start.character,
-            location.range.end.line,
-            location.range.end.character,
-        );
-        if seen.insert(key) {
-            unique_locations.push(location);
+/// References to the symbol at `position`, each with its contract, function and kind, in
+/// file and position order.
+pub fn grouped_references(
+    ast_data: &Value,
+    file_uri: &Url,
+    position: Position,
+    source_bytes: &[u8],
+    documents: &Snapshot,
+) -> Vec<GroupedReference> {
+    let Some(index) = ReferenceIndex::new(ast_data) else {
+        return vec![];
+    };
+    let Some(target) = index.target_at(file_uri, position, source_bytes) else {
+        return vec![];
+    };
+
+    let mut contexts = HashMap::new();
+    for contents in ast_data
+        .get("sources")
+        .and_then(Value::as_object)
+        .into_iter()
+        .flat_map(|sources| sources.values())
+    {
+        if let Some(ast) = contents.pointer("/0/source_file/ast") {
+            collect_contexts(ast, &NodeContext::default(), &mut contexts);
         }
     }
 
-    unique_locations
+    let mut references: Vec<GroupedReference> = index
+        .located(target, documents)
+        .into_iter()
+        .map(|(id, location)| {
+            let context = contexts.remove(&id).unwrap_or_default();
+            GroupedReference {
+                location,
+                contract: context.contract,
+                function: context.function,
+                kind: index.kind(id, target),
+                stale: false,
+            }
+        })
+        .collect();
+    references.sort_by(|a, b| {
+        (a.location.uri.as_str(), a.location.range.start)
+            .cmp(&(b.location.uri.as_str(), b.location.range.start))
+    });
+    references
 }
 
 #[cfg(test)]
@@ -263,7 +586,13 @@ mod tests {
 
         // Test goto references on "name" in add_vote function (line 22, column 8)
         let position = Position::new(21, 8);
-        let references = goto_references(&ast_data, &file_uri, position, &source_bytes);
+        let references = goto_references(
+            &ast_data,
+            &file_uri,
+            position,
+            &source_bytes,
+            &Snapshot::default(),
+        );
 
         // The function should return a vector (may be empty if no references found)
         // This is just testing that the function runs without panicking
@@ -295,12 +624,23 @@ mod tests {
 
         // Test goto references from a usage of myValue (line 8: myValue = _value)
         let position = Position::new(7, 8); // Position of "myValue" in assignment
-        let references_from_usage = goto_references(&ast_data, &file_uri, position, &source_bytes);
+        let references_from_usage = goto_references(
+            &ast_data,
+            &file_uri,
+            position,
+            &source_bytes,
+            &Snapshot::default(),
+        );
 
         // Test goto references from the declaration of myValue (line 5: uint256 public myValue)
         let position_declaration = Position::new(4, 13); // Position of "myValue" in declaration
-        let references_from_declaration =
-            goto_references(&ast_data, &file_uri, position_declaration, &source_bytes);
+        let references_from_declaration = goto_references(
+            &ast_data,
+            &file_uri,
+            position_declaration,
+            &source_bytes,
+            &Snapshot::default(),
+        );
 
         // Both should return the same number of references (declaration + all usages)
         assert_eq!(
@@ -373,4 +713,221 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_grouped_references_have_context_and_kind() {
+        const SOURCE: &str = "\
+contract Vault {
+    uint256 total;
+    mapping(address => uint256) balances;
+
+    constructor() {
+        total = 1;
+    }
+
+    function deposit(uint256 amount) public {
+        total += amount;
+        balances[msg.sender] = total;
+        delete total;
+    }
+}
+";
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("Vault.sol");
+        std::fs::write(&path, SOURCE).unwrap();
+        let ast_data = crate::syntax::parse(path.to_str().unwrap(), SOURCE);
+        let uri = Url::from_file_path(&path).unwrap();
+
+        let references = grouped_references(
+            &ast_data,
+            &uri,
+            Position::new(1, 12),
+            SOURCE.as_bytes(),
+            &Snapshot::default(),
+        );
+        let summary: Vec<(u32, Option<&str>, Option<&str>, ReferenceKind)> = references
+            .iter()
+            .map(|reference| {
+                (
+                    reference.location.range.start.line,
+                    reference.contract.as_deref(),
+                    reference.function.as_deref(),
+                    reference.kind,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (1, Some("Vault"), None, ReferenceKind::Declaration),
+                (5, Some("Vault"), Some("constructor"), ReferenceKind::Write),
+                (9, Some("Vault"), Some("deposit"), ReferenceKind::Write),
+                (10, Some("Vault"), Some("deposit"), ReferenceKind::Read),
+                (11, Some("Vault"), Some("deposit"), ReferenceKind::Write),
+            ]
+        );
+
+        // The mapping is written through an index, whose key is read
+        let references = grouped_references(
+            &ast_data,
+            &uri,
+            Position::new(10, 9),
+            SOURCE.as_bytes(),
+            &Snapshot::default(),
+        );
+        assert_eq!(
+            references
+                .iter()
+                .map(|reference| reference.kind)
+                .collect::<Vec<_>>(),
+            [ReferenceKind::Declaration, ReferenceKind::Write]
+        );
+    }
+
+    #[test]
+    fn test_document_highlights_by_access() {
+        const SOURCE: &str = "\
+contract Vault {
+    uint256 total;
+
+    function bump() internal {
+        total += 1;
+    }
+
+    function f() public returns (uint256) {
+        bump();
+        bump();
+        return total;
+    }
+}
+";
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("Vault.sol");
+        std::fs::write(&path, SOURCE).unwrap();
+        let ast_data = crate::syntax::parse(path.to_str().unwrap(), SOURCE);
+        let uri = Url::from_file_path(&path).unwrap();
+        let highlights = |line, character| {
+            document_highlights(
+                &ast_data,
+                &uri,
+                Position::new(line, character),
+                SOURCE.as_bytes(),
+            )
+            .into_iter()
+            .map(|highlight| (highlight.range.start.line, highlight.kind.unwrap()))
+            .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            highlights(10, 16),
+            [
+                (1, DocumentHighlightKind::TEXT),
+                (4, DocumentHighlightKind::WRITE),
+                (10, DocumentHighlightKind::READ),
+            ]
+        );
+        assert_eq!(
+            highlights(8, 9),
+            [
+                (3, DocumentHighlightKind::TEXT),
+                (8, DocumentHighlightKind::READ),
+                (9, DocumentHighlightKind::READ),
+            ]
+        );
+        let references = grouped_references(
+            &ast_data,
+            &uri,
+            Position::new(8, 9),
+            SOURCE.as_bytes(),
+            &Snapshot::default(),
+        );
+        assert_eq!(
+            references
+                .iter()
+                .map(|reference| reference.kind)
+                .collect::<Vec<_>>(),
+            [
+                ReferenceKind::Declaration,
+                ReferenceKind::Call,
+                ReferenceKind::Call
+            ]
+        );
+    }
+
+    #[test]
+    fn test_highlights_use_the_buffer() {
+        // An unsaved buffer: nothing is on disk at this path
+        const SOURCE: &str = "\
+contract Vault {
+    function f(uint256 amount) public pure returns (uint256) {
+        amount = amount * amount;
+        return amount;
+    }
+}
+";
+        let path = "/nonexistent/src/Vault.sol";
+        let uri = Url::from_file_path(path).unwrap();
+        let ast_data = crate::syntax::parse(path, SOURCE);
+
+        let highlights: Vec<(Range, DocumentHighlightKind)> =
+            document_highlights(&ast_data, &uri, Position::new(3, 16), SOURCE.as_bytes())
+                .into_iter()
+                .map(|highlight| (highlight.range, highlight.kind.unwrap()))
+                .collect();
+        let range =
+            |line, start| Range::new(Position::new(line, start), Position::new(line, start + 6));
+        assert_eq!(
+            highlights,
+            [
+                (range(1, 23), DocumentHighlightKind::TEXT),
+                (range(2, 8), DocumentHighlightKind::WRITE),
+                (range(2, 17), DocumentHighlightKind::READ),
+                (range(2, 26), DocumentHighlightKind::READ),
+                (range(3, 15), DocumentHighlightKind::READ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_linked_editing_ranges() {
+        const SOURCE: &str = "\
+contract Vault {
+    struct Position { uint256 shares; }
+    uint256 total;
+
+    function f(uint256 amount) public returns (uint256) {
+        Position memory position;
+        position.shares = amount + total;
+        return amount;
+    }
+}
+";
+        let path = "/nonexistent/src/Vault.sol";
+        let uri = Url::from_file_path(path).unwrap();
+        let ast_data = crate::syntax::parse(path, SOURCE);
+        let ranges = |line, character| {
+            linked_editing_ranges(
+                &ast_data,
+                &uri,
+                Position::new(line, character),
+                SOURCE.as_bytes(),
+            )
+            .map(|linked| {
+                linked
+                    .ranges
+                    .iter()
+                    .map(|range| (range.start.line, range.start.character))
+                    .collect::<Vec<_>>()
+            })
+        };
+
+        assert_eq!(ranges(7, 16), Some(vec![(4, 23), (6, 26), (7, 15)]));
+        assert_eq!(ranges(6, 8), Some(vec![(5, 24), (6, 8)]));
+        // An occurrence moved by an edit since the parse
+        let edited = SOURCE.replace("return amount", "return  amount");
+        assert_eq!(
+            linked_editing_ranges(&ast_data, &uri, Position::new(4, 24), edited.as_bytes()),
+            None
+        );
+    }
 }
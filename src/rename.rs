@@ -1,39 +1,56 @@
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
-use tower_lsp::lsp_types::{Position, TextEdit, Url, WorkspaceEdit};
-
-use crate::references;
+use thiserror::Error;
+use tower_lsp::lsp_types::{
+    AnnotatedTextEdit, ChangeAnnotation, DocumentChanges, Location, OneOf,
+    OptionalVersionedTextDocumentIdentifier, Position, Range, RenameParams, TextDocumentEdit,
+    TextEdit, Url, WorkspaceEdit,
+};
+
+use crate::{
+    ast::{self, parse_src},
+    documents::{LineIndex, Snapshot},
+    edits::EditBuilder,
+    goto::{bytes_to_pos, pos_to_bytes},
+    paths,
+    references::{self, ImportAlias},
+    utils,
+};
 
 /// Extract the identifier (word) at the given position in the source bytes
 pub fn get_identifier_at_position(source_bytes: &[u8], position: Position) -> Option<String> {
-    let text = String::from_utf8_lossy(source_bytes);
-    let lines: Vec<&str> = text.lines().collect();
-
-    if position.line as usize >= lines.len() {
-        return None;
-    }
+    identifier_at(source_bytes, position).map(|(_, identifier)| identifier)
+}
 
-    let line = lines[position.line as usize];
-    if position.character as usize > line.len() {
+/// The identifier (word) at `position` and its range on the line, with UTF-16 columns.
+pub fn identifier_at(source_bytes: &[u8], position: Position) -> Option<(Range, String)> {
+    let text = String::from_utf8_lossy(source_bytes);
+    let line_index = LineIndex::new(&text);
+    let line_start = line_index.line_start(position.line as usize)?;
+    let line_end = text[line_start..]
+        .find(['\r', '\n'])
+        .map_or(text.len(), |end| line_start + end);
+
+    // Positions past the end of the line point at no word
+    let offset = line_index.offset(&text, position);
+    if line_index.position(&text, offset).character < position.character {
         return None;
     }
 
     // Find the word boundaries around the character position
-    let mut start = position.character as usize;
-    let mut end = position.character as usize;
+    let bytes = text.as_bytes();
+    let is_word = |byte: u8| byte.is_ascii_alphanumeric() || byte == b'_';
+    let mut start = offset;
+    let mut end = offset;
 
     // Move start backwards to find word start
-    while start > 0
-        && (line.as_bytes()[start - 1].is_ascii_alphanumeric()
-            || line.as_bytes()[start - 1] == b'_')
-    {
+    while start > line_start && is_word(bytes[start - 1]) {
         start -= 1;
     }
 
     // Move end forwards to find word end
-    while end < line.len()
-        && (line.as_bytes()[end].is_ascii_alphanumeric() || line.as_bytes()[end] == b'_')
-    {
+    while end < line_end && is_word(bytes[end]) {
         end += 1;
     }
 
@@ -42,51 +59,586 @@ pub fn get_identifier_at_position(source_bytes: &[u8], position: Position) -> Op
     }
 
     // Check if it starts with a digit (not a valid identifier)
-    if line.as_bytes()[start].is_ascii_digit() {
+    if bytes[start].is_ascii_digit() {
         return None;
     }
 
-    Some(line[start..end].to_string())
+    let range = Range::new(
+        line_index.position(&text, start),
+        line_index.position(&text, end),
+    );
+    Some((range, text[start..end].to_string()))
+}
+
+/// Why a position or a new name can't be renamed.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum RenameError {
+    #[error("no identifier at the cursor")]
+    NotIdentifier,
+    #[error("cannot rename inside a string literal or comment")]
+    Literal,
+    #[error("`{0}` is a keyword")]
+    Keyword(String),
+    #[error("`{0}` is a builtin")]
+    Builtin(String),
+    #[error("`{0}` is not a valid Solidity identifier")]
+    InvalidName(String),
+}
+
+/// Reserved words of Solidity, including units and names reserved for future use.
+const KEYWORDS: &[&str] = &[
+    "abstract",
+    "after",
+    "alias",
+    "anonymous",
+    "apply",
+    "as",
+    "assembly",
+    "auto",
+    "bool",
+    "break",
+    "byte",
+    "calldata",
+    "case",
+    "catch",
+    "constant",
+    "constructor",
+    "continue",
+    "contract",
+    "copyof",
+    "days",
+    "default",
+    "define",
+    "delete",
+    "do",
+    "else",
+    "emit",
+    "enum",
+    "ether",
+    "event",
+    "external",
+    "fallback",
+    "false",
+    "final",
+    "for",
+    "function",
+    "gwei",
+    "hex",
+    "hours",
+    "if",
+    "immutable",
+    "implements",
+    "import",
+    "in",
+    "indexed",
+    "inline",
+    "interface",
+    "internal",
+    "is",
+    "let",
+    "library",
+    "macro",
+    "mapping",
+    "match",
+    "memory",
+    "minutes",
+    "modifier",
+    "mutable",
+    "new",
+    "null",
+    "of",
+    "override",
+    "partial",
+    "payable",
+    "pragma",
+    "private",
+    "promise",
+    "public",
+    "pure",
+    "receive",
+    "reference",
+    "relocatable",
+    "return",
+    "returns",
+    "sealed",
+    "seconds",
+    "sizeof",
+    "static",
+    "storage",
+    "string",
+    "struct",
+    "supports",
+    "switch",
+    "true",
+    "try",
+    "type",
+    "typedef",
+    "typeof",
+    "unchecked",
+    "unicode",
+    "using",
+    "var",
+    "view",
+    "virtual",
+    "weeks",
+    "wei",
+    "while",
+    "years",
+];
+
+/// Globals and global functions the language provides.
+const BUILTINS: &[&str] = &[
+    "abi",
+    "addmod",
+    "assert",
+    "blobhash",
+    "block",
+    "blockhash",
+    "ecrecover",
+    "gasleft",
+    "keccak256",
+    "msg",
+    "mulmod",
+    "now",
+    "require",
+    "revert",
+    "ripemd160",
+    "selfdestruct",
+    "sha256",
+    "super",
+    "this",
+    "tx",
+];
+
+/// Whether `name` is an elementary type: `address`, `bytes`, `uint256`, `bytes32`,
+/// `fixed128x18` and the like.
+fn is_elementary_type(name: &str) -> bool {
+    let sized = |prefix: &str| {
+        name.strip_prefix(prefix)
+            .is_some_and(|size| size.bytes().all(|b| b.is_ascii_digit()))
+    };
+    let fixed = |prefix: &str| {
+        name.strip_prefix(prefix).is_some_and(|size| {
+            size.is_empty()
+                || size
+                    .split_once('x')
+                    .is_some_and(|(m, n)| !m.is_empty() && !n.is_empty())
+                    && size.bytes().all(|b| b.is_ascii_digit() || b == b'x')
+        })
+    };
+    name == "address"
+        || sized("uint")
+        || sized("int")
+        || sized("bytes")
+        || fixed("fixed")
+        || fixed("ufixed")
+}
+
+/// Whether `name` is an identifier a declaration can have.
+fn check_name(name: &str) -> Result<(), RenameError> {
+    if KEYWORDS.contains(&name) || is_elementary_type(name) {
+        return Err(RenameError::Keyword(name.to_string()));
+    }
+    if BUILTINS.contains(&name) {
+        return Err(RenameError::Builtin(name.to_string()));
+    }
+    Ok(())
+}
+
+/// Whether `offset` is in code rather than in a string literal or comment.
+fn in_code(source_bytes: &[u8], offset: usize) -> bool {
+    let mut i = 0;
+    while i < offset.min(source_bytes.len()) {
+        let rest = &source_bytes[i..];
+        let end = if rest.starts_with(b"//") {
+            rest.iter().position(|&b| b == b'\n').map(|n| i + n)
+        } else if rest.starts_with(b"/*") {
+            rest.windows(2)
+                .skip(2)
+                .position(|w| w == b"*/")
+                .map(|n| i + n + 4)
+        } else if let quote @ (b'"' | b'\'') = rest[0] {
+            let mut j = 1;
+            while j < rest.len() && rest[j] != quote && rest[j] != b'\n' {
+                j += if rest[j] == b'\\' { 2 } else { 1 };
+            }
+            Some(i + j + 1)
+        } else {
+            i += 1;
+            continue;
+        };
+        match end {
+            Some(end) if end <= offset => i = end,
+            _ => return false,
+        }
+    }
+    true
+}
+
+/// Validate a rename at `position`: the range and text of the identifier there, or why it
+/// can't be renamed.
+pub fn prepare_rename(
+    source_bytes: &[u8],
+    position: Position,
+) -> Result<(Range, String), RenameError> {
+    let (range, identifier) =
+        identifier_at(source_bytes, position).ok_or(RenameError::NotIdentifier)?;
+    if !in_code(source_bytes, pos_to_bytes(source_bytes, range.start)) {
+        return Err(RenameError::Literal);
+    }
+    check_name(&identifier)?;
+    Ok((range, identifier))
+}
+
+/// Validate the new name of a rename.
+pub fn validate_new_name(name: &str) -> Result<(), RenameError> {
+    if !utils::is_valid_solidity_identifier(name) {
+        return Err(RenameError::InvalidName(name.to_string()));
+    }
+    check_name(name)
+}
+
+/// Text covered by `range` in `source`.
+fn text_at(source: &[u8], range: Range) -> Option<&[u8]> {
+    let start = pos_to_bytes(source, range.start);
+    let end = pos_to_bytes(source, range.end);
+    source.get(start..end)
 }
 
 /// Handle a rename request by finding all references to the symbol at the given position
 /// and creating a WorkspaceEdit with the new name
+///
+/// Usages are matched by the name written at the cursor, so an import alias and the
+/// declaration it names are renamed independently: renaming `Bar` from
+/// `import {Foo as Bar}` only touches the alias and its uses in the importing file, and
+/// renaming `Foo` leaves `Bar` in place. Other files are edited as they are on disk.
 pub fn rename_symbol(
     ast_data: &Value,
     file_uri: &Url,
     position: Position,
-    _source_bytes: &[u8],
+    source_bytes: &[u8],
     new_name: String,
 ) -> Option<WorkspaceEdit> {
+    let documents = Snapshot::default();
+    rename_with_overrides(
+        ast_data,
+        file_uri,
+        position,
+        source_bytes,
+        new_name,
+        true,
+        &documents,
+    )
+    .map(|(edit, _)| edit)
+}
+
+/// The functions and modifiers overriding `declaration` or overridden by it, directly or
+/// through each other: the interface declarations it implements, the overrides in derived
+/// contracts, and the other implementations of the declarations it overrides. Renaming
+/// one without the others breaks the `override`.
+pub fn override_family(ast_data: &Value, declaration: u64) -> Vec<u64> {
+    let Some(nodes) = ast_data.get("sources").map(ast::index_nodes) else {
+        return vec![];
+    };
+    let bases = |node: &Value| -> Vec<u64> {
+        ["baseFunctions", "baseModifiers"]
+            .iter()
+            .filter_map(|key| node.get(*key)?.as_array())
+            .flatten()
+            .filter_map(Value::as_u64)
+            .collect()
+    };
+    let mut overrides: HashMap<u64, Vec<u64>> = HashMap::new();
+    for (&id, node) in &nodes {
+        for base in bases(node) {
+            overrides.entry(base).or_default().push(id);
+        }
+    }
+
+    let mut family = vec![declaration];
+    let mut stack = vec![declaration];
+    while let Some(id) = stack.pop() {
+        let up = nodes.get(&id).map(|node| bases(node)).unwrap_or_default();
+        let down = overrides.get(&id).cloned().unwrap_or_default();
+        for related in up.into_iter().chain(down) {
+            if !family.contains(&related) {
+                family.push(related);
+                stack.push(related);
+            }
+        }
+    }
+    family.remove(0);
+    family.sort_unstable();
+    family
+}
+
+/// [`rename_symbol`], renaming the [`override_family`] of a function or modifier along with
+/// it when `overrides` is set. Returns the edit and the locations renamed for the family.
+/// Files other than `file_uri` are edited as `documents` has them.
+pub fn rename_with_overrides(
+    ast_data: &Value,
+    file_uri: &Url,
+    position: Position,
+    source_bytes: &[u8],
+    new_name: String,
+    overrides: bool,
+    documents: &Snapshot,
+) -> Option<(WorkspaceEdit, Vec<Location>)> {
+    let index = references::ReferenceIndex::new(ast_data)?;
+    let name = get_identifier_at_position(source_bytes, position)?;
+    let file_key = paths::uri_to_key(file_uri)?;
+    let abs_path = paths::lookup_path(&index.path_to_abs, file_uri.as_str())?;
+    let target = index.target_at(file_uri, position, source_bytes);
+
+    // An alias declared in this file, either at the cursor or used there
+    let alias = index.aliases.iter().find(|alias| {
+        alias.local == name
+            && &alias.file == abs_path
+            && target.is_none_or(|target| {
+                target == alias.declaration || alias_at(alias, source_bytes, position)
+            })
+    });
+
+    let declaration = match alias {
+        Some(alias) => alias.declaration,
+        None => target?,
+    };
+
     // Get all locations for renaming (declaration + references)
     // The AST provides exact ranges, so we use them directly
-    let locations = references::goto_references(ast_data, file_uri, position, _source_bytes);
+    let mut locations = index.locations(declaration, documents);
+
+    // Uses of an aliased declaration are spelled either way: keep those spelled like the
+    // name being renamed
+    let aliased = index
+        .aliases
+        .iter()
+        .any(|alias| alias.declaration == declaration);
+    let mut sources: HashMap<Url, Option<Vec<u8>>> = HashMap::new();
+    locations.retain(|location| {
+        if !aliased {
+            return true;
+        }
+        if alias.is_some() && paths::uri_to_key(&location.uri).as_ref() != Some(&file_key) {
+            return false;
+        }
+        let source = sources
+            .entry(location.uri.clone())
+            .or_insert_with(|| documents.read(&location.uri.to_file_path().ok()?));
+        source
+            .as_deref()
+            .and_then(|source| text_at(source, location.range))
+            .is_some_and(|text| text == name.as_bytes())
+    });
+    if let Some(range) = alias.and_then(|alias| alias_range(alias, source_bytes)) {
+        locations.push(Location {
+            uri: file_uri.clone(),
+            range,
+        });
+    }
+
+    let mut family_locations: Vec<Location> = Vec::new();
+    if overrides {
+        for member in override_family(ast_data, declaration) {
+            for location in index.locations(member, documents) {
+                if !locations.contains(&location) && !family_locations.contains(&location) {
+                    family_locations.push(location);
+                }
+            }
+        }
+        locations.extend(family_locations.iter().cloned());
+    }
 
     if locations.is_empty() {
         return None;
     }
 
     // Group locations by URI
-    let mut changes: HashMap<Url, Vec<TextEdit>> = HashMap::new();
-
+    let mut ranges: HashMap<Url, Vec<Range>> = HashMap::new();
     for location in locations {
-        // The AST already provides the exact range for the symbol, so use it directly
-        let text_edit = TextEdit {
-            range: location.range,
-            new_text: new_name.clone(),
-        };
-        changes.entry(location.uri).or_default().push(text_edit);
+        ranges.entry(location.uri).or_default().push(location.range);
     }
 
+    // Validate the edits of each file against its text, so a bad range fails the rename
+    // instead of corrupting the file
+    let mut changes: HashMap<Url, Vec<TextEdit>> = HashMap::new();
+    for (uri, ranges) in ranges {
+        let source = if &uri == file_uri {
+            source_bytes.to_vec()
+        } else {
+            documents.read(&uri.to_file_path().ok()?)?
+        };
+        let source = String::from_utf8_lossy(&source);
+        let mut edits = EditBuilder::new(&source);
+        for range in ranges {
+            edits.replace_range(range, new_name.clone()).ok()?;
+        }
+        changes.insert(uri, edits.build());
+    }
 
-
-    Some(WorkspaceEdit {
+    let edit = WorkspaceEdit {
         changes: Some(changes),
         document_changes: None,
         change_annotations: None,
+    };
+    Some((edit, family_locations))
+}
+
+/// Id of the change annotation of the edits renaming overriding and overridden functions.
+pub const OVERRIDES_ANNOTATION: &str = "forge-lsp.renameOverrides";
+
+/// The versioned `edit` with its edits at `overrides` annotated, so the client asks before
+/// renaming the functions in other contracts of the inheritance chain.
+pub fn annotate_overrides(mut edit: WorkspaceEdit, overrides: &[Location]) -> WorkspaceEdit {
+    let Some(DocumentChanges::Edits(document_edits)) = edit.document_changes.as_mut() else {
+        return edit;
+    };
+    let mut annotated = false;
+    for document_edit in document_edits {
+        for text_edit in &mut document_edit.edits {
+            let OneOf::Left(plain) = text_edit else {
+                continue;
+            };
+            let location = Location::new(document_edit.text_document.uri.clone(), plain.range);
+            if overrides.contains(&location) {
+                *text_edit = OneOf::Right(AnnotatedTextEdit {
+                    text_edit: plain.clone(),
+                    annotation_id: OVERRIDES_ANNOTATION.to_string(),
+                });
+                annotated = true;
+            }
+        }
+    }
+    if annotated {
+        edit.change_annotations = Some(HashMap::from([(
+            OVERRIDES_ANNOTATION.to_string(),
+            ChangeAnnotation {
+                label: "Rename overriding and overridden functions".to_string(),
+                needs_confirmation: Some(true),
+                description: Some(
+                    "Declarations in base contracts, interfaces and derived contracts, and their uses"
+                        .to_string(),
+                ),
+            },
+        )]));
+    }
+    edit
+}
+
+/// Name of the custom request.
+pub const SCOPED_RENAME_METHOD: &str = "forge-lsp/scopedRename";
+
+/// Which uses of a symbol a rename changes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RenameScope {
+    /// Every use in the workspace, like `textDocument/rename`.
+    #[default]
+    Workspace,
+    /// Uses in the file of the cursor.
+    File,
+    /// Uses in the contract around the cursor.
+    Contract,
+}
+
+/// Parameters of `forge-lsp/scopedRename`: those of a rename, and its scope.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScopedRenameParams {
+    #[serde(flatten)]
+    pub rename: RenameParams,
+    #[serde(default)]
+    pub scope: RenameScope,
+}
+
+/// Byte span of the contract, interface or library around `position` of `file_uri`.
+fn contract_span(
+    ast_data: &Value,
+    file_uri: &Url,
+    position: Position,
+    source_bytes: &[u8],
+) -> Option<(usize, usize)> {
+    let offset = pos_to_bytes(source_bytes, position);
+    ast::source_unit(ast_data, file_uri)?
+        .get("nodes")?
+        .as_array()?
+        .iter()
+        .filter(|node| node.get("nodeType").and_then(Value::as_str) == Some("ContractDefinition"))
+        .find(|node| ast::contains(node, offset))
+        .and_then(|node| parse_src(node.get("src")?.as_str()?))
+        .map(|(start, length, _)| (start, start + length))
+}
+
+/// `edit` restricted to the uses of `scope`, forking the name instead of renaming it
+/// everywhere. `None` if the scope is a contract and `position` is outside of one.
+pub fn restrict_to_scope(
+    mut edit: WorkspaceEdit,
+    scope: RenameScope,
+    ast_data: &Value,
+    file_uri: &Url,
+    position: Position,
+    source_bytes: &[u8],
+) -> Option<WorkspaceEdit> {
+    let span = match scope {
+        RenameScope::Workspace => return Some(edit),
+        RenameScope::File => None,
+        RenameScope::Contract => Some(contract_span(ast_data, file_uri, position, source_bytes)?),
+    };
+    let file_key = paths::uri_to_key(file_uri);
+    if let Some(changes) = edit.changes.as_mut() {
+        changes.retain(|uri, _| paths::uri_to_key(uri) == file_key);
+        for edits in changes.values_mut() {
+            edits.retain(|edit| {
+                span.is_none_or(|(start, end)| {
+                    start <= pos_to_bytes(source_bytes, edit.range.start)
+                        && pos_to_bytes(source_bytes, edit.range.end) <= end
+                })
+            });
+        }
+        changes.retain(|_, edits| !edits.is_empty());
+    }
+    Some(edit)
+}
+
+/// `edit` as one text document edit per file, in URI order, for the client to apply.
+/// Files open in the editor carry their version from `versions`, so the client rejects
+/// the edit if a buffer changed since; files without a version in `versions` are edited
+/// as the client has them.
+pub fn versioned_edit(edit: WorkspaceEdit, versions: &HashMap<Url, i32>) -> WorkspaceEdit {
+    let mut changes: Vec<(Url, Vec<TextEdit>)> =
+        edit.changes.unwrap_or_default().into_iter().collect();
+    changes.sort_by(|(a, _), (b, _)| a.as_str().cmp(b.as_str()));
+    let edits = changes
+        .into_iter()
+        .map(|(uri, edits)| TextDocumentEdit {
+            text_document: OptionalVersionedTextDocumentIdentifier {
+                version: versions.get(&uri).copied(),
+                uri,
+            },
+            edits: edits.into_iter().map(OneOf::Left).collect(),
+        })
+        .collect();
+    WorkspaceEdit {
+        changes: None,
+        document_changes: Some(DocumentChanges::Edits(edits)),
+        change_annotations: None,
+    }
+}
+
+/// Range of the alias name after `as`.
+fn alias_range(alias: &ImportAlias, source_bytes: &[u8]) -> Option<Range> {
+    let (start, length, _) = parse_src(alias.name_location.as_deref()?)?;
+    Some(Range {
+        start: bytes_to_pos(source_bytes, start)?,
+        end: bytes_to_pos(source_bytes, start + length)?,
     })
 }
 
+fn alias_at(alias: &ImportAlias, source_bytes: &[u8], position: Position) -> bool {
+    alias_range(alias, source_bytes)
+        .is_some_and(|range| range.start <= position && position <= range.end)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -400,4 +952,481 @@ mod tests {
         // Should have changes on lines 5 (declaration), 8 (setMyValue), and 12 (getMyValue)
         assert_eq!(lines_with_changes, vec![4, 7, 11]);
     }
+
+    const A: &str = "contract Foo {}\n";
+    const B: &str = "import {Foo as Bar} from \"./A.sol\";\ncontract C is Bar {\n    Bar b;\n}\n";
+    const C: &str = "import {Foo} from \"./A.sol\";\ncontract D is Foo {}\n";
+
+    /// `src` of the `nth` occurrence of `needle` in `source`.
+    fn src_in(source: &str, needle: &str, nth: usize, file: u32) -> String {
+        let start = source.match_indices(needle).nth(nth).unwrap().0;
+        format!("{start}:{}:{file}", needle.len())
+    }
+
+    fn whole(source: &str, file: u32) -> String {
+        format!("0:{}:{file}", source.len())
+    }
+
+    fn reference(id: u64, node_type: &str, name: &str, src: String) -> serde_json::Value {
+        serde_json::json!({
+            "id": id,
+            "src": src,
+            "nodeType": node_type,
+            "name": name,
+            "referencedDeclaration": 2
+        })
+    }
+
+    fn import(id: u64, source: &str, file: u32, local: Option<&str>) -> serde_json::Value {
+        let mut alias = serde_json::json!({
+            "foreign": reference(id + 1, "Identifier", "Foo", src_in(source, "Foo", 0, file)),
+            "local": local
+        });
+        if let Some(local) = local {
+            alias["nameLocation"] = serde_json::json!(src_in(source, local, 0, file));
+        }
+        serde_json::json!({
+            "id": id,
+            "src": format!("0:{}:{file}", source.find(';').unwrap() + 1),
+            "nodeType": "ImportDirective",
+            "symbolAliases": [alias]
+        })
+    }
+
+    fn contract(id: u64, name: &str, source: &str, file: u32, base: &str) -> serde_json::Value {
+        let base_src = src_in(source, &format!("is {base}"), 0, file);
+        let (start, _) = base_src.split_once(':').unwrap();
+        let base_src = format!(
+            "{}:{}:{file}",
+            start.parse::<usize>().unwrap() + 3,
+            base.len()
+        );
+        serde_json::json!({
+            "id": id,
+            "src": format!("{}:0:{file}", source.find("contract").unwrap()),
+            "nodeType": "ContractDefinition",
+            "name": name,
+            "nameLocation": src_in(source, name, 0, file),
+            "baseContracts": [{
+                "id": id + 1,
+                "src": base_src.clone(),
+                "nodeType": "InheritanceSpecifier",
+                "baseName": reference(id + 2, "IdentifierPath", base, base_src)
+            }],
+            "nodes": []
+        })
+    }
+
+    fn source_unit(
+        path: &str,
+        id: u64,
+        src: String,
+        nodes: Vec<serde_json::Value>,
+    ) -> serde_json::Value {
+        crate::ast::mock_source(
+            id,
+            serde_json::json!({
+                "id": id * 100 + 1,
+                "src": src,
+                "nodeType": "SourceUnit",
+                "absolutePath": path,
+                "nodes": nodes
+            }),
+        )
+    }
+
+    fn alias_ast(dir: &std::path::Path) -> serde_json::Value {
+        let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+
+        let foo = serde_json::json!({
+            "id": 2,
+            "src": src_in(A, "contract Foo {}", 0, 0),
+            "nodeType": "ContractDefinition",
+            "name": "Foo",
+            "nameLocation": src_in(A, "Foo", 0, 0),
+            "nodes": []
+        });
+        let mut c = contract(30, "C", B, 1, "Bar");
+        // `Bar b;`
+        c["nodes"] = serde_json::json!([{
+            "id": 40,
+            "src": src_in(B, "Bar b", 0, 1),
+            "nodeType": "VariableDeclaration",
+            "name": "b",
+            "nameLocation": src_in(B, "b;", 0, 1),
+            "typeName": reference(41, "UserDefinedTypeName", "Bar", src_in(B, "Bar", 2, 1))
+        }]);
+
+        let b_nodes = vec![import(20, B, 1, Some("Bar")), c];
+        let c_nodes = vec![import(50, C, 2, None), contract(60, "D", C, 2, "Foo")];
+        serde_json::json!({
+            "sources": {
+                path("A.sol"): source_unit(&path("A.sol"), 0, whole(A, 0), vec![foo]),
+                path("B.sol"): source_unit(&path("B.sol"), 1, whole(B, 1), b_nodes),
+                path("C.sol"): source_unit(&path("C.sol"), 2, whole(C, 2), c_nodes)
+            },
+            "build_infos": [{
+                "source_id_to_path": { "0": path("A.sol"), "1": path("B.sol"), "2": path("C.sol") }
+            }]
+        })
+    }
+
+    fn edited_text(edit: &WorkspaceEdit, uri: &Url, source: &str) -> Vec<String> {
+        let mut ranges: Vec<Range> = edit.changes.as_ref().unwrap()[uri]
+            .iter()
+            .map(|edit| edit.range)
+            .collect();
+        ranges.sort_by_key(|range| range.start);
+        ranges
+            .into_iter()
+            .map(|range| {
+                let text = text_at(source.as_bytes(), range).unwrap();
+                format!("{}:{}", range.start.line, String::from_utf8_lossy(text))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_rename_import_alias() {
+        let dir = tempfile::tempdir().unwrap();
+        for (name, source) in [("A.sol", A), ("B.sol", B), ("C.sol", C)] {
+            std::fs::write(dir.path().join(name), source).unwrap();
+        }
+        let ast = alias_ast(dir.path());
+        let uri = |name: &str| Url::from_file_path(dir.path().join(name)).unwrap();
+
+        // From a use of the alias: only the alias and its uses in B.sol
+        let position = bytes_to_pos(B.as_bytes(), B.find("Bar b").unwrap()).unwrap();
+        let edit =
+            rename_symbol(&ast, &uri("B.sol"), position, B.as_bytes(), "Baz".into()).unwrap();
+        assert_eq!(edit.changes.as_ref().unwrap().len(), 1);
+        assert_eq!(
+            edited_text(&edit, &uri("B.sol"), B),
+            vec!["0:Bar", "1:Bar", "2:Bar"]
+        );
+
+        // From the alias in the import directive
+        let position = bytes_to_pos(B.as_bytes(), B.find("Bar}").unwrap()).unwrap();
+        let edit =
+            rename_symbol(&ast, &uri("B.sol"), position, B.as_bytes(), "Baz".into()).unwrap();
+        assert_eq!(
+            edited_text(&edit, &uri("B.sol"), B),
+            vec!["0:Bar", "1:Bar", "2:Bar"]
+        );
+
+        // From the declaration: the real name everywhere, aliases untouched
+        let position = bytes_to_pos(A.as_bytes(), A.find("Foo").unwrap()).unwrap();
+        let edit =
+            rename_symbol(&ast, &uri("A.sol"), position, A.as_bytes(), "Vault".into()).unwrap();
+        assert_eq!(edited_text(&edit, &uri("A.sol"), A), vec!["0:Foo"]);
+        assert_eq!(edited_text(&edit, &uri("B.sol"), B), vec!["0:Foo"]);
+        assert_eq!(edited_text(&edit, &uri("C.sol"), C), vec!["0:Foo", "1:Foo"]);
+    }
+
+    #[test]
+    fn test_restrict_rename_to_file_or_contract() {
+        const SOURCE: &str = "\
+function helper() {}
+
+contract A {
+    uint256 total;
+}
+
+contract B {
+    uint256 total;
+}
+";
+        let path = "/project/src/A.sol";
+        let uri = Url::from_file_path(path).unwrap();
+        let other = Url::from_file_path("/project/src/Other.sol").unwrap();
+        let ast_data = crate::syntax::parse(path, SOURCE);
+        let edit = |line: u32| TextEdit {
+            range: Range::new(Position::new(line, 12), Position::new(line, 17)),
+            new_text: "supply".to_string(),
+        };
+        let workspace_edit = WorkspaceEdit {
+            changes: Some(HashMap::from([
+                (uri.clone(), vec![edit(3), edit(7)]),
+                (other.clone(), vec![edit(0)]),
+            ])),
+            ..Default::default()
+        };
+        let restrict = |scope, position| {
+            restrict_to_scope(
+                workspace_edit.clone(),
+                scope,
+                &ast_data,
+                &uri,
+                position,
+                SOURCE.as_bytes(),
+            )
+            .map(|edit| edit.changes.unwrap())
+        };
+
+        let all = restrict(RenameScope::Workspace, Position::new(3, 14)).unwrap();
+        assert_eq!(all.len(), 2);
+        let file = restrict(RenameScope::File, Position::new(3, 14)).unwrap();
+        assert_eq!(file, HashMap::from([(uri.clone(), vec![edit(3), edit(7)])]));
+        let contract = restrict(RenameScope::Contract, Position::new(7, 14)).unwrap();
+        assert_eq!(contract, HashMap::from([(uri.clone(), vec![edit(7)])]));
+        assert_eq!(restrict(RenameScope::Contract, Position::new(0, 10)), None);
+    }
+
+    #[test]
+    fn test_scoped_rename_params_default_to_workspace() {
+        let params: ScopedRenameParams = serde_json::from_value(serde_json::json!({
+            "textDocument": {"uri": "file:///project/src/A.sol"},
+            "position": {"line": 3, "character": 14},
+            "newName": "supply"
+        }))
+        .unwrap();
+        assert_eq!(params.scope, RenameScope::Workspace);
+        assert_eq!(params.rename.new_name, "supply");
+
+        let params: ScopedRenameParams = serde_json::from_value(serde_json::json!({
+            "textDocument": {"uri": "file:///project/src/A.sol"},
+            "position": {"line": 3, "character": 14},
+            "newName": "supply",
+            "scope": "contract"
+        }))
+        .unwrap();
+        assert_eq!(params.scope, RenameScope::Contract);
+    }
+
+    #[test]
+    fn test_prepare_rename_validates_the_identifier() {
+        let source = b"\
+contract Vault {
+    // total of all deposits
+    uint256 total = 1 ether;
+
+    function f() public {
+        require(msg.sender != address(this), \"total\");
+        /* total
+           total */ total++;
+    }
+}
+";
+        let prepare = |line, character| prepare_rename(source, Position::new(line, character));
+
+        assert_eq!(
+            prepare(2, 14),
+            Ok((
+                Range::new(Position::new(2, 12), Position::new(2, 17)),
+                "total".to_string()
+            ))
+        );
+        assert_eq!(
+            prepare(7, 23).map(|(_, name)| name),
+            Ok("total".to_string())
+        );
+        assert_eq!(
+            prepare(0, 2),
+            Err(RenameError::Keyword("contract".to_string()))
+        );
+        assert_eq!(
+            prepare(2, 6),
+            Err(RenameError::Keyword("uint256".to_string()))
+        );
+        assert_eq!(
+            prepare(2, 22),
+            Err(RenameError::Keyword("ether".to_string()))
+        );
+        assert_eq!(prepare(5, 17), Err(RenameError::Builtin("msg".to_string())));
+        assert_eq!(
+            prepare(5, 10),
+            Err(RenameError::Builtin("require".to_string()))
+        );
+        assert_eq!(prepare(2, 20), Err(RenameError::NotIdentifier));
+        assert_eq!(prepare(1, 10), Err(RenameError::Literal));
+        assert_eq!(prepare(5, 48), Err(RenameError::Literal));
+        assert_eq!(prepare(7, 12), Err(RenameError::Literal));
+    }
+
+    #[test]
+    fn test_identifier_at_counts_utf16() {
+        let source = "uint a;\r\n/* é */ total = 1;\r\n".as_bytes();
+        assert_eq!(
+            identifier_at(source, Position::new(1, 10)),
+            Some((
+                Range::new(Position::new(1, 8), Position::new(1, 13)),
+                "total".to_string()
+            ))
+        );
+        assert_eq!(identifier_at(source, Position::new(0, 6)).unwrap().1, "a");
+        assert_eq!(identifier_at(source, Position::new(0, 9)), None);
+    }
+
+    #[test]
+    fn test_validate_new_name() {
+        assert_eq!(validate_new_name("supply"), Ok(()));
+        assert_eq!(validate_new_name("_uint"), Ok(()));
+        assert_eq!(
+            validate_new_name("bytes32"),
+            Err(RenameError::Keyword("bytes32".to_string()))
+        );
+        assert_eq!(
+            validate_new_name("fixed8x2"),
+            Err(RenameError::Keyword("fixed8x2".to_string()))
+        );
+        assert_eq!(
+            validate_new_name("this"),
+            Err(RenameError::Builtin("this".to_string()))
+        );
+        assert_eq!(
+            validate_new_name("1st"),
+            Err(RenameError::InvalidName("1st".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_versioned_edit_covers_every_file() {
+        let open = Url::from_file_path("/project/src/A.sol").unwrap();
+        let closed = Url::from_file_path("/project/src/B.sol").unwrap();
+        let edit = TextEdit {
+            range: Range::new(Position::new(1, 4), Position::new(1, 9)),
+            new_text: "supply".to_string(),
+        };
+        let workspace_edit = WorkspaceEdit {
+            changes: Some(HashMap::from([
+                (closed.clone(), vec![edit.clone()]),
+                (open.clone(), vec![edit.clone()]),
+            ])),
+            ..Default::default()
+        };
+
+        let versioned = versioned_edit(workspace_edit, &HashMap::from([(open.clone(), 7)]));
+        assert_eq!(versioned.changes, None);
+        let Some(DocumentChanges::Edits(edits)) = versioned.document_changes else {
+            panic!("expected text document edits");
+        };
+        let documents: Vec<(&Url, Option<i32>)> = edits
+            .iter()
+            .map(|edit| (&edit.text_document.uri, edit.text_document.version))
+            .collect();
+        assert_eq!(documents, [(&open, Some(7)), (&closed, None)]);
+        assert_eq!(edits[0].edits, [OneOf::Left(edit)]);
+    }
+
+    static IVAULT: &str = "interface IVault {
+    function deposit(uint256 amount) external;
+}
+";
+
+    static VAULT: &str = "import {IVault} from \"./IVault.sol\";
+
+contract Vault is IVault {
+    function deposit(uint256 amount) public virtual override {}
+
+    function depositTwice(uint256 amount) external {
+        deposit(amount);
+        deposit(amount);
+    }
+}
+
+contract Child is Vault {
+    function deposit(uint256 amount) public override {}
+}
+";
+
+    /// `IVault.sol` and `Vault.sol` on disk, parsed, with the overrides of `deposit` solc
+    /// would record.
+    fn overrides_project() -> (tempfile::TempDir, Value, Url, Url) {
+        let dir = tempfile::tempdir().unwrap();
+        let interface = dir.path().join("IVault.sol");
+        let vault = dir.path().join("Vault.sol");
+        std::fs::write(&interface, IVAULT).unwrap();
+        std::fs::write(&vault, VAULT).unwrap();
+        let mut ast = crate::syntax::parse_files([
+            (interface.to_str().unwrap(), IVAULT),
+            (vault.to_str().unwrap(), VAULT),
+        ]);
+        let deposit = |ast: &Value, contract: &str| {
+            let mut id = None;
+            ast::walk(ast, &mut |node| {
+                if node["nodeType"] == "ContractDefinition" && node["name"] == contract {
+                    ast::walk(node, &mut |member| {
+                        if member["nodeType"] == "FunctionDefinition" && member["name"] == "deposit"
+                        {
+                            id = member["id"].as_u64();
+                        }
+                    });
+                }
+            });
+            id.unwrap()
+        };
+        let (base, vault_id, child) = (
+            deposit(&ast, "IVault"),
+            deposit(&ast, "Vault"),
+            deposit(&ast, "Child"),
+        );
+        fn set_bases(node: &mut Value, id: u64, bases: &Value) {
+            match node {
+                Value::Object(map) if map.get("id").and_then(Value::as_u64) == Some(id) => {
+                    map.insert("baseFunctions".to_string(), bases.clone());
+                }
+                Value::Object(map) => map.values_mut().for_each(|v| set_bases(v, id, bases)),
+                Value::Array(items) => items.iter_mut().for_each(|v| set_bases(v, id, bases)),
+                _ => {}
+            }
+        }
+        set_bases(&mut ast, vault_id, &serde_json::json!([base]));
+        set_bases(&mut ast, child, &serde_json::json!([vault_id]));
+        let interface = Url::from_file_path(interface).unwrap();
+        let vault = Url::from_file_path(vault).unwrap();
+        (dir, ast, interface, vault)
+    }
+
+    #[test]
+    fn test_rename_propagates_to_overrides() {
+        let (_dir, ast, interface, vault) = overrides_project();
+        let renamed = |overrides: bool| {
+            rename_with_overrides(
+                &ast,
+                &interface,
+                Position::new(1, 13),
+                IVAULT.as_bytes(),
+                "supply".to_string(),
+                overrides,
+                &Snapshot::default(),
+            )
+            .unwrap()
+        };
+
+        let (edit, family) = renamed(true);
+        let changes = edit.changes.unwrap();
+        let applied = EditBuilder::with_edits(VAULT, &changes[&vault])
+            .unwrap()
+            .apply();
+        assert_eq!(applied.matches("function supply(").count(), 2);
+        assert_eq!(applied.matches("supply(amount);").count(), 2);
+        assert_eq!(changes[&interface].len(), 1);
+        assert_eq!(family.len(), 4);
+        assert!(family.iter().all(|location| location.uri == vault));
+
+        let (edit, family) = renamed(false);
+        assert_eq!(
+            edit.changes.unwrap().keys().collect::<Vec<_>>(),
+            [&interface]
+        );
+        assert!(family.is_empty());
+
+        // The edits of the inheritance chain ask for confirmation, the renamed one doesn't
+        let (edit, family) = renamed(true);
+        let annotated = annotate_overrides(versioned_edit(edit, &HashMap::new()), &family);
+        let Some(DocumentChanges::Edits(documents)) = &annotated.document_changes else {
+            panic!("expected text document edits");
+        };
+        for document in documents {
+            let expected = document.text_document.uri == vault;
+            for edit in &document.edits {
+                assert_eq!(matches!(edit, OneOf::Right(_)), expected);
+            }
+        }
+        let annotations = annotated.change_annotations.unwrap();
+        assert_eq!(
+            annotations[OVERRIDES_ANNOTATION].needs_confirmation,
+            Some(true)
+        );
+    }
 }
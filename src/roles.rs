@@ -0,0 +1,528 @@
+//! The role graph of `AccessControl` contracts.
+//!
+//! Roles are `bytes32` constants, `MINTER_ROLE` and `DEFAULT_ADMIN_ROLE`, named where a
+//! function is guarded by `onlyRole(ROLE)` or checks `hasRole(ROLE, ...)` or
+//! `_checkRole(ROLE)`, and where the role is granted with `grantRole`, `_grantRole` or
+//! `_setupRole`. `forge-lsp/roleGraph` maps each role to the functions it protects and
+//! the places granting and revoking it; go to implementation on a role constant jumps to
+//! the places granting it. Arguments other than constants, like the `role` parameter of
+//! `AccessControl` itself, are not roles of the graph.
+
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+};
+use tower_lsp::lsp_types::{Location, Range};
+
+use crate::{
+    ast::{self, parse_src},
+    goto::bytes_to_pos,
+    paths,
+};
+
+/// Name of the custom request.
+pub const ROLE_GRAPH_METHOD: &str = "forge-lsp/roleGraph";
+
+/// Calls checking that the caller has the role of their first argument.
+const CHECKS: &[&str] = &["hasRole", "_checkRole"];
+
+/// Modifiers restricting a function to the role of their argument.
+const GUARDS: &[&str] = &["onlyRole"];
+
+/// Calls granting the role of their first argument.
+const GRANTS: &[&str] = &["grantRole", "_grantRole", "_setupRole"];
+
+/// Calls revoking the role of their first argument.
+const REVOKES: &[&str] = &["revokeRole", "_revokeRole", "renounceRole"];
+
+/// Call making its second argument the admin role of its first.
+const SET_ADMIN: &str = "_setRoleAdmin";
+
+/// A function guarded by a role.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProtectedFunction {
+    /// `Contract.function`.
+    pub function: String,
+    /// The name of the function.
+    pub location: Location,
+    /// The modifier or call checking the role.
+    pub check: String,
+}
+
+/// A call granting or revoking a role.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RoleChange {
+    /// `Contract.function` the call is made in.
+    pub function: String,
+    pub location: Location,
+    pub call: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Role {
+    pub name: String,
+    /// Contract declaring the constant, `None` for file-level constants.
+    pub contract: Option<String>,
+    /// The name of the constant.
+    pub location: Location,
+    /// Roles `_setRoleAdmin` makes the admin of this one.
+    pub admins: Vec<String>,
+    pub protects: Vec<ProtectedFunction>,
+    pub grants: Vec<RoleChange>,
+    pub revokes: Vec<RoleChange>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RoleGraph {
+    pub roles: Vec<Role>,
+}
+
+/// Source files of a project read from disk, by forge path.
+struct Sources<'a> {
+    root: &'a Path,
+    files: HashMap<String, Option<Vec<u8>>>,
+}
+
+impl Sources<'_> {
+    /// The location of the `nameLocation` of `node`, or of its `src`, in the file `path`.
+    fn location(&mut self, path: &str, node: &Value) -> Option<Location> {
+        let src = node.get("nameLocation").or_else(|| node.get("src"))?;
+        let (start, length, _) = parse_src(src.as_str()?)?;
+        let file = self.root.join(path);
+        let source = self
+            .files
+            .entry(path.to_string())
+            .or_insert_with(|| std::fs::read(&file).ok());
+        let source = source.as_deref()?;
+        Some(Location {
+            uri: paths::path_to_uri(&file)?,
+            range: Range::new(
+                bytes_to_pos(source, start)?,
+                bytes_to_pos(source, start + length)?,
+            ),
+        })
+    }
+}
+
+/// The name of the function or modifier a call or modifier invocation refers to.
+fn callee_name(expression: &Value) -> Option<&str> {
+    match expression["nodeType"].as_str()? {
+        "Identifier" | "IdentifierPath" => expression["name"].as_str(),
+        "MemberAccess" => expression["memberName"].as_str(),
+        _ => None,
+    }
+}
+
+/// The declaration the `index`th argument of `arguments` refers to.
+fn argument_declaration(arguments: &Value, index: usize) -> Option<u64> {
+    arguments.get(index)?.get("referencedDeclaration")?.as_u64()
+}
+
+/// Every source unit of `ast_data` with its path.
+fn source_units(ast_data: &Value) -> Vec<(&str, &Value)> {
+    let units = ast_data.get("sources").and_then(Value::as_object);
+    let mut units: Vec<(&str, &Value)> = units
+        .into_iter()
+        .flatten()
+        .filter_map(|(path, contents)| {
+            let unit = contents.get(0)?.get("source_file")?.get("ast")?;
+            let path = unit
+                .get("absolutePath")
+                .and_then(Value::as_str)
+                .unwrap_or(path);
+            Some((path, unit))
+        })
+        .collect();
+    units.sort_by_key(|(path, _)| *path);
+    units
+}
+
+/// A modifier or call naming a role.
+#[derive(Debug, Clone)]
+struct Use<'a> {
+    path: &'a str,
+    /// `Contract.function` of the function or modifier it is in.
+    function: String,
+    /// The function it guards, for checks, or the call itself.
+    node: &'a Value,
+    /// Name of the modifier or called function.
+    name: &'a str,
+}
+
+/// The uses of roles in the functions and modifiers of the contracts of `units`.
+#[derive(Debug, Default)]
+struct RoleUses<'a> {
+    /// Checks of each role, by role id.
+    protects: HashMap<u64, Vec<Use<'a>>>,
+    grants: HashMap<u64, Vec<Use<'a>>>,
+    revokes: HashMap<u64, Vec<Use<'a>>>,
+    /// Admin role ids, by role id.
+    admins: HashMap<u64, Vec<u64>>,
+}
+
+fn function_name(contract: &Value, function: &Value) -> String {
+    let name = match function["name"].as_str() {
+        Some(name) if !name.is_empty() => name,
+        _ => function["kind"].as_str().unwrap_or("function"),
+    };
+    format!("{}.{name}", contract["name"].as_str().unwrap_or_default())
+}
+
+fn role_uses<'a>(units: &[(&'a str, &'a Value)]) -> RoleUses<'a> {
+    let mut uses = RoleUses::default();
+    for (path, unit) in units {
+        let contracts = unit
+            .get("nodes")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter(|node| node["nodeType"] == "ContractDefinition");
+        for contract in contracts {
+            let functions = contract
+                .get("nodes")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter(|node| {
+                    matches!(
+                        node["nodeType"].as_str(),
+                        Some("FunctionDefinition" | "ModifierDefinition")
+                    )
+                });
+            for function in functions {
+                let use_of = |node, name| Use {
+                    path,
+                    function: function_name(contract, function),
+                    node,
+                    name,
+                };
+                let modifiers = function.get("modifiers").and_then(Value::as_array);
+                for modifier in modifiers.into_iter().flatten() {
+                    if let Some(guard) = callee_name(&modifier["modifierName"])
+                        .filter(|guard| GUARDS.contains(guard))
+                        && let Some(role) = argument_declaration(&modifier["arguments"], 0)
+                    {
+                        let check = use_of(function, guard);
+                        uses.protects.entry(role).or_default().push(check);
+                    }
+                }
+                let Some(body) = function.get("body") else {
+                    continue;
+                };
+                ast::walk(body, &mut |node| {
+                    if node["nodeType"] != "FunctionCall" {
+                        return;
+                    }
+                    let Some(callee) = callee_name(&node["expression"]) else {
+                        return;
+                    };
+                    let Some(role) = argument_declaration(&node["arguments"], 0) else {
+                        return;
+                    };
+                    if CHECKS.contains(&callee) {
+                        let check = use_of(function, callee);
+                        uses.protects.entry(role).or_default().push(check);
+                    } else if GRANTS.contains(&callee) {
+                        uses.grants
+                            .entry(role)
+                            .or_default()
+                            .push(use_of(node, callee));
+                    } else if REVOKES.contains(&callee) {
+                        uses.revokes
+                            .entry(role)
+                            .or_default()
+                            .push(use_of(node, callee));
+                    } else if callee == SET_ADMIN
+                        && let Some(admin) = argument_declaration(&node["arguments"], 1)
+                    {
+                        uses.admins.entry(role).or_default().push(admin);
+                    }
+                });
+            }
+        }
+    }
+    uses
+}
+
+/// The `bytes32` constants of `units`, with their path and declaring contract, by id.
+fn constants<'a>(
+    units: &[(&'a str, &'a Value)],
+) -> HashMap<u64, (&'a str, Option<&'a str>, &'a Value)> {
+    let mut constants = HashMap::new();
+    for (path, unit) in units {
+        for node in unit
+            .get("nodes")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+        {
+            let contract = (node["nodeType"] == "ContractDefinition")
+                .then(|| node["name"].as_str())
+                .flatten();
+            let members = match contract {
+                Some(_) => node.get("nodes").and_then(Value::as_array),
+                None => None,
+            };
+            let candidates = members
+                .into_iter()
+                .flatten()
+                .chain(contract.is_none().then_some(node));
+            for candidate in candidates {
+                if candidate["nodeType"] == "VariableDeclaration"
+                    && candidate["constant"] == true
+                    && candidate
+                        .pointer("/typeDescriptions/typeString")
+                        .and_then(Value::as_str)
+                        == Some("bytes32")
+                    && let Some(id) = candidate["id"].as_u64()
+                {
+                    constants.insert(id, (*path, contract, candidate));
+                }
+            }
+        }
+    }
+    constants
+}
+
+fn changes(sources: &mut Sources, uses: Option<&Vec<Use>>) -> Vec<RoleChange> {
+    uses.into_iter()
+        .flatten()
+        .filter_map(|change| {
+            Some(RoleChange {
+                function: change.function.clone(),
+                location: sources.location(change.path, change.node)?,
+                call: change.name.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// The role graph of the project AST `ast_data` of the project at `root`: every constant
+/// used as a role, and every `bytes32` constant named `*_ROLE`.
+pub fn role_graph(ast_data: &Value, root: &Path) -> RoleGraph {
+    let units = source_units(ast_data);
+    let uses = role_uses(&units);
+    let constants = constants(&units);
+    let mut sources = Sources {
+        root,
+        files: HashMap::new(),
+    };
+    let name_of = |id: &u64| {
+        constants
+            .get(id)
+            .and_then(|(_, _, node)| node["name"].as_str())
+            .map(str::to_string)
+    };
+
+    let mut roles = BTreeMap::new();
+    for (id, (path, contract, node)) in &constants {
+        let Some(name) = node["name"].as_str() else {
+            continue;
+        };
+        let used = uses.protects.contains_key(id)
+            || uses.grants.contains_key(id)
+            || uses.revokes.contains_key(id)
+            || uses.admins.contains_key(id);
+        if !used && !name.ends_with("_ROLE") {
+            continue;
+        }
+        let Some(location) = sources.location(path, node) else {
+            continue;
+        };
+
+        let mut protects: Vec<ProtectedFunction> = uses
+            .protects
+            .get(id)
+            .into_iter()
+            .flatten()
+            .filter_map(|check| {
+                Some(ProtectedFunction {
+                    function: check.function.clone(),
+                    location: sources.location(check.path, check.node)?,
+                    check: check.name.to_string(),
+                })
+            })
+            .collect();
+        protects.dedup();
+        let grants = changes(&mut sources, uses.grants.get(id));
+        let revokes = changes(&mut sources, uses.revokes.get(id));
+        let mut admins: Vec<String> = uses
+            .admins
+            .get(id)
+            .into_iter()
+            .flatten()
+            .filter_map(name_of)
+            .collect();
+        admins.sort();
+        admins.dedup();
+
+        let role = Role {
+            name: name.to_string(),
+            contract: contract.map(str::to_string),
+            location,
+            admins,
+            protects,
+            grants,
+            revokes,
+        };
+        roles.insert((name.to_string(), role.location.uri.to_string()), role);
+    }
+    RoleGraph {
+        roles: roles.into_values().collect(),
+    }
+}
+
+/// The places granting the role declared by the node `id` of the project AST `ast_data` of
+/// the project at `root`, if it is a `bytes32` constant.
+pub fn grant_sites(ast_data: &Value, root: &Path, id: u64) -> Option<Vec<Location>> {
+    let units = source_units(ast_data);
+    if !constants(&units).contains_key(&id) {
+        return None;
+    }
+    let mut sources = Sources {
+        root,
+        files: HashMap::new(),
+    };
+    let grants = role_uses(&units).grants.remove(&id).unwrap_or_default();
+    Some(
+        changes(&mut sources, Some(&grants))
+            .into_iter()
+            .map(|grant| grant.location)
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tower_lsp::lsp_types::Position;
+
+    const SOURCE: &str = "\
+contract Vault is AccessControl {
+    bytes32 public constant MINTER_ROLE = keccak256(\"MINTER\");
+    bytes32 public constant PAUSER_ROLE = keccak256(\"PAUSER\");
+    constructor() {
+        _grantRole(MINTER_ROLE, msg.sender);
+        _setRoleAdmin(MINTER_ROLE, PAUSER_ROLE);
+    }
+    function mint() public onlyRole(MINTER_ROLE) {}
+    function pause() public {
+        require(hasRole(PAUSER_ROLE, msg.sender));
+        revokeRole(MINTER_ROLE, msg.sender);
+    }
+}
+";
+
+    fn src(text: &str, nth: usize) -> String {
+        let start = SOURCE.match_indices(text).nth(nth).unwrap().0;
+        format!("{start}:{}:0", text.len())
+    }
+
+    fn constant(id: u64, name: &str) -> Value {
+        json!({
+            "nodeType": "VariableDeclaration",
+            "id": id,
+            "name": name,
+            "constant": true,
+            "nameLocation": src(name, 0),
+            "typeDescriptions": { "typeString": "bytes32" }
+        })
+    }
+
+    fn role(id: u64) -> Value {
+        json!({ "nodeType": "Identifier", "referencedDeclaration": id })
+    }
+
+    fn call(name: &str, nth: usize, arguments: Vec<Value>) -> Value {
+        json!({
+            "nodeType": "FunctionCall",
+            "src": src(name, nth),
+            "expression": { "nodeType": "Identifier", "name": name },
+            "arguments": arguments
+        })
+    }
+
+    fn project() -> (tempfile::TempDir, Value) {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("Vault.sol"), SOURCE).unwrap();
+        let function = |name: &str, kind: &str, modifiers: Value, statements: Vec<Value>| {
+            json!({
+                "nodeType": "FunctionDefinition",
+                "name": name,
+                "kind": kind,
+                "nameLocation": if name.is_empty() { src("constructor", 0) } else { src(name, 0) },
+                "modifiers": modifiers,
+                "body": { "nodeType": "Block", "statements": statements }
+            })
+        };
+        let ast_data = ast::mock_ast(
+            "Vault.sol",
+            json!({
+                "nodeType": "SourceUnit",
+                "absolutePath": "Vault.sol",
+                "nodes": [{
+                    "nodeType": "ContractDefinition",
+                    "name": "Vault",
+                    "nodes": [
+                        constant(1, "MINTER_ROLE"),
+                        constant(2, "PAUSER_ROLE"),
+                        function("", "constructor", json!([]), vec![
+                            call("_grantRole", 0, vec![role(1)]),
+                            call("_setRoleAdmin", 0, vec![role(1), role(2)]),
+                        ]),
+                        function("mint", "function", json!([{
+                            "nodeType": "ModifierInvocation",
+                            "modifierName": { "nodeType": "IdentifierPath", "name": "onlyRole" },
+                            "arguments": [role(1)]
+                        }]), vec![]),
+                        function("pause", "function", json!([]), vec![
+                            call("require", 0, vec![call("hasRole", 0, vec![role(2)])]),
+                            call("revokeRole", 0, vec![role(1)]),
+                        ]),
+                    ]
+                }]
+            }),
+        );
+        (dir, ast_data)
+    }
+
+    #[test]
+    fn test_role_graph() {
+        let (dir, ast_data) = project();
+        let graph = role_graph(&ast_data, dir.path());
+        let names: Vec<&str> = graph.roles.iter().map(|role| role.name.as_str()).collect();
+        assert_eq!(names, ["MINTER_ROLE", "PAUSER_ROLE"]);
+
+        let minter = &graph.roles[0];
+        assert_eq!(minter.contract.as_deref(), Some("Vault"));
+        assert_eq!(minter.location.range.start, Position::new(1, 28));
+        assert_eq!(minter.admins, ["PAUSER_ROLE"]);
+        let protects: Vec<(&str, &str)> = minter
+            .protects
+            .iter()
+            .map(|check| (check.function.as_str(), check.check.as_str()))
+            .collect();
+        assert_eq!(protects, [("Vault.mint", "onlyRole")]);
+        assert_eq!(minter.grants.len(), 1);
+        assert_eq!(minter.grants[0].function, "Vault.constructor");
+        assert_eq!(minter.grants[0].location.range.start, Position::new(4, 8));
+        assert_eq!(minter.revokes[0].call, "revokeRole");
+
+        let pauser = &graph.roles[1];
+        assert_eq!(pauser.protects[0].function, "Vault.pause");
+        assert_eq!(pauser.protects[0].check, "hasRole");
+        assert!(pauser.grants.is_empty());
+
+        let sites = grant_sites(&ast_data, dir.path(), 1).unwrap();
+        assert_eq!(sites, [minter.grants[0].location.clone()]);
+        assert_eq!(grant_sites(&ast_data, dir.path(), 2), Some(vec![]));
+        assert_eq!(grant_sites(&ast_data, dir.path(), 3), None);
+    }
+}
@@ -1,45 +1,285 @@
-use crate::{build::build_output_to_diagnostics, lint::lint_output_to_diagnostics};
-use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use crate::{
+    build::build_output_to_diagnostics,
+    build_info::{SizeLimit, find_project_root, read_forge_output},
+    fuzz_config::Fuzzer,
+    lint::lint_output_to_diagnostics,
+    singleflight::SingleFlight,
+};
+use serde::{Deserialize, Deserializer, Serialize, de::IgnoredAny};
+use std::{
+    borrow::Cow,
+    future::Future,
+    hash::Hash,
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::Arc,
+};
 use thiserror::Error;
-use tokio::process::Command;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader},
+    process::Command,
+    sync::mpsc::UnboundedSender,
+};
 use tower_lsp::{
     async_trait,
     lsp_types::{Diagnostic, Url},
 };
 
-pub struct ForgeRunner;
+/// Runner of the `forge` on the `PATH`.
+#[derive(Debug, Clone, Default)]
+pub struct ForgeRunner {
+    /// Size of `forge build` output above which only the sections the server reads are
+    /// parsed.
+    max_output_size: SizeLimit,
+}
+
+impl ForgeRunner {
+    pub fn new(max_output_size: SizeLimit) -> Self {
+        Self { max_output_size }
+    }
+
+    /// Run `forge build --json --no-cache --ast` with the given extra arguments.
+    async fn build_ast(&self, args: &[&str]) -> Result<BuildOutput, RunnerError> {
+        let output = forge_command("build")
+            .args(args)
+            .arg("--json")
+            .arg("--no-cache")
+            .arg("--ast")
+            .env("FOUNDRY_LINT_LINT_ON_BUILD", "false")
+            .output()
+            .await?;
+
+        read_forge_output(&output.stdout, self.max_output_size.get())
+    }
+}
+
+/// Suffix of Foundry test files.
+pub const TEST_FILE_SUFFIX: &str = ".t.sol";
+
+/// Tests a `forge test` run is restricted to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TestFilter<'a> {
+    /// Test file, relative to the project root.
+    pub path: &'a str,
+    pub contract: &'a str,
+    /// A single test function of the contract, or all of them.
+    pub test: Option<&'a str>,
+}
 
 #[async_trait]
 pub trait Runner: Send + Sync {
-    async fn build(&self, file: &str) -> Result<serde_json::Value, RunnerError>;
+    async fn build(&self, file: &str) -> Result<BuildOutput, RunnerError>;
     async fn lint(&self, file: &str) -> Result<serde_json::Value, RunnerError>;
     async fn ast(&self, file: &str) -> Result<serde_json::Value, RunnerError>;
-    async fn get_build_diagnostics(&self, file: &Url) -> Result<Vec<Diagnostic>, RunnerError>;
-    async fn get_lint_diagnostics(&self, file: &Url) -> Result<Vec<Diagnostic>, RunnerError>;
+
+    /// Compile the test file `file` the way `forge test` does, for diagnostics in the test
+    /// context. The default implementation is a plain [`Runner::build`].
+    async fn build_tests(&self, file: &str) -> Result<BuildOutput, RunnerError> {
+        self.build(file).await
+    }
+
+    async fn get_lint_diagnostics(&self, file: &Url) -> Result<Vec<Diagnostic>, RunnerError> {
+        let path: PathBuf = file.to_file_path().map_err(|_| RunnerError::InvalidUrl)?;
+        let path_str = path.to_str().ok_or(RunnerError::InvalidUrl)?;
+        let content = tokio::fs::read_to_string(&path)
+            .await
+            .map_err(|_| RunnerError::ReadError)?;
+        let lint_output = self.lint(path_str).await?;
+        let diagnostics = lint_output_to_diagnostics(&lint_output, path_str, &content);
+        Ok(diagnostics)
+    }
+
+    async fn get_build_diagnostics(&self, file: &Url) -> Result<Vec<Diagnostic>, RunnerError> {
+        let path = file.to_file_path().map_err(|_| RunnerError::InvalidUrl)?;
+        let path_str = path.to_str().ok_or(RunnerError::InvalidUrl)?;
+        let filename = path
+            .file_name()
+            .and_then(|os_str| os_str.to_str())
+            .ok_or(RunnerError::InvalidUrl)?;
+        let content = tokio::fs::read_to_string(&path)
+            .await
+            .map_err(|_| RunnerError::ReadError)?;
+        let build_output = if filename.ends_with(TEST_FILE_SUFFIX) {
+            self.build_tests(path_str).await?
+        } else {
+            self.build(path_str).await?
+        };
+        let diagnostics = build_output_to_diagnostics(&build_output.errors, filename, &content);
+        Ok(diagnostics)
+    }
+
+    /// AST data of every source of the project rooted at `root`, where [`Runner::ast`]
+    /// compiles a single file and the files it imports. The default implementation is
+    /// [`Runner::ast`] on the root.
+    async fn project_ast(&self, root: &str) -> Result<serde_json::Value, RunnerError> {
+        self.ast(root).await
+    }
+
+    /// AST data of the file `file` and the files it imports, like [`Runner::ast`], with the
+    /// `contracts` output of the contract `contract` alone. The default implementation
+    /// filters [`Runner::ast`].
+    async fn contract_ast(
+        &self,
+        file: &str,
+        contract: &str,
+    ) -> Result<serde_json::Value, RunnerError> {
+        let mut output = self.ast(file).await?;
+        if let Some(contracts) = output.get_mut("contracts") {
+            retain_contract(contracts, contract);
+        }
+        Ok(output)
+    }
+
+    /// Generate the markdown documentation of the project at `root` into `out`. Runners
+    /// that never run forge cannot generate documentation.
+    async fn doc(&self, _root: &str, _out: &str) -> Result<(), RunnerError> {
+        Err(RunnerError::SubprocessDisabled)
+    }
+
+    /// Format `source` with the formatter settings of the project at `root`.
+    async fn fmt(&self, _root: &str, _source: &str) -> Result<String, RunnerError> {
+        Err(RunnerError::SubprocessDisabled)
+    }
+
+    /// The source of `file` of the project at `root` with every file it imports inlined.
+    async fn flatten(&self, _root: &str, _file: &str) -> Result<String, RunnerError> {
+        Err(RunnerError::SubprocessDisabled)
+    }
+
+    /// Run the tests of the project at `root` selected by `filter`, returning the
+    /// `forge test --json` results.
+    async fn test(
+        &self,
+        _root: &str,
+        _filter: TestFilter<'_>,
+    ) -> Result<serde_json::Value, RunnerError> {
+        Err(RunnerError::SubprocessDisabled)
+    }
+
+    /// Run the tests selected by `filter` like [`Runner::test`], with the traces of the
+    /// failures (`-vvv`), sending each line forge prints besides the results to `lines` as
+    /// it is printed.
+    async fn test_streamed(
+        &self,
+        _root: &str,
+        _filter: TestFilter<'_>,
+        _lines: UnboundedSender<String>,
+    ) -> Result<serde_json::Value, RunnerError> {
+        Err(RunnerError::SubprocessDisabled)
+    }
+
+    /// Run the tests of the project at `root`, returning the `forge test --gas-report
+    /// --json` gas report.
+    async fn gas_report(&self, _root: &str) -> Result<serde_json::Value, RunnerError> {
+        Err(RunnerError::SubprocessDisabled)
+    }
+
+    /// Compile the project at `root` with the compiler's static gas estimates, returning
+    /// the `forge build --json` output.
+    async fn gas_estimates(&self, _root: &str) -> Result<serde_json::Value, RunnerError> {
+        Err(RunnerError::SubprocessDisabled)
+    }
+
+    /// Storage layout of `contract`, a name or `path:Name`, in the project at `root`, as
+    /// printed by `forge inspect <contract> storage-layout --json`.
+    async fn storage_layout(
+        &self,
+        _root: &str,
+        _contract: &str,
+    ) -> Result<serde_json::Value, RunnerError> {
+        Err(RunnerError::SubprocessDisabled)
+    }
+
+    /// Build the project at `root` with `forge build`, writing its artifacts.
+    async fn rebuild(&self, _root: &str) -> Result<(), RunnerError> {
+        Err(RunnerError::SubprocessDisabled)
+    }
+
+    /// Generate the bindings of the project at `root` with `forge bind` and the given extra
+    /// arguments.
+    async fn bind(&self, _root: &str, _args: &[String]) -> Result<(), RunnerError> {
+        Err(RunnerError::SubprocessDisabled)
+    }
+
+    /// Compile the project at `root` with solc's SMTChecker configured by `config`, an
+    /// inline TOML `model_checker` table, returning the `forge build --json` output.
+    async fn model_check(
+        &self,
+        _root: &str,
+        _config: &str,
+    ) -> Result<serde_json::Value, RunnerError> {
+        Err(RunnerError::SubprocessDisabled)
+    }
+
+    /// Run `fuzzer` on the project at `root` with the configuration file `config`, on
+    /// `contract` only when given, sending each line of its output to `lines` as it is
+    /// printed. Returns whether the fuzzer exited successfully, with no property violated.
+    async fn run_fuzzer(
+        &self,
+        _root: &str,
+        _fuzzer: Fuzzer,
+        _config: &str,
+        _contract: Option<&str>,
+        _lines: UnboundedSender<String>,
+    ) -> Result<bool, RunnerError> {
+        Err(RunnerError::SubprocessDisabled)
+    }
+}
+
+/// Send the lines of `output` to `lines` until it ends or the receiver is dropped.
+async fn forward_lines(output: impl AsyncRead + Unpin, lines: UnboundedSender<String>) {
+    let mut reader = BufReader::new(output).lines();
+    while let Ok(Some(line)) = reader.next_line().await {
+        if lines.send(line).is_err() {
+            break;
+        }
+    }
+}
+
+/// Create a `forge <subcommand>` invocation with the environment shared by all runs.
+fn forge_command(subcommand: &str) -> Command {
+    let mut command = Command::new("forge");
+    command
+        .arg(subcommand)
+        .env("FOUNDRY_DISABLE_NIGHTLY_WARNING", "1");
+    command
+}
+
+/// Create the `forge test --json` invocation of the tests of the project at `root` selected
+/// by `filter`.
+fn forge_test_command(root: &str, filter: TestFilter<'_>) -> Command {
+    let mut command = forge_command("test");
+    command
+        .arg("--root")
+        .arg(root)
+        .arg("--match-path")
+        .arg(filter.path)
+        .arg("--match-contract")
+        .arg(format!("^{}$", filter.contract));
+    if let Some(test) = filter.test {
+        command.arg("--match-test").arg(format!("^{test}$"));
+    }
+    command.arg("--json");
+    command
 }
 
 #[async_trait]
 impl Runner for ForgeRunner {
     async fn lint(&self, file_path: &str) -> Result<serde_json::Value, RunnerError> {
-        let output = Command::new("forge")
-            .arg("lint")
+        let output = forge_command("lint")
             .arg(file_path)
             .arg("--json")
-            .env("FOUNDRY_DISABLE_NIGHTLY_WARNING", "1")
             .output()
             .await?;
 
-        let stderr_str = String::from_utf8_lossy(&output.stderr);
-
-        // Parse JSON output line by line
+        // Parse JSON output line by line, straight from the raw bytes
         let mut diagnostics = Vec::new();
-        for line in stderr_str.lines() {
-            if line.trim().is_empty() {
+        for line in output.stderr.split(|b| *b == b'\n') {
+            if line.trim_ascii().is_empty() {
                 continue;
             }
 
-            match serde_json::from_str::<serde_json::Value>(line) {
+            match serde_json::from_slice::<serde_json::Value>(line) {
                 Ok(value) => diagnostics.push(value),
                 Err(_e) => {
                     continue;
@@ -50,63 +290,530 @@ impl Runner for ForgeRunner {
         Ok(serde_json::Value::Array(diagnostics))
     }
 
-    async fn build(&self, file_path: &str) -> Result<serde_json::Value, RunnerError> {
-        let output = Command::new("forge")
-            .arg("build")
-            .arg(file_path)
+    async fn build(&self, file_path: &str) -> Result<BuildOutput, RunnerError> {
+        self.build_ast(&[file_path]).await
+    }
+
+    async fn ast(&self, file_path: &str) -> Result<serde_json::Value, RunnerError> {
+        self.build_ast(&[file_path])
+            .await
+            .map(BuildOutput::into_value)
+    }
+
+    async fn build_tests(&self, file_path: &str) -> Result<BuildOutput, RunnerError> {
+        // `forge test` runs from the project root, so the test file sees that project's
+        // configuration and remappings, like `forge-std/`, whatever the server's directory
+        let path = Path::new(file_path);
+        let root = find_project_root(path)
+            .or_else(|| path.parent().map(Path::to_path_buf))
+            .ok_or(RunnerError::InvalidUrl)?;
+        let root = root.to_str().ok_or(RunnerError::InvalidUrl)?;
+        self.build_ast(&[file_path, "--root", root]).await
+    }
+
+    async fn project_ast(&self, root: &str) -> Result<serde_json::Value, RunnerError> {
+        // No path filter: let forge compile everything under the root
+        self.build_ast(&["--root", root])
+            .await
+            .map(BuildOutput::into_value)
+    }
+
+    async fn contract_ast(
+        &self,
+        file_path: &str,
+        contract: &str,
+    ) -> Result<serde_json::Value, RunnerError> {
+        // Solc emits ASTs per source unit, so the path filter compiles the defining file and
+        // its imports, and the other contracts' output is dropped before anyone reads it
+        let mut output = self.build_ast(&[file_path]).await?;
+        if let Some(contracts) = &mut output.contracts {
+            retain_contract(contracts, contract);
+        }
+        Ok(output.into_value())
+    }
+
+    async fn doc(&self, root: &str, out: &str) -> Result<(), RunnerError> {
+        // Only the markdown pages are read back, so the mdbook is not built
+        let output = forge_command("doc")
+            .arg("--root")
+            .arg(root)
+            .arg("--out")
+            .arg(out)
+            .output()
+            .await?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(RunnerError::CommandFailed(stderr.trim().to_string()));
+        }
+        Ok(())
+    }
+
+    async fn fmt(&self, root: &str, source: &str) -> Result<String, RunnerError> {
+        // `-` reads the source from stdin, `--raw` prints the formatted code instead of a diff
+        let mut child = forge_command("fmt")
+            .arg("--root")
+            .arg(root)
+            .arg("--raw")
+            .arg("-")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(source.as_bytes()).await?;
+        }
+        let output = child.wait_with_output().await?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(RunnerError::CommandFailed(stderr.trim().to_string()));
+        }
+        String::from_utf8(output.stdout).map_err(|_| RunnerError::ReadError)
+    }
+
+    async fn flatten(&self, root: &str, file: &str) -> Result<String, RunnerError> {
+        // Without `--output` the flattened source is printed
+        let output = forge_command("flatten")
+            .arg("--root")
+            .arg(root)
+            .arg(file)
+            .output()
+            .await?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(RunnerError::CommandFailed(stderr.trim().to_string()));
+        }
+        String::from_utf8(output.stdout).map_err(|_| RunnerError::ReadError)
+    }
+
+    async fn test(
+        &self,
+        root: &str,
+        filter: TestFilter<'_>,
+    ) -> Result<serde_json::Value, RunnerError> {
+        let output = forge_test_command(root, filter).output().await?;
+
+        // Failing tests exit with an error too, so only output without results is one
+        match serde_json::from_slice(&output.stdout) {
+            Ok(results) => Ok(results),
+            Err(_) if !output.status.success() => {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                Err(RunnerError::CommandFailed(stderr.trim().to_string()))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn test_streamed(
+        &self,
+        root: &str,
+        filter: TestFilter<'_>,
+        lines: UnboundedSender<String>,
+    ) -> Result<serde_json::Value, RunnerError> {
+        let mut child = forge_test_command(root, filter)
+            .arg("-vvv")
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+        let stdout = child.stdout.take().ok_or(RunnerError::EmptyOutput)?;
+        let stderr = child.stderr.take().ok_or(RunnerError::EmptyOutput)?;
+
+        // The results are the one JSON line; the error of a run without them is the last
+        // line printed to stderr
+        let read_stdout = async {
+            let mut results = None;
+            let mut reader = BufReader::new(stdout).lines();
+            while let Ok(Some(line)) = reader.next_line().await {
+                match serde_json::from_str::<serde_json::Value>(&line) {
+                    Ok(value) if value.is_object() => results = Some(value),
+                    _ => _ = lines.send(line),
+                }
+            }
+            results
+        };
+        let read_stderr = async {
+            let mut last_error = String::new();
+            let mut reader = BufReader::new(stderr).lines();
+            while let Ok(Some(line)) = reader.next_line().await {
+                if !line.trim().is_empty() {
+                    last_error = line.trim().to_string();
+                }
+                _ = lines.send(line);
+            }
+            last_error
+        };
+        let (results, last_error) = tokio::join!(read_stdout, read_stderr);
+        let status = child.wait().await?;
+        match results {
+            Some(results) => Ok(results),
+            None if !status.success() => Err(RunnerError::CommandFailed(last_error)),
+            None => Err(RunnerError::EmptyOutput),
+        }
+    }
+
+    async fn gas_report(&self, root: &str) -> Result<serde_json::Value, RunnerError> {
+        let output = forge_command("test")
+            .arg("--root")
+            .arg(root)
+            .arg("--gas-report")
+            .arg("--json")
+            .output()
+            .await?;
+
+        // The report is the last JSON line, failing tests still measure the others
+        let report = output
+            .stdout
+            .split(|b| *b == b'\n')
+            .rev()
+            .find_map(|line| serde_json::from_slice::<serde_json::Value>(line).ok())
+            .filter(serde_json::Value::is_array);
+        match report {
+            Some(report) => Ok(report),
+            None if !output.status.success() => {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                Err(RunnerError::CommandFailed(stderr.trim().to_string()))
+            }
+            None => Err(RunnerError::EmptyOutput),
+        }
+    }
+
+    async fn gas_estimates(&self, root: &str) -> Result<serde_json::Value, RunnerError> {
+        let output = forge_command("build")
+            .arg("--root")
+            .arg(root)
             .arg("--json")
             .arg("--no-cache")
-            .arg("--ast")
-            .env("FOUNDRY_DISABLE_NIGHTLY_WARNING", "1")
+            .arg("--extra-output")
+            .arg("evm.gasEstimates")
             .env("FOUNDRY_LINT_LINT_ON_BUILD", "false")
             .output()
             .await?;
+        Ok(serde_json::from_slice(&output.stdout)?)
+    }
 
-        let stdout_str = String::from_utf8_lossy(&output.stdout);
-        let parsed: serde_json::Value = serde_json::from_str(&stdout_str)?;
+    async fn storage_layout(
+        &self,
+        root: &str,
+        contract: &str,
+    ) -> Result<serde_json::Value, RunnerError> {
+        let output = forge_command("inspect")
+            .arg("--root")
+            .arg(root)
+            .arg(contract)
+            .arg("storage-layout")
+            .arg("--json")
+            .env("FOUNDRY_LINT_LINT_ON_BUILD", "false")
+            .output()
+            .await?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(RunnerError::CommandFailed(stderr.trim().to_string()));
+        }
+        Ok(serde_json::from_slice(&output.stdout)?)
+    }
 
-        Ok(parsed)
+    async fn rebuild(&self, root: &str) -> Result<(), RunnerError> {
+        let output = forge_command("build")
+            .arg("--root")
+            .arg(root)
+            .env("FOUNDRY_LINT_LINT_ON_BUILD", "false")
+            .output()
+            .await?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(RunnerError::CommandFailed(stderr.trim().to_string()));
+        }
+        Ok(())
     }
 
-    async fn ast(&self, file_path: &str) -> Result<serde_json::Value, RunnerError> {
-        let output = Command::new("forge")
-            .arg("build")
-            .arg(file_path)
+    async fn bind(&self, root: &str, args: &[String]) -> Result<(), RunnerError> {
+        let output = forge_command("bind")
+            .arg("--root")
+            .arg(root)
+            .args(args)
+            .env("FOUNDRY_LINT_LINT_ON_BUILD", "false")
+            .output()
+            .await?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(RunnerError::CommandFailed(stderr.trim().to_string()));
+        }
+        Ok(())
+    }
+
+    async fn model_check(
+        &self,
+        root: &str,
+        config: &str,
+    ) -> Result<serde_json::Value, RunnerError> {
+        let output = forge_command("build")
+            .arg("--root")
+            .arg(root)
             .arg("--json")
             .arg("--no-cache")
-            .arg("--ast")
-            .env("FOUNDRY_DISABLE_NIGHTLY_WARNING", "1")
+            .env("FOUNDRY_MODEL_CHECKER", config)
             .env("FOUNDRY_LINT_LINT_ON_BUILD", "false")
             .output()
             .await?;
+        Ok(serde_json::from_slice(&output.stdout)?)
+    }
 
-        let stdout_str = String::from_utf8_lossy(&output.stdout);
-        let parsed: serde_json::Value = serde_json::from_str(&stdout_str)?;
+    async fn run_fuzzer(
+        &self,
+        root: &str,
+        fuzzer: Fuzzer,
+        config: &str,
+        contract: Option<&str>,
+        lines: UnboundedSender<String>,
+    ) -> Result<bool, RunnerError> {
+        let args = fuzzer.command_line(root, config, contract);
+        let mut child = Command::new(&args[0])
+            .args(&args[1..])
+            .current_dir(root)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+        let stdout = child.stdout.take().ok_or(RunnerError::EmptyOutput)?;
+        let stderr = child.stderr.take().ok_or(RunnerError::EmptyOutput)?;
+        tokio::join!(
+            forward_lines(stdout, lines.clone()),
+            forward_lines(stderr, lines)
+        );
+        Ok(child.wait().await?.success())
+    }
+}
 
-        Ok(parsed)
+/// Drop every entry of the `contracts` output except the contract called `name`.
+fn retain_contract(contracts: &mut serde_json::Value, name: &str) {
+    if let Some(files) = contracts.as_object_mut() {
+        for contracts in files.values_mut() {
+            if let Some(contracts) = contracts.as_object_mut() {
+                contracts.retain(|contract_name, _| contract_name == name);
+            }
+        }
+        files.retain(|_, contracts| contracts.as_object().is_some_and(|c| !c.is_empty()));
     }
+}
 
-    async fn get_lint_diagnostics(&self, file: &Url) -> Result<Vec<Diagnostic>, RunnerError> {
-        let path: PathBuf = file.to_file_path().map_err(|_| RunnerError::InvalidUrl)?;
-        let path_str = path.to_str().ok_or(RunnerError::InvalidUrl)?;
-        let lint_output = self.lint(path_str).await?;
-        let diagnostics = lint_output_to_diagnostics(&lint_output, path_str);
-        Ok(diagnostics)
+/// A forge build that concurrent callers can share. Build diagnostics and file ASTs come
+/// from the same command, so they share one job.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum BuildJob {
+    /// `forge build --ast` on a file.
+    Compile(String),
+    /// `forge build --ast` on a test file from its project root.
+    CompileTests(String),
+}
+
+/// Any other forge invocation that concurrent callers can share.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum ForgeJob {
+    Lint(String),
+    Project(String),
+    /// `forge build --ast` on a file, keeping the output of one of its contracts.
+    Contract {
+        file: String,
+        contract: String,
+    },
+    /// `forge test --gas-report` on a project root.
+    GasReport(String),
+    /// `forge build` of a project root with the compiler's gas estimates.
+    GasEstimates(String),
+    /// `forge inspect storage-layout` of a contract in a project root.
+    StorageLayout {
+        root: String,
+        contract: String,
+    },
+}
+
+type SharedOutput<T> = Result<Arc<T>, Arc<RunnerError>>;
+
+/// Run `work` as `job`, or wait for the run of `job` in flight, and hand out its output.
+async fn share<K, T, Fut>(
+    flights: &SingleFlight<K, SharedOutput<T>>,
+    job: K,
+    work: impl FnOnce() -> Fut,
+) -> Result<T, RunnerError>
+where
+    K: Eq + Hash + Clone,
+    T: Clone,
+    Fut: Future<Output = Result<T, RunnerError>>,
+{
+    flights
+        .run(job, || async {
+            work().await.map(Arc::new).map_err(Arc::new)
+        })
+        .await
+        .map(Arc::unwrap_or_clone)
+        .map_err(|e| Arc::try_unwrap(e).unwrap_or_else(RunnerError::Shared))
+}
+
+/// Runner decorator that coalesces concurrent identical forge invocations.
+///
+/// When several events ask for the same build at once (a save, a watched-file change and
+/// an explicit command, say), only one process is spawned and every caller receives its
+/// output. Invocations that start after it finishes run forge again.
+pub struct CoalescingRunner<R> {
+    inner: R,
+    builds: SingleFlight<BuildJob, SharedOutput<BuildOutput>>,
+    flights: SingleFlight<ForgeJob, SharedOutput<serde_json::Value>>,
+}
+
+impl<R: Runner> CoalescingRunner<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            builds: SingleFlight::new(),
+            flights: SingleFlight::new(),
+        }
     }
 
-    async fn get_build_diagnostics(&self, file: &Url) -> Result<Vec<Diagnostic>, RunnerError> {
-        let path = file.to_file_path().map_err(|_| RunnerError::InvalidUrl)?;
-        let path_str = path.to_str().ok_or(RunnerError::InvalidUrl)?;
-        let filename = path
-            .file_name()
-            .and_then(|os_str| os_str.to_str())
-            .ok_or(RunnerError::InvalidUrl)?;
-        let content = tokio::fs::read_to_string(&path)
+    async fn run_build(&self, job: BuildJob) -> Result<BuildOutput, RunnerError> {
+        share(&self.builds, job.clone(), || async {
+            match &job {
+                BuildJob::Compile(file) => self.inner.build(file).await,
+                BuildJob::CompileTests(file) => self.inner.build_tests(file).await,
+            }
+        })
+        .await
+    }
+
+    async fn run(&self, job: ForgeJob) -> Result<serde_json::Value, RunnerError> {
+        share(&self.flights, job.clone(), || async {
+            match &job {
+                ForgeJob::Lint(file) => self.inner.lint(file).await,
+                ForgeJob::Project(root) => self.inner.project_ast(root).await,
+                ForgeJob::Contract { file, contract } => {
+                    self.inner.contract_ast(file, contract).await
+                }
+                ForgeJob::GasReport(root) => self.inner.gas_report(root).await,
+                ForgeJob::GasEstimates(root) => self.inner.gas_estimates(root).await,
+                ForgeJob::StorageLayout { root, contract } => {
+                    self.inner.storage_layout(root, contract).await
+                }
+            }
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl<R: Runner> Runner for CoalescingRunner<R> {
+    async fn build(&self, file: &str) -> Result<BuildOutput, RunnerError> {
+        self.run_build(BuildJob::Compile(file.to_string())).await
+    }
+
+    async fn lint(&self, file: &str) -> Result<serde_json::Value, RunnerError> {
+        self.run(ForgeJob::Lint(file.to_string())).await
+    }
+
+    async fn build_tests(&self, file: &str) -> Result<BuildOutput, RunnerError> {
+        self.run_build(BuildJob::CompileTests(file.to_string()))
+            .await
+    }
+
+    async fn ast(&self, file: &str) -> Result<serde_json::Value, RunnerError> {
+        self.run_build(BuildJob::Compile(file.to_string()))
+            .await
+            .map(BuildOutput::into_value)
+    }
+
+    async fn project_ast(&self, root: &str) -> Result<serde_json::Value, RunnerError> {
+        self.run(ForgeJob::Project(root.to_string())).await
+    }
+
+    async fn contract_ast(
+        &self,
+        file: &str,
+        contract: &str,
+    ) -> Result<serde_json::Value, RunnerError> {
+        self.run(ForgeJob::Contract {
+            file: file.to_string(),
+            contract: contract.to_string(),
+        })
+        .await
+    }
+
+    async fn doc(&self, root: &str, out: &str) -> Result<(), RunnerError> {
+        // Each run writes to its own output directory, so there is nothing to share
+        self.inner.doc(root, out).await
+    }
+
+    async fn fmt(&self, root: &str, source: &str) -> Result<String, RunnerError> {
+        self.inner.fmt(root, source).await
+    }
+
+    async fn flatten(&self, root: &str, file: &str) -> Result<String, RunnerError> {
+        self.inner.flatten(root, file).await
+    }
+
+    async fn test(
+        &self,
+        root: &str,
+        filter: TestFilter<'_>,
+    ) -> Result<serde_json::Value, RunnerError> {
+        self.inner.test(root, filter).await
+    }
+
+    async fn test_streamed(
+        &self,
+        root: &str,
+        filter: TestFilter<'_>,
+        lines: UnboundedSender<String>,
+    ) -> Result<serde_json::Value, RunnerError> {
+        self.inner.test_streamed(root, filter, lines).await
+    }
+
+    async fn gas_report(&self, root: &str) -> Result<serde_json::Value, RunnerError> {
+        self.run(ForgeJob::GasReport(root.to_string())).await
+    }
+
+    async fn gas_estimates(&self, root: &str) -> Result<serde_json::Value, RunnerError> {
+        self.run(ForgeJob::GasEstimates(root.to_string())).await
+    }
+
+    async fn storage_layout(
+        &self,
+        root: &str,
+        contract: &str,
+    ) -> Result<serde_json::Value, RunnerError> {
+        self.run(ForgeJob::StorageLayout {
+            root: root.to_string(),
+            contract: contract.to_string(),
+        })
+        .await
+    }
+
+    async fn rebuild(&self, root: &str) -> Result<(), RunnerError> {
+        self.inner.rebuild(root).await
+    }
+
+    async fn bind(&self, root: &str, args: &[String]) -> Result<(), RunnerError> {
+        self.inner.bind(root, args).await
+    }
+
+    async fn model_check(
+        &self,
+        root: &str,
+        config: &str,
+    ) -> Result<serde_json::Value, RunnerError> {
+        self.inner.model_check(root, config).await
+    }
+
+    async fn run_fuzzer(
+        &self,
+        root: &str,
+        fuzzer: Fuzzer,
+        config: &str,
+        contract: Option<&str>,
+        lines: UnboundedSender<String>,
+    ) -> Result<bool, RunnerError> {
+        self.inner
+            .run_fuzzer(root, fuzzer, config, contract, lines)
             .await
-            .map_err(|_| RunnerError::ReadError)?;
-        let build_output = self.build(path_str).await?;
-        let diagnostics = build_output_to_diagnostics(&build_output, filename, &content);
-        Ok(diagnostics)
     }
 }
 
@@ -122,34 +829,339 @@ pub enum RunnerError {
     EmptyOutput,
     #[error("ReadError")]
     ReadError,
+    #[error("Failed to read build info: {0}")]
+    ReadBuildInfo(std::io::Error),
+    #[error("Running forge is disabled (--no-subprocess)")]
+    SubprocessDisabled,
+    #[error("Workspace is not trusted, not running forge")]
+    Untrusted,
+    #[error("No build info found for {0}, run `forge build` first")]
+    MissingBuildInfo(String),
+    #[error("forge failed: {0}")]
+    CommandFailed(String),
+    /// An error from a forge run that was shared with other callers.
+    #[error(transparent)]
+    Shared(Arc<RunnerError>),
+}
+
+/// Source span of a compiler error. Strings borrow from the parsed build output.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SourceLocation<'a> {
+    #[serde(borrow)]
+    pub file: Cow<'a, str>,
+    #[serde(default)]
+    pub start: i32, // Changed to i32 to handle -1 values
+    #[serde(default)]
+    pub end: i32, // Changed to i32 to handle -1 values
+}
+
+/// A single entry of the `errors` array in `forge build --json` output.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ForgeDiagnosticMessage<'a> {
+    #[serde(
+        rename = "sourceLocation",
+        borrow,
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub source_location: Option<SourceLocation<'a>>,
+    #[serde(rename = "type", borrow, default)]
+    pub error_type: Cow<'a, str>,
+    #[serde(borrow, default)]
+    pub component: Cow<'a, str>,
+    #[serde(borrow, default)]
+    pub severity: Cow<'a, str>,
+    #[serde(
+        rename = "errorCode",
+        borrow,
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub error_code: Option<Cow<'a, str>>,
+    #[serde(borrow, default)]
+    pub message: Cow<'a, str>,
+    #[serde(
+        rename = "formattedMessage",
+        borrow,
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub formatted_message: Option<Cow<'a, str>>,
+}
+
+fn owned(text: Cow<'_, str>) -> Cow<'static, str> {
+    Cow::Owned(text.into_owned())
+}
+
+impl ForgeDiagnosticMessage<'_> {
+    /// The message with its strings no longer borrowed.
+    pub fn into_owned(self) -> ForgeDiagnosticMessage<'static> {
+        ForgeDiagnosticMessage {
+            source_location: self.source_location.map(|loc| SourceLocation {
+                file: owned(loc.file),
+                start: loc.start,
+                end: loc.end,
+            }),
+            error_type: owned(self.error_type),
+            component: owned(self.component),
+            severity: owned(self.severity),
+            error_code: self.error_code.map(owned),
+            message: owned(self.message),
+            formatted_message: self.formatted_message.map(owned),
+        }
+    }
+}
+
+/// An entry of `errors`, which doesn't fail the whole output when it isn't a compiler error.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ErrorEntry<'a> {
+    Message(#[serde(borrow)] ForgeDiagnosticMessage<'a>),
+    Invalid(IgnoredAny),
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-pub struct SourceLocation {
-    file: String,
-    start: i32, // Changed to i32 to handle -1 values
-    end: i32,   // Changed to i32 to handle -1 values
-}
-
-#[derive(Debug, Deserialize, Serialize)]
-pub struct ForgeDiagnosticMessage {
-    #[serde(rename = "sourceLocation")]
-    source_location: SourceLocation,
-    #[serde(rename = "type")]
-    error_type: String,
-    component: String,
-    severity: String,
-    #[serde(rename = "errorCode")]
-    error_code: String,
-    message: String,
-    #[serde(rename = "formattedMessage")]
-    formatted_message: String,
-}
-
-#[derive(Debug, Deserialize, Serialize)]
-pub struct CompileOutput {
-    errors: Option<Vec<ForgeDiagnosticMessage>>,
-    sources: serde_json::Value,
-    contracts: serde_json::Value,
-    build_infos: Vec<serde_json::Value>,
+/// The entries of `errors` that are compiler errors, each deserialized on its own.
+fn deserialize_errors<'de: 'a, 'a, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<ForgeDiagnosticMessage<'a>>, D::Error> {
+    let entries = Option::<Vec<ErrorEntry<'a>>>::deserialize(deserializer)?;
+    Ok(entries
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| match entry {
+            ErrorEntry::Message(message) => Some(message),
+            ErrorEntry::Invalid(_) => None,
+        })
+        .collect())
+}
+
+#[derive(Deserialize)]
+struct CompilerErrors<'a> {
+    #[serde(borrow, default, deserialize_with = "deserialize_errors")]
+    errors: Vec<ForgeDiagnosticMessage<'a>>,
+}
+
+/// The compiler errors of the build output `output`, borrowing their strings from it.
+pub fn compiler_errors(output: &serde_json::Value) -> Vec<ForgeDiagnosticMessage<'_>> {
+    CompilerErrors::deserialize(output)
+        .map(|output| output.errors)
+        .unwrap_or_default()
+}
+
+/// `forge build --json` output, deserialized straight from the bytes forge printed. The
+/// compiler errors borrow their strings from them; `contracts` is read as a `C`,
+/// [`serde::de::IgnoredAny`] skipping it.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct CompileOutput<'a, C> {
+    #[serde(borrow, default, deserialize_with = "deserialize_errors")]
+    pub errors: Vec<ForgeDiagnosticMessage<'a>>,
+    #[serde(default)]
+    pub sources: serde_json::Map<String, serde_json::Value>,
+    #[serde(default = "Option::default")]
+    pub contracts: Option<C>,
+    #[serde(default)]
+    pub build_infos: Vec<serde_json::Value>,
+}
+
+/// Output of a build, kept past the bytes forge printed.
+pub type BuildOutput = CompileOutput<'static, serde_json::Value>;
+
+impl<C> CompileOutput<'_, C> {
+    /// The output with `contracts` in place of its own and the errors no longer borrowed.
+    pub fn into_owned<T>(self, contracts: Option<T>) -> CompileOutput<'static, T> {
+        CompileOutput {
+            errors: self
+                .errors
+                .into_iter()
+                .map(ForgeDiagnosticMessage::into_owned)
+                .collect(),
+            sources: self.sources,
+            contracts,
+            build_infos: self.build_infos,
+        }
+    }
+}
+
+impl BuildOutput {
+    /// The output of the build `output`, a value shaped like forge's JSON.
+    pub fn from_value(mut output: serde_json::Value) -> Self {
+        let errors = compiler_errors(&output)
+            .into_iter()
+            .map(ForgeDiagnosticMessage::into_owned)
+            .collect();
+        let mut take = |key: &str| output.get_mut(key).map(serde_json::Value::take);
+        Self {
+            errors,
+            sources: match take("sources") {
+                Some(serde_json::Value::Object(sources)) => sources,
+                _ => serde_json::Map::new(),
+            },
+            contracts: take("contracts"),
+            build_infos: match take("build_infos") {
+                Some(serde_json::Value::Array(build_infos)) => build_infos,
+                _ => Vec::new(),
+            },
+        }
+    }
+
+    /// The output as the JSON forge printed, with `contracts` when they were kept.
+    pub fn into_value(self) -> serde_json::Value {
+        let mut output = serde_json::json!({
+            "errors": self.errors,
+            "sources": self.sources,
+            "build_infos": self.build_infos,
+        });
+        if let Some(contracts) = self.contracts {
+            output["contracts"] = contracts;
+        }
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Build output telling the file it was built for and whether it was the test build.
+    fn output_of(file: &str, tests: bool) -> BuildOutput {
+        BuildOutput {
+            sources: serde_json::Map::from_iter(
+                ["src/C.sol", "src/A.sol"].map(|path| (path.to_string(), serde_json::json!([]))),
+            ),
+            contracts: Some(serde_json::json!({
+                "src/C.sol": { "C": [{}], "E": [{}] },
+                "src/A.sol": { "A": [{}] }
+            })),
+            build_infos: vec![serde_json::json!({ "file": file, "tests": tests })],
+            ..Default::default()
+        }
+    }
+
+    #[derive(Default)]
+    struct SlowRunner {
+        builds: AtomicUsize,
+        test_builds: AtomicUsize,
+        lints: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl Runner for SlowRunner {
+        async fn build(&self, file: &str) -> Result<BuildOutput, RunnerError> {
+            self.builds.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            if file.ends_with("Broken.sol") {
+                return Err(RunnerError::EmptyOutput);
+            }
+            Ok(output_of(file, false))
+        }
+
+        async fn lint(&self, _: &str) -> Result<serde_json::Value, RunnerError> {
+            self.lints.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            Ok(serde_json::json!([]))
+        }
+
+        async fn ast(&self, file: &str) -> Result<serde_json::Value, RunnerError> {
+            self.build(file).await.map(BuildOutput::into_value)
+        }
+
+        async fn build_tests(&self, file: &str) -> Result<BuildOutput, RunnerError> {
+            self.test_builds.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            Ok(output_of(file, true))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_coalescing_runner_shares_concurrent_builds() {
+        let runner = CoalescingRunner::new(SlowRunner::default());
+
+        let (build, ast, lint) = tokio::join!(
+            runner.build("src/A.sol"),
+            runner.ast("src/A.sol"),
+            runner.lint("src/A.sol")
+        );
+
+        assert_eq!(build.unwrap().build_infos[0]["file"], "src/A.sol");
+        assert_eq!(ast.unwrap()["build_infos"][0]["file"], "src/A.sol");
+        lint.unwrap();
+        assert_eq!(runner.inner.builds.load(Ordering::SeqCst), 1);
+        assert_eq!(runner.inner.lints.load(Ordering::SeqCst), 1);
+
+        // Different targets and later requests get their own run
+        let _ = tokio::join!(runner.build("src/A.sol"), runner.build("src/B.sol"));
+        assert_eq!(runner.inner.builds.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_test_files_compile_in_the_test_context() {
+        let dir = tempfile::tempdir().unwrap();
+        let test_file = dir.path().join("Vault.t.sol");
+        std::fs::write(&test_file, "contract VaultTest {}").unwrap();
+        let source_file = dir.path().join("Vault.sol");
+        std::fs::write(&source_file, "contract Vault {}").unwrap();
+        let runner = CoalescingRunner::new(SlowRunner::default());
+
+        let test_uri = Url::from_file_path(&test_file).unwrap();
+        let (a, b) = tokio::join!(
+            runner.get_build_diagnostics(&test_uri),
+            runner.build_tests(test_file.to_str().unwrap())
+        );
+        a.unwrap();
+        assert_eq!(b.unwrap().build_infos[0]["tests"], true);
+        assert_eq!(runner.inner.test_builds.load(Ordering::SeqCst), 1);
+        assert_eq!(runner.inner.builds.load(Ordering::SeqCst), 0);
+
+        runner
+            .get_build_diagnostics(&Url::from_file_path(&source_file).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(runner.inner.test_builds.load(Ordering::SeqCst), 1);
+        assert_eq!(runner.inner.builds.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_retain_contract() {
+        let mut contracts = serde_json::json!({
+            "src/C.sol": { "C": [{}], "E": [{}] },
+            "src/A.sol": { "A": [{}] }
+        });
+
+        retain_contract(&mut contracts, "E");
+
+        let files = contracts.as_object().unwrap();
+        assert_eq!(files.len(), 1);
+        assert!(files["src/C.sol"].get("E").is_some());
+        assert!(files["src/C.sol"].get("C").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_contract_ast_keeps_one_contract() {
+        let runner = CoalescingRunner::new(SlowRunner::default());
+        let output = runner.contract_ast("src/C.sol", "E").await.unwrap();
+        assert_eq!(runner.inner.builds.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            output["contracts"],
+            serde_json::json!({ "src/C.sol": { "E": [{}] } })
+        );
+        // The sources stay, so references into imported files still resolve
+        assert_eq!(output["sources"].as_object().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_coalescing_runner_shares_errors() {
+        let runner = CoalescingRunner::new(SlowRunner::default());
+
+        let (a, b) = tokio::join!(
+            runner.build("src/Broken.sol"),
+            runner.build("src/Broken.sol")
+        );
+
+        assert_eq!(runner.inner.builds.load(Ordering::SeqCst), 1);
+        for result in [a, b] {
+            let err = result.unwrap_err();
+            assert_eq!(err.to_string(), RunnerError::EmptyOutput.to_string());
+        }
+    }
 }
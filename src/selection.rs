@@ -0,0 +1,135 @@
+//! Selection ranges: the chain of enclosing AST nodes around a position, used to expand
+//! a selection one syntactic level at a time, from an identifier through its expressions,
+//! statement, function and contract to the whole file.
+
+use serde_json::Value;
+use tower_lsp::lsp_types::{Position, Range, SelectionRange, Url};
+
+use crate::{
+    ast::{self, parse_src},
+    goto::{bytes_to_pos, pos_to_bytes},
+};
+
+/// Byte spans of the nodes containing `offset`, innermost first and without repeats.
+/// Statements whose span stops before their `;` also get a span including it.
+fn enclosing_spans(source_unit: &Value, source_bytes: &[u8], offset: usize) -> Vec<(usize, usize)> {
+    let mut spans = Vec::new();
+    ast::walk(source_unit, &mut |node| {
+        if let Some((start, length, _)) =
+            node.get("src").and_then(Value::as_str).and_then(parse_src)
+            && start <= offset
+            && offset <= start + length
+        {
+            let end = start + length;
+            spans.push((start, end));
+            let is_statement = node
+                .get("nodeType")
+                .and_then(Value::as_str)
+                .is_some_and(|node_type| node_type.ends_with("Statement"));
+            if is_statement && source_bytes.get(end) == Some(&b';') {
+                spans.push((start, end + 1));
+            }
+        }
+    });
+    // The whole file, without trailing whitespace, which the source unit's span also ends at
+    let file_end = source_bytes.trim_ascii_end().len();
+    spans.push((0, file_end));
+    for (start, end) in &mut spans {
+        *end = (*end).min(file_end).max(*start);
+    }
+    spans.sort_by_key(|(start, end)| end - start);
+    spans.dedup();
+    spans
+}
+
+fn selection_range(source_unit: &Value, source_bytes: &[u8], position: Position) -> SelectionRange {
+    let offset = pos_to_bytes(source_bytes, position);
+    let ranges = enclosing_spans(source_unit, source_bytes, offset)
+        .into_iter()
+        .filter_map(|(start, end)| {
+            Some(Range::new(
+                bytes_to_pos(source_bytes, start)?,
+                bytes_to_pos(source_bytes, end)?,
+            ))
+        });
+
+    // Build the chain from the outermost node in
+    let mut selection: Option<SelectionRange> = None;
+    for range in ranges.collect::<Vec<_>>().into_iter().rev() {
+        selection = Some(SelectionRange {
+            range,
+            parent: selection.map(Box::new),
+        });
+    }
+    selection.unwrap_or(SelectionRange {
+        range: Range::new(position, position),
+        parent: None,
+    })
+}
+
+/// One selection range per position of `uri`, in the order of `positions`.
+pub fn selection_ranges(
+    ast_data: &Value,
+    uri: &Url,
+    source_bytes: &[u8],
+    positions: &[Position],
+) -> Vec<SelectionRange> {
+    let Some(source_unit) = ast::source_unit(ast_data, uri) else {
+        return positions
+            .iter()
+            .map(|position| SelectionRange {
+                range: Range::new(*position, *position),
+                parent: None,
+            })
+            .collect();
+    };
+    positions
+        .iter()
+        .map(|position| selection_range(source_unit, source_bytes, *position))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax;
+
+    const SOURCE: &str = "\
+pragma solidity ^0.8.0;
+
+contract Vault {
+    function deposit(uint256 amount) public {
+        total += amount * 2;
+    }
+}
+";
+
+    #[test]
+    fn test_selection_expands_to_enclosing_nodes() {
+        let path = "/project/src/Vault.sol";
+        let uri = Url::from_file_path(path).unwrap();
+        let ast = syntax::parse(path, SOURCE);
+
+        let ranges = selection_ranges(&ast, &uri, SOURCE.as_bytes(), &[Position::new(4, 18)]);
+        let mut texts = Vec::new();
+        let mut selection = Some(&ranges[0]);
+        while let Some(current) = selection {
+            let start = pos_to_bytes(SOURCE.as_bytes(), current.range.start);
+            let end = pos_to_bytes(SOURCE.as_bytes(), current.range.end);
+            texts.push(&SOURCE[start..end]);
+            selection = current.parent.as_deref();
+        }
+        assert_eq!(
+            texts[..4],
+            [
+                "amount",
+                "amount * 2",
+                "total += amount * 2",
+                "total += amount * 2;"
+            ]
+        );
+        assert!(texts[4].starts_with('{'));
+        assert!(texts[texts.len() - 2].starts_with("contract Vault"));
+        assert_eq!(*texts.last().unwrap(), SOURCE.trim_end());
+    }
+}
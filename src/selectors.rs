@@ -0,0 +1,693 @@
+//! Functions grouped by their 4-byte selector. Proxies and diamonds dispatch on the
+//! selector alone, so every function sharing one is a potential implementation, and two
+//! differently named functions sharing one is a clash.
+
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use tiny_keccak::{Hasher, Keccak};
+use tower_lsp::lsp_types::{
+    Diagnostic, DiagnosticRelatedInformation, DiagnosticSeverity, Location, NumberOrString,
+    Position, Range, Url,
+};
+
+use crate::{
+    ast::{self, parse_src},
+    goto::bytes_to_pos,
+    paths,
+};
+
+/// Command returning every function in the workspace that shares the selector of the
+/// function at a position. Arguments: the file URI and the position.
+pub const SELECTOR_IMPLEMENTATIONS_COMMAND: &str = "forge-lsp.selectorImplementations";
+
+/// Diagnostic code for two differently named functions with the same selector.
+pub const SELECTOR_CLASH_CODE: &str = "selector-clash";
+
+/// Diagnostic code for two functions with the same selector in one inheritance set.
+pub const SELECTOR_COLLISION_CODE: &str = "selector-collision";
+
+/// A public or external function (or public state variable getter) with a selector.
+#[derive(Debug, Clone)]
+pub struct SelectorFunction {
+    pub id: u64,
+    /// Id of the declaring contract.
+    pub contract_id: u64,
+    /// `0x`-prefixed selector, e.g. `0xa9059cbb`.
+    pub selector: String,
+    pub contract: String,
+    pub name: String,
+    /// Source path as reported by forge.
+    pub path: String,
+    /// Byte span of the function name.
+    pub name_span: (usize, usize),
+}
+
+impl SelectorFunction {
+    pub fn qualified_name(&self) -> String {
+        format!("{}.{}", self.contract, self.name)
+    }
+
+    fn location(&self, source: &[u8]) -> Option<Location> {
+        let (start, length) = self.name_span;
+        Some(Location {
+            uri: paths::path_to_uri(&paths::resolve_source_path(&self.path)?)?,
+            range: Range {
+                start: bytes_to_pos(source, start)?,
+                end: bytes_to_pos(source, start + length)?,
+            },
+        })
+    }
+}
+
+/// Collect every contract member with a `functionSelector` from all sources.
+pub fn collect_functions(ast_data: &Value) -> Vec<SelectorFunction> {
+    let mut functions = Vec::new();
+    let Some(sources) = ast_data.get("sources").and_then(Value::as_object) else {
+        return functions;
+    };
+
+    for (path, contents) in sources {
+        let Some(source_ast) = contents
+            .get(0)
+            .and_then(|content| content.get("source_file"))
+            .and_then(|source_file| source_file.get("ast"))
+        else {
+            continue;
+        };
+        let path = source_ast
+            .get("absolutePath")
+            .and_then(Value::as_str)
+            .unwrap_or(path);
+
+        let contracts = source_ast
+            .get("nodes")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter(|node| node["nodeType"] == "ContractDefinition");
+        for contract in contracts {
+            let contract_name = contract["name"].as_str().unwrap_or_default();
+            let contract_id = contract["id"].as_u64().unwrap_or_default();
+            let members = contract
+                .get("nodes")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten();
+            functions.extend(members.filter_map(|member| {
+                let selector = member.get("functionSelector")?.as_str()?;
+                let (start, length, _) = parse_src(
+                    member
+                        .get("nameLocation")
+                        .or_else(|| member.get("src"))?
+                        .as_str()?,
+                )?;
+                Some(SelectorFunction {
+                    id: member.get("id")?.as_u64()?,
+                    contract_id,
+                    selector: format!("0x{selector}"),
+                    contract: contract_name.to_string(),
+                    name: member.get("name")?.as_str()?.to_string(),
+                    path: path.to_string(),
+                    name_span: (start, length),
+                })
+            }));
+        }
+    }
+
+    functions
+}
+
+/// Keccak-256 of `bytes`.
+pub fn keccak256(bytes: &[u8]) -> [u8; 32] {
+    let mut hasher = Keccak::v256();
+    hasher.update(bytes);
+    let mut hash = [0; 32];
+    hasher.finalize(&mut hash);
+    hash
+}
+
+/// The ABI type of the type name `type_name`, struct members resolved through `index`.
+/// `None` for types without one, like mappings.
+fn abi_type(type_name: &Value, index: &HashMap<u64, &Value>) -> Option<String> {
+    match type_name.get("nodeType")?.as_str()? {
+        "ElementaryTypeName" => {
+            let name = type_name.get("name")?.as_str()?;
+            // `address payable` is an `address` in signatures
+            Some(match name.split(' ').next()? {
+                "uint" => "uint256".to_string(),
+                "int" => "int256".to_string(),
+                "byte" => "bytes1".to_string(),
+                name => name.to_string(),
+            })
+        }
+        "ArrayTypeName" => {
+            let length = type_name
+                .get("length")
+                .and_then(|length| length.get("value"))
+                .and_then(Value::as_str)
+                .unwrap_or_default();
+            Some(format!(
+                "{}[{length}]",
+                abi_type(type_name.get("baseType")?, index)?
+            ))
+        }
+        "UserDefinedTypeName" => {
+            let id = type_name.get("referencedDeclaration")?.as_u64()?;
+            let declaration = index.get(&id)?;
+            match declaration.get("nodeType")?.as_str()? {
+                "ContractDefinition" => Some("address".to_string()),
+                "EnumDefinition" => Some("uint8".to_string()),
+                "UserDefinedValueTypeDefinition" => {
+                    abi_type(declaration.get("underlyingType")?, index)
+                }
+                "StructDefinition" => {
+                    let members = declaration
+                        .get("members")?
+                        .as_array()?
+                        .iter()
+                        .map(|member| abi_type(member.get("typeName")?, index))
+                        .collect::<Option<Vec<_>>>()?;
+                    Some(format!("({})", members.join(",")))
+                }
+                _ => None,
+            }
+        }
+        "FunctionTypeName" => Some("function".to_string()),
+        _ => None,
+    }
+}
+
+/// The canonical signature of the function, error or event `definition`, like
+/// `transfer(address,uint256)`.
+pub fn canonical_signature(definition: &Value, index: &HashMap<u64, &Value>) -> Option<String> {
+    let types = definition
+        .get("parameters")?
+        .get("parameters")?
+        .as_array()?
+        .iter()
+        .map(|parameter| abi_type(parameter.get("typeName")?, index))
+        .collect::<Option<Vec<_>>>()?;
+    Some(format!(
+        "{}({})",
+        definition.get("name")?.as_str()?,
+        types.join(",")
+    ))
+}
+
+/// What the hash of a signature identifies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureHashKind {
+    /// 4-byte selector of a function or error.
+    Selector,
+    /// 32-byte first topic of an event.
+    Topic,
+}
+
+/// The selector or topic of a definition, with the signature it hashes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignatureHash {
+    pub kind: SignatureHashKind,
+    pub signature: String,
+    /// `0x`-prefixed hash.
+    pub hash: String,
+}
+
+impl SignatureHash {
+    /// The hash as a line of a hover.
+    pub fn to_markdown(&self) -> String {
+        let label = match self.kind {
+            SignatureHashKind::Selector => "Selector",
+            SignatureHashKind::Topic => "Topic",
+        };
+        format!("{label}: `{}` · `{}`", self.hash, self.signature)
+    }
+}
+
+/// The selector of the externally visible function or error `definition`, or the topic of
+/// the non-anonymous event `definition`. Solc reports them in the AST since 0.8.20 for
+/// errors and events; for older compilers they are computed from the signature.
+pub fn signature_hash(definition: &Value, index: &HashMap<u64, &Value>) -> Option<SignatureHash> {
+    let (kind, reported) = match definition.get("nodeType")?.as_str()? {
+        "FunctionDefinition" => {
+            if definition.get("kind").and_then(Value::as_str) != Some("function")
+                || !matches!(
+                    definition.get("visibility").and_then(Value::as_str),
+                    Some("public" | "external")
+                )
+            {
+                return None;
+            }
+            (SignatureHashKind::Selector, "functionSelector")
+        }
+        "ErrorDefinition" => (SignatureHashKind::Selector, "errorSelector"),
+        "EventDefinition" => {
+            if definition.get("anonymous").and_then(Value::as_bool) == Some(true) {
+                return None;
+            }
+            (SignatureHashKind::Topic, "eventSelector")
+        }
+        _ => return None,
+    };
+    let signature = canonical_signature(definition, index)?;
+    let hash = match definition.get(reported).and_then(Value::as_str) {
+        Some(hash) => format!("0x{hash}"),
+        None => {
+            let hash = keccak256(signature.as_bytes());
+            let length = match kind {
+                SignatureHashKind::Selector => 4,
+                SignatureHashKind::Topic => 32,
+            };
+            let hex: String = hash[..length].iter().map(|b| format!("{b:02x}")).collect();
+            format!("0x{hex}")
+        }
+    };
+    Some(SignatureHash {
+        kind,
+        signature,
+        hash,
+    })
+}
+
+/// Every other function sharing the selector of the function at `position`, either
+/// its declaration or a call to it.
+pub fn selector_implementations(
+    ast_data: &Value,
+    file_uri: &Url,
+    position: Position,
+    source_bytes: &[u8],
+) -> Vec<Location> {
+    let Some(symbol) = ast::symbol_at_position(ast_data, file_uri, position, source_bytes) else {
+        return vec![];
+    };
+    let functions = collect_functions(ast_data);
+    let Some(target) = functions
+        .iter()
+        .find(|function| function.id == symbol.declaration_id)
+    else {
+        return vec![];
+    };
+
+    let mut sources = SourceCache::default();
+    functions
+        .iter()
+        .filter(|function| function.selector == target.selector && function.id != target.id)
+        .filter_map(|function| sources.location(function))
+        .collect()
+}
+
+/// Source files read from disk, by forge path, to turn byte offsets into positions.
+#[derive(Default)]
+struct SourceCache {
+    sources: HashMap<String, Option<Vec<u8>>>,
+}
+
+impl SourceCache {
+    fn location(&mut self, function: &SelectorFunction) -> Option<Location> {
+        let source = self
+            .sources
+            .entry(function.path.clone())
+            .or_insert_with(|| std::fs::read(paths::resolve_source_path(&function.path)?).ok());
+        function.location(source.as_deref()?)
+    }
+}
+
+/// Warnings for each function in `file_uri` whose selector is shared by a differently
+/// named function elsewhere in the AST.
+pub fn selector_clashes(ast_data: &Value, file_uri: &Url, source_bytes: &[u8]) -> Vec<Diagnostic> {
+    let Some(file_key) = paths::uri_to_key(file_uri) else {
+        return vec![];
+    };
+    let functions = collect_functions(ast_data);
+    let mut by_selector: HashMap<&str, Vec<&SelectorFunction>> = HashMap::new();
+    for function in &functions {
+        by_selector
+            .entry(&function.selector)
+            .or_default()
+            .push(function);
+    }
+
+    functions
+        .iter()
+        .filter(|function| {
+            paths::normalize_path(&function.path) == file_key
+                || paths::canonical_key(&function.path) == file_key
+        })
+        .filter_map(|function| {
+            let mut clashing: Vec<String> = by_selector[function.selector.as_str()]
+                .iter()
+                .filter(|other| other.name != function.name)
+                .map(|other| format!("`{}`", other.qualified_name()))
+                .collect();
+            if clashing.is_empty() {
+                return None;
+            }
+            clashing.sort();
+            clashing.dedup();
+
+            let range = function.location(source_bytes)?.range;
+            Some(Diagnostic {
+                range,
+                severity: Some(DiagnosticSeverity::WARNING),
+                code: Some(NumberOrString::String(SELECTOR_CLASH_CODE.to_string())),
+                source: Some("forge-lsp".to_string()),
+                message: format!(
+                    "selector {} of `{}` clashes with {}",
+                    function.selector,
+                    function.qualified_name(),
+                    clashing.join(", ")
+                ),
+                ..Diagnostic::default()
+            })
+        })
+        .collect()
+}
+
+/// Errors for contracts in `file_uri` whose inheritance set contains externally visible
+/// functions with the same selector, with related information at each signature.
+pub fn inheritance_collisions(
+    ast_data: &Value,
+    file_uri: &Url,
+    source_bytes: &[u8],
+) -> Vec<Diagnostic> {
+    let Some(source_unit) = ast::source_unit(ast_data, file_uri) else {
+        return vec![];
+    };
+    let functions = collect_functions(ast_data);
+    let mut sources = SourceCache::default();
+
+    let contracts = source_unit
+        .get("nodes")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter(|node| node["nodeType"] == "ContractDefinition");
+
+    let mut diagnostics = Vec::new();
+    for contract in contracts {
+        let bases: Vec<u64> = contract
+            .get("linearizedBaseContracts")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_u64)
+            .collect();
+        let mut by_selector: HashMap<&str, Vec<&SelectorFunction>> = HashMap::new();
+        for function in functions
+            .iter()
+            .filter(|function| bases.contains(&function.contract_id))
+        {
+            by_selector
+                .entry(&function.selector)
+                .or_default()
+                .push(function);
+        }
+
+        let Some(range) = contract
+            .get("nameLocation")
+            .or_else(|| contract.get("src"))
+            .and_then(Value::as_str)
+            .and_then(parse_src)
+            .and_then(|(start, length, _)| {
+                Some(Range {
+                    start: bytes_to_pos(source_bytes, start)?,
+                    end: bytes_to_pos(source_bytes, start + length)?,
+                })
+            })
+        else {
+            continue;
+        };
+        let contract_name = contract["name"].as_str().unwrap_or_default();
+
+        let mut collisions: Vec<(&str, Vec<&SelectorFunction>)> = by_selector
+            .into_iter()
+            .filter_map(|(selector, mut group)| {
+                // One function per name: overrides share their base's selector
+                group.sort_by_key(|function| {
+                    bases.iter().position(|id| *id == function.contract_id)
+                });
+                let mut names = HashSet::new();
+                group.retain(|function| names.insert(function.name.as_str()));
+                (group.len() > 1).then_some((selector, group))
+            })
+            .collect();
+        collisions.sort_by_key(|(selector, _)| *selector);
+
+        for (selector, group) in collisions {
+            let names: Vec<String> = group
+                .iter()
+                .map(|function| format!("`{}`", function.qualified_name()))
+                .collect();
+            let related_information = group
+                .iter()
+                .filter_map(|function| {
+                    Some(DiagnosticRelatedInformation {
+                        location: sources.location(function)?,
+                        message: format!("`{}` has selector {selector}", function.qualified_name()),
+                    })
+                })
+                .collect();
+            diagnostics.push(Diagnostic {
+                range,
+                severity: Some(DiagnosticSeverity::ERROR),
+                code: Some(NumberOrString::String(SELECTOR_COLLISION_CODE.to_string())),
+                source: Some("forge-lsp".to_string()),
+                message: format!(
+                    "functions {} of `{contract_name}` share selector {selector}",
+                    names.join(", ")
+                ),
+                related_information: Some(related_information),
+                ..Diagnostic::default()
+            });
+        }
+    }
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const PROXY: &str = r#"contract Proxy {
+    function collate_propagate_storage(bytes16) external {}
+    function upgradeTo(address) external {}
+}
+"#;
+    const IMPL: &str = r#"contract Token {
+    function burn(uint256) external {}
+    function upgradeTo(address) external {}
+}
+"#;
+
+    fn function(source: &str, id: u64, name: &str, selector: &str) -> Value {
+        let start = source.find(&format!("function {name}")).unwrap() + "function ".len();
+        json!({
+            "id": id,
+            "src": format!("{start}:{}:0", name.len()),
+            "nodeType": "FunctionDefinition",
+            "name": name,
+            "nameLocation": format!("{start}:{}:0", name.len()),
+            "functionSelector": selector
+        })
+    }
+
+    fn source_unit(
+        path: &str,
+        id: u64,
+        contract: &str,
+        bases: &[u64],
+        functions: Vec<Value>,
+    ) -> Value {
+        ast::mock_source(
+            id,
+            json!({
+                "id": id * 100,
+                "src": "0:0:0",
+                "nodeType": "SourceUnit",
+                "absolutePath": path,
+                "nodes": [{
+                    "id": id * 100 + 50,
+                    "src": "0:0:0",
+                    "nodeType": "ContractDefinition",
+                    "name": contract,
+                    "linearizedBaseContracts": bases,
+                    "nodes": functions
+                }]
+            }),
+        )
+    }
+
+    fn mock_ast(proxy: &str, token: &str) -> Value {
+        // `collate_propagate_storage(bytes16)` is the well-known collision with `burn(uint256)`
+        json!({
+            "sources": {
+                proxy: source_unit(proxy, 0, "Proxy", &[50], vec![
+                    function(PROXY, 1, "collate_propagate_storage", "42966c68"),
+                    function(PROXY, 2, "upgradeTo", "3659cfe6"),
+                ]),
+                token: source_unit(token, 1, "Token", &[150, 50], vec![
+                    function(IMPL, 3, "burn", "42966c68"),
+                    function(IMPL, 4, "upgradeTo", "3659cfe6"),
+                ])
+            }
+        })
+    }
+
+    fn write_sources() -> (tempfile::TempDir, String, String) {
+        let dir = tempfile::tempdir().unwrap();
+        let proxy = dir.path().join("Proxy.sol");
+        let token = dir.path().join("Token.sol");
+        std::fs::write(&proxy, PROXY).unwrap();
+        std::fs::write(&token, IMPL).unwrap();
+        let proxy = proxy.to_string_lossy().into_owned();
+        let token = token.to_string_lossy().into_owned();
+        (dir, proxy, token)
+    }
+
+    #[test]
+    fn test_selector_implementations() {
+        let (_dir, proxy, token) = write_sources();
+        let ast = mock_ast(&proxy, &token);
+        let uri = Url::from_file_path(&proxy).unwrap();
+        let offset = PROXY.find("upgradeTo").unwrap();
+        let position = bytes_to_pos(PROXY.as_bytes(), offset).unwrap();
+
+        let locations = selector_implementations(&ast, &uri, position, PROXY.as_bytes());
+        assert_eq!(locations.len(), 1);
+        assert_eq!(locations[0].uri, Url::from_file_path(&token).unwrap());
+        let token_offset = IMPL.find("upgradeTo").unwrap();
+        assert_eq!(
+            locations[0].range.start,
+            bytes_to_pos(IMPL.as_bytes(), token_offset).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_selector_clashes_ignore_overrides() {
+        let (_dir, proxy, token) = write_sources();
+        let ast = mock_ast(&proxy, &token);
+        let uri = Url::from_file_path(&token).unwrap();
+
+        let diagnostics = selector_clashes(&ast, &uri, IMPL.as_bytes());
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].message,
+            "selector 0x42966c68 of `Token.burn` clashes with `Proxy.collate_propagate_storage`"
+        );
+        let burn = IMPL.find("burn").unwrap();
+        assert_eq!(
+            diagnostics[0].range.start,
+            bytes_to_pos(IMPL.as_bytes(), burn).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_inheritance_collisions() {
+        let (_dir, proxy, token) = write_sources();
+        let ast = mock_ast(&proxy, &token);
+
+        // `Token is Proxy`: `burn` and `collate_propagate_storage` collide, while
+        // `upgradeTo` is an override and does not
+        let uri = Url::from_file_path(&token).unwrap();
+        let diagnostics = inheritance_collisions(&ast, &uri, IMPL.as_bytes());
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::ERROR));
+        assert_eq!(
+            diagnostics[0].message,
+            "functions `Token.burn`, `Proxy.collate_propagate_storage` of `Token` share \
+             selector 0x42966c68"
+        );
+        let related = diagnostics[0].related_information.as_ref().unwrap();
+        let uris: Vec<&Url> = related.iter().map(|info| &info.location.uri).collect();
+        assert_eq!(uris, vec![&uri, &Url::from_file_path(&proxy).unwrap()]);
+
+        // The base contract alone has no collision
+        let proxy_uri = Url::from_file_path(&proxy).unwrap();
+        assert!(inheritance_collisions(&ast, &proxy_uri, PROXY.as_bytes()).is_empty());
+    }
+
+    fn elementary(name: &str) -> Value {
+        json!({ "nodeType": "ElementaryTypeName", "name": name })
+    }
+
+    fn user_defined(id: u64) -> Value {
+        json!({ "nodeType": "UserDefinedTypeName", "referencedDeclaration": id })
+    }
+
+    fn definition(node_type: &str, name: &str, types: Vec<Value>) -> Value {
+        json!({
+            "nodeType": node_type,
+            "kind": "function",
+            "visibility": "external",
+            "name": name,
+            "parameters": {
+                "parameters": types
+                    .into_iter()
+                    .map(|type_name| json!({ "typeName": type_name }))
+                    .collect::<Vec<_>>()
+            }
+        })
+    }
+
+    #[test]
+    fn test_signature_hashes() {
+        let token = json!({ "nodeType": "ContractDefinition", "id": 1 });
+        let side = json!({ "nodeType": "EnumDefinition", "id": 2 });
+        let order = json!({
+            "nodeType": "StructDefinition",
+            "id": 3,
+            "members": [
+                { "typeName": user_defined(1) },
+                { "typeName": user_defined(2) },
+                { "typeName": {
+                    "nodeType": "ArrayTypeName",
+                    "baseType": elementary("uint"),
+                    "length": { "value": "2" }
+                }}
+            ]
+        });
+        let index = HashMap::from([(1, &token), (2, &side), (3, &order)]);
+
+        let transfer = definition(
+            "FunctionDefinition",
+            "transfer",
+            vec![elementary("address payable"), elementary("uint256")],
+        );
+        let hash = signature_hash(&transfer, &index).unwrap();
+        assert_eq!(hash.kind, SignatureHashKind::Selector);
+        assert_eq!(
+            hash.to_markdown(),
+            "Selector: `0xa9059cbb` · `transfer(address,uint256)`"
+        );
+        let error = definition("ErrorDefinition", "Error", vec![elementary("string")]);
+        assert_eq!(signature_hash(&error, &index).unwrap().hash, "0x08c379a0");
+
+        let event = definition(
+            "EventDefinition",
+            "Transfer",
+            vec![user_defined(1), elementary("address"), elementary("uint")],
+        );
+        let hash = signature_hash(&event, &index).unwrap();
+        assert_eq!(hash.kind, SignatureHashKind::Topic);
+        assert_eq!(
+            hash.hash,
+            "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef"
+        );
+
+        let mut fill = definition("FunctionDefinition", "fill", vec![user_defined(3)]);
+        assert_eq!(
+            canonical_signature(&fill, &index).as_deref(),
+            Some("fill((address,uint8,uint256[2]))")
+        );
+        // Solc's own selector wins over the computed one
+        fill["functionSelector"] = json!("12345678");
+        assert_eq!(signature_hash(&fill, &index).unwrap().hash, "0x12345678");
+
+        // Internal functions and anonymous events have neither
+        fill["visibility"] = json!("internal");
+        assert!(signature_hash(&fill, &index).is_none());
+        let mut anonymous = event;
+        anonymous["anonymous"] = json!(true);
+        assert!(signature_hash(&anonymous, &index).is_none());
+    }
+}
@@ -0,0 +1,374 @@
+//! Semantic tokens classifying identifiers by the declaration they name, for highlighting
+//! that textmate grammars cannot do: state variables against locals, events, modifiers,
+//! constants and user-defined types.
+
+use serde_json::Value;
+use std::collections::HashMap;
+use tower_lsp::lsp_types::{
+    Position, Range, SemanticToken, SemanticTokenModifier, SemanticTokenType, SemanticTokensLegend,
+    Url,
+};
+
+use crate::ast::{self, parse_src};
+
+const TOKEN_TYPES: [SemanticTokenType; 13] = [
+    SemanticTokenType::NAMESPACE,
+    SemanticTokenType::CLASS,
+    SemanticTokenType::INTERFACE,
+    SemanticTokenType::STRUCT,
+    SemanticTokenType::ENUM,
+    SemanticTokenType::ENUM_MEMBER,
+    SemanticTokenType::TYPE,
+    SemanticTokenType::FUNCTION,
+    SemanticTokenType::DECORATOR,
+    SemanticTokenType::EVENT,
+    SemanticTokenType::PROPERTY,
+    SemanticTokenType::VARIABLE,
+    SemanticTokenType::PARAMETER,
+];
+
+const TOKEN_MODIFIERS: [SemanticTokenModifier; 2] = [
+    SemanticTokenModifier::DECLARATION,
+    SemanticTokenModifier::READONLY,
+];
+
+/// Bit of [`SemanticTokenModifier::DECLARATION`].
+const DECLARATION: u32 = 1 << 0;
+/// Bit of [`SemanticTokenModifier::READONLY`], for constants and immutables.
+const READONLY: u32 = 1 << 1;
+
+/// Token types and modifiers advertised in the server capabilities.
+pub fn legend() -> SemanticTokensLegend {
+    SemanticTokensLegend {
+        token_types: TOKEN_TYPES.to_vec(),
+        token_modifiers: TOKEN_MODIFIERS.to_vec(),
+    }
+}
+
+fn type_index(token_type: SemanticTokenType) -> u32 {
+    TOKEN_TYPES
+        .iter()
+        .position(|t| *t == token_type)
+        .unwrap_or_default() as u32
+}
+
+/// An identifier as a byte range of the source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Token {
+    start: usize,
+    length: usize,
+    token_type: u32,
+    modifiers: u32,
+}
+
+/// Token type and modifiers of the identifiers naming `declaration`.
+fn classify(declaration: &Value, index: &HashMap<u64, &Value>) -> Option<(SemanticTokenType, u32)> {
+    let token_type = match declaration.get("nodeType")?.as_str()? {
+        "ContractDefinition" => match declaration.get("contractKind").and_then(Value::as_str) {
+            Some("interface") => SemanticTokenType::INTERFACE,
+            Some("library") => SemanticTokenType::NAMESPACE,
+            _ => SemanticTokenType::CLASS,
+        },
+        "StructDefinition" => SemanticTokenType::STRUCT,
+        "EnumDefinition" => SemanticTokenType::ENUM,
+        "EnumValue" => SemanticTokenType::ENUM_MEMBER,
+        "UserDefinedValueTypeDefinition" => SemanticTokenType::TYPE,
+        "FunctionDefinition" => SemanticTokenType::FUNCTION,
+        "ModifierDefinition" => SemanticTokenType::DECORATOR,
+        "EventDefinition" | "ErrorDefinition" => SemanticTokenType::EVENT,
+        "VariableDeclaration" => {
+            let readonly = matches!(
+                declaration.get("mutability").and_then(Value::as_str),
+                Some("constant" | "immutable")
+            ) || declaration.get("constant").and_then(Value::as_bool) == Some(true);
+            let modifiers = if readonly { READONLY } else { 0 };
+
+            // Parameters are scoped to their callable, locals to the enclosing block
+            let scope = declaration
+                .get("scope")
+                .and_then(Value::as_u64)
+                .and_then(|id| index.get(&id))
+                .and_then(|scope| scope.get("nodeType"))
+                .and_then(Value::as_str);
+            let token_type = if declaration.get("stateVariable").and_then(Value::as_bool)
+                == Some(true)
+                || scope == Some("StructDefinition")
+            {
+                SemanticTokenType::PROPERTY
+            } else if matches!(
+                scope,
+                Some(
+                    "FunctionDefinition"
+                        | "ModifierDefinition"
+                        | "EventDefinition"
+                        | "ErrorDefinition"
+                )
+            ) {
+                SemanticTokenType::PARAMETER
+            } else {
+                SemanticTokenType::VARIABLE
+            };
+            return Some((token_type, modifiers));
+        }
+        _ => return None,
+    };
+    Some((token_type, 0))
+}
+
+fn location(node: &Value, key: &str) -> Option<(usize, usize)> {
+    let (start, length, _) = parse_src(node.get(key)?.as_str()?)?;
+    (length > 0).then_some((start, length))
+}
+
+/// Where the identifier of a reference node is, if the node references a declaration.
+fn reference_location(node: &Value) -> Option<(usize, usize)> {
+    match node.get("nodeType")?.as_str()? {
+        "Identifier" => location(node, "src"),
+        "IdentifierPath" => node
+            .get("nameLocations")
+            .and_then(Value::as_array)
+            .and_then(|locations| locations.last())
+            .and_then(Value::as_str)
+            .and_then(parse_src)
+            .map(|(start, length, _)| (start, length))
+            .or_else(|| location(node, "src")),
+        "MemberAccess" => location(node, "memberLocation").or_else(|| {
+            // Older compilers do not report `memberLocation`; the member ends the node
+            let (start, length) = location(node, "src")?;
+            let member = node.get("memberName")?.as_str()?.len();
+            Some((start + length.checked_sub(member)?, member))
+        }),
+        // Compilers before `pathNode` reference the declaration from the type name itself
+        "UserDefinedTypeName" if node.get("pathNode").is_none() => location(node, "src"),
+        _ => None,
+    }
+}
+
+fn tokens(ast_data: &Value, uri: &Url) -> Vec<Token> {
+    let (Some(sources), Some(source_unit)) =
+        (ast_data.get("sources"), ast::source_unit(ast_data, uri))
+    else {
+        return vec![];
+    };
+    let index = ast::index_nodes(sources);
+
+    let mut tokens = Vec::new();
+    ast::walk(source_unit, &mut |node| {
+        let (declaration, (start, length), modifiers) = if let Some(id) =
+            node.get("referencedDeclaration").and_then(Value::as_u64)
+        {
+            let (Some(declaration), Some(location)) = (index.get(&id), reference_location(node))
+            else {
+                return;
+            };
+            (*declaration, location, 0)
+        } else {
+            let Some(location) = location(node, "nameLocation") else {
+                return;
+            };
+            (node, location, DECLARATION)
+        };
+        if let Some((token_type, declaration_modifiers)) = classify(declaration, &index) {
+            tokens.push(Token {
+                start,
+                length,
+                token_type: type_index(token_type),
+                modifiers: modifiers | declaration_modifiers,
+            });
+        }
+    });
+
+    tokens.sort_by_key(|token| token.start);
+    tokens.dedup_by_key(|token| token.start);
+    tokens
+}
+
+/// Encode tokens as deltas of UTF-16 positions, skipping those outside `range`.
+fn encode(source: &[u8], tokens: &[Token], range: Option<Range>) -> Vec<SemanticToken> {
+    let text = String::from_utf8_lossy(source);
+    let mut encoded = Vec::new();
+    let (mut line, mut character) = (0u32, 0u32);
+    let (mut previous_line, mut previous_start) = (0u32, 0u32);
+    let mut offset = 0;
+    let mut chars = text.char_indices().peekable();
+
+    for token in tokens {
+        // Advance the position to the token start
+        while let Some(&(i, c)) = chars.peek() {
+            if i >= token.start {
+                break;
+            }
+            if c == '\n' {
+                line += 1;
+                character = 0;
+            } else {
+                character += c.len_utf16() as u32;
+            }
+            offset = i + c.len_utf8();
+            chars.next();
+        }
+        // Past the end of the source
+        if offset < token.start && chars.peek().is_none() {
+            break;
+        }
+        let Some(name) = text.get(token.start..token.start + token.length) else {
+            continue;
+        };
+        if name.contains('\n') {
+            continue;
+        }
+        if let Some(range) = range {
+            let position = Position::new(line, character);
+            if position < range.start || position >= range.end {
+                continue;
+            }
+        }
+
+        let delta_line = line - previous_line;
+        let delta_start = if delta_line == 0 {
+            character - previous_start
+        } else {
+            character
+        };
+        encoded.push(SemanticToken {
+            delta_line,
+            delta_start,
+            length: name.encode_utf16().count() as u32,
+            token_type: token.token_type,
+            token_modifiers_bitset: token.modifiers,
+        });
+        previous_line = line;
+        previous_start = character;
+    }
+    encoded
+}
+
+/// Semantic tokens of `uri`, or of the part of it in `range`.
+pub fn semantic_tokens(
+    ast_data: &Value,
+    uri: &Url,
+    source: &[u8],
+    range: Option<Range>,
+) -> Vec<SemanticToken> {
+    encode(source, &tokens(ast_data, uri), range)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::mock_src;
+    use crate::goto::bytes_to_pos;
+    use serde_json::json;
+
+    fn position(source: &str, offset: usize) -> Position {
+        bytes_to_pos(source.as_bytes(), offset).unwrap()
+    }
+
+    const SOURCE: &str = concat!(
+        "contract Vault {\n",
+        "    uint256 constant FEE = 1;\n",
+        "    function pay(uint256 amount) public {\n",
+        "        amount + FEE;\n",
+        "    }\n",
+        "}\n",
+    );
+
+    fn mock_ast() -> Value {
+        let declaration = |id: u64, name: &str, scope: u64, state: bool| {
+            json!({
+                "id": id,
+                "nodeType": "VariableDeclaration",
+                "name": name,
+                "nameLocation": mock_src(SOURCE, name, name.len()),
+                "scope": scope,
+                "stateVariable": state,
+                "mutability": if state { "constant" } else { "mutable" }
+            })
+        };
+        let reference = |id: u64, needle: &str, declaration: u64| {
+            json!({
+                "id": id,
+                "nodeType": "Identifier",
+                "src": format!("{}:{}:0", SOURCE.rfind(needle).unwrap(), needle.len()),
+                "referencedDeclaration": declaration
+            })
+        };
+        let mut function = json!({
+            "id": 4,
+            "nodeType": "FunctionDefinition",
+            "name": "pay",
+            "nameLocation": mock_src(SOURCE, "pay", 3)
+        });
+        function["parameters"] = json!({ "parameters": [declaration(5, "amount", 4, false)] });
+        function["body"] =
+            json!({ "statements": [reference(6, "amount", 5), reference(7, "FEE", 3)] });
+        let mut contract = json!({
+            "id": 2,
+            "nodeType": "ContractDefinition",
+            "name": "Vault",
+            "nameLocation": mock_src(SOURCE, "Vault", 5)
+        });
+        contract["nodes"] = json!([declaration(3, "FEE", 2, true), function]);
+        ast::mock_ast(
+            "/project/src/Vault.sol",
+            json!({
+                "id": 1,
+                "nodeType": "SourceUnit",
+                "absolutePath": "/project/src/Vault.sol",
+                "nodes": [contract]
+            }),
+        )
+    }
+
+    /// Absolute positions and classification of encoded tokens.
+    fn decode(tokens: &[SemanticToken]) -> Vec<(Position, u32, SemanticTokenType, u32)> {
+        let (mut line, mut character) = (0, 0);
+        tokens
+            .iter()
+            .map(|token| {
+                if token.delta_line > 0 {
+                    character = 0;
+                }
+                line += token.delta_line;
+                character += token.delta_start;
+                (
+                    Position::new(line, character),
+                    token.length,
+                    TOKEN_TYPES[token.token_type as usize].clone(),
+                    token.token_modifiers_bitset,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_semantic_tokens() {
+        let uri = Url::parse("file:///project/src/Vault.sol").unwrap();
+        let tokens = semantic_tokens(&mock_ast(), &uri, SOURCE.as_bytes(), None);
+        let at = |needle: &str| position(SOURCE, SOURCE.find(needle).unwrap());
+        let last = |needle: &str| position(SOURCE, SOURCE.rfind(needle).unwrap());
+        assert_eq!(
+            decode(&tokens),
+            [
+                (at("Vault"), 5, SemanticTokenType::CLASS, DECLARATION),
+                (
+                    at("FEE"),
+                    3,
+                    SemanticTokenType::PROPERTY,
+                    DECLARATION | READONLY
+                ),
+                (at("pay"), 3, SemanticTokenType::FUNCTION, DECLARATION),
+                (at("amount"), 6, SemanticTokenType::PARAMETER, DECLARATION),
+                (last("amount"), 6, SemanticTokenType::PARAMETER, 0),
+                (last("FEE"), 3, SemanticTokenType::PROPERTY, READONLY),
+            ]
+        );
+
+        // Only the body of `pay`
+        let range = Range::new(Position::new(3, 0), Position::new(4, 0));
+        let tokens = semantic_tokens(&mock_ast(), &uri, SOURCE.as_bytes(), Some(range));
+        let decoded = decode(&tokens);
+        assert_eq!(decoded.len(), 2);
+        assert_eq!(decoded[0].0, last("amount"));
+    }
+}
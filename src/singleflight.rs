@@ -0,0 +1,101 @@
+//! Single-flight execution: concurrent callers asking for the same key share one run.
+
+use std::{collections::HashMap, future::Future, hash::Hash, sync::Arc};
+use tokio::sync::{Mutex, OnceCell};
+
+pub struct SingleFlight<K, V> {
+    in_flight: Mutex<HashMap<K, Arc<OnceCell<V>>>>,
+}
+
+impl<K, V> Default for SingleFlight<K, V> {
+    fn default() -> Self {
+        Self {
+            in_flight: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> SingleFlight<K, V> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run `work` for `key`, unless a run for the same key is already in progress, in
+    /// which case wait for it and return a clone of its result. Once a run completes the
+    /// key is released, so later calls start a fresh run.
+    pub async fn run<F, Fut>(&self, key: K, work: F) -> V
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = V>,
+    {
+        let cell = self
+            .in_flight
+            .lock()
+            .await
+            .entry(key.clone())
+            .or_insert_with(|| Arc::new(OnceCell::new()))
+            .clone();
+
+        let value = cell.get_or_init(work).await.clone();
+
+        let mut in_flight = self.in_flight.lock().await;
+        if in_flight
+            .get(&key)
+            .is_some_and(|current| Arc::ptr_eq(current, &cell))
+        {
+            in_flight.remove(&key);
+        }
+
+        value
+    }
+
+    /// Number of keys currently being worked on.
+    pub async fn len(&self) -> usize {
+        self.in_flight.lock().await.len()
+    }
+
+    pub async fn is_empty(&self) -> bool {
+        self.len().await == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_concurrent_runs_share_one_execution() {
+        let flights = SingleFlight::<&str, usize>::new();
+        let calls = AtomicUsize::new(0);
+        let work = || async {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            calls.fetch_add(1, Ordering::SeqCst) + 1
+        };
+
+        let (a, b, c) = tokio::join!(
+            flights.run("build", work),
+            flights.run("build", work),
+            flights.run("build", work)
+        );
+
+        assert_eq!((a, b, c), (1, 1, 1));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert!(flights.is_empty().await);
+    }
+
+    #[tokio::test]
+    async fn test_distinct_keys_and_sequential_runs() {
+        let flights = SingleFlight::<&str, usize>::new();
+        let calls = AtomicUsize::new(0);
+        let work = || async { calls.fetch_add(1, Ordering::SeqCst) };
+
+        tokio::join!(flights.run("a", work), flights.run("b", work));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        // A finished key does not keep serving its old value
+        flights.run("a", work).await;
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+}
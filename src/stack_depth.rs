@@ -0,0 +1,380 @@
+//! Stack usage of functions, against the stack-too-deep limit of the legacy code generator.
+//!
+//! The EVM reaches only the top 16 slots of its stack, so a function whose parameters,
+//! return variables and locals need more slots fails to compile with "Stack too deep".
+//! Functions getting close to the limit show a lens with their count, an upper bound that
+//! takes every local as live at once: dynamic calldata arrays, `bytes` and `string` and
+//! external function pointers need two slots each. When solc reports the error, its
+//! diagnostic names the locals of the function that could move into a memory struct, with
+//! a quick fix declaring that struct next to the function.
+
+use serde_json::Value;
+use tower_lsp::lsp_types::{
+    CodeLens, Command, Diagnostic, DiagnosticRelatedInformation, Location, Range, TextEdit, Url,
+};
+
+use crate::{
+    ast::{self, node_type, parse_src},
+    code_actions::Fix,
+    goto::{bytes_to_pos, pos_to_bytes},
+};
+
+/// Stack slots the EVM can reach with `DUP16` and `SWAP16`.
+pub const STACK_SLOTS: usize = 16;
+
+/// Slot count from which functions show their stack usage.
+pub const LENS_THRESHOLD: usize = 12;
+
+/// Slots taken by the variables of a function.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StackUsage {
+    pub parameters: usize,
+    pub returns: usize,
+    pub locals: usize,
+}
+
+impl StackUsage {
+    pub fn total(&self) -> usize {
+        self.parameters + self.returns + self.locals
+    }
+
+    /// Label of the usage, such as `13 of 16 stack slots: 4 for parameters, 1 for returns, 8 for locals`.
+    pub fn summary(&self) -> String {
+        format!(
+            "{} of {STACK_SLOTS} stack slots: {} for parameters, {} for returns, {} for locals",
+            self.total(),
+            self.parameters,
+            self.returns,
+            self.locals
+        )
+    }
+}
+
+/// Slots a value of the variable `declaration` takes on the stack.
+fn slots(declaration: &Value) -> usize {
+    let type_name = &declaration["typeName"];
+    let dynamic_calldata = declaration["storageLocation"] == "calldata"
+        && match node_type(type_name) {
+            Some("ArrayTypeName") => type_name.get("length").is_none_or(Value::is_null),
+            Some("ElementaryTypeName") => {
+                matches!(type_name["name"].as_str(), Some("bytes" | "string"))
+            }
+            _ => false,
+        };
+    let external_function =
+        node_type(type_name) == Some("FunctionTypeName") && type_name["visibility"] == "external";
+    if dynamic_calldata || external_function {
+        2
+    } else {
+        1
+    }
+}
+
+fn parameters<'a>(function: &'a Value, key: &str) -> impl Iterator<Item = &'a Value> {
+    function[key]["parameters"].as_array().into_iter().flatten()
+}
+
+/// The locals the body of `function` declares, in order.
+fn locals(function: &Value) -> Vec<&Value> {
+    let mut locals = Vec::new();
+    if let Some(body) = function.get("body") {
+        ast::walk(body, &mut |node| {
+            if node_type(node) == Some("VariableDeclarationStatement") {
+                // Skipped components of a tuple are null
+                locals.extend(
+                    node["declarations"]
+                        .as_array()
+                        .into_iter()
+                        .flatten()
+                        .filter(|declaration| declaration.is_object()),
+                );
+            }
+        });
+    }
+    // Children are walked by key, not in source order
+    locals.sort_by_key(|local| {
+        local["src"]
+            .as_str()
+            .and_then(parse_src)
+            .map(|(start, ..)| start)
+    });
+    locals
+}
+
+/// The stack usage of the `FunctionDefinition` `function`.
+pub fn stack_usage(function: &Value) -> StackUsage {
+    StackUsage {
+        parameters: parameters(function, "parameters").map(slots).sum(),
+        returns: parameters(function, "returnParameters").map(slots).sum(),
+        locals: locals(function).into_iter().map(slots).sum(),
+    }
+}
+
+/// The implemented functions of the file at `uri`.
+fn functions<'a>(ast_data: &'a Value, uri: &Url) -> Vec<&'a Value> {
+    let mut functions = Vec::new();
+    if let Some(unit) = ast::source_unit(ast_data, uri) {
+        ast::walk(unit, &mut |node| {
+            if node_type(node) == Some("FunctionDefinition")
+                && node.get("body").is_some_and(Value::is_object)
+            {
+                functions.push(node);
+            }
+        });
+    }
+    functions
+}
+
+fn src_range(source: &[u8], node: &Value) -> Option<Range> {
+    let (start, length, _) = parse_src(node.as_str()?)?;
+    Some(Range::new(
+        bytes_to_pos(source, start)?,
+        bytes_to_pos(source, start + length)?,
+    ))
+}
+
+fn name_range(source: &[u8], function: &Value) -> Option<Range> {
+    src_range(
+        source,
+        function.get("nameLocation").unwrap_or(&function["src"]),
+    )
+}
+
+/// Lenses with the stack usage of the functions of `uri` that use at least
+/// [`LENS_THRESHOLD`] slots.
+pub fn stack_lenses(ast_data: &Value, uri: &Url, source_bytes: &[u8]) -> Vec<CodeLens> {
+    functions(ast_data, uri)
+        .into_iter()
+        .filter_map(|function| {
+            let usage = stack_usage(function);
+            if usage.total() < LENS_THRESHOLD {
+                return None;
+            }
+            Some(CodeLens {
+                range: name_range(source_bytes, function)?,
+                // Without a command to run the lens is a label
+                command: Some(Command {
+                    title: usage.summary(),
+                    command: String::new(),
+                    arguments: None,
+                }),
+                data: None,
+            })
+        })
+        .collect()
+}
+
+/// Whether `diagnostic` is solc's stack-too-deep error, from either code generator.
+pub fn is_stack_too_deep(diagnostic: &Diagnostic) -> bool {
+    diagnostic.message.to_lowercase().contains("too deep")
+}
+
+/// Name of the struct packing the locals of `function`, `DepositVars` for `deposit`.
+fn struct_name(function: &Value) -> String {
+    let name = function["name"]
+        .as_str()
+        .filter(|name| !name.is_empty())
+        .or_else(|| function["kind"].as_str())
+        .unwrap_or("function");
+    let mut chars = name.chars();
+    let first = chars.next().map(|c| c.to_ascii_uppercase());
+    format!(
+        "{}{}Vars",
+        first.into_iter().collect::<String>(),
+        chars.as_str()
+    )
+}
+
+fn text<'a>(source: &'a [u8], node: &Value) -> Option<&'a str> {
+    let (start, length, _) = parse_src(node["src"].as_str()?)?;
+    std::str::from_utf8(source.get(start..start + length)?).ok()
+}
+
+/// Edit declaring the struct `name` with a field for each of `candidates` before
+/// `function`, at its indentation and above its NatSpec.
+fn struct_edit(
+    source: &[u8],
+    function: &Value,
+    name: &str,
+    candidates: &[&Value],
+) -> Option<TextEdit> {
+    let start = [function.get("documentation"), Some(function)]
+        .into_iter()
+        .flatten()
+        .filter_map(|node| Some(parse_src(node["src"].as_str()?)?.0))
+        .min()?;
+    let line_start = source[..start]
+        .iter()
+        .rposition(|&b| b == b'\n')
+        .map_or(0, |newline| newline + 1);
+    let indent = std::str::from_utf8(&source[line_start..start]).ok()?;
+    if !indent.chars().all(char::is_whitespace) {
+        return None;
+    }
+    let mut text = format!("{indent}struct {name} {{\n");
+    for candidate in candidates {
+        let ty = text_of_type(source, candidate)?;
+        let field = candidate["name"].as_str()?;
+        text.push_str(&format!("{indent}    {ty} {field};\n"));
+    }
+    text.push_str(&format!("{indent}}}\n\n"));
+    let position = bytes_to_pos(source, line_start)?;
+    Some(TextEdit::new(Range::new(position, position), text))
+}
+
+fn text_of_type<'a>(source: &'a [u8], declaration: &Value) -> Option<&'a str> {
+    text(source, &declaration["typeName"])
+}
+
+/// Point each stack-too-deep error among `diagnostics` of `uri` at the locals of its
+/// function that could move into a memory struct, with a fix declaring the struct.
+/// Storage pointers and calldata references can't be struct fields and are left out.
+pub fn explain_stack_too_deep(
+    diagnostics: &mut [Diagnostic],
+    ast_data: &Value,
+    uri: &Url,
+    source: &[u8],
+) {
+    let functions = functions(ast_data, uri);
+    for diagnostic in diagnostics.iter_mut().filter(|d| is_stack_too_deep(d)) {
+        let offset = pos_to_bytes(source, diagnostic.range.start);
+        let Some(function) = functions
+            .iter()
+            .find(|function| ast::contains(function, offset))
+        else {
+            continue;
+        };
+        let candidates: Vec<&Value> = locals(function)
+            .into_iter()
+            .filter(|local| {
+                !matches!(
+                    local["storageLocation"].as_str(),
+                    Some("storage" | "calldata")
+                ) && local["name"].as_str().is_some_and(|name| !name.is_empty())
+                    && text_of_type(source, local).is_some()
+            })
+            .collect();
+        let usage = stack_usage(function);
+        diagnostic.message = format!("{}\n{}", diagnostic.message, usage.summary());
+        if candidates.is_empty() {
+            continue;
+        }
+        let name = struct_name(function);
+        let names: Vec<String> = candidates
+            .iter()
+            .filter_map(|local| Some(format!("`{}`", local["name"].as_str()?)))
+            .collect();
+        diagnostic.message = format!(
+            "{}\nPack {} into a `{name}` memory struct",
+            diagnostic.message,
+            names.join(", ")
+        );
+        diagnostic.related_information = Some(
+            candidates
+                .iter()
+                .filter_map(|local| {
+                    Some(DiagnosticRelatedInformation {
+                        location: Location::new(uri.clone(), src_range(source, &local["src"])?),
+                        message: format!("`{}` could move into `{name}`", local["name"].as_str()?),
+                    })
+                })
+                .collect(),
+        );
+        if let Some(edit) = struct_edit(source, function, &name, &candidates) {
+            diagnostic.data = Fix::new(
+                format!("Declare struct `{name}` for the locals"),
+                vec![edit],
+            )
+            .to_data();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{edits::EditBuilder, syntax};
+    use tower_lsp::lsp_types::Position;
+
+    static SOURCE: &str = "contract Vault {
+    function deposit(bytes calldata data, uint256 amount, address to) external returns (uint256 shares) {
+        uint256 a = 1;
+        uint256 b = 2;
+        (uint256 c, , address d) = (3, 4, to);
+        Position storage position = positions[to];
+        for (uint256 i; i < 4; i++) {
+            uint256 e = i;
+        }
+        return a + b + c + e;
+    }
+
+    function small(uint256 x) internal pure returns (uint256) {
+        uint256 y = x;
+        return y;
+    }
+}
+";
+
+    fn setup() -> (Url, Value) {
+        let path = "/project/src/Vault.sol";
+        (
+            Url::from_file_path(path).unwrap(),
+            syntax::parse(path, SOURCE),
+        )
+    }
+
+    #[test]
+    fn test_stack_lenses() {
+        let (uri, tree) = setup();
+        let lenses = stack_lenses(&tree, &uri, SOURCE.as_bytes());
+        assert_eq!(lenses.len(), 1);
+        assert_eq!(lenses[0].range.start, Position::new(1, 13));
+        assert_eq!(
+            lenses[0].command.as_ref().unwrap().title,
+            "12 of 16 stack slots: 4 for parameters, 1 for returns, 7 for locals"
+        );
+    }
+
+    #[test]
+    fn test_explain_stack_too_deep() {
+        let (uri, tree) = setup();
+        let error = Diagnostic {
+            range: Range::new(Position::new(9, 15), Position::new(9, 16)),
+            message: "[forge build] Stack too deep. Try compiling with `--via-ir`".to_string(),
+            ..Diagnostic::default()
+        };
+        let other = Diagnostic {
+            message: "[forge build] Unused local variable.".to_string(),
+            ..error.clone()
+        };
+        let mut diagnostics = vec![error, other.clone()];
+        explain_stack_too_deep(&mut diagnostics, &tree, &uri, SOURCE.as_bytes());
+
+        assert_eq!(diagnostics[1], other);
+        let explained = &diagnostics[0];
+        assert!(explained.message.ends_with(
+            "\n12 of 16 stack slots: 4 for parameters, 1 for returns, 7 for locals\n\
+             Pack `a`, `b`, `c`, `d`, `i`, `e` into a `DepositVars` memory struct"
+        ));
+        let related = explained.related_information.as_ref().unwrap();
+        assert_eq!(related.len(), 6);
+        assert_eq!(related[0].location.range.start, Position::new(2, 8));
+
+        let fix = Fix::from_diagnostic(explained).unwrap();
+        assert_eq!(fix.title, "Declare struct `DepositVars` for the locals");
+        let text = EditBuilder::with_edits(SOURCE, &fix.edits).unwrap().apply();
+        assert!(text.starts_with(
+            "contract Vault {
+    struct DepositVars {
+        uint256 a;
+        uint256 b;
+        uint256 c;
+        address d;
+        uint256 i;
+        uint256 e;
+    }
+
+    function deposit("
+        ));
+    }
+}
@@ -0,0 +1,95 @@
+//! Marking results answered from an outdated index.
+//!
+//! Navigation and references answer from the AST of the last successful build, whose
+//! offsets are those of the files it compiled. Once one of them changes, in the editor or on
+//! disk, results in it can be a few characters or lines off until the next build. Rather
+//! than mix such results with fresh ones silently, the server sends a `forge-lsp/staleIndex`
+//! notification naming the changed files a request's results come from, and tags grouped
+//! references in them.
+
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::{Location, Url, notification::Notification};
+
+use crate::index::content_hash;
+
+/// Name of the custom notification.
+pub const STALE_INDEX_METHOD: &str = "forge-lsp/staleIndex";
+
+/// Results of a request were answered from an outdated index.
+pub enum StaleIndex {}
+
+impl Notification for StaleIndex {
+    type Params = StaleIndexParams;
+    const METHOD: &'static str = STALE_INDEX_METHOD;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StaleIndexParams {
+    /// The request, like `textDocument/references`.
+    pub method: String,
+    /// The document the request was made in.
+    pub uri: Url,
+    /// Files of the results changed since the index was built.
+    pub stale_files: Vec<Url>,
+}
+
+/// The files an answer to a request in `uri` depends on: `uri`, whose offsets locate the
+/// symbol, then those of `locations`, each once.
+pub fn result_files(uri: &Url, locations: &[Location]) -> Vec<Url> {
+    let mut files = vec![uri.clone()];
+    for location in locations {
+        if !files.contains(&location.uri) {
+            files.push(location.uri.clone());
+        }
+    }
+    files
+}
+
+/// Whether a file reading `current` is outdated in an AST built from contents hashing to
+/// `built`. ASTs without a hash, compiled for the file alone, were built from the file on
+/// disk, `on_disk`.
+pub fn is_stale(
+    current: &[u8],
+    built: Option<u64>,
+    on_disk: impl FnOnce() -> Option<Vec<u8>>,
+) -> bool {
+    match built {
+        Some(hash) => content_hash(current) != hash,
+        None => on_disk().is_some_and(|disk| disk != current),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower_lsp::lsp_types::Range;
+
+    #[test]
+    fn test_stale_result_files() {
+        let uri = |name: &str| Url::parse(&format!("file:///project/src/{name}")).unwrap();
+        let location = |name: &str| Location::new(uri(name), Range::default());
+        assert_eq!(
+            result_files(
+                &uri("Vault.sol"),
+                &[
+                    location("Token.sol"),
+                    location("Vault.sol"),
+                    location("Token.sol")
+                ]
+            ),
+            [uri("Vault.sol"), uri("Token.sol")]
+        );
+
+        let built = content_hash(b"contract Vault {}");
+        assert!(!is_stale(b"contract Vault {}", Some(built), || None));
+        assert!(is_stale(b"contract Vault { }", Some(built), || None));
+        // Compiled alone from the disk, which unsaved edits differ from
+        assert!(is_stale(b"contract Vault { }", None, || Some(
+            b"contract Vault {}".to_vec()
+        )));
+        assert!(!is_stale(b"contract Vault {}", None, || Some(
+            b"contract Vault {}".to_vec()
+        )));
+    }
+}
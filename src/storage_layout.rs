@@ -0,0 +1,714 @@
+//! Storage layout of contracts, computed from the solc AST, and the `__gap` check for
+//! upgradeable contracts.
+//!
+//! Upgradeable contracts in the OpenZeppelin style end with a `uint256[N] private __gap`
+//! so that later versions can add state variables without shifting the storage of derived
+//! contracts. The convention is that each contract's own variables and its gap together
+//! occupy [`RESERVED_SLOTS`] slots, so adding a variable means shrinking the gap.
+//!
+//! The layouts computed here only cover the variables a contract declares itself. The
+//! complete layout, inherited variables included, comes from `forge inspect`, which
+//! `forge-lsp.storageLayout` runs for one contract.
+
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString, Position, Range, Url};
+
+use crate::{
+    ast::{self, parse_src},
+    goto::bytes_to_pos,
+};
+
+/// Slots each upgradeable contract reserves for its own variables plus its `__gap`.
+pub const RESERVED_SLOTS: u64 = 50;
+
+/// Name of the storage gap variable.
+pub const GAP_NAME: &str = "__gap";
+
+/// Diagnostic code for a gap that does not match the contract's storage use.
+pub const STORAGE_GAP_CODE: &str = "storage-gap";
+
+/// Command showing the storage layout of a contract as `forge inspect` computes it.
+pub const STORAGE_LAYOUT_COMMAND: &str = "forge-lsp.storageLayout";
+
+const SLOT_SIZE: u64 = 32;
+
+/// Storage a value of some type takes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TypeSize {
+    /// Packs with neighbours when it fits in the current slot.
+    Bytes(u64),
+    /// Starts a fresh slot, and the next variable starts a fresh slot as well.
+    Slots(u64),
+}
+
+/// Where a state variable lives relative to the first variable of its contract.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StorageSlot {
+    pub id: u64,
+    pub name: String,
+    pub slot: u64,
+    pub offset: u64,
+    /// Whole slots for structs, arrays and mappings, bytes otherwise.
+    pub size: u64,
+}
+
+/// Storage layout of the variables a contract declares itself, starting at slot 0.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ContractLayout {
+    pub variables: Vec<StorageSlot>,
+    /// Slots used in total.
+    pub slots: u64,
+}
+
+/// Computes storage layouts, resolving user-defined types through an index of every AST
+/// node by id.
+pub struct StorageLayoutIndex<'a> {
+    nodes: HashMap<u64, &'a Value>,
+}
+
+impl<'a> StorageLayoutIndex<'a> {
+    pub fn new(ast_data: &'a Value) -> Self {
+        let nodes = ast_data
+            .get("sources")
+            .map(ast::index_nodes)
+            .unwrap_or_default();
+        Self { nodes }
+    }
+
+    /// Layout of the non-constant, non-immutable state variables declared in `contract`.
+    /// Inherited variables are not included.
+    pub fn contract_layout(&self, contract: &Value) -> ContractLayout {
+        let variables = contract
+            .get("nodes")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter(|node| is_storage_variable(node));
+        self.layout(variables)
+    }
+
+    fn layout(&self, variables: impl Iterator<Item = &'a Value>) -> ContractLayout {
+        let mut layout = ContractLayout::default();
+        let mut slot = 0;
+        let mut offset = 0;
+
+        for variable in variables {
+            let Some(size) = variable.get("typeName").and_then(|t| self.type_size(t)) else {
+                continue;
+            };
+            let (variable_slot, variable_offset, size) = match size {
+                TypeSize::Bytes(bytes) => {
+                    if offset + bytes > SLOT_SIZE {
+                        slot += 1;
+                        offset = 0;
+                    }
+                    let position = (slot, offset, bytes);
+                    offset += bytes;
+                    position
+                }
+                TypeSize::Slots(slots) => {
+                    if offset > 0 {
+                        slot += 1;
+                        offset = 0;
+                    }
+                    let position = (slot, 0, slots);
+                    slot += slots;
+                    position
+                }
+            };
+            layout.variables.push(StorageSlot {
+                id: variable
+                    .get("id")
+                    .and_then(Value::as_u64)
+                    .unwrap_or_default(),
+                name: variable["name"].as_str().unwrap_or_default().to_string(),
+                slot: variable_slot,
+                offset: variable_offset,
+                size,
+            });
+        }
+
+        layout.slots = if offset > 0 { slot + 1 } else { slot };
+        layout
+    }
+
+    fn type_size(&self, type_name: &Value) -> Option<TypeSize> {
+        match type_name.get("nodeType")?.as_str()? {
+            "ElementaryTypeName" => elementary_size(type_name.get("name")?.as_str()?),
+            "Mapping" => Some(TypeSize::Slots(1)),
+            "FunctionTypeName" => {
+                let external = type_name["visibility"] == "external";
+                Some(TypeSize::Bytes(if external { 24 } else { 8 }))
+            }
+            "UserDefinedTypeName" => {
+                let id = type_name
+                    .get("referencedDeclaration")
+                    .or_else(|| type_name.get("pathNode")?.get("referencedDeclaration"))?
+                    .as_u64()?;
+                self.declaration_size(self.nodes.get(&id)?)
+            }
+            "ArrayTypeName" => {
+                let Some(length) = type_name.get("length").filter(|l| !l.is_null()) else {
+                    // Dynamic arrays keep only their length in place
+                    return Some(TypeSize::Slots(1));
+                };
+                let length: u64 = length.get("value")?.as_str()?.parse().ok()?;
+                let slots = match self.type_size(type_name.get("baseType")?)? {
+                    TypeSize::Bytes(bytes) => length.div_ceil(SLOT_SIZE / bytes.max(1)),
+                    TypeSize::Slots(slots) => length * slots,
+                };
+                Some(TypeSize::Slots(slots))
+            }
+            _ => None,
+        }
+    }
+
+    fn declaration_size(&self, declaration: &'a Value) -> Option<TypeSize> {
+        match declaration.get("nodeType")?.as_str()? {
+            "ContractDefinition" => Some(TypeSize::Bytes(20)),
+            "EnumDefinition" => Some(TypeSize::Bytes(1)),
+            "UserDefinedValueTypeDefinition" => self.type_size(declaration.get("underlyingType")?),
+            "StructDefinition" => {
+                let members = declaration.get("members")?.as_array()?;
+                Some(TypeSize::Slots(self.layout(members.iter()).slots.max(1)))
+            }
+            _ => None,
+        }
+    }
+}
+
+fn is_storage_variable(node: &Value) -> bool {
+    node["nodeType"] == "VariableDeclaration"
+        && node["stateVariable"].as_bool().unwrap_or(true)
+        && !node["constant"].as_bool().unwrap_or(false)
+        && node["mutability"] != "immutable"
+        && node["mutability"] != "constant"
+        && node["storageLocation"] != "transient"
+}
+
+fn elementary_size(name: &str) -> Option<TypeSize> {
+    let bits = |digits: &str| digits.parse::<u64>().ok().map(|bits| bits / 8);
+    let size = match name {
+        "bool" => TypeSize::Bytes(1),
+        "address" | "address payable" => TypeSize::Bytes(20),
+        "string" | "bytes" => TypeSize::Slots(1),
+        "uint" | "int" => TypeSize::Bytes(32),
+        _ => {
+            if let Some(digits) = name
+                .strip_prefix("uint")
+                .or_else(|| name.strip_prefix("int"))
+            {
+                TypeSize::Bytes(bits(digits)?)
+            } else {
+                TypeSize::Bytes(name.strip_prefix("bytes")?.parse().ok()?)
+            }
+        }
+    };
+    Some(size)
+}
+
+/// Warnings for upgradeable contracts in `file_uri` whose own variables and `__gap` do not
+/// add up to [`RESERVED_SLOTS`].
+pub fn storage_gap_diagnostics(
+    ast_data: &Value,
+    file_uri: &Url,
+    source_bytes: &[u8],
+) -> Vec<Diagnostic> {
+    let index = StorageLayoutIndex::new(ast_data);
+    let source_ast = ast::source_unit(ast_data, file_uri);
+
+    let contracts = source_ast
+        .and_then(|source_ast| source_ast.get("nodes"))
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter(|node| node["nodeType"] == "ContractDefinition");
+
+    contracts
+        .filter_map(|contract| gap_diagnostic(&index, contract, source_bytes))
+        .collect()
+}
+
+fn gap_diagnostic(
+    index: &StorageLayoutIndex,
+    contract: &Value,
+    source_bytes: &[u8],
+) -> Option<Diagnostic> {
+    let layout = index.contract_layout(contract);
+    let gap = layout.variables.iter().find(|v| v.name == GAP_NAME)?;
+    let used = layout.slots - gap.size;
+    if used + gap.size == RESERVED_SLOTS {
+        return None;
+    }
+
+    let contract_name = contract["name"].as_str().unwrap_or_default();
+    let message = if used >= RESERVED_SLOTS {
+        format!(
+            "`{contract_name}` uses {used} storage slots, leaving no room for `{GAP_NAME}` \
+             within {RESERVED_SLOTS} reserved slots"
+        )
+    } else {
+        format!(
+            "`{contract_name}` uses {used} storage slots and a {}-slot `{GAP_NAME}`, {} in \
+             total; resize `{GAP_NAME}` to {} to keep {RESERVED_SLOTS} reserved slots",
+            gap.size,
+            used + gap.size,
+            RESERVED_SLOTS - used
+        )
+    };
+
+    let gap_node = index.nodes.get(&gap.id)?;
+    let (start, length, _) = parse_src(
+        gap_node
+            .get("nameLocation")
+            .or_else(|| gap_node.get("src"))?
+            .as_str()?,
+    )?;
+    Some(Diagnostic {
+        range: Range {
+            start: bytes_to_pos(source_bytes, start)?,
+            end: bytes_to_pos(source_bytes, start + length)?,
+        },
+        severity: Some(DiagnosticSeverity::WARNING),
+        code: Some(NumberOrString::String(STORAGE_GAP_CODE.to_string())),
+        source: Some("forge-lsp".to_string()),
+        message,
+        ..Diagnostic::default()
+    })
+}
+
+/// A state variable in the layout printed by `forge inspect <contract> storage-layout`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InspectedVariable {
+    /// `path:Name` of the contract declaring the variable.
+    pub contract: String,
+    pub label: String,
+    /// Decimal slot number, as forge prints it.
+    pub slot: String,
+    pub offset: u64,
+    pub type_label: String,
+    pub bytes: u64,
+}
+
+/// The storage layout of a contract, inherited variables included, in slot order.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InspectedLayout {
+    pub contract: String,
+    pub variables: Vec<InspectedVariable>,
+}
+
+impl InspectedLayout {
+    /// Parse the `forge inspect <contract> storage-layout --json` output `output`.
+    pub fn parse(contract: &str, output: &Value) -> Option<Self> {
+        let types = output.get("types");
+        let variables = output
+            .get("storage")?
+            .as_array()?
+            .iter()
+            .filter_map(|entry| {
+                let type_id = entry.get("type")?.as_str()?;
+                let type_info = types.and_then(|types| types.get(type_id));
+                let number = |value: Option<&Value>| match value? {
+                    Value::String(text) => text.parse().ok(),
+                    value => value.as_u64(),
+                };
+                Some(InspectedVariable {
+                    contract: entry.get("contract")?.as_str()?.to_string(),
+                    label: entry.get("label")?.as_str()?.to_string(),
+                    slot: match entry.get("slot")? {
+                        Value::String(slot) => slot.clone(),
+                        slot => slot.as_u64()?.to_string(),
+                    },
+                    offset: number(entry.get("offset")).unwrap_or_default(),
+                    type_label: type_info
+                        .and_then(|info| info.get("label")?.as_str())
+                        .unwrap_or(type_id)
+                        .to_string(),
+                    bytes: number(type_info.and_then(|info| info.get("numberOfBytes")))
+                        .unwrap_or_default(),
+                })
+            })
+            .collect();
+        Some(Self {
+            contract: contract.to_string(),
+            variables,
+        })
+    }
+
+    /// The variable `label` declared by the contract called `contract`.
+    pub fn variable(&self, contract: &str, label: &str) -> Option<&InspectedVariable> {
+        self.variables.iter().find(|variable| {
+            variable.label == label && contract_name(&variable.contract) == contract
+        })
+    }
+
+    /// The layout as a Markdown table.
+    pub fn to_markdown(&self) -> String {
+        let mut lines = vec![
+            format!("**Storage layout of `{}`**", self.contract),
+            String::new(),
+        ];
+        if self.variables.is_empty() {
+            lines.push("No state variables".to_string());
+            return lines.join("\n");
+        }
+        lines.push("| Slot | Offset | Bytes | Name | Type | Contract |".to_string());
+        lines.push("| --- | --- | --- | --- | --- | --- |".to_string());
+        for variable in &self.variables {
+            lines.push(format!(
+                "| {} | {} | {} | `{}` | `{}` | `{}` |",
+                variable.slot,
+                variable.offset,
+                variable.bytes,
+                variable.label,
+                variable.type_label,
+                contract_name(&variable.contract)
+            ));
+        }
+        lines.join("\n")
+    }
+}
+
+/// `Name` of a `path:Name` contract identifier.
+fn contract_name(identifier: &str) -> &str {
+    identifier
+        .rsplit_once(':')
+        .map_or(identifier, |(_, name)| name)
+}
+
+/// The contract and name of the state variable whose name is at `position` in
+/// `file_uri`.
+pub fn state_variable_at<'a>(
+    ast_data: &'a Value,
+    file_uri: &Url,
+    position: Position,
+    source_bytes: &[u8],
+) -> Option<(&'a str, &'a str)> {
+    let contracts = ast::source_unit(ast_data, file_uri)?
+        .get("nodes")?
+        .as_array()?
+        .iter()
+        .filter(|node| node["nodeType"] == "ContractDefinition");
+    for contract in contracts {
+        let variables = contract
+            .get("nodes")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter(|node| is_storage_variable(node));
+        for variable in variables {
+            let Some((start, length, _)) = variable
+                .get("nameLocation")
+                .and_then(Value::as_str)
+                .and_then(parse_src)
+            else {
+                continue;
+            };
+            let (Some(from), Some(to)) = (
+                bytes_to_pos(source_bytes, start),
+                bytes_to_pos(source_bytes, start + length),
+            ) else {
+                continue;
+            };
+            if from <= position && position <= to {
+                return Some((contract["name"].as_str()?, variable["name"].as_str()?));
+            }
+        }
+    }
+    None
+}
+
+/// Where the variable `label` of `contract` is stored, for its hover.
+pub fn storage_hover_section(
+    layout: &InspectedLayout,
+    contract: &str,
+    label: &str,
+) -> Option<String> {
+    let variable = layout.variable(contract, label)?;
+    Some(format!(
+        "---\nStorage: slot {}, offset {}, `{}` ({} bytes)",
+        variable.slot, variable.offset, variable.type_label, variable.bytes
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn elementary(name: &str) -> Value {
+        json!({ "nodeType": "ElementaryTypeName", "name": name })
+    }
+
+    fn variable(id: u64, name: &str, type_name: Value) -> Value {
+        json!({
+            "id": id,
+            "src": "0:0:0",
+            "nodeType": "VariableDeclaration",
+            "name": name,
+            "stateVariable": true,
+            "constant": false,
+            "mutability": "mutable",
+            "typeName": type_name
+        })
+    }
+
+    fn gap(id: u64, length: u64, src: &str) -> Value {
+        let mut gap = variable(
+            id,
+            GAP_NAME,
+            json!({
+                "nodeType": "ArrayTypeName",
+                "baseType": elementary("uint256"),
+                "length": { "nodeType": "Literal", "value": length.to_string() }
+            }),
+        );
+        gap["nameLocation"] = json!(src);
+        gap
+    }
+
+    fn mock_ast(path: &str, contract_nodes: Vec<Value>) -> Value {
+        ast::mock_ast(
+            path,
+            json!({
+                "id": 1,
+                "src": "0:0:0",
+                "nodeType": "SourceUnit",
+                "absolutePath": path,
+                "nodes": [{
+                    "id": 2,
+                    "src": "0:0:0",
+                    "nodeType": "ContractDefinition",
+                    "name": "Vault",
+                    "nodes": contract_nodes
+                }, {
+                    "id": 3,
+                    "src": "0:0:0",
+                    "nodeType": "StructDefinition",
+                    "name": "Position",
+                    "members": [
+                        variable(4, "owner", elementary("address")),
+                        variable(5, "open", elementary("bool")),
+                        variable(6, "amount", elementary("uint256"))
+                    ]
+                }]
+            }),
+        )
+    }
+
+    #[test]
+    fn test_contract_layout_packs_small_types() {
+        let ast = mock_ast(
+            "Vault.sol",
+            vec![
+                variable(10, "owner", elementary("address")),
+                variable(11, "paused", elementary("bool")),
+                variable(12, "total", elementary("uint256")),
+                variable(
+                    13,
+                    "position",
+                    json!({ "nodeType": "UserDefinedTypeName", "referencedDeclaration": 3 }),
+                ),
+                variable(14, "fee", elementary("uint16")),
+                variable(
+                    15,
+                    "balances",
+                    json!({
+                        "nodeType": "Mapping",
+                        "keyType": elementary("address"),
+                        "valueType": elementary("uint256")
+                    }),
+                ),
+                json!({
+                    "id": 16,
+                    "nodeType": "VariableDeclaration",
+                    "name": "VERSION",
+                    "constant": true,
+                    "mutability": "constant",
+                    "typeName": elementary("uint256")
+                }),
+            ],
+        );
+        let index = StorageLayoutIndex::new(&ast);
+        let contract = &ast["sources"]["Vault.sol"][0]["source_file"]["ast"]["nodes"][0];
+        let layout = index.contract_layout(contract);
+
+        let positions: Vec<(&str, u64, u64)> = layout
+            .variables
+            .iter()
+            .map(|v| (v.name.as_str(), v.slot, v.offset))
+            .collect();
+        assert_eq!(
+            positions,
+            vec![
+                ("owner", 0, 0),
+                ("paused", 0, 20),
+                ("total", 1, 0),
+                ("position", 2, 0),
+                ("fee", 4, 0),
+                ("balances", 5, 0),
+            ]
+        );
+        assert_eq!(layout.slots, 6);
+    }
+
+    #[test]
+    fn test_contract_layout_skips_transient_variables() {
+        let mut locked = variable(11, "locked", elementary("bool"));
+        locked["storageLocation"] = json!("transient");
+        let ast = mock_ast(
+            "Vault.sol",
+            vec![
+                variable(10, "owner", elementary("address")),
+                locked,
+                variable(12, "total", elementary("uint256")),
+            ],
+        );
+        let index = StorageLayoutIndex::new(&ast);
+        let contract = &ast["sources"]["Vault.sol"][0]["source_file"]["ast"]["nodes"][0];
+        let layout = index.contract_layout(contract);
+
+        let positions: Vec<(&str, u64, u64)> = layout
+            .variables
+            .iter()
+            .map(|v| (v.name.as_str(), v.slot, v.offset))
+            .collect();
+        assert_eq!(positions, vec![("owner", 0, 0), ("total", 1, 0)]);
+        assert_eq!(layout.slots, 2);
+    }
+
+    #[test]
+    fn test_storage_gap_diagnostics() {
+        let source = "contract Vault { uint256 a; uint256 b; uint256[48] private __gap; }";
+        let gap_src = format!("{}:5:0", source.find(GAP_NAME).unwrap());
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("Vault.sol");
+        let uri = Url::from_file_path(&path).unwrap();
+        let path = path.to_string_lossy();
+
+        // 2 variables + 48 gap slots
+        let balanced = mock_ast(
+            &path,
+            vec![
+                variable(10, "a", elementary("uint256")),
+                variable(11, "b", elementary("uint256")),
+                gap(12, 48, &gap_src),
+            ],
+        );
+        assert!(storage_gap_diagnostics(&balanced, &uri, source.as_bytes()).is_empty());
+
+        // A variable was added without shrinking the gap
+        let grown = mock_ast(
+            &path,
+            vec![
+                variable(10, "a", elementary("uint256")),
+                variable(11, "b", elementary("uint256")),
+                variable(13, "c", elementary("uint256")),
+                gap(12, 48, &gap_src),
+            ],
+        );
+        let diagnostics = storage_gap_diagnostics(&grown, &uri, source.as_bytes());
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].message,
+            "`Vault` uses 3 storage slots and a 48-slot `__gap`, 51 in total; resize `__gap` \
+             to 47 to keep 50 reserved slots"
+        );
+        assert_eq!(
+            diagnostics[0].range.start,
+            bytes_to_pos(source.as_bytes(), source.find(GAP_NAME).unwrap()).unwrap()
+        );
+
+        // Contracts without a gap are not upgradeable in this style
+        let plain = mock_ast(&path, vec![variable(10, "a", elementary("uint256"))]);
+        assert!(storage_gap_diagnostics(&plain, &uri, source.as_bytes()).is_empty());
+    }
+
+    #[test]
+    fn test_inspected_layout() {
+        let output = json!({
+            "storage": [
+                {
+                    "astId": 3,
+                    "contract": "src/Base.sol:Base",
+                    "label": "owner",
+                    "offset": 0,
+                    "slot": "0",
+                    "type": "t_address"
+                },
+                {
+                    "astId": 9,
+                    "contract": "src/Vault.sol:Vault",
+                    "label": "paused",
+                    "offset": 20,
+                    "slot": "0",
+                    "type": "t_bool"
+                },
+                {
+                    "astId": 11,
+                    "contract": "src/Vault.sol:Vault",
+                    "label": "balances",
+                    "offset": 0,
+                    "slot": "1",
+                    "type": "t_mapping(t_address,t_uint256)"
+                }
+            ],
+            "types": {
+                "t_address": { "encoding": "inplace", "label": "address", "numberOfBytes": "20" },
+                "t_bool": { "encoding": "inplace", "label": "bool", "numberOfBytes": "1" },
+                "t_mapping(t_address,t_uint256)": {
+                    "encoding": "mapping",
+                    "label": "mapping(address => uint256)",
+                    "numberOfBytes": "32"
+                }
+            }
+        });
+        let layout = InspectedLayout::parse("src/Vault.sol:Vault", &output).unwrap();
+        assert_eq!(layout.variables.len(), 3);
+        assert_eq!(
+            layout.variables[2],
+            InspectedVariable {
+                contract: "src/Vault.sol:Vault".to_string(),
+                label: "balances".to_string(),
+                slot: "1".to_string(),
+                offset: 0,
+                type_label: "mapping(address => uint256)".to_string(),
+                bytes: 32,
+            }
+        );
+
+        // Inherited variables are found under their declaring contract
+        assert!(layout.variable("Vault", "owner").is_none());
+        assert_eq!(
+            storage_hover_section(&layout, "Base", "owner").as_deref(),
+            Some("---\nStorage: slot 0, offset 0, `address` (20 bytes)")
+        );
+        assert!(
+            layout
+                .to_markdown()
+                .contains("| 0 | 20 | 1 | `paused` | `bool` | `Vault` |")
+        );
+        assert!(InspectedLayout::parse("Vault", &json!({})).is_none());
+    }
+
+    #[test]
+    fn test_state_variable_at() {
+        let source = "contract Vault { uint256 a; uint256 b; }";
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("Vault.sol");
+        let uri = Url::from_file_path(&path).unwrap();
+        let mut b = variable(11, "b", elementary("uint256"));
+        b["nameLocation"] = json!(format!("{}:1:0", source.find("b;").unwrap()));
+        let ast_data = mock_ast(&path.to_string_lossy(), vec![b]);
+
+        let at = |column| {
+            state_variable_at(&ast_data, &uri, Position::new(0, column), source.as_bytes())
+        };
+        assert_eq!(at(36), Some(("Vault", "b")));
+        assert_eq!(at(25), None);
+    }
+}
@@ -0,0 +1,426 @@
+//! Struct literals with named fields, `Limit({cap: 10, strict: false})`, checked against
+//! the struct declaration: fields left out, with a fix adding them with zero values, and
+//! fields given out of declaration order, with a fix reordering them.
+//!
+//! The literals come from the in-process parse, so the checks follow unsaved edits. Structs
+//! declared in other files are looked up in the project index by name.
+
+use serde_json::Value;
+use std::collections::HashMap;
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString, Range, Url};
+
+use crate::{
+    ast::{self, children, name, node_type, span_bounds},
+    code_actions::Fix,
+    deploy_script::placeholder,
+    edits::EditBuilder,
+    goto::bytes_to_pos,
+};
+
+/// Diagnostic code of a struct literal leaving out fields.
+pub const STRUCT_MISSING_FIELDS_CODE: &str = "struct-missing-fields";
+
+/// Diagnostic code of a struct literal giving its fields out of declaration order.
+pub const STRUCT_FIELD_ORDER_CODE: &str = "struct-field-order";
+
+/// How deep zero values of nested structs are spelled out.
+const MAX_NESTING: usize = 4;
+
+/// A struct declaration and the nodes of the AST it was found in, by id.
+struct Declared<'a> {
+    node: &'a Value,
+    index: &'a HashMap<u64, &'a Value>,
+}
+
+/// The struct `callee` constructs: the declaration it resolves to in the file, else the
+/// struct of the project named like the callee as written, `Pair` at file level or `Types.Pair`
+/// in a contract or library.
+fn struct_declaration<'a>(
+    callee: &Value,
+    source: &str,
+    local: &'a HashMap<u64, &'a Value>,
+    project: &'a HashMap<u64, &'a Value>,
+    project_units: &[&'a Value],
+) -> Option<Declared<'a>> {
+    if let Some(node) = callee
+        .get("referencedDeclaration")
+        .and_then(Value::as_u64)
+        .and_then(|id| local.get(&id))
+    {
+        return (node_type(node) == Some("StructDefinition"))
+            .then_some(Declared { node, index: local });
+    }
+    let (start, end) = span_bounds(callee)?;
+    let written: String = source
+        .get(start..end)?
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect();
+    let (scope, struct_name) = match written.split_once('.') {
+        Some((scope, struct_name)) => (Some(scope), struct_name),
+        None => (None, written.as_str()),
+    };
+    let is_struct =
+        |node: &&Value| node_type(node) == Some("StructDefinition") && name(node) == struct_name;
+    let node = project_units.iter().find_map(|unit| {
+        let mut nodes = children(unit, "nodes");
+        match scope {
+            None => nodes.find(is_struct),
+            Some(scope) => nodes
+                .find(|node| node_type(node) == Some("ContractDefinition") && name(node) == scope)
+                .and_then(|contract| children(contract, "nodes").find(is_struct)),
+        }
+    })?;
+    Some(Declared {
+        node,
+        index: project,
+    })
+}
+
+/// Source text of the type `type_name`, as written where it is declared.
+pub fn type_text(type_name: &Value) -> Option<String> {
+    match node_type(type_name)? {
+        "ElementaryTypeName" => {
+            let payable = type_name.get("stateMutability").and_then(Value::as_str)
+                == Some("payable")
+                && name(type_name) == "address";
+            Some(if payable {
+                "address payable".to_string()
+            } else {
+                name(type_name).to_string()
+            })
+        }
+        "UserDefinedTypeName" => Some(name(type_name.get("pathNode")?).to_string()),
+        "ArrayTypeName" => {
+            let base = type_text(type_name.get("baseType")?)?;
+            match type_name.get("length").filter(|length| !length.is_null()) {
+                None => Some(format!("{base}[]")),
+                Some(length) => Some(format!("{base}[{}]", length.get("value")?.as_str()?)),
+            }
+        }
+        _ => None,
+    }
+}
+
+/// An expression of the zero value of `type_name`, when it can be written in one.
+fn zero_value(type_name: &Value, index: &HashMap<u64, &Value>, depth: usize) -> Option<String> {
+    match node_type(type_name)? {
+        "ElementaryTypeName" => placeholder(&type_text(type_name)?),
+        "ArrayTypeName" if type_name.get("length").is_none_or(Value::is_null) => {
+            Some(format!("new {}(0)", type_text(type_name)?))
+        }
+        "UserDefinedTypeName" => {
+            let path = type_text(type_name)?;
+            let id = type_name.get("referencedDeclaration")?.as_u64()?;
+            let declaration = index.get(&id)?;
+            match node_type(declaration)? {
+                "EnumDefinition" => Some(format!("{path}(0)")),
+                "ContractDefinition" => Some(format!("{path}(address(0))")),
+                "UserDefinedValueTypeDefinition" => Some(format!(
+                    "{path}.wrap({})",
+                    zero_value(declaration.get("underlyingType")?, index, depth)?
+                )),
+                "StructDefinition" if depth < MAX_NESTING => {
+                    let fields: Vec<String> = children(declaration, "members")
+                        .map(|member| {
+                            let value = zero_value(member.get("typeName")?, index, depth + 1)?;
+                            Some(format!("{}: {value}", name(member)))
+                        })
+                        .collect::<Option<_>>()?;
+                    Some(format!("{path}({{{}}})", fields.join(", ")))
+                }
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+fn diagnostic(
+    range: Range,
+    severity: DiagnosticSeverity,
+    code: &str,
+    message: String,
+    fix: Option<Fix>,
+) -> Diagnostic {
+    Diagnostic {
+        range,
+        severity: Some(severity),
+        code: Some(NumberOrString::String(code.to_string())),
+        source: Some("forge-lsp".to_string()),
+        message,
+        data: fix.and_then(|fix| fix.to_data()),
+        ..Diagnostic::default()
+    }
+}
+
+/// `names` as inline code, like "`cap` and `strict`".
+fn listed(names: &[&str]) -> String {
+    let quoted: Vec<String> = names.iter().map(|name| format!("`{name}`")).collect();
+    match quoted.split_last() {
+        Some((last, rest)) if !rest.is_empty() => format!("{} and {last}", rest.join(", ")),
+        _ => quoted.concat(),
+    }
+}
+
+/// Diagnostics for the struct literals of `uri` with named fields: an error for missing
+/// fields, fixed by adding them with zero values, and information for fields out of
+/// declaration order, fixed by reordering them. `project_ast` resolves structs declared in
+/// other files.
+pub fn struct_literal_diagnostics(
+    tree: &Value,
+    uri: &Url,
+    source_bytes: &[u8],
+    project_ast: Option<&Value>,
+) -> Vec<Diagnostic> {
+    let (Some(sources), Some(unit)) = (tree.get("sources"), ast::source_unit(tree, uri)) else {
+        return vec![];
+    };
+    let local = ast::index_nodes(sources);
+    let project = project_ast
+        .and_then(|ast_data| ast_data.get("sources"))
+        .map(ast::index_nodes)
+        .unwrap_or_default();
+    let project_units: Vec<&Value> = project_ast
+        .and_then(|ast_data| ast_data.get("sources")?.as_object())
+        .into_iter()
+        .flat_map(|sources| sources.values())
+        .filter_map(|contents| contents.get(0)?.get("source_file")?.get("ast"))
+        .collect();
+    let source = String::from_utf8_lossy(source_bytes);
+
+    let mut diagnostics = vec![];
+    ast::walk(unit, &mut |call| {
+        if node_type(call) != Some("FunctionCall") {
+            return;
+        }
+        let given: Vec<&str> = children(call, "names").filter_map(Value::as_str).collect();
+        let arguments: Vec<(usize, usize)> = children(call, "arguments")
+            .filter_map(span_bounds)
+            .collect();
+        let (Some(callee), Some((_, call_end))) = (call.get("expression"), span_bounds(call))
+        else {
+            return;
+        };
+        if given.is_empty() || given.len() != arguments.len() {
+            return;
+        }
+        let Some(declared) = struct_declaration(callee, &source, &local, &project, &project_units)
+        else {
+            return;
+        };
+        let Some((callee_start, callee_end)) = span_bounds(callee) else {
+            return;
+        };
+        let (Some(start), Some(end)) = (
+            bytes_to_pos(source_bytes, callee_start),
+            bytes_to_pos(source_bytes, callee_end),
+        ) else {
+            return;
+        };
+        let range = Range::new(start, end);
+        let struct_name = name(declared.node);
+        let members: Vec<&Value> = children(declared.node, "members").collect();
+        let fields: Vec<&str> = members.iter().map(|member| name(member)).collect();
+
+        let missing: Vec<&Value> = members
+            .iter()
+            .copied()
+            .filter(|member| !given.contains(&name(member)))
+            .collect();
+        if !missing.is_empty() {
+            let names: Vec<&str> = missing.iter().map(|member| name(member)).collect();
+            let values: Option<Vec<String>> = missing
+                .iter()
+                .map(|member| {
+                    let value = zero_value(member.get("typeName")?, declared.index, 0)?;
+                    Some(format!("{}: {value}", name(member)))
+                })
+                .collect();
+            let fix = values.and_then(|values| {
+                let (_, last) = arguments.last()?;
+                let mut builder = EditBuilder::new(&source);
+                builder
+                    .insert(*last, format!(", {}", values.join(", ")))
+                    .ok()?;
+                Some(Fix::new("Add missing fields", builder.build()))
+            });
+            let plural = if names.len() == 1 { "field" } else { "fields" };
+            diagnostics.push(diagnostic(
+                range,
+                DiagnosticSeverity::ERROR,
+                STRUCT_MISSING_FIELDS_CODE,
+                format!("`{struct_name}` is missing {plural} {}", listed(&names)),
+                fix,
+            ));
+        }
+
+        // Only fields the struct declares can be put in its order
+        let positions: Option<Vec<usize>> = given
+            .iter()
+            .map(|field| fields.iter().position(|declared| declared == field))
+            .collect();
+        let Some(positions) = positions else {
+            return;
+        };
+        if positions.is_sorted() {
+            return;
+        }
+        let mut ordered: Vec<(usize, &str, (usize, usize))> = positions
+            .iter()
+            .zip(&given)
+            .zip(&arguments)
+            .map(|((position, field), argument)| (*position, *field, *argument))
+            .collect();
+        ordered.sort_by_key(|(position, ..)| *position);
+        let fix = (|| {
+            let texts: Vec<String> = ordered
+                .iter()
+                .map(|(_, field, (start, end))| {
+                    Some(format!("{field}: {}", source.get(*start..*end)?))
+                })
+                .collect::<Option<_>>()?;
+            let open = callee_end + source.get(callee_end..)?.find('{')?;
+            let close = open + source.get(open..call_end)?.rfind('}')?;
+            let mut builder = EditBuilder::new(&source);
+            builder.replace(open + 1, close, texts.join(", ")).ok()?;
+            Some(Fix::new(
+                "Reorder fields to declaration order",
+                builder.build(),
+            ))
+        })();
+        diagnostics.push(diagnostic(
+            range,
+            DiagnosticSeverity::INFORMATION,
+            STRUCT_FIELD_ORDER_CODE,
+            format!(
+                "fields of `{struct_name}` are not in declaration order: {}",
+                fields.join(", ")
+            ),
+            fix,
+        ));
+    });
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax;
+
+    const SOURCE: &str = "\
+contract Vault {
+    enum Mode { Open, Closed }
+    struct Limit { uint256 cap; address payable to; Mode mode; uint256[] ids; }
+    struct Rule { Limit limit; bool strict; }
+
+    function run() public {
+        Limit memory limit = Limit({cap: 10});
+        Rule memory rule = Rule({strict: true, limit: limit});
+        Limit memory full = Limit({ids: new uint256[](1), cap: 1, to: payable(address(0)), mode: Mode.Open});
+        Rule memory bare = Rule({strict: false});
+    }
+}
+";
+
+    fn diagnostics() -> Vec<Diagnostic> {
+        let path = "/project/src/Vault.sol";
+        let uri = Url::from_file_path(path).unwrap();
+        let tree = syntax::parse(path, SOURCE);
+        struct_literal_diagnostics(&tree, &uri, SOURCE.as_bytes(), None)
+    }
+
+    /// The line of the first edit of `diagnostic`'s fix, once applied.
+    fn fixed_line(diagnostic: &Diagnostic) -> String {
+        let fix = Fix::from_diagnostic(diagnostic).unwrap();
+        let text = EditBuilder::with_edits(SOURCE, &fix.edits).unwrap().apply();
+        let line = fix.edits[0].range.start.line as usize;
+        text.lines().nth(line).unwrap().trim().to_string()
+    }
+
+    #[test]
+    fn test_missing_and_misordered_struct_fields() {
+        let diagnostics = diagnostics();
+        let summary: Vec<(u32, &str, &str)> = diagnostics
+            .iter()
+            .map(|diagnostic| {
+                let Some(NumberOrString::String(code)) = &diagnostic.code else {
+                    panic!("expected a code");
+                };
+                (
+                    diagnostic.range.start.line,
+                    code.as_str(),
+                    diagnostic.message.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (
+                    6,
+                    STRUCT_MISSING_FIELDS_CODE,
+                    "`Limit` is missing fields `to`, `mode` and `ids`"
+                ),
+                (
+                    7,
+                    STRUCT_FIELD_ORDER_CODE,
+                    "fields of `Rule` are not in declaration order: limit, strict"
+                ),
+                (
+                    8,
+                    STRUCT_FIELD_ORDER_CODE,
+                    "fields of `Limit` are not in declaration order: cap, to, mode, ids"
+                ),
+                (
+                    9,
+                    STRUCT_MISSING_FIELDS_CODE,
+                    "`Rule` is missing field `limit`"
+                ),
+            ]
+        );
+
+        assert_eq!(
+            fixed_line(&diagnostics[0]),
+            "Limit memory limit = Limit({cap: 10, to: payable(address(0)), mode: Mode(0), \
+             ids: new uint256[](0)});"
+        );
+        assert_eq!(
+            fixed_line(&diagnostics[1]),
+            "Rule memory rule = Rule({limit: limit, strict: true});"
+        );
+        assert_eq!(
+            fixed_line(&diagnostics[2]),
+            "Limit memory full = Limit({cap: 1, to: payable(address(0)), mode: Mode.Open, \
+             ids: new uint256[](1)});"
+        );
+        // Nested structs are spelled out field by field
+        assert_eq!(
+            fixed_line(&diagnostics[3]),
+            "Rule memory bare = Rule({strict: false, limit: Limit({cap: 0, \
+             to: payable(address(0)), mode: Mode(0), ids: new uint256[](0)})});"
+        );
+    }
+
+    #[test]
+    fn test_structs_of_other_files_resolve_in_the_project() {
+        let library = "library Types { struct Pair { uint256 a; bool b; } }";
+        let library_tree = syntax::parse("/project/src/Types.sol", library);
+        let source = "\
+import {Types} from \"./Types.sol\";
+contract User {
+    function run() public { Types.Pair memory p = Types.Pair({b: true}); }
+}
+";
+        let path = "/project/src/User.sol";
+        let uri = Url::from_file_path(path).unwrap();
+        let tree = syntax::parse(path, source);
+
+        let diagnostics =
+            struct_literal_diagnostics(&tree, &uri, source.as_bytes(), Some(&library_tree));
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].message, "`Pair` is missing field `a`");
+        let fix = Fix::from_diagnostic(&diagnostics[0]).unwrap();
+        assert_eq!(fix.edits[0].new_text, ", a: 0");
+    }
+}
@@ -0,0 +1,217 @@
+//! Comments silencing the server's own diagnostics, in the style of `solhint`:
+//!
+//! ```solidity
+//! // forge-lsp-disable-file natspec-missing
+//!
+//! // forge-lsp-disable-next-line unused-return
+//! token.transfer(to, amount);
+//! token.approve(spender, 0); // forge-lsp-disable-line unused-return, other-code
+//! ```
+//!
+//! A directive lists the codes it silences, separated by spaces or commas; one without
+//! codes silences every code. Diagnostics of forge's build and lint have their own
+//! directives and are never silenced here.
+
+use std::collections::HashMap;
+use tower_lsp::lsp_types::{Diagnostic, NumberOrString};
+
+/// Source of the diagnostics the directives apply to.
+const SOURCE: &str = "forge-lsp";
+
+/// Comment silencing diagnostics of the line after it.
+pub const DISABLE_NEXT_LINE: &str = "forge-lsp-disable-next-line";
+
+/// Comment silencing diagnostics of its own line.
+pub const DISABLE_LINE: &str = "forge-lsp-disable-line";
+
+/// Comment silencing diagnostics of the whole file, wherever it is.
+pub const DISABLE_FILE: &str = "forge-lsp-disable-file";
+
+/// Offset of the `//` starting the comment of `line`. String literals are skipped the way
+/// `grammar::tokens` skips them, so `"https://x"` starts no comment.
+fn comment_start(line: &str) -> Option<usize> {
+    let bytes = line.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'/' if bytes.get(i + 1) == Some(&b'/') => return Some(i),
+            quote @ (b'"' | b'\'') => {
+                i += 1;
+                while i < bytes.len() && bytes[i] != quote {
+                    i += if bytes[i] == b'\\' { 2 } else { 1 };
+                }
+                i += 1;
+            }
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+/// The codes listed by the comment `directive` on `line`, empty for every code, or `None`
+/// if the line has no such comment.
+fn directive_codes<'a>(line: &'a str, directive: &str) -> Option<Vec<&'a str>> {
+    let comment = &line[comment_start(line)? + 2..];
+    let codes = comment.trim_start().strip_prefix(directive)?;
+    // `forge-lsp-disable-line-foo` is another directive
+    if codes.starts_with(|c: char| !c.is_whitespace()) {
+        return None;
+    }
+    Some(
+        codes
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|code| !code.is_empty())
+            .collect(),
+    )
+}
+
+fn silences(codes: &[Vec<&str>], code: &str) -> bool {
+    codes
+        .iter()
+        .any(|listed| listed.is_empty() || listed.contains(&code))
+}
+
+/// The suppression directives of a source.
+#[derive(Debug, Default)]
+pub struct Suppressions<'a> {
+    /// Code lists of the directives applying to each line.
+    lines: HashMap<u32, Vec<Vec<&'a str>>>,
+    /// Code lists of the file-level directives.
+    file: Vec<Vec<&'a str>>,
+}
+
+impl<'a> Suppressions<'a> {
+    pub fn parse(source: &'a str) -> Self {
+        let mut suppressions = Self::default();
+        for (line, text) in (0..).zip(source.lines()) {
+            if let Some(codes) = directive_codes(text, DISABLE_LINE) {
+                suppressions.lines.entry(line).or_default().push(codes);
+            } else if let Some(codes) = directive_codes(text, DISABLE_NEXT_LINE) {
+                suppressions.lines.entry(line + 1).or_default().push(codes);
+            } else if let Some(codes) = directive_codes(text, DISABLE_FILE) {
+                suppressions.file.push(codes);
+            }
+        }
+        suppressions
+    }
+
+    /// Whether a directive silences `code` on `line`.
+    pub fn is_suppressed(&self, line: u32, code: &str) -> bool {
+        silences(&self.file, code)
+            || self
+                .lines
+                .get(&line)
+                .is_some_and(|codes| silences(codes, code))
+    }
+}
+
+/// The code listed by a directive of `line` that covers the byte `offset` of the line, with
+/// its byte range.
+pub fn code_at(line: &str, offset: usize) -> Option<(&str, (usize, usize))> {
+    let codes = [DISABLE_LINE, DISABLE_NEXT_LINE, DISABLE_FILE]
+        .iter()
+        .find_map(|directive| directive_codes(line, directive))?;
+    codes.into_iter().find_map(|code| {
+        // The codes are slices of the line
+        let start = code.as_ptr() as usize - line.as_ptr() as usize;
+        let end = start + code.len();
+        (start..=end)
+            .contains(&offset)
+            .then_some((code, (start, end)))
+    })
+}
+
+/// Whether a directive of `source` silences `code` on `line`.
+pub fn is_suppressed(source: &str, line: u32, code: &str) -> bool {
+    Suppressions::parse(source).is_suppressed(line, code)
+}
+
+/// Drop the server's diagnostics that the directives of `source` silence, by the code and
+/// the first line of each.
+pub fn retain_unsuppressed(diagnostics: &mut Vec<Diagnostic>, source: &str) {
+    let suppressions = Suppressions::parse(source);
+    diagnostics.retain(|diagnostic| match &diagnostic.code {
+        Some(NumberOrString::String(code)) if diagnostic.source.as_deref() == Some(SOURCE) => {
+            !suppressions.is_suppressed(diagnostic.range.start.line, code)
+        }
+        _ => true,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower_lsp::lsp_types::{Position, Range};
+
+    #[test]
+    fn test_suppression_comments() {
+        let source = "\
+a(); // forge-lsp-disable-line unused-return, other
+// forge-lsp-disable-next-line
+b();
+c(); // forge-lsp-disable-line-other
+";
+        assert!(is_suppressed(source, 0, "unused-return"));
+        assert!(is_suppressed(source, 0, "other"));
+        assert!(!is_suppressed(source, 0, "third"));
+        assert!(is_suppressed(source, 2, "unused-return"));
+        assert!(!is_suppressed(source, 3, "unused-return"));
+
+        let line = source.lines().next().unwrap();
+        assert_eq!(code_at(line, 33), Some(("unused-return", (31, 44))));
+        assert_eq!(code_at(line, 48), Some(("other", (46, 51))));
+        assert_eq!(code_at(line, 20), None);
+        assert_eq!(code_at("a(); // unused-return", 12), None);
+    }
+
+    #[test]
+    fn test_slashes_in_strings_start_no_comment() {
+        let source = "\
+string url = \"https://x\"; // forge-lsp-disable-line unused-return
+string s = 'a\\'//'; a(); // forge-lsp-disable-line other
+string t = \"// forge-lsp-disable-line unused-return\";
+";
+        assert!(is_suppressed(source, 0, "unused-return"));
+        assert!(is_suppressed(source, 1, "other"));
+        assert!(!is_suppressed(source, 2, "unused-return"));
+        let line = source.lines().next().unwrap();
+        assert_eq!(code_at(line, 55), Some(("unused-return", (52, 65))));
+    }
+
+    #[test]
+    fn test_file_directives_and_diagnostic_sources() {
+        let source = "\
+contract Vault {
+    // forge-lsp-disable-file natspec-missing storage-gap
+    function run() public {}
+}
+";
+        let diagnostic = |line: u32, code: &str, source: &str| Diagnostic {
+            range: Range::new(Position::new(line, 0), Position::new(line, 1)),
+            code: Some(NumberOrString::String(code.to_string())),
+            source: Some(source.to_string()),
+            ..Diagnostic::default()
+        };
+        let mut diagnostics = vec![
+            diagnostic(0, "storage-gap", SOURCE),
+            diagnostic(2, "natspec-missing", SOURCE),
+            diagnostic(2, "unused-return", SOURCE),
+            // Forge's own findings are silenced with forge's directives
+            diagnostic(2, "natspec-missing", "forge-lint"),
+        ];
+        retain_unsuppressed(&mut diagnostics, source);
+        let kept: Vec<(&str, &str)> = diagnostics
+            .iter()
+            .map(|diagnostic| {
+                let Some(NumberOrString::String(code)) = &diagnostic.code else {
+                    unreachable!()
+                };
+                (code.as_str(), diagnostic.source.as_deref().unwrap())
+            })
+            .collect();
+        assert_eq!(
+            kept,
+            [("unused-return", SOURCE), ("natspec-missing", "forge-lint")]
+        );
+    }
+}
@@ -2,7 +2,7 @@
 
 use serde_json::Value;
 use tower_lsp::lsp_types::{DocumentSymbol, Location, Range, SymbolInformation, SymbolKind, Url, Position};
-use crate::utils::byte_offset_to_position;
+use crate::{paths, utils::byte_offset_to_position};
 
 pub fn extract_symbols(ast_data: &Value) -> Vec<SymbolInformation> {
     let mut symbols = Vec::new();
@@ -37,10 +37,13 @@ pub fn extract_symbols(ast_data: &Value) -> Vec<SymbolInformation> {
 pub fn extract_document_symbols(ast_data: &Value, file_path: &str) -> Vec<DocumentSymbol> {
     let mut symbols = Vec::new();
 
+    let file_key = paths::normalize_path(file_path);
+
     if let Some(sources) = ast_data.get("sources")
         && let Some(sources_obj) = sources.as_object() {
             for (path, contents) in sources_obj {
-                if (path == file_path || path.ends_with(&format!("/{}", file_path)) || path.ends_with(file_path))
+                let path = paths::normalize_path(path);
+                if (path == file_key || path.ends_with(&format!("/{}", file_key)) || path.ends_with(&file_key))
                     && let Some(contents_array) = contents.as_array()
                     && let Some(first_content) = contents_array.first()
                     && let Some(source_file) = first_content.get("source_file")
@@ -55,402 +58,138 @@ pub fn extract_document_symbols(ast_data: &Value, file_path: &str) -> Vec<Docume
 }
 
 fn extract_document_symbols_from_ast(ast: &Value, file_path: &str) -> Vec<DocumentSymbol> {
-    let mut symbols = Vec::new();
-
-    // First, find all top-level nodes (contracts, interfaces, libraries, etc.)
-    if let Some(nodes) = ast.get("nodes").and_then(|v| v.as_array()) {
-        for node in nodes {
-            if let Some(node_type) = node.get("nodeType").and_then(|v| v.as_str()) {
-                match node_type {
-                    "ContractDefinition" | "InterfaceDefinition" | "LibraryDefinition" => {
-                        if let Some(symbol) = create_contract_document_symbol_with_children(node, file_path) {
-                            symbols.push(symbol);
-                        }
-                    }
-                    "UsingForDirective" => {
-                        if let Some(symbol) = create_using_for_document_symbol(node, file_path) {
-                            symbols.push(symbol);
-                        }
-                    }
-                    "ImportDirective" => {
-                        if let Some(symbol) = create_import_document_symbol(node, file_path) {
-                            symbols.push(symbol);
-                        }
-                    }
-                    "PragmaDirective" => {
-                        if let Some(symbol) = create_pragma_document_symbol(node, file_path) {
-                            symbols.push(symbol);
-                        }
-                    }
-                    _ => {}
-                }
-            }
-        }
-    }
-
-    symbols
-}
-
-fn create_contract_document_symbol_with_children(node: &Value, file_path: &str) -> Option<DocumentSymbol> {
-    let name = node.get("name").and_then(|v| v.as_str())?;
-    let range = get_node_range(node, file_path)?;
-    let mut children = Vec::new();
-
-    // Process contract members
-    if let Some(nodes) = node.get("nodes").and_then(|v| v.as_array()) {
-        for member_node in nodes {
-            if let Some(node_type) = member_node.get("nodeType").and_then(|v| v.as_str()) {
-                match node_type {
-                    "FunctionDefinition" => {
-                        if let Some(symbol) = create_function_document_symbol_with_children(member_node, file_path) {
-                            children.push(symbol);
-                        }
-                    }
-                    "VariableDeclaration" => {
-                        if let Some(symbol) = create_variable_document_symbol(member_node, file_path) {
-                            children.push(symbol);
-                        }
-                    }
-                    "EventDefinition" => {
-                        if let Some(symbol) = create_event_document_symbol(member_node, file_path) {
-                            children.push(symbol);
-                        }
-                    }
-                    "ModifierDefinition" => {
-                        if let Some(symbol) = create_modifier_document_symbol(member_node, file_path) {
-                            children.push(symbol);
-                        }
-                    }
-                    "StructDefinition" => {
-                        if let Some(symbol) = create_struct_document_symbol_with_children(member_node, file_path) {
-                            children.push(symbol);
-                        }
-                    }
-                    "EnumDefinition" => {
-                        if let Some(symbol) = create_enum_document_symbol_with_children(member_node, file_path) {
-                            children.push(symbol);
-                        }
-                    }
-                    "ConstructorDefinition" => {
-                        if let Some(symbol) = create_constructor_document_symbol(member_node, file_path) {
-                            children.push(symbol);
-                        }
-                    }
-                    "ErrorDefinition" => {
-                        if let Some(symbol) = create_error_document_symbol(member_node, file_path) {
-                            children.push(symbol);
-                        }
-                    }
-                    "UsingForDirective" => {
-                        if let Some(symbol) = create_using_for_document_symbol(member_node, file_path) {
-                            children.push(symbol);
-                        }
-                    }
-                    "FallbackFunctionDefinition" => {
-                        if let Some(symbol) = create_fallback_document_symbol(member_node, file_path) {
-                            children.push(symbol);
-                        }
-                    }
-                    "ReceiveFunctionDefinition" => {
-                        if let Some(symbol) = create_receive_document_symbol(member_node, file_path) {
-                            children.push(symbol);
-                        }
-                    }
-                    _ => {}
-                }
-            }
-        }
-    }
-
-    Some(DocumentSymbol {
-        name: name.to_string(),
-        detail: None,
-        kind: SymbolKind::CLASS,
-        range,
-        selection_range: range,
-        children: if children.is_empty() { None } else { Some(children) },
-        tags: None,
-        deprecated: None,
-    })
-}
-
-
-
-fn create_function_document_symbol_with_children(node: &Value, file_path: &str) -> Option<DocumentSymbol> {
-    let range = get_node_range(node, file_path)?;
-    let is_constructor = node.get("kind").and_then(|v| v.as_str()) == Some("constructor");
-
-    let name = if is_constructor {
-        "constructor".to_string()
-    } else {
-        match node.get("name").and_then(|v| v.as_str()) {
-            Some(n) if !n.is_empty() => n.to_string(),
-            _ => return None, // Skip functions with no name
-        }
-    };
-
-    let kind = if is_constructor {
-        SymbolKind::CONSTRUCTOR
-    } else {
-        SymbolKind::FUNCTION
+    // Offsets are converted against the file the AST was built from
+    let Ok(content) = std::fs::read_to_string(file_path) else {
+        return Vec::new();
     };
-
-    // Extract parameters as children
-    let mut children = Vec::new();
-
-    // Try different AST structures for parameters
-    let param_array = node.get("parameters").and_then(|p| p.get("parameters")).and_then(|p| p.as_array())
-        .or_else(|| node.get("parameters").and_then(|p| p.as_array()));
-
-    if let Some(parameters) = param_array {
-        for param in parameters {
-            if let Some(param_symbol) = create_parameter_document_symbol(param, file_path) {
-                children.push(param_symbol);
-            }
-        }
-    }
-
-    Some(DocumentSymbol {
-        name,
-        detail: None,
-        kind,
-        range,
-        selection_range: range,
-        children: if children.is_empty() { None } else { Some(children) },
-        tags: None,
-        deprecated: None,
-    })
+    outline(ast, &content)
 }
 
-fn create_variable_document_symbol(node: &Value, file_path: &str) -> Option<DocumentSymbol> {
-    let name = node.get("name").and_then(|v| v.as_str())?;
-    let range = get_node_range(node, file_path)?;
-
-    // Determine if this is a state variable or local variable
-    let kind = if is_state_variable(node) {
-        SymbolKind::FIELD
-    } else {
-        SymbolKind::VARIABLE
-    };
-
-    Some(DocumentSymbol {
-        name: name.to_string(),
-        detail: None,
-        kind,
-        range,
-        selection_range: range,
-        children: None,
-        tags: None,
-        deprecated: None,
-    })
+/// Document symbols of the source unit `ast` parsed from `content`.
+pub fn outline(ast: &Value, content: &str) -> Vec<DocumentSymbol> {
+    outline_children(ast.get("nodes"), Container::SourceUnit, content).unwrap_or_default()
 }
 
-fn create_event_document_symbol(node: &Value, file_path: &str) -> Option<DocumentSymbol> {
-    let name = node.get("name").and_then(|v| v.as_str())?;
-    let range = get_node_range(node, file_path)?;
-
-    Some(DocumentSymbol {
-        name: name.to_string(),
-        detail: None,
-        kind: SymbolKind::EVENT,
-        range,
-        selection_range: range,
-        children: None,
-        tags: None,
-        deprecated: None,
-    })
+/// The outline node a declaration is nested in, which decides the kind of variables.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Container {
+    SourceUnit,
+    Contract,
+    Struct,
+    /// Functions, modifiers, events and errors, whose children are parameters
+    Callable,
 }
 
-fn create_modifier_document_symbol(node: &Value, file_path: &str) -> Option<DocumentSymbol> {
-    let name = node.get("name").and_then(|v| v.as_str())?;
-    let range = get_node_range(node, file_path)?;
-
-    Some(DocumentSymbol {
-        name: name.to_string(),
-        detail: None,
-        kind: SymbolKind::METHOD, // Modifiers are represented as methods
-        range,
-        selection_range: range,
-        children: None,
-        tags: None,
-        deprecated: None,
-    })
+fn outline_children(
+    nodes: Option<&Value>,
+    container: Container,
+    content: &str,
+) -> Option<Vec<DocumentSymbol>> {
+    let children: Vec<DocumentSymbol> = nodes?
+        .as_array()?
+        .iter()
+        .filter_map(|node| outline_symbol(node, container, content))
+        .collect();
+    if children.is_empty() { None } else { Some(children) }
 }
 
-fn create_struct_document_symbol_with_children(node: &Value, file_path: &str) -> Option<DocumentSymbol> {
-    let name = node.get("name").and_then(|v| v.as_str())?;
-    let range = get_node_range(node, file_path)?;
-
-    // Extract struct members as children
+/// Parameters followed by return parameters of a callable.
+fn parameter_children(node: &Value, content: &str) -> Option<Vec<DocumentSymbol>> {
     let mut children = Vec::new();
-    if let Some(members) = node.get("members").and_then(|v| v.as_array()) {
-        for member in members {
-            if let Some(member_symbol) = create_struct_member_document_symbol(member, file_path) {
-                children.push(member_symbol);
-            }
+    for key in ["parameters", "returnParameters"] {
+        let list = node.get(key).and_then(|p| p.get("parameters").or(Some(p)));
+        if let Some(params) = outline_children(list, Container::Callable, content) {
+            children.extend(params);
         }
     }
-
-    Some(DocumentSymbol {
-        name: name.to_string(),
-        detail: None,
-        kind: SymbolKind::STRUCT,
-        range,
-        selection_range: range,
-        children: if children.is_empty() { None } else { Some(children) },
-        tags: None,
-        deprecated: None,
-    })
-}
-
-fn create_struct_member_document_symbol(node: &Value, file_path: &str) -> Option<DocumentSymbol> {
-    let name = node.get("name").and_then(|v| v.as_str())?;
-    let range = get_node_range(node, file_path)?;
-
-    Some(DocumentSymbol {
-        name: name.to_string(),
-        detail: None,
-        kind: SymbolKind::FIELD,
-        range,
-        selection_range: range,
-        children: None,
-        tags: None,
-        deprecated: None,
-    })
-}
-
-fn create_enum_document_symbol_with_children(node: &Value, file_path: &str) -> Option<DocumentSymbol> {
-    let name = node.get("name").and_then(|v| v.as_str())?;
-    let range = get_node_range(node, file_path)?;
-
-    // Extract enum members as children
-    let mut children = Vec::new();
-    if let Some(members) = node.get("members").and_then(|v| v.as_array()) {
-        for member in members {
-            if let Some(member_symbol) = create_enum_member_document_symbol(member, file_path) {
-                children.push(member_symbol);
-            }
+    if children.is_empty() { None } else { Some(children) }
+}
+
+fn outline_symbol(node: &Value, container: Container, content: &str) -> Option<DocumentSymbol> {
+    let node_type = node.get("nodeType").and_then(|v| v.as_str())?;
+    let name = node.get("name").and_then(|v| v.as_str()).filter(|n| !n.is_empty());
+    let mut detail = None;
+
+    let (name, kind, children) = match node_type {
+        "ContractDefinition" => {
+            let kind = match node.get("contractKind").and_then(|v| v.as_str()) {
+                Some("interface") => SymbolKind::INTERFACE,
+                Some("library") => SymbolKind::MODULE,
+                _ => SymbolKind::CLASS,
+            };
+            let members = outline_children(node.get("nodes"), Container::Contract, content);
+            (name?.to_string(), kind, members)
         }
-    }
-
-    Some(DocumentSymbol {
-        name: name.to_string(),
-        detail: None,
-        kind: SymbolKind::STRUCT,
-        range,
-        selection_range: range,
-        children: if children.is_empty() { None } else { Some(children) },
-        tags: None,
-        deprecated: None,
-    })
-}
-
-fn create_enum_member_document_symbol(node: &Value, file_path: &str) -> Option<DocumentSymbol> {
-    let name = node.get("name").and_then(|v| v.as_str())?;
-    let range = get_node_range(node, file_path)?;
-
-    Some(DocumentSymbol {
-        name: name.to_string(),
-        detail: None,
-        kind: SymbolKind::ENUM,
-        range,
-        selection_range: range,
-        children: None,
-        tags: None,
-        deprecated: None,
-    })
-}
-
-fn create_constructor_document_symbol(node: &Value, file_path: &str) -> Option<DocumentSymbol> {
-    let range = get_node_range(node, file_path)?;
-
-    Some(DocumentSymbol {
-        name: "constructor".to_string(),
-        detail: None,
-        kind: SymbolKind::CONSTRUCTOR,
-        range,
-        selection_range: range,
-        children: None,
-        tags: None,
-        deprecated: None,
-    })
-}
-
-fn create_error_document_symbol(node: &Value, file_path: &str) -> Option<DocumentSymbol> {
-    let name = node.get("name").and_then(|v| v.as_str())?;
-    let range = get_node_range(node, file_path)?;
-
-    Some(DocumentSymbol {
-        name: name.to_string(),
-        detail: None,
-        kind: SymbolKind::EVENT, // Errors are similar to events in Solidity
-        range,
-        selection_range: range,
-        children: None,
-        tags: None,
-        deprecated: None,
-    })
-}
-
-fn create_fallback_document_symbol(node: &Value, file_path: &str) -> Option<DocumentSymbol> {
-    let range = get_node_range(node, file_path)?;
-
-    Some(DocumentSymbol {
-        name: "fallback".to_string(),
-        detail: None,
-        kind: SymbolKind::FUNCTION,
-        range,
-        selection_range: range,
-        children: None,
-        tags: None,
-        deprecated: None,
-    })
-}
-
-fn create_receive_document_symbol(node: &Value, file_path: &str) -> Option<DocumentSymbol> {
-    let range = get_node_range(node, file_path)?;
-
-    Some(DocumentSymbol {
-        name: "receive".to_string(),
-        detail: None,
-        kind: SymbolKind::FUNCTION,
-        range,
-        selection_range: range,
-        children: None,
-        tags: None,
-        deprecated: None,
-    })
-}
-
-fn create_parameter_document_symbol(node: &Value, file_path: &str) -> Option<DocumentSymbol> {
-    let name = node.get("name").and_then(|v| v.as_str())?;
-    // Skip unnamed parameters
-    if name.is_empty() {
-        return None;
-    }
+        "FunctionDefinition" => {
+            let (name, kind) = match node.get("kind").and_then(|v| v.as_str()) {
+                Some("constructor") => ("constructor".to_string(), SymbolKind::CONSTRUCTOR),
+                Some(kind @ ("fallback" | "receive")) => (kind.to_string(), SymbolKind::FUNCTION),
+                _ => (name?.to_string(), SymbolKind::FUNCTION),
+            };
+            (name, kind, parameter_children(node, content))
+        }
+        // Modifiers are represented as methods, errors are similar to events
+        "ModifierDefinition" => {
+            (name?.to_string(), SymbolKind::METHOD, parameter_children(node, content))
+        }
+        "EventDefinition" | "ErrorDefinition" => {
+            (name?.to_string(), SymbolKind::EVENT, parameter_children(node, content))
+        }
+        "StructDefinition" | "EnumDefinition" => {
+            let members = outline_children(node.get("members"), Container::Struct, content);
+            (name?.to_string(), SymbolKind::STRUCT, members)
+        }
+        "EnumValue" => (name?.to_string(), SymbolKind::ENUM, None),
+        "VariableDeclaration" => {
+            let kind = match container {
+                Container::Contract | Container::Struct => SymbolKind::FIELD,
+                Container::SourceUnit => SymbolKind::CONSTANT,
+                Container::Callable => SymbolKind::VARIABLE,
+            };
+            detail = node
+                .get("typeDescriptions")
+                .and_then(|t| t.get("typeString"))
+                .and_then(|v| v.as_str())
+                .map(str::to_string);
+            (name?.to_string(), kind, None)
+        }
+        // Using directives are properties/attributes
+        "UsingForDirective" => (using_for_name(node), SymbolKind::PROPERTY, None),
+        "ImportDirective" => {
+            let name = match node.get("file").and_then(|v| v.as_str()) {
+                Some(file) => format!("import {}", file),
+                None => "import".to_string(),
+            };
+            (name, SymbolKind::MODULE, None)
+        }
+        // Pragma directives are like string literals
+        "PragmaDirective" => (pragma_name(node), SymbolKind::STRING, None),
+        _ => return None,
+    };
 
-    let range = get_node_range(node, file_path)?;
+    let range = src_range(node.get("src").and_then(|v| v.as_str())?, content)?;
+    let selection_range = node
+        .get("nameLocation")
+        .and_then(|v| v.as_str())
+        .and_then(|src| src_range(src, content))
+        .filter(|name_range| name_range.start >= range.start && name_range.end <= range.end)
+        .unwrap_or(range);
 
     Some(DocumentSymbol {
-        name: name.to_string(),
-        detail: None,
-        kind: SymbolKind::VARIABLE,
+        name,
+        detail,
+        kind,
         range,
-        selection_range: range,
-        children: None,
+        selection_range,
+        children,
         tags: None,
         deprecated: None,
     })
 }
 
-
-
-fn create_using_for_document_symbol(node: &Value, file_path: &str) -> Option<DocumentSymbol> {
-    let range = get_node_range(node, file_path)?;
-
+fn using_for_name(node: &Value) -> String {
     // Build the name from the AST data
-    let mut name_parts = Vec::new();
-    name_parts.push("using".to_string());
+    let mut name_parts = vec!["using".to_string()];
 
     // Add library name if present
     if let Some(library_name) = node.get("libraryName")
@@ -466,18 +205,7 @@ fn create_using_for_document_symbol(node: &Value, file_path: &str) -> Option<Doc
             name_parts.push(name_str);
         }
 
-    let name = name_parts.join(" ");
-
-    Some(DocumentSymbol {
-        name,
-        detail: None,
-        kind: SymbolKind::PROPERTY, // Using directives are properties/attributes
-        range,
-        selection_range: range,
-        children: None,
-        tags: None,
-        deprecated: None,
-    })
+    name_parts.join(" ")
 }
 
 fn extract_type_name(type_node: &Value) -> Option<String> {
@@ -499,33 +227,9 @@ fn extract_type_name(type_node: &Value) -> Option<String> {
     }
 }
 
-fn create_import_document_symbol(node: &Value, file_path: &str) -> Option<DocumentSymbol> {
-    let range = get_node_range(node, file_path)?;
-
-    // Try to get the file name being imported
-    let name = if let Some(file) = node.get("file").and_then(|v| v.as_str()) {
-        format!("import {}", file)
-    } else {
-        "import".to_string()
-    };
-
-    Some(DocumentSymbol {
-        name,
-        detail: None,
-        kind: SymbolKind::MODULE,
-        range,
-        selection_range: range,
-        children: None,
-        tags: None,
-        deprecated: None,
-    })
-}
-
-fn create_pragma_document_symbol(node: &Value, file_path: &str) -> Option<DocumentSymbol> {
-    let range = get_node_range(node, file_path)?;
-
+fn pragma_name(node: &Value) -> String {
     // Extract a clean pragma name
-    let name = if let Some(literals) = node.get("literals").and_then(|v| v.as_array()) {
+    if let Some(literals) = node.get("literals").and_then(|v| v.as_array()) {
         let parts: Vec<String> = literals.iter()
             .filter_map(|v| v.as_str())
             .map(|s| s.trim().to_string()) // Trim spaces from each part
@@ -543,18 +247,7 @@ fn create_pragma_document_symbol(node: &Value, file_path: &str) -> Option<Docume
         }
     } else {
         "pragma".to_string()
-    };
-
-    Some(DocumentSymbol {
-        name,
-        detail: None,
-        kind: SymbolKind::STRING, // Pragma directives are like string literals
-        range,
-        selection_range: range,
-        children: None,
-        tags: None,
-        deprecated: None,
-    })
+    }
 }
 
 fn extract_symbols_from_ast(ast: &Value, file_path: &str) -> Vec<SymbolInformation> {
@@ -614,7 +307,7 @@ fn create_contract_symbol_info(node: &Value, file_path: &str) -> Option<SymbolIn
     let name = node.get("name").and_then(|v| v.as_str())?;
     let range = get_node_range(node, file_path)?;
     let location = Location {
-        uri: Url::from_file_path(file_path).ok()?,
+        uri: source_uri(file_path)?,
         range,
     };
 
@@ -632,7 +325,7 @@ fn create_function_symbol_info(node: &Value, file_path: &str) -> Option<SymbolIn
     let name = node.get("name").and_then(|v| v.as_str())?;
     let range = get_node_range(node, file_path)?;
     let location = Location {
-        uri: Url::from_file_path(file_path).ok()?,
+        uri: source_uri(file_path)?,
         range,
     };
 
@@ -661,7 +354,7 @@ fn create_variable_symbol_info(node: &Value, file_path: &str) -> Option<SymbolIn
     let name = node.get("name").and_then(|v| v.as_str())?;
     let range = get_node_range(node, file_path)?;
     let location = Location {
-        uri: Url::from_file_path(file_path).ok()?,
+        uri: source_uri(file_path)?,
         range,
     };
 
@@ -686,7 +379,7 @@ fn create_event_symbol_info(node: &Value, file_path: &str) -> Option<SymbolInfor
     let name = node.get("name").and_then(|v| v.as_str())?;
     let range = get_node_range(node, file_path)?;
     let location = Location {
-        uri: Url::from_file_path(file_path).ok()?,
+        uri: source_uri(file_path)?,
         range,
     };
 
@@ -704,7 +397,7 @@ fn create_modifier_symbol_info(node: &Value, file_path: &str) -> Option<SymbolIn
     let name = node.get("name").and_then(|v| v.as_str())?;
     let range = get_node_range(node, file_path)?;
     let location = Location {
-        uri: Url::from_file_path(file_path).ok()?,
+        uri: source_uri(file_path)?,
         range,
     };
 
@@ -722,7 +415,7 @@ fn create_struct_symbol_info(node: &Value, file_path: &str) -> Option<SymbolInfo
     let name = node.get("name").and_then(|v| v.as_str())?;
     let range = get_node_range(node, file_path)?;
     let location = Location {
-        uri: Url::from_file_path(file_path).ok()?,
+        uri: source_uri(file_path)?,
         range,
     };
 
@@ -740,7 +433,7 @@ fn create_enum_symbol_info(node: &Value, file_path: &str) -> Option<SymbolInform
     let name = node.get("name").and_then(|v| v.as_str())?;
     let range = get_node_range(node, file_path)?;
     let location = Location {
-        uri: Url::from_file_path(file_path).ok()?,
+        uri: source_uri(file_path)?,
         range,
     };
 
@@ -754,8 +447,21 @@ fn create_enum_symbol_info(node: &Value, file_path: &str) -> Option<SymbolInform
     })
 }
 
+/// URI for a source path as reported by forge, which may be relative to the project root.
+fn source_uri(file_path: &str) -> Option<Url> {
+    paths::path_to_uri(&paths::resolve_source_path(file_path)?)
+}
+
 fn get_node_range(node: &Value, file_path: &str) -> Option<Range> {
     let src = node.get("src").and_then(|v| v.as_str())?;
+
+    // Read the file content to convert byte offsets to positions
+    let content = std::fs::read_to_string(file_path).ok()?;
+    src_range(src, &content)
+}
+
+/// Range of a `start:length:file` source location in `content`.
+fn src_range(src: &str, content: &str) -> Option<Range> {
     let parts: Vec<&str> = src.split(':').collect();
     if parts.len() < 3 {
         return None;
@@ -764,10 +470,8 @@ fn get_node_range(node: &Value, file_path: &str) -> Option<Range> {
     let start_offset: usize = parts[0].parse().ok()?;
     let length: usize = parts[1].parse().ok()?;
 
-    // Read the file content to convert byte offsets to positions
-    let content = std::fs::read_to_string(file_path).ok()?;
-    let (start_line, start_col) = byte_offset_to_position(&content, start_offset);
-    let (end_line, end_col) = byte_offset_to_position(&content, start_offset + length);
+    let (start_line, start_col) = byte_offset_to_position(content, start_offset);
+    let (end_line, end_col) = byte_offset_to_position(content, start_offset + length);
 
     Some(Range {
         start: Position { line: start_line, character: start_col },
@@ -810,6 +514,8 @@ fn is_state_variable(node: &Value) -> bool {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::ast;
+    use serde_json::json;
     use std::process::Command;
 
     fn get_test_ast_data() -> Option<serde_json::Value> {
@@ -947,4 +653,85 @@ mod tests {
             println!("Found enum with members in test data");
         }
     }
+    #[test]
+    fn test_document_symbol_hierarchy() {
+        let source = concat!(
+            "uint256 constant MAX = 1;\n",
+            "interface Vault {\n",
+            "    function deposit(uint256 amount) external returns (bool ok);\n",
+            "    fallback() external;\n",
+            "}\n",
+        );
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("Vault.sol");
+        std::fs::write(&path, source).unwrap();
+        let path = path.to_str().unwrap();
+
+        let at = |needle: &str| source.find(needle).unwrap();
+        let src = |needle: &str| format!("{}:{}:0", at(needle), needle.len());
+        let variable = |needle: &str, name: &str, type_string: &str| {
+            json!({
+                "nodeType": "VariableDeclaration",
+                "name": name,
+                "src": src(needle),
+                "nameLocation": src(name),
+                "typeDescriptions": { "typeString": type_string }
+            })
+        };
+        let mut deposit = json!({
+            "nodeType": "FunctionDefinition",
+            "kind": "function",
+            "name": "deposit",
+            "src": src("function deposit(uint256 amount) external returns (bool ok);"),
+            "nameLocation": src("deposit")
+        });
+        let amount = variable("uint256 amount", "amount", "uint256");
+        deposit["parameters"] = json!({ "parameters": [amount] });
+        deposit["returnParameters"] = json!({ "parameters": [variable("bool ok", "ok", "bool")] });
+        let fallback = json!({
+            "nodeType": "FunctionDefinition",
+            "kind": "fallback",
+            "name": "",
+            "src": src("fallback() external;")
+        });
+        let mut vault = json!({
+            "nodeType": "ContractDefinition",
+            "contractKind": "interface",
+            "name": "Vault",
+            "src": format!("{}:{}:0", at("interface"), source.len() - at("interface") - 1),
+            "nameLocation": src("Vault")
+        });
+        vault["nodes"] = json!([deposit, fallback]);
+        let ast = ast::mock_ast(
+            path,
+            json!({
+                "nodeType": "SourceUnit",
+                "nodes": [variable("uint256 constant MAX = 1", "MAX", "uint256"), vault]
+            }),
+        );
+
+        let symbols = extract_document_symbols(&ast, path);
+        let names: Vec<_> = symbols.iter().map(|s| (s.name.as_str(), s.kind)).collect();
+        assert_eq!(names, [("MAX", SymbolKind::CONSTANT), ("Vault", SymbolKind::INTERFACE)]);
+
+        let vault = &symbols[1];
+        assert_eq!(vault.selection_range.start, Position::new(1, 10));
+        assert_eq!(vault.selection_range.end, Position::new(1, 15));
+        let members = vault.children.as_ref().unwrap();
+        let names: Vec<_> = members.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["deposit", "fallback"]);
+
+        let params = members[0].children.as_ref().unwrap();
+        let names: Vec<_> = params
+            .iter()
+            .map(|s| (s.name.as_str(), s.kind, s.detail.as_deref()))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                ("amount", SymbolKind::VARIABLE, Some("uint256")),
+                ("ok", SymbolKind::VARIABLE, Some("bool"))
+            ]
+        );
+    }
 }
@@ -0,0 +1,1800 @@
+//! In-process Solidity parsing for syntax-level features.
+//!
+//! [`parse`] turns a source into the JSON shape of `forge build --ast` output: declarations,
+//! statements and expressions, without type information. Document symbols, folding ranges,
+//! selection ranges and goto within a file run on it straight from the editor buffer, so
+//! they answer instantly and on unsaved edits. Identifiers are resolved by name through the
+//! enclosing scopes, which covers locals, parameters, contract members and declarations
+//! elsewhere in the file; the compiler's AST refines this once a build has run.
+
+use serde_json::{Map, Value, json};
+use solang_parser::{
+    diagnostics::Diagnostic as ParserDiagnostic,
+    pt::{self, CodeLocation, Expression, Loc, Statement},
+};
+use std::{
+    collections::HashMap,
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+};
+use tokio::sync::Mutex;
+use tower_lsp::{
+    async_trait,
+    lsp_types::{Location, Position, Range, Url},
+};
+
+use crate::{
+    annotations, ast,
+    goto::{bytes_to_pos, cache_ids, goto_bytes, pos_to_bytes},
+    grammar,
+    index::content_hash,
+    runner::{BuildOutput, Runner, RunnerError},
+};
+
+/// Converts a parse tree into solc-style AST nodes, numbering nodes and resolving names as
+/// it goes.
+struct Converter<'a> {
+    source: &'a str,
+    file_id: usize,
+    next_id: u64,
+    /// Names visible at the current point, innermost scope last.
+    scopes: Vec<HashMap<String, u64>>,
+    /// Names reachable through `Name.member` on contracts and enums, by declaration.
+    members: HashMap<u64, HashMap<String, u64>>,
+}
+
+fn name_of(identifier: &Option<pt::Identifier>) -> &str {
+    identifier
+        .as_ref()
+        .map_or("", |identifier| identifier.name.as_str())
+}
+
+/// Span from the start of `first` to the end of `last`.
+fn span(first: Loc, last: Loc) -> Loc {
+    match (first, last) {
+        (Loc::File(file, start, _), Loc::File(_, _, end)) => Loc::File(file, start, end),
+        _ => first,
+    }
+}
+
+/// Lexically resolve a relative import against the importing file.
+fn import_path(importer: &str, file: &str) -> String {
+    if !file.starts_with("./") && !file.starts_with("../") {
+        return file.to_string();
+    }
+    let base = Path::new(importer).parent().unwrap_or(Path::new(""));
+    let mut resolved = PathBuf::new();
+    for component in base.join(file).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            component => resolved.push(component),
+        }
+    }
+    resolved.to_string_lossy().into_owned()
+}
+
+fn binary_operator(expression: &Expression) -> Option<(&'static str, &Expression, &Expression)> {
+    use Expression::*;
+    let (operator, left, right) = match expression {
+        Power(_, l, r) => ("**", l, r),
+        Multiply(_, l, r) => ("*", l, r),
+        Divide(_, l, r) => ("/", l, r),
+        Modulo(_, l, r) => ("%", l, r),
+        Add(_, l, r) => ("+", l, r),
+        Subtract(_, l, r) => ("-", l, r),
+        ShiftLeft(_, l, r) => ("<<", l, r),
+        ShiftRight(_, l, r) => (">>", l, r),
+        BitwiseAnd(_, l, r) => ("&", l, r),
+        BitwiseXor(_, l, r) => ("^", l, r),
+        BitwiseOr(_, l, r) => ("|", l, r),
+        Less(_, l, r) => ("<", l, r),
+        More(_, l, r) => (">", l, r),
+        LessEqual(_, l, r) => ("<=", l, r),
+        MoreEqual(_, l, r) => (">=", l, r),
+        Equal(_, l, r) => ("==", l, r),
+        NotEqual(_, l, r) => ("!=", l, r),
+        And(_, l, r) => ("&&", l, r),
+        Or(_, l, r) => ("||", l, r),
+        _ => return None,
+    };
+    Some((operator, left, right))
+}
+
+fn assignment_operator(
+    expression: &Expression,
+) -> Option<(&'static str, &Expression, &Expression)> {
+    use Expression::*;
+    let (operator, left, right) = match expression {
+        Assign(_, l, r) => ("=", l, r),
+        AssignOr(_, l, r) => ("|=", l, r),
+        AssignAnd(_, l, r) => ("&=", l, r),
+        AssignXor(_, l, r) => ("^=", l, r),
+        AssignShiftLeft(_, l, r) => ("<<=", l, r),
+        AssignShiftRight(_, l, r) => (">>=", l, r),
+        AssignAdd(_, l, r) => ("+=", l, r),
+        AssignSubtract(_, l, r) => ("-=", l, r),
+        AssignMultiply(_, l, r) => ("*=", l, r),
+        AssignDivide(_, l, r) => ("/=", l, r),
+        AssignModulo(_, l, r) => ("%=", l, r),
+        _ => return None,
+    };
+    Some((operator, left, right))
+}
+
+fn unary_operator(expression: &Expression) -> Option<(&'static str, bool, &Expression)> {
+    use Expression::*;
+    let (operator, prefix, operand) = match expression {
+        PostIncrement(_, e) => ("++", false, e),
+        PostDecrement(_, e) => ("--", false, e),
+        PreIncrement(_, e) => ("++", true, e),
+        PreDecrement(_, e) => ("--", true, e),
+        Not(_, e) => ("!", true, e),
+        BitwiseNot(_, e) => ("~", true, e),
+        Delete(_, e) => ("delete", true, e),
+        UnaryPlus(_, e) => ("+", true, e),
+        Negate(_, e) => ("-", true, e),
+        _ => return None,
+    };
+    Some((operator, prefix, operand))
+}
+
+fn visibility(visibility: &pt::Visibility) -> &'static str {
+    match visibility {
+        pt::Visibility::External(_) => "external",
+        pt::Visibility::Public(_) => "public",
+        pt::Visibility::Internal(_) => "internal",
+        pt::Visibility::Private(_) => "private",
+    }
+}
+
+fn storage_location(storage: &Option<pt::StorageLocation>) -> &'static str {
+    match storage {
+        Some(pt::StorageLocation::Memory(_)) => "memory",
+        Some(pt::StorageLocation::Storage(_)) => "storage",
+        Some(pt::StorageLocation::Calldata(_)) => "calldata",
+        None => "default",
+    }
+}
+
+/// Name of a contract member as declared, for the contract's scope.
+fn contract_part_name(part: &pt::ContractPart) -> Option<&str> {
+    let name = match part {
+        pt::ContractPart::StructDefinition(d) => &d.name,
+        pt::ContractPart::EventDefinition(d) => &d.name,
+        pt::ContractPart::EnumDefinition(d) => &d.name,
+        pt::ContractPart::ErrorDefinition(d) => &d.name,
+        pt::ContractPart::VariableDefinition(d) => &d.name,
+        pt::ContractPart::FunctionDefinition(d) => &d.name,
+        pt::ContractPart::TypeDefinition(d) => return Some(&d.name.name),
+        _ => return None,
+    };
+    name.as_ref().map(|name| name.name.as_str())
+}
+
+fn source_unit_part_name(part: &pt::SourceUnitPart) -> Option<&str> {
+    let name = match part {
+        pt::SourceUnitPart::ContractDefinition(d) => &d.name,
+        pt::SourceUnitPart::EnumDefinition(d) => &d.name,
+        pt::SourceUnitPart::StructDefinition(d) => &d.name,
+        pt::SourceUnitPart::EventDefinition(d) => &d.name,
+        pt::SourceUnitPart::ErrorDefinition(d) => &d.name,
+        pt::SourceUnitPart::FunctionDefinition(d) => &d.name,
+        pt::SourceUnitPart::VariableDefinition(d) => &d.name,
+        pt::SourceUnitPart::TypeDefinition(d) => return Some(&d.name.name),
+        _ => return None,
+    };
+    name.as_ref().map(|name| name.name.as_str())
+}
+
+impl<'a> Converter<'a> {
+    fn id(&mut self) -> u64 {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    fn src(&self, loc: Loc) -> String {
+        match loc {
+            Loc::File(_, start, end) => {
+                format!("{start}:{}:{}", end.saturating_sub(start), self.file_id)
+            }
+            _ => "-1:-1:-1".to_string(),
+        }
+    }
+
+    fn text(&self, loc: Loc) -> &'a str {
+        match loc {
+            Loc::File(_, start, end) => self.source.get(start..end).unwrap_or_default(),
+            _ => "",
+        }
+    }
+
+    /// A node with the fields every node has, plus `fields`.
+    fn node(&self, id: u64, node_type: &str, loc: Loc, fields: Value) -> Value {
+        let mut node = match fields {
+            Value::Object(map) => map,
+            _ => Map::new(),
+        };
+        node.insert("id".to_string(), id.into());
+        node.insert("nodeType".to_string(), node_type.into());
+        node.insert("src".to_string(), self.src(loc).into());
+        Value::Object(node)
+    }
+
+    /// Add `name` to the innermost scope. The first declaration of a name wins, which
+    /// picks the first of a set of overloads.
+    fn declare(&mut self, name: &str, id: u64) {
+        if let Some(scope) = self.scopes.last_mut()
+            && !name.is_empty()
+        {
+            scope.entry(name.to_string()).or_insert(id);
+        }
+    }
+
+    fn resolve(&self, name: &str) -> Option<u64> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).copied())
+    }
+
+    /// The declaration `expression` names, when it is a name or a `Name.member` chain.
+    fn resolve_expression(&self, expression: &Expression) -> Option<u64> {
+        match expression {
+            Expression::Variable(identifier) => self.resolve(&identifier.name),
+            Expression::MemberAccess(_, base, member) => {
+                let base = self.resolve_expression(base)?;
+                self.members.get(&base)?.get(&member.name).copied()
+            }
+            _ => None,
+        }
+    }
+
+    fn resolve_path(&self, path: &pt::IdentifierPath) -> Option<u64> {
+        let (first, rest) = path.identifiers.split_first()?;
+        rest.iter()
+            .try_fold(self.resolve(&first.name)?, |id, member| {
+                self.members.get(&id)?.get(&member.name).copied()
+            })
+    }
+
+    fn with_scope<T>(&mut self, scope: HashMap<String, u64>, f: impl FnOnce(&mut Self) -> T) -> T {
+        self.scopes.push(scope);
+        let result = f(self);
+        self.scopes.pop();
+        result
+    }
+
+    fn identifier_path(&mut self, path: &pt::IdentifierPath) -> Value {
+        let id = self.id();
+        let name_locations: Vec<String> = path
+            .identifiers
+            .iter()
+            .map(|identifier| self.src(identifier.loc))
+            .collect();
+        let name = path
+            .identifiers
+            .iter()
+            .map(|identifier| identifier.name.as_str())
+            .collect::<Vec<_>>()
+            .join(".");
+        let mut node = self.node(
+            id,
+            "IdentifierPath",
+            path.loc,
+            json!({ "name": name, "nameLocations": name_locations }),
+        );
+        if let Some(target) = self.resolve_path(path) {
+            node["referencedDeclaration"] = target.into();
+        }
+        node
+    }
+
+    fn source_unit(&mut self, path: &str, unit: &pt::SourceUnit) -> Value {
+        let id = self.id();
+        let mut scope = HashMap::new();
+        let declared: Vec<Option<u64>> = unit
+            .0
+            .iter()
+            .map(|part| {
+                let name = source_unit_part_name(part)?;
+                let id = self.id();
+                scope.entry(name.to_string()).or_insert(id);
+                Some(id)
+            })
+            .collect();
+
+        self.scopes.push(scope);
+        let nodes: Vec<Value> = unit
+            .0
+            .iter()
+            .zip(declared)
+            .filter_map(|(part, declared)| self.source_unit_part(path, part, declared))
+            .collect();
+        self.scopes.pop();
+
+        self.node(
+            id,
+            "SourceUnit",
+            Loc::File(self.file_id, 0, self.source.len()),
+            json!({ "absolutePath": path, "nodes": nodes }),
+        )
+    }
+
+    fn source_unit_part(
+        &mut self,
+        path: &str,
+        part: &pt::SourceUnitPart,
+        declared: Option<u64>,
+    ) -> Option<Value> {
+        let id = declared.unwrap_or_else(|| self.id());
+        Some(match part {
+            pt::SourceUnitPart::PragmaDirective(pragma) => self.pragma(id, pragma),
+            pt::SourceUnitPart::ImportDirective(import) => self.import(id, path, import),
+            pt::SourceUnitPart::ContractDefinition(contract) => self.contract(id, contract),
+            pt::SourceUnitPart::EnumDefinition(definition) => self.enumeration(id, definition),
+            pt::SourceUnitPart::StructDefinition(definition) => self.structure(id, definition),
+            pt::SourceUnitPart::EventDefinition(definition) => self.event(id, definition),
+            pt::SourceUnitPart::ErrorDefinition(definition) => self.error(id, definition),
+            pt::SourceUnitPart::FunctionDefinition(function) => self.function(id, function, true),
+            pt::SourceUnitPart::VariableDefinition(variable) => self.state_variable(id, variable),
+            pt::SourceUnitPart::TypeDefinition(definition) => self.type_definition(id, definition),
+            pt::SourceUnitPart::Using(using) => self.using(id, using),
+            pt::SourceUnitPart::Annotation(_) | pt::SourceUnitPart::StraySemicolon(_) => {
+                return None;
+            }
+        })
+    }
+
+    fn pragma(&mut self, id: u64, pragma: &pt::PragmaDirective) -> Value {
+        let (loc, name) = match pragma {
+            pt::PragmaDirective::Identifier(loc, name, _) => (*loc, name.as_ref()),
+            pt::PragmaDirective::StringLiteral(loc, name, _)
+            | pt::PragmaDirective::Version(loc, name, _) => (*loc, Some(name)),
+        };
+        // `pragma solidity ^0.8.0;` gives `["solidity", "^0.8.0"]`
+        let mut literals: Vec<String> = name.map(|name| name.name.clone()).into_iter().collect();
+        if let (Some(name), Loc::File(_, _, end)) = (name, loc)
+            && let Loc::File(file, _, name_end) = name.loc
+        {
+            let rest = self.text(Loc::File(file, name_end, end));
+            let rest: String = rest.trim_end_matches(';').split_whitespace().collect();
+            if !rest.is_empty() {
+                literals.push(rest);
+            }
+        }
+        self.node(id, "PragmaDirective", loc, json!({ "literals": literals }))
+    }
+
+    fn import(&mut self, id: u64, importer: &str, import: &pt::Import) -> Value {
+        let (path, loc) = match import {
+            pt::Import::Plain(path, loc)
+            | pt::Import::GlobalSymbol(path, _, loc)
+            | pt::Import::Rename(path, _, loc) => (path, *loc),
+        };
+        let file = match path {
+            pt::ImportPath::Filename(literal) => literal.string.clone(),
+            pt::ImportPath::Path(path) => self.text(path.loc).to_string(),
+        };
+        let mut node = self.node(
+            id,
+            "ImportDirective",
+            loc,
+            json!({
+                "file": file,
+                "absolutePath": import_path(importer, &file),
+                "unitAlias": "",
+                "symbolAliases": []
+            }),
+        );
+        match import {
+            pt::Import::GlobalSymbol(_, alias, _) => {
+                node["unitAlias"] = alias.name.clone().into();
+                node["nameLocation"] = self.src(alias.loc).into();
+            }
+            pt::Import::Rename(_, symbols, _) => {
+                let aliases: Vec<Value> = symbols
+                    .iter()
+                    .map(|(foreign, local)| {
+                        let foreign_id = self.id();
+                        let foreign_node = self.node(
+                            foreign_id,
+                            "Identifier",
+                            foreign.loc,
+                            json!({ "name": foreign.name }),
+                        );
+                        let mut alias = json!({ "foreign": foreign_node });
+                        if let Some(local) = local {
+                            alias["local"] = local.name.clone().into();
+                            alias["nameLocation"] = self.src(local.loc).into();
+                        }
+                        alias
+                    })
+                    .collect();
+                node["symbolAliases"] = aliases.into();
+            }
+            pt::Import::Plain(..) => {}
+        }
+        node
+    }
+
+    fn contract(&mut self, id: u64, contract: &pt::ContractDefinition) -> Value {
+        let (kind, is_abstract) = match contract.ty {
+            pt::ContractTy::Abstract(_) => ("contract", true),
+            pt::ContractTy::Contract(_) => ("contract", false),
+            pt::ContractTy::Interface(_) => ("interface", false),
+            pt::ContractTy::Library(_) => ("library", false),
+        };
+        let base_contracts: Vec<Value> = contract
+            .base
+            .iter()
+            .map(|base| {
+                let base_id = self.id();
+                let base_name = self.identifier_path(&base.name);
+                let mut node = self.node(
+                    base_id,
+                    "InheritanceSpecifier",
+                    base.loc,
+                    json!({ "baseName": base_name }),
+                );
+                if let Some(args) = &base.args {
+                    let arguments: Vec<Value> =
+                        args.iter().map(|arg| self.expression(arg)).collect();
+                    node["arguments"] = arguments.into();
+                }
+                node
+            })
+            .collect();
+
+        // Own members shadow inherited ones
+        let mut scope = HashMap::new();
+        let declared: Vec<Option<u64>> = contract
+            .parts
+            .iter()
+            .map(|part| {
+                let name = contract_part_name(part)?;
+                let id = self.id();
+                scope.entry(name.to_string()).or_insert(id);
+                Some(id)
+            })
+            .collect();
+        for base in &contract.base {
+            if let Some(inherited) = self
+                .resolve_path(&base.name)
+                .and_then(|base| self.members.get(&base))
+            {
+                for (name, id) in inherited {
+                    scope.entry(name.clone()).or_insert(*id);
+                }
+            }
+        }
+        self.members.insert(id, scope.clone());
+
+        let nodes: Vec<Value> = self.with_scope(scope, |this| {
+            contract
+                .parts
+                .iter()
+                .zip(declared)
+                .filter_map(|(part, declared)| this.contract_part(part, declared))
+                .collect()
+        });
+
+        let mut node = self.node(
+            id,
+            "ContractDefinition",
+            contract.loc,
+            json!({
+                "name": name_of(&contract.name),
+                "contractKind": kind,
+                "abstract": is_abstract,
+                "baseContracts": base_contracts,
+                "nodes": nodes
+            }),
+        );
+        if let Some(name) = &contract.name {
+            node["nameLocation"] = self.src(name.loc).into();
+        }
+        node
+    }
+
+    fn contract_part(&mut self, part: &pt::ContractPart, declared: Option<u64>) -> Option<Value> {
+        let id = declared.unwrap_or_else(|| self.id());
+        Some(match part {
+            pt::ContractPart::StructDefinition(definition) => self.structure(id, definition),
+            pt::ContractPart::EventDefinition(definition) => self.event(id, definition),
+            pt::ContractPart::EnumDefinition(definition) => self.enumeration(id, definition),
+            pt::ContractPart::ErrorDefinition(definition) => self.error(id, definition),
+            pt::ContractPart::VariableDefinition(variable) => self.state_variable(id, variable),
+            pt::ContractPart::FunctionDefinition(function) => self.function(id, function, false),
+            pt::ContractPart::TypeDefinition(definition) => self.type_definition(id, definition),
+            pt::ContractPart::Using(using) => self.using(id, using),
+            pt::ContractPart::Annotation(_) | pt::ContractPart::StraySemicolon(_) => return None,
+        })
+    }
+
+    /// A named declaration node with `nameLocation` set when it has a name.
+    fn declaration(
+        &self,
+        id: u64,
+        node_type: &str,
+        loc: Loc,
+        name: &Option<pt::Identifier>,
+        mut fields: Value,
+    ) -> Value {
+        fields["name"] = name_of(name).into();
+        let mut node = self.node(id, node_type, loc, fields);
+        if let Some(name) = name {
+            node["nameLocation"] = self.src(name.loc).into();
+        }
+        node
+    }
+
+    fn structure(&mut self, id: u64, definition: &pt::StructDefinition) -> Value {
+        let members: Vec<Value> = definition
+            .fields
+            .iter()
+            .map(|field| self.variable(field.loc, &field.ty, &field.storage, &field.name, false))
+            .collect();
+        self.declaration(
+            id,
+            "StructDefinition",
+            definition.loc,
+            &definition.name,
+            json!({ "members": members, "visibility": "public" }),
+        )
+    }
+
+    fn enumeration(&mut self, id: u64, definition: &pt::EnumDefinition) -> Value {
+        let mut values = HashMap::new();
+        let members: Vec<Value> = definition
+            .values
+            .iter()
+            .flatten()
+            .map(|value| {
+                let value_id = self.id();
+                values.insert(value.name.clone(), value_id);
+                self.declaration(
+                    value_id,
+                    "EnumValue",
+                    value.loc,
+                    &Some(value.clone()),
+                    json!({}),
+                )
+            })
+            .collect();
+        self.members.insert(id, values);
+        self.declaration(
+            id,
+            "EnumDefinition",
+            definition.loc,
+            &definition.name,
+            json!({ "members": members }),
+        )
+    }
+
+    fn parameter_list(&mut self, loc: Loc, parameters: &[Value]) -> Value {
+        let id = self.id();
+        self.node(
+            id,
+            "ParameterList",
+            loc,
+            json!({ "parameters": parameters }),
+        )
+    }
+
+    fn event(&mut self, id: u64, definition: &pt::EventDefinition) -> Value {
+        let parameters: Vec<Value> = definition
+            .fields
+            .iter()
+            .map(|field| {
+                let mut parameter = self.variable(field.loc, &field.ty, &None, &field.name, false);
+                parameter["indexed"] = field.indexed.into();
+                parameter
+            })
+            .collect();
+        let parameters = self.parameter_list(definition.loc, &parameters);
+        self.declaration(
+            id,
+            "EventDefinition",
+            definition.loc,
+            &definition.name,
+            json!({ "parameters": parameters, "anonymous": definition.anonymous }),
+        )
+    }
+
+    fn error(&mut self, id: u64, definition: &pt::ErrorDefinition) -> Value {
+        let parameters: Vec<Value> = definition
+            .fields
+            .iter()
+            .map(|field| self.variable(field.loc, &field.ty, &None, &field.name, false))
+            .collect();
+        let parameters = self.parameter_list(definition.loc, &parameters);
+        self.declaration(
+            id,
+            "ErrorDefinition",
+            definition.loc,
+            &definition.name,
+            json!({ "parameters": parameters }),
+        )
+    }
+
+    fn type_definition(&mut self, id: u64, definition: &pt::TypeDefinition) -> Value {
+        let underlying = self.type_name(&definition.ty);
+        self.declaration(
+            id,
+            "UserDefinedValueTypeDefinition",
+            definition.loc,
+            &Some(definition.name.clone()),
+            json!({ "underlyingType": underlying }),
+        )
+    }
+
+    fn using(&mut self, id: u64, using: &pt::Using) -> Value {
+        let mut node = self.node(
+            id,
+            "UsingForDirective",
+            using.loc,
+            json!({ "global": using.global.is_some() }),
+        );
+        match &using.list {
+            pt::UsingList::Library(path) => node["libraryName"] = self.identifier_path(path),
+            pt::UsingList::Functions(functions) => {
+                let list: Vec<Value> = functions
+                    .iter()
+                    .map(|function| json!({ "function": self.identifier_path(&function.path) }))
+                    .collect();
+                node["functionList"] = list.into();
+            }
+            pt::UsingList::Error => {}
+        }
+        if let Some(ty) = &using.ty {
+            node["typeName"] = self.type_name(ty);
+        }
+        node
+    }
+
+    /// A `VariableDeclaration` of a parameter, local, struct member or event or error
+    /// field. Only locals and parameters are added to the current scope.
+    fn variable(
+        &mut self,
+        loc: Loc,
+        ty: &Expression,
+        storage: &Option<pt::StorageLocation>,
+        name: &Option<pt::Identifier>,
+        declare: bool,
+    ) -> Value {
+        let id = self.id();
+        let type_name = self.type_name(ty);
+        let node = self.declaration(
+            id,
+            "VariableDeclaration",
+            loc,
+            name,
+            json!({
+                "typeName": type_name,
+                // The type as written; the compiler's AST has the resolved type
+                "typeDescriptions": { "typeString": self.text(ty.loc()) },
+                "storageLocation": storage_location(storage),
+                "stateVariable": false,
+                "constant": false,
+                "mutability": "mutable",
+                "visibility": "internal"
+            }),
+        );
+        if declare && let Some(name) = name {
+            self.declare(&name.name, id);
+        }
+        node
+    }
+
+    fn parameters(&mut self, loc: Loc, parameters: &pt::ParameterList) -> Value {
+        // Without parameters the list is empty, so it never covers the name at `loc`
+        let list_loc = match (parameters.first(), parameters.last()) {
+            (Some((first, _)), Some((last, _))) => span(*first, *last),
+            _ => loc.with_start(loc.end()),
+        };
+        let declarations: Vec<Value> = parameters
+            .iter()
+            .filter_map(|(loc, parameter)| {
+                let parameter = parameter.as_ref()?;
+                Some(self.variable(
+                    *loc,
+                    &parameter.ty,
+                    &parameter.storage,
+                    &parameter.name,
+                    true,
+                ))
+            })
+            .collect();
+        self.parameter_list(list_loc, &declarations)
+    }
+
+    fn state_variable(&mut self, id: u64, variable: &pt::VariableDefinition) -> Value {
+        let mut visibility_name = "internal";
+        let mut mutability = "mutable";
+        let mut storage_location = "default";
+        for attribute in &variable.attrs {
+            match attribute {
+                pt::VariableAttribute::Visibility(v) => visibility_name = visibility(v),
+                pt::VariableAttribute::Constant(_) => mutability = "constant",
+                pt::VariableAttribute::Immutable(_) => mutability = "immutable",
+                pt::VariableAttribute::StorageType(pt::StorageType::Temporary(_)) => {
+                    storage_location = "transient"
+                }
+                _ => {}
+            }
+        }
+        let type_name = self.type_name(&variable.ty);
+        let mut node = self.declaration(
+            id,
+            "VariableDeclaration",
+            variable.loc,
+            &variable.name,
+            json!({
+                "typeName": type_name,
+                "typeDescriptions": { "typeString": self.text(variable.ty.loc()) },
+                "storageLocation": storage_location,
+                "stateVariable": true,
+                "constant": mutability == "constant",
+                "mutability": mutability,
+                "visibility": visibility_name
+            }),
+        );
+        if let Some(initializer) = &variable.initializer {
+            node["value"] = self.expression(initializer);
+        }
+        node
+    }
+
+    fn function(&mut self, id: u64, function: &pt::FunctionDefinition, free: bool) -> Value {
+        let mut visibility_name = if free { "internal" } else { "public" };
+        let mut state_mutability = "nonpayable";
+        let mut is_virtual = false;
+        let mut invocations = Vec::new();
+        for attribute in &function.attributes {
+            match attribute {
+                pt::FunctionAttribute::Visibility(v) => visibility_name = visibility(v),
+                pt::FunctionAttribute::Mutability(m) => {
+                    state_mutability = match m {
+                        pt::Mutability::Pure(_) => "pure",
+                        pt::Mutability::View(_) | pt::Mutability::Constant(_) => "view",
+                        pt::Mutability::Payable(_) => "payable",
+                    }
+                }
+                pt::FunctionAttribute::Virtual(_) => is_virtual = true,
+                pt::FunctionAttribute::BaseOrModifier(loc, base) => invocations.push((*loc, base)),
+                _ => {}
+            }
+        }
+
+        self.with_scope(HashMap::new(), |this| {
+            let parameters = this.parameters(function.name_loc, &function.params);
+            let return_parameters = this.parameters(function.loc_prototype, &function.returns);
+            let modifiers: Vec<Value> = invocations
+                .into_iter()
+                .map(|(loc, base)| {
+                    let invocation_id = this.id();
+                    let name = this.identifier_path(&base.name);
+                    let arguments: Vec<Value> = base
+                        .args
+                        .iter()
+                        .flatten()
+                        .map(|arg| this.expression(arg))
+                        .collect();
+                    this.node(
+                        invocation_id,
+                        "ModifierInvocation",
+                        loc,
+                        json!({ "modifierName": name, "arguments": arguments }),
+                    )
+                })
+                .collect();
+
+            let (node_type, kind) = match function.ty {
+                pt::FunctionTy::Modifier => ("ModifierDefinition", None),
+                pt::FunctionTy::Constructor => ("FunctionDefinition", Some("constructor")),
+                pt::FunctionTy::Fallback => ("FunctionDefinition", Some("fallback")),
+                pt::FunctionTy::Receive => ("FunctionDefinition", Some("receive")),
+                pt::FunctionTy::Function if free => ("FunctionDefinition", Some("freeFunction")),
+                pt::FunctionTy::Function => ("FunctionDefinition", Some("function")),
+            };
+            let mut node = this.declaration(
+                id,
+                node_type,
+                function.loc,
+                &function.name,
+                json!({
+                    "parameters": parameters,
+                    "visibility": visibility_name,
+                    "virtual": is_virtual,
+                    "implemented": function.body.is_some()
+                }),
+            );
+            if let Some(kind) = kind {
+                node["kind"] = kind.into();
+                node["stateMutability"] = state_mutability.into();
+                node["returnParameters"] = return_parameters;
+                node["modifiers"] = modifiers.into();
+            }
+            if let Some(body) = &function.body {
+                node["body"] = this.statement(body);
+            }
+            node
+        })
+    }
+
+    /// The type name node of a type written as `expression`.
+    fn type_name(&mut self, expression: &Expression) -> Value {
+        let id = self.id();
+        let loc = expression.loc();
+        match expression {
+            Expression::Type(
+                _,
+                pt::Type::Mapping {
+                    key,
+                    value,
+                    key_name,
+                    value_name,
+                    ..
+                },
+            ) => {
+                let key_type = self.type_name(key);
+                let value_type = self.type_name(value);
+                self.node(
+                    id,
+                    "Mapping",
+                    loc,
+                    json!({
+                        "keyType": key_type,
+                        "valueType": value_type,
+                        "keyName": name_of(key_name),
+                        "valueName": name_of(value_name)
+                    }),
+                )
+            }
+            Expression::Type(_, pt::Type::Function { .. }) => {
+                self.node(id, "FunctionTypeName", loc, json!({}))
+            }
+            Expression::Type(..) => self.node(
+                id,
+                "ElementaryTypeName",
+                loc,
+                json!({ "name": self.text(loc) }),
+            ),
+            Expression::Variable(_) | Expression::MemberAccess(..) => {
+                let path_id = self.id();
+                let mut path = self.node(
+                    path_id,
+                    "IdentifierPath",
+                    loc,
+                    json!({ "name": self.text(loc), "nameLocations": [self.src(loc)] }),
+                );
+                let mut node = self.node(id, "UserDefinedTypeName", loc, json!({}));
+                if let Some(target) = self.resolve_expression(expression) {
+                    path["referencedDeclaration"] = target.into();
+                    node["referencedDeclaration"] = target.into();
+                }
+                node["pathNode"] = path;
+                node
+            }
+            Expression::ArraySubscript(_, base, length) => {
+                let base_type = self.type_name(base);
+                let mut node =
+                    self.node(id, "ArrayTypeName", loc, json!({ "baseType": base_type }));
+                if let Some(length) = length {
+                    node["length"] = self.expression(length);
+                }
+                node
+            }
+            _ => self.expression(expression),
+        }
+    }
+
+    fn expressions(&mut self, expressions: &[Expression]) -> Vec<Value> {
+        expressions.iter().map(|e| self.expression(e)).collect()
+    }
+
+    fn named_arguments(&mut self, arguments: &[pt::NamedArgument]) -> (Vec<Value>, Vec<String>) {
+        let values = arguments.iter().map(|a| self.expression(&a.expr)).collect();
+        let names = arguments.iter().map(|a| a.name.name.clone()).collect();
+        (values, names)
+    }
+
+    /// `new T(...)` parses as `new` applied to the call; like the compiler's AST, the call
+    /// is made of a `NewExpression` of `T` instead.
+    fn new_expression(&mut self, id: u64, loc: Loc, ty: &Expression) -> Value {
+        // From `new` through the end of the callee
+        let callee_loc = |callee: &Expression| loc.with_end(callee.loc().end());
+        match ty {
+            Expression::FunctionCall(_, callee, arguments) => {
+                let callee_id = self.id();
+                let callee = self.new_expression(callee_id, callee_loc(callee), callee);
+                let arguments = self.expressions(arguments);
+                self.node(
+                    id,
+                    "FunctionCall",
+                    loc,
+                    json!({ "expression": callee, "arguments": arguments, "names": [] }),
+                )
+            }
+            Expression::NamedFunctionCall(_, callee, arguments) => {
+                let callee_id = self.id();
+                let callee = self.new_expression(callee_id, callee_loc(callee), callee);
+                let (arguments, names) = self.named_arguments(arguments);
+                self.node(
+                    id,
+                    "FunctionCall",
+                    loc,
+                    json!({ "expression": callee, "arguments": arguments, "names": names }),
+                )
+            }
+            Expression::FunctionCallBlock(_, callee, block) => {
+                let callee_id = self.id();
+                let callee = self.new_expression(callee_id, callee_loc(callee), callee);
+                let (options, names) = match block.as_ref() {
+                    Statement::Args(_, arguments) => self.named_arguments(arguments),
+                    _ => (vec![], vec![]),
+                };
+                self.node(
+                    id,
+                    "FunctionCallOptions",
+                    loc,
+                    json!({ "expression": callee, "options": options, "names": names }),
+                )
+            }
+            _ => {
+                let type_name = self.type_name(ty);
+                self.node(id, "NewExpression", loc, json!({ "typeName": type_name }))
+            }
+        }
+    }
+
+    fn expression(&mut self, expression: &Expression) -> Value {
+        let id = self.id();
+        let loc = expression.loc();
+        if let Some((operator, left, right)) = binary_operator(expression) {
+            let (left, right) = (self.expression(left), self.expression(right));
+            return self.node(
+                id,
+                "BinaryOperation",
+                loc,
+                json!({ "operator": operator, "leftExpression": left, "rightExpression": right }),
+            );
+        }
+        if let Some((operator, left, right)) = assignment_operator(expression) {
+            let (left, right) = (self.expression(left), self.expression(right));
+            return self.node(
+                id,
+                "Assignment",
+                loc,
+                json!({ "operator": operator, "leftHandSide": left, "rightHandSide": right }),
+            );
+        }
+        if let Some((operator, prefix, operand)) = unary_operator(expression) {
+            let operand = self.expression(operand);
+            return self.node(
+                id,
+                "UnaryOperation",
+                loc,
+                json!({ "operator": operator, "prefix": prefix, "subExpression": operand }),
+            );
+        }
+
+        match expression {
+            Expression::Variable(identifier) => {
+                let mut node = self.node(id, "Identifier", loc, json!({ "name": identifier.name }));
+                if let Some(target) = self.resolve(&identifier.name) {
+                    node["referencedDeclaration"] = target.into();
+                }
+                node
+            }
+            Expression::MemberAccess(_, base, member) => {
+                let target = self.resolve_expression(expression);
+                let base = self.expression(base);
+                let mut node = self.node(
+                    id,
+                    "MemberAccess",
+                    loc,
+                    json!({
+                        "expression": base,
+                        "memberName": member.name,
+                        "memberLocation": self.src(member.loc)
+                    }),
+                );
+                if let Some(target) = target {
+                    node["referencedDeclaration"] = target.into();
+                }
+                node
+            }
+            Expression::FunctionCall(_, callee, arguments) => {
+                let callee = self.expression(callee);
+                let arguments = self.expressions(arguments);
+                self.node(
+                    id,
+                    "FunctionCall",
+                    loc,
+                    json!({ "expression": callee, "arguments": arguments, "names": [] }),
+                )
+            }
+            Expression::NamedFunctionCall(_, callee, arguments) => {
+                let callee = self.expression(callee);
+                let (arguments, names) = self.named_arguments(arguments);
+                self.node(
+                    id,
+                    "FunctionCall",
+                    loc,
+                    json!({ "expression": callee, "arguments": arguments, "names": names }),
+                )
+            }
+            Expression::FunctionCallBlock(_, callee, block) => {
+                let callee = self.expression(callee);
+                let (options, names) = match block.as_ref() {
+                    Statement::Args(_, arguments) => self.named_arguments(arguments),
+                    _ => (vec![], vec![]),
+                };
+                self.node(
+                    id,
+                    "FunctionCallOptions",
+                    loc,
+                    json!({ "expression": callee, "options": options, "names": names }),
+                )
+            }
+            Expression::ArraySubscript(_, base, index) => {
+                let base = self.expression(base);
+                let mut node = self.node(id, "IndexAccess", loc, json!({ "baseExpression": base }));
+                if let Some(index) = index {
+                    node["indexExpression"] = self.expression(index);
+                }
+                node
+            }
+            Expression::ArraySlice(_, base, start, end) => {
+                let base = self.expression(base);
+                let mut node = self.node(
+                    id,
+                    "IndexRangeAccess",
+                    loc,
+                    json!({ "baseExpression": base }),
+                );
+                if let Some(start) = start {
+                    node["startExpression"] = self.expression(start);
+                }
+                if let Some(end) = end {
+                    node["endExpression"] = self.expression(end);
+                }
+                node
+            }
+            Expression::Parenthesis(_, inner) => {
+                let inner = self.expression(inner);
+                self.node(
+                    id,
+                    "TupleExpression",
+                    loc,
+                    json!({ "components": [inner], "isInlineArray": false }),
+                )
+            }
+            Expression::List(_, parameters) => {
+                let components: Vec<Value> = parameters
+                    .iter()
+                    .map(|(_, parameter)| match parameter {
+                        Some(parameter) => self.expression(&parameter.ty),
+                        None => Value::Null,
+                    })
+                    .collect();
+                self.node(
+                    id,
+                    "TupleExpression",
+                    loc,
+                    json!({ "components": components, "isInlineArray": false }),
+                )
+            }
+            Expression::ArrayLiteral(_, items) => {
+                let items = self.expressions(items);
+                self.node(
+                    id,
+                    "TupleExpression",
+                    loc,
+                    json!({ "components": items, "isInlineArray": true }),
+                )
+            }
+            Expression::ConditionalOperator(_, condition, when_true, when_false) => {
+                let condition = self.expression(condition);
+                let when_true = self.expression(when_true);
+                let when_false = self.expression(when_false);
+                self.node(
+                    id,
+                    "Conditional",
+                    loc,
+                    json!({
+                        "condition": condition,
+                        "trueExpression": when_true,
+                        "falseExpression": when_false
+                    }),
+                )
+            }
+            Expression::New(_, ty) => self.new_expression(id, loc, ty),
+            Expression::Type(..) => {
+                let type_name = self.type_name(expression);
+                self.node(
+                    id,
+                    "ElementaryTypeNameExpression",
+                    loc,
+                    json!({ "typeName": type_name }),
+                )
+            }
+            Expression::BoolLiteral(_, value) => self.node(
+                id,
+                "Literal",
+                loc,
+                json!({ "kind": "bool", "value": value.to_string() }),
+            ),
+            Expression::StringLiteral(parts) => {
+                let value: String = parts.iter().map(|part| part.string.as_str()).collect();
+                self.node(
+                    id,
+                    "Literal",
+                    loc,
+                    json!({ "kind": "string", "value": value }),
+                )
+            }
+            Expression::HexLiteral(parts) => {
+                let value: String = parts.iter().map(|part| part.hex.as_str()).collect();
+                self.node(
+                    id,
+                    "Literal",
+                    loc,
+                    json!({ "kind": "hexString", "hexValue": value }),
+                )
+            }
+            _ => self.node(
+                id,
+                "Literal",
+                loc,
+                json!({ "kind": "number", "value": self.text(loc) }),
+            ),
+        }
+    }
+
+    fn block(&mut self, id: u64, loc: Loc, unchecked: bool, statements: &[Statement]) -> Value {
+        let statements: Vec<Value> = self.with_scope(HashMap::new(), |this| {
+            statements.iter().map(|s| this.statement(s)).collect()
+        });
+        let node_type = if unchecked { "UncheckedBlock" } else { "Block" };
+        self.node(id, node_type, loc, json!({ "statements": statements }))
+    }
+
+    fn expression_statement(&mut self, id: u64, loc: Loc, expression: &Expression) -> Value {
+        let expression = self.expression(expression);
+        self.node(
+            id,
+            "ExpressionStatement",
+            loc,
+            json!({ "expression": expression }),
+        )
+    }
+
+    /// `(uint a, bool b) = f();`, which parses as an assignment to a list of parameters.
+    fn tuple_declaration(
+        &mut self,
+        id: u64,
+        loc: Loc,
+        parameters: &pt::ParameterList,
+        value: &Expression,
+    ) -> Value {
+        let value = self.expression(value);
+        let mut assignments = Vec::new();
+        let declarations: Vec<Value> = parameters
+            .iter()
+            .map(|(loc, parameter)| match parameter {
+                Some(parameter) => {
+                    let declaration = self.variable(
+                        *loc,
+                        &parameter.ty,
+                        &parameter.storage,
+                        &parameter.name,
+                        true,
+                    );
+                    assignments.push(declaration["id"].clone());
+                    declaration
+                }
+                None => Value::Null,
+            })
+            .collect();
+        self.node(
+            id,
+            "VariableDeclarationStatement",
+            loc,
+            json!({
+                "declarations": declarations,
+                "assignments": assignments,
+                "initialValue": value
+            }),
+        )
+    }
+
+    fn statement(&mut self, statement: &Statement) -> Value {
+        let id = self.id();
+        match statement {
+            Statement::Block {
+                loc,
+                unchecked,
+                statements,
+            } => self.block(id, *loc, *unchecked, statements),
+            Statement::VariableDefinition(loc, declaration, value) => {
+                // The initializer sees the scope before the declaration
+                let value = value.as_ref().map(|value| self.expression(value));
+                let declaration = self.variable(
+                    declaration.loc,
+                    &declaration.ty,
+                    &declaration.storage,
+                    &declaration.name,
+                    true,
+                );
+                let mut node = self.node(
+                    id,
+                    "VariableDeclarationStatement",
+                    *loc,
+                    json!({
+                        "assignments": [declaration["id"].clone()],
+                        "declarations": [declaration]
+                    }),
+                );
+                if let Some(value) = value {
+                    node["initialValue"] = value;
+                }
+                node
+            }
+            Statement::Expression(loc, expression @ Expression::Assign(_, left, right)) => {
+                match left.as_ref() {
+                    Expression::List(_, parameters)
+                        if parameters
+                            .iter()
+                            .any(|(_, p)| p.as_ref().is_some_and(|p| p.name.is_some())) =>
+                    {
+                        self.tuple_declaration(id, *loc, parameters, right)
+                    }
+                    _ => self.expression_statement(id, *loc, expression),
+                }
+            }
+            Statement::Expression(loc, Expression::Variable(identifier))
+                if identifier.name == "_" =>
+            {
+                self.node(id, "PlaceholderStatement", *loc, json!({}))
+            }
+            Statement::Expression(loc, expression) => {
+                self.expression_statement(id, *loc, expression)
+            }
+            Statement::If(loc, condition, when_true, when_false) => {
+                let condition = self.expression(condition);
+                let when_true = self.statement(when_true);
+                let mut node = self.node(
+                    id,
+                    "IfStatement",
+                    *loc,
+                    json!({ "condition": condition, "trueBody": when_true }),
+                );
+                if let Some(when_false) = when_false {
+                    node["falseBody"] = self.statement(when_false);
+                }
+                node
+            }
+            Statement::While(loc, condition, body) => {
+                let condition = self.expression(condition);
+                let body = self.statement(body);
+                self.node(
+                    id,
+                    "WhileStatement",
+                    *loc,
+                    json!({ "condition": condition, "body": body }),
+                )
+            }
+            Statement::DoWhile(loc, body, condition) => {
+                let body = self.statement(body);
+                let condition = self.expression(condition);
+                self.node(
+                    id,
+                    "DoWhileStatement",
+                    *loc,
+                    json!({ "condition": condition, "body": body }),
+                )
+            }
+            Statement::For(loc, init, condition, next, body) => {
+                self.with_scope(HashMap::new(), |this| {
+                    let mut node = this.node(id, "ForStatement", *loc, json!({}));
+                    if let Some(init) = init {
+                        node["initializationExpression"] = this.statement(init);
+                    }
+                    if let Some(condition) = condition {
+                        node["condition"] = this.expression(condition);
+                    }
+                    if let Some(next) = next {
+                        let next_id = this.id();
+                        node["loopExpression"] =
+                            this.expression_statement(next_id, next.loc(), next);
+                    }
+                    if let Some(body) = body {
+                        node["body"] = this.statement(body);
+                    }
+                    node
+                })
+            }
+            Statement::Return(loc, value) => {
+                let mut node = self.node(id, "Return", *loc, json!({}));
+                if let Some(value) = value {
+                    node["expression"] = self.expression(value);
+                }
+                node
+            }
+            Statement::Emit(loc, call) => {
+                let call = self.expression(call);
+                self.node(id, "EmitStatement", *loc, json!({ "eventCall": call }))
+            }
+            Statement::Revert(loc, path, arguments) => {
+                let arguments = self.expressions(arguments);
+                self.revert(id, *loc, path, arguments, vec![])
+            }
+            Statement::RevertNamedArgs(loc, path, arguments) => {
+                let (arguments, names) = self.named_arguments(arguments);
+                self.revert(id, *loc, path, arguments, names)
+            }
+            Statement::Try(loc, call, returns, catches) => {
+                let call = self.expression(call);
+                let mut clauses = Vec::new();
+                if let Some((parameters, body)) = returns {
+                    clauses.push(self.catch_clause(*loc, "", Some(parameters), body));
+                }
+                for catch in catches {
+                    let clause = match catch {
+                        pt::CatchClause::Simple(loc, parameter, body) => {
+                            let parameters: pt::ParameterList =
+                                parameter.iter().map(|p| (p.loc, Some(p.clone()))).collect();
+                            self.catch_clause(*loc, "", Some(&parameters), body)
+                        }
+                        pt::CatchClause::Named(loc, name, parameter, body) => {
+                            let parameters = vec![(parameter.loc, Some(parameter.clone()))];
+                            self.catch_clause(*loc, &name.name, Some(&parameters), body)
+                        }
+                    };
+                    clauses.push(clause);
+                }
+                self.node(
+                    id,
+                    "TryStatement",
+                    *loc,
+                    json!({ "externalCall": call, "clauses": clauses }),
+                )
+            }
+            Statement::Assembly { loc, .. } => self.node(id, "InlineAssembly", *loc, json!({})),
+            Statement::Continue(loc) => self.node(id, "Continue", *loc, json!({})),
+            Statement::Break(loc) => self.node(id, "Break", *loc, json!({})),
+            Statement::Args(loc, _) | Statement::Error(loc) => {
+                self.node(id, "ExpressionStatement", *loc, json!({}))
+            }
+        }
+    }
+
+    fn revert(
+        &mut self,
+        id: u64,
+        loc: Loc,
+        path: &Option<pt::IdentifierPath>,
+        arguments: Vec<Value>,
+        names: Vec<String>,
+    ) -> Value {
+        let call_id = self.id();
+        let callee_id = self.id();
+        let mut callee = match path {
+            Some(path) => self.node(
+                callee_id,
+                "Identifier",
+                path.loc,
+                json!({ "name": self.text(path.loc) }),
+            ),
+            None => self.node(callee_id, "Identifier", loc, json!({ "name": "revert" })),
+        };
+        if let Some(target) = path.as_ref().and_then(|path| self.resolve_path(path)) {
+            callee["referencedDeclaration"] = target.into();
+        }
+        let call = self.node(
+            call_id,
+            "FunctionCall",
+            loc,
+            json!({ "expression": callee, "arguments": arguments, "names": names }),
+        );
+        self.node(id, "RevertStatement", loc, json!({ "errorCall": call }))
+    }
+
+    fn catch_clause(
+        &mut self,
+        loc: Loc,
+        error_name: &str,
+        parameters: Option<&pt::ParameterList>,
+        body: &Statement,
+    ) -> Value {
+        let id = self.id();
+        self.with_scope(HashMap::new(), |this| {
+            let mut node = this.node(
+                id,
+                "TryCatchClause",
+                loc,
+                json!({ "errorName": error_name }),
+            );
+            if let Some(parameters) = parameters {
+                node["parameters"] = this.parameters(loc, parameters);
+            }
+            node["block"] = this.statement(body);
+            node
+        })
+    }
+}
+
+/// Entries of the `errors` array of `forge build --json` output for parser errors.
+fn parser_errors(path: &str, diagnostics: &[ParserDiagnostic]) -> Vec<Value> {
+    diagnostics
+        .iter()
+        .map(|diagnostic| {
+            let (start, end) = match diagnostic.loc {
+                Loc::File(_, start, end) => (start as i64, end as i64),
+                _ => (-1, -1),
+            };
+            json!({
+                "sourceLocation": { "file": path, "start": start, "end": end },
+                "type": "ParserError",
+                "component": "general",
+                "severity": "error",
+                "message": diagnostic.message,
+                "formattedMessage": format!("ParserError: {}", diagnostic.message)
+            })
+        })
+        .collect()
+}
+
+/// Parse `source` as source number `file_id`, numbering nodes after `first_id`. Returns the
+/// source entry and the last id used.
+fn parse_source(
+    path: &str,
+    source: &str,
+    file_id: usize,
+    first_id: u64,
+) -> Result<(Value, u64), Vec<Value>> {
+    let lowered = grammar::lower(source);
+    let source = lowered.as_ref().map_or(source, |lowered| &lowered.source);
+    let (unit, _comments) =
+        solang_parser::parse(source, file_id).map_err(|errors| parser_errors(path, &errors))?;
+    let mut converter = Converter {
+        source,
+        file_id,
+        next_id: first_id,
+        scopes: Vec::new(),
+        members: HashMap::new(),
+    };
+    let mut ast = converter.source_unit(path, &unit);
+    if let Some(lowered) = &lowered {
+        grammar::restore(&mut ast, lowered, file_id);
+    }
+    let entry = json!([{ "source_file": { "id": file_id, "ast": ast } }]);
+    Ok((entry, converter.next_id))
+}
+
+/// Parse the files `(path, source)` into one output shaped like `forge build --ast --json`.
+/// Files that fail to parse are left out and their errors listed under `errors`.
+pub fn parse_files<'a>(files: impl IntoIterator<Item = (&'a str, &'a str)>) -> Value {
+    let mut sources = Map::new();
+    let mut source_id_to_path = Map::new();
+    let mut errors = Vec::new();
+    let mut next_id = 0;
+    for (file_id, (path, source)) in files.into_iter().enumerate() {
+        source_id_to_path.insert(file_id.to_string(), path.into());
+        match parse_source(path, source, file_id, next_id) {
+            Ok((entry, last_id)) => {
+                sources.insert(path.to_string(), entry);
+                next_id = last_id;
+            }
+            Err(file_errors) => errors.extend(file_errors),
+        }
+    }
+    json!({
+        "errors": errors,
+        "sources": sources,
+        "contracts": {},
+        "build_infos": [{ "source_id_to_path": source_id_to_path }]
+    })
+}
+
+/// Parse a single source. See [`parse_files`].
+pub fn parse(path: &str, source: &str) -> Value {
+    parse_files([(path, source)])
+}
+
+/// Whether `ast_data` has a syntax tree for at least one source.
+fn has_sources(ast_data: &Value) -> bool {
+    ast_data
+        .get("sources")
+        .and_then(Value::as_object)
+        .is_some_and(|sources| !sources.is_empty())
+}
+
+/// The declaration the name at `position` refers to, within the same file.
+pub fn goto_definition(
+    ast_data: &Value,
+    uri: &Url,
+    position: Position,
+    source_bytes: &[u8],
+) -> Option<Location> {
+    let id_to_path = ast::id_to_path(ast_data)?
+        .iter()
+        .map(|(id, path)| (id.clone(), path.as_str().unwrap_or_default().to_string()))
+        .collect();
+    let (nodes, path_to_abs) = cache_ids(ast_data.get("sources")?);
+    let (_, offset) = goto_bytes(
+        &nodes,
+        &path_to_abs,
+        &id_to_path,
+        uri.as_str(),
+        pos_to_bytes(source_bytes, position),
+    )?;
+    let target = bytes_to_pos(source_bytes, offset)?;
+    Some(Location {
+        uri: uri.clone(),
+        range: Range::new(target, target),
+    })
+}
+
+/// The last syntax tree of each document that parsed. While an edit leaves the buffer
+/// unparsable, the previous tree is returned so syntax features keep working.
+#[derive(Default)]
+pub struct SyntaxTrees {
+    trees: Mutex<HashMap<Url, (u64, Arc<Value>)>>,
+}
+
+impl SyntaxTrees {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The syntax tree of `uri` with the contents `source`.
+    pub async fn get(&self, uri: &Url, source: &[u8]) -> Option<Arc<Value>> {
+        let hash = content_hash(source);
+        let mut trees = self.trees.lock().await;
+        if let Some((tree_hash, tree)) = trees.get(uri)
+            && *tree_hash == hash
+        {
+            return Some(tree.clone());
+        }
+
+        let path = uri.to_file_path().ok()?;
+        let tree = parse(&path.to_string_lossy(), &String::from_utf8_lossy(source));
+        if has_sources(&tree) {
+            let tree = Arc::new(tree);
+            trees.insert(uri.clone(), (hash, tree.clone()));
+            return Some(tree);
+        }
+        trees.get(uri).map(|(_, tree)| tree.clone())
+    }
+
+    pub async fn remove(&self, uri: &Url) {
+        self.trees.lock().await.remove(uri);
+    }
+}
+
+/// Runner that parses sources in process instead of running forge. Builds report syntax
+/// errors only, there are no lints, and ASTs carry no type information.
+pub struct SyntaxRunner;
+
+async fn read_source(file: &str) -> Result<String, RunnerError> {
+    tokio::fs::read_to_string(file)
+        .await
+        .map_err(|_| RunnerError::ReadError)
+}
+
+#[async_trait]
+impl Runner for SyntaxRunner {
+    async fn build(&self, file: &str) -> Result<BuildOutput, RunnerError> {
+        let output = parse(file, &read_source(file).await?);
+        Ok(BuildOutput::from_value(output))
+    }
+
+    async fn lint(&self, _file: &str) -> Result<Value, RunnerError> {
+        Ok(json!([]))
+    }
+
+    async fn ast(&self, file: &str) -> Result<Value, RunnerError> {
+        let output = parse(file, &read_source(file).await?);
+        if has_sources(&output) {
+            return Ok(output);
+        }
+        let message = output["errors"][0]["message"].as_str().unwrap_or_default();
+        Err(RunnerError::CommandFailed(format!("{file}: {message}")))
+    }
+
+    async fn project_ast(&self, root: &str) -> Result<Value, RunnerError> {
+        let mut files = Vec::new();
+        for path in annotations::project_sources(Path::new(root)) {
+            if let Ok(source) = tokio::fs::read_to_string(&path).await {
+                files.push((path.to_string_lossy().into_owned(), source));
+            }
+        }
+        Ok(parse_files(
+            files
+                .iter()
+                .map(|(path, source)| (path.as_str(), source.as_str())),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "\
+pragma solidity ^0.8.0;
+
+contract Base {
+    uint256 public total;
+    enum Status { Open, Closed }
+}
+
+contract Vault is Base {
+    error Empty();
+
+    modifier nonEmpty(uint256 amount) {
+        if (amount == 0) revert Empty();
+        _;
+    }
+
+    function deposit(uint256 amount) public nonEmpty(amount) returns (Status) {
+        (uint256 before, ) = (total, amount);
+        total = before + amount;
+        return Status.Open;
+    }
+}
+";
+
+    fn target_of(ast: &Value, uri: &Url, needle: &str, skip: usize) -> String {
+        let offset = SOURCE.match_indices(needle).nth(skip).unwrap().0;
+        let position = bytes_to_pos(SOURCE.as_bytes(), offset).unwrap();
+        let location = goto_definition(ast, uri, position, SOURCE.as_bytes()).unwrap();
+        let start = pos_to_bytes(SOURCE.as_bytes(), location.range.start);
+        SOURCE[start..]
+            .split(|c: char| !c.is_alphanumeric())
+            .next()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn test_names_resolve_within_the_file() {
+        let path = "/project/src/Vault.sol";
+        let uri = Url::from_file_path(path).unwrap();
+        let ast = parse(path, SOURCE);
+        assert_eq!(ast["errors"], json!([]));
+
+        let unit = ast::source_unit(&ast, &uri).unwrap();
+        assert_eq!(unit["nodes"][0]["literals"], json!(["solidity", "^0.8.0"]));
+
+        let declaration = |needle: &str, skip: usize| -> usize {
+            SOURCE.match_indices(needle).nth(skip).unwrap().0
+        };
+        // Inherited state variable, base contract, modifier, error, tuple local, enum value
+        for (needle, skip, target) in [
+            ("total = before", 0, declaration("total;", 0)),
+            ("Base {", 1, declaration("Base", 0)),
+            ("nonEmpty(amount)", 0, declaration("nonEmpty", 0)),
+            ("Empty()", 1, declaration("Empty", 0)),
+            ("before + amount", 0, declaration("before", 0)),
+            ("amount;", 0, declaration("amount)", 1)),
+            ("Open;", 0, declaration("Open", 0)),
+        ] {
+            let offset = SOURCE.match_indices(needle).nth(skip).unwrap().0;
+            let position = bytes_to_pos(SOURCE.as_bytes(), offset).unwrap();
+            let location = goto_definition(&ast, &uri, position, SOURCE.as_bytes())
+                .unwrap_or_else(|| panic!("no definition for {needle}"));
+            assert_eq!(location.uri, uri);
+            assert_eq!(
+                pos_to_bytes(SOURCE.as_bytes(), location.range.start),
+                target,
+                "{needle}"
+            );
+        }
+        assert_eq!(target_of(&ast, &uri, "Status)", 0), "Status");
+    }
+
+    #[test]
+    fn test_recent_syntax_parses() {
+        let path = "/project/src/Lock.sol";
+        let uri = Url::from_file_path(path).unwrap();
+        let source = "pragma solidity ^0.8.29;
+contract Lock layout at 0x1000 {
+    uint256 transient locked;
+    uint256 count;
+
+    function enter() public {
+        assembly { tstore(0, 1) }
+        count = locked;
+    }
+}
+";
+        let ast = parse(path, source);
+        assert_eq!(ast["errors"], json!([]));
+
+        let contract = &ast::source_unit(&ast, &uri).unwrap()["nodes"][1];
+        assert_eq!(contract["name"], "Lock");
+        assert_eq!(
+            contract["storageLayout"]["src"],
+            format!("{}:16:0", source.find("layout").unwrap())
+        );
+        let locations: Vec<_> = contract["nodes"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|node| node["nodeType"] == "VariableDeclaration")
+            .map(|node| (node["storageLocation"].clone(), node["mutability"].clone()))
+            .collect();
+        assert_eq!(
+            locations,
+            [
+                (json!("transient"), json!("mutable")),
+                (json!("default"), json!("mutable"))
+            ]
+        );
+
+        // `locked` in `enter` resolves to the transient variable
+        let locked = source.rfind("locked").unwrap();
+        let declaration = source.find("locked").unwrap();
+        let position = bytes_to_pos(source.as_bytes(), locked).unwrap();
+        let location = goto_definition(&ast, &uri, position, source.as_bytes()).unwrap();
+        assert_eq!(
+            pos_to_bytes(source.as_bytes(), location.range.start),
+            declaration
+        );
+    }
+
+    #[test]
+    fn test_parser_errors_in_forge_shape() {
+        let output = parse("/project/src/Broken.sol", "contract Broken { function }");
+        assert_eq!(output["sources"], json!({}));
+        let error = &output["errors"][0];
+        assert_eq!(error["type"], "ParserError");
+        assert_eq!(error["severity"], "error");
+        assert_eq!(error["sourceLocation"]["file"], "/project/src/Broken.sol");
+    }
+
+    #[tokio::test]
+    async fn test_syntax_trees_keep_the_last_parse() {
+        let uri = Url::from_file_path("/project/src/Vault.sol").unwrap();
+        let trees = SyntaxTrees::new();
+        let tree = trees.get(&uri, SOURCE.as_bytes()).await.unwrap();
+        assert!(Arc::ptr_eq(
+            &tree,
+            &trees.get(&uri, SOURCE.as_bytes()).await.unwrap()
+        ));
+
+        let broken = SOURCE.replace("return Status.Open;", "return Status.");
+        let kept = trees.get(&uri, broken.as_bytes()).await.unwrap();
+        assert!(Arc::ptr_eq(&tree, &kept));
+
+        trees.remove(&uri).await;
+        assert!(trees.get(&uri, broken.as_bytes()).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_syntax_runner_parses_the_project() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("src")).unwrap();
+        std::fs::write(dir.path().join("src/Vault.sol"), SOURCE).unwrap();
+        std::fs::write(dir.path().join("src/Token.sol"), "contract Token {}").unwrap();
+        let root = dir.path().to_string_lossy().into_owned();
+
+        let output = SyntaxRunner.project_ast(&root).await.unwrap();
+        assert_eq!(output["sources"].as_object().unwrap().len(), 2);
+        let ids = ast::index_nodes(&output["sources"]);
+        assert!(ids.len() > 40);
+
+        assert_eq!(SyntaxRunner.lint(&root).await.unwrap(), json!([]));
+        let vault = dir.path().join("src/Vault.sol");
+        let build = SyntaxRunner.build(&vault.to_string_lossy()).await.unwrap();
+        assert!(build.errors.is_empty());
+        assert!(build.sources.contains_key(vault.to_str().unwrap()));
+    }
+}
@@ -0,0 +1,473 @@
+//! Test explorer support: `forgeLsp/discoverTests` and `forgeLsp/runTests`.
+//!
+//! Discovery lists the test contracts of the indexed projects the way `forge test` runs
+//! them: deployable contracts outside the library directories with `test*`, `invariant*` or
+//! `statefulFuzz*` functions, declared or inherited, each test located at its declaration.
+//! Suites are identified like forge names them, `test/Vault.t.sol:VaultTest`, and tests as
+//! `test/Vault.t.sol:VaultTest::test_deposit`, so results map back to the explorer's items.
+//! Running returns the result of each test: its status, duration, gas, the counterexample
+//! of a failed fuzz or invariant test, and its logs. After each build of a project the
+//! server sends `forgeLsp/testsChanged`, for explorers to discover its tests again.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::Path;
+use tower_lsp::lsp_types::{Location, Url, notification::Notification};
+
+use crate::{
+    ast::name,
+    forge_test::{CounterexampleCall, counterexample_calls},
+    project::ProjectConfig,
+    test_names,
+};
+
+/// Name of the custom request listing the tests.
+pub const DISCOVER_TESTS_METHOD: &str = "forgeLsp/discoverTests";
+
+/// Name of the custom request running tests, with a [`crate::forge_test::TestRunParams`].
+pub const RUN_TESTS_METHOD: &str = "forgeLsp/runTests";
+
+/// Name of the custom notification.
+pub const TESTS_CHANGED_METHOD: &str = "forgeLsp/testsChanged";
+
+/// Prefixes of the functions forge runs as invariant tests.
+const INVARIANT_PREFIXES: &[&str] = &["invariant", "statefulFuzz"];
+
+/// The tests of a project may have changed.
+pub enum TestsChanged {}
+
+impl Notification for TestsChanged {
+    type Params = TestsChangedParams;
+    const METHOD: &'static str = TESTS_CHANGED_METHOD;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TestsChangedParams {
+    /// Root of the project that was built.
+    pub root: Url,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiscoverTestsParams {
+    /// Only the suites declared in this file, instead of every suite of the workspace.
+    #[serde(default)]
+    pub uri: Option<Url>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TestKind {
+    /// A test without parameters.
+    Unit,
+    /// A test forge calls with random arguments.
+    Fuzz,
+    /// An invariant checked after random sequences of calls.
+    Invariant,
+}
+
+/// A test function of a suite.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TestItem {
+    /// `<suite id>::<name>`.
+    pub id: String,
+    pub name: String,
+    pub kind: TestKind,
+    /// Name of the function's declaration, in a base contract for inherited tests.
+    pub location: Location,
+}
+
+/// A test contract.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TestSuite {
+    /// `<path relative to the project root>:<contract>`, as forge names it.
+    pub id: String,
+    pub contract: String,
+    /// Name of the contract's declaration.
+    pub location: Location,
+    pub tests: Vec<TestItem>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TestStatus {
+    Passed,
+    Failed,
+    Skipped,
+}
+
+/// Result of a test function.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TestResult {
+    /// Id of the test, as in discovery.
+    pub id: String,
+    pub suite: String,
+    pub name: String,
+    pub status: TestStatus,
+    pub duration_ms: Option<f64>,
+    /// Gas of a unit test, median gas of the runs of a fuzz test.
+    pub gas: Option<u64>,
+    /// Runs of a fuzz or invariant test.
+    pub runs: Option<u64>,
+    /// Revert reason or assertion message of a failure.
+    pub reason: Option<String>,
+    /// Calls of the counterexample of a failed fuzz or invariant test, empty otherwise.
+    pub counterexample: Vec<CounterexampleCall>,
+    /// Logs the test emitted, decoded.
+    pub logs: Vec<String>,
+}
+
+/// The kind of test `function` is, if forge runs it as one.
+fn test_kind(function: &Value) -> Option<TestKind> {
+    let name = name(function);
+    if function.get("nodeType").and_then(Value::as_str) != Some("FunctionDefinition")
+        || function.get("kind").and_then(Value::as_str) != Some("function")
+        || !matches!(
+            function.get("visibility").and_then(Value::as_str),
+            Some("public" | "external")
+        )
+    {
+        return None;
+    }
+    if INVARIANT_PREFIXES
+        .iter()
+        .any(|prefix| name.starts_with(prefix))
+    {
+        return Some(TestKind::Invariant);
+    }
+    if !name.starts_with("test") {
+        return None;
+    }
+    let parameters = function
+        .get("parameters")
+        .and_then(|parameters| parameters.get("parameters"))
+        .and_then(Value::as_array);
+    Some(match parameters {
+        Some(parameters) if !parameters.is_empty() => TestKind::Fuzz,
+        _ => TestKind::Unit,
+    })
+}
+
+/// The test suites of the project at `root` with the project AST `ast_data`, sorted by id.
+pub fn discover_tests(ast_data: &Value, root: &Path) -> Vec<TestSuite> {
+    let config = ProjectConfig::load(root);
+    let contracts = test_names::contracts_by_id(ast_data);
+    let mut suites = Vec::new();
+    for (path, contract) in contracts.values() {
+        if contract.get("contractKind").and_then(Value::as_str) != Some("contract")
+            || contract.get("abstract").and_then(Value::as_bool) == Some(true)
+            || config.is_dependency(&root.join(path))
+        {
+            continue;
+        }
+        let contract_name = name(contract);
+        let id = format!("{path}:{contract_name}");
+
+        // The most derived declaration of each name, in the order of the linearization
+        let mut tests: Vec<TestItem> = Vec::new();
+        let bases = contract
+            .get("linearizedBaseContracts")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(|id| contracts.get(&id.as_u64()?));
+        for (base_path, base) in bases {
+            let functions = base.get("nodes").and_then(Value::as_array);
+            for function in functions.into_iter().flatten() {
+                let (Some(kind), test) = (test_kind(function), name(function)) else {
+                    continue;
+                };
+                if tests.iter().any(|item| item.name == test) {
+                    continue;
+                }
+                let Some(location) = test_names::location(root, base_path, function) else {
+                    continue;
+                };
+                tests.push(TestItem {
+                    id: format!("{id}::{test}"),
+                    name: test.to_string(),
+                    kind,
+                    location,
+                });
+            }
+        }
+        if tests.is_empty() {
+            continue;
+        }
+        let Some(location) = test_names::location(root, path, contract) else {
+            continue;
+        };
+        tests.sort_by(|a, b| {
+            (a.location.uri.as_str(), a.location.range.start)
+                .cmp(&(b.location.uri.as_str(), b.location.range.start))
+        });
+        suites.push(TestSuite {
+            id,
+            contract: contract_name.to_string(),
+            location,
+            tests,
+        });
+    }
+    suites.sort_by(|a, b| a.id.cmp(&b.id));
+    suites
+}
+
+fn string(value: &Value, key: &str) -> Option<String> {
+    value.get(key).and_then(Value::as_str).map(str::to_string)
+}
+
+/// The results of the tests in `forge test --json` output, sorted by id.
+pub fn test_results(output: &Value) -> Vec<TestResult> {
+    let Some(suites) = output.as_object() else {
+        return vec![];
+    };
+
+    let mut results = Vec::new();
+    for (suite, suite_results) in suites {
+        let Some(tests) = suite_results.get("test_results").and_then(Value::as_object) else {
+            continue;
+        };
+        for (signature, result) in tests {
+            let name = signature.split('(').next().unwrap_or(signature);
+            let status = match result.get("status").and_then(Value::as_str) {
+                Some("Success") => TestStatus::Passed,
+                Some("Skipped") => TestStatus::Skipped,
+                _ => TestStatus::Failed,
+            };
+            let duration_ms = result.get("duration").and_then(|duration| {
+                let secs = duration.get("secs")?.as_f64()?;
+                let nanos = duration.get("nanos")?.as_f64()?;
+                Some(secs * 1000.0 + nanos / 1_000_000.0)
+            });
+            let kind = result.get("kind");
+            let unit = kind.and_then(|kind| kind.get("Unit"));
+            let fuzz = kind.and_then(|kind| kind.get("Fuzz"));
+            let invariant = kind.and_then(|kind| kind.get("Invariant"));
+            let gas = unit
+                .and_then(|unit| unit.get("gas"))
+                .or_else(|| fuzz.and_then(|fuzz| fuzz.get("median_gas")))
+                .and_then(Value::as_u64);
+            let runs = fuzz
+                .or(invariant)
+                .and_then(|kind| kind.get("runs"))
+                .and_then(Value::as_u64);
+            results.push(TestResult {
+                id: format!("{suite}::{name}"),
+                suite: suite.clone(),
+                name: name.to_string(),
+                status,
+                duration_ms,
+                gas,
+                runs,
+                reason: string(result, "reason"),
+                counterexample: counterexample_calls(result),
+                logs: result
+                    .get("decoded_logs")
+                    .and_then(Value::as_array)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|log| log.as_str().map(str::to_string))
+                    .collect(),
+            });
+        }
+    }
+    results.sort_by(|a, b| a.id.cmp(&b.id));
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast;
+    use serde_json::json;
+
+    const BASE: &str = "\
+abstract contract Base {
+    function test_shared() public {}
+    function invariant_solvent() public {}
+}
+";
+    const TEST: &str = "\
+import {Base} from \"./Base.sol\";
+contract VaultTest is Base {
+    function setUp() public {}
+    function test_deposit() public {}
+    function testFuzz_withdraw(uint256 amount) public {}
+    function test_helper() internal {}
+}
+";
+
+    fn function(source: &str, name: &str, visibility: &str, parameters: usize) -> Value {
+        json!({
+            "nodeType": "FunctionDefinition",
+            "kind": "function",
+            "name": name,
+            "nameLocation": format!("{}:{}:0", source.find(name).unwrap(), name.len()),
+            "visibility": visibility,
+            "parameters": { "parameters": vec![json!({}); parameters] }
+        })
+    }
+
+    #[test]
+    fn test_discover_tests() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("test")).unwrap();
+        std::fs::write(root.join("test/Base.sol"), BASE).unwrap();
+        std::fs::write(root.join("test/Vault.t.sol"), TEST).unwrap();
+        let unit = |path: &str, contract: Value| {
+            ast::mock_source(
+                0,
+                json!({
+                    "nodeType": "SourceUnit",
+                    "absolutePath": path,
+                    "nodes": [contract]
+                }),
+            )
+        };
+        let base = json!({
+            "nodeType": "ContractDefinition",
+            "id": 1,
+            "name": "Base",
+            "contractKind": "contract",
+            "abstract": true,
+            "linearizedBaseContracts": [1],
+            "nodes": [
+                function(BASE, "test_shared", "public", 0),
+                function(BASE, "invariant_solvent", "public", 0),
+            ]
+        });
+        let vault = json!({
+            "nodeType": "ContractDefinition",
+            "id": 2,
+            "name": "VaultTest",
+            "nameLocation": format!("{}:9:0", TEST.find("VaultTest").unwrap()),
+            "contractKind": "contract",
+            "abstract": false,
+            "linearizedBaseContracts": [2, 1],
+            "nodes": [
+                function(TEST, "setUp", "public", 0),
+                function(TEST, "test_deposit", "public", 0),
+                function(TEST, "testFuzz_withdraw", "public", 1),
+                function(TEST, "test_helper", "internal", 0),
+            ]
+        });
+        let ast_data = json!({ "sources": {
+            "test/Base.sol": unit("test/Base.sol", base),
+            "test/Vault.t.sol": unit("test/Vault.t.sol", vault),
+        }});
+
+        let suites = discover_tests(&ast_data, root);
+        assert_eq!(suites.len(), 1);
+        let suite = &suites[0];
+        assert_eq!(suite.id, "test/Vault.t.sol:VaultTest");
+        assert_eq!(suite.location.range.start.line, 1);
+        let tests: Vec<(&str, TestKind, u32)> = suite
+            .tests
+            .iter()
+            .map(|test| (test.id.as_str(), test.kind, test.location.range.start.line))
+            .collect();
+        assert_eq!(
+            tests,
+            [
+                ("test/Vault.t.sol:VaultTest::test_shared", TestKind::Unit, 1),
+                (
+                    "test/Vault.t.sol:VaultTest::invariant_solvent",
+                    TestKind::Invariant,
+                    2
+                ),
+                (
+                    "test/Vault.t.sol:VaultTest::test_deposit",
+                    TestKind::Unit,
+                    3
+                ),
+                (
+                    "test/Vault.t.sol:VaultTest::testFuzz_withdraw",
+                    TestKind::Fuzz,
+                    4
+                ),
+            ]
+        );
+        assert!(
+            suite.tests[0]
+                .location
+                .uri
+                .path()
+                .ends_with("test/Base.sol")
+        );
+    }
+
+    #[test]
+    fn test_test_results() {
+        let output = json!({
+            "test/Vault.t.sol:VaultTest": { "test_results": {
+                "test_deposit()": {
+                    "status": "Success",
+                    "reason": null,
+                    "counterexample": null,
+                    "decoded_logs": ["deposited 1"],
+                    "kind": { "Unit": { "gas": 31204 } },
+                    "duration": { "secs": 0, "nanos": 2500000 }
+                },
+                "testFuzz_withdraw(uint256)": {
+                    "status": "Failure",
+                    "reason": "panic: arithmetic underflow or overflow (0x11)",
+                    "counterexample": { "Single": {
+                        "calldata": "0x2e1a7d4d0000000000000000000000000000000000000000000000000000000000000002",
+                        "signature": null,
+                        "args": "2"
+                    }},
+                    "decoded_logs": [],
+                    "kind": { "Fuzz": { "runs": 3, "mean_gas": 40000, "median_gas": 41000 } },
+                    "duration": { "secs": 1, "nanos": 0 }
+                },
+                "invariant_solvent()": {
+                    "status": "Failure",
+                    "reason": "not solvent",
+                    "counterexample": { "Sequence": [
+                        { "sender": "0x01", "addr": "0x02", "contract_name": "Vault",
+                          "signature": "deposit(uint256)", "args": "5", "calldata": "0xb6b55f25" },
+                        { "sender": "0x01", "addr": "0x02", "contract_name": "Vault",
+                          "signature": "withdraw(uint256)", "args": "6", "calldata": "0x2e1a7d4d" }
+                    ]},
+                    "kind": { "Invariant": { "runs": 12, "calls": 180, "reverts": 4 } }
+                }
+            }}
+        });
+        let results = test_results(&output);
+        let ids: Vec<&str> = results.iter().map(|result| result.name.as_str()).collect();
+        assert_eq!(
+            ids,
+            ["invariant_solvent", "testFuzz_withdraw", "test_deposit"]
+        );
+
+        let [invariant, fuzz, unit] = &results[..] else {
+            unreachable!()
+        };
+        assert_eq!(unit.id, "test/Vault.t.sol:VaultTest::test_deposit");
+        assert_eq!(unit.status, TestStatus::Passed);
+        assert_eq!(unit.gas, Some(31204));
+        assert_eq!(unit.duration_ms, Some(2.5));
+        assert_eq!(unit.logs, ["deposited 1"]);
+        assert!(unit.counterexample.is_empty());
+
+        assert_eq!(fuzz.status, TestStatus::Failed);
+        assert_eq!((fuzz.gas, fuzz.runs), (Some(41000), Some(3)));
+        assert_eq!(fuzz.counterexample.len(), 1);
+        assert_eq!(fuzz.counterexample[0].args.as_deref(), Some("2"));
+
+        assert_eq!((invariant.gas, invariant.runs), (None, Some(12)));
+        assert_eq!(invariant.duration_ms, None);
+        let calls: Vec<&str> = invariant
+            .counterexample
+            .iter()
+            .filter_map(|call| call.signature.as_deref())
+            .collect();
+        assert_eq!(calls, ["deposit(uint256)", "withdraw(uint256)"]);
+        assert_eq!(invariant.counterexample[0].address.as_deref(), Some("0x02"));
+    }
+}
@@ -0,0 +1,298 @@
+//! `forge-lsp/resolveTestName`: the definitions of a test or contract named the way
+//! `forge test` and `forge build` print them, so terminal integrations can make the names
+//! in their output clickable.
+//!
+//! Recognized are `CounterTest::test_Increment`, with or without a parameter list,
+//! `test/Counter.t.sol:CounterTest` as in suite headers, and artifact paths like
+//! `out/Counter.sol/Counter.json`. A test inherited from a base contract resolves to the
+//! base's function.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{collections::HashMap, path::Path};
+use tower_lsp::lsp_types::{Location, Range};
+
+use crate::{
+    ast::{self, parse_src},
+    goto::bytes_to_pos,
+    paths,
+};
+
+/// Name of the custom request.
+pub const RESOLVE_TEST_NAME_METHOD: &str = "forge-lsp/resolveTestName";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResolveTestNameParams {
+    /// The name as printed, e.g. `CounterTest::test_Increment()`.
+    pub name: String,
+}
+
+/// A contract, and optionally one of its functions, named in forge output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TestName<'a> {
+    /// Trailing part of the path of the defining file.
+    pub path: Option<&'a str>,
+    pub contract: &'a str,
+    pub test: Option<&'a str>,
+}
+
+fn is_identifier(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+}
+
+impl<'a> TestName<'a> {
+    pub fn parse(text: &'a str) -> Option<Self> {
+        let text = text.trim();
+
+        // `out/<file>/<contract>.json`
+        if let Some(artifact) = text.strip_suffix(".json") {
+            let (dir, contract) = artifact.rsplit_once('/')?;
+            let file = dir.rsplit('/').next()?;
+            return (file.ends_with(".sol") && is_identifier(contract)).then_some(Self {
+                path: Some(file),
+                contract,
+                test: None,
+            });
+        }
+
+        let (qualified, test) = match text.split_once("::") {
+            Some((qualified, test)) => {
+                let test = test.split('(').next()?;
+                if !is_identifier(test) {
+                    return None;
+                }
+                (qualified, Some(test))
+            }
+            None => (text, None),
+        };
+        let (path, contract) = match qualified.rsplit_once(':') {
+            Some((path, contract)) if path.ends_with(".sol") => (Some(path), contract),
+            Some(_) => return None,
+            None => (None, qualified),
+        };
+        is_identifier(contract).then_some(Self {
+            path,
+            contract,
+            test,
+        })
+    }
+}
+
+/// Whether the source `path` ends with the path components of `suffix`.
+fn path_matches(path: &str, suffix: &str) -> bool {
+    let path = path.replace('\\', "/");
+    path == suffix || path.ends_with(&format!("/{}", suffix.trim_start_matches("./")))
+}
+
+/// Location of the name of `node`, read from the file at `path` relative to `root`.
+pub(crate) fn location(root: &Path, path: &str, node: &Value) -> Option<Location> {
+    let src = node.get("nameLocation").or_else(|| node.get("src"))?;
+    let (start, length, _) = parse_src(src.as_str()?)?;
+    let file = root.join(path);
+    let source = std::fs::read(&file).ok()?;
+    Some(Location {
+        uri: paths::path_to_uri(&file)?,
+        range: Range::new(
+            bytes_to_pos(&source, start)?,
+            bytes_to_pos(&source, start + length)?,
+        ),
+    })
+}
+
+/// Every contract of the project AST `ast_data` with the path of its source, by id.
+pub(crate) fn contracts_by_id(ast_data: &Value) -> HashMap<u64, (&str, &Value)> {
+    let mut contracts: HashMap<u64, (&str, &Value)> = HashMap::new();
+    let units = ast_data.get("sources").and_then(Value::as_object);
+    for (path, contents) in units.into_iter().flatten() {
+        let Some(unit) = contents
+            .get(0)
+            .and_then(|content| content.get("source_file")?.get("ast"))
+        else {
+            continue;
+        };
+        let path = unit
+            .get("absolutePath")
+            .and_then(Value::as_str)
+            .unwrap_or(path);
+        ast::walk(unit, &mut |node| {
+            if node.get("nodeType").and_then(Value::as_str) == Some("ContractDefinition")
+                && let Some(id) = node.get("id").and_then(Value::as_u64)
+            {
+                contracts.insert(id, (path, node));
+            }
+        });
+    }
+    contracts
+}
+
+/// The definitions `name` refers to in the project AST `ast_data` of the project at
+/// `root`: the test function, declared by the contract or inherited, or the contract.
+pub fn resolve_test_name(ast_data: &Value, root: &Path, name: &TestName) -> Vec<Location> {
+    let contracts = contracts_by_id(ast_data);
+    let mut named: Vec<&(&str, &Value)> = contracts
+        .values()
+        .filter(|(path, contract)| {
+            contract.get("name").and_then(Value::as_str) == Some(name.contract)
+                && name.path.is_none_or(|suffix| path_matches(path, suffix))
+        })
+        .collect();
+    named.sort_by_key(|(path, _)| *path);
+
+    let mut locations = Vec::new();
+    for (path, contract) in named {
+        let Some(test) = name.test else {
+            locations.extend(location(root, path, contract));
+            continue;
+        };
+        // The most derived definition, in the order of the linearization
+        let definition = contract
+            .get("linearizedBaseContracts")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(|id| contracts.get(&id.as_u64()?))
+            .find_map(|(path, base)| {
+                let function =
+                    base.get("nodes")
+                        .and_then(Value::as_array)?
+                        .iter()
+                        .find(|node| {
+                            node.get("nodeType").and_then(Value::as_str)
+                                == Some("FunctionDefinition")
+                                && node.get("name").and_then(Value::as_str) == Some(test)
+                        })?;
+                Some((*path, function))
+            });
+        if let Some((path, function)) = definition {
+            locations.extend(location(root, path, function));
+        }
+    }
+    locations.dedup();
+    locations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_test_names() {
+        let name = |path, contract, test| TestName {
+            path,
+            contract,
+            test,
+        };
+        assert_eq!(
+            TestName::parse("CounterTest::test_Increment()"),
+            Some(name(None, "CounterTest", Some("test_Increment")))
+        );
+        assert_eq!(
+            TestName::parse(" CounterTest::testFuzz_Set(uint256) "),
+            Some(name(None, "CounterTest", Some("testFuzz_Set")))
+        );
+        assert_eq!(
+            TestName::parse("test/Counter.t.sol:CounterTest"),
+            Some(name(Some("test/Counter.t.sol"), "CounterTest", None))
+        );
+        assert_eq!(
+            TestName::parse("out/Counter.sol/Counter.json"),
+            Some(name(Some("Counter.sol"), "Counter", None))
+        );
+        assert_eq!(
+            TestName::parse("CounterTest"),
+            Some(name(None, "CounterTest", None))
+        );
+        for text in ["", "Counter Test", "Counter::", "a:b", "out/abi.json"] {
+            assert_eq!(TestName::parse(text), None, "{text}");
+        }
+    }
+
+    const BASE: &str = "abstract contract Base {\n    function test_Shared() public {}\n}\n";
+    const TEST: &str = "\
+import {Base} from \"./Base.sol\";
+contract CounterTest is Base {
+    function test_Increment() public {}
+}
+";
+
+    fn contract(id: u64, name: &str, source: &str, bases: &[u64], functions: &[&str]) -> Value {
+        let at = |text: &str| format!("{}:{}:0", source.find(text).unwrap(), text.len());
+        json!({
+            "nodeType": "ContractDefinition",
+            "id": id,
+            "name": name,
+            "nameLocation": at(name),
+            "linearizedBaseContracts": bases,
+            "nodes": functions.iter().map(|function| json!({
+                "nodeType": "FunctionDefinition",
+                "name": function,
+                "nameLocation": at(function)
+            })).collect::<Vec<_>>()
+        })
+    }
+
+    #[test]
+    fn test_resolve_test_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("test")).unwrap();
+        std::fs::write(root.join("test/Base.sol"), BASE).unwrap();
+        std::fs::write(root.join("test/Counter.t.sol"), TEST).unwrap();
+        let unit = |path: &str, contract: Value| {
+            ast::mock_source(
+                0,
+                json!({
+                    "nodeType": "SourceUnit",
+                    "absolutePath": path,
+                    "nodes": [contract]
+                }),
+            )
+        };
+        let ast_data = json!({ "sources": {
+            "test/Base.sol": unit(
+                "test/Base.sol",
+                contract(1, "Base", BASE, &[1], &["test_Shared"])
+            ),
+            "test/Counter.t.sol": unit(
+                "test/Counter.t.sol",
+                contract(2, "CounterTest", TEST, &[2, 1], &["test_Increment"])
+            ),
+        }});
+
+        let resolve = |text: &str| {
+            resolve_test_name(&ast_data, root, &TestName::parse(text).unwrap())
+                .into_iter()
+                .map(|location| {
+                    let path = location.uri.to_file_path().unwrap();
+                    let file = path
+                        .strip_prefix(root)
+                        .unwrap()
+                        .to_string_lossy()
+                        .into_owned();
+                    (file, location.range.start.line)
+                })
+                .collect::<Vec<_>>()
+        };
+        let at = |file: &str, line| vec![(file.to_string(), line)];
+        assert_eq!(
+            resolve("CounterTest::test_Increment()"),
+            at("test/Counter.t.sol", 2)
+        );
+        assert_eq!(
+            resolve("CounterTest::test_Shared()"),
+            at("test/Base.sol", 1)
+        );
+        assert_eq!(
+            resolve("test/Counter.t.sol:CounterTest"),
+            at("test/Counter.t.sol", 1)
+        );
+        assert!(resolve("src/Counter.t.sol:CounterTest").is_empty());
+        assert!(resolve("CounterTest::test_Missing").is_empty());
+    }
+}
@@ -0,0 +1,279 @@
+//! Workspace trust.
+//!
+//! Running `forge` can execute project-defined code (FFI cheatcodes, build scripts, custom
+//! lint commands from `foundry.toml`). Before the first such run the user is asked through
+//! `window/showMessageRequest` whether the workspace is trusted; the answer holds for the
+//! rest of the session.
+
+use crate::{
+    fuzz_config::Fuzzer,
+    runner::{BuildOutput, Runner, RunnerError, TestFilter},
+};
+use std::{collections::HashMap, sync::Arc};
+use tokio::sync::{Mutex, mpsc::UnboundedSender};
+use tower_lsp::{
+    Client, async_trait,
+    lsp_types::{MessageActionItem, MessageType},
+};
+
+const TRUST_ACTION: &str = "Trust Workspace";
+const DENY_ACTION: &str = "Don't Trust";
+
+pub struct WorkspaceTrust {
+    /// Client to prompt. Without one, only an explicit [`WorkspaceTrust::grant`] trusts.
+    client: Option<Client>,
+    decision: Mutex<Option<bool>>,
+}
+
+impl WorkspaceTrust {
+    pub fn new(client: Client) -> Self {
+        Self {
+            client: Some(client),
+            decision: Mutex::new(None),
+        }
+    }
+
+    /// Trust state that never prompts the user.
+    pub fn without_prompt() -> Self {
+        Self {
+            client: None,
+            decision: Mutex::new(None),
+        }
+    }
+
+    /// Mark the workspace as trusted, e.g. from the `trustedWorkspace` setting.
+    pub async fn grant(&self) {
+        *self.decision.lock().await = Some(true);
+    }
+
+    /// Whether the workspace is trusted, asking the user if they have not decided yet.
+    ///
+    /// Concurrent callers wait for the same prompt. A dismissed prompt counts as "not
+    /// trusted" for this call only, so the user is asked again on the next run.
+    pub async fn is_trusted(&self) -> bool {
+        let mut decision = self.decision.lock().await;
+        if let Some(trusted) = *decision {
+            return trusted;
+        }
+
+        let answer = self.prompt().await;
+        if answer.is_some() {
+            *decision = answer;
+        }
+        answer.unwrap_or(false)
+    }
+
+    async fn prompt(&self) -> Option<bool> {
+        let client = self.client.as_ref()?;
+        let action = |title: &str| MessageActionItem {
+            title: title.to_string(),
+            properties: HashMap::new(),
+        };
+
+        let response = client
+            .show_message_request(
+                MessageType::WARNING,
+                "forge-lsp runs `forge` in this workspace, which can execute project-defined \
+                 code. Do you trust this workspace?",
+                Some(vec![action(TRUST_ACTION), action(DENY_ACTION)]),
+            )
+            .await
+            .ok()
+            .flatten()?;
+
+        Some(response.title == TRUST_ACTION)
+    }
+}
+
+/// Runner decorator that only lets calls through once the workspace is trusted.
+pub struct TrustedRunner<R> {
+    inner: R,
+    trust: Arc<WorkspaceTrust>,
+}
+
+impl<R: Runner> TrustedRunner<R> {
+    pub fn new(inner: R, trust: Arc<WorkspaceTrust>) -> Self {
+        Self { inner, trust }
+    }
+
+    async fn check(&self) -> Result<(), RunnerError> {
+        if self.trust.is_trusted().await {
+            Ok(())
+        } else {
+            Err(RunnerError::Untrusted)
+        }
+    }
+}
+
+#[async_trait]
+impl<R: Runner> Runner for TrustedRunner<R> {
+    async fn build(&self, file: &str) -> Result<BuildOutput, RunnerError> {
+        self.check().await?;
+        self.inner.build(file).await
+    }
+
+    async fn lint(&self, file: &str) -> Result<serde_json::Value, RunnerError> {
+        self.check().await?;
+        self.inner.lint(file).await
+    }
+
+    async fn ast(&self, file: &str) -> Result<serde_json::Value, RunnerError> {
+        self.check().await?;
+        self.inner.ast(file).await
+    }
+
+    async fn build_tests(&self, file: &str) -> Result<BuildOutput, RunnerError> {
+        self.check().await?;
+        self.inner.build_tests(file).await
+    }
+
+    async fn project_ast(&self, root: &str) -> Result<serde_json::Value, RunnerError> {
+        self.check().await?;
+        self.inner.project_ast(root).await
+    }
+
+    async fn contract_ast(
+        &self,
+        file: &str,
+        contract: &str,
+    ) -> Result<serde_json::Value, RunnerError> {
+        self.check().await?;
+        self.inner.contract_ast(file, contract).await
+    }
+
+    async fn doc(&self, root: &str, out: &str) -> Result<(), RunnerError> {
+        self.check().await?;
+        self.inner.doc(root, out).await
+    }
+
+    async fn fmt(&self, root: &str, source: &str) -> Result<String, RunnerError> {
+        self.check().await?;
+        self.inner.fmt(root, source).await
+    }
+
+    async fn flatten(&self, root: &str, file: &str) -> Result<String, RunnerError> {
+        self.check().await?;
+        self.inner.flatten(root, file).await
+    }
+
+    async fn test(
+        &self,
+        root: &str,
+        filter: TestFilter<'_>,
+    ) -> Result<serde_json::Value, RunnerError> {
+        self.check().await?;
+        self.inner.test(root, filter).await
+    }
+
+    async fn test_streamed(
+        &self,
+        root: &str,
+        filter: TestFilter<'_>,
+        lines: UnboundedSender<String>,
+    ) -> Result<serde_json::Value, RunnerError> {
+        self.check().await?;
+        self.inner.test_streamed(root, filter, lines).await
+    }
+
+    async fn gas_report(&self, root: &str) -> Result<serde_json::Value, RunnerError> {
+        self.check().await?;
+        self.inner.gas_report(root).await
+    }
+
+    async fn gas_estimates(&self, root: &str) -> Result<serde_json::Value, RunnerError> {
+        self.check().await?;
+        self.inner.gas_estimates(root).await
+    }
+
+    async fn storage_layout(
+        &self,
+        root: &str,
+        contract: &str,
+    ) -> Result<serde_json::Value, RunnerError> {
+        self.check().await?;
+        self.inner.storage_layout(root, contract).await
+    }
+
+    async fn rebuild(&self, root: &str) -> Result<(), RunnerError> {
+        self.check().await?;
+        self.inner.rebuild(root).await
+    }
+
+    async fn bind(&self, root: &str, args: &[String]) -> Result<(), RunnerError> {
+        self.check().await?;
+        self.inner.bind(root, args).await
+    }
+
+    async fn model_check(
+        &self,
+        root: &str,
+        config: &str,
+    ) -> Result<serde_json::Value, RunnerError> {
+        self.check().await?;
+        self.inner.model_check(root, config).await
+    }
+
+    async fn run_fuzzer(
+        &self,
+        root: &str,
+        fuzzer: Fuzzer,
+        config: &str,
+        contract: Option<&str>,
+        lines: UnboundedSender<String>,
+    ) -> Result<bool, RunnerError> {
+        self.check().await?;
+        self.inner
+            .run_fuzzer(root, fuzzer, config, contract, lines)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Default)]
+    struct CountingRunner {
+        calls: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl Runner for CountingRunner {
+        async fn build(&self, _: &str) -> Result<BuildOutput, RunnerError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(BuildOutput::default())
+        }
+
+        async fn lint(&self, _: &str) -> Result<serde_json::Value, RunnerError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(serde_json::Value::Null)
+        }
+
+        async fn ast(&self, _: &str) -> Result<serde_json::Value, RunnerError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(serde_json::Value::Null)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_untrusted_workspace_blocks_runner() {
+        let trust = Arc::new(WorkspaceTrust::without_prompt());
+        let runner = TrustedRunner::new(CountingRunner::default(), trust.clone());
+
+        assert!(matches!(
+            runner.build("src/A.sol").await,
+            Err(RunnerError::Untrusted)
+        ));
+        assert!(matches!(
+            runner.project_ast(".").await,
+            Err(RunnerError::Untrusted)
+        ));
+        assert_eq!(runner.inner.calls.load(Ordering::SeqCst), 0);
+
+        trust.grant().await;
+        runner.build("src/A.sol").await.unwrap();
+        runner.lint("src/A.sol").await.unwrap();
+        assert_eq!(runner.inner.calls.load(Ordering::SeqCst), 2);
+    }
+}
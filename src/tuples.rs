@@ -0,0 +1,432 @@
+//! Destructuring the values a function call returns: a code action declaring them, either
+//! for a call whose results are dropped, `split(x);`, or for the positions a destructuring
+//! skips, `(, uint256 shares, ) = split(x);`, and a hover on the tuple mapping each returned
+//! value to its position.
+
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use tower_lsp::lsp_types::{
+    CodeAction, CodeActionKind, CodeActionOrCommand, Hover, HoverContents, MarkupContent,
+    MarkupKind, Position, Range, TextEdit, Url, WorkspaceEdit,
+};
+
+use crate::{
+    ast::{self, children, name, node_type, span_bounds},
+    edits::EditBuilder,
+    goto::{bytes_to_pos, pos_to_bytes},
+    struct_literals::type_text,
+};
+
+/// The function `call` calls and the parameters it returns, when it returns any.
+fn returned<'a>(
+    call: &Value,
+    index: &HashMap<u64, &'a Value>,
+) -> Option<(&'a Value, Vec<&'a Value>)> {
+    if node_type(call) != Some("FunctionCall")
+        || call
+            .get("kind")
+            .and_then(Value::as_str)
+            .unwrap_or("functionCall")
+            != "functionCall"
+    {
+        return None;
+    }
+    // `vault.withdraw{value: 1}(...)`
+    let mut callee = call.get("expression")?;
+    while node_type(callee) == Some("FunctionCallOptions") {
+        callee = callee.get("expression")?;
+    }
+    let function = index.get(&callee.get("referencedDeclaration")?.as_u64()?)?;
+    if node_type(function) != Some("FunctionDefinition") {
+        return None;
+    }
+    let returns: Vec<&Value> = children(function.get("returnParameters")?, "parameters").collect();
+    (!returns.is_empty()).then_some((*function, returns))
+}
+
+/// `uint256 shares` for the returned parameter `parameter`, without its name.
+fn returned_type(parameter: &Value) -> Option<String> {
+    let ty = type_text(parameter.get("typeName")?)?;
+    // Returned values are copied to memory, except storage pointers of internal calls
+    let location = match parameter.get("storageLocation").and_then(Value::as_str) {
+        Some("memory" | "calldata") => " memory",
+        Some("storage") => " storage",
+        _ => "",
+    };
+    Some(format!("{ty}{location}"))
+}
+
+/// A statement or assignment taking the returned values of a call.
+struct Destructuring<'a> {
+    /// The `VariableDeclarationStatement`, `ExpressionStatement` or `Assignment`.
+    node: &'a Value,
+    call: &'a Value,
+    /// Byte range of the tuple, parentheses included; `None` when the results are dropped.
+    tuple: Option<(usize, usize)>,
+    /// What each position of the tuple declares or assigns, `None` where it skips a value.
+    targets: Vec<Option<&'a Value>>,
+}
+
+/// The innermost destructuring, or call statement, containing `offset`.
+fn destructuring_at<'a>(unit: &'a Value, offset: usize, source: &str) -> Option<Destructuring<'a>> {
+    let mut found = None;
+    ast::walk(unit, &mut |node| {
+        let Some((start, end)) = span_bounds(node) else {
+            return;
+        };
+        if !(start..=end).contains(&offset) {
+            return;
+        }
+        let destructuring = match node_type(node) {
+            Some("VariableDeclarationStatement") => (|| {
+                let call = node.get("initialValue")?;
+                let (call_start, _) = span_bounds(call)?;
+                // Only `(...) = ` declares a tuple
+                source.get(start..)?.starts_with('(').then_some(())?;
+                let close = start + source.get(start..call_start)?.rfind(')')?;
+                Some(Destructuring {
+                    node,
+                    call,
+                    tuple: Some((start, close + 1)),
+                    targets: children(node, "declarations")
+                        .map(|declaration| (!declaration.is_null()).then_some(declaration))
+                        .collect(),
+                })
+            })(),
+            Some("Assignment") => (|| {
+                let tuple = node.get("leftHandSide")?;
+                (node_type(tuple) == Some("TupleExpression")).then_some(())?;
+                Some(Destructuring {
+                    node,
+                    call: node.get("rightHandSide")?,
+                    tuple: Some(span_bounds(tuple)?),
+                    targets: children(tuple, "components")
+                        .map(|component| (!component.is_null()).then_some(component))
+                        .collect(),
+                })
+            })(),
+            Some("ExpressionStatement") => node.get("expression").map(|call| Destructuring {
+                node,
+                call,
+                tuple: None,
+                targets: vec![],
+            }),
+            _ => None,
+        };
+        // Statements nest in blocks, so the last one found is the innermost
+        if let Some(destructuring) = destructuring {
+            found = Some(destructuring);
+        }
+    });
+    found
+}
+
+/// Byte ranges between the parentheses and commas of the tuple at `tuple` in `source`.
+fn positions(source: &str, (start, end): (usize, usize)) -> Option<Vec<(usize, usize)>> {
+    let inner = source.get(start + 1..end - 1)?;
+    let mut positions = vec![];
+    let mut depth = 0usize;
+    let mut position_start = start + 1;
+    for (i, c) in inner.char_indices() {
+        match c {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => {
+                positions.push((position_start, start + 1 + i));
+                position_start = start + 2 + i;
+            }
+            _ => {}
+        }
+    }
+    positions.push((position_start, end - 1));
+    Some(positions)
+}
+
+/// Names declared in the function around `offset`, which new declarations must not shadow.
+fn names_in_scope(unit: &Value, offset: usize) -> HashSet<String> {
+    let mut function = None;
+    ast::walk(unit, &mut |node| {
+        if matches!(
+            node_type(node),
+            Some("FunctionDefinition" | "ModifierDefinition")
+        ) && span_bounds(node).is_some_and(|(start, end)| (start..end).contains(&offset))
+        {
+            function = Some(node);
+        }
+    });
+    let mut names = HashSet::new();
+    if let Some(function) = function {
+        ast::walk(function, &mut |node| {
+            if node_type(node) == Some("VariableDeclaration") {
+                names.insert(name(node).to_string());
+            }
+        });
+    }
+    names
+}
+
+/// A name for the returned parameter `parameter` at `position` that isn't taken yet.
+fn fresh_name(parameter: &Value, position: usize, taken: &mut HashSet<String>) -> String {
+    let base = match name(parameter) {
+        "" => format!("value{position}"),
+        named => named.to_string(),
+    };
+    let mut candidate = base.clone();
+    let mut suffix = 1;
+    while taken.contains(&candidate) {
+        suffix += 1;
+        candidate = format!("{base}{suffix}");
+    }
+    taken.insert(candidate.clone());
+    candidate
+}
+
+fn code_action(title: &str, uri: &Url, edits: Vec<TextEdit>) -> CodeActionOrCommand {
+    CodeActionOrCommand::CodeAction(CodeAction {
+        title: title.to_string(),
+        kind: Some(CodeActionKind::REFACTOR_REWRITE),
+        edit: Some(WorkspaceEdit {
+            changes: Some(HashMap::from([(uri.clone(), edits)])),
+            ..WorkspaceEdit::default()
+        }),
+        ..CodeAction::default()
+    })
+}
+
+/// "Declare the returned values" on a call statement dropping what the function returns,
+/// or "Declare the skipped returned values" on a tuple declaration leaving positions empty.
+pub fn destructure_action(
+    ast_data: &Value,
+    uri: &Url,
+    position: Position,
+    source_bytes: &[u8],
+) -> Option<CodeActionOrCommand> {
+    let unit = ast::source_unit(ast_data, uri)?;
+    let index = ast::index_nodes(ast_data.get("sources")?);
+    let source = String::from_utf8_lossy(source_bytes);
+    let offset = pos_to_bytes(source_bytes, position);
+    let destructuring = destructuring_at(unit, offset, &source)?;
+    let (_, returns) = returned(destructuring.call, &index)?;
+    let mut taken = names_in_scope(unit, offset);
+    let declare = |(i, parameter): (usize, &&Value), taken: &mut HashSet<String>| {
+        Some(format!(
+            "{} {}",
+            returned_type(parameter)?,
+            fresh_name(parameter, i, taken)
+        ))
+    };
+
+    let mut builder = EditBuilder::new(&source);
+    let title = match (node_type(destructuring.node)?, destructuring.tuple) {
+        ("ExpressionStatement", None) => {
+            let declarations: Vec<String> = returns
+                .iter()
+                .enumerate()
+                .map(|parameter| declare(parameter, &mut taken))
+                .collect::<Option<_>>()?;
+            let target = match declarations.as_slice() {
+                [single] => single.clone(),
+                _ => format!("({})", declarations.join(", ")),
+            };
+            let (start, _) = span_bounds(destructuring.node)?;
+            builder.insert(start, format!("{target} = ")).ok()?;
+            "Declare the returned values"
+        }
+        ("VariableDeclarationStatement", Some(tuple)) => {
+            let positions = positions(&source, tuple)?;
+            if positions.len() != returns.len()
+                || destructuring.targets.len() != returns.len()
+                || destructuring.targets.iter().all(Option::is_some)
+            {
+                return None;
+            }
+            for (i, ((target, parameter), (start, end))) in destructuring
+                .targets
+                .iter()
+                .zip(&returns)
+                .zip(&positions)
+                .enumerate()
+            {
+                if target.is_none() {
+                    let declaration = declare((i, parameter), &mut taken)?;
+                    let separator = if i == 0 { "" } else { " " };
+                    builder
+                        .replace(*start, *end, format!("{separator}{declaration}"))
+                        .ok()?;
+                }
+            }
+            "Declare the skipped returned values"
+        }
+        _ => return None,
+    };
+    Some(code_action(title, uri, builder.build()))
+}
+
+/// Which returned value goes to which position of the tuple at `position`: a declaration,
+/// an assignment, or a skipped value.
+pub fn tuple_hover(
+    ast_data: &Value,
+    uri: &Url,
+    position: Position,
+    source_bytes: &[u8],
+) -> Option<Hover> {
+    let unit = ast::source_unit(ast_data, uri)?;
+    let index = ast::index_nodes(ast_data.get("sources")?);
+    let source = String::from_utf8_lossy(source_bytes);
+    let offset = pos_to_bytes(source_bytes, position);
+    let destructuring = destructuring_at(unit, offset, &source)?;
+    let (start, end) = destructuring.tuple?;
+    if !(start..end).contains(&offset) {
+        return None;
+    }
+    let (function, returns) = returned(destructuring.call, &index)?;
+    if destructuring.targets.len() != returns.len() {
+        return None;
+    }
+
+    let lines: Vec<String> = returns
+        .iter()
+        .zip(&destructuring.targets)
+        .enumerate()
+        .map(|(i, (parameter, target))| {
+            let value = returned_type(parameter).unwrap_or_default();
+            let value = format!("{value} {}", name(parameter)).trim().to_string();
+            let target = match target {
+                None => "skipped".to_string(),
+                Some(target) if node_type(target) == Some("VariableDeclaration") => {
+                    format!("`{}`", name(target))
+                }
+                Some(target) => match span_bounds(target).and_then(|(s, e)| source.get(s..e)) {
+                    Some(text) => format!("`{text}`"),
+                    None => "assigned".to_string(),
+                },
+            };
+            format!("{i}. `{value}` → {target}")
+        })
+        .collect();
+    let plural = if returns.len() == 1 {
+        "value"
+    } else {
+        "values"
+    };
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: format!(
+                "`{}` returns {} {plural}:\n\n{}",
+                name(function),
+                returns.len(),
+                lines.join("\n")
+            ),
+        }),
+        range: Some(Range::new(
+            bytes_to_pos(source_bytes, start)?,
+            bytes_to_pos(source_bytes, end)?,
+        )),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax;
+
+    const SOURCE: &str = "\
+contract Vault {
+    struct Limit { uint256 cap; }
+
+    function split(uint256 x) internal returns (uint256 assets, uint256, Limit memory) {}
+
+    function run(uint256 assets) public {
+        split(1);
+        (, uint256 shares, ) = split(2);
+        uint256 a;
+        (a, , ) = split(3);
+    }
+}
+";
+
+    fn parse() -> (Url, Value) {
+        let path = "/project/src/Vault.sol";
+        (
+            Url::from_file_path(path).unwrap(),
+            syntax::parse(path, SOURCE),
+        )
+    }
+
+    fn destructure(line: u32, character: u32) -> Option<(String, String)> {
+        let (uri, tree) = parse();
+        let CodeActionOrCommand::CodeAction(action) = destructure_action(
+            &tree,
+            &uri,
+            Position::new(line, character),
+            SOURCE.as_bytes(),
+        )?
+        else {
+            panic!("expected a code action");
+        };
+        let edits = &action.edit.unwrap().changes.unwrap()[&uri];
+        let text = EditBuilder::with_edits(SOURCE, edits).unwrap().apply();
+        let line = text.lines().nth(line as usize).unwrap().trim().to_string();
+        Some((action.title, line))
+    }
+
+    fn hover_text(line: u32, character: u32) -> Option<String> {
+        let (uri, tree) = parse();
+        let hover = tuple_hover(
+            &tree,
+            &uri,
+            Position::new(line, character),
+            SOURCE.as_bytes(),
+        )?;
+        let HoverContents::Markup(markup) = hover.contents else {
+            panic!("expected markdown");
+        };
+        Some(markup.value)
+    }
+
+    #[test]
+    fn test_declare_returned_values() {
+        // The parameter `assets` is taken, so the first value gets another name
+        assert_eq!(
+            destructure(6, 9),
+            Some((
+                "Declare the returned values".to_string(),
+                "(uint256 assets2, uint256 value1, Limit memory value2) = split(1);".to_string()
+            ))
+        );
+        assert_eq!(
+            destructure(7, 30),
+            Some((
+                "Declare the skipped returned values".to_string(),
+                "(uint256 assets2, uint256 shares, Limit memory value2) = split(2);".to_string()
+            ))
+        );
+        // Assignments can't declare
+        assert_eq!(destructure(9, 15), None);
+    }
+
+    #[test]
+    fn test_hover_maps_returned_values_to_positions() {
+        assert_eq!(
+            hover_text(7, 9).as_deref(),
+            Some(
+                "`split` returns 3 values:\n\n\
+                 0. `uint256 assets` → skipped\n\
+                 1. `uint256` → `shares`\n\
+                 2. `Limit memory` → skipped"
+            )
+        );
+        assert_eq!(
+            hover_text(9, 11).as_deref(),
+            Some(
+                "`split` returns 3 values:\n\n\
+                 0. `uint256 assets` → `a`\n\
+                 1. `uint256` → skipped\n\
+                 2. `Limit memory` → skipped"
+            )
+        );
+        // Only the tuple itself hovers
+        assert_eq!(hover_text(7, 30), None);
+    }
+}
@@ -0,0 +1,696 @@
+//! Imports, local variables and parameters a file never uses.
+//!
+//! Imports are matched by name against the identifiers and paths of the rest of the file,
+//! since the compiler resolves uses of imported symbols to their declarations in the files
+//! they come from: a symbol alias is unused when its local name never appears, a unit alias
+//! likewise, and a plain import when none of the names it brings in does. Plain imports of
+//! files missing from the AST are left alone. Locals and parameters are matched by the
+//! declarations their identifiers reference; writing to a local doesn't use it.
+//!
+//! solc warns on unused locals and parameters too; these findings are hints carrying the
+//! quick fix removing them. Functions with assembly whose references the AST doesn't record,
+//! and virtual functions, whose overrides may need their parameters, are skipped.
+
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use tower_lsp::lsp_types::{
+    Diagnostic, DiagnosticSeverity, DiagnosticTag, NumberOrString, Range, TextEdit, Url,
+};
+
+use crate::{
+    ast::{self, name, node_type, parse_src},
+    code_actions::Fix,
+    edits::EditBuilder,
+};
+
+/// Diagnostic code of an import none of whose symbols are used.
+pub const UNUSED_IMPORT_CODE: &str = "unused-import";
+/// Diagnostic code of a local variable that is never read.
+pub const UNUSED_VARIABLE_CODE: &str = "unused-variable";
+/// Diagnostic code of a named parameter that is never used.
+pub const UNUSED_PARAMETER_CODE: &str = "unused-parameter";
+
+/// Top-level declarations a file exports under their name.
+const DECLARATIONS: &[&str] = &[
+    "ContractDefinition",
+    "StructDefinition",
+    "EnumDefinition",
+    "ErrorDefinition",
+    "EventDefinition",
+    "FunctionDefinition",
+    "VariableDeclaration",
+    "UserDefinedValueTypeDefinition",
+];
+
+fn byte_range(node: &Value, key: &str) -> Option<(usize, usize)> {
+    let (start, length, _) = parse_src(node.get(key)?.as_str()?)?;
+    Some((start, start + length))
+}
+
+/// The names used by `node` outside import directives: identifiers, the first segment of
+/// paths like `Lib.Token`, and the bases of `@inheritdoc` tags.
+fn used_names<'a>(node: &'a Value, names: &mut HashSet<&'a str>) {
+    match node {
+        Value::Object(map) => {
+            match node_type(node) {
+                Some("ImportDirective") => return,
+                Some("Identifier" | "IdentifierPath" | "UserDefinedTypeName") => {
+                    if let Some(first) = name(node).split('.').next() {
+                        names.insert(first);
+                    }
+                }
+                Some("StructuredDocumentation") => {
+                    let text = node.get("text").and_then(Value::as_str).unwrap_or_default();
+                    names.extend(
+                        text.split("@inheritdoc")
+                            .skip(1)
+                            .filter_map(|rest| rest.split_whitespace().next()),
+                    );
+                }
+                _ => {}
+            }
+            map.values().for_each(|child| used_names(child, names));
+        }
+        Value::Array(items) => items.iter().for_each(|child| used_names(child, names)),
+        _ => {}
+    }
+}
+
+/// The source units of `ast_data` by absolute path.
+fn units(ast_data: &Value) -> HashMap<&str, &Value> {
+    let mut units = HashMap::new();
+    for (path, contents) in ast_data
+        .get("sources")
+        .and_then(Value::as_object)
+        .into_iter()
+        .flatten()
+    {
+        if let Some(unit) = contents
+            .get(0)
+            .and_then(|content| content.get("source_file"))
+            .and_then(|source_file| source_file.get("ast"))
+        {
+            let path = unit
+                .get("absolutePath")
+                .and_then(Value::as_str)
+                .unwrap_or(path);
+            units.insert(path, unit);
+        }
+    }
+    units
+}
+
+/// The names `unit` exports: its declarations and what it imports, from the compiler's
+/// `exportedSymbols` when recorded.
+fn exported_names<'a>(
+    unit: &'a Value,
+    units: &HashMap<&str, &'a Value>,
+    visited: &mut HashSet<&'a str>,
+    names: &mut HashSet<&'a str>,
+) {
+    if let Some(exported) = unit.get("exportedSymbols").and_then(Value::as_object) {
+        names.extend(exported.keys().map(String::as_str));
+        return;
+    }
+    if let Some(path) = unit.get("absolutePath").and_then(Value::as_str)
+        && !visited.insert(path)
+    {
+        return;
+    }
+    for node in unit
+        .get("nodes")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+    {
+        match node_type(node) {
+            Some("ImportDirective") => {
+                let aliases = node.get("symbolAliases").and_then(Value::as_array);
+                let unit_alias = name_of_alias(node);
+                if !unit_alias.is_empty() {
+                    names.insert(unit_alias);
+                } else if let Some(aliases) = aliases.filter(|aliases| !aliases.is_empty()) {
+                    names.extend(aliases.iter().map(local_name));
+                } else if let Some(imported) = node
+                    .get("absolutePath")
+                    .and_then(Value::as_str)
+                    .and_then(|path| units.get(path))
+                {
+                    exported_names(imported, units, visited, names);
+                }
+            }
+            Some(kind) if DECLARATIONS.contains(&kind) => {
+                names.insert(name(node));
+            }
+            _ => {}
+        }
+    }
+}
+
+fn name_of_alias(directive: &Value) -> &str {
+    directive
+        .get("unitAlias")
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+}
+
+/// The name a symbol alias of an import directive declares in the importing file.
+fn local_name(alias: &Value) -> &str {
+    alias
+        .get("local")
+        .and_then(Value::as_str)
+        .filter(|local| !local.is_empty())
+        .unwrap_or_else(|| name(&alias["foreign"]))
+}
+
+/// `start..end` widened to the whole line when nothing else is on it, its newline included.
+fn line_removal(source: &str, start: usize, end: usize) -> (usize, usize) {
+    let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = source[end..]
+        .find('\n')
+        .map_or(source.len(), |i| end + i + 1);
+    if source[line_start..start].trim().is_empty() && source[end..line_end].trim().is_empty() {
+        (line_start, line_end)
+    } else {
+        (start, end)
+    }
+}
+
+/// The end of the statement ending at `end`, past its `;` when the range stops before it.
+fn statement_end(source: &str, end: usize) -> usize {
+    let rest = &source[end..];
+    let trimmed = rest.trim_start();
+    if trimmed.starts_with(';') {
+        end + (rest.len() - trimmed.len()) + 1
+    } else {
+        end
+    }
+}
+
+/// Title and byte-range edits of a fix.
+type Removal = (String, Vec<(usize, usize, String)>);
+
+struct Finding {
+    range: (usize, usize),
+    code: &'static str,
+    severity: DiagnosticSeverity,
+    message: String,
+    /// The fix, if there is a safe one.
+    fix: Option<Removal>,
+}
+
+fn import_findings(ast_data: &Value, unit: &Value, source: &str) -> Vec<Finding> {
+    let mut used = HashSet::new();
+    used_names(unit, &mut used);
+    let units = units(ast_data);
+    let mut findings = Vec::new();
+    for directive in unit
+        .get("nodes")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+    {
+        if node_type(directive) != Some("ImportDirective") {
+            continue;
+        }
+        let Some((start, end)) = byte_range(directive, "src") else {
+            continue;
+        };
+        let end = statement_end(source, end);
+        let (line_start, line_end) = line_removal(source, start, end);
+        let remove_directive =
+            |title: String| Some((title, vec![(line_start, line_end, String::new())]));
+        let file = directive
+            .get("file")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        let aliases = directive
+            .get("symbolAliases")
+            .and_then(Value::as_array)
+            .filter(|aliases| !aliases.is_empty());
+        let unit_alias = name_of_alias(directive);
+
+        if let Some(aliases) = aliases {
+            let unused: Vec<&Value> = aliases
+                .iter()
+                .filter(|alias| !used.contains(local_name(alias)))
+                .collect();
+            for (i, alias) in unused.iter().enumerate() {
+                let local = local_name(alias);
+                let Some((alias_start, foreign_end)) = byte_range(&alias["foreign"], "src") else {
+                    continue;
+                };
+                let alias_end = byte_range(alias, "nameLocation").map_or(foreign_end, |r| r.1);
+                let fix = if unused.len() == aliases.len() {
+                    // The last unused symbol's fix removes the directive; the others only
+                    // themselves, so applying all of them leaves no empty braces
+                    if i + 1 == unused.len() {
+                        remove_directive(format!("Remove unused import of `{file}`"))
+                    } else {
+                        symbol_removal(source, alias_start, alias_end, local)
+                    }
+                } else {
+                    symbol_removal(source, alias_start, alias_end, local)
+                };
+                findings.push(Finding {
+                    range: (alias_start, alias_end),
+                    code: UNUSED_IMPORT_CODE,
+                    severity: DiagnosticSeverity::WARNING,
+                    message: format!("`{local}` is imported but never used"),
+                    fix,
+                });
+            }
+        } else if !unit_alias.is_empty() {
+            if !used.contains(unit_alias) {
+                findings.push(Finding {
+                    range: (start, end),
+                    code: UNUSED_IMPORT_CODE,
+                    severity: DiagnosticSeverity::WARNING,
+                    message: format!("`{unit_alias}` is imported but never used"),
+                    fix: remove_directive(format!("Remove unused import of `{file}`")),
+                });
+            }
+        } else {
+            let Some(imported) = directive
+                .get("absolutePath")
+                .and_then(Value::as_str)
+                .and_then(|path| units.get(path))
+            else {
+                continue;
+            };
+            let mut exported = HashSet::new();
+            exported_names(imported, &units, &mut HashSet::new(), &mut exported);
+            if exported.is_disjoint(&used) {
+                findings.push(Finding {
+                    range: (start, end),
+                    code: UNUSED_IMPORT_CODE,
+                    severity: DiagnosticSeverity::WARNING,
+                    message: format!("nothing `{file}` declares is used"),
+                    fix: remove_directive(format!("Remove unused import of `{file}`")),
+                });
+            }
+        }
+    }
+    findings
+}
+
+/// The fix removing the symbol at `start..end` from the braces of its import directive,
+/// with the comma separating it from the next symbol, or from the previous one when last.
+fn symbol_removal(source: &str, start: usize, end: usize, local: &str) -> Option<Removal> {
+    let after = &source[end..];
+    let (start, end) = match after.trim_start().strip_prefix(',') {
+        Some(rest) => (start, source.len() - rest.trim_start().len()),
+        None => {
+            let before = source[..start].trim_end();
+            before.strip_suffix(',')?;
+            (before.len() - 1, end)
+        }
+    };
+    Some((
+        format!("Remove unused import of `{local}`"),
+        vec![(start, end, String::new())],
+    ))
+}
+
+/// Reads and writes of the declarations referenced in `body`.
+#[derive(Default)]
+struct Uses {
+    read: HashSet<u64>,
+    written: HashSet<u64>,
+}
+
+/// Ids of the identifiers assigned to with `=` in `node`, directly or in tuples.
+fn assigned(node: &Value, ids: &mut HashSet<u64>) {
+    match node_type(node) {
+        Some("Identifier") => ids.extend(node.get("id").and_then(Value::as_u64)),
+        Some("TupleExpression") => node
+            .get("components")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .for_each(|component| assigned(component, ids)),
+        _ => {}
+    }
+}
+
+/// The uses in `body`, or `None` when it has assembly whose references aren't recorded.
+fn uses(body: &Value) -> Option<Uses> {
+    let mut targets = HashSet::new();
+    let mut opaque = false;
+    ast::walk(body, &mut |node| match node_type(node) {
+        Some("Assignment") if node.get("operator").and_then(Value::as_str) == Some("=") => {
+            assigned(&node["leftHandSide"], &mut targets);
+        }
+        Some("InlineAssembly") => opaque |= node.get("externalReferences").is_none(),
+        _ => {}
+    });
+    if opaque {
+        return None;
+    }
+    let mut uses = Uses::default();
+    ast::walk(body, &mut |node| match node_type(node) {
+        Some("Identifier") => {
+            let Some(declaration) = node.get("referencedDeclaration").and_then(Value::as_u64)
+            else {
+                return;
+            };
+            let write = node
+                .get("id")
+                .and_then(Value::as_u64)
+                .is_some_and(|id| targets.contains(&id));
+            if write {
+                uses.written.insert(declaration);
+            } else {
+                uses.read.insert(declaration);
+            }
+        }
+        Some("InlineAssembly") => {
+            let references = node.get("externalReferences").and_then(Value::as_array);
+            uses.read.extend(
+                references
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|reference| reference.get("declaration")?.as_u64()),
+            );
+        }
+        _ => {}
+    });
+    Some(uses)
+}
+
+/// Whether evaluating `expression` only computes a value: no calls but type conversions,
+/// assignments, increments, deletions or contract creations.
+fn side_effect_free(expression: &Value) -> bool {
+    let mut free = true;
+    ast::walk(expression, &mut |node| match node_type(node) {
+        Some("FunctionCall") => {
+            free &= node.get("kind").and_then(Value::as_str) == Some("typeConversion");
+        }
+        Some("Assignment" | "NewExpression" | "FunctionCallOptions") => free = false,
+        Some("UnaryOperation") => {
+            let operator = node.get("operator").and_then(Value::as_str);
+            free &= !matches!(operator, Some("++" | "--" | "delete"));
+        }
+        _ => {}
+    });
+    free
+}
+
+fn local_findings(function: &Value, uses: &Uses, source: &str) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    ast::walk(&function["body"], &mut |statement| {
+        if node_type(statement) != Some("VariableDeclarationStatement") {
+            return;
+        }
+        let Some(declarations) = statement.get("declarations").and_then(Value::as_array) else {
+            return;
+        };
+        let Some((start, end)) = byte_range(statement, "src") else {
+            return;
+        };
+        let end = statement_end(source, end);
+        let is_unused = |declaration: &Value| {
+            declaration
+                .get("id")
+                .and_then(Value::as_u64)
+                .is_some_and(|id| !uses.read.contains(&id))
+        };
+        let named: Vec<&Value> = declarations
+            .iter()
+            .filter(|declaration| !declaration.is_null() && !name(declaration).is_empty())
+            .collect();
+        let all_unused = named.iter().all(|declaration| is_unused(declaration));
+        let value = statement
+            .get("initialValue")
+            .filter(|value| !value.is_null());
+        for declaration in named.iter().filter(|declaration| is_unused(declaration)) {
+            let variable = name(declaration);
+            let Some(range) =
+                byte_range(declaration, "nameLocation").or_else(|| byte_range(declaration, "src"))
+            else {
+                continue;
+            };
+            let written = declaration
+                .get("id")
+                .and_then(Value::as_u64)
+                .is_some_and(|id| uses.written.contains(&id));
+            let title = format!("Remove unused variable `{variable}`");
+            let fix = if written {
+                // The assignments would be left without their variable
+                None
+            } else if !all_unused {
+                // Other components of the tuple stay declared
+                byte_range(declaration, "src")
+                    .map(|(start, end)| (start, end, String::new()))
+                    .map(|edit| (title, vec![edit]))
+            } else {
+                match value.map(|value| (value, byte_range(value, "src"))) {
+                    None => {
+                        let (start, end) = line_removal(source, start, end);
+                        Some((title, vec![(start, end, String::new())]))
+                    }
+                    Some((value, _)) if side_effect_free(value) => {
+                        let (start, end) = line_removal(source, start, end);
+                        Some((title, vec![(start, end, String::new())]))
+                    }
+                    // The initializer stays, `f();`, for what it does
+                    Some((_, Some((value_start, _)))) => {
+                        Some((title, vec![(start, value_start, String::new())]))
+                    }
+                    Some((_, None)) => None,
+                }
+            };
+            findings.push(Finding {
+                range,
+                code: UNUSED_VARIABLE_CODE,
+                severity: DiagnosticSeverity::HINT,
+                message: if written {
+                    format!("the local variable `{variable}` is assigned but never read")
+                } else {
+                    format!("the local variable `{variable}` is never used")
+                },
+                fix,
+            });
+        }
+    });
+    findings
+}
+
+fn parameter_findings(function: &Value, uses: &Uses, source: &str) -> Vec<Finding> {
+    if function.get("virtual").and_then(Value::as_bool) == Some(true) {
+        return vec![];
+    }
+    let documentation = match function.get("documentation") {
+        Some(Value::String(text)) => text.as_str(),
+        Some(documentation) => documentation
+            .get("text")
+            .and_then(Value::as_str)
+            .unwrap_or_default(),
+        None => "",
+    };
+    let parameters = function["parameters"]["parameters"].as_array();
+    let mut findings = Vec::new();
+    for parameter in parameters.into_iter().flatten() {
+        let parameter_name = name(parameter);
+        let Some(id) = parameter.get("id").and_then(Value::as_u64) else {
+            continue;
+        };
+        if parameter_name.is_empty() || uses.read.contains(&id) || uses.written.contains(&id) {
+            continue;
+        }
+        let range = byte_range(parameter, "nameLocation").or_else(|| {
+            let (_, end) = byte_range(parameter, "src")?;
+            let start = end.checked_sub(parameter_name.len())?;
+            (source.get(start..end) == Some(parameter_name)).then_some((start, end))
+        });
+        let Some((name_start, name_end)) = range else {
+            continue;
+        };
+        // Documented parameters keep their name for the NatSpec
+        let documented = documentation
+            .split("@param")
+            .skip(1)
+            .any(|rest| rest.split_whitespace().next() == Some(parameter_name));
+        let fix = (!documented).then(|| {
+            let start = source[..name_start].trim_end().len();
+            (
+                format!("Remove the name of unused parameter `{parameter_name}`"),
+                vec![(start, name_end, String::new())],
+            )
+        });
+        findings.push(Finding {
+            range: (name_start, name_end),
+            code: UNUSED_PARAMETER_CODE,
+            severity: DiagnosticSeverity::HINT,
+            message: format!("the parameter `{parameter_name}` is never used"),
+            fix,
+        });
+    }
+    findings
+}
+
+/// Unused imports of `uri` in `ast_data`, as warnings, and unused locals and parameters of
+/// its functions and modifiers, as hints, each with the fix removing it when safe.
+pub fn unused_diagnostics(ast_data: &Value, uri: &Url, source_bytes: &[u8]) -> Vec<Diagnostic> {
+    let (Some(unit), Ok(source)) = (
+        ast::source_unit(ast_data, uri),
+        std::str::from_utf8(source_bytes),
+    ) else {
+        return vec![];
+    };
+    let mut findings = import_findings(ast_data, unit, source);
+    ast::walk(unit, &mut |function| {
+        if !matches!(
+            node_type(function),
+            Some("FunctionDefinition" | "ModifierDefinition")
+        ) || function.get("body").is_none_or(Value::is_null)
+        {
+            return;
+        }
+        let Some(uses) = uses(&function["body"]) else {
+            return;
+        };
+        findings.extend(local_findings(function, &uses, source));
+        findings.extend(parameter_findings(function, &uses, source));
+    });
+    findings.sort_by_key(|finding| finding.range);
+
+    let builder = EditBuilder::new(source);
+    findings
+        .into_iter()
+        .filter(|finding| finding.range.1 <= source.len())
+        .map(|finding| {
+            let range = |(start, end): (usize, usize)| {
+                Range::new(builder.position(start), builder.position(end))
+            };
+            let data = finding.fix.and_then(|(title, edits)| {
+                let edits = edits
+                    .into_iter()
+                    .map(|(start, end, new_text)| TextEdit {
+                        range: range((start, end)),
+                        new_text,
+                    })
+                    .collect();
+                Fix::new(title, edits).to_data()
+            });
+            Diagnostic {
+                range: range(finding.range),
+                severity: Some(finding.severity),
+                code: Some(NumberOrString::String(finding.code.to_string())),
+                source: Some("forge-lsp".to_string()),
+                message: finding.message,
+                tags: Some(vec![DiagnosticTag::UNNECESSARY]),
+                data,
+                ..Diagnostic::default()
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{edits, syntax};
+
+    const TOKEN: &str = "\
+struct Permit { uint256 deadline; }
+contract Token {}
+";
+
+    const VAULT: &str = "\
+import \"./Token.sol\";
+import {Token, Permit} from \"./Token.sol\";
+import * as T from \"./Token.sol\";
+
+contract Vault {
+    function deposit(uint256 amount, address to) public returns (uint256 shares) {
+        uint256 fee = amount / 100;
+        uint256 price = quote();
+        uint256 unread;
+        unread = 1;
+        (uint256 a, uint256 b) = split(amount);
+        shares = a;
+    }
+
+    function quote() internal returns (uint256) {}
+
+    function split(uint256 amount) internal pure returns (uint256, uint256) {
+        return (amount, amount);
+    }
+}
+";
+
+    fn diagnostics(files: &[(&str, &str)], file: &str) -> Vec<Diagnostic> {
+        let ast_data = syntax::parse_files(files.iter().copied());
+        let uri = Url::from_file_path(file).unwrap();
+        let source = files.iter().find(|(path, _)| *path == file).unwrap().1;
+        unused_diagnostics(&ast_data, &uri, source.as_bytes())
+    }
+
+    fn fixed(source: &str, diagnostic: &Diagnostic) -> String {
+        let fix = Fix::from_diagnostic(diagnostic).unwrap();
+        edits::apply(source, &fix.edits).unwrap()
+    }
+
+    #[test]
+    fn test_unused_diagnostics() {
+        let files = [
+            ("/project/src/Token.sol", TOKEN),
+            ("/project/src/Vault.sol", VAULT),
+        ];
+        let found = diagnostics(&files, "/project/src/Vault.sol");
+        let messages: Vec<&str> = found.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                "nothing `./Token.sol` declares is used",
+                "`Token` is imported but never used",
+                "`Permit` is imported but never used",
+                "`T` is imported but never used",
+                "the parameter `to` is never used",
+                "the local variable `fee` is never used",
+                "the local variable `price` is never used",
+                "the local variable `unread` is assigned but never read",
+                "the local variable `b` is never used",
+            ]
+        );
+        assert_eq!(found[0].severity, Some(DiagnosticSeverity::WARNING));
+        assert_eq!(found[4].severity, Some(DiagnosticSeverity::HINT));
+
+        // Of two unused symbols, one goes first, the other with the directive
+        assert!(fixed(VAULT, &found[1]).contains("import {Permit} from"));
+        assert!(!fixed(VAULT, &found[2]).contains("import {"));
+        assert!(fixed(VAULT, &found[4]).contains("deposit(uint256 amount, address)"));
+        assert!(!fixed(VAULT, &found[5]).contains("fee"));
+        assert!(fixed(VAULT, &found[6]).contains("\n        quote();\n"));
+        assert!(Fix::from_diagnostic(&found[7]).is_none());
+        assert!(fixed(VAULT, &found[8]).contains("(uint256 a, ) = split(amount);"));
+    }
+
+    #[test]
+    fn test_unused_imports_used_through_names() {
+        let vault = "\
+import {Token as Asset} from \"./Token.sol\";
+import \"./Token.sol\";
+import * as T from \"./Token.sol\";
+import {Missing} from \"./Missing.sol\";
+
+contract Vault is T.Token {
+    Asset asset;
+
+    function permit(Permit memory) public {}
+}
+";
+        let files = [
+            ("/project/src/Token.sol", TOKEN),
+            ("/project/src/Vault.sol", vault),
+        ];
+        let found = diagnostics(&files, "/project/src/Vault.sol");
+        let messages: Vec<&str> = found.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(messages, ["`Missing` is imported but never used"]);
+        assert_eq!(
+            fixed(vault, &found[0]),
+            vault.replace("import {Missing} from \"./Missing.sol\";\n", "")
+        );
+    }
+}
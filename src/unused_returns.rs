@@ -0,0 +1,170 @@
+//! Warnings for external calls whose return values are silently dropped, like the `bool`
+//! of `token.transfer(to, amount);`.
+//!
+//! A warning is silenced like the server's other diagnostics, by a
+//! `// forge-lsp-disable-next-line unused-return` comment on the line before the call (see
+//! [`crate::suppressions`]).
+
+use serde_json::Value;
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString, Range, Url};
+
+use crate::{
+    ast::{self, node_type, parse_src},
+    goto::bytes_to_pos,
+    inlay_hints::returned,
+};
+
+/// Diagnostic code of an external call dropping its return values.
+pub const UNUSED_RETURN_CODE: &str = "unused-return";
+
+fn type_string(node: &Value) -> Option<&str> {
+    node.get("typeDescriptions")?.get("typeString")?.as_str()
+}
+
+/// Warnings for the call statements of `uri` calling a function of another contract and
+/// dropping what it returns.
+pub fn unused_return_diagnostics(
+    ast_data: &Value,
+    uri: &Url,
+    source_bytes: &[u8],
+) -> Vec<Diagnostic> {
+    let (Some(sources), Some(unit)) = (ast_data.get("sources"), ast::source_unit(ast_data, uri))
+    else {
+        return vec![];
+    };
+    let index = ast::index_nodes(sources);
+
+    let mut diagnostics = vec![];
+    ast::walk(unit, &mut |statement| {
+        let Some(call) = statement
+            .get("expression")
+            .filter(|_| node_type(statement) == Some("ExpressionStatement"))
+            .filter(|call| node_type(call) == Some("FunctionCall"))
+        else {
+            return;
+        };
+        let Some(returns) = returned(call, &index) else {
+            return;
+        };
+        let mut callee = &call["expression"];
+        while node_type(callee) == Some("FunctionCallOptions") {
+            callee = &callee["expression"];
+        }
+        // A member of a contract value, `token.transfer` or `this.total`, is called by message
+        let external = node_type(callee) == Some("MemberAccess")
+            && callee
+                .get("expression")
+                .and_then(type_string)
+                .is_some_and(|ty| ty.starts_with("contract "));
+        if !external {
+            return;
+        }
+        let Some((start, length, _)) = call.get("src").and_then(Value::as_str).and_then(parse_src)
+        else {
+            return;
+        };
+        let (Some(start), Some(end)) = (
+            bytes_to_pos(source_bytes, start),
+            bytes_to_pos(source_bytes, start + length),
+        ) else {
+            return;
+        };
+        let types: Vec<&str> = returns
+            .iter()
+            .map(|parameter| type_string(parameter).unwrap_or("_"))
+            .collect();
+        let member = callee
+            .get("memberName")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        diagnostics.push(Diagnostic {
+            range: Range::new(start, end),
+            severity: Some(DiagnosticSeverity::WARNING),
+            code: Some(NumberOrString::String(UNUSED_RETURN_CODE.to_string())),
+            source: Some("forge-lsp".to_string()),
+            message: format!(
+                "the return value of `{member}` is ignored: returns ({})",
+                types.join(", ")
+            ),
+            ..Diagnostic::default()
+        });
+    });
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{suppressions, syntax};
+    use std::collections::HashMap;
+
+    const SOURCE: &str = "\
+interface IToken {
+    function transfer(address to, uint256 amount) external returns (bool);
+    function burn(uint256 amount) external;
+}
+
+contract Vault {
+    IToken token;
+
+    function run(address to) public {
+        token.transfer(to, 1);
+        token.burn(1);
+        // forge-lsp-disable-next-line unused-return
+        token.transfer(to, 2);
+        token.transfer(to, 3); // forge-lsp-disable-line
+        // forge-lsp-disable-next-line other-code
+        token.transfer(to, 4);
+        bool ok = token.transfer(to, 5);
+    }
+}
+";
+
+    /// The in-process parse, with the types and declarations the compiler resolves for the
+    /// members of `token`.
+    fn typed_tree(path: &str) -> Value {
+        let mut tree = syntax::parse(path, SOURCE);
+        let functions: HashMap<String, u64> = ast::index_nodes(&tree["sources"])
+            .values()
+            .filter(|node| node_type(node) == Some("FunctionDefinition"))
+            .map(|node| {
+                (
+                    node["name"].as_str().unwrap().to_string(),
+                    node["id"].as_u64().unwrap(),
+                )
+            })
+            .collect();
+        let mut stack = vec![&mut tree];
+        while let Some(node) = stack.pop() {
+            if node.get("nodeType").and_then(Value::as_str) == Some("MemberAccess")
+                && node["expression"]["name"] == "token"
+            {
+                let member = node["memberName"].as_str().unwrap();
+                node["referencedDeclaration"] = functions[member].into();
+                node["expression"]["typeDescriptions"]["typeString"] = "contract IToken".into();
+            }
+            match node {
+                Value::Array(items) => stack.extend(items.iter_mut()),
+                Value::Object(fields) => stack.extend(fields.values_mut()),
+                _ => {}
+            }
+        }
+        tree
+    }
+
+    #[test]
+    fn test_ignored_return_values_of_external_calls() {
+        let path = "/project/src/Vault.sol";
+        let uri = Url::from_file_path(path).unwrap();
+        let tree = typed_tree(path);
+
+        let mut diagnostics = unused_return_diagnostics(&tree, &uri, SOURCE.as_bytes());
+        suppressions::retain_unsuppressed(&mut diagnostics, SOURCE);
+        let lines: Vec<u32> = diagnostics.iter().map(|d| d.range.start.line).collect();
+        assert_eq!(lines, [9, 15]);
+        assert_eq!(
+            diagnostics[0].message,
+            "the return value of `transfer` is ignored: returns (bool)"
+        );
+    }
+}
@@ -1,55 +1,16 @@
-pub fn byte_offset_to_position(source: &str, byte_offset: usize) -> (u32, u32) {
-    let mut line = 0;
-    let mut col = 0;
-    let mut i = 0;
-
-    let bytes = source.as_bytes();
-    while i < byte_offset && i < bytes.len() {
-        match bytes[i] {
-            b'\n' => {
-                line += 1;
-                col = 0;
-                i += 1;
-            }
-            b'\r' if i + 1 < bytes.len() && bytes[i + 1] == b'\n' => {
-                line += 1;
-                col = 0;
-                i += 2;
-            }
-            _ => {
-                col += 1;
-                i += 1;
-            }
-        }
-    }
+use crate::documents::LineIndex;
+use tower_lsp::lsp_types::Position;
 
-    (line, col)
+/// Line and UTF-16 column of the byte `byte_offset` of `source`, clamped to its end.
+pub fn byte_offset_to_position(source: &str, byte_offset: usize) -> (u32, u32) {
+    let position = LineIndex::new(source).position(source, byte_offset);
+    (position.line, position.character)
 }
 
+/// Byte offset of the UTF-16 column `character` of `line` in `source`. Columns past the end
+/// of a line clamp to it, and lines past the last one to the end of the text.
 pub fn position_to_byte_offset(source: &str, line: u32, character: u32) -> usize {
-    let mut current_line = 0;
-    let mut current_col = 0;
-
-    for (i, ch) in source.char_indices() {
-        if current_line == line && current_col == character {
-            return i;
-        }
-
-        match ch {
-            '\n' => {
-                if current_line == line && current_col < character {
-                    return i; // clamp to end of line
-                }
-                current_line += 1;
-                current_col = 0;
-            }
-            _ => {
-                current_col += 1;
-            }
-        }
-    }
-
-    source.len()
+    LineIndex::new(source).offset(source, Position::new(line, character))
 }
 
 /// Check if a string is a valid Solidity identifier
@@ -0,0 +1,326 @@
+//! Verification bundles: what block explorers need to verify a deployed contract by hand.
+//!
+//! `forge-lsp.verificationBundle` assembles, from the artifacts of the last `forge build`,
+//! what Etherscan's and Sourcify's manual verification forms ask for: the standard JSON
+//! input the contract was compiled from, narrowed to the sources its metadata lists, the
+//! full compiler version, the optimizer settings, the contract's metadata, and the
+//! constructor's signature with a placeholder for its ABI-encoded arguments, which only
+//! the deployment knows. With a `directory`, the bundle is also written there as files
+//! ready to upload.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value, json};
+use std::{
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+use tower_lsp::lsp_types::Url;
+
+use crate::{
+    build_info::{build_info_files, read_full_build_info},
+    events::{artifact_path, canonical_type},
+};
+
+/// Assembles the verification bundle of a contract, given as
+/// `{"uri": ..., "contract": ..., "directory": ...}`.
+pub const VERIFICATION_BUNDLE_COMMAND: &str = "forge-lsp.verificationBundle";
+
+/// The standard JSON input in a bundle directory.
+pub const STANDARD_JSON_INPUT_FILE: &str = "standard-json-input.json";
+
+/// The contract's metadata in a bundle directory, for Sourcify.
+pub const METADATA_FILE: &str = "metadata.json";
+
+/// Everything else a form asks for, in a bundle directory.
+pub const VERIFICATION_FILE: &str = "verification.json";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VerificationBundleParams {
+    /// File declaring the contract.
+    pub uri: Url,
+    pub contract: String,
+    /// Directory the bundle is written to, relative to the project root, when given.
+    #[serde(default)]
+    pub directory: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VerificationBundle {
+    /// Fully qualified name, `src/Vault.sol:Vault`.
+    pub contract: String,
+    /// Full compiler version as explorers list it, `v0.8.24+commit.e11b9ed9`.
+    pub compiler_version: String,
+    pub optimizer_enabled: bool,
+    pub optimizer_runs: Option<u64>,
+    pub evm_version: Option<String>,
+    pub via_ir: bool,
+    /// Canonical signature of the constructor, `constructor(address,uint256)`, when the
+    /// contract declares one taking parameters.
+    pub constructor_signature: Option<String>,
+    /// The ABI-encoded constructor arguments: empty without parameters, otherwise a
+    /// placeholder naming them, to replace with the encoding of the deployed values.
+    pub constructor_arguments: String,
+    pub standard_json_input: Value,
+    /// The contract's metadata, when the artifact has it.
+    pub metadata: Option<Value>,
+}
+
+/// The metadata of the artifact `artifact`, kept as an object or as the `rawMetadata`
+/// string depending on the forge version.
+fn artifact_metadata(artifact: &Value) -> Option<Value> {
+    match artifact.get("metadata") {
+        Some(metadata @ Value::Object(_)) => Some(metadata.clone()),
+        _ => serde_json::from_str(artifact.get("rawMetadata")?.as_str()?).ok(),
+    }
+}
+
+/// The full compiler version a build info was compiled with.
+fn build_info_version(info: &Value) -> Option<&str> {
+    [
+        "solcLongVersion",
+        "solc_long_version",
+        "solcVersion",
+        "solc_version",
+    ]
+    .iter()
+    .find_map(|key| info.get(*key)?.as_str())
+}
+
+/// The constructor signature and the placeholder of its arguments in the ABI `abi`.
+fn constructor(abi: &Value) -> (Option<String>, String) {
+    let Some(inputs) = abi
+        .as_array()
+        .into_iter()
+        .flatten()
+        .find(|entry| entry["type"] == "constructor")
+        .and_then(|entry| entry.get("inputs")?.as_array())
+        .filter(|inputs| !inputs.is_empty())
+    else {
+        return (None, String::new());
+    };
+    let types: Vec<String> = inputs
+        .iter()
+        .map(|input| canonical_type(input).unwrap_or_default())
+        .collect();
+    let parameters: Vec<String> = inputs
+        .iter()
+        .zip(&types)
+        .map(|(input, abi_type)| match input["name"].as_str() {
+            Some(name) if !name.is_empty() => format!("{abi_type} {name}"),
+            _ => abi_type.clone(),
+        })
+        .collect();
+    let signature = format!("constructor({})", types.join(","));
+    (
+        Some(signature),
+        format!("<ABI-encoded {}>", parameters.join(", ")),
+    )
+}
+
+/// The standard JSON input of the build info `info`, with only the sources of `sources`
+/// when given. Forge keeps its own fields next to solc's in the input; only solc's are kept.
+pub fn standard_json_input(info: &Value, sources: Option<&Map<String, Value>>) -> Option<Value> {
+    let input = info.get("input")?;
+    let mut input_sources = input.get("sources")?.as_object()?.clone();
+    if let Some(sources) = sources {
+        input_sources.retain(|path, _| sources.contains_key(path));
+    }
+    Some(json!({
+        "language": input.get("language").cloned().unwrap_or_else(|| json!("Solidity")),
+        "sources": input_sources,
+        "settings": input.get("settings").cloned().unwrap_or_else(|| json!({})),
+    }))
+}
+
+/// The bundle of `contract`, declared in the file at `path`, from its artifact in `out` and
+/// the build info in `out` that compiled it.
+pub fn verification_bundle(
+    root: &Path,
+    out: &Path,
+    path: &Path,
+    contract: &str,
+) -> Result<VerificationBundle, String> {
+    let artifact_file = artifact_path(out, path, contract);
+    let text = std::fs::read_to_string(&artifact_file).map_err(|e| {
+        format!(
+            "Failed to read {}, run `forge build` first: {e}",
+            artifact_file.display()
+        )
+    })?;
+    let artifact: Value =
+        serde_json::from_str(&text).map_err(|e| format!("Invalid artifact of {contract}: {e}"))?;
+    let metadata = artifact_metadata(&artifact);
+    let version = metadata
+        .as_ref()
+        .and_then(|metadata| metadata["compiler"]["version"].as_str());
+    let source = path
+        .strip_prefix(root)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .replace('\\', "/");
+
+    // The newest build info compiling the file, with the artifact's compiler when known
+    let mut files = build_info_files(&out.join("build-info")).map_err(|e| e.to_string())?;
+    files.sort_by_key(|file| {
+        std::cmp::Reverse(
+            std::fs::metadata(file)
+                .and_then(|metadata| metadata.modified())
+                .unwrap_or(SystemTime::UNIX_EPOCH),
+        )
+    });
+    let info = files
+        .iter()
+        .filter_map(|file| read_full_build_info(file).ok())
+        .find(|info| {
+            info["input"]["sources"].get(&source).is_some()
+                && version.is_none_or(|version| build_info_version(info) == Some(version))
+        })
+        .ok_or_else(|| format!("No build info compiled {source}, run `forge build` first"))?;
+
+    let sources = metadata
+        .as_ref()
+        .and_then(|metadata| metadata["sources"].as_object());
+    let standard_json_input = standard_json_input(&info, sources)
+        .ok_or_else(|| format!("The build info of {source} has no standard JSON input"))?;
+    let compiler_version = version
+        .or_else(|| build_info_version(&info))
+        .map(|version| format!("v{}", version.trim_start_matches('v')))
+        .ok_or_else(|| format!("The build info of {source} names no compiler version"))?;
+    let settings = &standard_json_input["settings"];
+    let (constructor_signature, constructor_arguments) = constructor(&artifact["abi"]);
+    Ok(VerificationBundle {
+        contract: format!("{source}:{contract}"),
+        compiler_version,
+        optimizer_enabled: settings["optimizer"]["enabled"].as_bool().unwrap_or(false),
+        optimizer_runs: settings["optimizer"]["runs"].as_u64(),
+        evm_version: settings["evmVersion"].as_str().map(str::to_string),
+        via_ir: settings["viaIR"].as_bool().unwrap_or(false),
+        constructor_signature,
+        constructor_arguments,
+        standard_json_input,
+        metadata,
+    })
+}
+
+/// Write `bundle` to `directory` as [`STANDARD_JSON_INPUT_FILE`], [`METADATA_FILE`] and
+/// [`VERIFICATION_FILE`], returning the files written.
+pub fn write_bundle(bundle: &VerificationBundle, directory: &Path) -> Result<Vec<PathBuf>, String> {
+    let write = |name: &str, value: &Value| {
+        let file = directory.join(name);
+        let text = serde_json::to_string_pretty(value).map_err(|e| e.to_string())?;
+        std::fs::write(&file, text + "\n")
+            .map_err(|e| format!("Failed to write {}: {e}", file.display()))?;
+        Ok::<_, String>(file)
+    };
+    std::fs::create_dir_all(directory)
+        .map_err(|e| format!("Failed to create {}: {e}", directory.display()))?;
+    let mut files = vec![write(
+        STANDARD_JSON_INPUT_FILE,
+        &bundle.standard_json_input,
+    )?];
+    if let Some(metadata) = &bundle.metadata {
+        files.push(write(METADATA_FILE, metadata)?);
+    }
+    let mut rest = serde_json::to_value(bundle).map_err(|e| e.to_string())?;
+    if let Some(fields) = rest.as_object_mut() {
+        fields.remove("standardJsonInput");
+        fields.remove("metadata");
+    }
+    files.push(write(VERIFICATION_FILE, &rest)?);
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_verification_bundle() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let out = root.join("out");
+        fs::create_dir_all(out.join("build-info")).unwrap();
+        fs::create_dir_all(out.join("Vault.sol")).unwrap();
+        let metadata = json!({
+            "compiler": { "version": "0.8.24+commit.e11b9ed9" },
+            "sources": { "src/Vault.sol": {}, "src/Token.sol": {} }
+        });
+        let artifact = json!({
+            "abi": [{
+                "type": "constructor",
+                "inputs": [
+                    { "name": "token", "type": "address" },
+                    { "name": "", "type": "uint256" }
+                ]
+            }],
+            "rawMetadata": metadata.to_string()
+        });
+        fs::write(out.join("Vault.sol/Vault.json"), artifact.to_string()).unwrap();
+        let sources = json!({
+            "src/Vault.sol": { "content": "contract Vault {}" },
+            "src/Token.sol": { "content": "contract Token {}" },
+            "test/Vault.t.sol": { "content": "contract VaultTest {}" }
+        });
+        let settings =
+            json!({ "optimizer": { "enabled": true, "runs": 200 }, "evmVersion": "cancun" });
+        let info = |version: &str| {
+            json!({
+                "solcLongVersion": version,
+                "input": { "language": "Solidity", "sources": sources, "settings": settings, "allowPaths": [] }
+            })
+        };
+        fs::write(
+            out.join("build-info/a.json"),
+            info("0.8.24+commit.e11b9ed9").to_string(),
+        )
+        .unwrap();
+        // Another compiler, as with several profiles
+        fs::write(
+            out.join("build-info/b.json"),
+            info("0.8.19+commit.7dd6d404").to_string(),
+        )
+        .unwrap();
+
+        let path = root.join("src/Vault.sol");
+        let bundle = verification_bundle(root, &out, &path, "Vault").unwrap();
+        assert_eq!(bundle.contract, "src/Vault.sol:Vault");
+        assert_eq!(bundle.compiler_version, "v0.8.24+commit.e11b9ed9");
+        assert!(bundle.optimizer_enabled);
+        assert_eq!(bundle.optimizer_runs, Some(200));
+        assert_eq!(bundle.evm_version.as_deref(), Some("cancun"));
+        assert_eq!(
+            bundle.constructor_signature.as_deref(),
+            Some("constructor(address,uint256)")
+        );
+        assert_eq!(
+            bundle.constructor_arguments,
+            "<ABI-encoded address token, uint256>"
+        );
+        assert_eq!(
+            bundle.standard_json_input,
+            json!({
+                "language": "Solidity",
+                "sources": {
+                    "src/Vault.sol": { "content": "contract Vault {}" },
+                    "src/Token.sol": { "content": "contract Token {}" }
+                },
+                "settings": settings
+            })
+        );
+        assert_eq!(bundle.metadata, Some(metadata));
+
+        let files = write_bundle(&bundle, &root.join("verify/Vault")).unwrap();
+        assert_eq!(files.len(), 3);
+        let written: Value = serde_json::from_str(
+            &fs::read_to_string(root.join("verify/Vault/verification.json")).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(written["compilerVersion"], "v0.8.24+commit.e11b9ed9");
+        assert!(written.get("standardJsonInput").is_none());
+
+        assert!(verification_bundle(root, &out, &path, "Token").is_err());
+    }
+}
@@ -0,0 +1,125 @@
+//! Project files watched besides the sources: the configuration, the dependencies and the
+//! build info of each Foundry project.
+//!
+//! A change to any of them can change what every source of the project compiles to, so
+//! the project's index entry and cached ASTs are dropped and the project is rebuilt. This
+//! is what makes a `forge install` or an edited remapping take effect without restarting
+//! the server.
+
+use std::path::{Component, Path, PathBuf};
+
+use crate::annotations::SKIPPED_DIRS;
+
+/// Watcher globs for the configuration, dependencies and build info of the projects.
+pub const PROJECT_GLOBS: [&str; 4] = [
+    "**/foundry.toml",
+    "**/remappings.txt",
+    "**/lib/**",
+    "**/out/build-info/**",
+];
+
+/// Files configuring a project from its root.
+const CONFIG_FILES: [&str; 2] = ["foundry.toml", "remappings.txt"];
+
+fn names(path: &Path) -> impl Iterator<Item = &str> {
+    path.components().filter_map(|component| match component {
+        Component::Normal(name) => name.to_str(),
+        _ => None,
+    })
+}
+
+/// Whether `path`, relative to the workspace folder, is inside a dependency, build output
+/// or hidden directory, where the directories with a `foundry.toml` are not projects of
+/// the workspace.
+fn in_skipped_dir(path: &Path) -> bool {
+    let mut dirs: Vec<&str> = names(path).collect();
+    dirs.pop();
+    dirs.iter()
+        .any(|name| name.starts_with('.') || SKIPPED_DIRS.contains(name))
+}
+
+/// Whether a change to `path` can make the projects of the workspace `folder` stale: it is
+/// a `foundry.toml` or `remappings.txt`, under a `lib/` directory, or build info.
+pub fn is_project_file(folder: &Path, path: &Path) -> bool {
+    let relative = path.strip_prefix(folder).unwrap_or(path);
+    let names: Vec<&str> = names(relative).collect();
+    names.last().is_some_and(|name| CONFIG_FILES.contains(name))
+        || names[..names.len().saturating_sub(1)].contains(&"lib")
+        || names.windows(2).any(|pair| pair == ["out", "build-info"])
+}
+
+/// Root of the project of the workspace `folder` that a change to the project file `path`
+/// makes stale: the directory of a changed `foundry.toml`, even a deleted one, or the
+/// innermost directory with a `foundry.toml` that isn't itself a dependency.
+pub fn affected_root(folder: &Path, path: &Path) -> Option<PathBuf> {
+    let relative = |dir: &Path| dir.strip_prefix(folder).unwrap_or(dir).to_path_buf();
+    if path.file_name().is_some_and(|name| name == "foundry.toml")
+        && !in_skipped_dir(&relative(path))
+    {
+        return path.parent().map(Path::to_path_buf);
+    }
+    path.ancestors()
+        .skip(1)
+        .take_while(|dir| !path.starts_with(folder) || dir.starts_with(folder))
+        .filter(|dir| !in_skipped_dir(&relative(&dir.join("foundry.toml"))))
+        .find(|dir| dir.join("foundry.toml").is_file())
+        .map(Path::to_path_buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_project_files_and_their_roots() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path();
+        for project in ["", "packages/core", "lib/forge-std"] {
+            std::fs::create_dir_all(folder.join(project)).unwrap();
+            std::fs::write(folder.join(project).join("foundry.toml"), "").unwrap();
+        }
+
+        for path in [
+            "foundry.toml",
+            "packages/core/remappings.txt",
+            "lib/forge-std/src/Test.sol",
+            "packages/core/lib/solmate/src/tokens/ERC20.sol",
+            "out/build-info/abc.json",
+        ] {
+            assert!(is_project_file(folder, &folder.join(path)), "{path}");
+        }
+        for path in [
+            "src/Vault.sol",
+            "lib",
+            "script/remappings.sol",
+            "out/Vault.sol/Vault.json",
+        ] {
+            assert!(!is_project_file(folder, &folder.join(path)), "{path}");
+        }
+
+        let root = |path: &str| affected_root(folder, &folder.join(path));
+        // A dependency, even one with a `foundry.toml`, belongs to the project installing it
+        assert_eq!(
+            root("lib/forge-std/src/Test.sol"),
+            Some(folder.to_path_buf())
+        );
+        assert_eq!(
+            root("lib/forge-std/foundry.toml"),
+            Some(folder.to_path_buf())
+        );
+        assert_eq!(
+            root("packages/core/lib/solmate/src/tokens/ERC20.sol"),
+            Some(folder.join("packages/core"))
+        );
+        assert_eq!(
+            root("packages/core/remappings.txt"),
+            Some(folder.join("packages/core"))
+        );
+        assert_eq!(root("out/build-info/abc.json"), Some(folder.to_path_buf()));
+        // A new or deleted project
+        assert_eq!(
+            root("packages/new/foundry.toml"),
+            Some(folder.join("packages/new"))
+        );
+    }
+}
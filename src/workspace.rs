@@ -0,0 +1,148 @@
+//! Folders of a multi-root workspace.
+//!
+//! Clients name the workspace folders on `initialize` and report the ones added and
+//! removed with `workspace/didChangeWorkspaceFolders`. Each folder may hold several Foundry
+//! projects, like a monorepo with `contracts/` and `periphery/`, and every project found
+//! in any folder is indexed and compiled on its own. Without folders, the workspace is the
+//! directory the server was started in.
+
+use std::path::{Path, PathBuf};
+use tower_lsp::lsp_types::{InitializeParams, WorkspaceFolder, WorkspaceFoldersChangeEvent};
+
+use crate::index;
+
+fn folder_paths<'a>(folders: impl IntoIterator<Item = &'a WorkspaceFolder>) -> Vec<PathBuf> {
+    folders
+        .into_iter()
+        .filter_map(|folder| folder.uri.to_file_path().ok())
+        .collect()
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WorkspaceFolders {
+    folders: Vec<PathBuf>,
+}
+
+impl WorkspaceFolders {
+    pub fn new(folders: Vec<PathBuf>) -> Self {
+        let mut workspace = Self::default();
+        for folder in folders {
+            workspace.add(folder);
+        }
+        workspace
+    }
+
+    /// The folders of `params`, or its root when the client doesn't support folders.
+    pub fn from_params(params: &InitializeParams) -> Self {
+        #[allow(deprecated)]
+        let root = params
+            .root_uri
+            .as_ref()
+            .and_then(|uri| uri.to_file_path().ok());
+        match &params.workspace_folders {
+            Some(folders) => Self::new(folder_paths(folders)),
+            None => Self::new(root.into_iter().collect()),
+        }
+    }
+
+    fn add(&mut self, folder: PathBuf) {
+        if !self.folders.contains(&folder) {
+            self.folders.push(folder);
+        }
+    }
+
+    /// Apply `event`. Returns the folders removed.
+    pub fn change(&mut self, event: &WorkspaceFoldersChangeEvent) -> Vec<PathBuf> {
+        let removed = folder_paths(&event.removed);
+        self.folders.retain(|folder| !removed.contains(folder));
+        for folder in folder_paths(&event.added) {
+            self.add(folder);
+        }
+        removed
+    }
+
+    /// The folders of the workspace, the current directory when the client named none.
+    pub fn folders(&self) -> Vec<PathBuf> {
+        if self.folders.is_empty() {
+            return std::env::current_dir().into_iter().collect();
+        }
+        self.folders.clone()
+    }
+
+    /// The innermost folder containing `path`.
+    pub fn folder_for(&self, path: &Path) -> Option<PathBuf> {
+        self.folders()
+            .into_iter()
+            .filter(|folder| path.starts_with(folder))
+            .max_by_key(|folder| folder.components().count())
+    }
+
+    /// The Foundry projects of every folder, ordered by root.
+    pub fn projects(&self) -> Vec<PathBuf> {
+        let mut projects: Vec<PathBuf> = self
+            .folders()
+            .iter()
+            .flat_map(|folder| index::discover_projects(folder))
+            .collect();
+        projects.sort();
+        projects.dedup();
+        projects
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower_lsp::lsp_types::Url;
+
+    fn folder(path: &Path) -> WorkspaceFolder {
+        WorkspaceFolder {
+            uri: Url::from_file_path(path).unwrap(),
+            name: path.display().to_string(),
+        }
+    }
+
+    #[test]
+    fn test_workspace_folders() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        for project in ["contracts", "periphery", "periphery/lib/forge-std"] {
+            std::fs::create_dir_all(root.join(project)).unwrap();
+            std::fs::write(root.join(project).join("foundry.toml"), "").unwrap();
+        }
+        std::fs::create_dir_all(root.join("periphery/src")).unwrap();
+
+        let params = InitializeParams {
+            workspace_folders: Some(vec![
+                folder(&root.join("contracts")),
+                folder(&root.join("periphery")),
+                folder(&root.join("periphery/src")),
+            ]),
+            ..InitializeParams::default()
+        };
+        let mut workspace = WorkspaceFolders::from_params(&params);
+        assert_eq!(
+            workspace.projects(),
+            [root.join("contracts"), root.join("periphery")]
+        );
+        assert_eq!(
+            workspace.folder_for(&root.join("periphery/src/Router.sol")),
+            Some(root.join("periphery/src"))
+        );
+        assert_eq!(workspace.folder_for(&root.join("other/A.sol")), None);
+
+        let removed = workspace.change(&WorkspaceFoldersChangeEvent {
+            added: vec![folder(&root.join("contracts"))],
+            removed: vec![
+                folder(&root.join("periphery")),
+                folder(&root.join("periphery/src")),
+            ],
+        });
+        assert_eq!(
+            removed,
+            [root.join("periphery"), root.join("periphery/src")]
+        );
+        assert_eq!(workspace.folders(), [root.join("contracts")]);
+        assert_eq!(workspace.projects(), [root.join("contracts")]);
+    }
+}